@@ -0,0 +1,46 @@
+use std::{
+    hint::black_box,
+    num::{NonZeroU8, NonZeroUsize},
+};
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use vapoursynth_zoomvtools::dct::{DctHelper, DctKind, DctPool};
+
+pub fn bench_dct_helper_new_16x16(c: &mut Criterion) {
+    c.bench_function("DctHelper::new 16x16 8-bit", |b| {
+        let size = NonZeroUsize::new(16).unwrap();
+        let bits = NonZeroU8::new(8).unwrap();
+
+        b.iter(|| {
+            DctHelper::new(
+                black_box(size),
+                black_box(size),
+                black_box(bits),
+                DctKind::Float,
+            )
+            .unwrap()
+        })
+    });
+}
+
+pub fn bench_dct_pool_take_16x16(c: &mut Criterion) {
+    c.bench_function("DctPool::take 16x16 8-bit", |b| {
+        let size = NonZeroUsize::new(16).unwrap();
+        let bits = NonZeroU8::new(8).unwrap();
+        let pool = DctPool::default();
+
+        b.iter(|| {
+            let helper = pool
+                .take(black_box(size), black_box(size), black_box(bits))
+                .unwrap();
+            pool.give_back(helper);
+        })
+    });
+}
+
+criterion_group!(
+    bench_dct,
+    bench_dct_helper_new_16x16,
+    bench_dct_pool_take_16x16
+);
+criterion_main!(bench_dct);
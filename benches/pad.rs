@@ -3,7 +3,7 @@ use std::{hint::black_box, num::NonZeroUsize};
 use criterion::{Criterion, criterion_group, criterion_main};
 use rand::{Rng, SeedableRng};
 use rand_xoshiro::Xoshiro128StarStar;
-use vapoursynth_zoomvtools::pad::pad_reference_frame;
+use vapoursynth_zoomvtools::{pad::pad_reference_frame, params::PadMode};
 
 pub fn bench_pad_reference_frame_8bit(c: &mut Criterion) {
     c.bench_function("pad_reference_frame 8-bit", |b| {
@@ -28,6 +28,7 @@ pub fn bench_pad_reference_frame_8bit(c: &mut Criterion) {
                 black_box(vpad),
                 black_box(resolution),
                 black_box(resolution),
+                PadMode::Replicate,
                 black_box(&mut plane),
             )
         })
@@ -57,6 +58,7 @@ pub fn bench_pad_reference_frame_16bit(c: &mut Criterion) {
                 black_box(vpad),
                 black_box(resolution),
                 black_box(resolution),
+                PadMode::Replicate,
                 black_box(&mut plane),
             )
         })
@@ -8,7 +8,7 @@ use rand::{Rng, SeedableRng};
 use rand_xoshiro::Xoshiro128StarStar;
 use vapoursynth_zoomvtools::{
     mv_plane::MVPlane,
-    params::Subpel,
+    params::{PadMode, Subpel},
     refine::{
         refine_diagonal_bilinear,
         refine_horizontal_bicubic,
@@ -17,6 +17,7 @@ use vapoursynth_zoomvtools::{
         refine_vertical_bicubic,
         refine_vertical_bilinear,
         refine_vertical_wiener,
+        refine_vertical_wiener_transposed,
     },
 };
 
@@ -48,6 +49,7 @@ pub fn bench_refine_ext_pel2_8bit(c: &mut Criterion) {
                 black_box(&src),
                 black_box(src_2x_resolution),
                 black_box(false),
+                PadMode::Replicate,
                 black_box(&mut dest),
             )
         })
@@ -82,6 +84,7 @@ pub fn bench_refine_ext_pel2_8bit_padded(c: &mut Criterion) {
                 black_box(&src),
                 black_box(src_2x_resolution),
                 black_box(true),
+                PadMode::Replicate,
                 black_box(&mut dest),
             )
         })
@@ -116,6 +119,7 @@ pub fn bench_refine_ext_pel2_16bit(c: &mut Criterion) {
                 black_box(&src),
                 black_box(src_2x_resolution),
                 black_box(false),
+                PadMode::Replicate,
                 black_box(&mut dest),
             )
         })
@@ -150,6 +154,7 @@ pub fn bench_refine_ext_pel2_16bit_padded(c: &mut Criterion) {
                 black_box(&src),
                 black_box(src_2x_resolution),
                 black_box(true),
+                PadMode::Replicate,
                 black_box(&mut dest),
             )
         })
@@ -184,6 +189,7 @@ pub fn bench_refine_ext_pel4_8bit(c: &mut Criterion) {
                 black_box(&src),
                 black_box(src_4x_resolution),
                 black_box(false),
+                PadMode::Replicate,
                 black_box(&mut dest),
             )
         })
@@ -218,6 +224,7 @@ pub fn bench_refine_ext_pel4_8bit_padded(c: &mut Criterion) {
                 black_box(&src),
                 black_box(src_4x_resolution),
                 black_box(true),
+                PadMode::Replicate,
                 black_box(&mut dest),
             )
         })
@@ -252,6 +259,7 @@ pub fn bench_refine_ext_pel4_16bit(c: &mut Criterion) {
                 black_box(&src),
                 black_box(src_4x_resolution),
                 black_box(false),
+                PadMode::Replicate,
                 black_box(&mut dest),
             )
         })
@@ -286,6 +294,7 @@ pub fn bench_refine_ext_pel4_16bit_padded(c: &mut Criterion) {
                 black_box(&src),
                 black_box(src_4x_resolution),
                 black_box(true),
+                PadMode::Replicate,
                 black_box(&mut dest),
             )
         })
@@ -632,6 +641,54 @@ pub fn bench_refine_vertical_wiener_16bit(c: &mut Criterion) {
     });
 }
 
+pub fn bench_refine_vertical_wiener_transposed_8bit(c: &mut Criterion) {
+    c.bench_function("refine_vertical_wiener_transposed 8-bit", |b| {
+        let mut rng = Xoshiro128StarStar::from_seed(*b"deadbeeflolcakes");
+        let resolution = NonZeroUsize::new(256).unwrap();
+        let mut dest = vec![0u8; resolution.get() * resolution.get()];
+        let mut src = vec![0u8; resolution.get() * resolution.get()];
+
+        for p in src.iter_mut() {
+            *p = rng.random();
+        }
+
+        b.iter(|| {
+            refine_vertical_wiener_transposed(
+                black_box(&mut dest),
+                black_box(&src),
+                black_box(resolution),
+                black_box(resolution),
+                black_box(resolution),
+                black_box(NonZeroU8::new(8).unwrap()),
+            )
+        })
+    });
+}
+
+pub fn bench_refine_vertical_wiener_transposed_16bit(c: &mut Criterion) {
+    c.bench_function("refine_vertical_wiener_transposed 16-bit", |b| {
+        let mut rng = Xoshiro128StarStar::from_seed(*b"deadbeeflolcakes");
+        let resolution = NonZeroUsize::new(256).unwrap();
+        let mut dest = vec![0u16; resolution.get() * resolution.get()];
+        let mut src = vec![0u16; resolution.get() * resolution.get()];
+
+        for p in src.iter_mut() {
+            *p = rng.random();
+        }
+
+        b.iter(|| {
+            refine_vertical_wiener_transposed(
+                black_box(&mut dest),
+                black_box(&src),
+                black_box(resolution),
+                black_box(resolution),
+                black_box(resolution),
+                black_box(NonZeroU8::new(16).unwrap()),
+            )
+        })
+    });
+}
+
 criterion_group!(
     bench_refine_ext_pel2,
     bench_refine_ext_pel2_8bit,
@@ -667,7 +724,9 @@ criterion_group!(
     bench_refine_horizontal_wiener_8bit,
     bench_refine_horizontal_wiener_16bit,
     bench_refine_vertical_wiener_8bit,
-    bench_refine_vertical_wiener_16bit
+    bench_refine_vertical_wiener_16bit,
+    bench_refine_vertical_wiener_transposed_8bit,
+    bench_refine_vertical_wiener_transposed_16bit
 );
 criterion_main!(
     bench_refine_ext_pel2,
@@ -13,6 +13,10 @@ use crate::util::Pixel;
 /// pixels, storing the result in the destination buffer. The averaging uses ceiling
 /// division to ensure proper rounding for integer pixel values.
 ///
+/// Each result lies between its two source pixels, so it never exceeds the maximum
+/// of the bit depth of the sources, e.g. 1023 for 10-bit content in `u16` samples,
+/// and no clamping to it is needed.
+///
 /// # Parameters
 /// - `src1`: First source image buffer
 /// - `src2`: Second source image buffer
@@ -35,3 +39,54 @@ pub fn average2<T: Pixel>(
 
     rust::average2(dest, src1, src2, pitch, width, height);
 }
+
+/// The total weight of the two images in [`blend2`].
+pub const BLEND_SCALE: u32 = 256;
+
+/// Converts the `time` parameter of motion compensation, from 0 to 255,
+/// into the weight of the compensated image for [`blend2`].
+///
+/// `time=128` weighs both images equally, and `time=255` is treated as full compensation.
+#[must_use]
+pub fn time_weight(time: u8) -> u32 {
+    if time == u8::MAX {
+        BLEND_SCALE
+    } else {
+        u32::from(time)
+    }
+}
+
+/// Blends two images pixel by pixel, weighing `src2` by `weight2 / BLEND_SCALE`
+/// and `src1` by the rest.
+///
+/// This generalizes [`average2`], which gives the same result for a weight of
+/// `BLEND_SCALE / 2`. It is used to blend the source with its motion compensation,
+/// with the weight from [`time_weight`].
+///
+/// Like [`average2`], each result lies between its two source pixels.
+///
+/// # Parameters
+/// - `dest`: Destination buffer to store the blended result
+/// - `src1`: First source image buffer, fully used at a weight of 0
+/// - `src2`: Second source image buffer, fully used at a weight of `BLEND_SCALE`
+/// - `pitch`: Number of pixels per row (including any padding)
+/// - `width`: Width of the image in pixels
+/// - `height`: Height of the image in pixels
+/// - `weight2`: Weight of `src2`, at most `BLEND_SCALE`
+pub fn blend2<T: Pixel>(
+    dest: &mut [T],
+    src1: &[T],
+    src2: &[T],
+    pitch: NonZeroUsize,
+    width: NonZeroUsize,
+    height: NonZeroUsize,
+    weight2: u32,
+) {
+    let max_offset = (height.get() - 1) * pitch.get() + width.get();
+    debug_assert!(src1.len() >= max_offset);
+    debug_assert!(src2.len() >= max_offset);
+    debug_assert!(dest.len() >= max_offset);
+    debug_assert!(weight2 <= BLEND_SCALE);
+
+    rust::blend2(dest, src1, src2, pitch, width, height, weight2);
+}
@@ -1,5 +1,6 @@
 use std::num::NonZeroUsize;
 
+use super::BLEND_SCALE;
 use crate::util::Pixel;
 
 /// # Notes
@@ -51,3 +52,28 @@ pub(super) fn average2<T: Pixel>(
         offset += pitch.get();
     }
 }
+
+pub(super) fn blend2<T: Pixel>(
+    dest: &mut [T],
+    src1: &[T],
+    src2: &[T],
+    pitch: NonZeroUsize,
+    width: NonZeroUsize,
+    height: NonZeroUsize,
+    weight2: u32,
+) {
+    let weight1 = BLEND_SCALE - weight2;
+    for y in 0..height.get() {
+        let offset = y * pitch.get();
+        let dest_row = &mut dest[offset..][..width.get()];
+        let src1_row = &src1[offset..][..width.get()];
+        let src2_row = &src2[offset..][..width.get()];
+        for ((dest, a), b) in dest_row.iter_mut().zip(src1_row).zip(src2_row) {
+            let a: u32 = a.to_u32().expect("fits in u32");
+            let b: u32 = b.to_u32().expect("fits in u32");
+            *dest = T::from_u32_or_max_value(
+                (a * weight1 + b * weight2 + BLEND_SCALE / 2) / BLEND_SCALE,
+            );
+        }
+    }
+}
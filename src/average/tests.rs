@@ -4,6 +4,11 @@
 use std::num::NonZeroUsize;
 
 use pastey::paste;
+use rand::{Rng, SeedableRng};
+use rand_xoshiro::Xoshiro128StarStar;
+
+use super::{BLEND_SCALE, average2, blend2, time_weight};
+use crate::util::Pixel;
 
 macro_rules! average2_tests {
     ($module:ident) => {
@@ -282,3 +287,145 @@ macro_rules! average2_tests {
 }
 
 average2_tests!(rust);
+
+fn blend_planes<T: Pixel>(src: &[T], compensated: &[T], time: u8) -> Vec<T> {
+    let mut dest = vec![T::zero(); src.len()];
+    blend2(
+        &mut dest,
+        src,
+        compensated,
+        NonZeroUsize::new(40).unwrap(),
+        NonZeroUsize::new(37).unwrap(),
+        NonZeroUsize::new(src.len() / 40).unwrap(),
+        time_weight(time),
+    );
+    dest
+}
+
+fn random_plane<T: Pixel>(rng: &mut Xoshiro128StarStar, max: u32) -> Vec<T> {
+    std::iter::repeat_with(|| T::from_u32_or_max_value(rng.random_range(0..=max)))
+        .take(40 * 12)
+        .collect()
+}
+
+#[test]
+fn time_weight_covers_both_ends() {
+    assert_eq!(time_weight(0), 0);
+    assert_eq!(time_weight(128), BLEND_SCALE / 2);
+    assert_eq!(time_weight(254), 254);
+    assert_eq!(time_weight(255), BLEND_SCALE);
+}
+
+#[test]
+fn blend2_time_extremes_select_one_image() {
+    let mut rng = Xoshiro128StarStar::from_seed(*b"deadbeeflolcakes");
+    let src: Vec<u8> = random_plane(&mut rng, 255);
+    let compensated: Vec<u8> = random_plane(&mut rng, 255);
+
+    let visible = |plane: &[u8]| -> Vec<u8> {
+        plane
+            .chunks_exact(40)
+            .flat_map(|row| row[..37].to_vec())
+            .collect()
+    };
+    assert_eq!(visible(&blend_planes(&src, &compensated, 0)), visible(&src));
+    assert_eq!(
+        visible(&blend_planes(&src, &compensated, 255)),
+        visible(&compensated)
+    );
+}
+
+#[test]
+fn blend2_time_128_is_the_midpoint() {
+    let mut rng = Xoshiro128StarStar::from_seed(*b"deadbeeflolcakes");
+    for bits in [8, 10, 16] {
+        let max = (1u32 << bits) - 1;
+        let src: Vec<u16> = random_plane(&mut rng, max);
+        let compensated: Vec<u16> = random_plane(&mut rng, max);
+
+        let mut midpoint = vec![0u16; src.len()];
+        average2(
+            &mut midpoint,
+            &src,
+            &compensated,
+            NonZeroUsize::new(40).unwrap(),
+            NonZeroUsize::new(37).unwrap(),
+            NonZeroUsize::new(12).unwrap(),
+        );
+        let blended = blend_planes(&src, &compensated, 128);
+        for (y, (row, expected)) in blended
+            .chunks_exact(40)
+            .zip(midpoint.chunks_exact(40))
+            .enumerate()
+        {
+            assert_eq!(row[..37], expected[..37], "{bits}-bit row {y}");
+        }
+    }
+}
+
+#[test]
+fn blend2_weighs_by_time() {
+    let src = [0u8, 100, 255, 40];
+    let compensated = [255u8, 0, 255, 200];
+    let mut dest = [0u8; 4];
+    blend2(
+        &mut dest,
+        &src,
+        &compensated,
+        NonZeroUsize::new(2).unwrap(),
+        NonZeroUsize::new(2).unwrap(),
+        NonZeroUsize::new(2).unwrap(),
+        time_weight(64),
+    );
+    // A quarter of the way from the source to the compensation, rounded to nearest
+    assert_eq!(dest, [64, 75, 255, 80]);
+}
+
+#[test]
+fn average2_keeps_10_bit_maximum() {
+    let src1 = [1023u16, 1023, 1022, 0];
+    let src2 = [1023u16, 1022, 1023, 1023];
+    let mut dest = [0u16; 4];
+    average2(
+        &mut dest,
+        &src1,
+        &src2,
+        NonZeroUsize::new(2).unwrap(),
+        NonZeroUsize::new(2).unwrap(),
+        NonZeroUsize::new(2).unwrap(),
+    );
+    assert_eq!(dest, [1023, 1023, 1023, 512]);
+}
+
+#[test]
+fn results_stay_between_their_sources() {
+    let mut rng = Xoshiro128StarStar::from_seed(*b"deadbeeflolcakes");
+    for bits in [9, 10, 12, 16] {
+        let max = (1u32 << bits) - 1;
+        let src: Vec<u16> = random_plane(&mut rng, max);
+        let compensated: Vec<u16> = random_plane(&mut rng, max);
+
+        let mut averaged = vec![0u16; src.len()];
+        average2(
+            &mut averaged,
+            &src,
+            &compensated,
+            NonZeroUsize::new(40).unwrap(),
+            NonZeroUsize::new(37).unwrap(),
+            NonZeroUsize::new(12).unwrap(),
+        );
+        let time = rng.random();
+        let blended = blend_planes(&src, &compensated, time);
+        for (i, (&a, &b)) in src.iter().zip(&compensated).enumerate() {
+            if i % 40 >= 37 {
+                continue;
+            }
+            let range = a.min(b)..=a.max(b);
+            assert!(range.contains(&averaged[i]), "{bits}-bit average at {i}");
+            assert!(
+                range.contains(&blended[i]),
+                "{bits}-bit blend at {i} with time {time}"
+            );
+        }
+    }
+}
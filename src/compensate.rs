@@ -0,0 +1,505 @@
+//! Motion compensation of a frame from its reference frame, similar to mvtools' MCompensate
+
+#[cfg(test)]
+mod tests;
+
+use std::num::{NonZeroU8, NonZeroUsize};
+
+use anyhow::{Result, anyhow, bail};
+use vapoursynth::{
+    core::CoreRef,
+    format::{ColorFamily, Format},
+    frame::{FrameRef, FrameRefMut},
+    node::Node,
+    plugins::{Filter, FrameContext},
+    prelude::{API, Property},
+    video_info::VideoInfo,
+};
+
+use crate::{
+    average::{BLEND_SCALE, blend2, time_weight},
+    compensation::{CompensationRef, overlap_blocks},
+    error::{ZooMvError, parse_param},
+    mv_clip::{DEFAULT_THSCD1, DEFAULT_THSCD2, MVClip, detect_scene_change, scale_thscd1},
+    mv_plane::SuperPlaneLayout,
+    params::{MVPlaneSet, SceneChangeBehavior, Subpel},
+    util::{Pixel, plane_with_padding, plane_with_padding_mut},
+    windows::{OverlapWindows, WindowMode},
+};
+
+/// Rebuilds each frame from the blocks of its reference frame that its vectors point to.
+///
+/// Blocks whose SAD is above `thsad` are taken from the source frame instead,
+/// and overlapping blocks are blended with [`OverlapWindows`].
+/// The compensated frame is then blended with the source frame according to `time`.
+/// Frames whose vectors are not usable, because the reference frame is outside of the clip
+/// or because of a scene change, are replaced according to [`SceneChangeBehavior`].
+#[derive(Debug)]
+pub struct Compensate<'core> {
+    /// Source clip
+    node: Node<'core>,
+    /// Super clip, used to fetch sub-pixel positions of the reference frame
+    super_: Node<'core>,
+    vectors: MVClip<'core>,
+    /// Block SAD above which the source block is used,
+    /// scaled to the block size and bit depth
+    thsad: u64,
+    scbehavior: SceneChangeBehavior,
+    /// Which planes are compensated, the others are copied from the source clip
+    planes: [bool; 3],
+    /// Block SAD above which a block has changed, scaled to the block size and bit depth
+    thscd1: u64,
+    /// How many out of every 256 blocks must change for a scene change
+    thscd2: u8,
+    /// Blending windows for the luma and chroma blocks
+    windows: [OverlapWindows; 2],
+    /// Weight of the compensated frame against the source frame, out of [`BLEND_SCALE`]
+    time_weight: u32,
+    super_hpad: usize,
+    super_vpad: usize,
+    pel: Subpel,
+    format: Format<'core>,
+    width: NonZeroUsize,
+    height: NonZeroUsize,
+    x_ratio_uv: NonZeroU8,
+    y_ratio_uv: NonZeroU8,
+}
+
+impl<'core> Compensate<'core> {
+    /// Creates a compensation filter for the vectors clip `vectors`.
+    ///
+    /// The chroma planes are only compensated if the super clip contains them,
+    /// and are copied from the source clip otherwise.
+    ///
+    /// `time` is the weight of the compensated frame out of 255, where 255 gives the fully
+    /// compensated frame and 0 the source frame.
+    pub fn new(
+        clip: Node<'core>,
+        super_: Node<'core>,
+        vectors: Node<'core>,
+        scbehavior: Option<i64>,
+        thsad: Option<i64>,
+        thscd1: Option<i64>,
+        thscd2: Option<i64>,
+        time: Option<i64>,
+    ) -> Result<Self> {
+        let scbehavior = parse_param(
+            "Compensate",
+            "scbehavior",
+            scbehavior,
+            SceneChangeBehavior::UseSource,
+            "0 or 1",
+        )?;
+        let thsad: u64 = parse_param("Compensate", "thsad", thsad, 10000, "at least 0")?;
+        let thscd1 = parse_param("Compensate", "thscd1", thscd1, DEFAULT_THSCD1, "at least 0")?;
+        let thscd2 = parse_param(
+            "Compensate",
+            "thscd2",
+            thscd2,
+            DEFAULT_THSCD2,
+            "between 0 and 255",
+        )?;
+        let time: u8 = parse_param("Compensate", "time", time, u8::MAX, "between 0 and 255")?;
+
+        let video_info = clip.info();
+        let variable = ZooMvError::VariableFormat {
+            filter: "Compensate",
+            clip: "clip",
+        };
+        let Property::Constant(resolution) = video_info.resolution else {
+            bail!(variable);
+        };
+        let width = NonZeroUsize::new(resolution.width).ok_or(variable)?;
+        let height = NonZeroUsize::new(resolution.height).ok_or(variable)?;
+        let format = video_info.format;
+        if ![ColorFamily::YUV, ColorFamily::Gray].contains(&format.color_family()) {
+            bail!(ZooMvError::UnsupportedFormat {
+                filter: "Compensate",
+                requirement: "be GRAY or YUV",
+            });
+        }
+
+        let vectors = MVClip::new(vectors, "Compensate")?;
+        let data = *vectors.analysis_data();
+        if data.width != width || data.height != height {
+            bail!(ZooMvError::IncompatibleClip {
+                filter: "Compensate",
+                clip: "vectors",
+                requirement: "have the same dimensions as the input clip",
+            });
+        }
+        if data.bits_per_sample.get() != format.bits_per_sample() {
+            bail!(ZooMvError::IncompatibleClip {
+                filter: "Compensate",
+                clip: "vectors",
+                requirement: "have the same bit depth as the input clip",
+            });
+        }
+        if vectors.node().info().num_frames < video_info.num_frames {
+            bail!(ZooMvError::IncompatibleClip {
+                filter: "Compensate",
+                clip: "vectors",
+                requirement: "have at least as many frames as the input clip",
+            });
+        }
+
+        let first = super_.get_frame(0).map_err(|e| {
+            anyhow!(
+                "Compensate: failed to retrieve first frame from super clip. Error message: {e}"
+            )
+        })?;
+        let super_props = first.props();
+        let super_props_err = ZooMvError::IncompatibleSuper {
+            filter: "Compensate",
+            reason: "required properties not found in first frame of super clip. Maybe clip \
+                     didn't come from mv.Super? Was the first frame trimmed away?",
+        };
+        let super_props_err2 = ZooMvError::IncompatibleSuper {
+            filter: "Compensate",
+            reason: "parameters from super clip appear to be wrong.",
+        };
+        let get_prop = |key: &str| -> Result<usize> {
+            let value = super_props.get_int(key).map_err(|_| super_props_err)?;
+            Ok(usize::try_from(value).map_err(|_| super_props_err2)?)
+        };
+        let super_hpad = get_prop("Super_hpad")?;
+        let super_vpad = get_prop("Super_vpad")?;
+        let super_pel = get_prop("Super_pel")?;
+        let super_mode_yuv =
+            MVPlaneSet::from_bits(get_prop("Super_modeyuv")? as u8).ok_or(super_props_err2)?;
+        if super_pel != NonZeroUsize::from(data.pel).get()
+            || super_hpad != data.h_padding
+            || super_vpad != data.v_padding
+        {
+            bail!(ZooMvError::IncompatibleSuper {
+                filter: "Compensate",
+                reason: "wrong super clip (pel or padding) used for vectors",
+            });
+        }
+        let chroma =
+            format.color_family() == ColorFamily::YUV && super_mode_yuv == MVPlaneSet::YUVPLANES;
+        let planes = [true, chroma, chroma];
+
+        let x_ratio_uv = data.x_ratio_uv;
+        let y_ratio_uv = data.y_ratio_uv;
+        let chroma_size = |size: NonZeroUsize, ratio: NonZeroU8| {
+            NonZeroUsize::new(size.get() / ratio.get() as usize).ok_or(
+                ZooMvError::IncompatibleClip {
+                    filter: "Compensate",
+                    clip: "vectors",
+                    requirement: "have blocks of at least one chroma sample",
+                },
+            )
+        };
+        let windows = [
+            OverlapWindows::new(
+                data.blk_size_x,
+                data.blk_size_y,
+                data.overlap_x,
+                data.overlap_y,
+                WindowMode::Cosine,
+            )?,
+            OverlapWindows::new(
+                chroma_size(data.blk_size_x, x_ratio_uv)?,
+                chroma_size(data.blk_size_y, y_ratio_uv)?,
+                data.overlap_x / x_ratio_uv.get() as usize,
+                data.overlap_y / y_ratio_uv.get() as usize,
+                WindowMode::Cosine,
+            )?,
+        ];
+
+        // Scene changes are detected on the searched blocks
+        let thscd1 = scale_thscd1(thscd1, &vectors.base_analysis_data());
+
+        Ok(Self {
+            node: clip,
+            super_,
+            // The threshold is given for an 8x8 block with 8-bit samples
+            thsad: scale_thscd1(thsad, &data),
+            vectors,
+            scbehavior,
+            planes,
+            thscd1,
+            thscd2,
+            windows,
+            time_weight: time_weight(time),
+            super_hpad,
+            super_vpad,
+            pel: data.pel,
+            format,
+            width,
+            height,
+            x_ratio_uv,
+            y_ratio_uv,
+        })
+    }
+
+    /// Finds the frame referenced by the vectors for source frame `n`,
+    /// or `None` if it is outside of the clip.
+    fn reference_frame(&self, n: usize) -> Option<usize> {
+        let data = self.vectors.analysis_data();
+        let delta = data.delta_frame.unsigned_abs();
+        let reference = if data.is_backward {
+            n + delta
+        } else {
+            n.checked_sub(delta)?
+        };
+        (reference < self.node.info().num_frames).then_some(reference)
+    }
+
+    fn get_frame_internal<T: Pixel>(
+        &self,
+        core: CoreRef<'core>,
+        context: FrameContext,
+        n: usize,
+    ) -> Result<FrameRef<'core>> {
+        let src = self
+            .node
+            .get_frame_filter(context, n)
+            .ok_or_else(|| anyhow!("Compensate: get_frame_filter past end of video"))?;
+        let Some(reference) = self.reference_frame(n) else {
+            return Ok(src);
+        };
+        let vectors_frame = self
+            .vectors
+            .node()
+            .get_frame_filter(context, n)
+            .ok_or_else(|| anyhow!("Compensate: get_frame_filter vectors past end of video"))?;
+        // Scene changes are detected on the searched blocks, whose SADs
+        // the subblocks of `divide` only share out
+        let scene_change = self
+            .vectors
+            .base_vectors(&vectors_frame)
+            .map_err(|e| anyhow!("Compensate: {e}"))?
+            .is_none_or(|vectors| detect_scene_change(&vectors, self.thscd1, self.thscd2));
+        let vectors = self
+            .vectors
+            .finest_vectors(&vectors_frame)
+            .map_err(|e| anyhow!("Compensate: {e}"))?
+            .filter(|_| !scene_change);
+        let Some(vectors) = vectors else {
+            return match self.scbehavior {
+                SceneChangeBehavior::UseReference => self
+                    .node
+                    .get_frame_filter(context, reference)
+                    .ok_or_else(|| anyhow!("Compensate: get_frame_filter past end of video")),
+                SceneChangeBehavior::UseSource => Ok(src),
+            };
+        };
+        let super_frame = self
+            .super_
+            .get_frame_filter(context, reference)
+            .ok_or_else(|| anyhow!("Compensate: get_frame_filter super past end of video"))?;
+
+        let mut dest = FrameRefMut::copy_of(core, &src);
+        let data = self.vectors.analysis_data();
+        for plane in 0..self.format.plane_count() {
+            if !self.planes[plane] {
+                continue;
+            }
+            let is_chroma = plane > 0;
+            let (width, height, x_ratio, y_ratio) = if is_chroma {
+                // SAFETY: Width and height must be at least the value of their ratio
+                unsafe {
+                    (
+                        NonZeroUsize::new_unchecked(
+                            self.width.get() / self.x_ratio_uv.get() as usize,
+                        ),
+                        NonZeroUsize::new_unchecked(
+                            self.height.get() / self.y_ratio_uv.get() as usize,
+                        ),
+                        self.x_ratio_uv,
+                        self.y_ratio_uv,
+                    )
+                }
+            } else {
+                (self.width, self.height, NonZeroU8::MIN, NonZeroU8::MIN)
+            };
+
+            let layout = SuperPlaneLayout::new(
+                is_chroma,
+                self.width,
+                self.height,
+                0,
+                self.pel,
+                self.super_hpad,
+                self.super_vpad,
+                self.x_ratio_uv,
+                self.y_ratio_uv,
+                // SAFETY: stride must be at least width and non-zero
+                unsafe { NonZeroUsize::new_unchecked(super_frame.stride(plane) / size_of::<T>()) },
+            );
+            let reference = CompensationRef {
+                plane: plane_with_padding::<T>(&super_frame, plane)?,
+                layout: &layout,
+                vectors: &vectors,
+                thsad: self.thsad,
+            };
+
+            // SAFETY: stride must be at least width and non-zero
+            let src_pitch =
+                unsafe { NonZeroUsize::new_unchecked(src.stride(plane) / size_of::<T>()) };
+            compensate_plane(
+                plane_with_padding_mut::<T>(&mut dest, plane)?,
+                src_pitch,
+                plane_with_padding::<T>(&src, plane)?,
+                src_pitch,
+                width,
+                height,
+                &reference,
+                &self.windows[usize::from(is_chroma)],
+                data.blk_x,
+                data.blk_y,
+                x_ratio,
+                y_ratio,
+                self.pel,
+                self.time_weight,
+            );
+        }
+
+        Ok(dest.into())
+    }
+}
+
+impl<'core> Filter<'core> for Compensate<'core> {
+    fn video_info(&self, _api: API, _core: CoreRef<'core>) -> Vec<VideoInfo<'core>> {
+        vec![self.node.info()]
+    }
+
+    fn get_frame_initial(
+        &self,
+        _api: API,
+        _core: CoreRef<'core>,
+        context: FrameContext,
+        n: usize,
+    ) -> Result<Option<FrameRef<'core>>> {
+        self.node.request_frame_filter(context, n);
+        if let Some(reference) = self.reference_frame(n) {
+            self.vectors.node().request_frame_filter(context, n);
+            self.super_.request_frame_filter(context, reference);
+            if self.scbehavior == SceneChangeBehavior::UseReference {
+                self.node.request_frame_filter(context, reference);
+            }
+        }
+        Ok(None)
+    }
+
+    fn get_frame(
+        &self,
+        _api: API,
+        core: CoreRef<'core>,
+        context: FrameContext,
+        n: usize,
+    ) -> Result<FrameRef<'core>> {
+        match self.format.bytes_per_sample() {
+            1 => self.get_frame_internal::<u8>(core, context, n),
+            2 => self.get_frame_internal::<u16>(core, context, n),
+            _ => bail!(ZooMvError::UnsupportedBitDepth {
+                filter: "Compensate",
+                bits: self.format.bits_per_sample(),
+            }),
+        }
+    }
+}
+
+/// Compensates one plane by taking each block from `reference` where its vector points to.
+///
+/// Blocks whose SAD is above the `thsad` of `reference` are taken from `src` instead.
+/// The blocks of a `blk_x` by `blk_y` grid are blended with `windows`,
+/// and pixels not covered by any block are copied from `src`.
+///
+/// `x_ratio` and `y_ratio` are the subsampling ratios of the plane relative to luma.
+///
+/// The compensated plane is blended with `src` by [`blend2`] with a weight of `time_weight`,
+/// which requires `dest_pitch` and `src_pitch` to be equal unless it is [`BLEND_SCALE`].
+pub(crate) fn compensate_plane<T: Pixel>(
+    dest: &mut [T],
+    dest_pitch: NonZeroUsize,
+    src: &[T],
+    src_pitch: NonZeroUsize,
+    width: NonZeroUsize,
+    height: NonZeroUsize,
+    reference: &CompensationRef<'_, T>,
+    windows: &OverlapWindows,
+    blk_x: NonZeroUsize,
+    blk_y: NonZeroUsize,
+    x_ratio: NonZeroU8,
+    y_ratio: NonZeroU8,
+    pel: Subpel,
+    time_weight: u32,
+) {
+    if time_weight == BLEND_SCALE {
+        compensate_blocks(
+            dest, dest_pitch, src, src_pitch, width, height, reference, windows, blk_x, blk_y,
+            x_ratio, y_ratio, pel,
+        );
+        return;
+    }
+
+    debug_assert_eq!(dest_pitch, src_pitch);
+    let mut compensated = dest.to_vec();
+    compensate_blocks(
+        &mut compensated,
+        dest_pitch,
+        src,
+        src_pitch,
+        width,
+        height,
+        reference,
+        windows,
+        blk_x,
+        blk_y,
+        x_ratio,
+        y_ratio,
+        pel,
+    );
+    blend2(
+        dest,
+        src,
+        &compensated,
+        dest_pitch,
+        width,
+        height,
+        time_weight,
+    );
+}
+
+/// Compensates one plane as in [`compensate_plane`], without blending it with `src`.
+fn compensate_blocks<T: Pixel>(
+    dest: &mut [T],
+    dest_pitch: NonZeroUsize,
+    src: &[T],
+    src_pitch: NonZeroUsize,
+    width: NonZeroUsize,
+    height: NonZeroUsize,
+    reference: &CompensationRef<'_, T>,
+    windows: &OverlapWindows,
+    blk_x: NonZeroUsize,
+    blk_y: NonZeroUsize,
+    x_ratio: NonZeroU8,
+    y_ratio: NonZeroU8,
+    pel: Subpel,
+) {
+    // The vector of the current block, or `None` to take it from `src`
+    let mut current = None;
+    overlap_blocks(
+        dest,
+        dest_pitch,
+        src,
+        src_pitch,
+        width,
+        height,
+        windows,
+        blk_x,
+        blk_y,
+        &mut current,
+        |current, block| {
+            let (mv_x, mv_y, sad) = reference.vector(block, x_ratio, y_ratio);
+            *current = (sad <= reference.thsad).then_some((mv_x, mv_y));
+        },
+        |current, x, y| match *current {
+            Some((mv_x, mv_y)) => reference.sample(pel, x, y, mv_x, mv_y),
+            None => src[y * src_pitch.get() + x].to_u64().expect("fits in u64"),
+        },
+    );
+}
@@ -0,0 +1,169 @@
+#![allow(clippy::unwrap_used, reason = "allow in test files")]
+#![allow(clippy::undocumented_unsafe_blocks, reason = "allow in test files")]
+
+use super::*;
+use crate::{mv::MotionVector, tests::planes::*};
+
+fn compensate(src: &[u8], reference: &CompensationRef<'_, u8>) -> Vec<u8> {
+    compensate_with(src, reference, false, Subpel::Full, u8::MAX)
+}
+
+/// Compensates the luma plane, or a 4:2:0 chroma plane if `chroma` is set.
+fn compensate_with(
+    src: &[u8],
+    reference: &CompensationRef<'_, u8>,
+    chroma: bool,
+    pel: Subpel,
+    time: u8,
+) -> Vec<u8> {
+    let ratio = if chroma { 2 } else { 1 };
+    let windows = OverlapWindows::new(
+        NonZeroUsize::new(BLK_SIZE / ratio).unwrap(),
+        NonZeroUsize::new(BLK_SIZE / ratio).unwrap(),
+        OVERLAP / ratio,
+        OVERLAP / ratio,
+        WindowMode::Cosine,
+    )
+    .unwrap();
+    let pitch = NonZeroUsize::new(WIDTH / ratio).unwrap();
+    let mut dest = vec![0u8; WIDTH * HEIGHT / ratio / ratio];
+    compensate_plane(
+        &mut dest,
+        pitch,
+        src,
+        pitch,
+        pitch,
+        NonZeroUsize::new(HEIGHT / ratio).unwrap(),
+        reference,
+        &windows,
+        NonZeroUsize::new(BLK_X).unwrap(),
+        NonZeroUsize::new(BLK_Y).unwrap(),
+        NonZeroU8::new(ratio as u8).unwrap(),
+        NonZeroU8::new(ratio as u8).unwrap(),
+        pel,
+        time_weight(time),
+    );
+    dest
+}
+
+#[test]
+fn follows_vectors_into_reference() {
+    // The reference is the source moved right by 2 and down by 1
+    let src = plane(pattern);
+    let reference = padded_plane(|x, y| pattern(x - 2, y - 1));
+    let layout = layout();
+    let vectors = vec![MotionVector { x: 2, y: 1, sad: 0 }; BLK_X * BLK_Y];
+    let reference = CompensationRef {
+        plane: &reference,
+        layout: &layout,
+        vectors: &vectors,
+        thsad: 400,
+    };
+
+    assert_eq!(compensate(&plane(|_, _| 0), &reference), src);
+}
+
+#[test]
+fn blocks_above_thsad_use_source() {
+    let src = plane(pattern);
+    let reference = padded_plane(|x, y| pattern(x, y) / 2);
+    let layout = layout();
+    let matching = vec![
+        MotionVector {
+            x: 0,
+            y: 0,
+            sad: 400,
+        };
+        BLK_X * BLK_Y
+    ];
+    let mismatched = vec![
+        MotionVector {
+            x: 0,
+            y: 0,
+            sad: 401,
+        };
+        BLK_X * BLK_Y
+    ];
+    let reference = CompensationRef {
+        plane: &reference,
+        layout: &layout,
+        vectors: &matching,
+        thsad: 400,
+    };
+    assert_eq!(
+        compensate(&src, &reference),
+        plane(|x, y| pattern(x, y) / 2)
+    );
+
+    let reference = CompensationRef {
+        vectors: &mismatched,
+        ..reference
+    };
+    assert_eq!(compensate(&src, &reference), src);
+}
+
+#[test]
+fn chroma_follows_subsampled_vectors() {
+    // The luma vectors move by 4 and 2, which is 2 and 1 in 4:2:0 chroma
+    let layout = super_layout(true, WIDTH, HEIGHT, Subpel::Full);
+    let reference = super_plane(&layout, Subpel::Full, |x, y| pattern(x - 2, y - 1));
+    let vectors = vec![MotionVector { x: 4, y: 2, sad: 0 }; BLK_X * BLK_Y];
+    let reference = CompensationRef {
+        plane: &reference,
+        layout: &layout,
+        vectors: &vectors,
+        thsad: 400,
+    };
+
+    let src = sized_plane(WIDTH / 2, HEIGHT / 2, |_, _| 0);
+    assert_eq!(
+        compensate_with(&src, &reference, true, Subpel::Full, u8::MAX),
+        sized_plane(WIDTH / 2, HEIGHT / 2, pattern)
+    );
+}
+
+#[test]
+fn subpel_vectors_fetch_between_pixels() {
+    // Each half-pel position has its own value, so the exact sub-pixel window must be used
+    let half_pel = |x: i32, y: i32| (100 + x * 3 + y * 7) as u8;
+    let layout = super_layout(false, WIDTH, HEIGHT, Subpel::Half);
+    let reference = super_plane(&layout, Subpel::Half, half_pel);
+    let vectors = vec![
+        MotionVector {
+            x: 3,
+            y: -1,
+            sad: 0
+        };
+        BLK_X * BLK_Y
+    ];
+    let reference = CompensationRef {
+        plane: &reference,
+        layout: &layout,
+        vectors: &vectors,
+        thsad: 400,
+    };
+
+    assert_eq!(
+        compensate_with(&plane(|_, _| 0), &reference, false, Subpel::Half, u8::MAX),
+        plane(|x, y| half_pel(x * 2 + 3, y * 2 - 1))
+    );
+}
+
+#[test]
+fn time_blends_with_source() {
+    let src = plane(|_, _| 40);
+    let reference = padded_plane(|_, _| 200);
+    let layout = layout();
+    let vectors = vec![MotionVector { x: 0, y: 0, sad: 0 }; BLK_X * BLK_Y];
+    let reference = CompensationRef {
+        plane: &reference,
+        layout: &layout,
+        vectors: &vectors,
+        thsad: 400,
+    };
+
+    let blend = |time| compensate_with(&src, &reference, false, Subpel::Full, time);
+    assert_eq!(blend(0), src);
+    assert_eq!(blend(128), plane(|_, _| 120));
+    assert_eq!(blend(255), plane(|_, _| 200));
+}
@@ -0,0 +1,119 @@
+//! Building blocks shared by the filters that rebuild a plane from motion compensated blocks,
+//! i.e. [`Compensate`](crate::compensate::Compensate) and [`Degrain`](crate::degrain::Degrain)
+
+use std::num::{NonZeroU8, NonZeroUsize};
+
+use crate::{
+    flow_fps::sample,
+    mv::MotionVector,
+    mv_plane::SuperPlaneLayout,
+    params::Subpel,
+    util::Pixel,
+    windows::{OverlapWindows, WindowPosition},
+};
+
+/// A reference frame that the blocks of one plane are compensated from.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct CompensationRef<'a, T> {
+    /// The plane of the reference frame's superframe
+    pub plane: &'a [T],
+    /// Layout of the level 0 sub-pixel windows within `plane`
+    pub layout: &'a SuperPlaneLayout,
+    /// Finest level vectors from the source frame to the reference, in row-major block order
+    pub vectors: &'a [MotionVector],
+    /// SAD threshold of the reference's blocks, scaled to the block size and bit depth.
+    /// What it means is up to the filter.
+    pub thsad: u64,
+}
+
+impl<T: Pixel> CompensationRef<'_, T> {
+    /// The vector of block `block`, in `pel` units of a plane with the given subsampling ratios,
+    /// along with its SAD.
+    #[must_use]
+    pub fn vector(&self, block: usize, x_ratio: NonZeroU8, y_ratio: NonZeroU8) -> (i32, i32, u64) {
+        let mv = self.vectors[block];
+        (
+            mv.x >> x_ratio.trailing_zeros(),
+            mv.y >> y_ratio.trailing_zeros(),
+            mv.sad.max(0) as u64,
+        )
+    }
+
+    /// Fetches the pixel at `(x, y)` of the source plane, moved by `(mv_x, mv_y)`.
+    ///
+    /// The vector is in `pel` units, as returned by [`Self::vector`].
+    #[must_use]
+    pub fn sample(&self, pel: Subpel, x: usize, y: usize, mv_x: i32, mv_y: i32) -> u64 {
+        let pel_val = i32::from(u8::from(pel));
+        sample(
+            self.plane,
+            self.layout,
+            pel,
+            x as i32 * pel_val + mv_x,
+            y as i32 * pel_val + mv_y,
+        )
+        .to_u64()
+        .expect("fits in u64")
+    }
+}
+
+/// Rebuilds one plane from overlapping blocks of a `blk_x` by `blk_y` grid.
+///
+/// For each block in row-major order, `start_block` is called with the block's index to
+/// update `state`, then `pixel` gives the value of each of its pixels from `state` and the
+/// pixel's coordinates in the plane. The blocks are blended with `windows`,
+/// and pixels not covered by any block are copied from `src`.
+pub(crate) fn overlap_blocks<T: Pixel, S>(
+    dest: &mut [T],
+    dest_pitch: NonZeroUsize,
+    src: &[T],
+    src_pitch: NonZeroUsize,
+    width: NonZeroUsize,
+    height: NonZeroUsize,
+    windows: &OverlapWindows,
+    blk_x: NonZeroUsize,
+    blk_y: NonZeroUsize,
+    state: &mut S,
+    mut start_block: impl FnMut(&mut S, usize),
+    pixel: impl Fn(&S, usize, usize) -> u64,
+) {
+    let blk_w = windows.blk_size_x().get();
+    let blk_h = windows.blk_size_y().get();
+    let step_x = blk_w - windows.overlap_x();
+    let step_y = blk_h - windows.overlap_y();
+
+    // Window-weighted sums of the blocks and of their windows, for each pixel
+    let mut sum = vec![0u64; width.get() * height.get()];
+    let mut total = vec![0u64; width.get() * height.get()];
+    for by in 0..blk_y.get() {
+        for bx in 0..blk_x.get() {
+            start_block(state, by * blk_x.get() + bx);
+
+            let window = windows.get(WindowPosition::from_block(bx, by, blk_x, blk_y));
+            let x0 = bx * step_x;
+            let y0 = by * step_y;
+            for j in 0..blk_h.min(height.get().saturating_sub(y0)) {
+                let y = y0 + j;
+                for i in 0..blk_w.min(width.get().saturating_sub(x0)) {
+                    let x = x0 + i;
+                    let window_weight = u64::from(window[j * blk_w + i]);
+                    sum[y * width.get() + x] += pixel(state, x, y) * window_weight;
+                    total[y * width.get() + x] += window_weight;
+                }
+            }
+        }
+    }
+
+    for y in 0..height.get() {
+        let dest_row = &mut dest[y * dest_pitch.get()..][..width.get()];
+        let src_row = &src[y * src_pitch.get()..][..width.get()];
+        for (x, (dest_pixel, &src_pixel)) in dest_row.iter_mut().zip(src_row).enumerate() {
+            let i = y * width.get() + x;
+            *dest_pixel = if total[i] > 0 {
+                T::from_u32_or_max_value(((sum[i] + total[i] / 2) / total[i]) as u32)
+            } else {
+                src_pixel
+            };
+        }
+    }
+}
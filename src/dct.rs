@@ -1,7 +1,18 @@
-use std::num::{NonZeroU8, NonZeroUsize};
+//! Block DCT, used by `Analyse` to compare blocks in the frequency domain
+
+#[cfg(test)]
+mod tests;
+
+use std::{
+    f64::consts::PI,
+    fmt,
+    num::{NonZeroU8, NonZeroUsize},
+    sync::{Mutex, PoisonError},
+};
 
 use anyhow::Result;
 use fftw::{
+    array::AlignedVec,
     plan::{R2RPlan, R2RPlan32},
     types::{Flag, R2RKind},
 };
@@ -9,6 +20,56 @@ use num_traits::clamp;
 
 use crate::util::{Pixel, round_ties_to_even};
 
+/// Fractional bits of the cosine tables used by [`DctKind::Integer`]
+const COS_BITS: u32 = 14;
+/// Fractional bits kept after the second pass of [`DctKind::Integer`],
+/// before scaling by `sqrt(2) / 2`
+const SCALE_BITS: u32 = 15;
+/// `sqrt(2) / 2` with [`SCALE_BITS`] fractional bits
+const SQRT_HALF: i64 = 23_170;
+
+/// How [`DctHelper`] computes the transform.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DctKind {
+    /// FFTW's single precision DCT, as in mvtools' `DCTFFTW`
+    Float,
+    /// A separable fixed-point DCT, whose output only depends on the input block,
+    /// regardless of the FFTW build or the SIMD instructions it selects.
+    /// Coefficients are within 1 of [`DctKind::Float`] before they are scaled down.
+    ///
+    /// Selected by `Analyse`'s `fixed_dct` parameter.
+    Integer,
+}
+
+enum Transform {
+    /// The `fftw` crate passes a single kind to multi-dimensional plans, where FFTW expects
+    /// one kind per dimension, so the 2D DCT is done as a pass over the rows
+    /// followed by a pass over the columns.
+    Float {
+        row_plan: R2RPlan32,
+        column_plan: R2RPlan32,
+        row: AlignedVec<f32>,
+        row_dct: AlignedVec<f32>,
+        column: AlignedVec<f32>,
+        column_dct: AlignedVec<f32>,
+        /// The DCT of each row
+        rows: Box<[f32]>,
+        src_dct: Box<[f32]>,
+    },
+    Integer {
+        /// [`cos_table`] of `size_x`
+        cos_x: Box<[i64]>,
+        /// [`cos_table`] of `size_y`
+        cos_y: Box<[i64]>,
+        /// The DCT of each row, with [`COS_BITS`] fractional bits
+        rows: Box<[i64]>,
+    },
+}
+
+/// Computes the 2D DCT of a block, scaled to fit in its pixel type.
+///
+/// Equivalent to mvtools' `DCTFFTW`. [`DctKind::Integer`] computes the same transform
+/// in fixed point.
 pub struct DctHelper {
     size_x: NonZeroUsize,
     size_y: NonZeroUsize,
@@ -16,9 +77,18 @@ pub struct DctHelper {
     dct_shift: usize,
     dct_shift0: usize,
 
-    plan: R2RPlan32,
-    src: Box<[f32]>,
-    src_dct: Box<[f32]>,
+    transform: Transform,
+    /// The unnormalized DCT-II coefficients, with the AC coefficients multiplied by
+    /// `sqrt(2) / 2` and the DC coefficient halved, as in mvtools
+    scaled: Box<[i64]>,
+}
+
+/// Each clone gets its own FFTW plan and buffers, so clones can be used on different threads.
+impl Clone for DctHelper {
+    fn clone(&self) -> Self {
+        Self::new(self.size_x, self.size_y, self.bits_per_sample, self.kind())
+            .expect("a plan with the same sizes was already created")
+    }
 }
 
 impl DctHelper {
@@ -26,6 +96,7 @@ impl DctHelper {
         size_x: NonZeroUsize,
         size_y: NonZeroUsize,
         bits_per_sample: NonZeroU8,
+        kind: DctKind,
     ) -> Result<Self> {
         let size_2d = size_y.saturating_mul(size_x);
         let mut cur_size = 1usize;
@@ -36,25 +107,57 @@ impl DctHelper {
         }
         let dct_shift0 = dct_shift + 2;
 
-        let src = vec![0.0; size_2d.get()].into_boxed_slice();
-        let src_dct = vec![0.0; size_2d.get()].into_boxed_slice();
+        let transform = match kind {
+            DctKind::Float => Transform::Float {
+                row_plan: R2RPlan32::aligned(
+                    &[size_x.get()],
+                    R2RKind::FFTW_REDFT10,
+                    Flag::ESTIMATE,
+                )?,
+                column_plan: R2RPlan32::aligned(
+                    &[size_y.get()],
+                    R2RKind::FFTW_REDFT10,
+                    Flag::ESTIMATE,
+                )?,
+                row: AlignedVec::new(size_x.get()),
+                row_dct: AlignedVec::new(size_x.get()),
+                column: AlignedVec::new(size_y.get()),
+                column_dct: AlignedVec::new(size_y.get()),
+                rows: vec![0.0; size_2d.get()].into_boxed_slice(),
+                src_dct: vec![0.0; size_2d.get()].into_boxed_slice(),
+            },
+            DctKind::Integer => Transform::Integer {
+                cos_x: cos_table(size_x),
+                cos_y: cos_table(size_y),
+                rows: vec![0; size_2d.get()].into_boxed_slice(),
+            },
+        };
         let this = DctHelper {
             size_x,
             size_y,
             bits_per_sample,
             dct_shift,
             dct_shift0,
-            plan: R2RPlan32::aligned(
-                &[size_x.get(), size_y.get()],
-                R2RKind::FFTW_REDFT10,
-                Flag::ESTIMATE,
-            )?,
-            src,
-            src_dct,
+            transform,
+            scaled: vec![0; size_2d.get()].into_boxed_slice(),
         };
         Ok(this)
     }
 
+    #[must_use]
+    pub fn kind(&self) -> DctKind {
+        match self.transform {
+            Transform::Float { .. } => DctKind::Float,
+            Transform::Integer { .. } => DctKind::Integer,
+        }
+    }
+
+    /// Writes the DCT of the block at the start of `src_plane` to `dct_plane`.
+    ///
+    /// The coefficients are scaled down by the block area and offset by half of the pixel range,
+    /// so a block without any detail has all AC coefficients at the middle value.
+    /// The DC coefficient at index 0 is reduced further, to half of the block's mean,
+    /// again offset by half of the pixel range.
     pub fn bytes_2d<T: Pixel>(
         &mut self,
         src_plane: &[T],
@@ -62,48 +165,208 @@ impl DctHelper {
         dct_plane: &mut [T],
         dct_pitch: NonZeroUsize,
     ) -> Result<()> {
-        self.pixels_to_float_src(src_plane, src_pitch);
-        self.plan.r2r(&mut self.src, &mut self.src_dct)?;
-        self.float_src_to_pixels(dct_plane, dct_pitch);
+        let size_x = self.size_x.get();
+        let size_y = self.size_y.get();
+        match &mut self.transform {
+            Transform::Float {
+                row_plan,
+                column_plan,
+                row,
+                row_dct,
+                column,
+                column_dct,
+                rows,
+                src_dct,
+            } => {
+                for (j, dct) in rows.chunks_exact_mut(size_x).enumerate() {
+                    let pixels = &src_plane[j * src_pitch.get()..][..size_x];
+                    for (f, pixel) in row.iter_mut().zip(pixels) {
+                        *f = pixel.to_f32().expect("fits in f32");
+                    }
+                    row_plan.r2r(row, row_dct)?;
+                    dct.copy_from_slice(row_dct);
+                }
+                for i in 0..size_x {
+                    for (f, &dct) in column.iter_mut().zip(rows.iter().skip(i).step_by(size_x)) {
+                        *f = dct;
+                    }
+                    column_plan.r2r(column, column_dct)?;
+                    for (dct, &f) in src_dct
+                        .iter_mut()
+                        .skip(i)
+                        .step_by(size_x)
+                        .zip(column_dct.iter())
+                    {
+                        *dct = f;
+                    }
+                }
 
-        Ok(())
-    }
+                let sqrt_2_div_2: f32 = (2f32).sqrt() / 2.0;
+                for (scaled, &f) in self.scaled.iter_mut().zip(src_dct.iter()) {
+                    // to be compatible with integer DCTINT8
+                    *scaled = round_ties_to_even(f * sqrt_2_div_2) as i64;
+                }
+                // to be compatible with integer DCTINT8
+                self.scaled[0] = round_ties_to_even(src_dct[0] * 0.5) as i64;
+            }
+            Transform::Integer { cos_x, cos_y, rows } => {
+                for (j, dct) in rows.chunks_exact_mut(size_x).enumerate() {
+                    let pixels = &src_plane[j * src_pitch.get()..][..size_x];
+                    for (coefficient, cos) in dct.iter_mut().zip(cos_x.chunks_exact(size_x)) {
+                        *coefficient = pixels
+                            .iter()
+                            .zip(cos)
+                            .map(|(&pixel, &cos)| pixel.to_i64().expect("fits in i64") * cos)
+                            .sum();
+                    }
+                }
 
-    fn pixels_to_float_src<T: Pixel>(&mut self, src_plane: &[T], src_pitch: NonZeroUsize) {
-        for j in 0..(self.size_y.get()) {
-            let f_src = &mut self.src[j * self.size_x.get()..][..self.size_x.get()];
-            let p_src = &src_plane[j * src_pitch.get()..][..self.size_x.get()];
-            for (f, p) in f_src.iter_mut().zip(p_src.iter()) {
-                *f = p.to_f32().expect("fits in f32");
+                // Both passes leave `2 * COS_BITS` fractional bits, which are reduced to
+                // `SCALE_BITS` so that multiplying by `SQRT_HALF` cannot overflow
+                let reduce = 2 * COS_BITS - SCALE_BITS;
+                for (k, cos) in cos_y.chunks_exact(size_y).enumerate() {
+                    for i in 0..size_x {
+                        let sum: i64 = rows
+                            .iter()
+                            .skip(i)
+                            .step_by(size_x)
+                            .zip(cos)
+                            .map(|(&row, &cos)| row * cos)
+                            .sum();
+                        let index = k * size_x + i;
+                        self.scaled[index] = if index == 0 {
+                            round_shift(sum, 2 * COS_BITS + 1)
+                        } else {
+                            round_shift(round_shift(sum, reduce) * SQRT_HALF, 2 * SCALE_BITS)
+                        };
+                    }
+                }
             }
         }
-    }
+        self.scaled_to_pixels(dct_plane, dct_pitch);
 
-    fn float_src_to_pixels<T: Pixel>(&self, dst: &mut [T], dst_pitch: NonZeroUsize) {
-        let sqrt_2_div_2: f32 = (2f32).sqrt() / 2.0;
-        let real_data = &self.src_dct;
+        Ok(())
+    }
 
+    fn scaled_to_pixels<T: Pixel>(&self, dst: &mut [T], dst_pitch: NonZeroUsize) {
         // Have to do math in larger type to avoid overflow
-        let pixel_max = (1 << self.bits_per_sample.get() as usize) - 1;
-        let pixel_half = 1 << (self.bits_per_sample.get() as usize - 1);
+        let pixel_max: i64 = (1 << self.bits_per_sample.get() as usize) - 1;
+        let pixel_half: i64 = 1 << (self.bits_per_sample.get() as usize - 1);
 
         for j in 0..(self.size_y.get()) {
-            let real_data = &real_data[j * self.size_x.get()..][..self.size_x.get()];
+            let scaled = &self.scaled[j * self.size_x.get()..][..self.size_x.get()];
             let dst = &mut dst[j * dst_pitch.get()..][..self.size_x.get()];
-            for (f, p) in real_data.iter().zip(dst.iter_mut()) {
-                // to be compatible with integer DCTINT8
-                let f = *f * sqrt_2_div_2;
-                let integ = round_ties_to_even(f) as i32;
+            for (&integ, p) in scaled.iter().zip(dst.iter_mut()) {
                 *p = T::from(clamp((integ >> self.dct_shift) + pixel_half, 0, pixel_max))
                     .expect("clamp guarantees in range");
             }
         }
 
-        // to be compatible with integer DCTINT8
-        let f = real_data[0] * 0.5;
-        let integ = round_ties_to_even(f) as i32;
         // DC
-        dst[0] = T::from(clamp((integ >> self.dct_shift0) + pixel_half, 0, pixel_max))
-            .expect("clamp guarantees in range");
+        dst[0] = T::from(clamp(
+            (self.scaled[0] >> self.dct_shift0) + pixel_half,
+            0,
+            pixel_max,
+        ))
+        .expect("clamp guarantees in range");
+    }
+}
+
+/// A pool of [`DctHelper`]s shared by all frame requests of a filter instance,
+/// so that searches reuse the FFTW plans instead of creating them for every level of every frame.
+///
+/// All helpers of a pool are of the same kind, and are keyed by their block size and bit depth.
+pub struct DctPool {
+    kind: DctKind,
+    free: Mutex<Vec<DctHelper>>,
+}
+
+/// A pool of [`DctKind::Float`] helpers
+impl Default for DctPool {
+    fn default() -> Self {
+        Self::new(DctKind::Float)
+    }
+}
+
+/// FFTW plans have no useful debug output
+impl fmt::Debug for DctPool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DctPool")
+            .field("kind", &self.kind)
+            .finish_non_exhaustive()
     }
 }
+
+impl DctPool {
+    #[must_use]
+    pub fn new(kind: DctKind) -> Self {
+        Self {
+            kind,
+            free: Mutex::default(),
+        }
+    }
+
+    /// Takes a helper for the given block size and bit depth out of the pool,
+    /// or creates a new one if none is available.
+    pub fn take(
+        &self,
+        size_x: NonZeroUsize,
+        size_y: NonZeroUsize,
+        bits_per_sample: NonZeroU8,
+    ) -> Result<DctHelper> {
+        let reused = {
+            // Helpers hold no state between blocks, so the pool remains usable
+            // even if another request panicked while holding the lock.
+            let mut free = self.free.lock().unwrap_or_else(PoisonError::into_inner);
+            free.iter()
+                .position(|helper| {
+                    helper.size_x == size_x
+                        && helper.size_y == size_y
+                        && helper.bits_per_sample == bits_per_sample
+                })
+                .map(|i| free.swap_remove(i))
+        };
+        reused.map_or_else(
+            || DctHelper::new(size_x, size_y, bits_per_sample, self.kind),
+            Ok,
+        )
+    }
+
+    /// Returns a helper to the pool, to be reused by a later search.
+    pub fn give_back(&self, helper: DctHelper) {
+        debug_assert_eq!(helper.kind(), self.kind);
+        self.free
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(helper);
+    }
+
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.free
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .len()
+    }
+}
+
+/// The DCT-II basis of FFTW's unnormalized `REDFT10` for `size` samples,
+/// with [`COS_BITS`] fractional bits.
+///
+/// Rounding is symmetric around zero, so the basis functions keep their symmetry,
+/// and a flat block still has no AC coefficients.
+fn cos_table(size: NonZeroUsize) -> Box<[i64]> {
+    let size = size.get();
+    (0..size * size)
+        .map(|index| {
+            let (k, n) = (index / size, index % size);
+            let angle = PI * ((2 * n + 1) * k) as f64 / (2 * size) as f64;
+            (2.0 * angle.cos() * f64::from(1 << COS_BITS)).round() as i64
+        })
+        .collect()
+}
+
+/// Divides `value` by `2^shift`, rounding halves up.
+fn round_shift(value: i64, shift: u32) -> i64 {
+    (value + (1 << (shift - 1))) >> shift
+}
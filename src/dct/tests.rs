@@ -0,0 +1,159 @@
+#![allow(clippy::unwrap_used, reason = "allow in test files")]
+#![allow(clippy::undocumented_unsafe_blocks, reason = "allow in test files")]
+
+use std::num::{NonZeroU8, NonZeroUsize};
+
+use rand::{Rng, SeedableRng};
+use rand_xoshiro::Xoshiro128StarStar;
+
+use super::*;
+
+const BLOCK_SIZES: [(usize, usize); 6] = [(4, 4), (8, 4), (8, 8), (16, 2), (16, 8), (32, 16)];
+
+fn helper(width: usize, height: usize, bits: u8, kind: DctKind) -> DctHelper {
+    DctHelper::new(
+        NonZeroUsize::new(width).unwrap(),
+        NonZeroUsize::new(height).unwrap(),
+        NonZeroU8::new(bits).unwrap(),
+        kind,
+    )
+    .unwrap()
+}
+
+fn dct_with<T: Pixel>(helper: &mut DctHelper, block: &[T], width: usize) -> Vec<T> {
+    let pitch = NonZeroUsize::new(width).unwrap();
+    let mut dct = vec![T::zero(); block.len()];
+    helper.bytes_2d(block, pitch, &mut dct, pitch).unwrap();
+    dct
+}
+
+fn dct_of<T: Pixel>(block: &[T], width: usize, height: usize, bits: u8) -> Vec<T> {
+    dct_with(
+        &mut helper(width, height, bits, DctKind::Float),
+        block,
+        width,
+    )
+}
+
+#[test]
+fn flat_block_has_only_dc() {
+    for (width, height) in [(4, 4), (8, 4), (8, 8), (16, 2), (16, 8), (32, 16)] {
+        let block = vec![100u8; width * height];
+        let dct = dct_of(&block, width, height, 8);
+        // DC is half of the mean, and AC coefficients are centered on 128
+        assert_eq!(dct[0], 50 + 128, "{width}x{height}");
+        assert!(dct[1..].iter().all(|&c| c == 128), "{width}x{height}");
+    }
+
+    let block = vec![1000u16; 64];
+    let dct = dct_of(&block, 8, 8, 10);
+    assert_eq!(dct[0], 500 + 512);
+    assert!(dct[1..].iter().all(|&c| c == 512));
+}
+
+#[test]
+fn dc_offset_only_changes_dc() {
+    let block: Vec<u8> = (0..64u8).map(|i| (i % 8) * 9 + (i / 8) * 5 + 20).collect();
+    let offset_block: Vec<u8> = block.iter().map(|&pixel| pixel + 40).collect();
+
+    let dct = dct_of(&block, 8, 8, 8);
+    let offset_dct = dct_of(&offset_block, 8, 8, 8);
+    assert_eq!(offset_dct[0] - dct[0], 20);
+    assert_eq!(&offset_dct[1..], &dct[1..]);
+    // The gradient is not flat, so it has AC coefficients
+    assert!(dct[1..].iter().any(|&c| c != 128));
+}
+
+#[test]
+fn integer_flat_block_has_only_dc() {
+    for (width, height) in BLOCK_SIZES {
+        let block = vec![100u8; width * height];
+        let mut dct = helper(width, height, 8, DctKind::Integer);
+        let dct = dct_with(&mut dct, &block, width);
+        assert_eq!(dct[0], 50 + 128, "{width}x{height}");
+        assert!(dct[1..].iter().all(|&c| c == 128), "{width}x{height}");
+    }
+
+    // The largest coefficients do not overflow
+    let block = vec![u16::MAX; 128 * 128];
+    let mut dct = helper(128, 128, 16, DctKind::Integer);
+    let dct = dct_with(&mut dct, &block, 128);
+    assert_eq!(dct[0], 0x7fff + 0x8000);
+    assert!(dct[1..].iter().all(|&c| c == 0x8000));
+}
+
+#[test]
+fn integer_dct_is_deterministic() {
+    let mut rng = Xoshiro128StarStar::from_seed(*b"deadbeeflolcakes");
+    for (width, height) in BLOCK_SIZES {
+        let block: Vec<u16> = std::iter::repeat_with(|| rng.random_range(0..1024))
+            .take(width * height)
+            .collect();
+        let mut first = helper(width, height, 10, DctKind::Integer);
+        let expected = dct_with(&mut first, &block, width);
+
+        // Neither reusing nor cloning a helper changes its output
+        assert_eq!(dct_with(&mut first, &block, width), expected);
+        let mut clone = first.clone();
+        assert_eq!(clone.kind(), DctKind::Integer);
+        assert_eq!(dct_with(&mut clone, &block, width), expected);
+        let mut other = helper(width, height, 10, DctKind::Integer);
+        assert_eq!(dct_with(&mut other, &block, width), expected);
+    }
+}
+
+#[test]
+fn integer_dct_matches_float_reference() {
+    let mut rng = Xoshiro128StarStar::from_seed(*b"deadbeeflolcakes");
+    for (width, height) in BLOCK_SIZES {
+        let mut integer = helper(width, height, 8, DctKind::Integer);
+        let mut float = helper(width, height, 8, DctKind::Float);
+        for _ in 0..16 {
+            let block: Vec<u8> = std::iter::repeat_with(|| rng.random())
+                .take(width * height)
+                .collect();
+            let integer = dct_with(&mut integer, &block, width);
+            let float = dct_with(&mut float, &block, width);
+            // Rounding before the final shift can only move a coefficient by 1
+            for (index, (&a, &b)) in integer.iter().zip(&float).enumerate() {
+                assert!(
+                    a.abs_diff(b) <= 1,
+                    "{width}x{height} coefficient {index}: {a} != {b}"
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn pool_reuses_matching_helpers() {
+    let pool = DctPool::default();
+    let size = |n| NonZeroUsize::new(n).unwrap();
+    let bits = |n| NonZeroU8::new(n).unwrap();
+
+    let mut first = pool.take(size(8), size(8), bits(8)).unwrap();
+    assert_eq!(first.kind(), DctKind::Float);
+    let block: Vec<u8> = (0..64).map(|i| (i * 37 % 256) as u8).collect();
+    let expected = dct_with(&mut first, &block, 8);
+    pool.give_back(first);
+    assert_eq!(pool.len(), 1);
+
+    // A different block size or bit depth needs its own helper
+    for (x, y, b) in [(16, 8, 8), (8, 8, 10)] {
+        let other = pool.take(size(x), size(y), bits(b)).unwrap();
+        assert_eq!((other.size_x.get(), other.bits_per_sample.get()), (x, b));
+        assert_eq!(pool.len(), 1);
+    }
+
+    let mut reused = pool.take(size(8), size(8), bits(8)).unwrap();
+    assert_eq!(pool.len(), 0);
+    assert_eq!(dct_with(&mut reused, &block, 8), expected);
+}
+
+#[test]
+fn pool_hands_out_its_kind() {
+    let pool = DctPool::new(DctKind::Integer);
+    let size = NonZeroUsize::new(8).unwrap();
+    let helper = pool.take(size, size, NonZeroU8::new(8).unwrap()).unwrap();
+    assert_eq!(helper.kind(), DctKind::Integer);
+}
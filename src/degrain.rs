@@ -0,0 +1,600 @@
+//! Temporal denoising by blending motion compensated neighboring frames,
+//! similar to mvtools' MDegrain1, MDegrain2 and MDegrain3
+
+#[cfg(test)]
+mod tests;
+
+use std::num::{NonZeroU8, NonZeroUsize};
+
+use anyhow::{Result, anyhow, bail};
+use vapoursynth::{
+    core::CoreRef,
+    format::{ColorFamily, Format},
+    frame::{FrameRef, FrameRefMut},
+    node::Node,
+    plugins::{Filter, FrameContext},
+    prelude::{API, Property},
+    video_info::VideoInfo,
+};
+
+use crate::{
+    compensation::{CompensationRef, overlap_blocks},
+    error::{ZooMvError, parse_param},
+    mv_clip::{DEFAULT_THSCD1, DEFAULT_THSCD2, MVClip, detect_scene_change, scale_thscd1},
+    mv_plane::SuperPlaneLayout,
+    params::{MVPlaneSet, Subpel},
+    util::{Pixel, plane_with_padding, plane_with_padding_mut},
+    windows::{OverlapWindows, WindowMode},
+};
+
+/// Filter names by temporal radius, used as the prefix of error messages.
+const FILTER_NAMES: [&str; 3] = ["Degrain1", "Degrain2", "Degrain3"];
+/// Argument names of the backward and forward vectors by temporal distance.
+const VECTORS_NAMES: [[&str; 2]; 3] = [["mvbw", "mvfw"], ["mvbw2", "mvfw2"], ["mvbw3", "mvfw3"]];
+
+/// The blend weight of a block whose SAD is zero.
+pub const DEGRAIN_WEIGHT_FULL: u16 = 256;
+
+/// Converts the SAD of a compensated block into its blend weight for degraining.
+///
+/// Follows mvtools' `(thsad^2 - sad^2) / (thsad^2 + sad^2) * 256` curve: a perfectly matching
+/// block gets the full weight of [`DEGRAIN_WEIGHT_FULL`], and the weight falls off to zero
+/// as the SAD grows towards `thsad`. Blocks with a SAD of at least `thsad` are not used,
+/// so a `thsad` of zero disables every block.
+///
+/// This deliberately departs from the simpler `thsad^2 / (thsad^2 + sad^2) * 256` curve,
+/// which gives half weight at `sad == thsad` and never reaches zero. Matching mvtools
+/// keeps the output of `Degrain` comparable to `MDegrain` for the same `thsad`.
+/// A block with `sad == thsad / 2` gets a weight of 153.
+#[must_use]
+pub fn degrain_weight(sad: u64, thsad: u64) -> u16 {
+    if sad >= thsad {
+        return 0;
+    }
+
+    // Computed in floating point like mvtools, but from the ratio of the SADs,
+    // so that squaring a large `thsad` cannot overflow and turn the weight into NaN
+    let ratio_sq = (sad as f64 / thsad as f64).powi(2);
+    (f64::from(DEGRAIN_WEIGHT_FULL) * (1.0 - ratio_sq) / (1.0 + ratio_sq)) as u16
+}
+
+/// Reduces noise by averaging each block with the matching blocks of up to three
+/// previous and three next frames, found by following the motion vectors.
+///
+/// Each neighboring block is weighted by [`degrain_weight`] of its SAD, so blocks that do
+/// not really match, e.g. because of occlusions, contribute little or nothing. Overlapping
+/// blocks are blended with [`OverlapWindows`].
+#[derive(Debug)]
+pub struct Degrain<'core> {
+    /// Source clip
+    node: Node<'core>,
+    /// Super clip, used to fetch sub-pixel positions of the neighboring frames
+    super_: Node<'core>,
+    /// Backward and forward vectors for each temporal distance, nearest first
+    vectors: Vec<[MVClip<'core>; 2]>,
+    /// Luma and chroma SAD thresholds for each temporal distance,
+    /// scaled to the block size and bit depth
+    thsad: Vec<[u64; 2]>,
+    /// Which planes are denoised, the others are copied from the source clip
+    planes: [bool; 3],
+    /// Block SAD above which a block has changed, scaled to the block size and bit depth
+    thscd1: u64,
+    /// How many out of every 256 blocks must change for a neighbor to be skipped
+    thscd2: u8,
+    /// Blending windows for the luma and chroma blocks
+    windows: [OverlapWindows; 2],
+    name: &'static str,
+    super_hpad: usize,
+    super_vpad: usize,
+    pel: Subpel,
+    format: Format<'core>,
+    width: NonZeroUsize,
+    height: NonZeroUsize,
+    x_ratio_uv: NonZeroU8,
+    y_ratio_uv: NonZeroU8,
+}
+
+impl<'core> Degrain<'core> {
+    /// Creates a degrain filter with a temporal radius of `vectors.len()`,
+    /// which must be between 1 and 3.
+    ///
+    /// `vectors` holds the backward and forward vectors clips of each temporal distance,
+    /// nearest first. `thsad` and `thsadc` apply to the nearest frames, and the thresholds
+    /// of farther frames fall off linearly towards `thsad2` and `thsadc2` at the full radius.
+    ///
+    /// Neighbors across a scene change, as found by [`detect_scene_change`]
+    /// with `thscd1` and `thscd2`, are not used.
+    pub fn new(
+        clip: Node<'core>,
+        super_: Node<'core>,
+        vectors: Vec<[Node<'core>; 2]>,
+        thsad: Option<i64>,
+        thsadc: Option<i64>,
+        thsad2: Option<i64>,
+        thsadc2: Option<i64>,
+        plane: Option<i64>,
+        thscd1: Option<i64>,
+        thscd2: Option<i64>,
+    ) -> Result<Self> {
+        let radius = vectors.len();
+        if !(1..=FILTER_NAMES.len()).contains(&radius) {
+            bail!("Degrain: temporal radius must be between 1 and 3, got {radius}");
+        }
+        let name = FILTER_NAMES[radius - 1];
+
+        let thsad: u64 = parse_param(name, "thsad", thsad, 400, "at least 0")?;
+        let thsadc: u64 = parse_param(name, "thsadc", thsadc, thsad, "at least 0")?;
+        let thsad2: u64 = parse_param(name, "thsad2", thsad2, thsad, "at least 0")?;
+        let thsadc2: u64 = parse_param(name, "thsadc2", thsadc2, thsadc, "at least 0")?;
+        let plane: u8 = parse_param(name, "plane", plane, 4, "between 0 and 4")?;
+        let thscd1 = parse_param(name, "thscd1", thscd1, DEFAULT_THSCD1, "at least 0")?;
+        let thscd2 = parse_param(name, "thscd2", thscd2, DEFAULT_THSCD2, "between 0 and 255")?;
+        if plane > 4 {
+            bail!(ZooMvError::InvalidParam {
+                filter: name,
+                name: "plane",
+                value: i64::from(plane),
+                expected: "between 0 and 4",
+            });
+        }
+
+        let video_info = clip.info();
+        let (width, height) = match video_info.resolution {
+            Property::Variable => {
+                bail!(ZooMvError::VariableFormat {
+                    filter: name,
+                    clip: "clip",
+                })
+            }
+            // SAFETY: width and height must be positive
+            Property::Constant(resolution) => unsafe {
+                (
+                    NonZeroUsize::new_unchecked(resolution.width),
+                    NonZeroUsize::new_unchecked(resolution.height),
+                )
+            },
+        };
+        let format = video_info.format;
+        if ![ColorFamily::YUV, ColorFamily::Gray].contains(&format.color_family()) {
+            bail!(ZooMvError::UnsupportedFormat {
+                filter: name,
+                requirement: "be GRAY or YUV",
+            });
+        }
+        let is_gray = format.color_family() == ColorFamily::Gray;
+        let planes = if is_gray {
+            [true, false, false]
+        } else {
+            [
+                matches!(plane, 0 | 4),
+                matches!(plane, 1 | 3 | 4),
+                matches!(plane, 2..=4),
+            ]
+        };
+
+        let vectors = vectors
+            .into_iter()
+            .map(|[mvbw, mvfw]| Ok([MVClip::new(mvbw, name)?, MVClip::new(mvfw, name)?]))
+            .collect::<Result<Vec<_>>>()?;
+        let data = *vectors[0][0].analysis_data();
+        for (pair, names) in vectors.iter().zip(VECTORS_NAMES) {
+            for ((clip, is_backward), clip_name) in pair.iter().zip([true, false]).zip(names) {
+                let clip_data = clip.analysis_data();
+                if clip_data.is_backward != is_backward {
+                    bail!(ZooMvError::IncompatibleClip {
+                        filter: name,
+                        clip: clip_name,
+                        requirement: if is_backward {
+                            "be generated with isb=True"
+                        } else {
+                            "be generated with isb=False"
+                        },
+                    });
+                }
+                if clip_data.delta_frame <= 0 {
+                    bail!(ZooMvError::IncompatibleClip {
+                        filter: name,
+                        clip: clip_name,
+                        requirement: "be generated with a positive delta",
+                    });
+                }
+                if clip_data.width != width || clip_data.height != height {
+                    bail!(ZooMvError::IncompatibleClip {
+                        filter: name,
+                        clip: clip_name,
+                        requirement: "have the same dimensions as the input clip",
+                    });
+                }
+                if clip_data.bits_per_sample.get() != format.bits_per_sample() {
+                    bail!(ZooMvError::IncompatibleClip {
+                        filter: name,
+                        clip: clip_name,
+                        requirement: "have the same bit depth as the input clip",
+                    });
+                }
+                if clip_data.blk_size_x != data.blk_size_x
+                    || clip_data.blk_size_y != data.blk_size_y
+                    || clip_data.overlap_x != data.overlap_x
+                    || clip_data.overlap_y != data.overlap_y
+                    || clip_data.pel != data.pel
+                {
+                    bail!(ZooMvError::IncompatibleClip {
+                        filter: name,
+                        clip: clip_name,
+                        requirement: "have the same block size, overlap and pel as mvbw",
+                    });
+                }
+                if clip.divide() != vectors[0][0].divide() {
+                    bail!(ZooMvError::IncompatibleClip {
+                        filter: name,
+                        clip: clip_name,
+                        requirement: "have the same divide mode as mvbw",
+                    });
+                }
+                if clip.node().info().num_frames < video_info.num_frames {
+                    bail!(ZooMvError::IncompatibleClip {
+                        filter: name,
+                        clip: clip_name,
+                        requirement: "have at least as many frames as the input clip",
+                    });
+                }
+            }
+        }
+
+        let first = super_.get_frame(0).map_err(|e| {
+            anyhow!("{name}: failed to retrieve first frame from super clip. Error message: {e}")
+        })?;
+        let super_props = first.props();
+        let super_props_err = ZooMvError::IncompatibleSuper {
+            filter: name,
+            reason: "required properties not found in first frame of super clip. Maybe clip \
+                     didn't come from mv.Super? Was the first frame trimmed away?",
+        };
+        let super_props_err2 = ZooMvError::IncompatibleSuper {
+            filter: name,
+            reason: "parameters from super clip appear to be wrong.",
+        };
+        let get_prop = |key: &str| -> Result<usize> {
+            let value = super_props.get_int(key).map_err(|_| super_props_err)?;
+            Ok(usize::try_from(value).map_err(|_| super_props_err2)?)
+        };
+        let super_hpad = get_prop("Super_hpad")?;
+        let super_vpad = get_prop("Super_vpad")?;
+        let super_pel = get_prop("Super_pel")?;
+        let super_mode_yuv =
+            MVPlaneSet::from_bits(get_prop("Super_modeyuv")? as u8).ok_or(super_props_err2)?;
+        if super_pel != NonZeroUsize::from(data.pel).get()
+            || super_hpad != data.h_padding
+            || super_vpad != data.v_padding
+        {
+            bail!(ZooMvError::IncompatibleSuper {
+                filter: name,
+                reason: "wrong super clip (pel or padding) used for vectors",
+            });
+        }
+        if (planes[1] || planes[2]) && super_mode_yuv != MVPlaneSet::YUVPLANES {
+            bail!(ZooMvError::IncompatibleSuper {
+                filter: name,
+                reason: "super clip does not contain needed colour data.",
+            });
+        }
+
+        // The thresholds are given for an 8x8 block with 8-bit samples,
+        // and interpolated between the nearest and the farthest frames.
+        let scale = |thsad: u64| {
+            (thsad * (data.blk_size_x.get() * data.blk_size_y.get()) as u64 / 64)
+                << (format.bits_per_sample() - 8)
+        };
+        let thsad = (0..radius)
+            .map(|distance| {
+                [
+                    scale(thsad_falloff(thsad, thsad2, distance, radius)),
+                    scale(thsad_falloff(thsadc, thsadc2, distance, radius)),
+                ]
+            })
+            .collect();
+
+        let x_ratio_uv = data.x_ratio_uv;
+        let y_ratio_uv = data.y_ratio_uv;
+        let chroma_size = |size: NonZeroUsize, ratio: NonZeroU8| {
+            NonZeroUsize::new(size.get() / ratio.get() as usize).ok_or(
+                ZooMvError::IncompatibleClip {
+                    filter: name,
+                    clip: "mvbw",
+                    requirement: "have blocks of at least one chroma sample",
+                },
+            )
+        };
+        let windows = [
+            OverlapWindows::new(
+                data.blk_size_x,
+                data.blk_size_y,
+                data.overlap_x,
+                data.overlap_y,
+                WindowMode::Cosine,
+            )?,
+            OverlapWindows::new(
+                chroma_size(data.blk_size_x, x_ratio_uv)?,
+                chroma_size(data.blk_size_y, y_ratio_uv)?,
+                data.overlap_x / x_ratio_uv.get() as usize,
+                data.overlap_y / y_ratio_uv.get() as usize,
+                WindowMode::Cosine,
+            )?,
+        ];
+
+        // Scene changes are detected on the searched blocks
+        let thscd1 = scale_thscd1(thscd1, &vectors[0][0].base_analysis_data());
+
+        Ok(Self {
+            node: clip,
+            super_,
+            vectors,
+            thsad,
+            planes,
+            thscd1,
+            thscd2,
+            windows,
+            name,
+            super_hpad,
+            super_vpad,
+            pel: data.pel,
+            format,
+            width,
+            height,
+            x_ratio_uv,
+            y_ratio_uv,
+        })
+    }
+
+    /// Finds the frame referenced by the vectors `clip` for source frame `n`,
+    /// or `None` if it is outside of the clip.
+    fn reference_frame(&self, clip: &MVClip<'core>, n: usize) -> Option<usize> {
+        let data = clip.analysis_data();
+        let delta = data.delta_frame.unsigned_abs();
+        let reference = if data.is_backward {
+            n + delta
+        } else {
+            n.checked_sub(delta)?
+        };
+        (reference < self.node.info().num_frames).then_some(reference)
+    }
+
+    fn get_frame_internal<T: Pixel>(
+        &self,
+        core: CoreRef<'core>,
+        context: FrameContext,
+        n: usize,
+    ) -> Result<FrameRef<'core>> {
+        let name = self.name;
+        let src = self
+            .node
+            .get_frame_filter(context, n)
+            .ok_or_else(|| anyhow!("{name}: get_frame_filter past end of video"))?;
+        let mut dest = FrameRefMut::copy_of(core, &src);
+
+        // Each usable neighbor, as its superframe, vectors and index of its temporal distance
+        let mut neighbors = Vec::with_capacity(self.vectors.len() * 2);
+        for (distance, pair) in self.vectors.iter().enumerate() {
+            for clip in pair {
+                let Some(reference) = self.reference_frame(clip, n) else {
+                    continue;
+                };
+                let vectors_frame = clip
+                    .node()
+                    .get_frame_filter(context, n)
+                    .ok_or_else(|| anyhow!("{name}: get_frame_filter vectors past end of video"))?;
+                // Scene changes are detected on the searched blocks, whose SADs
+                // the subblocks of `divide` only share out
+                let Some(base_vectors) = clip
+                    .base_vectors(&vectors_frame)
+                    .map_err(|e| anyhow!("{name}: {e}"))?
+                else {
+                    // The vectors are not usable, most likely due to a scene change
+                    continue;
+                };
+                if detect_scene_change(&base_vectors, self.thscd1, self.thscd2) {
+                    continue;
+                }
+                let vectors = clip
+                    .finest_vectors(&vectors_frame)
+                    .map_err(|e| anyhow!("{name}: {e}"))?
+                    .expect("the finest vectors are valid along with the searched ones");
+                let super_frame = self
+                    .super_
+                    .get_frame_filter(context, reference)
+                    .ok_or_else(|| anyhow!("{name}: get_frame_filter super past end of video"))?;
+                neighbors.push((super_frame, vectors, distance));
+            }
+        }
+        if neighbors.is_empty() {
+            return Ok(dest.into());
+        }
+
+        let data = self.vectors[0][0].analysis_data();
+        for plane in 0..self.format.plane_count() {
+            if !self.planes[plane] {
+                continue;
+            }
+            let is_chroma = plane > 0;
+            let (width, height, x_ratio, y_ratio) = if is_chroma {
+                // SAFETY: Width and height must be at least the value of their ratio
+                unsafe {
+                    (
+                        NonZeroUsize::new_unchecked(
+                            self.width.get() / self.x_ratio_uv.get() as usize,
+                        ),
+                        NonZeroUsize::new_unchecked(
+                            self.height.get() / self.y_ratio_uv.get() as usize,
+                        ),
+                        self.x_ratio_uv,
+                        self.y_ratio_uv,
+                    )
+                }
+            } else {
+                (self.width, self.height, NonZeroU8::MIN, NonZeroU8::MIN)
+            };
+
+            let layouts: Vec<_> = neighbors
+                .iter()
+                .map(|(super_frame, ..)| {
+                    SuperPlaneLayout::new(
+                        is_chroma,
+                        self.width,
+                        self.height,
+                        0,
+                        self.pel,
+                        self.super_hpad,
+                        self.super_vpad,
+                        self.x_ratio_uv,
+                        self.y_ratio_uv,
+                        // SAFETY: stride must be at least width and non-zero
+                        unsafe {
+                            NonZeroUsize::new_unchecked(super_frame.stride(plane) / size_of::<T>())
+                        },
+                    )
+                })
+                .collect();
+            let refs = neighbors
+                .iter()
+                .zip(&layouts)
+                .map(|((super_frame, vectors, distance), layout)| {
+                    Ok(CompensationRef {
+                        plane: plane_with_padding::<T>(super_frame, plane)?,
+                        layout,
+                        vectors,
+                        thsad: self.thsad[*distance][usize::from(is_chroma)],
+                    })
+                })
+                .collect::<Result<Vec<_>>>()?;
+
+            // SAFETY: stride must be at least width and non-zero
+            let src_pitch =
+                unsafe { NonZeroUsize::new_unchecked(src.stride(plane) / size_of::<T>()) };
+            degrain_plane(
+                plane_with_padding_mut::<T>(&mut dest, plane)?,
+                src_pitch,
+                plane_with_padding::<T>(&src, plane)?,
+                src_pitch,
+                width,
+                height,
+                &refs,
+                &self.windows[usize::from(is_chroma)],
+                data.blk_x,
+                data.blk_y,
+                x_ratio,
+                y_ratio,
+                self.pel,
+            );
+        }
+
+        Ok(dest.into())
+    }
+}
+
+impl<'core> Filter<'core> for Degrain<'core> {
+    fn video_info(&self, _api: API, _core: CoreRef<'core>) -> Vec<VideoInfo<'core>> {
+        vec![self.node.info()]
+    }
+
+    fn get_frame_initial(
+        &self,
+        _api: API,
+        _core: CoreRef<'core>,
+        context: FrameContext,
+        n: usize,
+    ) -> Result<Option<FrameRef<'core>>> {
+        self.node.request_frame_filter(context, n);
+        for clip in self.vectors.iter().flatten() {
+            if let Some(reference) = self.reference_frame(clip, n) {
+                clip.node().request_frame_filter(context, n);
+                self.super_.request_frame_filter(context, reference);
+            }
+        }
+        Ok(None)
+    }
+
+    fn get_frame(
+        &self,
+        _api: API,
+        core: CoreRef<'core>,
+        context: FrameContext,
+        n: usize,
+    ) -> Result<FrameRef<'core>> {
+        match self.format.bytes_per_sample() {
+            1 => self.get_frame_internal::<u8>(core, context, n),
+            2 => self.get_frame_internal::<u16>(core, context, n),
+            _ => bail!(ZooMvError::UnsupportedBitDepth {
+                filter: self.name,
+                bits: self.format.bits_per_sample(),
+            }),
+        }
+    }
+}
+
+/// Interpolates the SAD threshold of the neighbors `distance` frames beyond the nearest ones,
+/// from `near` for the nearest neighbors to `far` for the neighbors at the full `radius`.
+#[must_use]
+pub(crate) fn thsad_falloff(near: u64, far: u64, distance: usize, radius: usize) -> u64 {
+    if radius <= 1 {
+        return near;
+    }
+    let (near, far) = (i128::from(near), i128::from(far));
+    (near + (far - near) * distance as i128 / (radius as i128 - 1)) as u64
+}
+
+/// Denoises one plane by blending each block of `src` with the blocks of `refs`
+/// that its vectors point to.
+///
+/// The source block has a weight of [`DEGRAIN_WEIGHT_FULL`], and each neighbor a weight
+/// of [`degrain_weight`] of its SAD. The blocks of a `blk_x` by `blk_y` grid are blended
+/// with `windows`, and pixels not covered by any block are copied from `src`.
+///
+/// `x_ratio` and `y_ratio` are the subsampling ratios of the plane relative to luma.
+pub(crate) fn degrain_plane<T: Pixel>(
+    dest: &mut [T],
+    dest_pitch: NonZeroUsize,
+    src: &[T],
+    src_pitch: NonZeroUsize,
+    width: NonZeroUsize,
+    height: NonZeroUsize,
+    refs: &[CompensationRef<'_, T>],
+    windows: &OverlapWindows,
+    blk_x: NonZeroUsize,
+    blk_y: NonZeroUsize,
+    x_ratio: NonZeroU8,
+    y_ratio: NonZeroU8,
+    pel: Subpel,
+) {
+    // The weight and vector of each neighbor for the current block, and their total weight
+    let mut current = (Vec::with_capacity(refs.len()), 0);
+    overlap_blocks(
+        dest,
+        dest_pitch,
+        src,
+        src_pitch,
+        width,
+        height,
+        windows,
+        blk_x,
+        blk_y,
+        &mut current,
+        |(weights, weight_sum), block| {
+            weights.clear();
+            weights.extend(refs.iter().map(|r| {
+                let (mv_x, mv_y, sad) = r.vector(block, x_ratio, y_ratio);
+                (u64::from(degrain_weight(sad, r.thsad)), mv_x, mv_y)
+            }));
+            *weight_sum = u64::from(DEGRAIN_WEIGHT_FULL) + weights.iter().map(|w| w.0).sum::<u64>();
+        },
+        |(weights, weight_sum), x, y| {
+            let mut value = u64::from(DEGRAIN_WEIGHT_FULL)
+                * src[y * src_pitch.get() + x].to_u64().expect("fits in u64");
+            for (r, &(weight, mv_x, mv_y)) in refs.iter().zip(weights) {
+                if weight > 0 {
+                    value += weight * r.sample(pel, x, y, mv_x, mv_y);
+                }
+            }
+            (value + weight_sum / 2) / weight_sum
+        },
+    );
+}
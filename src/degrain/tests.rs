@@ -0,0 +1,252 @@
+#![allow(clippy::unwrap_used, reason = "allow in test files")]
+#![allow(clippy::undocumented_unsafe_blocks, reason = "allow in test files")]
+
+use parameterized::parameterized;
+
+use super::*;
+use crate::{mv::MotionVector, tests::planes::*};
+
+#[parameterized(thsad = { 1, 400, 10_000, 1 << 40, u64::MAX })]
+fn zero_sad_has_full_weight(thsad: u64) {
+    assert_eq!(degrain_weight(0, thsad), DEGRAIN_WEIGHT_FULL);
+}
+
+#[parameterized(thsad = { 1, 400, 10_000, 1 << 40, u64::MAX })]
+fn sad_at_threshold_has_no_weight(thsad: u64) {
+    assert_eq!(degrain_weight(thsad, thsad), 0);
+    assert_eq!(degrain_weight(thsad.saturating_add(1), thsad), 0);
+    assert_eq!(degrain_weight(u64::MAX, thsad), 0);
+}
+
+#[parameterized(thsad = { 2, 400, 10_000, 1 << 40, u64::MAX - 1 })]
+fn sad_at_half_threshold_has_mvtools_weight(thsad: u64) {
+    // 256 * (1 - 1/4) / (1 + 1/4) = 153.6
+    assert_eq!(degrain_weight(thsad / 2, thsad), 153);
+}
+
+#[parameterized(thsad = { 1, 400, 10_000, 1 << 40 })]
+fn large_sad_has_near_zero_weight(thsad: u64) {
+    assert!(degrain_weight(thsad * 20, thsad) <= 1);
+    assert_eq!(degrain_weight(thsad * 100, thsad), 0);
+    assert_eq!(degrain_weight(u64::MAX, thsad), 0);
+}
+
+#[test]
+fn zero_thsad_disables_blocks() {
+    assert_eq!(degrain_weight(0, 0), 0);
+    assert_eq!(degrain_weight(100, 0), 0);
+}
+
+#[test]
+fn weight_decreases_with_sad() {
+    let thsad = 400;
+    let mut prev = degrain_weight(0, thsad);
+    for sad in (1..4000).step_by(7) {
+        let weight = degrain_weight(sad, thsad);
+        assert!(weight <= prev, "weight increased at sad {sad}");
+        prev = weight;
+    }
+}
+
+#[test]
+fn matches_mvtools_weights() {
+    // (sad, thsad, weight) from mvtools' `DegrainWeight`
+    let expected = [
+        (0, 400, 256),
+        (100, 400, 225),
+        (200, 400, 153),
+        (300, 400, 71),
+        (399, 400, 0),
+        (400, 400, 0),
+        (800, 400, 0),
+        (3, 10, 213),
+        (5000, 10_000, 153),
+        (0, 1, 256),
+        (1, 1, 0),
+    ];
+    for (sad, thsad, weight) in expected {
+        assert_eq!(
+            degrain_weight(sad, thsad),
+            weight,
+            "sad {sad}, thsad {thsad}"
+        );
+    }
+}
+
+#[test]
+fn thsad_falls_off_towards_farthest_neighbors() {
+    assert_eq!(thsad_falloff(400, 100, 0, 1), 400);
+    assert_eq!(thsad_falloff(400, 100, 0, 2), 400);
+    assert_eq!(thsad_falloff(400, 100, 1, 2), 100);
+    let radius3: Vec<_> = (0..3).map(|d| thsad_falloff(400, 100, d, 3)).collect();
+    assert_eq!(radius3, [400, 250, 100]);
+    // The threshold may also grow with the distance
+    assert_eq!(thsad_falloff(100, 400, 1, 3), 250);
+}
+
+/// Sum of absolute differences of `src` against `reference` moved by `(mv_x, mv_y)`,
+/// for each block of the grid.
+fn block_sads(src: &[u8], reference: impl Fn(i32, i32) -> u8, mv_x: i32, mv_y: i32) -> Vec<u64> {
+    let step = BLK_SIZE - OVERLAP;
+    (0..BLK_Y)
+        .flat_map(|by| (0..BLK_X).map(move |bx| (bx, by)))
+        .map(|(bx, by)| {
+            let mut sad = 0;
+            for y in by * step..by * step + BLK_SIZE {
+                for x in bx * step..bx * step + BLK_SIZE {
+                    let r = reference(x as i32 + mv_x, y as i32 + mv_y);
+                    sad += u64::from(src[y * WIDTH + x].abs_diff(r));
+                }
+            }
+            sad
+        })
+        .collect()
+}
+
+fn degrain(src: &[u8], refs: &[CompensationRef<'_, u8>]) -> Vec<u8> {
+    let windows = OverlapWindows::new(
+        NonZeroUsize::new(BLK_SIZE).unwrap(),
+        NonZeroUsize::new(BLK_SIZE).unwrap(),
+        OVERLAP,
+        OVERLAP,
+        WindowMode::Cosine,
+    )
+    .unwrap();
+    let pitch = NonZeroUsize::new(WIDTH).unwrap();
+    let mut dest = vec![0u8; WIDTH * HEIGHT];
+    degrain_plane(
+        &mut dest,
+        pitch,
+        src,
+        pitch,
+        pitch,
+        NonZeroUsize::new(HEIGHT).unwrap(),
+        refs,
+        &windows,
+        NonZeroUsize::new(BLK_X).unwrap(),
+        NonZeroUsize::new(BLK_Y).unwrap(),
+        NonZeroU8::MIN,
+        NonZeroU8::MIN,
+        Subpel::Full,
+    );
+    dest
+}
+
+#[test]
+fn degrain3_identical_neighbors_return_mean() {
+    // Six identical neighbors that match perfectly, around a brighter source frame
+    let src = plane(|x, y| pattern(x, y) + 70);
+    let neighbor = padded_plane(pattern);
+    let layout = layout();
+    let vectors = vec![MotionVector { x: 0, y: 0, sad: 0 }; BLK_X * BLK_Y];
+    let refs = vec![
+        CompensationRef {
+            plane: &neighbor,
+            layout: &layout,
+            vectors: &vectors,
+            thsad: 400,
+        };
+        6
+    ];
+
+    // All seven frames have the full weight, so every pixel is their mean
+    assert_eq!(degrain(&src, &refs), plane(|x, y| pattern(x, y) + 10));
+}
+
+#[test]
+fn degrain3_denoises_noisy_frame_between_clean_neighbors() {
+    let mut seed = 0x2545_f491u32;
+    let mut noise = || {
+        seed ^= seed << 13;
+        seed ^= seed >> 17;
+        seed ^= seed << 5;
+        (seed % 25) as i32 - 12
+    };
+    let clean = plane(pattern);
+    let src: Vec<u8> = clean
+        .iter()
+        .map(|&p| (i32::from(p) + noise()) as u8)
+        .collect();
+
+    // The clean neighbors are panning, and the vectors follow them
+    let layout = layout();
+    let neighbors: Vec<_> = (1..=3)
+        .flat_map(|d| [d, -d])
+        .map(|d: i32| {
+            let (mv_x, mv_y) = (d, -d);
+            let reference = move |x: i32, y: i32| pattern(x - mv_x, y - mv_y);
+            let vectors = block_sads(&src, reference, mv_x, mv_y)
+                .into_iter()
+                .map(|sad| MotionVector {
+                    x: mv_x,
+                    y: mv_y,
+                    sad: sad as i64,
+                })
+                .collect::<Vec<_>>();
+            (padded_plane(reference), vectors)
+        })
+        .collect();
+    let refs: Vec<_> = neighbors
+        .iter()
+        .map(|(plane, vectors)| CompensationRef {
+            plane,
+            layout: &layout,
+            vectors,
+            // The noise alone adds a SAD of about 400 to each block
+            thsad: 1200,
+        })
+        .collect();
+
+    let error = |plane: &[u8]| {
+        plane
+            .iter()
+            .zip(&clean)
+            .map(|(&a, &b)| u64::from(a.abs_diff(b)))
+            .sum::<u64>()
+    };
+    let dest = degrain(&src, &refs);
+    assert!(
+        error(&dest) * 3 < error(&src),
+        "noise reduced from {} to {}",
+        error(&src),
+        error(&dest)
+    );
+}
+
+#[test]
+fn mismatched_neighbors_are_ignored() {
+    let src = plane(pattern);
+    let neighbor = padded_plane(|x, y| pattern(x, y) / 2);
+    let layout = layout();
+    let vectors = vec![
+        MotionVector {
+            x: 0,
+            y: 0,
+            sad: 1000,
+        };
+        BLK_X * BLK_Y
+    ];
+    let reference = CompensationRef {
+        plane: &neighbor,
+        layout: &layout,
+        vectors: &vectors,
+        thsad: 400,
+    };
+
+    // Blocks with a SAD of at least the threshold are not used
+    assert_eq!(degrain(&src, &[reference, reference]), src);
+    // A zero threshold disables the neighbor, such as for distant frames with `thsad2=0`
+    let disabled = CompensationRef {
+        thsad: 0,
+        vectors: &[MotionVector { x: 0, y: 0, sad: 0 }; BLK_X * BLK_Y],
+        ..reference
+    };
+    assert_eq!(degrain(&src, &[disabled, disabled]), src);
+    // Below the threshold it contributes a little, in proportion to its weight
+    let blended = degrain(&src, &[CompensationRef {
+        thsad: 1200,
+        ..reference
+    }]);
+    assert!(blended.iter().zip(&src).all(|(&a, &b)| a <= b));
+    assert!(blended != src);
+}
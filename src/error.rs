@@ -0,0 +1,146 @@
+//! Errors returned by filters when they are created or when they produce frames
+
+#[cfg(test)]
+mod tests;
+
+use std::fmt;
+
+use vapoursynth::format::ColorFamily;
+
+/// An error caused by an invalid combination of clips and parameters passed to a filter,
+/// or by an input frame that does not match its clip.
+///
+/// Each variant carries the name of the filter, which prefixes its message.
+/// Filters return these through `anyhow`, so callers can still match on the variant
+/// with [`anyhow::Error::downcast_ref`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ZooMvError {
+    /// A clip does not have a constant format and resolution
+    VariableFormat {
+        filter: &'static str,
+        clip: &'static str,
+    },
+    /// The input clip has more bits per sample than supported
+    UnsupportedBitDepth { filter: &'static str, bits: u8 },
+    /// The input clip's sample type, color family, subsampling or dimensions are not supported
+    UnsupportedFormat {
+        filter: &'static str,
+        requirement: &'static str,
+    },
+    /// The input clip is smaller than the smallest frame the filter can process
+    InputTooSmall {
+        filter: &'static str,
+        width: usize,
+        height: usize,
+        min_width: usize,
+        min_height: usize,
+    },
+    /// The input clip is neither GRAY nor YUV, such as RGB, which must be converted first
+    UnsupportedColorFamily {
+        filter: &'static str,
+        family: ColorFamily,
+    },
+    /// An additional clip does not match the input clip
+    IncompatibleClip {
+        filter: &'static str,
+        clip: &'static str,
+        requirement: &'static str,
+    },
+    /// The super clip is missing properties, or they do not match the other clips
+    IncompatibleSuper {
+        filter: &'static str,
+        reason: &'static str,
+    },
+    /// A parameter is outside of its allowed values
+    InvalidParam {
+        filter: &'static str,
+        name: &'static str,
+        value: i64,
+        expected: &'static str,
+    },
+    /// A frame does not have the format or resolution declared by its clip
+    FrameMismatch {
+        filter: &'static str,
+        clip: &'static str,
+        frame: usize,
+    },
+}
+
+impl fmt::Display for ZooMvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::VariableFormat { filter, clip } => {
+                write!(
+                    f,
+                    "{filter}: '{clip}' must have a constant format and resolution"
+                )
+            }
+            Self::UnsupportedBitDepth { filter, bits } => {
+                write!(f, "{filter}: input clip must be 8-16 bits, got {bits}")
+            }
+            Self::UnsupportedFormat {
+                filter,
+                requirement,
+            } => write!(f, "{filter}: input clip must {requirement}"),
+            Self::InputTooSmall {
+                filter,
+                width,
+                height,
+                min_width,
+                min_height,
+            } => write!(
+                f,
+                "{filter}: input clip is too small, must be at least {min_width}x{min_height}, \
+                 got {width}x{height}"
+            ),
+            Self::UnsupportedColorFamily { filter, family } => write!(
+                f,
+                "{filter}: input clip must be GRAY or YUV, got {family}. Convert it first, e.g. \
+                 with core.resize.Bicubic(clip, format=vs.YUV444P16, matrix_s=\"709\")"
+            ),
+            Self::IncompatibleClip {
+                filter,
+                clip,
+                requirement,
+            } => write!(f, "{filter}: '{clip}' must {requirement}"),
+            Self::IncompatibleSuper { filter, reason } => write!(f, "{filter}: {reason}"),
+            Self::InvalidParam {
+                filter,
+                name,
+                value,
+                expected,
+            } => write!(f, "{filter}: '{name}' must be {expected}, got {value}"),
+            Self::FrameMismatch {
+                filter,
+                clip,
+                frame,
+            } => write!(
+                f,
+                "{filter}: frame {frame} of '{clip}' does not match the clip's format and \
+                 resolution"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ZooMvError {
+}
+
+/// Converts an optional integer argument of `filter` with `T::try_from`,
+/// falling back to `default` if it was not given.
+pub(crate) fn parse_param<T: TryFrom<i64>>(
+    filter: &'static str,
+    name: &'static str,
+    value: Option<i64>,
+    default: T,
+    expected: &'static str,
+) -> Result<T, ZooMvError> {
+    value.map_or(Ok(default), |value| {
+        T::try_from(value).map_err(|_| ZooMvError::InvalidParam {
+            filter,
+            name,
+            value,
+            expected,
+        })
+    })
+}
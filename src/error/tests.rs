@@ -0,0 +1,122 @@
+#![allow(clippy::unwrap_used, reason = "allow in test files")]
+#![allow(clippy::undocumented_unsafe_blocks, reason = "allow in test files")]
+
+use anyhow::anyhow;
+
+use super::*;
+
+#[test]
+fn messages_are_prefixed_with_filter() {
+    let cases = [
+        (
+            ZooMvError::VariableFormat {
+                filter: "Super",
+                clip: "clip",
+            },
+            "Super: 'clip' must have a constant format and resolution",
+        ),
+        (
+            ZooMvError::UnsupportedBitDepth {
+                filter: "Super",
+                bits: 32,
+            },
+            "Super: input clip must be 8-16 bits, got 32",
+        ),
+        (
+            ZooMvError::UnsupportedFormat {
+                filter: "Analyse",
+                requirement: "be integer format",
+            },
+            "Analyse: input clip must be integer format",
+        ),
+        (
+            ZooMvError::InputTooSmall {
+                filter: "Super",
+                width: 2,
+                height: 2,
+                min_width: 4,
+                min_height: 4,
+            },
+            "Super: input clip is too small, must be at least 4x4, got 2x2",
+        ),
+        (
+            ZooMvError::UnsupportedColorFamily {
+                filter: "Super",
+                family: ColorFamily::RGB,
+            },
+            "Super: input clip must be GRAY or YUV, got RGB. Convert it first, e.g. with \
+             core.resize.Bicubic(clip, format=vs.YUV444P16, matrix_s=\"709\")",
+        ),
+        (
+            ZooMvError::IncompatibleClip {
+                filter: "Super",
+                clip: "pelclip",
+                requirement: "have same format as input clip",
+            },
+            "Super: 'pelclip' must have same format as input clip",
+        ),
+        (
+            ZooMvError::IncompatibleSuper {
+                filter: "Analyse",
+                reason: "super clip does not contain needed colour data.",
+            },
+            "Analyse: super clip does not contain needed colour data.",
+        ),
+        (
+            ZooMvError::InvalidParam {
+                filter: "Super",
+                name: "pel",
+                value: 3,
+                expected: "1, 2, or 4",
+            },
+            "Super: 'pel' must be 1, 2, or 4, got 3",
+        ),
+        (
+            ZooMvError::FrameMismatch {
+                filter: "Analyse",
+                clip: "super",
+                frame: 7,
+            },
+            "Analyse: frame 7 of 'super' does not match the clip's format and resolution",
+        ),
+    ];
+
+    for (error, message) in cases {
+        assert_eq!(error.to_string(), message);
+    }
+}
+
+#[test]
+fn variant_survives_conversion_to_anyhow() {
+    let error = ZooMvError::UnsupportedBitDepth {
+        filter: "Super",
+        bits: 32,
+    };
+    let wrapped = anyhow::Error::from(error);
+    assert_eq!(wrapped.downcast_ref::<ZooMvError>(), Some(&error));
+    assert_eq!(wrapped.to_string(), error.to_string());
+
+    let other = anyhow!("Super: something else");
+    assert!(other.downcast_ref::<ZooMvError>().is_none());
+}
+
+#[test]
+fn parse_param_defaults_and_errors() {
+    assert_eq!(
+        parse_param::<usize>("Super", "hpad", None, 16, "at least 0"),
+        Ok(16)
+    );
+    assert_eq!(
+        parse_param::<usize>("Super", "hpad", Some(4), 16, "at least 0"),
+        Ok(4)
+    );
+    assert_eq!(
+        parse_param::<usize>("Super", "hpad", Some(-1), 16, "at least 0"),
+        Err(ZooMvError::InvalidParam {
+            filter: "Super",
+            name: "hpad",
+            value: -1,
+            expected: "at least 0",
+        })
+    );
+}
@@ -0,0 +1,394 @@
+//! Motion blur synthesis using per-pixel flow, similar to mvtools' MFlowBlur
+
+#[cfg(test)]
+mod tests;
+
+use std::num::{NonZeroU8, NonZeroUsize};
+
+use anyhow::{Result, anyhow, bail};
+use vapoursynth::{
+    core::CoreRef,
+    format::{ColorFamily, Format},
+    frame::{FrameRef, FrameRefMut},
+    node::Node,
+    plugins::{Filter, FrameContext},
+    prelude::{API, Property},
+    video_info::VideoInfo,
+};
+
+use crate::{
+    error::{ZooMvError, parse_param},
+    flow_fps::{FlowField, sample},
+    mv_clip::MVClip,
+    mv_plane::SuperPlaneLayout,
+    params::{MVPlaneSet, Subpel},
+    util::{Pixel, plane_with_padding, plane_with_padding_mut},
+};
+
+/// Number of steps in the blur interval, on each side of the frame.
+const BLUR_SCALE: u32 = 256;
+
+/// Simulates the motion blur of a camera shutter, by averaging each pixel of a frame
+/// with the pixels along its motion trajectory.
+///
+/// The per-pixel flow is obtained by bilinear upsampling of the block vectors, as in
+/// [`FlowFps`](crate::flow_fps::FlowFps). Each pixel is sampled along the backward vectors,
+/// towards the next frame, and along the forward vectors, towards the previous frame,
+/// for half of the blur interval each.
+#[derive(Debug)]
+pub struct FlowBlur<'core> {
+    /// Source clip
+    node: Node<'core>,
+    /// Super clip, used to fetch sub-pixel positions
+    super_: Node<'core>,
+    /// Backward vectors, from each frame to the next one
+    mvbw: MVClip<'core>,
+    /// Forward vectors, from each frame to the previous one
+    mvfw: MVClip<'core>,
+    /// Length of the trajectory sampled on each side of a pixel,
+    /// relative to its motion between frames and scaled to `BLUR_SCALE`
+    blur256: u32,
+    /// Maximum distance between two samples of the trajectory, in pixels
+    prec: NonZeroUsize,
+    super_hpad: usize,
+    super_vpad: usize,
+    pel: Subpel,
+    format: Format<'core>,
+    width: NonZeroUsize,
+    height: NonZeroUsize,
+    x_ratio_uv: NonZeroU8,
+    y_ratio_uv: NonZeroU8,
+}
+
+impl<'core> FlowBlur<'core> {
+    pub fn new(
+        clip: Node<'core>,
+        super_: Node<'core>,
+        mvbw: Node<'core>,
+        mvfw: Node<'core>,
+        blur: Option<i64>,
+        prec: Option<i64>,
+    ) -> Result<Self> {
+        // `blur` is the shutter time as a percentage of the interval between frames
+        let blur: u32 = parse_param("FlowBlur", "blur", blur, 50, "between 0 and 200")?;
+        if blur > 200 {
+            bail!(ZooMvError::InvalidParam {
+                filter: "FlowBlur",
+                name: "blur",
+                value: i64::from(blur),
+                expected: "between 0 and 200",
+            });
+        }
+        let prec: usize = parse_param("FlowBlur", "prec", prec, 1, "at least 1")?;
+        let prec = NonZeroUsize::new(prec).ok_or(ZooMvError::InvalidParam {
+            filter: "FlowBlur",
+            name: "prec",
+            value: 0,
+            expected: "at least 1",
+        })?;
+
+        let video_info = clip.info();
+        let variable = ZooMvError::VariableFormat {
+            filter: "FlowBlur",
+            clip: "clip",
+        };
+        let Property::Constant(resolution) = video_info.resolution else {
+            bail!(variable);
+        };
+        let width = NonZeroUsize::new(resolution.width).ok_or(variable)?;
+        let height = NonZeroUsize::new(resolution.height).ok_or(variable)?;
+        let format = video_info.format;
+        if ![ColorFamily::YUV, ColorFamily::Gray].contains(&format.color_family()) {
+            bail!(ZooMvError::UnsupportedFormat {
+                filter: "FlowBlur",
+                requirement: "be GRAY or YUV",
+            });
+        }
+
+        let mvbw = MVClip::new(mvbw, "FlowBlur")?;
+        let mvfw = MVClip::new(mvfw, "FlowBlur")?;
+        for (vectors, is_backward, name) in [(&mvbw, true, "mvbw"), (&mvfw, false, "mvfw")] {
+            let incompatible = |requirement| ZooMvError::IncompatibleClip {
+                filter: "FlowBlur",
+                clip: name,
+                requirement,
+            };
+            let data = vectors.analysis_data();
+            if data.is_backward != is_backward {
+                bail!(incompatible(if is_backward {
+                    "be generated with isb=True"
+                } else {
+                    "be generated with isb=False"
+                }));
+            }
+            if data.delta_frame.abs() != 1 {
+                bail!(incompatible("be generated with delta=1"));
+            }
+            if data.width != width || data.height != height {
+                bail!(incompatible("have the same dimensions as the input clip"));
+            }
+            if data.bits_per_sample.get() != format.bits_per_sample() {
+                bail!(incompatible("have the same bit depth as the input clip"));
+            }
+            if vectors.node().info().num_frames < video_info.num_frames {
+                bail!(incompatible(
+                    "have at least as many frames as the input clip"
+                ));
+            }
+        }
+        let data = *mvbw.analysis_data();
+
+        let first = super_.get_frame(0).map_err(|e| {
+            anyhow!("FlowBlur: failed to retrieve first frame from super clip. Error message: {e}")
+        })?;
+        let super_props = first.props();
+        let super_props_err = ZooMvError::IncompatibleSuper {
+            filter: "FlowBlur",
+            reason: "required properties not found in first frame of super clip. Maybe clip \
+                     didn't come from mv.Super? Was the first frame trimmed away?",
+        };
+        let super_props_err2 = ZooMvError::IncompatibleSuper {
+            filter: "FlowBlur",
+            reason: "parameters from super clip appear to be wrong.",
+        };
+        let get_prop = |key: &str| -> Result<usize> {
+            let value = super_props.get_int(key).map_err(|_| super_props_err)?;
+            Ok(usize::try_from(value).map_err(|_| super_props_err2)?)
+        };
+        let super_hpad = get_prop("Super_hpad")?;
+        let super_vpad = get_prop("Super_vpad")?;
+        let super_pel = get_prop("Super_pel")?;
+        let super_mode_yuv =
+            MVPlaneSet::from_bits(get_prop("Super_modeyuv")? as u8).ok_or(super_props_err2)?;
+        if super_pel != NonZeroUsize::from(data.pel).get()
+            || super_hpad != data.h_padding
+            || super_vpad != data.v_padding
+        {
+            bail!(ZooMvError::IncompatibleSuper {
+                filter: "FlowBlur",
+                reason: "wrong super clip (pel or padding) used for vectors",
+            });
+        }
+        if format.color_family() != ColorFamily::Gray && super_mode_yuv != MVPlaneSet::YUVPLANES {
+            bail!(ZooMvError::IncompatibleSuper {
+                filter: "FlowBlur",
+                reason: "super clip does not contain needed colour data.",
+            });
+        }
+
+        Ok(Self {
+            node: clip,
+            super_,
+            mvbw,
+            mvfw,
+            // Half of the shutter time is on each side of the frame
+            blur256: blur * BLUR_SCALE / 200,
+            prec,
+            super_hpad,
+            super_vpad,
+            pel: data.pel,
+            format,
+            width,
+            height,
+            x_ratio_uv: data.x_ratio_uv,
+            y_ratio_uv: data.y_ratio_uv,
+        })
+    }
+
+    fn get_frame_internal<T: Pixel>(
+        &self,
+        core: CoreRef<'core>,
+        context: FrameContext,
+        n: usize,
+    ) -> Result<FrameRef<'core>> {
+        let src = self
+            .node
+            .get_frame_filter(context, n)
+            .ok_or_else(|| anyhow!("FlowBlur: get_frame_filter past end of video"))?;
+        let mut dest = FrameRefMut::copy_of(core, &src);
+        if self.blur256 == 0 {
+            return Ok(dest.into());
+        }
+
+        let mvbw_frame = self
+            .mvbw
+            .node()
+            .get_frame_filter(context, n)
+            .ok_or_else(|| anyhow!("FlowBlur: get_frame_filter mvbw past end of video"))?;
+        let mvfw_frame = self
+            .mvfw
+            .node()
+            .get_frame_filter(context, n)
+            .ok_or_else(|| anyhow!("FlowBlur: get_frame_filter mvfw past end of video"))?;
+        let vectors_bw = self
+            .mvbw
+            .finest_vectors(&mvbw_frame)
+            .map_err(|e| anyhow!("FlowBlur: {e}"))?;
+        let vectors_fw = self
+            .mvfw
+            .finest_vectors(&mvfw_frame)
+            .map_err(|e| anyhow!("FlowBlur: {e}"))?;
+        let (Some(vectors_bw), Some(vectors_fw)) = (vectors_bw, vectors_fw) else {
+            // The vectors are not usable, most likely due to a scene change
+            // or because the frame is at either end of the clip
+            return Ok(dest.into());
+        };
+
+        let super_frame = self
+            .super_
+            .get_frame_filter(context, n)
+            .ok_or_else(|| anyhow!("FlowBlur: get_frame_filter super past end of video"))?;
+
+        let data = self.mvbw.analysis_data();
+        for plane in 0..self.format.plane_count() {
+            let (width, height, x_ratio, y_ratio) = if plane > 0 {
+                // SAFETY: Width and height must be at least the value of their ratio
+                unsafe {
+                    (
+                        NonZeroUsize::new_unchecked(
+                            self.width.get() / self.x_ratio_uv.get() as usize,
+                        ),
+                        NonZeroUsize::new_unchecked(
+                            self.height.get() / self.y_ratio_uv.get() as usize,
+                        ),
+                        self.x_ratio_uv,
+                        self.y_ratio_uv,
+                    )
+                }
+            } else {
+                (self.width, self.height, NonZeroU8::MIN, NonZeroU8::MIN)
+            };
+            let flow_bw = FlowField::new(&vectors_bw, data, width, height, x_ratio, y_ratio);
+            let flow_fw = FlowField::new(&vectors_fw, data, width, height, x_ratio, y_ratio);
+
+            // SAFETY: stride must be at least width and non-zero
+            let (super_pitch, dest_pitch) = unsafe {
+                (
+                    NonZeroUsize::new_unchecked(super_frame.stride(plane) / size_of::<T>()),
+                    NonZeroUsize::new_unchecked(src.stride(plane) / size_of::<T>()),
+                )
+            };
+            let layout = SuperPlaneLayout::new(
+                plane > 0,
+                self.width,
+                self.height,
+                0,
+                self.pel,
+                self.super_hpad,
+                self.super_vpad,
+                self.x_ratio_uv,
+                self.y_ratio_uv,
+                super_pitch,
+            );
+            flow_blur(
+                plane_with_padding_mut::<T>(&mut dest, plane)?,
+                dest_pitch,
+                plane_with_padding::<T>(&super_frame, plane)?,
+                &layout,
+                self.pel,
+                &flow_bw,
+                &flow_fw,
+                self.blur256,
+                self.prec,
+            );
+        }
+
+        Ok(dest.into())
+    }
+}
+
+impl<'core> Filter<'core> for FlowBlur<'core> {
+    fn video_info(&self, _api: API, _core: CoreRef<'core>) -> Vec<VideoInfo<'core>> {
+        vec![self.node.info()]
+    }
+
+    fn get_frame_initial(
+        &self,
+        _api: API,
+        _core: CoreRef<'core>,
+        context: FrameContext,
+        n: usize,
+    ) -> Result<Option<FrameRef<'core>>> {
+        self.node.request_frame_filter(context, n);
+        if self.blur256 > 0 {
+            self.super_.request_frame_filter(context, n);
+            self.mvbw.node().request_frame_filter(context, n);
+            self.mvfw.node().request_frame_filter(context, n);
+        }
+        Ok(None)
+    }
+
+    fn get_frame(
+        &self,
+        _api: API,
+        core: CoreRef<'core>,
+        context: FrameContext,
+        n: usize,
+    ) -> Result<FrameRef<'core>> {
+        match self.format.bytes_per_sample() {
+            1 => self.get_frame_internal::<u8>(core, context, n),
+            2 => self.get_frame_internal::<u16>(core, context, n),
+            _ => bail!(ZooMvError::UnsupportedBitDepth {
+                filter: "FlowBlur",
+                bits: self.format.bits_per_sample(),
+            }),
+        }
+    }
+}
+
+/// Blurs one plane by averaging each pixel along its motion trajectory,
+/// fetched from the level 0 sub-pixel windows of the superframe `src`.
+///
+/// The trajectory follows `flow_bw` on one side of the pixel and `flow_fw` on the other,
+/// each scaled by `blur256` relative to `BLUR_SCALE`. Both sides are sampled at the same
+/// number of evenly spaced positions, at most `prec` pixels apart.
+/// Pixels without any motion are left unchanged, so `dest` should already hold the source.
+pub(crate) fn flow_blur<T: Pixel>(
+    dest: &mut [T],
+    dest_pitch: NonZeroUsize,
+    src: &[T],
+    layout: &SuperPlaneLayout,
+    pel: Subpel,
+    flow_bw: &FlowField,
+    flow_fw: &FlowField,
+    blur256: u32,
+    prec: NonZeroUsize,
+) {
+    let blur = blur256 as f32 / BLUR_SCALE as f32;
+    let pel_val = i32::from(u8::from(pel));
+    let step = (prec.get() as i32 * pel_val) as f32;
+
+    for y in 0..layout.height.get() {
+        let dest_row = &mut dest[y * dest_pitch.get()..][..layout.width.get()];
+        for (x, dest_pixel) in dest_row.iter_mut().enumerate() {
+            let (bw_x, bw_y, _) = flow_bw.get(x, y);
+            let (fw_x, fw_y, _) = flow_fw.get(x, y);
+            let (bw_x, bw_y, fw_x, fw_y) = (bw_x * blur, bw_y * blur, fw_x * blur, fw_y * blur);
+            let length = bw_x.abs().max(bw_y.abs()).max(fw_x.abs()).max(fw_y.abs());
+            let samples = (length / step).ceil() as u32;
+            if samples == 0 {
+                continue;
+            }
+
+            let pos_x = x as i32 * pel_val;
+            let pos_y = y as i32 * pel_val;
+            let mut sum = 0;
+            for i in 0..samples {
+                let t = i as f32 / samples as f32;
+                for (vx, vy) in [(bw_x, bw_y), (fw_x, fw_y)] {
+                    sum += sample(
+                        src,
+                        layout,
+                        pel,
+                        pos_x + (vx * t).round() as i32,
+                        pos_y + (vy * t).round() as i32,
+                    )
+                    .to_u64()
+                    .expect("fits in u64");
+                }
+            }
+            let total = u64::from(samples) * 2;
+            *dest_pixel = T::from_u32_or_max_value(((sum + total / 2) / total) as u32);
+        }
+    }
+}
@@ -0,0 +1,165 @@
+#![allow(clippy::unwrap_used, reason = "allow in test files")]
+#![allow(clippy::undocumented_unsafe_blocks, reason = "allow in test files")]
+
+use vapoursynth::prelude::Environment;
+
+use super::*;
+use crate::{mv::MotionVector, tests::planes::*};
+
+fn uniform_flow(x: i32, y: i32) -> FlowField {
+    let data = analysis_data(WIDTH / 8, HEIGHT / 8, Subpel::Full);
+    let vectors = vec![MotionVector { x, y, sad: 0 }; data.blk_x.get() * data.blk_y.get()];
+    FlowField::new(
+        &vectors,
+        &data,
+        data.width,
+        data.height,
+        NonZeroU8::MIN,
+        NonZeroU8::MIN,
+    )
+}
+
+/// Builds a padded full-pel superframe plane from `pixel(x, y)`,
+/// along with the unpadded source plane.
+fn planes(pixel: impl Fn(i32, i32) -> u8) -> (Vec<u8>, Vec<u8>) {
+    (padded_plane(&pixel), plane(&pixel))
+}
+
+fn blur(padded: &[u8], source: &[u8], flow_x: i32, flow_y: i32, blur256: u32) -> Vec<u8> {
+    let mut dest = source.to_vec();
+    flow_blur(
+        &mut dest,
+        NonZeroUsize::new(WIDTH).unwrap(),
+        padded,
+        &layout(),
+        Subpel::Full,
+        &uniform_flow(flow_x, flow_y),
+        &uniform_flow(-flow_x, -flow_y),
+        blur256,
+        NonZeroUsize::MIN,
+    );
+    dest
+}
+
+#[test]
+fn zero_motion_leaves_source_unchanged() {
+    let (padded, source) = planes(|x, y| (x * 7 + y * 13) as u8);
+    for blur256 in [0, 64, 256] {
+        assert_eq!(
+            blur(&padded, &source, 0, 0, blur256),
+            source,
+            "blur {blur256}"
+        );
+    }
+}
+
+#[test]
+fn horizontal_motion_smears_impulse() {
+    let (padded, source) = planes(|x, y| if (x, y) == (16, 8) { 255 } else { 0 });
+
+    // Moving 8 pixels per frame, half of which is blurred on each side of the impulse,
+    // sampled every pixel
+    let dest = blur(&padded, &source, 8, 0, BLUR_SCALE / 2);
+    for (y, row) in dest.chunks_exact(WIDTH).enumerate() {
+        if y != 8 {
+            assert!(row.iter().all(|&p| p == 0), "row {y}: {row:?}");
+        }
+    }
+    let row = &dest[8 * WIDTH..][..WIDTH];
+    let mut expected = [0u8; WIDTH];
+    expected[13..20].fill(32);
+    // The impulse itself is the first sample on both sides
+    expected[16] = 64;
+    assert_eq!(row, expected);
+}
+
+#[test]
+fn prec_limits_sample_distance() {
+    let (padded, mut dest) = planes(|x, y| if (x, y) == (16, 8) { 255 } else { 0 });
+
+    // Samples 2 pixels apart skip every other pixel along the trajectory
+    flow_blur(
+        &mut dest,
+        NonZeroUsize::new(WIDTH).unwrap(),
+        &padded,
+        &layout(),
+        Subpel::Full,
+        &uniform_flow(8, 0),
+        &uniform_flow(-8, 0),
+        BLUR_SCALE / 2,
+        NonZeroUsize::new(2).unwrap(),
+    );
+    let row = &dest[8 * WIDTH..][..WIDTH];
+    let mut expected = [0u8; WIDTH];
+    expected[14] = 64;
+    expected[16] = 128;
+    expected[18] = 64;
+    assert_eq!(row, expected);
+}
+
+#[test]
+fn invalid_clips_and_params_are_rejected() {
+    let env = Environment::from_script(
+        r#"
+import vapoursynth as vs
+core = vs.core
+clip = core.std.BlankClip(width=64, height=48, format=vs.YUV420P8, length=2)
+clip.set_output(0)
+core.std.BlankClip(clip, format=vs.RGB24).set_output(1)
+core.std.Splice([clip, core.std.BlankClip(clip, width=32)], mismatch=True).set_output(2)
+"#,
+    )
+    .unwrap();
+    let (clip, _) = env.get_output(0).unwrap();
+    let (rgb, _) = env.get_output(1).unwrap();
+    let (variable, _) = env.get_output(2).unwrap();
+    let new = |clip: &Node<'_>, blur, prec| {
+        FlowBlur::new(
+            clip.clone(),
+            clip.clone(),
+            clip.clone(),
+            clip.clone(),
+            blur,
+            prec,
+        )
+        .unwrap_err()
+        .downcast_ref::<ZooMvError>()
+        .copied()
+    };
+    let invalid = |name, value, expected| {
+        Some(ZooMvError::InvalidParam {
+            filter: "FlowBlur",
+            name,
+            value,
+            expected,
+        })
+    };
+
+    assert_eq!(
+        new(&clip, Some(201), None),
+        invalid("blur", 201, "between 0 and 200")
+    );
+    assert_eq!(
+        new(&clip, Some(-1), None),
+        invalid("blur", -1, "between 0 and 200")
+    );
+    assert_eq!(new(&clip, None, Some(0)), invalid("prec", 0, "at least 1"));
+    assert_eq!(
+        new(&clip, None, Some(-2)),
+        invalid("prec", -2, "at least 1")
+    );
+    assert_eq!(
+        new(&rgb, None, None),
+        Some(ZooMvError::UnsupportedFormat {
+            filter: "FlowBlur",
+            requirement: "be GRAY or YUV",
+        })
+    );
+    assert_eq!(
+        new(&variable, None, None),
+        Some(ZooMvError::VariableFormat {
+            filter: "FlowBlur",
+            clip: "clip",
+        })
+    );
+}
@@ -0,0 +1,645 @@
+//! Motion-compensated frame rate conversion using per-pixel flow, similar to mvtools' MFlowFps
+
+#[cfg(test)]
+mod tests;
+
+use std::num::{NonZeroU8, NonZeroUsize};
+
+use anyhow::{Result, anyhow, bail};
+use vapoursynth::{
+    core::CoreRef,
+    format::{ColorFamily, Format},
+    frame::{FrameRef, FrameRefMut},
+    node::Node,
+    plugins::{Filter, FrameContext},
+    prelude::{API, Property},
+    video_info::{Framerate, VideoInfo},
+};
+
+use crate::{
+    degrain::{DEGRAIN_WEIGHT_FULL, degrain_weight},
+    mv::MotionVector,
+    mv_analyse::MVAnalysisData,
+    mv_clip::MVClip,
+    mv_plane::SuperPlaneLayout,
+    params::{MVPlaneSet, Subpel},
+    util::{Pixel, plane_with_padding, plane_with_padding_mut},
+};
+
+/// Number of steps in the time position between two source frames.
+const TIME_SCALE: u32 = 256;
+
+/// Creates a clip with a new frame rate, interpolating the intermediate frames
+/// by warping the neighboring source frames along a per-pixel motion flow.
+///
+/// The per-pixel flow is obtained by bilinear upsampling of the block vectors. Each output
+/// pixel is predicted from both the previous frame, using the backward vectors, and the next
+/// frame, using the forward vectors, and the predictions are blended by time position.
+/// Predictions from blocks with a high SAD are likely to be occluded, so they are given a
+/// lower weight.
+#[derive(Debug)]
+pub struct FlowFps<'core> {
+    /// Source clip
+    node: Node<'core>,
+    /// Super clip, used to fetch sub-pixel positions
+    super_: Node<'core>,
+    /// Backward vectors, from each frame to the next one
+    mvbw: MVClip<'core>,
+    /// Forward vectors, from each frame to the previous one
+    mvfw: MVClip<'core>,
+    /// Frame rate of the output clip
+    framerate: Framerate,
+    /// Output frame `n` is at source position `n * fb / fa`
+    fa: u64,
+    fb: u64,
+    /// Number of frames in the output clip
+    num_frames: usize,
+    /// SAD of a block at which its prediction is no longer used,
+    /// scaled to the block size and bit depth
+    thsad: u64,
+    /// Whether to blend the neighboring frames at scene changes,
+    /// instead of repeating the nearest one
+    blend: bool,
+    super_hpad: usize,
+    super_vpad: usize,
+    pel: Subpel,
+    format: Format<'core>,
+    width: NonZeroUsize,
+    height: NonZeroUsize,
+    x_ratio_uv: NonZeroU8,
+    y_ratio_uv: NonZeroU8,
+}
+
+impl<'core> FlowFps<'core> {
+    pub fn new(
+        clip: Node<'core>,
+        super_: Node<'core>,
+        mvbw: Node<'core>,
+        mvfw: Node<'core>,
+        num: Option<i64>,
+        den: Option<i64>,
+        thsad: Option<i64>,
+        blend: Option<i64>,
+    ) -> Result<Self> {
+        let num = num.map_or(Ok(25), u64::try_from)?;
+        let den = den.map_or(Ok(1), u64::try_from)?;
+        let thsad = thsad.map_or(Ok(400), u64::try_from)?;
+        let blend = blend.is_none_or(|blend| blend > 0);
+        if num == 0 || den == 0 {
+            bail!("FlowFps: num and den must be greater than 0");
+        }
+
+        let video_info = clip.info();
+        let (width, height) = match video_info.resolution {
+            Property::Variable => {
+                bail!("FlowFps: variable resolution input clips are not supported")
+            }
+            // SAFETY: width and height must be positive
+            Property::Constant(resolution) => unsafe {
+                (
+                    NonZeroUsize::new_unchecked(resolution.width),
+                    NonZeroUsize::new_unchecked(resolution.height),
+                )
+            },
+        };
+        let src_framerate = match video_info.framerate {
+            Property::Variable => {
+                bail!("FlowFps: variable frame rate input clips are not supported")
+            }
+            Property::Constant(framerate) => framerate,
+        };
+        let format = video_info.format;
+        if ![ColorFamily::YUV, ColorFamily::Gray].contains(&format.color_family()) {
+            bail!("FlowFps: input clip must be GRAY or YUV");
+        }
+
+        let mvbw = MVClip::new(mvbw, "FlowFps")?;
+        let mvfw = MVClip::new(mvfw, "FlowFps")?;
+        for (vectors, is_backward, name) in [(&mvbw, true, "mvbw"), (&mvfw, false, "mvfw")] {
+            let data = vectors.analysis_data();
+            if data.is_backward != is_backward {
+                bail!(
+                    "FlowFps: {name} must be generated with isb={}",
+                    if is_backward { "True" } else { "False" }
+                );
+            }
+            if data.delta_frame.abs() != 1 {
+                bail!("FlowFps: {name} must be generated with delta=1");
+            }
+            if data.width != width || data.height != height {
+                bail!("FlowFps: {name} dimensions do not match the input clip");
+            }
+            if data.bits_per_sample.get() != format.bits_per_sample() {
+                bail!("FlowFps: {name} bit depth does not match the input clip");
+            }
+            if vectors.node().info().num_frames < video_info.num_frames {
+                bail!("FlowFps: {name} must have at least as many frames as clip");
+            }
+        }
+        let data = *mvbw.analysis_data();
+
+        let first = super_.get_frame(0).map_err(|e| {
+            anyhow!("FlowFps: failed to retrieve first frame from super clip. Error message: {e}")
+        })?;
+        let super_props = first.props();
+        let super_props_err = "FlowFps: required properties not found in first frame of super \
+                               clip. Maybe clip didn't come from mv.Super? Was the first frame \
+                               trimmed away?";
+        let get_prop = |key: &str| -> Result<usize> {
+            let value = super_props
+                .get_int(key)
+                .map_err(|_| anyhow!(super_props_err))?;
+            usize::try_from(value)
+                .map_err(|_| anyhow!("FlowFps: parameters from super clip appear to be wrong."))
+        };
+        let super_hpad = get_prop("Super_hpad")?;
+        let super_vpad = get_prop("Super_vpad")?;
+        let super_pel = get_prop("Super_pel")?;
+        let super_mode_yuv = MVPlaneSet::from_bits(get_prop("Super_modeyuv")? as u8)
+            .ok_or_else(|| anyhow!("FlowFps: parameters from super clip appear to be wrong."))?;
+        if super_pel != NonZeroUsize::from(data.pel).get()
+            || super_hpad != data.h_padding
+            || super_vpad != data.v_padding
+        {
+            bail!("FlowFps: wrong super clip (pel or padding) used for vectors");
+        }
+        if format.color_family() != ColorFamily::Gray && super_mode_yuv != MVPlaneSet::YUVPLANES {
+            bail!("FlowFps: super clip does not contain needed colour data.");
+        }
+
+        // Reduce the ratio between the output and source frame rates,
+        // so that the time positions are computed without overflow.
+        let fa = src_framerate.denominator * num;
+        let fb = src_framerate.numerator * den;
+        let divisor = gcd(fa, fb);
+        let (fa, fb) = (fa / divisor, fb / divisor);
+        let num_frames = 1 + ((video_info.num_frames as u64 - 1) * fa / fb) as usize;
+
+        let divisor = gcd(num, den);
+        let framerate = Framerate {
+            numerator: num / divisor,
+            denominator: den / divisor,
+        };
+
+        // `thsad` is given for an 8x8 block with 8-bit samples
+        let thsad = (thsad * (data.blk_size_x.get() * data.blk_size_y.get()) as u64 / 64)
+            << (format.bits_per_sample() - 8);
+
+        Ok(Self {
+            node: clip,
+            super_,
+            mvbw,
+            mvfw,
+            framerate,
+            fa,
+            fb,
+            num_frames,
+            thsad,
+            blend,
+            super_hpad,
+            super_vpad,
+            pel: data.pel,
+            format,
+            width,
+            height,
+            x_ratio_uv: data.x_ratio_uv,
+            y_ratio_uv: data.y_ratio_uv,
+        })
+    }
+
+    /// Gets the source frame before output frame `n`,
+    /// and the time position of the output frame after it.
+    fn source_position(&self, n: usize) -> (usize, u32) {
+        let (left, time) = time_position(n as u64, self.fa, self.fb);
+        let src_frames = self.node.info().num_frames;
+        if left + 1 >= src_frames {
+            (src_frames - 1, 0)
+        } else {
+            (left, time)
+        }
+    }
+
+    fn get_frame_internal<T: Pixel>(
+        &self,
+        core: CoreRef<'core>,
+        context: FrameContext,
+        n: usize,
+    ) -> Result<FrameRef<'core>> {
+        let (left, time256) = self.source_position(n);
+        let src = self
+            .node
+            .get_frame_filter(context, left)
+            .ok_or_else(|| anyhow!("FlowFps: get_frame_filter past end of video"))?;
+        if time256 == 0 {
+            return self.finish_frame(FrameRefMut::copy_of(core, &src));
+        }
+
+        let next = self
+            .node
+            .get_frame_filter(context, left + 1)
+            .ok_or_else(|| anyhow!("FlowFps: get_frame_filter past end of video"))?;
+        let mvbw_frame = self
+            .mvbw
+            .node()
+            .get_frame_filter(context, left)
+            .ok_or_else(|| anyhow!("FlowFps: get_frame_filter mvbw past end of video"))?;
+        let mvfw_frame = self
+            .mvfw
+            .node()
+            .get_frame_filter(context, left + 1)
+            .ok_or_else(|| anyhow!("FlowFps: get_frame_filter mvfw past end of video"))?;
+        let vectors_bw = self
+            .mvbw
+            .finest_vectors(&mvbw_frame)
+            .map_err(|e| anyhow!("FlowFps: {e}"))?;
+        let vectors_fw = self
+            .mvfw
+            .finest_vectors(&mvfw_frame)
+            .map_err(|e| anyhow!("FlowFps: {e}"))?;
+
+        let (Some(vectors_bw), Some(vectors_fw)) = (vectors_bw, vectors_fw) else {
+            // The vectors are not usable, most likely due to a scene change
+            if self.blend {
+                let mut dest = FrameRefMut::copy_of(core, &src);
+                for plane in 0..self.format.plane_count() {
+                    let (width, height) = self.plane_dimensions(plane);
+                    blend_plane(
+                        plane_with_padding_mut::<T>(&mut dest, plane)?,
+                        self.pitch::<T>(&src, plane),
+                        plane_with_padding::<T>(&src, plane)?,
+                        self.pitch::<T>(&src, plane),
+                        plane_with_padding::<T>(&next, plane)?,
+                        self.pitch::<T>(&next, plane),
+                        width,
+                        height,
+                        time256,
+                    );
+                }
+                return self.finish_frame(dest);
+            }
+            let nearest = if time256 < TIME_SCALE / 2 {
+                &src
+            } else {
+                &next
+            };
+            return self.finish_frame(FrameRefMut::copy_of(core, nearest));
+        };
+
+        let super_prev = self
+            .super_
+            .get_frame_filter(context, left)
+            .ok_or_else(|| anyhow!("FlowFps: get_frame_filter super past end of video"))?;
+        let super_next = self
+            .super_
+            .get_frame_filter(context, left + 1)
+            .ok_or_else(|| anyhow!("FlowFps: get_frame_filter super past end of video"))?;
+
+        let data = self.mvbw.analysis_data();
+        let mut dest = FrameRefMut::copy_of(core, &src);
+        for plane in 0..self.format.plane_count() {
+            let (width, height) = self.plane_dimensions(plane);
+            let (x_ratio, y_ratio) = if plane > 0 {
+                (self.x_ratio_uv, self.y_ratio_uv)
+            } else {
+                (NonZeroU8::MIN, NonZeroU8::MIN)
+            };
+            let flow_bw = FlowField::new(&vectors_bw, data, width, height, x_ratio, y_ratio);
+            let flow_fw = FlowField::new(&vectors_fw, data, width, height, x_ratio, y_ratio);
+
+            let layout_prev = self.super_layout(plane, self.pitch::<T>(&super_prev, plane));
+            let layout_next = self.super_layout(plane, self.pitch::<T>(&super_next, plane));
+            let dest_pitch = self.pitch::<T>(&src, plane);
+            flow_inter(
+                plane_with_padding_mut::<T>(&mut dest, plane)?,
+                dest_pitch,
+                plane_with_padding::<T>(&super_prev, plane)?,
+                &layout_prev,
+                plane_with_padding::<T>(&super_next, plane)?,
+                &layout_next,
+                self.pel,
+                &flow_bw,
+                &flow_fw,
+                time256,
+                self.thsad,
+            );
+        }
+
+        self.finish_frame(dest)
+    }
+
+    fn finish_frame(&self, mut dest: FrameRefMut<'core>) -> Result<FrameRef<'core>> {
+        let mut props = dest.props_mut();
+        props.set_int("_DurationNum", self.framerate.denominator as i64)?;
+        props.set_int("_DurationDen", self.framerate.numerator as i64)?;
+        Ok(dest.into())
+    }
+
+    fn plane_dimensions(&self, plane: usize) -> (NonZeroUsize, NonZeroUsize) {
+        if plane == 0 {
+            return (self.width, self.height);
+        }
+        // SAFETY: Width and height must be at least the value of their ratio
+        unsafe {
+            (
+                NonZeroUsize::new_unchecked(self.width.get() / self.x_ratio_uv.get() as usize),
+                NonZeroUsize::new_unchecked(self.height.get() / self.y_ratio_uv.get() as usize),
+            )
+        }
+    }
+
+    fn pitch<T: Pixel>(&self, frame: &FrameRef<'core>, plane: usize) -> NonZeroUsize {
+        // SAFETY: stride must be at least width and non-zero
+        unsafe { NonZeroUsize::new_unchecked(frame.stride(plane) / size_of::<T>()) }
+    }
+
+    fn super_layout(&self, plane: usize, pitch: NonZeroUsize) -> SuperPlaneLayout {
+        SuperPlaneLayout::new(
+            plane > 0,
+            self.width,
+            self.height,
+            0,
+            self.pel,
+            self.super_hpad,
+            self.super_vpad,
+            self.x_ratio_uv,
+            self.y_ratio_uv,
+            pitch,
+        )
+    }
+}
+
+impl<'core> Filter<'core> for FlowFps<'core> {
+    fn video_info(&self, _api: API, _core: CoreRef<'core>) -> Vec<VideoInfo<'core>> {
+        let mut info = self.node.info();
+        info.framerate = Property::Constant(self.framerate);
+        info.num_frames = self.num_frames;
+        vec![info]
+    }
+
+    fn get_frame_initial(
+        &self,
+        _api: API,
+        _core: CoreRef<'core>,
+        context: FrameContext,
+        n: usize,
+    ) -> Result<Option<FrameRef<'core>>> {
+        let (left, time256) = self.source_position(n);
+        self.node.request_frame_filter(context, left);
+        if time256 > 0 {
+            self.node.request_frame_filter(context, left + 1);
+            self.super_.request_frame_filter(context, left);
+            self.super_.request_frame_filter(context, left + 1);
+            self.mvbw.node().request_frame_filter(context, left);
+            self.mvfw.node().request_frame_filter(context, left + 1);
+        }
+        Ok(None)
+    }
+
+    fn get_frame(
+        &self,
+        _api: API,
+        core: CoreRef<'core>,
+        context: FrameContext,
+        n: usize,
+    ) -> Result<FrameRef<'core>> {
+        match self.format.bytes_per_sample() {
+            1 => self.get_frame_internal::<u8>(core, context, n),
+            2 => self.get_frame_internal::<u16>(core, context, n),
+            _ => bail!("FlowFps: does not support clips greater than 16 bits"),
+        }
+    }
+}
+
+/// Splits the source position `n * fb / fa` into the index of the source frame before it,
+/// and the time position after that frame in units of `1 / TIME_SCALE`.
+#[must_use]
+pub(crate) fn time_position(n: u64, fa: u64, fb: u64) -> (usize, u32) {
+    let mut left = n * fb / fa;
+    let remainder = n * fb % fa;
+    let mut time = ((remainder * u64::from(TIME_SCALE) * 2 + fa) / (fa * 2)) as u32;
+    if time == TIME_SCALE {
+        left += 1;
+        time = 0;
+    }
+    (left as usize, time)
+}
+
+fn gcd(mut a: u64, mut b: u64) -> u64 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+/// A dense motion flow for a single plane, upsampled from a field of block vectors.
+#[derive(Debug, Clone)]
+pub(crate) struct FlowField {
+    width: NonZeroUsize,
+    /// Horizontal motion of each pixel, in `pel` units of the plane
+    x: Vec<f32>,
+    /// Vertical motion of each pixel, in `pel` units of the plane
+    y: Vec<f32>,
+    /// SAD of the blocks around each pixel
+    sad: Vec<u64>,
+}
+
+impl FlowField {
+    /// Upsamples the finest level `vectors` to one vector per pixel of a plane of
+    /// `width` by `height` pixels, by bilinear interpolation between the centers
+    /// of neighboring blocks.
+    ///
+    /// `x_ratio` and `y_ratio` are the subsampling ratios of the plane relative to luma.
+    #[must_use]
+    pub(crate) fn new(
+        vectors: &[MotionVector],
+        data: &MVAnalysisData,
+        width: NonZeroUsize,
+        height: NonZeroUsize,
+        x_ratio: NonZeroU8,
+        y_ratio: NonZeroU8,
+    ) -> Self {
+        let columns =
+            interpolation_taps(width, x_ratio, data.blk_size_x, data.overlap_x, data.blk_x);
+        let rows = interpolation_taps(height, y_ratio, data.blk_size_y, data.overlap_y, data.blk_y);
+        let x_ratio = f32::from(x_ratio.get());
+        let y_ratio = f32::from(y_ratio.get());
+        let blk_x = data.blk_x.get();
+
+        let size = width.get() * height.get();
+        let mut flow_x = Vec::with_capacity(size);
+        let mut flow_y = Vec::with_capacity(size);
+        let mut sad = Vec::with_capacity(size);
+        for &(row0, row1, fy) in &rows {
+            for &(col0, col1, fx) in &columns {
+                let v00 = &vectors[row0 * blk_x + col0];
+                let v01 = &vectors[row0 * blk_x + col1];
+                let v10 = &vectors[row1 * blk_x + col0];
+                let v11 = &vectors[row1 * blk_x + col1];
+                let lerp = |f: fn(&MotionVector) -> f32| {
+                    let top = (f(v01) - f(v00)).mul_add(fx, f(v00));
+                    let bottom = (f(v11) - f(v10)).mul_add(fx, f(v10));
+                    (bottom - top).mul_add(fy, top)
+                };
+                flow_x.push(lerp(|v| v.x as f32) / x_ratio);
+                flow_y.push(lerp(|v| v.y as f32) / y_ratio);
+                sad.push(lerp(|v| v.sad as f32).round() as u64);
+            }
+        }
+
+        Self {
+            width,
+            x: flow_x,
+            y: flow_y,
+            sad,
+        }
+    }
+
+    #[must_use]
+    pub(crate) fn get(&self, x: usize, y: usize) -> (f32, f32, u64) {
+        let i = y * self.width.get() + x;
+        (self.x[i], self.y[i], self.sad[i])
+    }
+}
+
+/// Finds the two nearest block centers along one axis for each pixel of a plane,
+/// and the interpolation weight of the second one.
+fn interpolation_taps(
+    size: NonZeroUsize,
+    ratio: NonZeroU8,
+    blk_size: NonZeroUsize,
+    overlap: usize,
+    blk_count: NonZeroUsize,
+) -> Vec<(usize, usize, f32)> {
+    let ratio = f32::from(ratio.get());
+    let step = (blk_size.get() - overlap) as f32;
+    let half_block = blk_size.get() as f32 / 2.0;
+    let last = blk_count.get() - 1;
+    (0..size.get())
+        .map(|i| {
+            // center of the pixel in luma coordinates
+            let luma_pos = (i as f32 + 0.5) * ratio;
+            let pos = ((luma_pos - half_block) / step).clamp(0.0, last as f32);
+            let first = (pos as usize).min(last);
+            let second = (first + 1).min(last);
+            (first, second, pos - first as f32)
+        })
+        .collect()
+}
+
+/// Fetches the pixel at `(x, y)`, in `pel` units relative to the top left non-padding pixel,
+/// from the level 0 sub-pixel windows of a superframe plane.
+///
+/// Positions outside of the padded plane are clamped to its edges.
+pub(crate) fn sample<T: Pixel>(
+    src: &[T],
+    layout: &SuperPlaneLayout,
+    pel: Subpel,
+    x: i32,
+    y: i32,
+) -> T {
+    let pel = i32::from(u8::from(pel));
+    let shift = pel.trailing_zeros();
+    let hpad = layout.hpad as i32;
+    let vpad = layout.vpad as i32;
+    let x = x.clamp(-hpad * pel, (layout.width.get() as i32 + hpad) * pel - 1);
+    let y = y.clamp(-vpad * pel, (layout.height.get() as i32 + vpad) * pel - 1);
+
+    let window = ((x & (pel - 1)) | ((y & (pel - 1)) << shift)) as usize;
+    let window_size = layout.pitch.get() * (layout.height.get() + 2 * layout.vpad);
+    let column = ((x >> shift) + hpad) as usize;
+    let row = ((y >> shift) + vpad) as usize;
+    src[layout.offset + window * window_size + row * layout.pitch.get() + column]
+}
+
+/// Interpolates one plane at `time256` between two frames, by fetching each pixel along
+/// the flow from both the previous and the next superframe and blending the two predictions.
+///
+/// `flow_bw` points from the previous frame to the next one, and `flow_fw` from the next
+/// frame to the previous one. Predictions are weighted by their distance in time, and by
+/// their SAD relative to `thsad` to reduce artifacts in occluded areas.
+pub(crate) fn flow_inter<T: Pixel>(
+    dest: &mut [T],
+    dest_pitch: NonZeroUsize,
+    prev: &[T],
+    prev_layout: &SuperPlaneLayout,
+    next: &[T],
+    next_layout: &SuperPlaneLayout,
+    pel: Subpel,
+    flow_bw: &FlowField,
+    flow_fw: &FlowField,
+    time256: u32,
+    thsad: u64,
+) {
+    let time = time256 as f32 / TIME_SCALE as f32;
+    let pel_val = i32::from(u8::from(pel));
+    let time_weight_prev = u64::from(TIME_SCALE - time256);
+    let time_weight_next = u64::from(time256);
+
+    for y in 0..prev_layout.height.get() {
+        let dest_row = &mut dest[y * dest_pitch.get()..][..prev_layout.width.get()];
+        for (x, dest_pixel) in dest_row.iter_mut().enumerate() {
+            let (bw_x, bw_y, bw_sad) = flow_bw.get(x, y);
+            let (fw_x, fw_y, fw_sad) = flow_fw.get(x, y);
+            let pos_x = x as i32 * pel_val;
+            let pos_y = y as i32 * pel_val;
+
+            let pred_prev = sample(
+                prev,
+                prev_layout,
+                pel,
+                pos_x - (bw_x * time).round() as i32,
+                pos_y - (bw_y * time).round() as i32,
+            )
+            .to_u64()
+            .expect("fits in u64");
+            let pred_next = sample(
+                next,
+                next_layout,
+                pel,
+                pos_x - (fw_x * (1.0 - time)).round() as i32,
+                pos_y - (fw_y * (1.0 - time)).round() as i32,
+            )
+            .to_u64()
+            .expect("fits in u64");
+
+            let mut weight_prev = time_weight_prev * u64::from(degrain_weight(bw_sad, thsad));
+            let mut weight_next = time_weight_next * u64::from(degrain_weight(fw_sad, thsad));
+            if weight_prev + weight_next == 0 {
+                // Both predictions are likely occluded, so fall back to blending by time
+                weight_prev = time_weight_prev * u64::from(DEGRAIN_WEIGHT_FULL);
+                weight_next = time_weight_next * u64::from(DEGRAIN_WEIGHT_FULL);
+            }
+            let total = weight_prev + weight_next;
+            let value = (pred_prev * weight_prev + pred_next * weight_next + total / 2) / total;
+            *dest_pixel = T::from_u32_or_max_value(value as u32);
+        }
+    }
+}
+
+/// Blends two planes by time position, without any motion compensation.
+pub(crate) fn blend_plane<T: Pixel>(
+    dest: &mut [T],
+    dest_pitch: NonZeroUsize,
+    prev: &[T],
+    prev_pitch: NonZeroUsize,
+    next: &[T],
+    next_pitch: NonZeroUsize,
+    width: NonZeroUsize,
+    height: NonZeroUsize,
+    time256: u32,
+) {
+    let weight_next = time256;
+    let weight_prev = TIME_SCALE - time256;
+    for y in 0..height.get() {
+        let dest_row = &mut dest[y * dest_pitch.get()..][..width.get()];
+        let prev_row = &prev[y * prev_pitch.get()..][..width.get()];
+        let next_row = &next[y * next_pitch.get()..][..width.get()];
+        for ((dest, &prev), &next) in dest_row.iter_mut().zip(prev_row).zip(next_row) {
+            let prev = prev.to_u32().expect("fits in u32");
+            let next = next.to_u32().expect("fits in u32");
+            *dest = T::from_u32_or_max_value(
+                (prev * weight_prev + next * weight_next + TIME_SCALE / 2) / TIME_SCALE,
+            );
+        }
+    }
+}
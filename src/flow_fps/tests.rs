@@ -0,0 +1,222 @@
+#![allow(clippy::unwrap_used, reason = "allow in test files")]
+#![allow(clippy::undocumented_unsafe_blocks, reason = "allow in test files")]
+
+use super::*;
+use crate::tests::planes::{analysis_data, super_layout};
+
+fn uniform_vectors(count: usize, x: i32, y: i32, sad: i64) -> Vec<MotionVector> {
+    vec![MotionVector { x, y, sad }; count]
+}
+
+/// Builds a padded full-pel superframe plane containing a horizontal ramp
+/// shifted right by `shift` pixels.
+fn panned_plane(layout: &SuperPlaneLayout, shift: i32) -> Vec<u8> {
+    let padded_height = layout.height.get() + 2 * layout.vpad;
+    let mut plane = vec![0u8; layout.pitch.get() * padded_height];
+    for (row, line) in plane.chunks_exact_mut(layout.pitch.get()).enumerate() {
+        for (column, pixel) in line.iter_mut().enumerate() {
+            let x = column as i32 - layout.hpad as i32 - shift;
+            *pixel = (x * 3 + row as i32 + 64) as u8;
+        }
+    }
+    plane
+}
+
+fn luma_flow(vectors: &[MotionVector], data: &MVAnalysisData) -> FlowField {
+    FlowField::new(
+        vectors,
+        data,
+        data.width,
+        data.height,
+        NonZeroU8::MIN,
+        NonZeroU8::MIN,
+    )
+}
+
+#[test]
+fn time_position_doubling_frame_rate() {
+    // 2x frame rate: output frames alternate between source frames and midpoints
+    assert_eq!(time_position(0, 2, 1), (0, 0));
+    assert_eq!(time_position(1, 2, 1), (0, 128));
+    assert_eq!(time_position(2, 2, 1), (1, 0));
+    assert_eq!(time_position(5, 2, 1), (2, 128));
+}
+
+#[test]
+fn time_position_rounds_to_next_frame() {
+    // 1000x frame rate: the last step before a source frame rounds up to it
+    assert_eq!(time_position(999, 1000, 1), (1, 0));
+    assert_eq!(time_position(1, 3, 1), (0, 85));
+    assert_eq!(time_position(2, 3, 1), (0, 171));
+}
+
+#[test]
+fn flow_field_uniform_vectors() {
+    let data = analysis_data(4, 3, Subpel::Half);
+    let vectors = uniform_vectors(12, 6, -4, 100);
+    let flow = luma_flow(&vectors, &data);
+    for y in 0..data.height.get() {
+        for x in 0..data.width.get() {
+            assert_eq!(flow.get(x, y), (6.0, -4.0, 100));
+        }
+    }
+
+    // Chroma planes have half the resolution, so the vectors are halved as well
+    let ratio = NonZeroU8::new(2).unwrap();
+    let chroma = FlowField::new(
+        &vectors,
+        &data,
+        NonZeroUsize::new(16).unwrap(),
+        NonZeroUsize::new(12).unwrap(),
+        ratio,
+        ratio,
+    );
+    assert_eq!(chroma.get(0, 0), (3.0, -2.0, 100));
+    assert_eq!(chroma.get(15, 11), (3.0, -2.0, 100));
+}
+
+#[test]
+fn flow_field_interpolates_between_block_centers() {
+    let data = analysis_data(2, 1, Subpel::Full);
+    let vectors = [MotionVector { x: 0, y: 0, sad: 0 }, MotionVector {
+        x: 8,
+        y: -8,
+        sad: 800,
+    }];
+    let flow = luma_flow(&vectors, &data);
+
+    // Pixels before the first block center and after the last one use the edge vectors
+    assert_eq!(flow.get(0, 0), (0.0, 0.0, 0));
+    assert_eq!(flow.get(3, 0), (0.0, 0.0, 0));
+    assert_eq!(flow.get(12, 0), (8.0, -8.0, 800));
+    assert_eq!(flow.get(15, 0), (8.0, -8.0, 800));
+
+    // Pixel centers between the block centers are interpolated linearly
+    assert_eq!(flow.get(4, 0), (0.5, -0.5, 50));
+    assert_eq!(flow.get(7, 0), (3.5, -3.5, 350));
+    assert_eq!(flow.get(11, 0), (7.5, -7.5, 750));
+}
+
+#[test]
+fn midpoint_of_linear_pan() {
+    let data = analysis_data(4, 2, Subpel::Full);
+    let layout = super_layout(false, data.width.get(), data.height.get(), Subpel::Full);
+    let prev = panned_plane(&layout, 0);
+    let next = panned_plane(&layout, 4);
+    // Content moves right by 4 pixels from the previous frame to the next one
+    let flow_bw = luma_flow(&uniform_vectors(8, 4, 0, 0), &data);
+    let flow_fw = luma_flow(&uniform_vectors(8, -4, 0, 0), &data);
+    let expected = panned_plane(&layout, 2);
+
+    let mut dest = vec![0u8; layout.pitch.get() * data.height.get()];
+    flow_inter(
+        &mut dest,
+        layout.pitch,
+        &prev,
+        &layout,
+        &next,
+        &layout,
+        Subpel::Full,
+        &flow_bw,
+        &flow_fw,
+        128,
+        400,
+    );
+
+    for y in 0..data.height.get() {
+        let dest_row = &dest[y * layout.pitch.get()..][..data.width.get()];
+        let expected_row =
+            &expected[layout.offset_data() + y * layout.pitch.get()..][..data.width.get()];
+        assert_eq!(dest_row, expected_row, "row {y}");
+    }
+}
+
+#[test]
+fn midpoint_of_single_pixel_pan() {
+    let data = analysis_data(4, 2, Subpel::Full);
+    let layout = super_layout(false, data.width.get(), data.height.get(), Subpel::Full);
+    let prev = panned_plane(&layout, 0);
+    let next = panned_plane(&layout, 2);
+    let flow_bw = luma_flow(&uniform_vectors(8, 2, 0, 0), &data);
+    let flow_fw = luma_flow(&uniform_vectors(8, -2, 0, 0), &data);
+    let expected = panned_plane(&layout, 1);
+
+    // Both predictions are one pixel away from their source frame
+    let mut dest = vec![0u8; layout.pitch.get() * data.height.get()];
+    flow_inter(
+        &mut dest,
+        layout.pitch,
+        &prev,
+        &layout,
+        &next,
+        &layout,
+        Subpel::Full,
+        &flow_bw,
+        &flow_fw,
+        128,
+        400,
+    );
+    let dest_row = &dest[..data.width.get()];
+    let expected_row = &expected[layout.offset_data()..][..data.width.get()];
+    assert_eq!(dest_row, expected_row);
+}
+
+#[test]
+fn occluded_prediction_is_ignored() {
+    let data = analysis_data(4, 2, Subpel::Full);
+    let layout = super_layout(false, data.width.get(), data.height.get(), Subpel::Full);
+    let prev = vec![40u8; layout.pitch.get() * (data.height.get() + 16)];
+    let next = vec![200u8; layout.pitch.get() * (data.height.get() + 16)];
+    let flow_good = luma_flow(&uniform_vectors(8, 0, 0, 0), &data);
+    let flow_occluded = luma_flow(&uniform_vectors(8, 0, 0, 1_000_000), &data);
+
+    let mut dest = vec![0u8; layout.pitch.get() * data.height.get()];
+    flow_inter(
+        &mut dest,
+        layout.pitch,
+        &prev,
+        &layout,
+        &next,
+        &layout,
+        Subpel::Full,
+        &flow_good,
+        &flow_occluded,
+        128,
+        400,
+    );
+    assert!(dest[..data.width.get()].iter().all(|&pixel| pixel == 40));
+
+    // When both predictions are occluded, they are blended by time
+    flow_inter(
+        &mut dest,
+        layout.pitch,
+        &prev,
+        &layout,
+        &next,
+        &layout,
+        Subpel::Full,
+        &flow_occluded,
+        &flow_occluded,
+        64,
+        400,
+    );
+    assert!(dest[..data.width.get()].iter().all(|&pixel| pixel == 80));
+}
+
+#[test]
+fn blend_plane_by_time() {
+    let width = NonZeroUsize::new(4).unwrap();
+    let height = NonZeroUsize::new(2).unwrap();
+    let pitch = NonZeroUsize::new(8).unwrap();
+    let prev = vec![1000u16; 16];
+    let next = vec![2000u16; 16];
+    let mut dest = vec![0u16; 16];
+
+    blend_plane(
+        &mut dest, pitch, &prev, pitch, &next, pitch, width, height, 64,
+    );
+    assert_eq!(&dest[..4], &[1250; 4]);
+    assert_eq!(&dest[8..12], &[1250; 4]);
+    // Pixels beyond the width are left untouched
+    assert_eq!(&dest[4..8], &[0; 4]);
+}
@@ -0,0 +1,154 @@
+//! Per-frame global motion estimation from a motion vector field, similar to DepanAnalyse
+
+#[cfg(test)]
+mod tests;
+
+use std::collections::HashMap;
+
+use anyhow::{Result, anyhow, bail};
+use vapoursynth::{
+    core::CoreRef,
+    frame::{FrameRef, FrameRefMut},
+    node::Node,
+    plugins::{Filter, FrameContext},
+    prelude::API,
+    video_info::VideoInfo,
+};
+
+use crate::{mv::MotionVector, mv_clip::MVClip, params::Subpel};
+
+pub(crate) const PROP_GLOBAL_MOTION_X: &str = "GlobalMotionX";
+pub(crate) const PROP_GLOBAL_MOTION_Y: &str = "GlobalMotionY";
+
+/// Vectors further than this from the most frequent vector, in pixels,
+/// are treated as outliers and excluded from the average.
+const INLIER_RADIUS: i32 = 2;
+
+/// Reduces the dense motion vector field of each frame to a single global translation,
+/// and attaches it to the frames of `clip` as the `GlobalMotionX` and `GlobalMotionY`
+/// frame properties, in pixels.
+///
+/// As with the vectors themselves, the translation points from the current frame
+/// to the reference frame.
+#[derive(Debug)]
+pub struct GlobalMotion<'core> {
+    /// Clip to attach the global motion properties to
+    node: Node<'core>,
+    /// Vectors clip from `Analyse`
+    vectors: MVClip<'core>,
+}
+
+impl<'core> GlobalMotion<'core> {
+    pub fn new(clip: Node<'core>, vectors: Node<'core>) -> Result<Self> {
+        let vectors = MVClip::new(vectors, "GlobalMotion")?;
+        if vectors.node().info().num_frames < clip.info().num_frames {
+            bail!("GlobalMotion: vectors clip must have at least as many frames as clip");
+        }
+
+        Ok(Self {
+            node: clip,
+            vectors,
+        })
+    }
+}
+
+impl<'core> Filter<'core> for GlobalMotion<'core> {
+    fn video_info(&self, _api: API, _core: CoreRef<'core>) -> Vec<VideoInfo<'core>> {
+        vec![self.node.info()]
+    }
+
+    fn get_frame_initial(
+        &self,
+        _api: API,
+        _core: CoreRef<'core>,
+        context: FrameContext,
+        n: usize,
+    ) -> Result<Option<FrameRef<'core>>> {
+        self.vectors.node().request_frame_filter(context, n);
+        self.node.request_frame_filter(context, n);
+        Ok(None)
+    }
+
+    fn get_frame(
+        &self,
+        _api: API,
+        core: CoreRef<'core>,
+        context: FrameContext,
+        n: usize,
+    ) -> Result<FrameRef<'core>> {
+        let src = self
+            .node
+            .get_frame_filter(context, n)
+            .ok_or_else(|| anyhow!("GlobalMotion: get_frame_filter past end of video"))?;
+        let vectors_frame = self
+            .vectors
+            .node()
+            .get_frame_filter(context, n)
+            .ok_or_else(|| anyhow!("GlobalMotion: get_frame_filter vectors past end of video"))?;
+
+        let (x, y) = self
+            .vectors
+            .finest_vectors(&vectors_frame)
+            .map_err(|e| anyhow!("GlobalMotion: {e}"))?
+            .and_then(|vectors| estimate_global_motion(&vectors, self.vectors.analysis_data().pel))
+            .unwrap_or((0.0, 0.0));
+
+        let mut dest = FrameRefMut::copy_of(core, &src);
+        let mut dest_props = dest.props_mut();
+        dest_props.set_float(PROP_GLOBAL_MOTION_X, x)?;
+        dest_props.set_float(PROP_GLOBAL_MOTION_Y, y)?;
+
+        Ok(dest.into())
+    }
+}
+
+/// Estimates the global translation, in pixels, from a field of vectors in `pel` units.
+///
+/// The most frequent vector components are found first, then all vectors close to them
+/// are averaged, so that independently moving objects do not skew the estimate.
+///
+/// Returns `None` if there are no vectors.
+#[must_use]
+pub(crate) fn estimate_global_motion(vectors: &[MotionVector], pel: Subpel) -> Option<(f64, f64)> {
+    let mode_x = most_frequent(vectors.iter().map(|v| v.x))?;
+    let mode_y = most_frequent(vectors.iter().map(|v| v.y))?;
+
+    let radius = INLIER_RADIUS * i32::from(u8::from(pel));
+    let mut sum_x = 0i64;
+    let mut sum_y = 0i64;
+    let mut num = 0i64;
+    for v in vectors {
+        if (v.x - mode_x).abs() <= radius && (v.y - mode_y).abs() <= radius {
+            sum_x += i64::from(v.x);
+            sum_y += i64::from(v.y);
+            num += 1;
+        }
+    }
+
+    let pel = f64::from(u8::from(pel));
+    if num > 0 {
+        Some((
+            sum_x as f64 / num as f64 / pel,
+            sum_y as f64 / num as f64 / pel,
+        ))
+    } else {
+        Some((f64::from(mode_x) / pel, f64::from(mode_y) / pel))
+    }
+}
+
+/// Finds the most frequent value, preferring the value closest to zero on ties.
+fn most_frequent(values: impl Iterator<Item = i32>) -> Option<i32> {
+    let mut counts: HashMap<i32, usize> = HashMap::new();
+    for value in values {
+        *counts.entry(value).or_default() += 1;
+    }
+    counts
+        .into_iter()
+        .max_by(|(a, count_a), (b, count_b)| {
+            count_a
+                .cmp(count_b)
+                .then_with(|| b.abs().cmp(&a.abs()))
+                .then_with(|| b.cmp(a))
+        })
+        .map(|(value, _)| value)
+}
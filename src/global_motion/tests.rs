@@ -0,0 +1,70 @@
+#![allow(clippy::unwrap_used, reason = "allow in test files")]
+#![allow(clippy::undocumented_unsafe_blocks, reason = "allow in test files")]
+
+use super::*;
+
+/// Builds a `blk_x` by `blk_y` vector field panning by `(pan_x, pan_y)` in `pel` units,
+/// with a small amount of jitter and a moving object covering the top left corner.
+fn pan_field(blk_x: usize, blk_y: usize, pan_x: i32, pan_y: i32) -> Vec<MotionVector> {
+    let mut vectors = Vec::with_capacity(blk_x * blk_y);
+    for by in 0..blk_y {
+        for bx in 0..blk_x {
+            let (x, y) = if bx < blk_x / 3 && by < blk_y / 3 {
+                // independently moving object
+                (-20, 15)
+            } else {
+                let jitter = ((bx + by) % 3) as i32 - 1;
+                (pan_x + jitter, pan_y - jitter)
+            };
+            vectors.push(MotionVector { x, y, sad: 100 });
+        }
+    }
+    vectors
+}
+
+#[test]
+fn recovers_pure_pan() {
+    for (pel, pan_x, pan_y) in [
+        (Subpel::Full, 5, -3),
+        (Subpel::Half, 14, 6),
+        (Subpel::Quarter, -26, 9),
+    ] {
+        let vectors = pan_field(40, 30, pan_x, pan_y);
+        let (x, y) = estimate_global_motion(&vectors, pel).unwrap();
+        let pel = f64::from(u8::from(pel));
+        let expected_x = f64::from(pan_x) / pel;
+        let expected_y = f64::from(pan_y) / pel;
+        assert!(
+            (x - expected_x).abs() < 1.0 && (y - expected_y).abs() < 1.0,
+            "expected ({expected_x}, {expected_y}), got ({x}, {y})"
+        );
+    }
+}
+
+#[test]
+fn exact_pan_without_jitter() {
+    let vectors = vec![
+        MotionVector {
+            x: 6,
+            y: -2,
+            sad: 0
+        };
+        100
+    ];
+    assert_eq!(
+        estimate_global_motion(&vectors, Subpel::Half),
+        Some((3.0, -1.0))
+    );
+}
+
+#[test]
+fn empty_field_has_no_estimate() {
+    assert_eq!(estimate_global_motion(&[], Subpel::Full), None);
+}
+
+#[test]
+fn most_frequent_prefers_zero_on_ties() {
+    assert_eq!(most_frequent([3, 3, 0, 0, -3].into_iter()), Some(0));
+    assert_eq!(most_frequent([2, -2].into_iter()), Some(-2));
+    assert_eq!(most_frequent([7, 1, 7].into_iter()), Some(7));
+}
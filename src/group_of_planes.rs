@@ -1,19 +1,79 @@
-use std::{
-    mem::transmute,
-    num::{NonZeroU8, NonZeroUsize},
-};
+#[cfg(test)]
+mod tests;
+
+use std::num::{NonZeroU8, NonZeroUsize};
 
 use anyhow::{Result, anyhow};
+use rayon::ThreadPool;
 use vapoursynth::frame::Frame;
 
 use crate::{
-    mv::{MV_SIZE, MotionVector},
+    dct::DctPool,
+    mv::{MV_SIZE, MotionVector, MvBounds, limit_mv},
     mv_gof::MVGroupOfFrames,
     params::{DctMode, DivideMode, MotionFlags, PenaltyScaling, SearchType, Subpel},
-    plane_of_blocks::{MvsOutput, PlaneOfBlocks},
+    plane_of_blocks::{MvsOutput, PlaneOfBlocks, SearchStats},
     util::{Pixel, median},
 };
 
+/// Number of blocks horizontally and vertically at the hierarchical `level`.
+///
+/// Every level uses the same block size, so each block of a coarser level covers twice
+/// the width and height of the finest level. `width` and `height` are the size of the
+/// finest level. Returns `None` if not even a single block fits at this level.
+#[must_use]
+pub(crate) fn level_blocks(
+    width: usize,
+    height: usize,
+    level: usize,
+    blk_size_x: NonZeroUsize,
+    blk_size_y: NonZeroUsize,
+    overlap_x: usize,
+    overlap_y: usize,
+) -> Option<(NonZeroUsize, NonZeroUsize)> {
+    let blocks = |size: usize, blk_size: NonZeroUsize, overlap: usize| {
+        let step = blk_size
+            .get()
+            .checked_sub(overlap)
+            .filter(|&step| step > 0)?;
+        NonZeroUsize::new((size >> level).checked_sub(overlap)? / step)
+    };
+    Some((
+        blocks(width, blk_size_x, overlap_x)?,
+        blocks(height, blk_size_y, overlap_y)?,
+    ))
+}
+
+/// Number of hierarchical levels to search, at most `levels` if it is given.
+///
+/// Coarser levels halve the plane, not the block, so `blksize` is searched at every level
+/// and always stays one of the [`BLOCK_SIZES`](crate::mv_analyse::BLOCK_SIZES) that `get_sad`
+/// supports. Levels are only limited by how many blocks still fit: the coarsest level is the
+/// last one with at least one block, however many levels are requested. Smaller blocks
+/// therefore allow more levels.
+#[must_use]
+pub(crate) fn level_count(
+    width: usize,
+    height: usize,
+    blk_size_x: NonZeroUsize,
+    blk_size_y: NonZeroUsize,
+    overlap_x: usize,
+    overlap_y: usize,
+    levels: Option<usize>,
+) -> usize {
+    let mut levels_max = 0;
+    while level_blocks(
+        width, height, levels_max, blk_size_x, blk_size_y, overlap_x, overlap_y,
+    )
+    .is_some()
+    {
+        levels_max += 1;
+    }
+    levels
+        .filter(|&levels| levels > 0)
+        .map_or(levels_max, |levels| levels.min(levels_max))
+}
+
 #[allow(dead_code)]
 pub struct GroupOfPlanes<T: Pixel> {
     pub blk_size_x: NonZeroUsize,
@@ -57,14 +117,22 @@ impl<T: Pixel> GroupOfPlanes<T> {
                 motion_flags_current |= MotionFlags::SMALLEST_PLANE;
             }
 
-            let blk_x_current = NonZeroUsize::new(
-                ((width_b.get() >> i) - overlap_x) / (blk_size_x.get() - overlap_x),
-            )
-            .ok_or_else(|| anyhow!("invalid block x calculation"))?;
-            let blk_y_current = NonZeroUsize::new(
-                ((height_b.get() >> i) - overlap_y) / (blk_size_y.get() - overlap_y),
+            let (blk_x_current, blk_y_current) = level_blocks(
+                width_b.get(),
+                height_b.get(),
+                i,
+                blk_size_x,
+                blk_size_y,
+                overlap_x,
+                overlap_y,
             )
-            .ok_or_else(|| anyhow!("invalid block y calculation"))?;
+            .ok_or_else(|| {
+                anyhow!(
+                    "level {i} is too small for a single {}x{} block",
+                    blk_size_x,
+                    blk_size_y
+                )
+            })?;
 
             planes.push(PlaneOfBlocks::new(
                 blk_x_current,
@@ -96,6 +164,16 @@ impl<T: Pixel> GroupOfPlanes<T> {
         })
     }
 
+    /// Searches only the blocks of a region whose top left corner is luma pixel `(left, top)`
+    /// of the finest level, which is halved along with the planes at coarser levels.
+    ///
+    /// `blk_x` and `blk_y` must count the blocks of the region rather than of the whole frame.
+    pub(crate) fn set_origin(&mut self, left: usize, top: usize) {
+        for (level, plane) in self.planes.iter_mut().enumerate() {
+            plane.set_origin(left >> level, top >> level);
+        }
+    }
+
     pub fn search_mvs(
         &mut self,
         src_gof: &MVGroupOfFrames,
@@ -115,10 +193,14 @@ impl<T: Pixel> GroupOfPlanes<T> {
         dct_mode: DctMode,
         penalty_zero: u16,
         mut penalty_global: u16,
+        mut global_weight: u16,
         bad_sad: u64,
+        trivial_sad: u64,
         bad_range: i32,
         meander: bool,
         try_many: bool,
+        dct_pool: &DctPool,
+        pool: Option<&ThreadPool>,
     ) -> Result<MvsOutput> {
         let mut out_idx = 0;
         let size = self.get_array_size();
@@ -144,23 +226,20 @@ impl<T: Pixel> GroupOfPlanes<T> {
 
         let mut global_mv = MotionVector::zero();
         if !global {
+            // The global predictor stays at zero, which must not pull the others
             penalty_global = penalty_zero;
+            global_weight = 0;
         }
 
         // Search the motion vectors, for the low details interpolations first
         let mut mean_luma_change = 0;
-        let search_type_smallest = if self.level_count == 1
-            || [SearchType::Horizontal, SearchType::Vertical].contains(&search_type)
-        {
-            search_type
-        } else {
-            search_type_coarse
-        };
-        let search_param_smallest = if self.level_count == 1 {
-            pel_search
-        } else {
-            search_param
-        };
+        let (search_type_smallest, search_param_smallest) = level_search(
+            self.level_count - 1,
+            search_type,
+            search_type_coarse,
+            search_param,
+            pel_search,
+        );
         let try_many_level = try_many && self.level_count > 1;
         self.planes[self.level_count - 1].search_mvs(
             out_idx,
@@ -181,10 +260,14 @@ impl<T: Pixel> GroupOfPlanes<T> {
             &mut mean_luma_change,
             penalty_zero,
             penalty_global,
+            global_weight,
             bad_sad,
+            trivial_sad,
             bad_range,
             meander,
             try_many_level,
+            dct_pool,
+            pool,
         )?;
         out_idx += self.planes[self.level_count - 1]
             .get_array_size(self.divide_extra)
@@ -192,17 +275,8 @@ impl<T: Pixel> GroupOfPlanes<T> {
 
         // Refining the search until we reach the highest detail interpolation.
         for i in (0..=(self.level_count - 2)).rev() {
-            // full search for coarse planes
-            let search_type_level = if i == 0
-                || search_type == SearchType::Horizontal
-                || search_type == SearchType::Vertical
-            {
-                search_type
-            } else {
-                search_type_coarse
-            };
-            // special case for finest level
-            let search_param_level = if i == 0 { pel_search } else { search_param };
+            let (search_type_level, search_param_level) =
+                level_search(i, search_type, search_type_coarse, search_param, pel_search);
 
             // Use split_at_mut to avoid borrowing conflicts
             let (planes_left, planes_right) = self.planes.split_at_mut(i + 1);
@@ -238,10 +312,14 @@ impl<T: Pixel> GroupOfPlanes<T> {
                 &mut mean_luma_change,
                 penalty_zero,
                 penalty_global,
+                global_weight,
                 bad_sad,
+                trivial_sad,
                 bad_range,
                 meander,
                 try_many_level,
+                dct_pool,
+                pool,
             )?;
             out_idx += self.planes[i].get_array_size(self.divide_extra).get();
         }
@@ -249,12 +327,69 @@ impl<T: Pixel> GroupOfPlanes<T> {
         Ok(vectors)
     }
 
-    pub fn extra_divide(&self, out: &mut MvsOutput) {
+    /// Statistics about the most recent search. The clamped and bad vector counts
+    /// are summed over all levels, while the SADs are those of the finest level.
+    #[must_use]
+    pub(crate) fn search_stats(&self) -> SearchStats {
+        let mut stats = self.planes[0].search_stats();
+        for plane in &self.planes[1..] {
+            let level_stats = plane.search_stats();
+            stats.clamped_count += level_stats.clamped_count;
+            stats.bad_count += level_stats.bad_count;
+        }
+        stats
+    }
+
+    /// The search range of every block of the finest level, in raster order.
+    #[must_use]
+    pub(crate) fn search_bounds(&mut self, src_gof: &MVGroupOfFrames) -> Vec<MvBounds> {
+        self.planes[0].search_bounds(&src_gof.frames[0])
+    }
+
+    /// Offset of the size of the finest level in the output of [`Self::search_mvs`].
+    #[must_use]
+    fn finest_level_offset(&self) -> usize {
         let mut start_idx = 2 * size_of::<i32>();
         // skip all levels up to finest estimated
         for i in (1..self.level_count).rev() {
             start_idx += self.planes[i].get_array_size(DivideMode::None).get();
         }
+        start_idx
+    }
+
+    /// Shortens the finest level vectors to a length of at most `limit`, in units of `pel`.
+    ///
+    /// Must be called after [`Self::extra_divide`], so the divided blocks are limited as well.
+    /// A shortened vector no longer points at the block its SAD was measured on, so it is given
+    /// the SAD of a failed search, which makes the consumers ignore the block.
+    pub(crate) fn limit_vectors(&self, out: &mut MvsOutput, limit: u32) {
+        let blk_count = self.planes[0].blk_x.get() * self.planes[0].blk_y.get();
+        let very_big_sad = self.planes[0].very_big_sad.get() as i64;
+        let start_idx = self.finest_level_offset();
+        limit_level(
+            &mut out.block_data[start_idx + size_of::<u32>()..][..blk_count * MV_SIZE],
+            limit,
+            very_big_sad,
+        );
+
+        if self.divide_extra != DivideMode::None {
+            let size = i32::from_le_bytes(
+                out.block_data[start_idx..][..size_of::<i32>()]
+                    .try_into()
+                    .expect("slice with incorrect length"),
+            ) as usize;
+            // The divided blocks are a quarter of the size, as in `extra_divide_block_data`
+            limit_level(
+                &mut out.block_data[start_idx + size + size_of::<u32>()..]
+                    [..blk_count * 4 * MV_SIZE],
+                limit,
+                very_big_sad >> 2,
+            );
+        }
+    }
+
+    pub fn extra_divide(&self, out: &mut MvsOutput) {
+        let start_idx = self.finest_level_offset();
 
         let size = i32::from_le_bytes(
             out.block_data[start_idx..][..size_of::<i32>()]
@@ -372,6 +507,42 @@ impl<T: Pixel> GroupOfPlanes<T> {
     }
 }
 
+/// Selects the search type and parameter for the hierarchical `level`, where 0 is the finest.
+///
+/// Coarse levels use `search_coarse` with `searchparam`, and the finest level uses `search`
+/// with `pelsearch`. Horizontal and vertical searches are used on every level,
+/// so the vectors stay constrained to that direction.
+#[must_use]
+fn level_search(
+    level: usize,
+    search_type: SearchType,
+    search_type_coarse: SearchType,
+    search_param: i32,
+    pel_search: i32,
+) -> (SearchType, i32) {
+    if level == 0 {
+        (search_type, pel_search)
+    } else if [SearchType::Horizontal, SearchType::Vertical].contains(&search_type) {
+        (search_type, search_param)
+    } else {
+        (search_type_coarse, search_param)
+    }
+}
+
+fn limit_level(block_data: &mut [u8], limit: u32, very_big_sad: i64) {
+    for bytes in block_data.chunks_exact_mut(MV_SIZE) {
+        let mv = MotionVector::read_from(bytes);
+        let limited = limit_mv(mv, limit);
+        if limited != mv {
+            MotionVector {
+                sad: very_big_sad,
+                ..limited
+            }
+            .write_to(bytes);
+        }
+    }
+}
+
 fn extra_divide_block_data(
     out: &mut MvsOutput,
     in_idx: usize,
@@ -379,26 +550,18 @@ fn extra_divide_block_data(
     bx: usize,
     blk_x: usize,
 ) {
-    // SAFETY: Size is checked
-    let mut block: MotionVector = unsafe {
-        transmute::<[u8; MV_SIZE], _>(
-            out.block_data[in_idx + bx * MV_SIZE..][..MV_SIZE]
-                .try_into()
-                .expect("slice with incorrect length"),
-        )
-    };
+    let mut block = MotionVector::read_from(&out.block_data[in_idx + bx * MV_SIZE..]);
     block.sad >>= 2;
 
-    let block_bytes = block.bytes();
     let data = &mut out.block_data[out_idx..];
     // top left subblock
-    data[bx * 2 * MV_SIZE..][..MV_SIZE].copy_from_slice(block_bytes);
+    block.write_to(&mut data[bx * 2 * MV_SIZE..]);
     // top right subblock
-    data[(bx * 2 + 1) * MV_SIZE..][..MV_SIZE].copy_from_slice(block_bytes);
+    block.write_to(&mut data[(bx * 2 + 1) * MV_SIZE..]);
     // bottom left subblock
-    data[(bx * 2 + blk_x * 2) * MV_SIZE..][..MV_SIZE].copy_from_slice(block_bytes);
+    block.write_to(&mut data[(bx * 2 + blk_x * 2) * MV_SIZE..]);
     // bottom right subblock
-    data[(bx * 2 + blk_x * 2 + 1) * MV_SIZE..][..MV_SIZE].copy_from_slice(block_bytes);
+    block.write_to(&mut data[(bx * 2 + blk_x * 2 + 1) * MV_SIZE..]);
 }
 
 fn get_median(v: &mut MotionVector, v1: MotionVector, v2: MotionVector, v3: MotionVector) {
@@ -424,39 +587,12 @@ fn assign_median(
     in_3_offset: isize,
     out_offset: usize,
 ) {
-    // SAFETY: block data is always transmuted to and from `MotionVector`s
-    let blkin_1: MotionVector = unsafe {
-        transmute::<[u8; MV_SIZE], _>(
-            out.block_data[in_idx + in_1_offset * MV_SIZE..][..MV_SIZE]
-                .try_into()
-                .expect("slice with incorrect length"),
-        )
-    };
-    // SAFETY: block data is always transmuted to and from `MotionVector`s
-    let blkin_2: MotionVector = unsafe {
-        transmute::<[u8; MV_SIZE], _>(
-            out.block_data[in_idx + in_2_offset * MV_SIZE..][..MV_SIZE]
-                .try_into()
-                .expect("slice with incorrect length"),
-        )
-    };
-    // SAFETY: block data is always transmuted to and from `MotionVector`s
-    let blkin_3: MotionVector = unsafe {
-        let start_idx = (in_idx as isize + in_3_offset * MV_SIZE as isize) as usize;
-        transmute::<[u8; MV_SIZE], _>(
-            out.block_data[start_idx..][..MV_SIZE]
-                .try_into()
-                .expect("slice with incorrect length"),
-        )
-    };
-    // SAFETY: block data is always transmuted to and from `MotionVector`s
-    let mut blkout: MotionVector = unsafe {
-        transmute::<[u8; MV_SIZE], _>(
-            out.block_data[out_idx + out_offset * MV_SIZE..][..MV_SIZE]
-                .try_into()
-                .expect("slice with incorrect length"),
-        )
-    };
+    let blkin_1 = MotionVector::read_from(&out.block_data[in_idx + in_1_offset * MV_SIZE..]);
+    let blkin_2 = MotionVector::read_from(&out.block_data[in_idx + in_2_offset * MV_SIZE..]);
+    let in_3_idx = (in_idx as isize + in_3_offset * MV_SIZE as isize) as usize;
+    let blkin_3 = MotionVector::read_from(&out.block_data[in_3_idx..]);
+    let out_data = &mut out.block_data[out_idx + out_offset * MV_SIZE..];
+    let mut blkout = MotionVector::read_from(out_data);
     get_median(&mut blkout, blkin_1, blkin_2, blkin_3);
-    out.block_data[out_idx + out_offset * MV_SIZE..][..MV_SIZE].copy_from_slice(blkout.bytes());
+    blkout.write_to(out_data);
 }
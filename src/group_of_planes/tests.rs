@@ -0,0 +1,362 @@
+#![allow(clippy::unwrap_used, reason = "allow in test files")]
+#![allow(clippy::undocumented_unsafe_blocks, reason = "allow in test files")]
+
+use super::*;
+
+fn create_group(divide_extra: DivideMode) -> GroupOfPlanes<u8> {
+    GroupOfPlanes::new(
+        NonZeroUsize::new(8).unwrap(),
+        NonZeroUsize::new(8).unwrap(),
+        2,
+        Subpel::Half,
+        MotionFlags::empty(),
+        0,
+        0,
+        NonZeroUsize::new(4).unwrap(),
+        NonZeroUsize::new(3).unwrap(),
+        NonZeroU8::new(2).unwrap(),
+        NonZeroU8::new(2).unwrap(),
+        divide_extra,
+        NonZeroU8::new(8).unwrap(),
+    )
+    .unwrap()
+}
+
+fn read_vectors(data: &[u8], count: usize) -> Vec<(i32, i32)> {
+    data[..count * MV_SIZE]
+        .chunks_exact(MV_SIZE)
+        .map(|bytes| {
+            let v = MotionVector::read_from(bytes);
+            (v.x, v.y)
+        })
+        .collect()
+}
+
+#[test]
+fn limit_vectors_only_changes_finest_level() {
+    let group = create_group(DivideMode::None);
+    let mut out = group.write_default_to_array();
+    let finest = group.finest_level_offset() + size_of::<u32>();
+    let coarse = 2 * size_of::<i32>() + size_of::<u32>();
+    let long = MotionVector {
+        x: 60,
+        y: -80,
+        sad: 0,
+    };
+    for i in 0..12 {
+        long.write_to(&mut out.block_data[finest + i * MV_SIZE..]);
+    }
+    // The coarse level has 2x1 blocks
+    for i in 0..2 {
+        long.write_to(&mut out.block_data[coarse + i * MV_SIZE..]);
+    }
+
+    group.limit_vectors(&mut out, 20);
+    assert_eq!(read_vectors(&out.block_data[finest..], 12), vec![
+        (12, -16);
+        12
+    ]);
+    assert_eq!(read_vectors(&out.block_data[coarse..], 2), vec![
+        (60, -80);
+        2
+    ]);
+}
+
+#[test]
+fn limit_vectors_after_extra_divide() {
+    let group = create_group(DivideMode::Original);
+    let mut out = group.write_default_to_array();
+    let finest = group.finest_level_offset() + size_of::<u32>();
+    let long = MotionVector {
+        x: -30,
+        y: 40,
+        sad: 400,
+    };
+    for i in 0..12 {
+        long.write_to(&mut out.block_data[finest + i * MV_SIZE..]);
+    }
+
+    group.extra_divide(&mut out);
+    group.limit_vectors(&mut out, 5);
+    assert_eq!(read_vectors(&out.block_data[finest..], 12), vec![
+        (-3, 4);
+        12
+    ]);
+    // The divided blocks follow the finest level and its size
+    let divided = finest + 12 * MV_SIZE + size_of::<u32>();
+    assert_eq!(read_vectors(&out.block_data[divided..], 48), vec![
+        (-3, 4);
+        48
+    ]);
+    let very_big_sad = group.planes[0].very_big_sad.get() as i64;
+    for i in 0..48 {
+        let v = MotionVector::read_from(&out.block_data[divided + i * MV_SIZE..]);
+        assert_eq!(v.sad, very_big_sad >> 2, "subblock {i}");
+    }
+}
+
+#[test]
+fn limited_vectors_get_the_sad_of_a_failed_search() {
+    let group = create_group(DivideMode::None);
+    let mut out = group.write_default_to_array();
+    let finest = group.finest_level_offset() + size_of::<u32>();
+    // The long vector matched its block well, but the shortened one points somewhere else
+    MotionVector {
+        x: 60,
+        y: -80,
+        sad: 10,
+    }
+    .write_to(&mut out.block_data[finest..]);
+    let short = MotionVector {
+        x: 6,
+        y: -8,
+        sad: 30,
+    };
+    short.write_to(&mut out.block_data[finest + MV_SIZE..]);
+
+    group.limit_vectors(&mut out, 20);
+    assert_eq!(
+        MotionVector::read_from(&out.block_data[finest..]),
+        MotionVector {
+            x: 12,
+            y: -16,
+            sad: group.planes[0].very_big_sad.get() as i64,
+        }
+    );
+    assert_eq!(
+        MotionVector::read_from(&out.block_data[finest + MV_SIZE..]),
+        short
+    );
+}
+
+/// Divides the 4x3 finest level blocks, whose vectors are given by `vector(bx, by)`,
+/// returning the 8x6 subblock vectors.
+fn divide(
+    divide_extra: DivideMode,
+    vector: impl Fn(usize, usize) -> (i32, i32),
+) -> Vec<(i32, i32)> {
+    let group = create_group(divide_extra);
+    let mut out = group.write_default_to_array();
+    let finest = group.finest_level_offset() + size_of::<u32>();
+    for i in 0..12 {
+        let (x, y) = vector(i % 4, i / 4);
+        MotionVector { x, y, sad: 0 }.write_to(&mut out.block_data[finest + i * MV_SIZE..]);
+    }
+
+    group.extra_divide(&mut out);
+    read_vectors(
+        &out.block_data[finest + 12 * MV_SIZE + size_of::<u32>()..],
+        48,
+    )
+}
+
+/// Sum of the vector differences between horizontally and vertically adjacent subblocks
+fn total_variation(subblocks: &[(i32, i32)]) -> i32 {
+    let difference = |a: (i32, i32), b: (i32, i32)| (a.0 - b.0).abs() + (a.1 - b.1).abs();
+    let mut variation = 0;
+    for y in 0..6 {
+        for x in 0..8 {
+            let v = subblocks[y * 8 + x];
+            if x < 7 {
+                variation += difference(v, subblocks[y * 8 + x + 1]);
+            }
+            if y < 5 {
+                variation += difference(v, subblocks[(y + 1) * 8 + x]);
+            }
+        }
+    }
+    variation
+}
+
+#[test]
+fn divide_original_copies_block_vectors() {
+    let vector = |bx, by| if bx >= 1 && by >= 1 { (8, -4) } else { (0, 0) };
+    let subblocks = divide(DivideMode::Original, vector);
+    for (i, &v) in subblocks.iter().enumerate() {
+        let (x, y) = (i % 8, i / 8);
+        assert_eq!(v, vector(x / 2, y / 2), "subblock ({x}, {y})");
+    }
+}
+
+#[test]
+fn divide_median_smooths_sharp_boundaries() {
+    // A moving area in the bottom right, whose top left corner is block (1, 1)
+    let corner = |bx, by| if bx >= 1 && by >= 1 { (8, -4) } else { (0, 0) };
+    let original = divide(DivideMode::Original, corner);
+    let median = divide(DivideMode::Median, corner);
+    // The corner subblock is mostly surrounded by static blocks
+    assert_eq!(median[2 * 8 + 2], (0, 0));
+    assert_eq!(
+        median.iter().zip(&original).filter(|(m, o)| m != o).count(),
+        1
+    );
+
+    // An isolated vector inside the frame disappears entirely
+    let isolated = |bx, by| if (bx, by) == (2, 1) { (-6, 10) } else { (2, 2) };
+    let original = divide(DivideMode::Original, isolated);
+    let median = divide(DivideMode::Median, isolated);
+    assert_eq!(median, vec![(2, 2); 48]);
+    assert!(total_variation(&median) < total_variation(&original));
+
+    // Straight boundaries are kept as they are
+    let edge = |bx, _| if bx >= 2 { (4, 0) } else { (0, 0) };
+    assert_eq!(
+        divide(DivideMode::Median, edge),
+        divide(DivideMode::Original, edge)
+    );
+}
+
+#[test]
+fn coarse_levels_use_search_coarse() {
+    for level in 1..4 {
+        assert_eq!(
+            level_search(level, SearchType::Hex2, SearchType::Exhaustive, 2, 1),
+            (SearchType::Exhaustive, 2)
+        );
+    }
+    assert_eq!(
+        level_search(0, SearchType::Hex2, SearchType::Exhaustive, 2, 1),
+        (SearchType::Hex2, 1)
+    );
+}
+
+#[test]
+fn directional_search_is_used_on_every_level() {
+    for search_type in [SearchType::Horizontal, SearchType::Vertical] {
+        assert_eq!(
+            level_search(2, search_type, SearchType::Exhaustive, 3, 1),
+            (search_type, 3)
+        );
+        assert_eq!(
+            level_search(0, search_type, SearchType::Exhaustive, 3, 1),
+            (search_type, 1)
+        );
+    }
+}
+
+#[test]
+fn level_blocks_halve_with_the_same_block_size() {
+    let blocks = |width, height, level, overlap_x, overlap_y| {
+        level_blocks(
+            width,
+            height,
+            level,
+            NonZeroUsize::new(16).unwrap(),
+            NonZeroUsize::new(8).unwrap(),
+            overlap_x,
+            overlap_y,
+        )
+        .map(|(x, y)| (x.get(), y.get()))
+    };
+
+    // 8x6 blocks of 16x8 cover 128x48
+    assert_eq!(blocks(128, 48, 0, 0, 0), Some((8, 6)));
+    assert_eq!(blocks(128, 48, 1, 0, 0), Some((4, 3)));
+    assert_eq!(blocks(128, 48, 2, 0, 0), Some((2, 1)));
+    assert_eq!(blocks(128, 48, 3, 0, 0), None);
+
+    // Overlapping blocks step by the block size minus the overlap
+    assert_eq!(blocks(120, 44, 0, 8, 4), Some((14, 10)));
+    assert_eq!(blocks(120, 44, 1, 8, 4), Some((6, 4)));
+
+    // Smaller than the overlap itself
+    assert_eq!(blocks(120, 44, 4, 8, 4), None);
+}
+
+#[test]
+fn non_square_blocks_shape_every_level() {
+    let group = GroupOfPlanes::<u8>::new(
+        NonZeroUsize::new(16).unwrap(),
+        NonZeroUsize::new(8).unwrap(),
+        3,
+        Subpel::Half,
+        MotionFlags::empty(),
+        0,
+        0,
+        NonZeroUsize::new(8).unwrap(),
+        NonZeroUsize::new(6).unwrap(),
+        NonZeroU8::new(2).unwrap(),
+        NonZeroU8::new(2).unwrap(),
+        DivideMode::None,
+        NonZeroU8::new(8).unwrap(),
+    )
+    .unwrap();
+
+    let shapes: Vec<_> = group
+        .planes
+        .iter()
+        .map(|plane| {
+            (
+                plane.blk_size_x.get(),
+                plane.blk_size_y.get(),
+                plane.blk_x.get(),
+                plane.blk_y.get(),
+            )
+        })
+        .collect();
+    assert_eq!(shapes, vec![(16, 8, 8, 6), (16, 8, 4, 3), (16, 8, 2, 1)]);
+
+    // Each level is stored coarsest first, as its size followed by one vector per block
+    let out = group.write_default_to_array();
+    let mut data = &out.block_data[2 * size_of::<i32>()..];
+    for blocks in [2, 12, 48] {
+        let size = u32::from_le_bytes(data[..4].try_into().unwrap()) as usize;
+        assert_eq!(size, size_of::<u32>() + blocks * MV_SIZE);
+        data = &data[size..];
+    }
+    assert!(data.is_empty());
+}
+
+#[test]
+fn too_many_levels_for_the_block_size() {
+    let result = GroupOfPlanes::<u8>::new(
+        NonZeroUsize::new(16).unwrap(),
+        NonZeroUsize::new(8).unwrap(),
+        4,
+        Subpel::Half,
+        MotionFlags::empty(),
+        0,
+        0,
+        NonZeroUsize::new(8).unwrap(),
+        NonZeroUsize::new(6).unwrap(),
+        NonZeroU8::new(2).unwrap(),
+        NonZeroU8::new(2).unwrap(),
+        DivideMode::None,
+        NonZeroU8::new(8).unwrap(),
+    );
+    let error = result.err().unwrap();
+    assert!(error.to_string().contains("level 3"), "{error}");
+}
+
+#[test]
+fn level_count_is_capped_where_blocks_stop_fitting() {
+    let size = NonZeroUsize::new(4).unwrap();
+    let count = |width, height, levels| level_count(width, height, size, size, 0, 0, levels);
+
+    // 4x4 blocks fit a 64x32 plane down to level 3, which is 8x4 pixels
+    assert_eq!(count(64, 32, None), 4);
+    assert_eq!(count(64, 32, Some(0)), 4);
+    assert_eq!(count(64, 32, Some(100)), 4);
+    assert_eq!(count(64, 32, Some(2)), 2);
+    assert!(level_blocks(64, 32, 3, size, size, 0, 0).is_some());
+    assert!(level_blocks(64, 32, 4, size, size, 0, 0).is_none());
+
+    // Larger blocks stop fitting at finer levels, rather than shrinking
+    let large = NonZeroUsize::new(16).unwrap();
+    assert_eq!(level_count(64, 32, large, large, 0, 0, Some(100)), 2);
+}
+
+#[test]
+fn many_levels_with_small_blocks_search_every_level() {
+    // The coarsest level of a 4x4 block search is a single block, which must still be searchable
+    let size = NonZeroUsize::new(4).unwrap();
+    let (width, height) = (64, 32);
+    let levels = level_count(width, height, size, size, 0, 0, Some(100));
+    for level in 0..levels {
+        let (blk_x, blk_y) = level_blocks(width, height, level, size, size, 0, 0).unwrap();
+        assert!(blk_x.get() * size.get() <= width >> level);
+        assert!(blk_y.get() * size.get() <= height >> level);
+    }
+    let (blk_x, blk_y) = level_blocks(width, height, levels - 1, size, size, 0, 0).unwrap();
+    assert_eq!((blk_x.get(), blk_y.get()), (2, 1));
+}
@@ -118,17 +118,30 @@
 #![allow(unsafe_op_in_unsafe_fn)]
 
 use anyhow::Error;
+use compensate::Compensate;
+use degrain::Degrain;
+use flow_blur::FlowBlur;
+use flow_fps::FlowFps;
+use global_motion::GlobalMotion;
 use mv_analyse::Analyse;
-use mv_super::Super;
+use super_level::SuperLevel;
 use vapoursynth::{
     api::API,
     core::CoreRef,
     export_vapoursynth_plugin,
     make_filter_function,
+    map::ValueIter,
     node::Node,
     plugins::{Filter, FilterArgument, Metadata},
 };
 
+// The block sizes accepted by `Analyse`
+pub use crate::mv_analyse::supported_block_sizes;
+// Block distortion metrics, usable outside of VapourSynth
+pub use crate::util::{Pixel, get_sad, get_satd};
+// Access to the planes of each level of a superframe, for tools built on top of `Super`
+pub use crate::{mv_plane::SuperPlaneLayout, mv_super::Super};
+
 #[cfg(test)]
 #[macro_use]
 mod tests;
@@ -136,14 +149,30 @@ mod tests;
 #[cfg(feature = "bench")]
 pub mod average;
 #[cfg(feature = "bench")]
+pub mod compensate;
+#[cfg(feature = "bench")]
+pub mod compensation;
+#[cfg(feature = "bench")]
 pub mod dct;
 #[cfg(feature = "bench")]
+pub mod degrain;
+#[cfg(feature = "bench")]
+pub mod error;
+#[cfg(feature = "bench")]
+pub mod flow_blur;
+#[cfg(feature = "bench")]
+pub mod flow_fps;
+#[cfg(feature = "bench")]
+pub mod global_motion;
+#[cfg(feature = "bench")]
 pub mod group_of_planes;
 #[cfg(feature = "bench")]
 pub mod mv;
 #[cfg(feature = "bench")]
 pub mod mv_analyse;
 #[cfg(feature = "bench")]
+pub mod mv_clip;
+#[cfg(feature = "bench")]
 pub mod mv_frame;
 #[cfg(feature = "bench")]
 pub mod mv_gof;
@@ -162,19 +191,39 @@ pub mod reduce;
 #[cfg(feature = "bench")]
 pub mod refine;
 #[cfg(feature = "bench")]
+pub mod super_level;
+#[cfg(feature = "bench")]
 pub mod util;
+#[cfg(feature = "bench")]
+pub mod windows;
 
 #[cfg(not(feature = "bench"))]
 mod average;
 #[cfg(not(feature = "bench"))]
+mod compensate;
+#[cfg(not(feature = "bench"))]
+mod compensation;
+#[cfg(not(feature = "bench"))]
 mod dct;
 #[cfg(not(feature = "bench"))]
+mod degrain;
+#[cfg(not(feature = "bench"))]
+mod error;
+#[cfg(not(feature = "bench"))]
+mod flow_blur;
+#[cfg(not(feature = "bench"))]
+mod flow_fps;
+#[cfg(not(feature = "bench"))]
+mod global_motion;
+#[cfg(not(feature = "bench"))]
 mod group_of_planes;
 #[cfg(not(feature = "bench"))]
 mod mv;
 #[cfg(not(feature = "bench"))]
 mod mv_analyse;
 #[cfg(not(feature = "bench"))]
+mod mv_clip;
+#[cfg(not(feature = "bench"))]
 mod mv_frame;
 #[cfg(not(feature = "bench"))]
 mod mv_gof;
@@ -194,7 +243,11 @@ mod reduce;
 mod refine;
 mod simd;
 #[cfg(not(feature = "bench"))]
+mod super_level;
+#[cfg(not(feature = "bench"))]
 mod util;
+#[cfg(not(feature = "bench"))]
+mod windows;
 
 pub const PLUGIN_IDENTIFIER: &str = "com.soichiro.zoomvtools";
 pub const PLUGIN_NAME: &str = "zoomvtools";
@@ -235,6 +288,19 @@ make_filter_function! {
         search_coarse: Option<i64>,
         dct: Option<i64>,
         clip: Option<Node<'core>>,
+        info: Option<i64>,
+        multi: Option<i64>,
+        limit: Option<i64>,
+        threads: Option<i64>,
+        trivialsad: Option<i64>,
+        export_sad: Option<i64>,
+        left: Option<i64>,
+        top: Option<i64>,
+        cropw: Option<i64>,
+        croph: Option<i64>,
+        global_weight: Option<i64>,
+        export_motion: Option<i64>,
+        fixed_dct: Option<i64>,
     ) -> Result<Option<Box<dyn Filter<'core> + 'core>>, Error> {
         // `opt` exists for compatibility purposes, but will not be used.
         // `clip` exists for compatibility purposes, but it was never used in the original plugin.
@@ -268,12 +334,212 @@ make_filter_function! {
             tff,
             search_coarse,
             dct,
+            info,
+            multi,
+            limit,
+            threads,
+            trivialsad,
+            export_sad,
+            left,
+            top,
+            cropw,
+            croph,
+            global_weight,
+            export_motion,
+            fixed_dct,
         )?;
 
         Ok(Some(Box::new(mvanalyse)))
     }
 }
 
+make_filter_function! {
+    CompensateFunction, "Compensate"
+    fn create_compensate<'core>(
+        _api: API,
+        _core: CoreRef<'core>,
+        clip: Node<'core>,
+        super_clip: Node<'core>,
+        vectors: Node<'core>,
+        scbehavior: Option<i64>,
+        thsad: Option<i64>,
+        thscd1: Option<i64>,
+        thscd2: Option<i64>,
+        time: Option<i64>,
+    ) -> Result<Option<Box<dyn Filter<'core> + 'core>>, Error> {
+        let compensate = Compensate::new(
+            clip,
+            super_clip,
+            vectors,
+            scbehavior,
+            thsad,
+            thscd1,
+            thscd2,
+            time,
+        )?;
+
+        Ok(Some(Box::new(compensate)))
+    }
+}
+
+make_filter_function! {
+    Degrain1Function, "Degrain1"
+    fn create_degrain1<'core>(
+        _api: API,
+        _core: CoreRef<'core>,
+        clip: Node<'core>,
+        super_clip: Node<'core>,
+        mvbw: Node<'core>,
+        mvfw: Node<'core>,
+        thsad: Option<i64>,
+        thsadc: Option<i64>,
+        plane: Option<i64>,
+        thscd1: Option<i64>,
+        thscd2: Option<i64>,
+    ) -> Result<Option<Box<dyn Filter<'core> + 'core>>, Error> {
+        let degrain = Degrain::new(
+            clip,
+            super_clip,
+            vec![[mvbw, mvfw]],
+            thsad,
+            thsadc,
+            None,
+            None,
+            plane,
+            thscd1,
+            thscd2,
+        )?;
+
+        Ok(Some(Box::new(degrain)))
+    }
+}
+
+make_filter_function! {
+    Degrain2Function, "Degrain2"
+    fn create_degrain2<'core>(
+        _api: API,
+        _core: CoreRef<'core>,
+        clip: Node<'core>,
+        super_clip: Node<'core>,
+        mvbw: Node<'core>,
+        mvfw: Node<'core>,
+        mvbw2: Node<'core>,
+        mvfw2: Node<'core>,
+        thsad: Option<i64>,
+        thsadc: Option<i64>,
+        thsad2: Option<i64>,
+        thsadc2: Option<i64>,
+        plane: Option<i64>,
+        thscd1: Option<i64>,
+        thscd2: Option<i64>,
+    ) -> Result<Option<Box<dyn Filter<'core> + 'core>>, Error> {
+        let degrain = Degrain::new(
+            clip,
+            super_clip,
+            vec![[mvbw, mvfw], [mvbw2, mvfw2]],
+            thsad,
+            thsadc,
+            thsad2,
+            thsadc2,
+            plane,
+            thscd1,
+            thscd2,
+        )?;
+
+        Ok(Some(Box::new(degrain)))
+    }
+}
+
+make_filter_function! {
+    Degrain3Function, "Degrain3"
+    fn create_degrain3<'core>(
+        _api: API,
+        _core: CoreRef<'core>,
+        clip: Node<'core>,
+        super_clip: Node<'core>,
+        mvbw: Node<'core>,
+        mvfw: Node<'core>,
+        mvbw2: Node<'core>,
+        mvfw2: Node<'core>,
+        mvbw3: Node<'core>,
+        mvfw3: Node<'core>,
+        thsad: Option<i64>,
+        thsadc: Option<i64>,
+        thsad2: Option<i64>,
+        thsadc2: Option<i64>,
+        plane: Option<i64>,
+        thscd1: Option<i64>,
+        thscd2: Option<i64>,
+    ) -> Result<Option<Box<dyn Filter<'core> + 'core>>, Error> {
+        let degrain = Degrain::new(
+            clip,
+            super_clip,
+            vec![[mvbw, mvfw], [mvbw2, mvfw2], [mvbw3, mvfw3]],
+            thsad,
+            thsadc,
+            thsad2,
+            thsadc2,
+            plane,
+            thscd1,
+            thscd2,
+        )?;
+
+        Ok(Some(Box::new(degrain)))
+    }
+}
+
+make_filter_function! {
+    FlowBlurFunction, "FlowBlur"
+    fn create_flow_blur<'core>(
+        _api: API,
+        _core: CoreRef<'core>,
+        clip: Node<'core>,
+        super_clip: Node<'core>,
+        mvbw: Node<'core>,
+        mvfw: Node<'core>,
+        blur: Option<i64>,
+        prec: Option<i64>,
+    ) -> Result<Option<Box<dyn Filter<'core> + 'core>>, Error> {
+        let flow_blur = FlowBlur::new(clip, super_clip, mvbw, mvfw, blur, prec)?;
+
+        Ok(Some(Box::new(flow_blur)))
+    }
+}
+
+make_filter_function! {
+    FlowFpsFunction, "FlowFps"
+    fn create_flow_fps<'core>(
+        _api: API,
+        _core: CoreRef<'core>,
+        clip: Node<'core>,
+        super_clip: Node<'core>,
+        mvbw: Node<'core>,
+        mvfw: Node<'core>,
+        num: Option<i64>,
+        den: Option<i64>,
+        thsad: Option<i64>,
+        blend: Option<i64>,
+    ) -> Result<Option<Box<dyn Filter<'core> + 'core>>, Error> {
+        let flow_fps = FlowFps::new(clip, super_clip, mvbw, mvfw, num, den, thsad, blend)?;
+
+        Ok(Some(Box::new(flow_fps)))
+    }
+}
+
+make_filter_function! {
+    GlobalMotionFunction, "GlobalMotion"
+    fn create_global_motion<'core>(
+        _api: API,
+        _core: CoreRef<'core>,
+        clip: Node<'core>,
+        vectors: Node<'core>,
+    ) -> Result<Option<Box<dyn Filter<'core> + 'core>>, Error> {
+        let global_motion = GlobalMotion::new(clip, vectors)?;
+
+        Ok(Some(Box::new(global_motion)))
+    }
+}
+
 make_filter_function! {
     SuperFunction, "Super"
     #[allow(unused_variables)]
@@ -290,14 +556,45 @@ make_filter_function! {
         rfilter: Option<i64>,
         pelclip: Option<Node<'core>>,
         opt: Option<i64>,
+        padmode: Option<i64>,
+        maxmem: Option<i64>,
+        sharp_coeffs: Option<ValueIter<'_, 'core, i64>>,
     ) -> Result<Option<Box<dyn Filter<'core> + 'core>>, Error> {
         // `opt` exists for compatibility purposes, but will not be used.
-        let mvsuper = Super::new(clip, hpad, vpad, pel, levels, chroma, sharp, rfilter, pelclip)?;
+        let sharp_coeffs = sharp_coeffs.map(Iterator::collect::<Vec<_>>);
+        let mvsuper = Super::new(
+            clip,
+            hpad,
+            vpad,
+            pel,
+            levels,
+            chroma,
+            sharp,
+            rfilter,
+            pelclip,
+            padmode,
+            maxmem,
+            sharp_coeffs.as_deref(),
+        )?;
 
         Ok(Some(Box::new(mvsuper)))
     }
 }
 
+make_filter_function! {
+    SuperLevelFunction, "SuperLevel"
+    fn create_super_level<'core>(
+        _api: API,
+        core: CoreRef<'core>,
+        super_clip: Node<'core>,
+        level: Option<i64>,
+    ) -> Result<Option<Box<dyn Filter<'core> + 'core>>, Error> {
+        let super_level = SuperLevel::new(core, super_clip, level)?;
+
+        Ok(Some(Box::new(super_level)))
+    }
+}
+
 export_vapoursynth_plugin! {
     Metadata {
         identifier: PLUGIN_IDENTIFIER,
@@ -307,6 +604,14 @@ export_vapoursynth_plugin! {
     },
     [
         AnalyseFunction::new(),
-        SuperFunction::new()
+        CompensateFunction::new(),
+        Degrain1Function::new(),
+        Degrain2Function::new(),
+        Degrain3Function::new(),
+        FlowBlurFunction::new(),
+        FlowFpsFunction::new(),
+        GlobalMotionFunction::new(),
+        SuperFunction::new(),
+        SuperLevelFunction::new()
     ]
 }
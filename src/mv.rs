@@ -1,11 +1,18 @@
+#[cfg(test)]
+mod tests;
+
 use std::mem::size_of;
 
 use bitflags::bitflags;
 
-pub const MV_SIZE: usize = size_of::<MotionVector>();
+use crate::params::Subpel;
+
+/// The size of a [`MotionVector`] in the `block_data` of the vectors clip,
+/// as written by [`MotionVector::write_to`].
+pub const MV_SIZE: usize = 2 * size_of::<i32>() + size_of::<i64>();
 
 #[repr(C)]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct MotionVector {
     pub x: i32,
     pub y: i32,
@@ -21,16 +28,42 @@ impl MotionVector {
 }
 
 impl MotionVector {
+    /// Packs the vector as `x`, `y` and `sad` in little endian,
+    /// the same layout as the `repr(C)` struct in mvtools.
     #[must_use]
-    pub(crate) fn bytes(&self) -> &[u8] {
-        // SAFETY: We've added `repr(c)` to ensure a predictable size of the struct
-        unsafe {
-            std::slice::from_raw_parts(
-                self as *const Self as *const u8,
-                std::mem::size_of::<Self>(),
-            )
+    pub(crate) fn to_bytes(self) -> [u8; MV_SIZE] {
+        let mut bytes = [0; MV_SIZE];
+        bytes[..4].copy_from_slice(&self.x.to_le_bytes());
+        bytes[4..8].copy_from_slice(&self.y.to_le_bytes());
+        bytes[8..].copy_from_slice(&self.sad.to_le_bytes());
+        bytes
+    }
+
+    /// Reads a vector from the first [`MV_SIZE`] bytes of `data`.
+    ///
+    /// # Panics
+    /// If `data` is shorter than [`MV_SIZE`].
+    #[must_use]
+    pub(crate) fn read_from(data: &[u8]) -> Self {
+        let field = |range: std::ops::Range<usize>| &data[..MV_SIZE][range];
+        MotionVector {
+            x: i32::from_le_bytes(field(0..4).try_into().expect("slice with incorrect length")),
+            y: i32::from_le_bytes(field(4..8).try_into().expect("slice with incorrect length")),
+            sad: i64::from_le_bytes(
+                field(8..16)
+                    .try_into()
+                    .expect("slice with incorrect length"),
+            ),
         }
     }
+
+    /// Writes the vector to the first [`MV_SIZE`] bytes of `data`.
+    ///
+    /// # Panics
+    /// If `data` is shorter than [`MV_SIZE`].
+    pub(crate) fn write_to(self, data: &mut [u8]) {
+        data[..MV_SIZE].copy_from_slice(&self.to_bytes());
+    }
 }
 
 bitflags! {
@@ -47,6 +80,14 @@ bitflags! {
 }
 
 impl MotionVector {
+    /// Converts the vector from units of the subpixel precision `pel` to full pixels,
+    /// as `(x, y)`, for consumers that expect fractional motion.
+    #[must_use]
+    pub fn to_pixels(self, pel: Subpel) -> (f64, f64) {
+        let pel = f64::from(u8::from(pel));
+        (f64::from(self.x) / pel, f64::from(self.y) / pel)
+    }
+
     #[must_use]
     pub fn zero() -> Self {
         MotionVector {
@@ -62,3 +103,51 @@ impl Default for MotionVector {
         Self::zero()
     }
 }
+
+/// The range of vectors that keep a block within the padded reference frame.
+///
+/// The minimums are inclusive and the maximums are exclusive, matching mvtools.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MvBounds {
+    pub dx_min: i32,
+    pub dy_min: i32,
+    pub dx_max: i32,
+    pub dy_max: i32,
+}
+
+impl MvBounds {
+    /// Whether the vector `(vx, vy)` lies within the bounds
+    #[must_use]
+    pub fn contains(&self, vx: i32, vy: i32) -> bool {
+        (vx >= self.dx_min) && (vy >= self.dy_min) && (vx < self.dx_max) && (vy < self.dy_max)
+    }
+}
+
+/// Shortens a vector to a length of at most `limit`, keeping its direction and SAD.
+///
+/// A `limit` of 0 leaves the vector unchanged.
+#[must_use]
+pub fn limit_mv(v: MotionVector, limit: u32) -> MotionVector {
+    let square_length = i64::from(v.x).pow(2) + i64::from(v.y).pow(2);
+    if limit == 0 || square_length <= i64::from(limit).pow(2) {
+        return v;
+    }
+
+    // Truncating towards zero keeps the shortened vector within the limit
+    let scale = f64::from(limit) / (square_length as f64).sqrt();
+    MotionVector {
+        x: (f64::from(v.x) * scale) as i32,
+        y: (f64::from(v.y) * scale) as i32,
+        sad: v.sad,
+    }
+}
+
+/// Clamps the components of a vector to `bounds`, keeping its SAD.
+#[must_use]
+pub fn clamp_mv(v: MotionVector, bounds: &MvBounds) -> MotionVector {
+    MotionVector {
+        x: v.x.max(bounds.dx_min).min(bounds.dx_max - 1),
+        y: v.y.max(bounds.dy_min).min(bounds.dy_max - 1),
+        sad: v.sad,
+    }
+}
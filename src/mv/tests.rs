@@ -0,0 +1,139 @@
+#![allow(clippy::unwrap_used, reason = "allow in test files")]
+#![allow(clippy::undocumented_unsafe_blocks, reason = "allow in test files")]
+
+use super::*;
+use crate::params::Subpel;
+
+const BOUNDS: MvBounds = MvBounds {
+    dx_min: -16,
+    dy_min: -8,
+    dx_max: 32,
+    dy_max: 24,
+};
+
+fn mv(x: i32, y: i32) -> MotionVector {
+    MotionVector { x, y, sad: 1234 }
+}
+
+#[test]
+fn clamp_mv_inside_bounds_is_unchanged() {
+    let clamped = clamp_mv(mv(3, -5), &BOUNDS);
+    assert_eq!((clamped.x, clamped.y, clamped.sad), (3, -5, 1234));
+}
+
+#[test]
+fn clamp_mv_maximum_is_exclusive() {
+    // A vector exactly on the maximum would place the block one pixel past the padding
+    let clamped = clamp_mv(mv(BOUNDS.dx_max, BOUNDS.dy_max), &BOUNDS);
+    assert_eq!(
+        (clamped.x, clamped.y),
+        (BOUNDS.dx_max - 1, BOUNDS.dy_max - 1)
+    );
+
+    let clamped = clamp_mv(mv(1000, 1000), &BOUNDS);
+    assert_eq!(
+        (clamped.x, clamped.y),
+        (BOUNDS.dx_max - 1, BOUNDS.dy_max - 1)
+    );
+}
+
+#[test]
+fn clamp_mv_minimum_is_inclusive() {
+    let clamped = clamp_mv(mv(BOUNDS.dx_min, BOUNDS.dy_min), &BOUNDS);
+    assert_eq!((clamped.x, clamped.y), (BOUNDS.dx_min, BOUNDS.dy_min));
+
+    let clamped = clamp_mv(mv(-1000, -1000), &BOUNDS);
+    assert_eq!((clamped.x, clamped.y), (BOUNDS.dx_min, BOUNDS.dy_min));
+}
+
+#[test]
+fn clamp_mv_components_are_independent() {
+    let clamped = clamp_mv(mv(-100, 10), &BOUNDS);
+    assert_eq!((clamped.x, clamped.y), (BOUNDS.dx_min, 10));
+
+    let clamped = clamp_mv(mv(10, 100), &BOUNDS);
+    assert_eq!((clamped.x, clamped.y), (10, BOUNDS.dy_max - 1));
+}
+
+#[test]
+fn bounds_contains_matches_clamp() {
+    for x in -20..36 {
+        for y in -12..28 {
+            let clamped = clamp_mv(mv(x, y), &BOUNDS);
+            assert_eq!(
+                BOUNDS.contains(x, y),
+                clamped.x == x && clamped.y == y,
+                "({x}, {y})"
+            );
+            assert!(BOUNDS.contains(clamped.x, clamped.y));
+        }
+    }
+}
+
+#[test]
+fn limit_mv_shortens_long_vectors() {
+    let limited = limit_mv(mv(30, 40), 10);
+    assert_eq!((limited.x, limited.y, limited.sad), (6, 8, 1234));
+
+    // The direction is kept, and the result never exceeds the limit
+    let limited = limit_mv(mv(-100, 7), 16);
+    assert_eq!((limited.x, limited.y), (-15, 1));
+    assert!(limited.x.pow(2) + limited.y.pow(2) <= 16 * 16);
+}
+
+#[test]
+fn limit_mv_keeps_short_vectors() {
+    let limited = limit_mv(mv(6, -8), 10);
+    assert_eq!((limited.x, limited.y), (6, -8));
+    let limited = limit_mv(mv(3, 1), 10);
+    assert_eq!((limited.x, limited.y), (3, 1));
+}
+
+#[test]
+fn limit_mv_zero_disables_limit() {
+    let limited = limit_mv(mv(1000, -1000), 0);
+    assert_eq!((limited.x, limited.y), (1000, -1000));
+}
+
+#[test]
+fn motion_vector_layout_matches_mv_size() {
+    // The vectors clip is read by other plugins as an array of C structs without padding
+    assert_eq!(MV_SIZE, 16);
+    assert_eq!(size_of::<MotionVector>(), MV_SIZE);
+    assert_eq!(std::mem::offset_of!(MotionVector, x), 0);
+    assert_eq!(std::mem::offset_of!(MotionVector, y), 4);
+    assert_eq!(std::mem::offset_of!(MotionVector, sad), 8);
+}
+
+#[test]
+fn motion_vector_bytes_round_trip() {
+    let vectors = [MotionVector::zero(), mv(i32::MIN, i32::MAX), MotionVector {
+        x: -3,
+        y: 17,
+        sad: i64::MAX,
+    }];
+    // Vectors may start at any offset in the block data
+    let mut data = vec![0xaau8; 1 + vectors.len() * MV_SIZE];
+    for (v, bytes) in vectors.iter().zip(data[1..].chunks_exact_mut(MV_SIZE)) {
+        v.write_to(bytes);
+    }
+    assert_eq!(data[0], 0xaa);
+    for (v, bytes) in vectors.iter().zip(data[1..].chunks_exact(MV_SIZE)) {
+        let read = MotionVector::read_from(bytes);
+        assert_eq!((read.x, read.y, read.sad), (v.x, v.y, v.sad));
+        assert_eq!(bytes, v.to_bytes());
+    }
+
+    // Little endian fields in declaration order
+    assert_eq!(mv(1, -2).to_bytes(), [
+        1, 0, 0, 0, 0xfe, 0xff, 0xff, 0xff, 0xd2, 0x04, 0, 0, 0, 0, 0, 0
+    ]);
+}
+
+#[test]
+fn to_pixels_divides_by_pel() {
+    assert_eq!(mv(6, -2).to_pixels(Subpel::Quarter), (1.5, -0.5));
+    assert_eq!(mv(3, 1).to_pixels(Subpel::Half), (1.5, 0.5));
+    assert_eq!(mv(6, -2).to_pixels(Subpel::Full), (6.0, -2.0));
+    assert_eq!(mv(-1, 0).to_pixels(Subpel::Quarter), (-0.25, 0.0));
+}
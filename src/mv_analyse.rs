@@ -1,12 +1,10 @@
 #[cfg(test)]
 mod tests;
 
-use std::{
-    cmp::min,
-    num::{NonZeroU8, NonZeroUsize},
-};
+use std::num::{NonZeroU8, NonZeroUsize};
 
 use anyhow::{Result, anyhow, bail};
+use rayon::{ThreadPool, ThreadPoolBuilder};
 use vapoursynth::{
     format::{ColorFamily, Format, SampleType},
     frame::{FrameRef, FrameRefMut},
@@ -15,14 +13,220 @@ use vapoursynth::{
 };
 
 use crate::{
-    group_of_planes::GroupOfPlanes,
-    mv_gof::MVGroupOfFrames,
-    params::{DctMode, DivideMode, MVPlaneSet, MotionFlags, PenaltyScaling, SearchType, Subpel},
-    util::Pixel,
+    dct::{DctKind, DctPool},
+    error::{ZooMvError, parse_param},
+    group_of_planes::{GroupOfPlanes, level_blocks, level_count},
+    mv::MvBounds,
+    mv_clip::{finest_motion, finest_sads},
+    mv_gof::{GofPool, MVGroupOfFrames},
+    params::{
+        DctMode,
+        DivideMode,
+        MVPlaneSet,
+        MotionFlags,
+        MotionPreset,
+        PenaltyScaling,
+        SearchType,
+        Subpel,
+    },
+    plane_of_blocks::SearchStats,
+    util::{Pixel, check_clip_frame, plane_pitches},
 };
 
-const PROP_MVANALYSISDATA: &str = "MVTools_MVAnalysisData";
-const PROP_VECTORS: &str = "MVTools_vectors";
+pub(crate) const PROP_MVANALYSISDATA: &str = "MVTools_MVAnalysisData";
+pub(crate) const PROP_VECTORS: &str = "MVTools_vectors";
+/// The maximum delta of a multi-vector clip, see [`multi_position`]
+pub(crate) const PROP_MULTI_DELTA: &str = "MVTools_multi_delta";
+/// The `divide` mode, which adds the subdivided blocks as an extra level of the vectors
+pub(crate) const PROP_DIVIDE: &str = "Analyse_divide";
+/// 1 if the vectors can be used, or 0 if they must not be, e.g. because the reference frame
+/// is outside of the clip. This is the validity stored in the vectors, so clients can check it
+/// without parsing them.
+pub(crate) const PROP_VALID: &str = "Analyse_valid";
+/// The number of hierarchical levels searched, which may be fewer than `levels` requested
+const PROP_LEVELS: &str = "Analyse_levels";
+/// The luma block size, as `[blksize, blksizev]`
+const PROP_BLKSIZE: &str = "Analyse_blksize";
+/// The luma block overlap, as `[overlap, overlapv]`
+const PROP_OVERLAP: &str = "Analyse_overlap";
+const PROP_INFO_CLAMPED: &str = "Analyse_clamped_count";
+const PROP_INFO_BAD: &str = "Analyse_bad_count";
+const PROP_INFO_AVERAGE_SAD: &str = "Analyse_average_sad";
+/// The search range of every block of the finest level, in row-major block order,
+/// as `[dx_min, dy_min, dx_max, dy_max]` in units of the subpixel precision
+const PROP_INFO_SEARCH_BOUNDS: &str = "Analyse_search_bounds";
+/// The SAD of every block of the finest level, in row-major block order
+const PROP_BLOCK_SADS: &str = "Analyse_block_sads";
+/// The vector of every block of the finest level in full pixels, in row-major block order,
+/// as `[x0, y0, x1, y1, ...]`
+const PROP_BLOCK_MOTION: &str = "Analyse_block_motion";
+
+/// The supported luma block sizes, as `(blksize, blksizev)`.
+///
+/// Every size divided by any supported chroma subsampling ratio
+/// must also be supported by `get_sad`, which is used for the chroma planes.
+pub(crate) const BLOCK_SIZES: [(usize, usize); 12] = [
+    (4, 4),
+    (8, 4),
+    (8, 8),
+    (16, 2),
+    (16, 8),
+    (16, 16),
+    (32, 16),
+    (32, 32),
+    (64, 32),
+    (64, 64),
+    (128, 64),
+    (128, 128),
+];
+
+/// The block sizes supported by `Analyse`, as `(blksize, blksizev)`.
+#[must_use]
+pub fn supported_block_sizes() -> &'static [(usize, usize)] {
+    &BLOCK_SIZES
+}
+
+/// Checks that `blksize`×`blksizev` is one of the [`supported_block_sizes`].
+///
+/// An unsupported `blksize` is reported first, then a `blksizev` which does not go with it.
+fn validate_block_size(blk_size_x: usize, blk_size_y: usize) -> Result<(), ZooMvError> {
+    if supported_block_sizes().contains(&(blk_size_x, blk_size_y)) {
+        return Ok(());
+    }
+    let (name, value) = if supported_block_heights(blk_size_x).is_some() {
+        ("blksizev", blk_size_y)
+    } else {
+        ("blksize", blk_size_x)
+    };
+    Err(ZooMvError::InvalidParam {
+        filter: "Analyse",
+        name,
+        value: value as i64,
+        expected: supported_block_heights(blk_size_x).unwrap_or("4, 8, 16, 32, 64 or 128"),
+    })
+}
+
+/// The `blksizev` which go with `blksize`, as listed in the error for any other `blksizev`,
+/// or `None` if `blksize` is not supported.
+fn supported_block_heights(blk_size_x: usize) -> Option<&'static str> {
+    Some(match blk_size_x {
+        4 => "4 when blksize=4",
+        8 => "4 or 8 when blksize=8",
+        16 => "2, 8 or 16 when blksize=16",
+        32 => "16 or 32 when blksize=32",
+        64 => "32 or 64 when blksize=64",
+        128 => "64 or 128 when blksize=128",
+        _ => return None,
+    })
+}
+
+/// Finds the size of the region searched by `Analyse`, which starts `left` pixels from the left
+/// and `top` pixels from the top of the clip, and is `cropw` by `croph` pixels.
+///
+/// A missing or zero `cropw` or `croph` extends the region to the right or bottom edge.
+/// The corner must fall on a chroma sample, so the chroma blocks line up with the luma blocks.
+fn analysis_region(
+    width: NonZeroUsize,
+    height: NonZeroUsize,
+    x_ratio_uv: NonZeroU8,
+    y_ratio_uv: NonZeroU8,
+    left: Option<i64>,
+    top: Option<i64>,
+    cropw: Option<i64>,
+    croph: Option<i64>,
+) -> Result<(NonZeroUsize, NonZeroUsize)> {
+    let axis = |size: NonZeroUsize,
+                ratio: NonZeroU8,
+                start: Option<i64>,
+                length: Option<i64>,
+                names: [&'static str; 2]|
+     -> Result<NonZeroUsize> {
+        let invalid = |name, value, expected| ZooMvError::InvalidParam {
+            filter: "Analyse",
+            name,
+            value,
+            expected,
+        };
+        let start_value = start.unwrap_or(0);
+        let start = usize::try_from(start_value)
+            .ok()
+            .filter(|&start| start < size.get() && start % ratio.get() as usize == 0)
+            .ok_or_else(|| {
+                invalid(
+                    names[0],
+                    start_value,
+                    "a multiple of the chroma subsampling inside the clip",
+                )
+            })?;
+        let length_value = length.unwrap_or(0);
+        let length = usize::try_from(length_value)
+            .ok()
+            .filter(|&length| start + length <= size.get())
+            .ok_or_else(|| {
+                invalid(
+                    names[1],
+                    length_value,
+                    "0 or a size that keeps the region inside the clip",
+                )
+            })?;
+        Ok(NonZeroUsize::new(length).unwrap_or_else(|| {
+            NonZeroUsize::new(size.get() - start).expect("start is inside the clip")
+        }))
+    };
+    Ok((
+        axis(width, x_ratio_uv, left, cropw, ["left", "cropw"])?,
+        axis(height, y_ratio_uv, top, croph, ["top", "croph"])?,
+    ))
+}
+
+/// Checks that `overlap`×`overlapv` leaves a step between blocks of `blksize`×`blksizev`
+/// and keeps every block on a chroma sample boundary.
+///
+/// Blocks step by `blksize - overlap`, so an overlap past half of the block size would
+/// cover pixels with three blocks. The chroma blocks step by the luma step shifted by the
+/// subsampling, which must not truncate, or they would drift away from the luma blocks.
+/// `divide` splits every block in half, so its halves need even overlaps on the chroma planes too.
+fn validate_overlap(
+    blk_size_x: usize,
+    blk_size_y: usize,
+    overlap_x: usize,
+    overlap_y: usize,
+    sub_sampling_w: u8,
+    sub_sampling_h: u8,
+    divide: bool,
+) -> Result<()> {
+    if overlap_x > blk_size_x / 2 || overlap_y > blk_size_y / 2 {
+        bail!(
+            "Analyse: overlap must be at most half of blksize, and overlapv must be at most half \
+             of blksizev"
+        );
+    }
+    let (step_x, step_y) = (blk_size_x - overlap_x, blk_size_y - overlap_y);
+    if !step_x.is_multiple_of(1 << sub_sampling_w) || !step_y.is_multiple_of(1 << sub_sampling_h) {
+        bail!(
+            "Analyse: blksize - overlap ({step_x}) must be a multiple of {}, and blksizev - \
+             overlapv ({step_y}) a multiple of {}, so that the chroma blocks of the super clip \
+             line up with the luma blocks.",
+            1 << sub_sampling_w,
+            1 << sub_sampling_h
+        );
+    }
+    if !overlap_x.is_multiple_of(1 << sub_sampling_w)
+        || !overlap_y.is_multiple_of(1 << sub_sampling_h)
+    {
+        bail!("Analyse: the requested overlap is incompatible with the super clip's subsampling.");
+    }
+    if divide
+        && (!overlap_x.is_multiple_of(2 << sub_sampling_w)
+            || !overlap_y.is_multiple_of(2 << sub_sampling_h))
+    {
+        bail!(
+            "Analyse: overlap and overlapv must be multiples of 2 or 4 when divide=True, \
+             depending on the super clip's subsampling."
+        );
+    }
+    Ok(())
+}
 
 #[derive(Debug)]
 #[allow(dead_code)]
@@ -38,8 +242,11 @@ pub struct Analyse<'core> {
     /// search type chosen for refinement in the EPZ
     search_type: SearchType,
     search_type_coarse: SearchType,
+    /// search radius, step count or step length at coarse levels, depending on the search type.
+    ///
+    /// At least 1 except for `Nstep`, and at most the largest frame dimension.
     search_param: i32,
-    /// search radius at finest level
+    /// search radius at finest level, limited like `search_param`
     pel_search: i32,
     /// set to true, it allows to take chroma into account when doing the motion estimation
     chroma: bool,
@@ -52,16 +259,20 @@ pub struct Analyse<'core> {
     lambda: u32,
     /// SAD limit for lambda usage
     lambda_sad: u32,
-    /// Penalty factors level scaling
+    /// Scaling of `lambda` with the hierarchical level
     penalty_level: PenaltyScaling,
     /// Use global motion predictor
     global: bool,
-    /// Penalty for New MV cost
+    /// Penalty for New MV cost, relative to the SAD and scaled to 256
     penalty_new: u16,
-    /// Penalty for Zero MV cost
+    /// Penalty for Zero MV cost, relative to the SAD and scaled to 256
     penalty_zero: u16,
-    /// Penalty for Global MV cost
+    /// Penalty for Global MV cost, relative to the SAD and scaled to 256
     penalty_global: u16,
+    /// How far each block's predictor is moved toward the global motion, scaled to 256,
+    /// so that ambiguous blocks follow a pan. Default is 0, which keeps the predictor local.
+    /// Only used with `global`.
+    global_weight: u16,
     /// Usage of block DCT (frequency spectrum) for block difference (SAD) calculation.
     /// In particular it can improve motion vector estimation around luma flicker and fades.
     dct_mode: DctMode,
@@ -71,16 +282,53 @@ pub struct Analyse<'core> {
     /// Value is scaled to block size 8x8.
     /// Default is 10000 (disabling value), recommended is about 1000-2000.
     bad_sad: u64,
+    /// SAD threshold below which the zero vector is kept without searching any further,
+    /// which speeds up mostly static content.
+    /// Value is scaled to block size 8x8. Default is 0 (disabled).
+    trivial_sad: u64,
     /// the range (radius) of wide search for bad blocks.
     /// Default is 24 (image pixel units).
     /// Use positive value for UMH search and negative for Exhaustive search.
+    /// Zero disables the wide search, as well as the small radius refinement after it,
+    /// so bad vectors are kept as they were found.
     bad_range: i32,
     /// Alternate blocks scan in rows from left to right and from right to left. Default is True
     meander: bool,
     /// try to start searches around many predictors. Default is false.
     try_many: bool,
+    /// The input consists of separated fields, e.g. from `std.SeparateFields`,
+    /// so vectors between fields of opposite parity are corrected by half a pixel.
     fields: bool,
+    /// Whether the first field is the top field. Overrides the `_Field` and `_FieldBased`
+    /// frame properties, which are used to find the field order when it is not given.
     tff: Option<bool>,
+    /// Write search statistics to frame properties, to help with tuning the search.
+    /// Default is false.
+    info: bool,
+    /// Write the SAD of every block to a frame property, to help with picking `thsad`.
+    /// The vectors are unchanged. Default is false.
+    export_sad: bool,
+    /// Write the vector of every block in full pixels to a frame property, as floats,
+    /// for tools that expect optical flow. The vectors are unchanged. Default is false.
+    export_motion: bool,
+    /// Output the backward and forward vectors of every delta from 1 to `delta`,
+    /// interleaved in a single clip. `isb` is ignored. Default is false.
+    multi: bool,
+    /// Output the backward and forward vectors of `delta`, interleaved in a single clip,
+    /// when `isb=2`. See [`both_position`].
+    both: bool,
+    /// Maximum length of the output vectors, in pixels. Longer vectors are shortened
+    /// to this length, keeping their direction. Default is 0, which disables the limit.
+    limit: u32,
+    /// Thread pool used to search each level of a frame in parallel, if `threads` is above 1.
+    /// Requires `meander=False` and `badrange=0`, which make the blocks in a diagonal wave
+    /// independent, so the vectors are the same as with a single thread.
+    pool: Option<ThreadPool>,
+    /// DCT helpers reused across frames, for the DCT modes.
+    /// `fixed_dct` selects the fixed-point DCT, whose vectors do not depend on the FFTW build.
+    dct_pool: DctPool,
+    /// Superframe layouts reused across frames, for the source and reference frames.
+    gof_pool: GofPool,
 
     // Internal fields
     analysis_data: MVAnalysisData,
@@ -92,10 +340,16 @@ pub struct Analyse<'core> {
     super_pel: Subpel,
     super_mode_yuv: MVPlaneSet,
     super_levels: usize,
+    /// Luma pixel where the analysed region starts, as `(left, top)`.
+    /// The vectors only cover the region, whose size is in `analysis_data`.
+    region_origin: (usize, usize),
+    /// Size of the whole source clip, which the search may reach into outside of the region
+    clip_width: NonZeroUsize,
+    clip_height: NonZeroUsize,
 }
 
 #[derive(Debug, Clone, Copy)]
-struct MVAnalysisData {
+pub(crate) struct MVAnalysisData {
     /// horizontal block size in pixels
     pub blk_size_x: NonZeroUsize,
     /// vertical block size in pixels
@@ -135,6 +389,9 @@ struct MVAnalysisData {
 }
 
 impl MVAnalysisData {
+    /// Size in bytes of the analysis data returned by [`Self::bytes`].
+    pub(crate) const SIZE: usize = size_of::<MVAnalysisPropData>();
+
     #[must_use]
     pub(crate) fn bytes(&self) -> Vec<u8> {
         let prop_data = MVAnalysisPropData::from(*self);
@@ -148,6 +405,79 @@ impl MVAnalysisData {
             .to_vec()
         }
     }
+
+    /// The analysis data of the subblocks written by `divide`,
+    /// which are stored as an extra level after the finest searched level.
+    #[must_use]
+    pub(crate) fn divided(&self) -> Self {
+        let mut div_data = *self;
+        // SAFETY: constant is non-zero
+        div_data.blk_x = div_data
+            .blk_x
+            .saturating_mul(unsafe { NonZeroUsize::new_unchecked(2) });
+        // SAFETY: constant is non-zero
+        div_data.blk_y = div_data
+            .blk_y
+            .saturating_mul(unsafe { NonZeroUsize::new_unchecked(2) });
+        div_data.blk_size_x =
+            NonZeroUsize::new(div_data.blk_size_x.get() / 2).expect("block size cannot not be 1");
+        div_data.blk_size_y =
+            NonZeroUsize::new(div_data.blk_size_y.get() / 2).expect("block size cannot not be 1");
+        div_data.overlap_x /= 2;
+        div_data.overlap_y /= 2;
+        div_data.level_count += 1;
+        div_data
+    }
+
+    /// The analysis data of the searched blocks, from the analysis data of `divide`d vectors.
+    ///
+    /// This is the inverse of [`Self::divided`].
+    pub(crate) fn undivided(&self) -> Result<Self> {
+        if !self.blk_x.get().is_multiple_of(2)
+            || !self.blk_y.get().is_multiple_of(2)
+            || self.level_count < 2
+        {
+            bail!(
+                "divided vectors must have an even number of blocks and at least 2 levels, got \
+                 {}x{} blocks and {} levels",
+                self.blk_x,
+                self.blk_y,
+                self.level_count
+            );
+        }
+        let mut data = *self;
+        // SAFETY: the block counts are even and non-zero, so at least 2
+        unsafe {
+            data.blk_x = NonZeroUsize::new_unchecked(self.blk_x.get() / 2);
+            data.blk_y = NonZeroUsize::new_unchecked(self.blk_y.get() / 2);
+        }
+        // SAFETY: constant is non-zero
+        data.blk_size_x = data
+            .blk_size_x
+            .saturating_mul(unsafe { NonZeroUsize::new_unchecked(2) });
+        // SAFETY: constant is non-zero
+        data.blk_size_y = data
+            .blk_size_y
+            .saturating_mul(unsafe { NonZeroUsize::new_unchecked(2) });
+        data.overlap_x *= 2;
+        data.overlap_y *= 2;
+        data.level_count -= 1;
+        Ok(data)
+    }
+
+    /// Parses the analysis data stored in the `MVTools_MVAnalysisData` frame property.
+    pub(crate) fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() != Self::SIZE {
+            bail!(
+                "invalid MVAnalysisData size, expected {} bytes, got {}",
+                Self::SIZE,
+                bytes.len()
+            );
+        }
+        // SAFETY: The length is checked above and every bit pattern is a valid `i32`
+        let prop_data = unsafe { (bytes.as_ptr() as *const MVAnalysisPropData).read_unaligned() };
+        Self::try_from(prop_data)
+    }
 }
 
 #[repr(C)]
@@ -174,6 +504,49 @@ struct MVAnalysisPropData {
     pub v_padding: i32,
 }
 
+impl TryFrom<MVAnalysisPropData> for MVAnalysisData {
+    type Error = anyhow::Error;
+
+    fn try_from(value: MVAnalysisPropData) -> Result<Self> {
+        let nonzero_usize = |val: i32, name: &str| {
+            usize::try_from(val)
+                .ok()
+                .and_then(NonZeroUsize::new)
+                .ok_or_else(|| anyhow!("invalid MVAnalysisData {name}: {val}"))
+        };
+        let usize = |val: i32, name: &str| {
+            usize::try_from(val).map_err(|_| anyhow!("invalid MVAnalysisData {name}: {val}"))
+        };
+        let nonzero_u8 = |val: i32, name: &str| {
+            u8::try_from(val)
+                .ok()
+                .and_then(NonZeroU8::new)
+                .ok_or_else(|| anyhow!("invalid MVAnalysisData {name}: {val}"))
+        };
+
+        Ok(MVAnalysisData {
+            blk_size_x: nonzero_usize(value.blk_size_x, "blk_size_x")?,
+            blk_size_y: nonzero_usize(value.blk_size_y, "blk_size_y")?,
+            pel: Subpel::try_from(i64::from(value.pel))?,
+            level_count: usize(value.level_count, "level_count")?,
+            delta_frame: value.delta_frame as isize,
+            is_backward: value.is_backward != 0,
+            motion_flags: MotionFlags::from_bits_truncate(value.motion_flags as u8),
+            width: nonzero_usize(value.width, "width")?,
+            height: nonzero_usize(value.height, "height")?,
+            overlap_x: usize(value.overlap_x, "overlap_x")?,
+            overlap_y: usize(value.overlap_y, "overlap_y")?,
+            blk_x: nonzero_usize(value.blk_x, "blk_x")?,
+            blk_y: nonzero_usize(value.blk_y, "blk_y")?,
+            bits_per_sample: nonzero_u8(value.bits_per_sample, "bits_per_sample")?,
+            y_ratio_uv: nonzero_u8(value.y_ratio_uv, "y_ratio_uv")?,
+            x_ratio_uv: nonzero_u8(value.x_ratio_uv, "x_ratio_uv")?,
+            h_padding: usize(value.h_padding, "h_padding")?,
+            v_padding: usize(value.v_padding, "v_padding")?,
+        })
+    }
+}
+
 impl From<MVAnalysisData> for MVAnalysisPropData {
     fn from(value: MVAnalysisData) -> Self {
         MVAnalysisPropData {
@@ -230,99 +603,101 @@ impl<'core> Analyse<'core> {
         tff: Option<i64>,
         search_coarse: Option<i64>,
         dct: Option<i64>,
+        info: Option<i64>,
+        multi: Option<i64>,
+        limit: Option<i64>,
+        threads: Option<i64>,
+        trivialsad: Option<i64>,
+        export_sad: Option<i64>,
+        left: Option<i64>,
+        top: Option<i64>,
+        cropw: Option<i64>,
+        croph: Option<i64>,
+        global_weight: Option<i64>,
+        export_motion: Option<i64>,
+        fixed_dct: Option<i64>,
     ) -> Result<Self> {
         let blk_size_x = blksize.map_or(Ok(8), usize::try_from)?;
         let blk_size_y = blksizev.map_or(Ok(blk_size_x), usize::try_from)?;
         let overlap_x = overlap.map_or(Ok(0), usize::try_from)?;
         let overlap_y = overlapv.map_or(Ok(overlap_x), usize::try_from)?;
         let truemotion = truemotion.is_none_or(|truemotion| truemotion > 0);
-        let penalty_new = pnew.map_or(Ok(if truemotion { 50 } else { 0 }), u16::try_from)?;
-        let penalty_zero = pzero.map_or(Ok(penalty_new), u16::try_from)?;
-        let penalty_global = pglobal.map_or(Ok(0), u16::try_from)?;
+        let preset = MotionPreset::new(truemotion, blk_size_x, blk_size_y);
+        let penalty_new = parse_penalty(pnew, preset.penalty_new, "pnew")?;
+        let penalty_zero = parse_penalty(pzero, penalty_new, "pzero")?;
+        let penalty_global = parse_penalty(pglobal, 0, "pglobal")?;
+        let global_weight = parse_penalty(global_weight, 0, "global_weight")?;
         let dctmode = dct.map_or(Ok(DctMode::Spatial), DctMode::try_from)?;
+        let dct_kind = if fixed_dct.is_some_and(|fixed_dct| fixed_dct > 0) {
+            DctKind::Integer
+        } else {
+            DctKind::Float
+        };
         let search_type = search.map_or(Ok(SearchType::Hex2), SearchType::try_from)?;
-        let mut search_param = searchparam.map_or(Ok(2), i32::try_from)?;
+        let search_param = searchparam.unwrap_or(2);
         let divide_extra = divide.map_or(Ok(DivideMode::None), DivideMode::try_from)?;
         let mut chroma = chroma.is_none_or(|chroma| chroma > 0);
-        let mut lambda = lambda.map_or(
-            Ok(if truemotion {
-                (1000 * blk_size_x * blk_size_y / 64) as u32
-            } else {
-                0
-            }),
-            u32::try_from,
-        )?;
-        let mut lambda_sad = lsad.map_or(Ok(if truemotion { 1200 } else { 400 }), u32::try_from)?;
+        let mut lambda = lambda.map_or(Ok(preset.lambda), u32::try_from)?;
+        let mut lambda_sad = lsad.map_or(Ok(preset.lambda_sad), u32::try_from)?;
         let mut bad_sad = badsad.map_or(Ok(10_000), u64::try_from)?;
-        let is_backward = isb.is_some_and(|isb| isb > 0);
+        let mut trivial_sad = parse_param("Analyse", "trivialsad", trivialsad, 0, "at least 0")?;
+        let both = isb == Some(2);
+        let is_backward = !both && isb.is_some_and(|isb| isb > 0);
         let delta_frame = delta.map_or(Ok(1), isize::try_from)?;
-        let mut pel_search = pelsearch.map_or(Ok(0), usize::try_from)?;
+        let pel_search: u32 = parse_param("Analyse", "pelsearch", pelsearch, 0, "at least 0")?;
+        let show_info = info.is_some_and(|info| info > 0);
+        let multi = multi.is_some_and(|multi| multi > 0);
+        let limit = parse_param("Analyse", "limit", limit, 0, "at least 0")?;
+        let bad_range = badrange.map_or(Ok(24), i32::try_from)?;
+        let meander = meander.is_none_or(|meander| meander > 0);
+        let threads = parse_param("Analyse", "threads", threads, 1, "at least 1")?;
+        if threads == 0 || (threads > 1 && (meander || bad_range != 0)) {
+            bail!(ZooMvError::InvalidParam {
+                filter: "Analyse",
+                name: "threads",
+                value: threads as i64,
+                expected: if threads == 0 {
+                    "at least 1"
+                } else {
+                    "1 unless meander=False and badrange=0"
+                },
+            });
+        }
+        let pool = (threads > 1)
+            .then(|| ThreadPoolBuilder::new().num_threads(threads).build())
+            .transpose()?;
 
         if dctmode.uses_satd() && blk_size_x == 16 && blk_size_y == 2 {
             bail!("Analyse: dct 5-10 cannot work with 16x2 blocks");
         }
-        match (blk_size_x, blk_size_y) {
-            // Allowed block sizes
-            (4, 4)
-            | (8, 4)
-            | (8, 8)
-            | (16, 2)
-            | (16, 8)
-            | (16, 16)
-            | (32, 16)
-            | (32, 32)
-            | (64, 32)
-            | (64, 64)
-            | (128, 64)
-            | (128, 128) => (),
-            _ => bail!(
-                "Analyse: the block size must be 4x4, 8x4, 8x8, 16x2, 16x8, 16x16, 32x16, 32x32, \
-                 64x32, 64x64, 128x64, or 128x128."
-            ),
-        }
-
-        if penalty_new > 256 {
-            bail!("Analyse: pnew must be between 0 and 256 (inclusive).");
-        }
-        if penalty_zero > 256 {
-            bail!("Analyse: pzero must be between 0 and 256 (inclusive).");
-        }
-        if penalty_global > 256 {
-            bail!("Analyse: pglobal must be between 0 and 256 (inclusive).");
-        }
-
-        if overlap_x > blk_size_x / 2 || overlap_y > blk_size_y / 2 {
-            bail!(
-                "Analyse: overlap must be at most half of blksize, and overlapv must be at most \
-                 half of blksizev"
-            );
-        }
+        validate_block_size(blk_size_x, blk_size_y)?;
 
         if divide_extra != DivideMode::None && (blk_size_x < 8 || blk_size_y < 8) {
             bail!("Analyse: blksize and blksizev must be at least 8 when divide=True.");
         }
 
-        if search_type == SearchType::Nstep {
-            if search_param < 0 {
-                search_param = 0;
-            }
-        } else if search_param < 1 {
-            search_param = 1;
-        }
-
         let info = super_.info();
         let format = info.format;
         if format.bits_per_sample() > 16 {
-            bail!("Analyse: input clip must be 8-16 bits");
+            bail!(ZooMvError::UnsupportedBitDepth {
+                filter: "Analyse",
+                bits: format.bits_per_sample(),
+            });
         }
         if format.sample_type() != SampleType::Integer {
-            bail!("Analyse: input clip must be integer super_format");
+            bail!(ZooMvError::UnsupportedFormat {
+                filter: "Analyse",
+                requirement: "be integer format",
+            });
         }
         if ![ColorFamily::YUV, ColorFamily::Gray].contains(&format.color_family())
             || format.sub_sampling_w() > 1
             || format.sub_sampling_h() > 1
         {
-            bail!("Analyse: input clip must be GRAY, 420, 422, 440, or 444");
+            bail!(ZooMvError::UnsupportedFormat {
+                filter: "Analyse",
+                requirement: "be GRAY, 420, 422, 440, or 444",
+            });
         }
 
         if format.color_family() == ColorFamily::Gray {
@@ -338,9 +713,11 @@ impl<'core> Analyse<'core> {
         let pixel_max = (1u32 << bits_per_sample.get()) - 1;
         lambda_sad = (lambda_sad as f32 * pixel_max as f32 / 255.0 + 0.5) as u32;
         bad_sad = (bad_sad as f32 * pixel_max as f32 / 255.0 + 0.5) as u64;
+        trivial_sad = (trivial_sad as f32 * pixel_max as f32 / 255.0 + 0.5) as u64;
         lambda = (lambda as f32 * pixel_max as f32 / 255.0 + 0.5) as u32;
         lambda_sad = (lambda_sad as usize * (blk_size_x * blk_size_y) / 64) as u32;
         bad_sad = bad_sad * (blk_size_x * blk_size_y) as u64 / 64;
+        trivial_sad = trivial_sad * (blk_size_x * blk_size_y) as u64 / 64;
 
         // TODO: Why are we using this instead of just checking the variables directly?
         let mut motion_flags = MotionFlags::empty();
@@ -357,21 +734,20 @@ impl<'core> Analyse<'core> {
             MVPlaneSet::YPLANE
         };
 
-        if overlap_x % (1 << format.sub_sampling_w()) > 0
-            || overlap_y % (1 << format.sub_sampling_h()) > 0
-        {
-            bail!(
-                "Analyse: the requested overlap is incompatible with the super clip's subsampling."
-            );
+        validate_overlap(
+            blk_size_x,
+            blk_size_y,
+            overlap_x,
+            overlap_y,
+            format.sub_sampling_w(),
+            format.sub_sampling_h(),
+            divide_extra != DivideMode::None,
+        )?;
+        if multi && delta_frame <= 0 {
+            bail!("Analyse: delta must be positive when multi is set.");
         }
-        if divide_extra != DivideMode::None
-            && (overlap_x % (2 << format.sub_sampling_w()) > 0
-                || overlap_y % (2 << format.sub_sampling_h()) > 0)
-        {
-            bail!(
-                "Analyse: overlap and overlapv must be multiples of 2 or 4 when divide=True, \
-                 depending on the super clip's subsampling."
-            );
+        if both && delta_frame <= 0 {
+            bail!("Analyse: delta must be positive when isb=2.");
         }
         if delta_frame <= 0 && (-delta_frame) >= info.num_frames as isize {
             bail!("Analyse: delta points to frame past the input clip's end.");
@@ -379,7 +755,10 @@ impl<'core> Analyse<'core> {
 
         let (width, _height) = match info.resolution {
             vapoursynth::prelude::Property::Variable => {
-                bail!("Analyse: variable resolution input clips are not supported")
+                bail!(ZooMvError::VariableFormat {
+                    filter: "Analyse",
+                    clip: "super",
+                })
             }
             // SAFETY: width and height must be positive
             vapoursynth::prelude::Property::Constant(resolution) => unsafe {
@@ -403,77 +782,107 @@ impl<'core> Analyse<'core> {
             }
         };
         let super_props = evil.props();
-        let super_props_err = "Analyse: required properties not found in first frame of super \
-                               clip. Maybe clip didn't come from mv.Super? Was the first frame \
-                               trimmed away?";
-        let super_props_err2 = "Analyse: parameters from super clip appear to be wrong.";
+        let super_props_err = ZooMvError::IncompatibleSuper {
+            filter: "Analyse",
+            reason: "required properties not found in first frame of super clip. Maybe clip \
+                     didn't come from mv.Super? Was the first frame trimmed away?",
+        };
+        let super_props_err2 = ZooMvError::IncompatibleSuper {
+            filter: "Analyse",
+            reason: "parameters from super clip appear to be wrong.",
+        };
         let super_height = NonZeroUsize::new(
             usize::try_from(
                 super_props
                     .get_int("Super_height")
-                    .map_err(|_| anyhow!(super_props_err))?,
+                    .map_err(|_| super_props_err)?,
             )
-            .map_err(|_| anyhow!(super_props_err2))?,
+            .map_err(|_| super_props_err2)?,
         )
-        .ok_or_else(|| anyhow!(super_props_err2))?;
+        .ok_or(super_props_err2)?;
         let super_hpad = usize::try_from(
             super_props
                 .get_int("Super_hpad")
-                .map_err(|_| anyhow!(super_props_err))?,
+                .map_err(|_| super_props_err)?,
         )
-        .map_err(|_| anyhow!(super_props_err2))?;
+        .map_err(|_| super_props_err2)?;
         let super_vpad = usize::try_from(
             super_props
                 .get_int("Super_vpad")
-                .map_err(|_| anyhow!(super_props_err))?,
+                .map_err(|_| super_props_err)?,
         )
-        .map_err(|_| anyhow!(super_props_err2))?;
+        .map_err(|_| super_props_err2)?;
         let super_pel = Subpel::try_from(
             super_props
                 .get_int("Super_pel")
-                .map_err(|_| anyhow!(super_props_err))?,
+                .map_err(|_| super_props_err)?,
         )
-        .map_err(|_| anyhow!(super_props_err2))?;
+        .map_err(|_| super_props_err2)?;
         let super_mode_yuv = MVPlaneSet::from_bits(
             u8::try_from(
                 super_props
                     .get_int("Super_modeyuv")
-                    .map_err(|_| anyhow!(super_props_err))?,
+                    .map_err(|_| super_props_err)?,
             )
-            .map_err(|_| anyhow!(super_props_err2))?,
+            .map_err(|_| super_props_err2)?,
         )
-        .ok_or_else(|| anyhow!(super_props_err2))?;
+        .ok_or(super_props_err2)?;
         let super_levels = usize::try_from(
             super_props
                 .get_int("Super_levels")
-                .map_err(|_| anyhow!(super_props_err))?,
+                .map_err(|_| super_props_err)?,
         )
-        .map_err(|_| anyhow!(super_props_err2))?;
+        .map_err(|_| super_props_err2)?;
         if super_hpad >= super_height.get() / 2 {
             bail!(super_props_err2);
         }
 
         if mode_yuv & super_mode_yuv != mode_yuv {
-            bail!("Analyse: super clip does not contain needed colour data.");
+            bail!(ZooMvError::IncompatibleSuper {
+                filter: "Analyse",
+                reason: "super clip does not contain needed colour data.",
+            });
         }
 
         let super_width = NonZeroUsize::new(width.get() - super_hpad * 2)
             .expect("super width should not be zero");
-        let blk_x = (super_width.get() - overlap_x) / (blk_size_x - overlap_x);
-        let blk_y = (super_height.get() - overlap_y) / (blk_size_y - overlap_y);
-        let width_b = (blk_size_x - overlap_x) * blk_x + overlap_x;
-        let height_b = (blk_size_y - overlap_y) * blk_y + overlap_y;
-
-        // calculate valid levels
-        let mut levels_max = 0;
-        while ((width_b >> levels_max) - overlap_x) / (blk_size_x - overlap_x) > 0
-            && ((height_b >> levels_max) - overlap_y) / (blk_size_y - overlap_y) > 0
-        {
-            levels_max += 1;
-        }
-        let level_count = levels
-            .filter(|l| *l > 0)
-            .map_or(levels_max, |levels| min(levels_max, levels as usize));
+        let blk_size_x = NonZeroUsize::new(blk_size_x)
+            .ok_or_else(|| anyhow!("Analyse: blksize must be greater than 0"))?;
+        let blk_size_y = NonZeroUsize::new(blk_size_y)
+            .ok_or_else(|| anyhow!("Analyse: blksizev must be greater than 0"))?;
+        let (region_width, region_height) = analysis_region(
+            super_width,
+            super_height,
+            x_ratio_uv,
+            y_ratio_uv,
+            left,
+            top,
+            cropw,
+            croph,
+        )?;
+        let blocks_at = |width, height, level| {
+            level_blocks(
+                width, height, level, blk_size_x, blk_size_y, overlap_x, overlap_y,
+            )
+        };
+        let (blk_x, blk_y) =
+            blocks_at(region_width.get(), region_height.get(), 0).ok_or_else(|| {
+                anyhow!(
+                    "Analyse: the clip is too small for a single {blk_size_x}x{blk_size_y} block"
+                )
+            })?;
+        let width_b = (blk_size_x.get() - overlap_x) * blk_x.get() + overlap_x;
+        let height_b = (blk_size_y.get() - overlap_y) * blk_y.get() + overlap_y;
+
+        let level_count = level_count(
+            width_b,
+            height_b,
+            blk_size_x,
+            blk_size_y,
+            overlap_x,
+            overlap_y,
+            levels.and_then(|levels| usize::try_from(levels).ok()),
+        );
         debug_assert!(level_count > 0);
 
         if level_count > super_levels {
@@ -484,26 +893,33 @@ impl<'core> Analyse<'core> {
             );
         }
 
-        if pel_search == 0 {
-            pel_search = super_pel as usize;
-        }
+        // No vector can be longer than the frame, so larger searches would only waste time
+        let max_search = super_width.max(super_height).get();
+        let search_param = search_range(search_type, search_param, max_search);
+        let pel_search = search_range(
+            search_type,
+            if pel_search == 0 {
+                i64::from(u8::from(super_pel))
+            } else {
+                i64::from(pel_search)
+            },
+            max_search * u8::from(super_pel) as usize,
+        );
 
         let analysis_data = MVAnalysisData {
-            blk_size_x: NonZeroUsize::new(blk_size_x)
-                .ok_or_else(|| anyhow!("Analyse: blksize must be greater than 0"))?,
-            blk_size_y: NonZeroUsize::new(blk_size_y)
-                .ok_or_else(|| anyhow!("Analyse: blksizev must be greater than 0"))?,
+            blk_size_x,
+            blk_size_y,
             pel: super_pel,
             level_count,
             delta_frame,
             is_backward,
             motion_flags,
-            width: super_width,
-            height: super_height,
+            width: region_width,
+            height: region_height,
             overlap_x,
             overlap_y,
-            blk_x: NonZeroUsize::new(blk_x).expect("block count should not be zero"),
-            blk_y: NonZeroUsize::new(blk_y).expect("block count should not be zero"),
+            blk_x,
+            blk_y,
             bits_per_sample,
             y_ratio_uv,
             x_ratio_uv,
@@ -511,25 +927,8 @@ impl<'core> Analyse<'core> {
             v_padding: super_vpad,
         };
 
-        let analysis_data_divided = (divide_extra != DivideMode::None).then(|| {
-            let mut div_data = analysis_data;
-            // SAFETY: constant is non-zero
-            div_data.blk_x = div_data
-                .blk_x
-                .saturating_mul(unsafe { NonZeroUsize::new_unchecked(2) });
-            // SAFETY: constant is non-zero
-            div_data.blk_y = div_data
-                .blk_y
-                .saturating_mul(unsafe { NonZeroUsize::new_unchecked(2) });
-            div_data.blk_size_x = NonZeroUsize::new(div_data.blk_size_x.get() / 2)
-                .expect("block size cannot not be 1");
-            div_data.blk_size_y = NonZeroUsize::new(div_data.blk_size_y.get() / 2)
-                .expect("block size cannot not be 1");
-            div_data.overlap_x /= 2;
-            div_data.overlap_y /= 2;
-            div_data.level_count += 1;
-            div_data
-        });
+        let analysis_data_divided =
+            (divide_extra != DivideMode::None).then(|| analysis_data.divided());
 
         Ok(Self {
             node: super_,
@@ -538,33 +937,35 @@ impl<'core> Analyse<'core> {
             search_type_coarse: search_coarse
                 .map_or(Ok(SearchType::Exhaustive), SearchType::try_from)?,
             search_param,
-            pel_search: pel_search as i32,
+            pel_search,
             chroma,
             truemotion,
             lambda,
             lambda_sad,
-            penalty_level: plevel.map_or_else(
-                || {
-                    Ok(if truemotion {
-                        PenaltyScaling::Linear
-                    } else {
-                        PenaltyScaling::None
-                    })
-                },
-                PenaltyScaling::try_from,
-            )?,
-            global: global.map_or(truemotion, |global| global > 0),
+            penalty_level: plevel.map_or(Ok(preset.penalty_level), PenaltyScaling::try_from)?,
+            global: global.map_or(preset.global, |global| global > 0),
             penalty_new,
             penalty_zero,
             penalty_global,
+            global_weight,
             dct_mode: dctmode,
             divide_extra,
             bad_sad,
-            bad_range: badrange.map_or(Ok(24), i32::try_from)?,
-            meander: meander.is_none_or(|meander| meander > 0),
+            trivial_sad,
+            bad_range,
+            meander,
             try_many: trymany.is_some_and(|trymany| trymany > 0),
             fields: fields.is_some_and(|fields| fields > 0),
             tff: tff.map(|tff| tff > 0),
+            info: show_info,
+            export_sad: export_sad.is_some_and(|export_sad| export_sad > 0),
+            export_motion: export_motion.is_some_and(|export_motion| export_motion > 0),
+            multi,
+            both: both && !multi,
+            limit,
+            pool,
+            dct_pool: DctPool::new(dct_kind),
+            gof_pool: GofPool::default(),
             analysis_data,
             analysis_data_divided,
             format,
@@ -574,15 +975,63 @@ impl<'core> Analyse<'core> {
             super_pel,
             super_mode_yuv,
             super_levels,
+            region_origin: (
+                left.map_or(0, |left| left as usize),
+                top.map_or(0, |top| top as usize),
+            ),
+            clip_width: super_width,
+            clip_height: super_height,
         })
     }
 
+    /// Whether frame `n` is a top field. If `tff` was passed, it overrides the frame
+    /// properties, with the parity alternating from one frame to the next.
+    fn is_top_field(&self, frame: &FrameRef<'core>, n: usize) -> Result<bool> {
+        if let Some(tff) = self.tff {
+            return Ok(is_top_field_from_tff(tff, n));
+        }
+
+        let props = frame.props();
+        match top_field_from_props(
+            props.get_int("_Field").ok(),
+            props.get_int("_FieldBased").ok(),
+            n,
+        ) {
+            Some(top_field) => Ok(top_field),
+            None if self.fields => {
+                bail!(
+                    "Analyse: neither _Field nor _FieldBased gives the field order of frame {n}. \
+                     Therefore, you must pass tff argument."
+                );
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// The source frame, delta and direction of the vectors in output frame `n`.
+    fn vector_source(&self, n: usize) -> (usize, isize, bool) {
+        if self.multi {
+            let multi_delta = NonZeroUsize::new(self.analysis_data.delta_frame as usize)
+                .expect("delta is positive when multi is set");
+            multi_position(n, multi_delta)
+        } else if self.both {
+            both_position(n, self.analysis_data.delta_frame)
+        } else {
+            (
+                n,
+                self.analysis_data.delta_frame,
+                self.analysis_data.is_backward,
+            )
+        }
+    }
+
     fn get_frame_internal<T: Pixel>(
         &self,
         core: vapoursynth::core::CoreRef<'core>,
         context: vapoursynth::plugins::FrameContext,
         n: usize,
     ) -> Result<FrameRef<'core>> {
+        let (n, delta_frame, is_backward) = self.vector_source(n);
         let mut vector_fields = GroupOfPlanes::<T>::new(
             self.analysis_data.blk_size_x,
             self.analysis_data.blk_size_y,
@@ -598,122 +1047,54 @@ impl<'core> Analyse<'core> {
             self.divide_extra,
             self.analysis_data.bits_per_sample,
         )?;
+        vector_fields.set_origin(self.region_origin.0, self.region_origin.1);
 
-        let nref = if self.analysis_data.delta_frame > 0 {
-            let offset = if self.analysis_data.is_backward {
-                self.analysis_data.delta_frame
-            } else {
-                -self.analysis_data.delta_frame
-            };
-            n as isize + offset
-        } else {
-            // special static mode
-            // positive fixed frame number
-            -self.analysis_data.delta_frame
-        };
+        let nref = valid_reference(n, delta_frame, is_backward, self.node.info().num_frames);
 
         let src = self
             .node
             .get_frame_filter(context, n)
             .ok_or_else(|| anyhow!("Analyse: get_frame_filter past end of video"))?;
-        let src_props = src.props();
-
-        let mut src_top_field = match src_props.get_int("_Field") {
-            Ok(field) => field > 0,
-            Err(_) if self.fields && self.tff.is_none() => {
-                bail!(
-                    "Analyse: _Field property not found in input frame. Therefore, you must pass \
-                     tff argument."
-                );
-            }
-            _ => false,
-        };
-        // if tff was passed, it overrides _Field.
-        if let Some(tff) = self.tff {
-            src_top_field = (tff as u8 ^ (n % 2) as u8) > 0;
-        }
+        check_clip_frame("Analyse", "super", &self.node, n, &src)?;
+        let src_top_field = self.is_top_field(&src, n)?;
 
-        let vectors = if nref >= 0 && (nref as usize) < self.node.info().num_frames {
+        let mut search_stats = SearchStats::default();
+        let mut search_bounds = Vec::new();
+        let vectors = if let Some(nref) = nref {
             let ref_ = self
                 .node
-                .get_frame_filter(context, nref as usize)
+                .get_frame_filter(context, nref)
                 .ok_or_else(|| anyhow!("Analyse: get_frame_filter ref past end of video"))?;
-            let ref_props = ref_.props();
-            let mut ref_top_field = match ref_props.get_int("_Field") {
-                Ok(field) => field > 0,
-                Err(_) if self.fields && self.tff.is_none() => {
-                    bail!(
-                        "Analyse: _Field property not found in input frame. Therefore, you must \
-                         pass tff argument."
-                    );
-                }
-                _ => false,
-            };
-
-            // if tff was passed, it overrides _Field.
-            if let Some(tff) = self.tff {
-                ref_top_field = (tff as u8 ^ (n % 2) as u8) > 0;
-            }
-
-            let mut field_shift = 0;
-            if self.fields
-                && self.analysis_data.pel > Subpel::Full
-                && (self.analysis_data.delta_frame % 2) > 0
-            {
-                // vertical shift of fields for fieldbased video at finest level pel2
-                field_shift = if src_top_field && !ref_top_field {
-                    (u8::from(self.analysis_data.pel) as u32 / 2) as i32
-                } else if ref_top_field && !src_top_field {
-                    -((u8::from(self.analysis_data.pel) as u32 / 2) as i32)
-                } else {
-                    0
-                };
-            }
+            check_clip_frame("Analyse", "super", &self.node, nref, &ref_)?;
+            let ref_top_field = self.is_top_field(&ref_, nref)?;
+            let field_shift = field_shift(
+                self.fields,
+                self.analysis_data.pel,
+                delta_frame,
+                src_top_field,
+                ref_top_field,
+            );
 
-            let src_pitch = [
-                // SAFETY: stride cannot be 0
-                unsafe { NonZeroUsize::new_unchecked(src.stride(0) / size_of::<T>()) },
-                // SAFETY: stride cannot be 0
-                unsafe { NonZeroUsize::new_unchecked(src.stride(1) / size_of::<T>()) },
-                // SAFETY: stride cannot be 0
-                unsafe { NonZeroUsize::new_unchecked(src.stride(2) / size_of::<T>()) },
-            ];
-            let ref_pitch = [
-                // SAFETY: stride cannot be 0
-                unsafe { NonZeroUsize::new_unchecked(ref_.stride(0) / size_of::<T>()) },
-                // SAFETY: stride cannot be 0
-                unsafe { NonZeroUsize::new_unchecked(ref_.stride(1) / size_of::<T>()) },
-                // SAFETY: stride cannot be 0
-                unsafe { NonZeroUsize::new_unchecked(ref_.stride(2) / size_of::<T>()) },
-            ];
-            let src_gof = MVGroupOfFrames::new(
-                self.super_levels,
-                self.analysis_data.width,
-                self.analysis_data.height,
-                self.super_pel,
-                self.super_hpad,
-                self.super_vpad,
-                self.super_mode_yuv,
-                self.analysis_data.x_ratio_uv,
-                self.analysis_data.y_ratio_uv,
-                self.analysis_data.bits_per_sample,
-                &src_pitch,
-                self.format,
-            )?;
-            let ref_gof = MVGroupOfFrames::new(
-                self.super_levels,
-                self.analysis_data.width,
-                self.analysis_data.height,
-                self.super_pel,
-                self.super_hpad,
-                self.super_vpad,
-                self.super_mode_yuv,
-                self.analysis_data.x_ratio_uv,
-                self.analysis_data.y_ratio_uv,
-                self.analysis_data.bits_per_sample,
-                &ref_pitch,
-                self.format,
-            )?;
+            let src_pitch = plane_pitches::<T>(&src);
+            let ref_pitch = plane_pitches::<T>(&ref_);
+            let build = |pitch: &[NonZeroUsize; 3]| {
+                MVGroupOfFrames::new(
+                    self.super_levels,
+                    self.clip_width,
+                    self.clip_height,
+                    self.super_pel,
+                    self.super_hpad,
+                    self.super_vpad,
+                    self.super_mode_yuv,
+                    self.analysis_data.x_ratio_uv,
+                    self.analysis_data.y_ratio_uv,
+                    self.analysis_data.bits_per_sample,
+                    pitch,
+                    self.format,
+                )
+            };
+            let src_gof = self.gof_pool.take(&src_pitch, || build(&src_pitch))?;
+            let ref_gof = self.gof_pool.take(&ref_pitch, || build(&ref_pitch))?;
 
             let mut vectors = vector_fields.search_mvs(
                 &src_gof,
@@ -733,45 +1114,251 @@ impl<'core> Analyse<'core> {
                 self.dct_mode,
                 self.penalty_zero,
                 self.penalty_global,
+                self.global_weight,
                 self.bad_sad,
+                self.trivial_sad,
                 self.bad_range,
                 self.meander,
                 self.try_many,
+                &self.dct_pool,
+                self.pool.as_ref(),
             )?;
             if self.divide_extra != DivideMode::None {
                 vector_fields.extra_divide(&mut vectors);
             }
+            if self.limit > 0 {
+                let pel = u32::from(u8::from(self.analysis_data.pel));
+                vector_fields.limit_vectors(&mut vectors, self.limit.saturating_mul(pel));
+            }
+            if self.info {
+                search_stats = vector_fields.search_stats();
+                search_bounds = vector_fields.search_bounds(&src_gof);
+            }
+            self.gof_pool.give_back(src_pitch, src_gof);
+            self.gof_pool.give_back(ref_pitch, ref_gof);
             vectors
         } else {
-            // too close to the beginning or end to do anything
+            // too close to the beginning or end to do anything,
+            // so downstream filters fall back to the source
             vector_fields.write_default_to_array()
         };
 
+        let mut analysis_data = if self.divide_extra != DivideMode::None {
+            self.analysis_data_divided
+        } else {
+            Some(self.analysis_data)
+        };
+        if let Some(data) = analysis_data.as_mut() {
+            data.delta_frame = delta_frame;
+            data.is_backward = is_backward;
+            data.motion_flags.set(MotionFlags::IS_BACKWARD, is_backward);
+        }
+
         let mut dest = FrameRefMut::copy_of(core, &src);
         let mut dest_props = dest.props_mut();
         dest_props.set_data(
             PROP_MVANALYSISDATA,
-            &if self.divide_extra != DivideMode::None {
-                self.analysis_data_divided
-                    .as_ref()
-                    .map_or_else(Vec::new, |data| data.bytes())
-            } else {
-                self.analysis_data.bytes()
-            },
+            &analysis_data.map_or_else(Vec::new, |data| data.bytes()),
         )?;
         dest_props.set_data(PROP_VECTORS, &vectors.block_data)?;
+        dest_props.set_int(PROP_VALID, i64::from(vectors.validity))?;
+        dest_props.set_int(PROP_LEVELS, self.analysis_data.level_count as i64)?;
+        dest_props.set_int(PROP_DIVIDE, self.divide_extra as i64)?;
+        dest_props.set_int_array(PROP_BLKSIZE, &[
+            self.analysis_data.blk_size_x.get() as i64,
+            self.analysis_data.blk_size_y.get() as i64,
+        ])?;
+        dest_props.set_int_array(PROP_OVERLAP, &[
+            self.analysis_data.overlap_x as i64,
+            self.analysis_data.overlap_y as i64,
+        ])?;
+        if self.multi {
+            dest_props.set_int(PROP_MULTI_DELTA, self.analysis_data.delta_frame as i64)?;
+        }
+        if self.info {
+            dest_props.set_int(PROP_INFO_CLAMPED, search_stats.clamped_count as i64)?;
+            dest_props.set_int(PROP_INFO_BAD, search_stats.bad_count as i64)?;
+            dest_props.set_float(PROP_INFO_AVERAGE_SAD, search_stats.average_sad())?;
+            if !search_bounds.is_empty() {
+                dest_props
+                    .set_int_array(PROP_INFO_SEARCH_BOUNDS, &flatten_bounds(&search_bounds))?;
+            }
+        }
+        if self.export_sad
+            && let Some(data) = analysis_data.as_ref()
+            && let Some(sads) = finest_sads(&vectors.block_data, data)?
+        {
+            dest_props.set_int_array(PROP_BLOCK_SADS, &sads)?;
+        }
+        if self.export_motion
+            && let Some(data) = analysis_data.as_ref()
+            && let Some(motion) = finest_motion(&vectors.block_data, data)?
+        {
+            dest_props.set_float_array(PROP_BLOCK_MOTION, &motion)?;
+        }
 
         Ok(dest.into())
     }
 }
 
+/// The reference frame for the vectors of frame `n`, which may be outside of the clip.
+///
+/// A `delta_frame` of 0 or less selects the static mode,
+/// where the reference is always frame `-delta_frame`.
+#[must_use]
+fn reference_frame(n: usize, delta_frame: isize, is_backward: bool) -> isize {
+    if delta_frame > 0 {
+        let offset = if is_backward {
+            delta_frame
+        } else {
+            -delta_frame
+        };
+        n as isize + offset
+    } else {
+        -delta_frame
+    }
+}
+
+/// Flattens block search ranges into `[dx_min, dy_min, dx_max, dy_max]` per block.
+#[must_use]
+fn flatten_bounds(bounds: &[MvBounds]) -> Vec<i64> {
+    bounds
+        .iter()
+        .flat_map(|b| [b.dx_min, b.dy_min, b.dx_max, b.dy_max].map(i64::from))
+        .collect()
+}
+
+/// The reference frame for the vectors of frame `n`, or `None` if it is outside of a clip
+/// of `num_frames` frames. Frames without a reference get invalid vectors.
+///
+/// The static mode's reference is checked when `Analyse` is created, so it is always valid.
+#[must_use]
+fn valid_reference(
+    n: usize,
+    delta_frame: isize,
+    is_backward: bool,
+    num_frames: usize,
+) -> Option<usize> {
+    usize::try_from(reference_frame(n, delta_frame, is_backward))
+        .ok()
+        .filter(|&nref| nref < num_frames)
+}
+
+/// The frames of the super clip needed for output frame `n`, in ascending order.
+/// The reference frame is only requested if it is inside the clip.
+fn requested_frames(n: usize, nref: Option<usize>) -> impl Iterator<Item = usize> {
+    let (first, second) = nref.map_or((n, None), |nref| (n.min(nref), Some(n.max(nref))));
+    std::iter::once(first).chain(second)
+}
+
+/// The source frame, delta and direction of frame `n` of a multi-vector clip
+/// with deltas up to `multi_delta`.
+///
+/// Each source frame is expanded to `2 * multi_delta` frames, holding
+/// the backward and forward vectors of each delta in turn: `bv1, fv1, bv2, fv2, ...`.
+#[must_use]
+pub(crate) fn multi_position(n: usize, multi_delta: NonZeroUsize) -> (usize, isize, bool) {
+    let per_frame = 2 * multi_delta.get();
+    let index = n % per_frame;
+    (
+        n / per_frame,
+        (index / 2 + 1) as isize,
+        index.is_multiple_of(2),
+    )
+}
+
+/// The source frame, delta and direction of frame `n` of a clip analysed with `isb=2`.
+///
+/// Each source frame is expanded to its backward and forward vectors, in the same order
+/// as a multi-vector clip with a single delta, so `std.SelectEvery(2, 0)` gives the same
+/// vectors as `isb=1` and `std.SelectEvery(2, 1)` the same as `isb=0`.
+#[must_use]
+pub(crate) fn both_position(n: usize, delta_frame: isize) -> (usize, isize, bool) {
+    (n / 2, delta_frame, n.is_multiple_of(2))
+}
+
+/// Coerces `searchparam` or `pelsearch` into the range where `search_type` does any work.
+///
+/// As in mvtools, every search type except `Nstep` searches at least 1 step away,
+/// while `Nstep` searches nothing with 0 steps. Values above `max` are clamped to it,
+/// which keeps the exhaustive and hexagon searches from spending their time outside the frame.
+#[must_use]
+fn search_range(search_type: SearchType, value: i64, max: usize) -> i32 {
+    let min = if search_type == SearchType::Nstep {
+        0
+    } else {
+        1
+    };
+    value.clamp(min, max.min(i32::MAX as usize) as i64) as i32
+}
+
+/// Parses one of the `pnew`, `pzero` or `pglobal` penalties, which are relative to the SAD
+/// and scaled to 256, so that 256 doubles the cost of a candidate.
+/// `global_weight` has the same range, with 256 moving the predictor all the way.
+fn parse_penalty(value: Option<i64>, default: u16, name: &str) -> Result<u16> {
+    match value {
+        None => Ok(default),
+        Some(value @ 0..=256) => Ok(value as u16),
+        Some(_) => bail!("Analyse: {name} must be between 0 and 256 (inclusive)."),
+    }
+}
+
+/// Separated fields alternate parity, starting with a top field at frame 0 if `tff` is set.
+#[must_use]
+fn is_top_field_from_tff(tff: bool, n: usize) -> bool {
+    tff ^ (n % 2 == 1)
+}
+
+/// Whether frame `n` is a top field, according to its `_Field` property, or else its
+/// `_FieldBased` property, where 1 is bottom field first and 2 is top field first.
+///
+/// Returns `None` if neither property gives a field order, e.g. for progressive frames.
+#[must_use]
+fn top_field_from_props(field: Option<i64>, field_based: Option<i64>, n: usize) -> Option<bool> {
+    match (field, field_based) {
+        (Some(field), _) => Some(field > 0),
+        (None, Some(1)) => Some(is_top_field_from_tff(false, n)),
+        (None, Some(2)) => Some(is_top_field_from_tff(true, n)),
+        _ => None,
+    }
+}
+
+/// Vertical shift between the source and reference fields, in units of `pel`.
+///
+/// Fields of opposite parity are offset by half a pixel vertically, which can only be
+/// represented when searching at subpixel precision with an odd frame distance.
+#[must_use]
+fn field_shift(
+    fields: bool,
+    pel: Subpel,
+    delta_frame: isize,
+    src_top_field: bool,
+    ref_top_field: bool,
+) -> i32 {
+    if !fields || pel == Subpel::Full || delta_frame % 2 == 0 {
+        return 0;
+    }
+
+    let half_pel = i32::from(u8::from(pel) / 2);
+    match (src_top_field, ref_top_field) {
+        (true, false) => half_pel,
+        (false, true) => -half_pel,
+        _ => 0,
+    }
+}
+
 impl<'core> Filter<'core> for Analyse<'core> {
     fn video_info(
         &self,
         _api: vapoursynth::prelude::API,
         _core: vapoursynth::core::CoreRef<'core>,
     ) -> Vec<vapoursynth::video_info::VideoInfo<'core>> {
-        let info = self.node.info();
+        let mut info = self.node.info();
+        if self.multi {
+            info.num_frames *= 2 * self.analysis_data.delta_frame as usize;
+        } else if self.both {
+            info.num_frames *= 2;
+        }
         vec![info]
     }
 
@@ -782,42 +1369,10 @@ impl<'core> Filter<'core> for Analyse<'core> {
         context: vapoursynth::plugins::FrameContext,
         n: usize,
     ) -> std::result::Result<Option<vapoursynth::prelude::FrameRef<'core>>, anyhow::Error> {
-        if self.analysis_data.delta_frame > 0 {
-            let offset = if self.analysis_data.is_backward {
-                self.analysis_data.delta_frame
-            } else {
-                -self.analysis_data.delta_frame
-            };
-            let nref = n as isize + offset;
-
-            if nref >= 0 && (nref as usize) < self.node.info().num_frames {
-                let nref = nref as usize;
-                if n < nref {
-                    self.node.request_frame_filter(context, n);
-                    self.node.request_frame_filter(context, nref);
-                } else {
-                    self.node.request_frame_filter(context, nref);
-                    self.node.request_frame_filter(context, n);
-                }
-            } else {
-                // too close to beginning/end of clip
-                self.node.request_frame_filter(context, n);
-            }
-        } else {
-            // special static mode
-
-            // positive fixed frame number
-            let nref = -self.analysis_data.delta_frame;
-            debug_assert!(nref >= 0);
-            let nref = nref as usize;
-
-            if n < nref {
-                self.node.request_frame_filter(context, n);
-                self.node.request_frame_filter(context, nref);
-            } else {
-                self.node.request_frame_filter(context, nref);
-                self.node.request_frame_filter(context, n);
-            }
+        let (n, delta_frame, is_backward) = self.vector_source(n);
+        let nref = valid_reference(n, delta_frame, is_backward, self.node.info().num_frames);
+        for frame in requested_frames(n, nref) {
+            self.node.request_frame_filter(context, frame);
         }
         Ok(None)
     }
@@ -1,21 +1,48 @@
 #![allow(clippy::unwrap_used, reason = "allow in test files")]
 #![allow(clippy::undocumented_unsafe_blocks, reason = "allow in test files")]
 
+use std::num::{NonZeroU8, NonZeroUsize};
+
 use anyhow::Result;
 use vapoursynth::{
     format::{FormatID, PresetFormat},
     prelude::Environment,
 };
 
-use super::Analyse;
-use crate::params::{
-    DctMode,
-    DivideMode,
-    MVPlaneSet,
-    MotionFlags,
-    PenaltyScaling,
-    SearchType,
-    Subpel,
+use super::{
+    Analyse,
+    BLOCK_SIZES,
+    analysis_region,
+    both_position,
+    field_shift,
+    is_top_field_from_tff,
+    multi_position,
+    parse_penalty,
+    reference_frame,
+    requested_frames,
+    search_range,
+    supported_block_heights,
+    supported_block_sizes,
+    top_field_from_props,
+    valid_reference,
+    validate_block_size,
+    validate_overlap,
+};
+use crate::{
+    dct::DctKind,
+    error::ZooMvError,
+    group_of_planes::level_blocks,
+    params::{
+        DctMode,
+        DivideMode,
+        MVPlaneSet,
+        MotionFlags,
+        MotionPreset,
+        PenaltyScaling,
+        SearchType,
+        Subpel,
+    },
+    util::get_sad,
 };
 
 fn create_test_env(
@@ -61,7 +88,8 @@ fn analyse_new_defaults() {
 
     let analyse = Analyse::new(
         node, None, None, None, None, None, None, None, None, None, None, None, None, None, None,
-        None, None, None, None, None, None, None, None, None, None, None, None, None, None,
+        None, None, None, None, None, None, None, None, None, None, None, None, None, None, None,
+        None, None, None, None, None, None, None, None, None, None, None, None,
     )
     .expect("Failed to create Analyse struct");
 
@@ -108,6 +136,14 @@ fn analyse_new_defaults() {
         analyse.penalty_global, 0,
         "Default penalty_global should be 0"
     );
+    assert_eq!(
+        analyse.global_weight, 0,
+        "Default global_weight should keep the predictor local"
+    );
+    assert!(
+        !analyse.export_motion,
+        "Vectors should not be exported as floats by default"
+    );
     assert_eq!(
         analyse.dct_mode,
         DctMode::Spatial,
@@ -297,6 +333,19 @@ fn analyse_new_custom_block_sizes() {
             None,
             None,
             None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
         )
         .unwrap_or_else(|_| panic!("Should accept valid block size {}x{}", blk_x, blk_y));
 
@@ -344,6 +393,19 @@ fn analyse_new_invalid_block_sizes() {
             None,
             None,
             None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
         );
 
         assert!(
@@ -352,11 +414,16 @@ fn analyse_new_invalid_block_sizes() {
             blk_x,
             blk_y
         );
+        let err = result.unwrap_err();
         assert!(
-            result
-                .unwrap_err()
-                .to_string()
-                .contains("block size must be")
+            matches!(
+                err.downcast_ref::<ZooMvError>(),
+                Some(&ZooMvError::InvalidParam {
+                    name: "blksize" | "blksizev",
+                    ..
+                })
+            ),
+            "{err}"
         );
     }
 }
@@ -398,6 +465,19 @@ fn analyse_new_penalty_validation() {
             None,
             None,
             None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
         );
         assert!(
             result.is_ok(),
@@ -406,8 +486,8 @@ fn analyse_new_penalty_validation() {
         );
     }
 
-    // Test invalid penalty values (>256)
-    for penalty in [257, 300, 1000] {
+    // Test invalid penalty values (<0 or >256)
+    for penalty in [-1, 257, 300, 1000] {
         // Test pnew
         let result = Analyse::new(
             node.clone(),
@@ -439,6 +519,19 @@ fn analyse_new_penalty_validation() {
             None,
             None,
             None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
         );
         assert!(
             result.is_err(),
@@ -483,6 +576,19 @@ fn analyse_new_penalty_validation() {
             None,
             None,
             None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
         );
         assert!(
             result.is_err(),
@@ -527,6 +633,19 @@ fn analyse_new_penalty_validation() {
             None,
             None,
             None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
         );
         assert!(
             result.is_err(),
@@ -578,6 +697,19 @@ fn analyse_new_overlap_validation() {
         None,
         None,
         None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
     );
     assert!(
         result.is_ok(),
@@ -615,6 +747,19 @@ fn analyse_new_overlap_validation() {
         None,
         None,
         None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
     );
     assert!(
         result.is_err(),
@@ -629,34 +774,19 @@ fn analyse_new_overlap_validation() {
 }
 
 #[test]
-fn analyse_new_divide_validation() {
+fn analyse_new_threads_validation() {
     let env = create_test_env(672, 2750, PresetFormat::YUV420P8, 10, 480, 16, 8, 7, 2, 16).unwrap();
     let (node, _) = env.get_output(0).unwrap();
 
-    // Test divide with valid block size (8x8)
+    // The wavefront search needs meander and the bad vector search disabled
     let result = Analyse::new(
         node.clone(),
-        Some(8),
-        Some(8),
-        None,
-        None,
-        None,
-        None,
-        None,
-        None,
-        None,
-        None,
-        None,
-        None,
-        None,
         None,
         None,
         None,
         None,
         None,
         None,
-        Some(1),
-        None,
         None,
         None,
         None,
@@ -664,14 +794,6 @@ fn analyse_new_divide_validation() {
         None,
         None,
         None,
-    );
-    assert!(result.is_ok(), "Should accept divide with 8x8 blocks");
-
-    // Test divide with invalid block size (4x4)
-    let result = Analyse::new(
-        node.clone(),
-        Some(4),
-        Some(4),
         None,
         None,
         None,
@@ -680,6 +802,8 @@ fn analyse_new_divide_validation() {
         None,
         None,
         None,
+        Some(0),
+        Some(0),
         None,
         None,
         None,
@@ -688,8 +812,8 @@ fn analyse_new_divide_validation() {
         None,
         None,
         None,
+        Some(4),
         None,
-        Some(1),
         None,
         None,
         None,
@@ -700,28 +824,13 @@ fn analyse_new_divide_validation() {
         None,
     );
     assert!(
-        result.is_err(),
-        "Should reject divide with blocks smaller than 8x8"
-    );
-    assert!(
-        result
-            .unwrap_err()
-            .to_string()
-            .contains("blksize and blksizev must be at least 8 when divide=True")
+        result.is_ok(),
+        "Should accept threads without meander or badrange"
     );
-}
 
-#[test]
-fn analyse_new_dct_satd_validation() {
-    let env = create_test_env(672, 2750, PresetFormat::YUV420P8, 10, 480, 16, 8, 7, 2, 16).unwrap();
-    let (node, _) = env.get_output(0).unwrap();
-
-    // Test SATD DCT modes with 16x2 blocks (should fail)
-    for dct_mode in [5, 6, 7, 8, 9, 10] {
+    for (badrange, meander) in [(None, Some(0)), (Some(0), None)] {
         let result = Analyse::new(
             node.clone(),
-            Some(16),
-            Some(2),
             None,
             None,
             None,
@@ -743,30 +852,40 @@ fn analyse_new_dct_satd_validation() {
             None,
             None,
             None,
+            badrange,
+            meander,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            Some(4),
+            None,
+            None,
+            None,
+            None,
+            None,
             None,
             None,
             None,
             None,
-            Some(dct_mode),
-        );
-        assert!(
-            result.is_err(),
-            "Should reject SATD DCT mode {} with 16x2 blocks",
-            dct_mode
         );
-        assert!(
-            result
-                .unwrap_err()
-                .to_string()
-                .contains("dct 5-10 cannot work with 16x2 blocks")
+        assert_eq!(
+            result.unwrap_err().downcast_ref::<ZooMvError>(),
+            Some(&ZooMvError::InvalidParam {
+                filter: "Analyse",
+                name: "threads",
+                value: 4,
+                expected: "1 unless meander=False and badrange=0",
+            })
         );
     }
 
-    // Test SATD DCT modes with compatible blocks (should succeed)
     let result = Analyse::new(
         node.clone(),
-        Some(8),
-        Some(8),
         None,
         None,
         None,
@@ -792,38 +911,41 @@ fn analyse_new_dct_satd_validation() {
         None,
         None,
         None,
-        Some(5),
-    );
-    assert!(
-        result.is_ok(),
-        "Should accept SATD DCT mode with compatible blocks"
-    );
-}
-
-#[test]
-fn analyse_new_truemotion_false() {
-    let env = create_test_env(672, 2750, PresetFormat::YUV420P8, 10, 480, 16, 8, 7, 2, 16).unwrap();
-    let (node, _) = env.get_output(0).unwrap();
-
-    let analyse = Analyse::new(
-        node,
-        None,
         None,
         None,
         None,
         None,
         None,
         None,
+        Some(0),
         None,
         None,
         None,
-        Some(0),
         None,
         None,
         None,
         None,
         None,
         None,
+    );
+    assert!(
+        result
+            .unwrap_err()
+            .to_string()
+            .contains("'threads' must be at least 1")
+    );
+}
+
+#[test]
+fn analyse_new_divide_validation() {
+    let env = create_test_env(672, 2750, PresetFormat::YUV420P8, 10, 480, 16, 8, 7, 2, 16).unwrap();
+    let (node, _) = env.get_output(0).unwrap();
+
+    // Test divide with valid block size (8x8)
+    let result = Analyse::new(
+        node.clone(),
+        Some(8),
+        Some(8),
         None,
         None,
         None,
@@ -835,37 +957,6 @@ fn analyse_new_truemotion_false() {
         None,
         None,
         None,
-    )
-    .unwrap();
-
-    assert!(!analyse.truemotion, "Truemotion should be false");
-    assert_eq!(
-        analyse.penalty_new, 0,
-        "Penalty_new should be 0 for truemotion=false"
-    );
-    assert_eq!(
-        analyse.penalty_level,
-        PenaltyScaling::None,
-        "Penalty_level should be None for truemotion=false"
-    );
-    assert!(
-        !analyse.global,
-        "Global should be false for truemotion=false"
-    );
-    assert_eq!(analyse.lambda, 0, "Lambda should be 0 for truemotion=false");
-    assert_eq!(
-        analyse.lambda_sad, 400,
-        "Lambda_sad should be 400 for truemotion=false"
-    );
-}
-
-#[test]
-fn analyse_new_backward_motion() {
-    let env = create_test_env(672, 2750, PresetFormat::YUV420P8, 10, 480, 16, 8, 7, 2, 16).unwrap();
-    let (node, _) = env.get_output(0).unwrap();
-
-    let analyse = Analyse::new(
-        node,
         None,
         None,
         None,
@@ -894,7 +985,360 @@ fn analyse_new_backward_motion() {
         None,
         None,
         None,
-    )
+    );
+    assert!(result.is_ok(), "Should accept divide with 8x8 blocks");
+
+    // Test divide with invalid block size (4x4)
+    let result = Analyse::new(
+        node.clone(),
+        Some(4),
+        Some(4),
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        Some(1),
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+    );
+    assert!(
+        result.is_err(),
+        "Should reject divide with blocks smaller than 8x8"
+    );
+    assert!(
+        result
+            .unwrap_err()
+            .to_string()
+            .contains("blksize and blksizev must be at least 8 when divide=True")
+    );
+}
+
+#[test]
+fn analyse_new_dct_satd_validation() {
+    let env = create_test_env(672, 2750, PresetFormat::YUV420P8, 10, 480, 16, 8, 7, 2, 16).unwrap();
+    let (node, _) = env.get_output(0).unwrap();
+
+    // Test SATD DCT modes with 16x2 blocks (should fail)
+    for dct_mode in [5, 6, 7, 8, 9, 10] {
+        let result = Analyse::new(
+            node.clone(),
+            Some(16),
+            Some(2),
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            Some(dct_mode),
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+        );
+        assert!(
+            result.is_err(),
+            "Should reject SATD DCT mode {} with 16x2 blocks",
+            dct_mode
+        );
+        assert!(
+            result
+                .unwrap_err()
+                .to_string()
+                .contains("dct 5-10 cannot work with 16x2 blocks")
+        );
+    }
+
+    // Test SATD DCT modes with compatible blocks (should succeed)
+    let result = Analyse::new(
+        node.clone(),
+        Some(8),
+        Some(8),
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        Some(5),
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+    );
+    assert!(
+        result.is_ok(),
+        "Should accept SATD DCT mode with compatible blocks"
+    );
+}
+
+#[test]
+fn fixed_dct_selects_the_integer_dct() {
+    let env = create_test_env(672, 2750, PresetFormat::YUV420P8, 10, 480, 16, 8, 7, 2, 16).unwrap();
+    let (node, _) = env.get_output(0).unwrap();
+    let size = NonZeroUsize::new(8).unwrap();
+    let bits = NonZeroU8::new(8).unwrap();
+
+    for (fixed_dct, kind) in [(None, DctKind::Float), (Some(1), DctKind::Integer)] {
+        let analyse = Analyse::new(
+            node.clone(),
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            Some(1),
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            fixed_dct,
+        )
+        .unwrap();
+        let dct = analyse.dct_pool.take(size, size, bits).unwrap();
+        assert_eq!(dct.kind(), kind, "fixed_dct {fixed_dct:?}");
+    }
+}
+
+#[test]
+fn analyse_new_truemotion_false() {
+    let env = create_test_env(672, 2750, PresetFormat::YUV420P8, 10, 480, 16, 8, 7, 2, 16).unwrap();
+    let (node, _) = env.get_output(0).unwrap();
+
+    let analyse = Analyse::new(
+        node,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        Some(0),
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+    )
+    .unwrap();
+
+    assert!(!analyse.truemotion, "Truemotion should be false");
+    assert_eq!(
+        analyse.penalty_new, 0,
+        "Penalty_new should be 0 for truemotion=false"
+    );
+    assert_eq!(
+        analyse.penalty_level,
+        PenaltyScaling::None,
+        "Penalty_level should be None for truemotion=false"
+    );
+    assert!(
+        !analyse.global,
+        "Global should be false for truemotion=false"
+    );
+    assert_eq!(analyse.lambda, 0, "Lambda should be 0 for truemotion=false");
+    assert_eq!(
+        analyse.lambda_sad, 400,
+        "Lambda_sad should be 400 for truemotion=false"
+    );
+}
+
+#[test]
+fn analyse_new_backward_motion() {
+    let env = create_test_env(672, 2750, PresetFormat::YUV420P8, 10, 480, 16, 8, 7, 2, 16).unwrap();
+    let (node, _) = env.get_output(0).unwrap();
+
+    let analyse = Analyse::new(
+        node,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        Some(1),
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+    )
     .unwrap();
 
     assert!(
@@ -943,6 +1387,19 @@ fn analyse_new_chroma_disabled() {
         None,
         None,
         None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
     )
     .unwrap();
 
@@ -966,7 +1423,8 @@ fn analyse_new_grayscale_format() {
 
     let analyse = Analyse::new(
         node, None, None, None, None, None, None, None, None, None, None, None, None, None, None,
-        None, None, None, None, None, None, None, None, None, None, None, None, None, None,
+        None, None, None, None, None, None, None, None, None, None, None, None, None, None, None,
+        None, None, None, None, None, None, None, None, None, None, None, None,
     )
     .unwrap();
 
@@ -1017,6 +1475,19 @@ fn analyse_new_custom_delta() {
         None,
         None,
         None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
     )
     .unwrap();
     assert_eq!(
@@ -1036,7 +1507,20 @@ fn analyse_new_custom_delta() {
         None,
         None,
         None,
-        Some(-15),
+        Some(-15),
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
         None,
         None,
         None,
@@ -1103,6 +1587,19 @@ fn analyse_new_divide_creates_extra_data() {
         None,
         None,
         None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
     )
     .unwrap();
 
@@ -1173,6 +1670,19 @@ fn analyse_new_search_param_adjustment() {
         None,
         None,
         None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
     )
     .unwrap();
     assert_eq!(
@@ -1182,7 +1692,7 @@ fn analyse_new_search_param_adjustment() {
 
     // Test non-Nstep search with param < 1 (should be adjusted to 1)
     let analyse = Analyse::new(
-        node,
+        node.clone(),
         None,
         None,
         None,
@@ -1211,12 +1721,73 @@ fn analyse_new_search_param_adjustment() {
         None,
         None,
         None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
     )
     .unwrap();
     assert_eq!(
         analyse.search_param, 1,
         "Zero search param should be adjusted to 1 for non-Nstep"
     );
+
+    // A logarithmic search with no steps would never leave its starting vector
+    let analyse = Analyse::new(
+        node,
+        None,
+        None,
+        None,
+        Some(2),
+        Some(0),
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+    )
+    .unwrap();
+    assert_eq!(analyse.search_param, 1);
 }
 
 #[test]
@@ -1255,6 +1826,19 @@ fn analyse_new_pel_search_auto() {
         None,
         None,
         None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
     )
     .unwrap();
     assert_eq!(
@@ -1293,6 +1877,19 @@ fn analyse_new_pel_search_auto() {
         None,
         None,
         None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
     )
     .unwrap();
     assert_eq!(
@@ -1338,6 +1935,19 @@ fn analyse_new_insufficient_super_levels() {
         None,
         None,
         None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
     );
 
     assert!(
@@ -1388,6 +1998,19 @@ fn analyse_new_colour_data_mismatch() {
         None,
         None,
         None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
     );
 
     assert!(
@@ -1401,3 +2024,473 @@ fn analyse_new_colour_data_mismatch() {
             .contains("super clip does not contain needed colour data")
     );
 }
+
+#[test]
+fn field_parity_from_tff() {
+    assert!(is_top_field_from_tff(true, 0));
+    assert!(!is_top_field_from_tff(true, 1));
+    assert!(is_top_field_from_tff(true, 4));
+    assert!(!is_top_field_from_tff(false, 0));
+    assert!(is_top_field_from_tff(false, 1));
+}
+
+#[test]
+fn field_parity_from_props() {
+    // `_Field` is used as is, even if `_FieldBased` disagrees
+    assert_eq!(top_field_from_props(Some(1), None, 0), Some(true));
+    assert_eq!(top_field_from_props(Some(0), None, 0), Some(false));
+    assert_eq!(top_field_from_props(Some(0), Some(2), 0), Some(false));
+    // Otherwise `_FieldBased` gives the parity of the first field
+    for n in 0..4 {
+        assert_eq!(
+            top_field_from_props(None, Some(2), n),
+            Some(is_top_field_from_tff(true, n))
+        );
+        assert_eq!(
+            top_field_from_props(None, Some(1), n),
+            Some(is_top_field_from_tff(false, n))
+        );
+    }
+    // Progressive or untagged frames have no field order
+    assert_eq!(top_field_from_props(None, Some(0), 1), None);
+    assert_eq!(top_field_from_props(None, None, 1), None);
+}
+
+#[test]
+fn field_shift_depends_on_tff() {
+    // Frame 3 searched against frame 2, as with delta=1 forward search
+    let (n, nref) = (3, 2);
+    let shift = |tff, pel| {
+        field_shift(
+            true,
+            pel,
+            1,
+            is_top_field_from_tff(tff, n),
+            is_top_field_from_tff(tff, nref),
+        )
+    };
+
+    // bottom field searched against top field
+    assert_eq!(shift(true, Subpel::Half), -1);
+    assert_eq!(shift(true, Subpel::Quarter), -2);
+    // top field searched against bottom field
+    assert_eq!(shift(false, Subpel::Half), 1);
+    assert_eq!(shift(false, Subpel::Quarter), 2);
+    assert_ne!(shift(true, Subpel::Half), shift(false, Subpel::Half));
+}
+
+#[test]
+fn field_shift_requires_fields_subpel_and_odd_delta() {
+    // not field-based
+    assert_eq!(field_shift(false, Subpel::Half, 1, true, false), 0);
+    // can't shift by half a pixel at full-pixel precision
+    assert_eq!(field_shift(true, Subpel::Full, 1, true, false), 0);
+    // same parity with an even delta
+    assert_eq!(field_shift(true, Subpel::Half, 2, true, true), 0);
+    // same parity
+    assert_eq!(field_shift(true, Subpel::Half, 1, false, false), 0);
+    assert_eq!(field_shift(true, Subpel::Half, 3, true, false), 1);
+}
+
+#[test]
+fn parse_penalty_range() {
+    assert_eq!(parse_penalty(None, 50, "pnew").unwrap(), 50);
+    assert_eq!(parse_penalty(Some(0), 50, "pnew").unwrap(), 0);
+    assert_eq!(parse_penalty(Some(256), 50, "pnew").unwrap(), 256);
+
+    for value in [-1, 257, i64::MIN, i64::MAX] {
+        let err = parse_penalty(Some(value), 0, "pglobal").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Analyse: pglobal must be between 0 and 256 (inclusive)."
+        );
+    }
+
+    assert_eq!(parse_penalty(Some(256), 0, "global_weight").unwrap(), 256);
+    let err = parse_penalty(Some(300), 0, "global_weight").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Analyse: global_weight must be between 0 and 256 (inclusive)."
+    );
+}
+
+#[test]
+fn plevel_selects_penalty_scaling() {
+    assert_eq!(PenaltyScaling::try_from(0).unwrap(), PenaltyScaling::None);
+    assert_eq!(PenaltyScaling::try_from(1).unwrap(), PenaltyScaling::Linear);
+    assert_eq!(
+        PenaltyScaling::try_from(2).unwrap(),
+        PenaltyScaling::Quadratic
+    );
+    assert!(PenaltyScaling::try_from(3).is_err());
+    assert!(PenaltyScaling::try_from(-1).is_err());
+}
+
+#[test]
+fn search_range_per_search_type() {
+    // Only the n-step search can do nothing at all
+    assert_eq!(search_range(SearchType::Nstep, -5, 64), 0);
+    assert_eq!(search_range(SearchType::Nstep, 0, 64), 0);
+    for search_type in [
+        SearchType::Onetime,
+        SearchType::Logarithmic,
+        SearchType::Exhaustive,
+        SearchType::Hex2,
+        SearchType::UnevenMultiHexagon,
+        SearchType::Horizontal,
+        SearchType::Vertical,
+    ] {
+        assert_eq!(search_range(search_type, -5, 64), 1, "{search_type:?}");
+        assert_eq!(search_range(search_type, 0, 64), 1, "{search_type:?}");
+        assert_eq!(search_range(search_type, 2, 64), 2, "{search_type:?}");
+    }
+
+    // Searching beyond the frame cannot find anything
+    assert_eq!(search_range(SearchType::Exhaustive, 1_000_000, 64), 64);
+    assert_eq!(search_range(SearchType::Hex2, i64::MAX, 64), 64);
+    assert_eq!(
+        search_range(SearchType::Logarithmic, i64::MAX, usize::MAX),
+        i32::MAX
+    );
+}
+
+#[test]
+fn motion_preset_matches_mvtools() {
+    let truemotion = MotionPreset::new(true, 16, 8);
+    assert_eq!(truemotion.lambda, 2000);
+    assert_eq!(truemotion.lambda_sad, 1200);
+    assert_eq!(truemotion.penalty_new, 50);
+    assert_eq!(truemotion.penalty_level, PenaltyScaling::Linear);
+    assert!(truemotion.global);
+
+    let speed = MotionPreset::new(false, 16, 8);
+    assert_eq!(speed.lambda, 0);
+    assert_eq!(speed.lambda_sad, 400);
+    assert_eq!(speed.penalty_new, 0);
+    assert_eq!(speed.penalty_level, PenaltyScaling::None);
+    assert!(!speed.global);
+}
+
+#[test]
+fn chroma_block_sizes_have_sad() {
+    // 444, 422, 440, and 420
+    let ratios = [(1, 1), (2, 1), (1, 2), (2, 2)];
+    let src = vec![10u8; 128 * 128];
+    let ref_ = vec![7u8; 128 * 128];
+    let pitch = NonZeroUsize::new(128).unwrap();
+    for (blk_size_x, blk_size_y) in BLOCK_SIZES {
+        for (x_ratio_uv, y_ratio_uv) in ratios {
+            let width = NonZeroUsize::new(blk_size_x / x_ratio_uv).unwrap();
+            let height = NonZeroUsize::new(blk_size_y / y_ratio_uv).unwrap();
+            let sad = get_sad(width, height, &src, pitch, &ref_, pitch);
+            assert_eq!(
+                sad,
+                3 * (width.get() * height.get()) as u64,
+                "{blk_size_x}x{blk_size_y} with ratio {x_ratio_uv}x{y_ratio_uv}"
+            );
+        }
+    }
+}
+
+#[test]
+fn reference_frame_directions() {
+    assert_eq!(reference_frame(10, 2, true), 12);
+    assert_eq!(reference_frame(10, 2, false), 8);
+    assert_eq!(reference_frame(1, 3, false), -2);
+    // Static mode always references the same frame
+    assert_eq!(reference_frame(10, 0, true), 0);
+    assert_eq!(reference_frame(10, -4, false), 4);
+}
+
+#[test]
+fn references_outside_the_clip_are_not_requested() {
+    let num_frames = 10;
+    // Forward vectors of the first frames and backward vectors of the last frames
+    // have no reference frame
+    assert_eq!(valid_reference(0, 1, false, num_frames), None);
+    assert_eq!(valid_reference(1, 2, false, num_frames), None);
+    assert_eq!(valid_reference(9, 1, true, num_frames), None);
+    assert_eq!(valid_reference(8, 2, true, num_frames), None);
+    assert_eq!(requested_frames(0, None).collect::<Vec<_>>(), [0]);
+    assert_eq!(requested_frames(9, None).collect::<Vec<_>>(), [9]);
+
+    // The opposite directions are inside the clip
+    assert_eq!(valid_reference(0, 1, true, num_frames), Some(1));
+    assert_eq!(valid_reference(9, 1, false, num_frames), Some(8));
+    assert_eq!(requested_frames(9, Some(8)).collect::<Vec<_>>(), [8, 9]);
+    assert_eq!(requested_frames(0, Some(1)).collect::<Vec<_>>(), [0, 1]);
+
+    // Static mode always has its reference frame
+    assert_eq!(valid_reference(9, -3, true, num_frames), Some(3));
+}
+
+#[test]
+fn multi_delta_frames_request_each_reference_once() {
+    // VapourSynth calls `get_frame_initial` once per output frame, so each output frame
+    // of a multi-vector clip requests its source frame and the reference of its own delta
+    let num_frames = 6;
+    let multi_delta = NonZeroUsize::new(3).unwrap();
+    for source in 0..num_frames {
+        let mut requested = Vec::new();
+        for n in source * 6..(source + 1) * 6 {
+            let (n, delta, is_backward) = multi_position(n, multi_delta);
+            assert_eq!(n, source);
+            let nref = valid_reference(n, delta, is_backward, num_frames);
+            let frames = requested_frames(n, nref).collect::<Vec<_>>();
+
+            let mut expected = vec![n];
+            expected.extend(nref);
+            expected.sort_unstable();
+            assert_eq!(
+                frames, expected,
+                "source {source} delta {delta} backward {is_backward}"
+            );
+            requested.extend(frames.into_iter().filter(|&frame| frame != n));
+        }
+
+        // Across the group, every reference inside the clip is requested exactly once
+        requested.sort_unstable();
+        let expected = (1..=3)
+            .flat_map(|delta| [source.checked_sub(delta), Some(source + delta)])
+            .flatten()
+            .filter(|&frame| frame < num_frames)
+            .collect::<std::collections::BTreeSet<_>>();
+        assert_eq!(
+            requested,
+            expected.into_iter().collect::<Vec<_>>(),
+            "source {source}"
+        );
+    }
+
+    // `isb=2` requests both neighbors of each source frame the same way
+    for n in 0..2 * num_frames {
+        let (source, delta, is_backward) = both_position(n, 1);
+        let nref = valid_reference(source, delta, is_backward, num_frames);
+        let frames = requested_frames(source, nref).collect::<Vec<_>>();
+        assert!(frames.contains(&source));
+        assert!(frames.iter().all(|&frame| frame < num_frames));
+        assert!(
+            frames.windows(2).all(|pair| pair[0] < pair[1]),
+            "frame {n}: {frames:?}"
+        );
+    }
+}
+
+#[test]
+fn multi_position_interleaves_directions() {
+    let multi_delta = NonZeroUsize::new(3).unwrap();
+    let expected = [
+        (0, 1, true),
+        (0, 1, false),
+        (0, 2, true),
+        (0, 2, false),
+        (0, 3, true),
+        (0, 3, false),
+        (1, 1, true),
+        (1, 1, false),
+    ];
+    for (n, position) in expected.into_iter().enumerate() {
+        assert_eq!(multi_position(n, multi_delta), position, "frame {n}");
+    }
+    assert_eq!(multi_position(6 * 7 + 5, multi_delta), (7, 3, false));
+
+    // A single delta only alternates the direction
+    let multi_delta = NonZeroUsize::MIN;
+    assert_eq!(multi_position(4, multi_delta), (2, 1, true));
+    assert_eq!(multi_position(5, multi_delta), (2, 1, false));
+}
+
+#[test]
+fn both_position_matches_single_delta_multi() {
+    for n in 0..8 {
+        assert_eq!(
+            both_position(n, 1),
+            multi_position(n, NonZeroUsize::MIN),
+            "frame {n}"
+        );
+    }
+    // The delta is kept for every frame instead of counting up from 1
+    assert_eq!(both_position(6, 3), (3, 3, true));
+    assert_eq!(both_position(7, 3), (3, 3, false));
+}
+
+#[test]
+fn unsupported_block_size_is_rejected() {
+    assert!(!supported_block_sizes().contains(&(24, 24)));
+    assert_eq!(
+        validate_block_size(24, 24),
+        Err(ZooMvError::InvalidParam {
+            filter: "Analyse",
+            name: "blksize",
+            value: 24,
+            expected: "4, 8, 16, 32, 64 or 128",
+        })
+    );
+
+    for &(blk_size_x, blk_size_y) in supported_block_sizes() {
+        assert!(validate_block_size(blk_size_x, blk_size_y).is_ok());
+    }
+    // The sizes are not interchangeable
+    assert_eq!(
+        validate_block_size(4, 8),
+        Err(ZooMvError::InvalidParam {
+            filter: "Analyse",
+            name: "blksizev",
+            value: 8,
+            expected: "4 when blksize=4",
+        })
+    );
+}
+
+#[test]
+fn block_size_errors_list_the_supported_sizes() {
+    let mut widths: Vec<_> = supported_block_sizes().iter().map(|&(x, _)| x).collect();
+    widths.dedup();
+    let widths: Vec<_> = widths.iter().map(usize::to_string).collect();
+    assert_eq!(
+        Some(format!(
+            "{} or {}",
+            widths[..widths.len() - 1].join(", "),
+            widths[widths.len() - 1]
+        )),
+        match validate_block_size(3, 3) {
+            Err(ZooMvError::InvalidParam { expected, .. }) => Some(expected.to_string()),
+            _ => None,
+        }
+    );
+
+    for width in [4, 8, 16, 32, 64, 128] {
+        let heights: Vec<_> = supported_block_sizes()
+            .iter()
+            .filter(|&&(x, _)| x == width)
+            .map(|(_, y)| y.to_string())
+            .collect();
+        let expected = if let [height] = heights.as_slice() {
+            height.clone()
+        } else {
+            format!(
+                "{} or {}",
+                heights[..heights.len() - 1].join(", "),
+                heights[heights.len() - 1]
+            )
+        };
+        assert_eq!(
+            supported_block_heights(width),
+            Some(format!("{expected} when blksize={width}").as_str()),
+            "blksize {width}"
+        );
+    }
+}
+
+#[test]
+fn overlap_is_limited_by_block_size_and_subsampling() {
+    // Up to half of the block size, independently in both dimensions
+    assert!(validate_overlap(16, 8, 8, 4, 1, 1, false).is_ok());
+    assert!(validate_overlap(16, 8, 4, 0, 1, 1, false).is_ok());
+    assert!(validate_overlap(16, 8, 0, 2, 1, 1, false).is_ok());
+    for (overlap_x, overlap_y) in [(10, 0), (0, 6), (16, 8)] {
+        let err = validate_overlap(16, 8, overlap_x, overlap_y, 0, 0, false).unwrap_err();
+        assert!(
+            err.to_string().contains("overlap must be at most half"),
+            "{overlap_x}x{overlap_y}: {err}"
+        );
+    }
+
+    // Odd overlaps would split a chroma sample between blocks
+    assert!(validate_overlap(16, 16, 3, 0, 0, 0, false).is_ok());
+    assert!(validate_overlap(16, 16, 3, 0, 1, 0, false).is_err());
+    assert!(validate_overlap(16, 16, 0, 3, 0, 1, false).is_err());
+    assert!(validate_overlap(16, 16, 3, 0, 0, 1, false).is_ok());
+
+    // Divided blocks need the overlap of their halves to stay on chroma samples
+    assert!(validate_overlap(16, 16, 4, 4, 1, 1, true).is_ok());
+    assert!(validate_overlap(16, 16, 2, 0, 0, 0, true).is_ok());
+    assert!(validate_overlap(16, 16, 2, 0, 1, 0, true).is_err());
+    assert!(validate_overlap(16, 16, 0, 6, 0, 1, true).is_err());
+}
+
+#[test]
+fn odd_chroma_step_is_rejected() {
+    // 4:2:0 halves the step of 16x16 blocks with an overlap of 3 from 13 to 6.5
+    let err = validate_overlap(16, 16, 3, 0, 1, 1, false).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Analyse: blksize - overlap (13) must be a multiple of 2, and blksizev - overlapv (16) a \
+         multiple of 2, so that the chroma blocks of the super clip line up with the luma blocks."
+    );
+    let err = validate_overlap(8, 8, 0, 1, 1, 1, false).unwrap_err();
+    assert!(err.to_string().contains("blksizev - overlapv (7)"), "{err}");
+
+    // 4:2:2 only subsamples horizontally
+    assert!(validate_overlap(8, 8, 0, 1, 1, 0, false).is_ok());
+    assert!(validate_overlap(8, 8, 2, 2, 1, 1, false).is_ok());
+}
+
+#[test]
+fn overlapped_block_counts_follow_the_step() {
+    let blk_size_x = NonZeroUsize::new(16).unwrap();
+    let blk_size_y = NonZeroUsize::new(8).unwrap();
+    // 16x8 blocks over 640x360 step by the block size minus the overlap in each dimension
+    for (overlap_x, overlap_y, expected) in [
+        (0, 0, (40, 45)),
+        (8, 0, (79, 45)),
+        (0, 4, (40, 89)),
+        (8, 4, (79, 89)),
+        (4, 2, (53, 59)),
+    ] {
+        validate_overlap(16, 8, overlap_x, overlap_y, 1, 1, false).unwrap();
+        let (blk_x, blk_y) =
+            level_blocks(640, 360, 0, blk_size_x, blk_size_y, overlap_x, overlap_y).unwrap();
+        assert_eq!(
+            (blk_x.get(), blk_y.get()),
+            expected,
+            "overlap {overlap_x}x{overlap_y}"
+        );
+        // The blocks never reach past the frame
+        assert!((16 - overlap_x) * blk_x.get() + overlap_x <= 640);
+        assert!((8 - overlap_y) * blk_y.get() + overlap_y <= 360);
+    }
+}
+
+#[test]
+fn analysis_region_defaults_to_the_rest_of_the_clip() {
+    let size = |width, height| {
+        (
+            NonZeroUsize::new(width).unwrap(),
+            NonZeroUsize::new(height).unwrap(),
+        )
+    };
+    let (width, height) = size(128, 96);
+    let ratio = NonZeroU8::new(2).unwrap();
+    let region = |left, top, cropw, croph| {
+        analysis_region(width, height, ratio, ratio, left, top, cropw, croph)
+    };
+
+    assert_eq!(region(None, None, None, None).unwrap(), size(128, 96));
+    assert_eq!(
+        region(Some(32), Some(16), None, None).unwrap(),
+        size(96, 80)
+    );
+    assert_eq!(
+        region(Some(32), Some(16), Some(64), Some(48)).unwrap(),
+        size(64, 48)
+    );
+    assert_eq!(
+        region(Some(32), Some(16), Some(0), Some(80)).unwrap(),
+        size(96, 80)
+    );
+
+    let invalid_name = |left, top, cropw, croph| match region(left, top, cropw, croph)
+        .unwrap_err()
+        .downcast_ref::<ZooMvError>()
+    {
+        Some(&ZooMvError::InvalidParam { name, .. }) => name,
+        other => panic!("unexpected error: {other:?}"),
+    };
+    // Not on a chroma sample
+    assert_eq!(invalid_name(Some(3), None, None, None), "left");
+    assert_eq!(invalid_name(None, Some(-2), None, None), "top");
+    assert_eq!(invalid_name(Some(128), None, None, None), "left");
+    assert_eq!(invalid_name(Some(32), None, Some(97), None), "cropw");
+    assert_eq!(invalid_name(None, Some(16), None, Some(-8)), "croph");
+}
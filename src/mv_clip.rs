@@ -0,0 +1,414 @@
+//! Access to the motion vectors produced by `Analyse`, for use by client filters
+
+#[cfg(test)]
+mod tests;
+
+use anyhow::{Result, anyhow, bail};
+use vapoursynth::{frame::FrameRef, node::Node};
+
+use crate::{
+    mv::{MV_SIZE, MotionVector},
+    mv_analyse::{MVAnalysisData, PROP_DIVIDE, PROP_MVANALYSISDATA, PROP_VALID, PROP_VECTORS},
+    params::DivideMode,
+    plane_of_blocks::MvsOutput,
+};
+
+/// A clip of motion vectors, as output by `Analyse`.
+///
+/// Equivalent to mvtools' `MVClipDicks`.
+#[derive(Debug)]
+pub struct MVClip<'core> {
+    node: Node<'core>,
+    analysis_data: MVAnalysisData,
+    /// Whether the finest level holds the subblocks written by `divide`,
+    /// in which case `analysis_data` describes them instead of the searched blocks
+    divide: DivideMode,
+}
+
+impl<'core> MVClip<'core> {
+    /// Reads the analysis data from the first frame of the vectors clip.
+    ///
+    /// Clips without the `Analyse_divide` property, such as those from mvtools,
+    /// are read as if they were not divided.
+    ///
+    /// `filter_name` is used as the prefix for error messages.
+    pub fn new(node: Node<'core>, filter_name: &str) -> Result<Self> {
+        let first = node.get_frame(0).map_err(|e| {
+            anyhow!(
+                "{filter_name}: failed to retrieve first frame from vectors clip. Error message: \
+                 {e}"
+            )
+        })?;
+        let props = first.props();
+        let data = props.get_data(PROP_MVANALYSISDATA).map_err(|_| {
+            anyhow!(
+                "{filter_name}: required properties not found in first frame of vectors clip. \
+                 Maybe clip didn't come from mv.Analyse? Was the first frame trimmed away?"
+            )
+        })?;
+        let analysis_data = MVAnalysisData::from_bytes(data)
+            .map_err(|e| anyhow!("{filter_name}: vectors clip has invalid analysis data: {e}"))?;
+        let divide = props
+            .get_int(PROP_DIVIDE)
+            .map_or(Ok(DivideMode::None), DivideMode::try_from)
+            .map_err(|e| anyhow!("{filter_name}: vectors clip has an invalid divide mode: {e}"))?;
+        if divide != DivideMode::None {
+            analysis_data.undivided().map_err(|e| {
+                anyhow!("{filter_name}: vectors clip has invalid analysis data: {e}")
+            })?;
+        }
+
+        Ok(Self {
+            node,
+            analysis_data,
+            divide,
+        })
+    }
+
+    #[must_use]
+    pub fn node(&self) -> &Node<'core> {
+        &self.node
+    }
+
+    /// The analysis data of the finest vectors,
+    /// which are the subdivided blocks if the clip was produced with `divide`.
+    #[must_use]
+    pub(crate) fn analysis_data(&self) -> &MVAnalysisData {
+        &self.analysis_data
+    }
+
+    /// The analysis data of the searched blocks, before any `divide` subdivision.
+    #[must_use]
+    pub(crate) fn base_analysis_data(&self) -> MVAnalysisData {
+        if self.divide == DivideMode::None {
+            self.analysis_data
+        } else {
+            self.analysis_data
+                .undivided()
+                .expect("divided analysis data is validated when the clip is created")
+        }
+    }
+
+    /// How the blocks of the finest level were subdivided by `Analyse`.
+    #[must_use]
+    pub fn divide(&self) -> DivideMode {
+        self.divide
+    }
+
+    /// Reads the finest level vectors from a frame of the vectors clip,
+    /// in row-major block order.
+    ///
+    /// If the clip was produced with `divide`, these are the subdivided blocks.
+    ///
+    /// Returns `None` if the vectors are not valid, e.g. because the reference frame
+    /// is before the start or past the end of the clip, as marked by the `Analyse_valid`
+    /// property or, for clips from mvtools, by the vectors themselves.
+    pub fn finest_vectors(&self, frame: &FrameRef<'core>) -> Result<Option<Vec<MotionVector>>> {
+        read_finest_level(frame, &self.analysis_data)
+    }
+
+    /// Reads the vectors of the searched blocks from a frame of the vectors clip,
+    /// in row-major block order, skipping any subdivided blocks written by `divide`.
+    ///
+    /// Returns `None` if the vectors are not valid.
+    pub fn base_vectors(&self, frame: &FrameRef<'core>) -> Result<Option<Vec<MotionVector>>> {
+        read_finest_level(frame, &self.base_analysis_data())
+    }
+}
+
+/// Reads the vectors of the finest level described by `analysis_data`
+/// from a frame of a vectors clip, in row-major block order.
+///
+/// Returns `None` if the vectors are not valid.
+fn read_finest_level(
+    frame: &FrameRef<'_>,
+    analysis_data: &MVAnalysisData,
+) -> Result<Option<Vec<MotionVector>>> {
+    let props = frame.props();
+    if is_marked_invalid(props.get_int(PROP_VALID).ok()) {
+        return Ok(None);
+    }
+    let data = props
+        .get_data(PROP_VECTORS)
+        .map_err(|_| anyhow!("vectors property not found in frame"))?;
+    let vectors = parse_vectors(data, analysis_data)?;
+    if !vectors.validity {
+        return Ok(None);
+    }
+    // The levels go from coarsest to finest, and there is at least one
+    Ok(level_vectors(&vectors, analysis_data)?.pop())
+}
+
+/// Whether the `Analyse_valid` property of a vectors frame, if it has one,
+/// says that its vectors must not be used.
+///
+/// Frames without the property, such as those from mvtools, only store the validity
+/// in the vectors.
+fn is_marked_invalid(valid: Option<i64>) -> bool {
+    valid == Some(0)
+}
+
+/// Size of the header before the first level of a vectors buffer.
+const VECTORS_HEADER_SIZE: usize = 2 * size_of::<i32>();
+/// Size of a vector with a 32-bit SAD, which this layout does not support.
+const MV_SIZE_32BIT_SAD: usize = 2 * size_of::<i32>() + size_of::<i32>();
+
+/// Parses a complete `MVTools_vectors` frame property into an [`MvsOutput`],
+/// checking that its layout matches `analysis_data`.
+///
+/// This reads the vectors of `Analyse` as well as those of mvtools' `Analyse`, which share
+/// this layout, so that vectors from either plugin can be used by the filters of the other.
+/// All values are little-endian:
+/// - `i32` size of the whole buffer in bytes, including this header
+/// - `i32` validity, 0 if the vectors must not be used, e.g. at the ends of the clip
+/// - for each of the `level_count` levels, from coarsest to finest:
+///   - `u32` size of the level in bytes, including this prefix
+///   - the vectors of each block of the level, in row-major order, as an `i32` x and `i32` y
+///     in `pel` units of the level, followed by an `i64` SAD
+///
+/// The subblocks written by `divide` follow the finest level in the same form, and are
+/// described by an analysis data with one more level.
+///
+/// The layout carries no version of its own, so it is gated on the sizes of its parts:
+/// the analysis data must be the 18 `i32`s read by [`MVAnalysisData::from_bytes`],
+/// and each vector must take [`MV_SIZE`] bytes. Buffers with 32-bit SADs are rejected.
+pub(crate) fn parse_vectors(data: &[u8], analysis_data: &MVAnalysisData) -> Result<MvsOutput> {
+    let (validity, _) = split_levels(data, analysis_data)?;
+    Ok(MvsOutput {
+        validity,
+        block_data: data.into(),
+    })
+}
+
+/// Magic bytes at the start of a [`StoredVectors`] blob.
+const STORED_VECTORS_MAGIC: [u8; 4] = *b"ZMVS";
+/// Version of the [`StoredVectors`] blobs written by this crate.
+///
+/// - 1: the analysis data and the vectors, with no flags
+/// - 2: the flags record the `divide` mode of the vectors
+pub(crate) const STORED_VECTORS_VERSION: u16 = 2;
+/// Size of the magic, version and flags before the analysis data of a [`StoredVectors`] blob.
+const STORED_VECTORS_HEADER_SIZE: usize =
+    STORED_VECTORS_MAGIC.len() + size_of::<u16>() + size_of::<u16>();
+/// Bits of the flags of a [`StoredVectors`] blob holding the `divide` mode, since version 2.
+const STORED_VECTORS_DIVIDE_MASK: u16 = 0b11;
+
+/// The vectors of one frame together with the analysis data describing them,
+/// serialized so that they can be stored and read back by later versions of this crate.
+///
+/// Unlike the frame properties shared with mvtools, the blob starts with a header,
+/// so that a reader never misinterprets a layout it does not know. All values are little-endian:
+/// - the magic bytes `ZMVS`
+/// - `u16` version, see [`STORED_VECTORS_VERSION`]
+/// - `u16` flags, whose meaning depends on the version
+/// - the analysis data, as read by [`MVAnalysisData::from_bytes`]
+/// - the vectors, laid out as described in [`parse_vectors`]
+///
+/// Blobs from older versions are upgraded when read,
+/// and blobs from newer versions are rejected.
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+pub(crate) struct StoredVectors {
+    /// The analysis data of the finest vectors, as in [`MVClip::analysis_data`]
+    pub analysis_data: MVAnalysisData,
+    pub divide: DivideMode,
+    pub vectors: MvsOutput,
+}
+
+#[allow(dead_code)]
+impl StoredVectors {
+    /// Serializes the vectors with the current [`STORED_VECTORS_VERSION`].
+    #[must_use]
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(
+            STORED_VECTORS_HEADER_SIZE + MVAnalysisData::SIZE + self.vectors.block_data.len(),
+        );
+        bytes.extend_from_slice(&STORED_VECTORS_MAGIC);
+        bytes.extend_from_slice(&STORED_VECTORS_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&(self.divide as u16).to_le_bytes());
+        bytes.extend_from_slice(&self.analysis_data.bytes());
+        bytes.extend_from_slice(&self.vectors.block_data);
+        bytes
+    }
+
+    /// Parses a blob written by [`Self::to_bytes`] of this or an older version of this crate.
+    ///
+    /// Version 1 blobs did not record the `divide` mode, and are read as undivided.
+    pub(crate) fn from_bytes(data: &[u8]) -> Result<Self> {
+        let header = data
+            .get(..STORED_VECTORS_HEADER_SIZE)
+            .ok_or_else(|| anyhow!("stored vectors are too short"))?;
+        if header[..4] != STORED_VECTORS_MAGIC {
+            bail!("stored vectors do not start with the expected magic bytes");
+        }
+        let version = u16::from_le_bytes([header[4], header[5]]);
+        let flags = u16::from_le_bytes([header[6], header[7]]);
+        let divide = match version {
+            1 if flags == 0 => DivideMode::None,
+            2 if flags & !STORED_VECTORS_DIVIDE_MASK == 0 => {
+                DivideMode::try_from(i64::from(flags & STORED_VECTORS_DIVIDE_MASK))?
+            }
+            1 | 2 => bail!("stored vectors of version {version} have unknown flags {flags:#06x}"),
+            0 => bail!("stored vectors have an invalid version 0"),
+            _ => {
+                bail!(
+                    "stored vectors have version {version}, but this version of zoomvtools only \
+                     reads up to version {STORED_VECTORS_VERSION}"
+                )
+            }
+        };
+
+        let body = &data[STORED_VECTORS_HEADER_SIZE..];
+        let analysis_data = MVAnalysisData::from_bytes(
+            body.get(..MVAnalysisData::SIZE)
+                .ok_or_else(|| anyhow!("stored vectors are too short"))?,
+        )?;
+        if divide != DivideMode::None {
+            analysis_data.undivided()?;
+        }
+        let vectors = parse_vectors(&body[MVAnalysisData::SIZE..], &analysis_data)?;
+
+        Ok(Self {
+            analysis_data,
+            divide,
+            vectors,
+        })
+    }
+}
+
+/// Reads the vectors of each level of `vectors`, from coarsest to finest,
+/// in row-major block order.
+pub(crate) fn level_vectors(
+    vectors: &MvsOutput,
+    analysis_data: &MVAnalysisData,
+) -> Result<Vec<Vec<MotionVector>>> {
+    let (_, levels) = split_levels(&vectors.block_data, analysis_data)?;
+    Ok(levels.into_iter().map(read_vectors).collect())
+}
+
+/// Extracts the finest level vectors from the `MVTools_vectors` frame property data,
+/// laid out as described in [`parse_vectors`].
+pub(crate) fn parse_finest_vectors(
+    data: &[u8],
+    analysis_data: &MVAnalysisData,
+) -> Result<Option<Vec<MotionVector>>> {
+    let (validity, levels) = split_levels(data, analysis_data)?;
+    Ok(validity.then(|| read_vectors(levels.last().expect("at least one level"))))
+}
+
+/// Reads the SAD of every finest level block from a vectors buffer, in row-major block order.
+///
+/// Returns `None` if the vectors are not valid, e.g. for frames without a reference frame.
+pub(crate) fn finest_sads(data: &[u8], analysis_data: &MVAnalysisData) -> Result<Option<Vec<i64>>> {
+    Ok(parse_finest_vectors(data, analysis_data)?
+        .map(|vectors| vectors.iter().map(|mv| mv.sad).collect()))
+}
+
+/// Reads the vector of every finest level block from a vectors buffer in full pixels,
+/// in row-major block order, as `[x0, y0, x1, y1, ...]`.
+///
+/// Returns `None` if the vectors are not valid, e.g. for frames without a reference frame.
+pub(crate) fn finest_motion(
+    data: &[u8],
+    analysis_data: &MVAnalysisData,
+) -> Result<Option<Vec<f64>>> {
+    Ok(parse_finest_vectors(data, analysis_data)?.map(|vectors| {
+        vectors
+            .iter()
+            .flat_map(|mv| {
+                let (x, y) = mv.to_pixels(analysis_data.pel);
+                [x, y]
+            })
+            .collect()
+    }))
+}
+
+/// Default `thscd1`: the SAD of an 8x8 block with 8-bit samples above which the block has changed.
+pub(crate) const DEFAULT_THSCD1: u64 = 400;
+/// Default `thscd2`: how many out of every 256 blocks must change for a scene change.
+pub(crate) const DEFAULT_THSCD2: u8 = 130;
+
+/// Scales `thscd1`, given for an 8x8 block with 8-bit samples,
+/// to the blocks and bit depth described by `analysis_data`.
+#[must_use]
+pub(crate) fn scale_thscd1(thscd1: u64, analysis_data: &MVAnalysisData) -> u64 {
+    let area = (analysis_data.blk_size_x.get() * analysis_data.blk_size_y.get()) as u64;
+    (thscd1 * area / 64) << (analysis_data.bits_per_sample.get() - 8)
+}
+
+/// Reports whether `vectors` cross a scene change, in which case they must not be used.
+///
+/// A block has changed if its SAD is above `thscd1`, which must already be scaled
+/// with [`scale_thscd1`]. It is a scene change if more than `thscd2` out of every 256 blocks
+/// have changed, so `thscd2 = 255` needs every block of a frame with 256 blocks to change.
+#[must_use]
+pub(crate) fn detect_scene_change(vectors: &[MotionVector], thscd1: u64, thscd2: u8) -> bool {
+    let changed = vectors
+        .iter()
+        .filter(|mv| mv.sad.max(0) as u64 > thscd1)
+        .count();
+    changed > usize::from(thscd2) * vectors.len() / 256
+}
+
+/// Checks the layout of a vectors buffer and splits it into the validity
+/// and the block data of each level, from coarsest to finest.
+fn split_levels<'a>(
+    data: &'a [u8],
+    analysis_data: &MVAnalysisData,
+) -> Result<(bool, Vec<&'a [u8]>)> {
+    let read_u32 = |start: usize| {
+        data.get(start..start + size_of::<u32>())
+            .map(|bytes| u32::from_le_bytes(bytes.try_into().expect("slice with incorrect length")))
+            .ok_or_else(|| anyhow!("vectors data is too short"))
+    };
+    let total_size = read_u32(0)? as usize;
+    if total_size != data.len() {
+        bail!(
+            "vectors data has {} bytes, but its header says {total_size}",
+            data.len()
+        );
+    }
+    let validity = read_u32(size_of::<i32>())? != 0;
+
+    let mut start = VECTORS_HEADER_SIZE;
+    let mut levels = Vec::with_capacity(analysis_data.level_count);
+    for _ in 0..analysis_data.level_count {
+        let size = read_u32(start)? as usize;
+        if size < size_of::<u32>() || start + size > data.len() {
+            bail!("vectors data has an invalid level size");
+        }
+        let level = &data[start + size_of::<u32>()..start + size];
+        if !level.len().is_multiple_of(MV_SIZE) {
+            if level.len().is_multiple_of(MV_SIZE_32BIT_SAD) {
+                bail!("vectors data appears to have 32-bit SADs, only 64-bit SADs are supported");
+            }
+            bail!("vectors data has an invalid level size");
+        }
+        levels.push(level);
+        start += size;
+    }
+    let finest = levels
+        .last()
+        .ok_or_else(|| anyhow!("vectors data has no levels"))?;
+
+    let blk_count = analysis_data.blk_x.get() * analysis_data.blk_y.get();
+    if finest.len() == blk_count * MV_SIZE_32BIT_SAD {
+        bail!("vectors data appears to have 32-bit SADs, only 64-bit SADs are supported");
+    }
+    if finest.len() != blk_count * MV_SIZE {
+        bail!(
+            "vectors data has {} bytes in the finest level, expected {}",
+            finest.len(),
+            blk_count * MV_SIZE
+        );
+    }
+
+    Ok((validity, levels))
+}
+
+fn read_vectors(level: &[u8]) -> Vec<MotionVector> {
+    level
+        .chunks_exact(MV_SIZE)
+        .map(MotionVector::read_from)
+        .collect()
+}
@@ -0,0 +1,521 @@
+#![allow(clippy::unwrap_used, reason = "allow in test files")]
+#![allow(clippy::undocumented_unsafe_blocks, reason = "allow in test files")]
+
+use std::{
+    fmt::Write,
+    num::{NonZeroU8, NonZeroUsize},
+};
+
+use vapoursynth::prelude::Environment;
+
+use super::*;
+use crate::{
+    group_of_planes::{GroupOfPlanes, level_blocks},
+    params::{DivideMode, MotionFlags, Subpel},
+};
+
+fn analysis_data(blk_x: usize, blk_y: usize, level_count: usize) -> MVAnalysisData {
+    MVAnalysisData {
+        blk_size_x: NonZeroUsize::new(8).unwrap(),
+        blk_size_y: NonZeroUsize::new(8).unwrap(),
+        pel: Subpel::Half,
+        level_count,
+        delta_frame: 1,
+        is_backward: false,
+        motion_flags: MotionFlags::USE_CHROMA_MOTION,
+        width: NonZeroUsize::new(blk_x * 8).unwrap(),
+        height: NonZeroUsize::new(blk_y * 8).unwrap(),
+        overlap_x: 0,
+        overlap_y: 0,
+        blk_x: NonZeroUsize::new(blk_x).unwrap(),
+        blk_y: NonZeroUsize::new(blk_y).unwrap(),
+        bits_per_sample: NonZeroU8::new(8).unwrap(),
+        y_ratio_uv: NonZeroU8::new(2).unwrap(),
+        x_ratio_uv: NonZeroU8::new(2).unwrap(),
+        h_padding: 16,
+        v_padding: 16,
+    }
+}
+
+fn group_of_planes(data: &MVAnalysisData, divide: DivideMode) -> GroupOfPlanes<u8> {
+    GroupOfPlanes::new(
+        data.blk_size_x,
+        data.blk_size_y,
+        data.level_count,
+        data.pel,
+        data.motion_flags,
+        data.overlap_x,
+        data.overlap_y,
+        data.blk_x,
+        data.blk_y,
+        data.x_ratio_uv,
+        data.y_ratio_uv,
+        divide,
+        data.bits_per_sample,
+    )
+    .unwrap()
+}
+
+#[test]
+fn analysis_data_round_trip() {
+    let data = analysis_data(10, 6, 3);
+    let parsed = MVAnalysisData::from_bytes(&data.bytes()).unwrap();
+    assert_eq!(parsed.bytes(), data.bytes());
+    assert_eq!(parsed.pel, Subpel::Half);
+    assert_eq!(parsed.blk_x.get(), 10);
+    assert_eq!(parsed.blk_y.get(), 6);
+}
+
+#[test]
+fn analysis_data_rejects_wrong_size() {
+    let data = analysis_data(10, 6, 3);
+    let bytes = data.bytes();
+    assert!(MVAnalysisData::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+    assert!(MVAnalysisData::from_bytes(&[]).is_err());
+}
+
+#[test]
+fn invalid_vectors_are_none() {
+    let data = analysis_data(10, 6, 3);
+    let out = group_of_planes(&data, DivideMode::None).write_default_to_array();
+    assert!(!out.validity);
+    assert!(
+        parse_finest_vectors(&out.block_data, &data)
+            .unwrap()
+            .is_none()
+    );
+}
+
+#[test]
+fn finds_finest_level() {
+    let data = analysis_data(10, 6, 3);
+    let mut out = group_of_planes(&data, DivideMode::None).write_default_to_array();
+    // mark the data as valid, and the last block of the finest level as recognizable
+    out.block_data[4..8].copy_from_slice(&1i32.to_le_bytes());
+    let last = out.block_data.len() - MV_SIZE;
+    MotionVector {
+        x: 3,
+        y: -5,
+        sad: 42,
+    }
+    .write_to(&mut out.block_data[last..]);
+
+    let vectors = parse_finest_vectors(&out.block_data, &data)
+        .unwrap()
+        .unwrap();
+    assert_eq!(vectors.len(), 60);
+    let last = vectors.last().unwrap();
+    assert_eq!((last.x, last.y, last.sad), (3, -5, 42));
+}
+
+#[test]
+fn finest_sads_match_block_vectors() {
+    let data = analysis_data(10, 6, 3);
+    let mut out = group_of_planes(&data, DivideMode::None).write_default_to_array();
+    assert_eq!(finest_sads(&out.block_data, &data).unwrap(), None);
+
+    // A synthetic field where every block has a different SAD
+    out.block_data[4..8].copy_from_slice(&1i32.to_le_bytes());
+    let blocks = data.blk_x.get() * data.blk_y.get();
+    let finest = out.block_data.len() - blocks * MV_SIZE;
+    for (i, bytes) in out.block_data[finest..]
+        .chunks_exact_mut(MV_SIZE)
+        .enumerate()
+    {
+        let mv = MotionVector {
+            x: i as i32 % 5,
+            y: -(i as i32 % 3),
+            sad: i as i64 * 37,
+        };
+        mv.write_to(bytes);
+    }
+
+    let vectors = parse_finest_vectors(&out.block_data, &data)
+        .unwrap()
+        .unwrap();
+    let sads = finest_sads(&out.block_data, &data).unwrap().unwrap();
+    assert_eq!(sads.len(), blocks);
+    for (i, (sad, mv)) in sads.iter().zip(&vectors).enumerate() {
+        assert_eq!(*sad, mv.sad, "block {i}");
+        assert_eq!(*sad, i as i64 * 37, "block {i}");
+    }
+}
+
+#[test]
+fn finest_motion_is_in_full_pixels() {
+    let mut data = analysis_data(10, 6, 3);
+    let mut out = group_of_planes(&data, DivideMode::None).write_default_to_array();
+    assert_eq!(finest_motion(&out.block_data, &data).unwrap(), None);
+
+    out.block_data[4..8].copy_from_slice(&1i32.to_le_bytes());
+    let blocks = data.blk_x.get() * data.blk_y.get();
+    let finest = out.block_data.len() - blocks * MV_SIZE;
+    for (i, bytes) in out.block_data[finest..]
+        .chunks_exact_mut(MV_SIZE)
+        .enumerate()
+    {
+        let mv = MotionVector {
+            x: i as i32 % 7 - 3,
+            y: -(i as i32 % 5),
+            sad: 0,
+        };
+        mv.write_to(bytes);
+    }
+
+    for pel in [Subpel::Full, Subpel::Half, Subpel::Quarter] {
+        data.pel = pel;
+        let motion = finest_motion(&out.block_data, &data).unwrap().unwrap();
+        assert_eq!(motion.len(), blocks * 2);
+        let scale = f64::from(u8::from(pel));
+        for (i, xy) in motion.chunks_exact(2).enumerate() {
+            let (x, y) = (i as i32 % 7 - 3, -(i as i32 % 5));
+            assert_eq!(
+                xy,
+                [f64::from(x) / scale, f64::from(y) / scale],
+                "block {i}"
+            );
+        }
+    }
+}
+
+#[test]
+fn finds_divided_level() {
+    let data = analysis_data(10, 6, 3);
+    let mut out = group_of_planes(&data, DivideMode::Original).write_default_to_array();
+    out.block_data[4..8].copy_from_slice(&1i32.to_le_bytes());
+
+    // divided data has twice as many blocks in each direction, stored as an extra level
+    let mut divided = analysis_data(20, 12, 4);
+    divided.blk_size_x = NonZeroUsize::new(4).unwrap();
+    divided.blk_size_y = NonZeroUsize::new(4).unwrap();
+    let vectors = parse_finest_vectors(&out.block_data, &divided)
+        .unwrap()
+        .unwrap();
+    assert_eq!(vectors.len(), 240);
+}
+
+#[test]
+fn rejects_mismatched_block_count() {
+    let data = analysis_data(10, 6, 3);
+    let mut out = group_of_planes(&data, DivideMode::None).write_default_to_array();
+    out.block_data[4..8].copy_from_slice(&1i32.to_le_bytes());
+    assert!(parse_finest_vectors(&out.block_data, &analysis_data(11, 6, 3)).is_err());
+    assert!(parse_finest_vectors(&out.block_data[..20], &data).is_err());
+}
+
+#[test]
+fn divided_analysis_data_round_trip() {
+    let data = analysis_data(10, 6, 3);
+    let divided = data.divided();
+    assert_eq!((divided.blk_x.get(), divided.blk_y.get()), (20, 12));
+    assert_eq!((divided.blk_size_x.get(), divided.blk_size_y.get()), (4, 4));
+    assert_eq!(divided.level_count, 4);
+    assert_eq!(divided.undivided().unwrap().bytes(), data.bytes());
+
+    // An odd number of blocks cannot come from subdividing
+    assert!(analysis_data(11, 6, 3).undivided().is_err());
+    assert!(analysis_data(10, 6, 1).undivided().is_err());
+}
+
+#[test]
+fn finds_base_level_of_divided_vectors() {
+    let data = analysis_data(10, 6, 3);
+    let mut out = group_of_planes(&data, DivideMode::Original).write_default_to_array();
+    out.block_data[4..8].copy_from_slice(&1i32.to_le_bytes());
+    // mark the last searched block, which comes right before the subdivided level
+    let divided_size = size_of::<u32>() + 240 * MV_SIZE;
+    let last = out.block_data.len() - divided_size - MV_SIZE;
+    MotionVector { x: 7, y: 1, sad: 9 }.write_to(&mut out.block_data[last..]);
+
+    let divided = data.divided();
+    let base = parse_finest_vectors(&out.block_data, &divided.undivided().unwrap())
+        .unwrap()
+        .unwrap();
+    assert_eq!(base.len(), 60);
+    let last = base.last().unwrap();
+    assert_eq!((last.x, last.y, last.sad), (7, 1, 9));
+
+    let finest = parse_finest_vectors(&out.block_data, &divided)
+        .unwrap()
+        .unwrap();
+    assert_eq!(finest.len(), 240);
+    assert!(finest.iter().all(|mv| (mv.x, mv.y) == (0, 0)));
+}
+
+/// Two levels of vectors for a 32x16 clip with 8x8 blocks, laid out as mvtools' `Analyse` writes them.
+#[rustfmt::skip]
+const MVTOOLS_VECTORS: &[u8] = &[
+    // total size 176, valid
+    176, 0, 0, 0, 1, 0, 0, 0,
+    // coarse level: 2x1 blocks
+    36, 0, 0, 0,
+    2, 0, 0, 0, 255, 255, 255, 255, 54, 1, 0, 0, 0, 0, 0, 0,
+    252, 255, 255, 255, 0, 0, 0, 0, 10, 5, 0, 0, 0, 0, 0, 0,
+    // finest level: 4x2 blocks
+    132, 0, 0, 0,
+    4, 0, 0, 0, 254, 255, 255, 255, 120, 0, 0, 0, 0, 0, 0, 0,
+    5, 0, 0, 0, 254, 255, 255, 255, 98, 0, 0, 0, 0, 0, 0, 0,
+    3, 0, 0, 0, 255, 255, 255, 255, 199, 1, 0, 0, 0, 0, 0, 0,
+    248, 255, 255, 255, 0, 0, 0, 0, 0, 4, 0, 0, 0, 0, 0, 0,
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    1, 0, 0, 0, 1, 0, 0, 0, 17, 0, 0, 0, 0, 0, 0, 0,
+    247, 255, 255, 255, 1, 0, 0, 0, 255, 7, 0, 0, 0, 0, 0, 0,
+    248, 255, 255, 255, 2, 0, 0, 0, 100, 2, 0, 0, 0, 0, 0, 0,
+];
+const MVTOOLS_COARSE: [(i32, i32, i64); 2] = [(2, -1, 310), (-4, 0, 1290)];
+const MVTOOLS_FINEST: [(i32, i32, i64); 8] = [
+    (4, -2, 120),
+    (5, -2, 98),
+    (3, -1, 455),
+    (-8, 0, 1024),
+    (0, 0, 0),
+    (1, 1, 17),
+    (-9, 1, 2047),
+    (-8, 2, 612),
+];
+
+#[test]
+fn mvtools_vectors_round_trip() {
+    let data = analysis_data(4, 2, 2);
+    let out = parse_vectors(MVTOOLS_VECTORS, &data).unwrap();
+    assert!(out.validity);
+    assert_eq!(&*out.block_data, MVTOOLS_VECTORS);
+
+    let levels = level_vectors(&out, &data).unwrap();
+    assert_eq!(levels.len(), 2);
+    for (level, (vectors, expected)) in levels
+        .iter()
+        .zip([&MVTOOLS_COARSE[..], &MVTOOLS_FINEST[..]])
+        .enumerate()
+    {
+        let (blk_x, blk_y) = level_blocks(
+            data.width.get(),
+            data.height.get(),
+            data.level_count - 1 - level,
+            data.blk_size_x,
+            data.blk_size_y,
+            data.overlap_x,
+            data.overlap_y,
+        )
+        .unwrap();
+        assert_eq!(vectors.len(), blk_x.get() * blk_y.get());
+        let vectors: Vec<_> = vectors.iter().map(|mv| (mv.x, mv.y, mv.sad)).collect();
+        assert_eq!(vectors, expected);
+    }
+
+    let finest = parse_finest_vectors(MVTOOLS_VECTORS, &data)
+        .unwrap()
+        .unwrap();
+    assert_eq!(finest.len(), 8);
+    assert_eq!((finest[6].x, finest[6].y, finest[6].sad), MVTOOLS_FINEST[6]);
+}
+
+#[test]
+fn mvclip_reads_mvtools_props() {
+    // mvtools writes neither `Analyse_valid` nor `Analyse_divide`
+    let hex = |bytes: &[u8]| {
+        bytes.iter().fold(String::new(), |mut hex, b| {
+            write!(hex, "{b:02x}").unwrap();
+            hex
+        })
+    };
+    let env = Environment::from_script(&format!(
+        r#"
+import vapoursynth as vs
+core = vs.core
+clip = core.std.BlankClip(width=32, height=16, format=vs.GRAY8, length=2)
+props = {{
+    "{PROP_MVANALYSISDATA}": bytes.fromhex("{}"),
+    "{PROP_VECTORS}": bytes.fromhex("{}"),
+}}
+valid = core.std.SetFrameProps(clip, **props)
+# The validity in the header of the second frame is 0
+props["{PROP_VECTORS}"] = bytes.fromhex("{}")
+invalid = core.std.SetFrameProps(clip, **props)
+(valid[0] + invalid[1]).set_output()
+"#,
+        hex(&analysis_data(4, 2, 2).bytes()),
+        hex(MVTOOLS_VECTORS),
+        hex(&[&MVTOOLS_VECTORS[..4], &[0; 4], &MVTOOLS_VECTORS[8..]].concat()),
+    ))
+    .unwrap();
+    let (node, _) = env.get_output(0).unwrap();
+
+    let clip = MVClip::new(node.clone(), "Test").unwrap();
+    assert_eq!(clip.divide(), DivideMode::None);
+    let vectors = clip
+        .finest_vectors(&node.get_frame(0).unwrap())
+        .unwrap()
+        .unwrap();
+    let vectors: Vec<_> = vectors.iter().map(|mv| (mv.x, mv.y, mv.sad)).collect();
+    assert_eq!(vectors, MVTOOLS_FINEST);
+    assert!(
+        clip.finest_vectors(&node.get_frame(1).unwrap())
+            .unwrap()
+            .is_none()
+    );
+}
+
+#[test]
+fn rejects_unsupported_vectors_layouts() {
+    let data = analysis_data(4, 2, 2);
+
+    // The header must describe the whole buffer
+    let mut truncated = MVTOOLS_VECTORS[..MVTOOLS_VECTORS.len() - MV_SIZE].to_vec();
+    assert!(parse_vectors(&truncated, &data).is_err());
+    let size = truncated.len() as u32;
+    truncated[..4].copy_from_slice(&size.to_le_bytes());
+    assert!(parse_vectors(&truncated, &data).is_err());
+
+    // Vectors with 32-bit SADs take 12 bytes each
+    let mut sad32 = Vec::new();
+    let levels = [&MVTOOLS_COARSE[..], &MVTOOLS_FINEST[..]];
+    let total = 8 + levels.iter().map(|l| 4 + l.len() * 12).sum::<usize>();
+    sad32.extend_from_slice(&(total as i32).to_le_bytes());
+    sad32.extend_from_slice(&1i32.to_le_bytes());
+    for level in levels {
+        sad32.extend_from_slice(&((4 + level.len() * 12) as u32).to_le_bytes());
+        for &(x, y, sad) in level {
+            sad32.extend_from_slice(&x.to_le_bytes());
+            sad32.extend_from_slice(&y.to_le_bytes());
+            sad32.extend_from_slice(&(sad as i32).to_le_bytes());
+        }
+    }
+    let err = parse_vectors(&sad32, &data).unwrap_err();
+    assert!(err.to_string().contains("32-bit SADs"), "{err}");
+}
+
+#[test]
+fn stored_vectors_round_trip() {
+    let data = analysis_data(4, 2, 2).divided();
+    let undivided = analysis_data(4, 2, 2);
+    let mut out = group_of_planes(&undivided, DivideMode::Median).write_default_to_array();
+    out.block_data[4..8].copy_from_slice(&1i32.to_le_bytes());
+    let stored = StoredVectors {
+        analysis_data: data,
+        divide: DivideMode::Median,
+        vectors: out,
+    };
+
+    let bytes = stored.to_bytes();
+    assert_eq!(&bytes[..4], b"ZMVS");
+    assert_eq!(
+        u16::from_le_bytes([bytes[4], bytes[5]]),
+        STORED_VECTORS_VERSION
+    );
+    let parsed = StoredVectors::from_bytes(&bytes).unwrap();
+    assert_eq!(parsed.divide, DivideMode::Median);
+    assert_eq!(parsed.analysis_data.bytes(), data.bytes());
+    assert!(parsed.vectors.validity);
+    assert_eq!(parsed.vectors.block_data, stored.vectors.block_data);
+}
+
+/// Builds a stored vectors blob with the given version and flags around the mvtools vectors.
+fn stored_blob(version: u16, flags: u16) -> Vec<u8> {
+    let mut blob = b"ZMVS".to_vec();
+    blob.extend_from_slice(&version.to_le_bytes());
+    blob.extend_from_slice(&flags.to_le_bytes());
+    blob.extend_from_slice(&analysis_data(4, 2, 2).bytes());
+    blob.extend_from_slice(MVTOOLS_VECTORS);
+    blob
+}
+
+#[test]
+fn stored_vectors_upgrade_version_1() {
+    let parsed = StoredVectors::from_bytes(&stored_blob(1, 0)).unwrap();
+    assert_eq!(parsed.divide, DivideMode::None);
+    assert_eq!(parsed.analysis_data.level_count, 2);
+    assert_eq!(&*parsed.vectors.block_data, MVTOOLS_VECTORS);
+
+    // Version 1 had no flags
+    assert!(StoredVectors::from_bytes(&stored_blob(1, 1)).is_err());
+}
+
+#[test]
+fn stored_vectors_reject_unknown_layouts() {
+    let err = StoredVectors::from_bytes(&stored_blob(STORED_VECTORS_VERSION + 1, 0)).unwrap_err();
+    assert!(
+        err.to_string().contains("only reads up to version 2"),
+        "{err}"
+    );
+    assert!(StoredVectors::from_bytes(&stored_blob(0, 0)).is_err());
+    assert!(StoredVectors::from_bytes(&stored_blob(2, 0x100)).is_err());
+    assert!(StoredVectors::from_bytes(&stored_blob(2, 3)).is_err());
+
+    // The raw vectors layout shared with mvtools has no header
+    assert!(StoredVectors::from_bytes(MVTOOLS_VECTORS).is_err());
+    assert!(StoredVectors::from_bytes(b"ZMV").is_err());
+    let blob = stored_blob(2, 0);
+    assert!(StoredVectors::from_bytes(&blob[..blob.len() - 1]).is_err());
+}
+
+fn vectors_with_changed_blocks(count: usize, changed: usize, sad: i64) -> Vec<MotionVector> {
+    (0..count)
+        .map(|i| MotionVector {
+            x: 0,
+            y: 0,
+            sad: if i < changed { sad } else { 0 },
+        })
+        .collect()
+}
+
+#[test]
+fn scene_change_needs_more_than_thscd2_blocks_above_thscd1() {
+    // 128 out of 256 blocks is half of the 100 blocks, so more than 50 must change
+    let thscd2 = 128;
+    assert!(detect_scene_change(
+        &vectors_with_changed_blocks(100, 51, 401),
+        400,
+        thscd2
+    ));
+    assert!(!detect_scene_change(
+        &vectors_with_changed_blocks(100, 50, 401),
+        400,
+        thscd2
+    ));
+    // A block at exactly `thscd1` has not changed
+    assert!(!detect_scene_change(
+        &vectors_with_changed_blocks(100, 100, 400),
+        400,
+        thscd2
+    ));
+
+    // Every block must change with the largest `thscd2` and a multiple of 256 blocks
+    assert!(detect_scene_change(
+        &vectors_with_changed_blocks(256, 256, 401),
+        400,
+        255
+    ));
+    assert!(!detect_scene_change(
+        &vectors_with_changed_blocks(256, 255, 401),
+        400,
+        255
+    ));
+    // Any changed block is a scene change with the smallest `thscd2`
+    assert!(detect_scene_change(
+        &vectors_with_changed_blocks(256, 1, 401),
+        400,
+        0
+    ));
+}
+
+#[test]
+fn thscd1_scales_with_block_size_and_bit_depth() {
+    let mut data = analysis_data(4, 4, 1);
+    assert_eq!(scale_thscd1(DEFAULT_THSCD1, &data), DEFAULT_THSCD1);
+
+    data.blk_size_x = NonZeroUsize::new(16).unwrap();
+    data.blk_size_y = NonZeroUsize::new(16).unwrap();
+    assert_eq!(scale_thscd1(DEFAULT_THSCD1, &data), DEFAULT_THSCD1 * 4);
+
+    data.bits_per_sample = NonZeroU8::new(10).unwrap();
+    assert_eq!(scale_thscd1(DEFAULT_THSCD1, &data), DEFAULT_THSCD1 * 16);
+}
+
+#[test]
+fn only_a_zero_valid_property_marks_vectors_invalid() {
+    assert!(is_marked_invalid(Some(0)));
+    assert!(!is_marked_invalid(Some(1)));
+    // mvtools does not write the property
+    assert!(!is_marked_invalid(None));
+}
@@ -5,12 +5,12 @@ use std::num::{NonZeroU8, NonZeroUsize};
 
 use anyhow::Result;
 use smallvec::SmallVec;
-use vapoursynth::frame::Frame;
 
 use crate::{
     mv_plane::MVPlane,
-    params::{MVPlaneSet, ReduceFilter, Subpel, SubpelMethod},
-    util::{Pixel, plane_with_padding_mut, plane_with_padding_split},
+    params::{MVPlaneSet, PadMode, Subpel, SubpelMethod},
+    reduce::ReduceFn,
+    util::{Pixel, SuperPlanes, split_plane},
 };
 
 #[derive(Debug, Clone)]
@@ -69,12 +69,18 @@ impl MVFrame {
         Ok(Self { planes, yuv_mode })
     }
 
-    pub(crate) fn reduce_to<T: Pixel>(
+    pub(crate) fn reset(&mut self) {
+        for plane in &mut self.planes {
+            plane.reset();
+        }
+    }
+
+    pub(crate) fn reduce_to<T: Pixel, P: SuperPlanes<T> + ?Sized>(
         &self,
         reduced_frame: &mut MVFrame,
         mode: MVPlaneSet,
-        filter: ReduceFilter,
-        frame: &mut Frame,
+        reduce: ReduceFn<T>,
+        frame: &mut P,
     ) {
         for i in 0..3 {
             if let Some(plane) = self.planes.get(i)
@@ -88,11 +94,14 @@ impl MVFrame {
                 // Use the new helper function to avoid cloning the source data
                 // SAFETY: The windows inside each plane are set up so that they do not overlap.
                 unsafe {
-                    let (src, dest) = plane_with_padding_split::<T>(frame, i)
-                        .expect("Super: plane should exist but does not");
+                    let (src, dest) = split_plane(
+                        frame
+                            .plane_mut(i)
+                            .expect("Super: plane should exist but does not"),
+                    );
                     plane.reduce_to::<T>(
                         &mut reduced_frame.planes[i],
-                        filter,
+                        reduce,
                         dest,
                         src,
                         reduced_pitch,
@@ -105,24 +114,31 @@ impl MVFrame {
         }
     }
 
-    pub(crate) fn pad<T: Pixel>(&mut self, mode: MVPlaneSet, frame: &mut Frame) {
+    pub(crate) fn pad<T: Pixel, P: SuperPlanes<T> + ?Sized>(
+        &mut self,
+        mode: MVPlaneSet,
+        pad_mode: PadMode,
+        frame: &mut P,
+    ) {
         for i in 0..3 {
             if let Some(plane) = self.planes.get_mut(i)
                 && (mode.bits() & (1 << i)) > 0
             {
                 plane.pad(
-                    plane_with_padding_mut::<T>(frame, i)
+                    pad_mode,
+                    frame
+                        .plane_mut(i)
                         .expect("Super: source plane should exist but does not"),
                 );
             }
         }
     }
 
-    pub(crate) fn refine<T: Pixel>(
+    pub(crate) fn refine<T: Pixel, P: SuperPlanes<T> + ?Sized>(
         &mut self,
         mode: MVPlaneSet,
         subpel: SubpelMethod,
-        frame: &mut Frame,
+        frame: &mut P,
     ) {
         for i in 0..3 {
             if let Some(plane) = self.planes.get_mut(i)
@@ -130,7 +146,8 @@ impl MVFrame {
             {
                 plane.refine::<T>(
                     subpel,
-                    plane_with_padding_mut::<T>(frame, i)
+                    frame
+                        .plane_mut(i)
                         .expect("Super: source plane should exist but does not"),
                 );
             }
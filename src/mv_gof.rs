@@ -3,18 +3,26 @@
 #[cfg(test)]
 mod tests;
 
-use std::num::{NonZeroU8, NonZeroUsize};
+use std::{
+    num::{NonZeroU8, NonZeroUsize},
+    sync::{Mutex, PoisonError},
+};
 
 use anyhow::Result;
+#[cfg(any(test, feature = "bench"))]
+use anyhow::bail;
 use smallvec::SmallVec;
-use vapoursynth::{format::Format, frame::Frame};
+use vapoursynth::format::Format;
 
 use crate::{
     mv_frame::MVFrame,
     mv_plane::{plane_height_luma, plane_super_offset, plane_width_luma},
-    params::{MVPlaneSet, ReduceFilter, Subpel, SubpelMethod},
-    util::Pixel,
+    params::{MVPlaneSet, PadMode, ReduceFilter, Subpel, SubpelMethod},
+    reduce::reduce_fn,
+    util::{Pixel, SuperPlanes},
 };
+#[cfg(any(test, feature = "bench"))]
+use crate::{mv_plane::SuperPlaneLayout, mv_super::super_frame_size};
 
 #[derive(Debug, Clone)]
 pub struct MVGroupOfFrames {
@@ -43,6 +51,38 @@ impl MVGroupOfFrames {
         bits_per_sample: NonZeroU8,
         pitch: &[NonZeroUsize; 3],
         format: Format,
+    ) -> Result<Self> {
+        Self::with_plane_count(
+            level_count,
+            width,
+            height,
+            pel,
+            hpad,
+            vpad,
+            yuv_mode,
+            x_ratio_uv,
+            y_ratio_uv,
+            bits_per_sample,
+            pitch,
+            format.plane_count(),
+        )
+    }
+
+    /// Builds the levels for a superframe with `plane_count` planes,
+    /// which are only accessed for the planes in `yuv_mode`.
+    fn with_plane_count(
+        level_count: usize,
+        width: NonZeroUsize,
+        height: NonZeroUsize,
+        pel: Subpel,
+        hpad: usize,
+        vpad: usize,
+        yuv_mode: MVPlaneSet,
+        x_ratio_uv: NonZeroU8,
+        y_ratio_uv: NonZeroU8,
+        bits_per_sample: NonZeroU8,
+        pitch: &[NonZeroUsize; 3],
+        plane_count: usize,
     ) -> Result<Self> {
         // SAFETY: Width must be at least the value of its ratio
         let chroma_width =
@@ -71,7 +111,7 @@ impl MVGroupOfFrames {
             let width_i = plane_width_luma(this.width[0], i, this.x_ratio_uv, this.hpad[0]);
             let height_i = plane_height_luma(this.height[0], i, this.y_ratio_uv, this.vpad[0]);
             let mut plane_offsets = SmallVec::with_capacity(3);
-            for plane in 0..format.plane_count() {
+            for plane in 0..plane_count {
                 let offset = plane_super_offset(
                     plane > 0,
                     this.height[plane],
@@ -104,20 +144,225 @@ impl MVGroupOfFrames {
         Ok(this)
     }
 
-    pub fn reduce<T: Pixel>(&mut self, mode: MVPlaneSet, filter: ReduceFilter, frame: &mut Frame) {
+    /// Marks every plane as not yet filled, padded or refined,
+    /// so that the group can be reused for another frame with the same layout.
+    pub fn reset(&mut self) {
+        for frame in &mut self.frames {
+            frame.reset();
+        }
+    }
+
+    pub fn reduce<T: Pixel, P: SuperPlanes<T> + ?Sized>(
+        &mut self,
+        mode: MVPlaneSet,
+        filter: ReduceFilter,
+        pad_mode: PadMode,
+        frame: &mut P,
+    ) {
+        let reduce = reduce_fn::<T>(filter);
         for i in 0..(self.level_count - 1) {
-            self.frames[i]
-                .clone()
-                .reduce_to::<T>(&mut self.frames[i + 1], mode, filter, frame);
-            self.frames[i + 1].pad::<T>(MVPlaneSet::YUVPLANES, frame);
+            let (finer, coarser) = self.frames.split_at_mut(i + 1);
+            finer[i].reduce_to::<T, P>(&mut coarser[0], mode, reduce, frame);
+            coarser[0].pad::<T, P>(MVPlaneSet::YUVPLANES, pad_mode, frame);
+        }
+    }
+
+    pub fn pad<T: Pixel, P: SuperPlanes<T> + ?Sized>(
+        &mut self,
+        mode: MVPlaneSet,
+        pad_mode: PadMode,
+        frame: &mut P,
+    ) {
+        self.frames[0].pad::<T, P>(mode, pad_mode, frame);
+    }
+
+    pub fn refine<T: Pixel, P: SuperPlanes<T> + ?Sized>(
+        &mut self,
+        mode: MVPlaneSet,
+        subpel: SubpelMethod,
+        frame: &mut P,
+    ) {
+        self.frames[0].refine::<T, P>(mode, subpel, frame);
+    }
+}
+
+/// A superframe pyramid built in plain buffers instead of a VapourSynth frame,
+/// so that the reduce, pad and refine chain can be tested and benchmarked on its own.
+#[cfg(any(test, feature = "bench"))]
+pub struct BufferPyramid<T: Pixel> {
+    gof: MVGroupOfFrames,
+    planes: Vec<Vec<T>>,
+    pitch: [NonZeroUsize; 3],
+}
+
+#[cfg(any(test, feature = "bench"))]
+impl<T: Pixel> BufferPyramid<T> {
+    /// Builds every level of a superframe from the unpadded source planes,
+    /// in the same order as `Super`: level 0 is filled, each coarser level is reduced
+    /// from the one above it and padded, then level 0 is padded and refined.
+    ///
+    /// A single source plane builds a luma-only pyramid,
+    /// otherwise all three planes are built with the given chroma subsampling.
+    pub fn new(
+        src: &[&[T]],
+        src_pitch: &[NonZeroUsize],
+        width: NonZeroUsize,
+        height: NonZeroUsize,
+        level_count: usize,
+        pel: Subpel,
+        hpad: usize,
+        vpad: usize,
+        x_ratio_uv: NonZeroU8,
+        y_ratio_uv: NonZeroU8,
+        bits_per_sample: NonZeroU8,
+        rfilter: ReduceFilter,
+        sharp: SubpelMethod,
+        pad_mode: PadMode,
+    ) -> Result<Self> {
+        let mode = match src.len() {
+            1 => MVPlaneSet::YPLANE,
+            3 => MVPlaneSet::YUVPLANES,
+            count => bail!("BufferPyramid: expected 1 or 3 source planes, got {count}"),
+        };
+        if src_pitch.len() != src.len() {
+            bail!("BufferPyramid: expected a pitch for each source plane");
         }
+
+        let Some((super_width, super_height)) = super_frame_size(
+            width,
+            height,
+            level_count,
+            pel,
+            hpad,
+            vpad,
+            x_ratio_uv,
+            y_ratio_uv,
+        ) else {
+            bail!("BufferPyramid: super frame is too large");
+        };
+        let chroma_width = super_width.get() / x_ratio_uv.get() as usize;
+        let chroma_height = super_height.get() / y_ratio_uv.get() as usize;
+        let pitch = [
+            super_width,
+            NonZeroUsize::new(chroma_width).unwrap_or(super_width),
+            NonZeroUsize::new(chroma_width).unwrap_or(super_width),
+        ];
+        let mut planes: Vec<Vec<T>> = (0..src.len())
+            .map(|plane| {
+                let rows = if plane == 0 {
+                    super_height.get()
+                } else {
+                    chroma_height
+                };
+                vec![T::zero(); pitch[plane].get() * rows]
+            })
+            .collect();
+
+        let mut gof = MVGroupOfFrames::with_plane_count(
+            level_count,
+            width,
+            height,
+            pel,
+            hpad,
+            vpad,
+            mode,
+            x_ratio_uv,
+            y_ratio_uv,
+            bits_per_sample,
+            &pitch,
+            src.len(),
+        )?;
+        for (plane, dest) in planes.iter_mut().enumerate() {
+            gof.frames[0].planes[plane].fill_plane(src[plane], src_pitch[plane], dest)?;
+        }
+        gof.reduce::<T, _>(mode, rfilter, pad_mode, planes.as_mut_slice());
+        gof.pad::<T, _>(mode, pad_mode, planes.as_mut_slice());
+        gof.refine::<T, _>(mode, sharp, planes.as_mut_slice());
+
+        Ok(Self { gof, planes, pitch })
+    }
+
+    /// Gets a single plane at one hierarchical level, laid out the same way
+    /// as the planes returned by `Super::level_plane`.
+    pub fn level_plane(&self, level: usize, plane: usize) -> Result<(&[T], SuperPlaneLayout)> {
+        if level >= self.gof.level_count {
+            bail!(
+                "BufferPyramid: level {level} requested, but the pyramid only has {} levels",
+                self.gof.level_count
+            );
+        }
+        let Some(data) = self.planes.get(plane) else {
+            bail!(
+                "BufferPyramid: plane {plane} requested, but the pyramid only has {} planes",
+                self.planes.len()
+            );
+        };
+
+        let layout = SuperPlaneLayout::new(
+            plane > 0,
+            self.gof.width[0],
+            self.gof.height[0],
+            level,
+            self.gof.pel,
+            self.gof.hpad[0],
+            self.gof.vpad[0],
+            self.gof.x_ratio_uv,
+            self.gof.y_ratio_uv,
+            self.pitch[plane],
+        );
+        let data = &data[layout.offset..layout.offset + layout.padded_len()];
+
+        Ok((data, layout))
+    }
+}
+
+/// A pool of [`MVGroupOfFrames`] shared by all frame requests of a filter instance,
+/// so that concurrent requests each reuse a group instead of building a new one per frame.
+///
+/// The groups only describe the planes of a superframe, whose samples live in the frames
+/// that the planes are built in or read from. Reusing the groups therefore leaves building
+/// a superframe without any heap allocations of its own.
+///
+/// Groups are keyed by the plane pitches they were built for, since those are the only
+/// part of the layout which may change between frames of the same clip.
+#[derive(Debug, Default)]
+pub struct GofPool {
+    free: Mutex<Vec<([NonZeroUsize; 3], MVGroupOfFrames)>>,
+}
+
+impl GofPool {
+    /// Takes a group built for `pitch` out of the pool,
+    /// or builds a new one with `build` if none is available.
+    pub fn take(
+        &self,
+        pitch: &[NonZeroUsize; 3],
+        build: impl FnOnce() -> Result<MVGroupOfFrames>,
+    ) -> Result<MVGroupOfFrames> {
+        let reused = {
+            // The pool only holds groups which were reset before being returned,
+            // so it remains usable even if another request panicked while holding the lock.
+            let mut free = self.free.lock().unwrap_or_else(PoisonError::into_inner);
+            free.iter()
+                .position(|(free_pitch, _)| free_pitch == pitch)
+                .map(|i| free.swap_remove(i).1)
+        };
+        reused.map_or_else(build, Ok)
     }
 
-    pub fn pad<T: Pixel>(&mut self, mode: MVPlaneSet, frame: &mut Frame) {
-        self.frames[0].pad::<T>(mode, frame);
+    /// Returns a group built for `pitch` to the pool, to be reused by a later request.
+    pub fn give_back(&self, pitch: [NonZeroUsize; 3], mut gof: MVGroupOfFrames) {
+        gof.reset();
+        self.free
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push((pitch, gof));
     }
 
-    pub fn refine<T: Pixel>(&mut self, mode: MVPlaneSet, subpel: SubpelMethod, frame: &mut Frame) {
-        self.frames[0].refine::<T>(mode, subpel, frame);
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.free
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .len()
     }
 }
@@ -4,7 +4,10 @@
 use vapoursynth::format::PresetFormat;
 
 use super::*;
-use crate::tests::create_test_env;
+use crate::{
+    params::{ReduceFilter, SubpelKernel, SubpelMethod},
+    tests::{count_allocations, create_test_env},
+};
 
 #[test]
 fn mvgof_struct_fields() {
@@ -172,3 +175,353 @@ fn mvgof_debug_and_clone() {
 // Note: More comprehensive tests would require complex VapourSynth Frame object creation
 // which is better suited for integration tests. These unit tests focus on the basic
 // constructor behavior and struct invariants that can be tested without complex mocking.
+
+#[test]
+fn gof_pool_reuses_groups_with_matching_pitch() {
+    let env = create_test_env(64, 48, PresetFormat::YUV420P8, 1).unwrap();
+    let (node, _) = env.get_output(0).unwrap();
+    let format = node.info().format;
+
+    let builds = std::cell::Cell::new(0);
+    let build = |pitch: &[NonZeroUsize; 3]| {
+        builds.set(builds.get() + 1);
+        MVGroupOfFrames::new(
+            3,
+            NonZeroUsize::new(64).unwrap(),
+            NonZeroUsize::new(48).unwrap(),
+            Subpel::Full,
+            8,
+            8,
+            MVPlaneSet::YPLANE,
+            NonZeroU8::new(2).unwrap(),
+            NonZeroU8::new(2).unwrap(),
+            NonZeroU8::new(8).unwrap(),
+            pitch,
+            format,
+        )
+    };
+    let pitch = [NonZeroUsize::new(80).unwrap(); 3];
+    let wider = [NonZeroUsize::new(96).unwrap(); 3];
+
+    let pool = GofPool::default();
+    let mut gof = pool.take(&pitch, || build(&pitch)).unwrap();
+    gof.frames[0].planes[0].is_filled = true;
+    gof.frames[1].planes[0].is_padded = true;
+    pool.give_back(pitch, gof);
+
+    // Every later frame with the same layout reuses the group, with its state cleared
+    for _ in 0..10 {
+        let gof = pool.take(&pitch, || build(&pitch)).unwrap();
+        assert!(
+            gof.frames
+                .iter()
+                .flat_map(|frame| &frame.planes)
+                .all(|plane| { !plane.is_filled && !plane.is_padded && !plane.is_refined })
+        );
+        pool.give_back(pitch, gof);
+    }
+    assert_eq!(builds.get(), 1);
+
+    // Concurrent requests and frames with a different pitch each need their own group
+    let first = pool.take(&pitch, || build(&pitch)).unwrap();
+    let second = pool.take(&pitch, || build(&pitch)).unwrap();
+    let other = pool.take(&wider, || build(&wider)).unwrap();
+    assert_eq!(builds.get(), 3);
+    assert_eq!(other.frames[0].planes[0].pitch, wider[0]);
+    pool.give_back(pitch, first);
+    pool.give_back(pitch, second);
+    pool.give_back(wider, other);
+    assert_eq!(pool.len(), 3);
+}
+
+#[test]
+fn pooled_superframes_do_not_allocate() {
+    let width = NonZeroUsize::new(64).unwrap();
+    let height = NonZeroUsize::new(48).unwrap();
+    let ratio = NonZeroU8::new(2).unwrap();
+    let (super_width, super_height) =
+        super_frame_size(width, height, 3, Subpel::Half, 8, 8, ratio, ratio).unwrap();
+    let pitch = [super_width; 3];
+    let src: Vec<u8> = (0..width.get() * height.get()).map(|i| i as u8).collect();
+    // Stands in for the output frame of `Super`
+    let mut planes = vec![vec![0u8; super_width.get() * super_height.get()]];
+    let build = || {
+        MVGroupOfFrames::with_plane_count(
+            3,
+            width,
+            height,
+            Subpel::Half,
+            8,
+            8,
+            MVPlaneSet::YPLANE,
+            ratio,
+            ratio,
+            NonZeroU8::new(8).unwrap(),
+            &pitch,
+            1,
+        )
+    };
+
+    // Builds a superframe the way `Super` does for each frame
+    let pool = GofPool::default();
+    let mut superframe = || {
+        let mut gof = pool.take(&pitch, build).unwrap();
+        gof.frames[0].planes[0]
+            .fill_plane(&src, width, &mut planes[0])
+            .unwrap();
+        gof.reduce::<u8, _>(
+            MVPlaneSet::YPLANE,
+            ReduceFilter::Average,
+            PadMode::Replicate,
+            planes.as_mut_slice(),
+        );
+        gof.pad::<u8, _>(
+            MVPlaneSet::YPLANE,
+            PadMode::Replicate,
+            planes.as_mut_slice(),
+        );
+        gof.refine::<u8, _>(
+            MVPlaneSet::YPLANE,
+            SubpelMethod::Bilinear,
+            planes.as_mut_slice(),
+        );
+        pool.give_back(pitch, gof);
+    };
+
+    let ((), first) = count_allocations(&mut superframe);
+    assert!(first > 0);
+    let ((), later) = count_allocations(|| {
+        for _ in 0..4 {
+            superframe();
+        }
+    });
+    assert_eq!(later, 0);
+}
+
+#[test]
+fn buffer_pyramid_reduces_and_pads_a_gradient() {
+    // A linear gradient `a * x + b * y` stays linear when averaged down,
+    // doubling its slopes and gaining an offset of `(a + b) / 2` at each level
+    let luma: Vec<u8> = (0..32 * 32)
+        .map(|i| (4 * (i % 32) + 2 * (i / 32)) as u8)
+        .collect();
+    let chroma: Vec<u8> = (0..16 * 16)
+        .map(|i| (8 * (i % 16) + 4 * (i / 16)) as u8)
+        .collect();
+    let pyramid = BufferPyramid::new(
+        &[&luma, &chroma, &chroma],
+        &[
+            NonZeroUsize::new(32).unwrap(),
+            NonZeroUsize::new(16).unwrap(),
+            NonZeroUsize::new(16).unwrap(),
+        ],
+        NonZeroUsize::new(32).unwrap(),
+        NonZeroUsize::new(32).unwrap(),
+        3,
+        Subpel::Full,
+        8,
+        8,
+        NonZeroU8::new(2).unwrap(),
+        NonZeroU8::new(2).unwrap(),
+        NonZeroU8::new(8).unwrap(),
+        ReduceFilter::Average,
+        SubpelMethod::Bilinear,
+        PadMode::Replicate,
+    )
+    .unwrap();
+
+    for (plane, (a, b, size)) in [(4usize, 2, 32), (8, 4, 16), (8, 4, 16)]
+        .into_iter()
+        .enumerate()
+    {
+        for level in 0..3 {
+            let (data, layout) = pyramid.level_plane(level, plane).unwrap();
+            let width = size >> level;
+            assert_eq!(layout.width.get(), width, "plane {plane} level {level}");
+            assert_eq!(layout.height.get(), width, "plane {plane} level {level}");
+
+            let offset = a.midpoint(b) * ((1 << level) - 1);
+            let pixel = |x: usize, y: usize| ((a * x + b * y) << level) + offset;
+            for (row, line) in data.chunks(layout.pitch.get()).enumerate() {
+                // Padding repeats the nearest edge pixel
+                let y = row.saturating_sub(layout.vpad).min(width - 1);
+                for (column, &value) in line[..width + 2 * layout.hpad].iter().enumerate() {
+                    let x = column.saturating_sub(layout.hpad).min(width - 1);
+                    assert_eq!(
+                        value as usize,
+                        pixel(x, y),
+                        "plane {plane} level {level} at ({column}, {row})"
+                    );
+                }
+            }
+        }
+    }
+
+    assert!(pyramid.level_plane(3, 0).is_err());
+    assert!(pyramid.level_plane(0, 3).is_err());
+}
+
+#[test]
+fn buffer_pyramid_refines_subpixel_windows() {
+    // Bilinear half-pel samples of a horizontal gradient fall halfway between its pixels
+    let luma: Vec<u16> = (0..16 * 16).map(|i| (64 * (i % 16)) as u16).collect();
+    let pyramid = BufferPyramid::new(
+        &[&luma],
+        &[NonZeroUsize::new(16).unwrap()],
+        NonZeroUsize::new(16).unwrap(),
+        NonZeroUsize::new(16).unwrap(),
+        1,
+        Subpel::Half,
+        4,
+        4,
+        NonZeroU8::MIN,
+        NonZeroU8::MIN,
+        NonZeroU8::new(10).unwrap(),
+        ReduceFilter::Average,
+        SubpelMethod::Bilinear,
+        PadMode::Replicate,
+    )
+    .unwrap();
+
+    let plane = &pyramid.gof.frames[0].planes[0];
+    let window = |index: usize| &pyramid.planes[0][plane.subpel_window_offsets[index]..];
+    let pitch = plane.pitch.get();
+    for y in 0..16 {
+        let row = (y + plane.vpad) * pitch + plane.hpad;
+        for x in 0..15 {
+            assert_eq!(window(0)[row + x], 64 * x as u16, "full pel ({x}, {y})");
+            assert_eq!(
+                window(1)[row + x],
+                64 * x as u16 + 32,
+                "half pel ({x}, {y})"
+            );
+            // Vertical half-pel samples of a horizontal gradient equal the full-pel ones
+            assert_eq!(window(2)[row + x], 64 * x as u16, "vertical ({x}, {y})");
+        }
+    }
+}
+
+#[test]
+fn luma_only_group_leaves_chroma_untouched() {
+    // A YUV superframe built without chroma must not read or write its chroma planes
+    let luma: Vec<u8> = (0..32 * 32).map(|i| (i * 7 % 251) as u8).collect();
+    let luma_pitch = NonZeroUsize::new(32).unwrap();
+    let width = NonZeroUsize::new(32).unwrap();
+    let ratio = NonZeroU8::new(2).unwrap();
+    let bits = NonZeroU8::new(8).unwrap();
+    let luma_only = BufferPyramid::new(
+        &[&luma],
+        &[luma_pitch],
+        width,
+        width,
+        3,
+        Subpel::Half,
+        8,
+        8,
+        ratio,
+        ratio,
+        bits,
+        ReduceFilter::Average,
+        SubpelMethod::Bilinear,
+        PadMode::Replicate,
+    )
+    .unwrap();
+
+    let pitch = luma_only.pitch;
+    let mut planes: Vec<Vec<u8>> = luma_only
+        .planes
+        .iter()
+        .map(|plane| vec![0; plane.len()])
+        .collect();
+    let chroma_len = pitch[1].get() * planes[0].len() / pitch[0].get() / 2;
+    planes.push(vec![0xaa; chroma_len]);
+    planes.push(vec![0xaa; chroma_len]);
+
+    let mode = MVPlaneSet::YPLANE;
+    let mut gof = MVGroupOfFrames::with_plane_count(
+        3,
+        width,
+        width,
+        Subpel::Half,
+        8,
+        8,
+        mode,
+        ratio,
+        ratio,
+        bits,
+        &pitch,
+        3,
+    )
+    .unwrap();
+    assert_eq!(gof.frames[0].planes.len(), 1);
+    gof.frames[0].planes[0]
+        .fill_plane(&luma, luma_pitch, &mut planes[0])
+        .unwrap();
+    gof.reduce::<u8, _>(
+        mode,
+        ReduceFilter::Average,
+        PadMode::Replicate,
+        planes.as_mut_slice(),
+    );
+    gof.pad::<u8, _>(mode, PadMode::Replicate, planes.as_mut_slice());
+    gof.refine::<u8, _>(mode, SubpelMethod::Bilinear, planes.as_mut_slice());
+
+    assert_eq!(planes[0], luma_only.planes[0]);
+    for plane in &planes[1..] {
+        assert!(plane.iter().all(|&p| p == 0xaa));
+    }
+}
+
+#[test]
+fn buffer_pyramid_refines_quarter_pel_windows_internally() {
+    // Without a pelclip, quarter-pel windows are refined to half-pel and averaged again.
+    // Every kernel reproduces a linear gradient, so window `4 * dy + dx` holds the gradient
+    // sampled `dx / 4` and `dy / 4` pixels further, up to rounding.
+    let luma: Vec<u16> = (0..24 * 24)
+        .map(|i| (64 * (i % 24) + 32 * (i / 24)) as u16)
+        .collect();
+    for sharp in [
+        SubpelMethod::Bilinear,
+        SubpelMethod::Bicubic,
+        SubpelMethod::Wiener,
+        SubpelMethod::Custom(
+            SubpelKernel::parse("Super", "sharp_coeffs", &[1, -4, 19, 19, -4, 1]).unwrap(),
+        ),
+    ] {
+        let pyramid = BufferPyramid::new(
+            &[&luma],
+            &[NonZeroUsize::new(24).unwrap()],
+            NonZeroUsize::new(24).unwrap(),
+            NonZeroUsize::new(24).unwrap(),
+            1,
+            Subpel::Quarter,
+            4,
+            4,
+            NonZeroU8::MIN,
+            NonZeroU8::MIN,
+            NonZeroU8::new(12).unwrap(),
+            ReduceFilter::Average,
+            sharp,
+            PadMode::Replicate,
+        )
+        .unwrap();
+
+        let plane = &pyramid.gof.frames[0].planes[0];
+        let pitch = plane.pitch.get();
+        // Stay clear of the padding, where the gradient is flattened
+        for y in 3..20 {
+            let row = (y + plane.vpad) * pitch + plane.hpad;
+            for x in 3..20 {
+                for (window, &offset) in plane.subpel_window_offsets.iter().enumerate() {
+                    let (dx, dy) = (window % 4, window / 4);
+                    let expected = (64 * x + 16 * dx + 32 * y + 8 * dy) as i32;
+                    let actual = i32::from(pyramid.planes[0][offset + row + x]);
+                    assert!(
+                        (actual - expected).abs() <= 1,
+                        "{sharp:?} window {window} at ({x}, {y}): {actual} != {expected}"
+                    );
+                }
+            }
+        }
+    }
+}
@@ -9,23 +9,18 @@ use smallvec::SmallVec;
 use crate::{
     average::average2,
     pad::pad_reference_frame,
-    params::{ReduceFilter, Subpel, SubpelMethod},
-    reduce::{
-        ReduceFn,
-        reduce_average,
-        reduce_bilinear,
-        reduce_cubic,
-        reduce_quadratic,
-        reduce_triangle,
-    },
+    params::{PadMode, Subpel, SubpelMethod},
+    reduce::ReduceFn,
     refine::{
         RefineFn,
         refine_diagonal_bilinear,
         refine_horizontal_bicubic,
         refine_horizontal_bilinear,
+        refine_horizontal_custom,
         refine_horizontal_wiener,
         refine_vertical_bicubic,
         refine_vertical_bilinear,
+        refine_vertical_custom,
         refine_vertical_wiener,
     },
     util::{Pixel, vs_bitblt},
@@ -64,7 +59,7 @@ impl MVPlane {
         plane_offset: usize,
         pitch: NonZeroUsize,
     ) -> Result<Self> {
-        let pel_val = u8::from(pel) as usize;
+        let pel_val = NonZeroUsize::from(pel).get();
         let padded_width = width.saturating_add(2 * hpad);
         let padded_height = height.saturating_add(2 * vpad);
         let offset_padding = pitch.get() * vpad + hpad;
@@ -96,9 +91,22 @@ impl MVPlane {
         })
     }
 
-    pub fn fill_plane<T: Pixel>(&mut self, src: &[T], src_pitch: NonZeroUsize, dest: &mut [T]) {
+    /// Marks the plane as not yet filled, padded or refined,
+    /// so that its layout can be reused for another frame.
+    pub fn reset(&mut self) {
+        self.is_padded = false;
+        self.is_refined = false;
+        self.is_filled = false;
+    }
+
+    pub fn fill_plane<T: Pixel>(
+        &mut self,
+        src: &[T],
+        src_pitch: NonZeroUsize,
+        dest: &mut [T],
+    ) -> Result<()> {
         if self.is_filled {
-            return;
+            return Ok(());
         }
 
         let offset = self.subpel_window_offsets[0] + self.offset_padding;
@@ -109,9 +117,10 @@ impl MVPlane {
             src_pitch,
             self.width,
             self.height,
-        );
+        )?;
 
         self.is_filled = true;
+        Ok(())
     }
 
     pub fn refine_ext<T: Pixel>(
@@ -119,6 +128,7 @@ impl MVPlane {
         src_2x: &[T],
         src_2x_pitch: NonZeroUsize,
         is_ext_padded: bool,
+        pad_mode: PadMode,
         dest: &mut [T],
     ) {
         if !self.is_refined {
@@ -127,10 +137,10 @@ impl MVPlane {
                     // No refinement needed
                 }
                 Subpel::Half => {
-                    self.refine_ext_pel2(src_2x, src_2x_pitch, is_ext_padded, dest);
+                    self.refine_ext_pel2(src_2x, src_2x_pitch, is_ext_padded, pad_mode, dest);
                 }
                 Subpel::Quarter => {
-                    self.refine_ext_pel4(src_2x, src_2x_pitch, is_ext_padded, dest);
+                    self.refine_ext_pel4(src_2x, src_2x_pitch, is_ext_padded, pad_mode, dest);
                 }
             }
         }
@@ -140,7 +150,7 @@ impl MVPlane {
     pub fn reduce_to<T: Pixel>(
         &self,
         reduced_plane: &mut MVPlane,
-        filter: ReduceFilter,
+        reduce: ReduceFn<T>,
         dest: &mut [T],
         src: &[T],
         dest_pitch: NonZeroUsize,
@@ -155,20 +165,12 @@ impl MVPlane {
         let dest =
             &mut dest[reduced_plane.subpel_window_offsets[0] + reduced_plane.offset_padding..];
         let src = &src[self.subpel_window_offsets[0] + self.offset_padding..];
-        let reduce: ReduceFn<T> = match filter {
-            ReduceFilter::Average => reduce_average,
-            ReduceFilter::Triangle => reduce_triangle,
-            ReduceFilter::Bilinear => reduce_bilinear,
-            ReduceFilter::Quadratic => reduce_quadratic,
-            ReduceFilter::Cubic => reduce_cubic,
-        };
-
         reduce(dest, src, dest_pitch, src_pitch, dest_width, dest_height);
 
         reduced_plane.is_filled = true;
     }
 
-    pub fn pad<T: Pixel>(&mut self, src: &mut [T]) {
+    pub fn pad<T: Pixel>(&mut self, pad_mode: PadMode, src: &mut [T]) {
         if !self.is_padded {
             pad_reference_frame(
                 self.subpel_window_offsets[0],
@@ -177,6 +179,7 @@ impl MVPlane {
                 self.vpad,
                 self.width,
                 self.height,
+                pad_mode,
                 src,
             );
             self.is_padded = true;
@@ -189,39 +192,16 @@ impl MVPlane {
         plane: &mut [T],
         src_offset: usize,
         dest_offset: usize,
-        refine_fn: RefineFn<T>,
-        pitch: NonZeroUsize,
-        padded_width: NonZeroUsize,
-        padded_height: NonZeroUsize,
-        bits_per_sample: NonZeroU8,
+        refine_fn: impl FnOnce(&mut [T], &[T]),
     ) {
-        debug_assert!(
-            bits_per_sample.get() as usize > (size_of::<T>() - 1) * 8
-                && (bits_per_sample.get() as usize <= size_of::<T>() * 8)
-        );
-
         if src_offset <= dest_offset {
             // Source comes before destination, split at destination
             let (left, right) = plane.split_at_mut(dest_offset);
-            refine_fn(
-                right,
-                &left[src_offset..],
-                pitch,
-                padded_width,
-                padded_height,
-                bits_per_sample,
-            );
+            refine_fn(right, &left[src_offset..]);
         } else {
             // Destination comes before source, split at source
             let (left, right) = plane.split_at_mut(src_offset);
-            refine_fn(
-                &mut left[dest_offset..],
-                right,
-                pitch,
-                padded_width,
-                padded_height,
-                bits_per_sample,
-            );
+            refine_fn(&mut left[dest_offset..], right);
         }
     }
 
@@ -304,22 +284,40 @@ impl MVPlane {
             return;
         }
 
-        let refine: [RefineFn<T>; 3] = match method {
-            SubpelMethod::Bilinear => [
-                refine_horizontal_bilinear,
-                refine_vertical_bilinear,
-                refine_diagonal_bilinear,
-            ],
-            SubpelMethod::Bicubic => [
-                refine_horizontal_bicubic,
-                refine_vertical_bicubic,
-                refine_horizontal_bicubic,
-            ],
-            SubpelMethod::Wiener => [
-                refine_horizontal_wiener,
-                refine_vertical_wiener,
-                refine_horizontal_wiener,
-            ],
+        let (pitch, width, height, bits) = (
+            self.pitch,
+            self.padded_width,
+            self.padded_height,
+            self.bits_per_sample,
+        );
+        // Horizontal, vertical and diagonal passes
+        let refine = |pass: usize, dest: &mut [T], src: &[T]| {
+            let refine: [RefineFn<T>; 3] = match method {
+                SubpelMethod::Bilinear => [
+                    refine_horizontal_bilinear,
+                    refine_vertical_bilinear,
+                    refine_diagonal_bilinear,
+                ],
+                SubpelMethod::Bicubic => [
+                    refine_horizontal_bicubic,
+                    refine_vertical_bicubic,
+                    refine_horizontal_bicubic,
+                ],
+                SubpelMethod::Wiener => [
+                    refine_horizontal_wiener,
+                    refine_vertical_wiener,
+                    refine_horizontal_wiener,
+                ],
+                SubpelMethod::Custom(kernel) => {
+                    if pass == 1 {
+                        refine_vertical_custom(dest, src, pitch, width, height, bits, kernel);
+                    } else {
+                        refine_horizontal_custom(dest, src, pitch, width, height, bits, kernel);
+                    }
+                    return;
+                }
+            };
+            refine[pass](dest, src, pitch, width, height, bits);
         };
 
         let mut src_offsets = [0; 3];
@@ -354,16 +352,9 @@ impl MVPlane {
 
         // Use the helper function to avoid clones
         for i in 0..3 {
-            Self::refine_with_split(
-                plane,
-                src_offsets[i],
-                dest_offsets[i],
-                refine[i],
-                self.pitch,
-                self.padded_width,
-                self.padded_height,
-                self.bits_per_sample,
-            );
+            Self::refine_with_split(plane, src_offsets[i], dest_offsets[i], |dest, src| {
+                refine(i, dest, src);
+            });
         }
 
         // Use helper function to avoid clones in average2 calls
@@ -638,7 +629,9 @@ pub fn plane_width_luma(
 /// - `y_ratio_uv`: Vertical chroma subsampling ratio
 ///
 /// # Returns
-/// The pixel offset where the specified plane begins in the superframe
+/// The pixel offset where the specified plane begins in the superframe.
+/// The arithmetic saturates at `usize::MAX` instead of wrapping around,
+/// so an offset too large to address can never be mistaken for a small one.
 #[must_use]
 pub fn plane_super_offset(
     chroma: bool,
@@ -657,11 +650,14 @@ pub fn plane_super_offset(
     if level == 0 {
         offset = 0;
     } else {
-        let pel = u8::from(pel) as usize;
+        let pel = NonZeroUsize::from(pel).get();
         let plane_pitch_val = plane_pitch.get();
         let src_height_val = src_height.get();
         let y_ratio_uv_val = y_ratio_uv.get() as usize;
-        offset = pel * pel * plane_pitch_val * (src_height_val + vpad * 2);
+        let vpad_rows = vpad.saturating_mul(2);
+        offset = (pel * pel)
+            .saturating_mul(plane_pitch_val)
+            .saturating_mul(src_height_val.saturating_add(vpad_rows));
 
         for i in 1..level {
             // NOTE: We use `src_height` here (not a running `height` variable) because
@@ -673,7 +669,7 @@ pub fn plane_super_offset(
                     src_height.saturating_mul(y_ratio_uv.into()),
                     i,
                     y_ratio_uv,
-                    vpad * y_ratio_uv_val,
+                    vpad.saturating_mul(y_ratio_uv_val),
                 )
                 .get()
                     / y_ratio_uv_val
@@ -681,9 +677,104 @@ pub fn plane_super_offset(
                 plane_height_luma(src_height, i, y_ratio_uv, vpad).get()
             };
 
-            offset += plane_pitch_val * (height + vpad * 2);
+            offset = offset
+                .saturating_add(plane_pitch_val.saturating_mul(height.saturating_add(vpad_rows)));
         }
     }
 
     offset
 }
+
+/// The location and dimensions of a single plane at one hierarchical level
+/// within a superframe, as produced by `Super`.
+///
+/// All values are in pixels. For level 0, only the first (full-pel) sub-pixel window
+/// is described; the remaining windows follow it contiguously.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SuperPlaneLayout {
+    /// Offset of the top-left padding pixel from the start of the superframe plane
+    pub offset: usize,
+    /// Width of the plane at this level, excluding padding
+    pub width: NonZeroUsize,
+    /// Height of the plane at this level, excluding padding
+    pub height: NonZeroUsize,
+    /// Horizontal padding on each side of the plane
+    pub hpad: usize,
+    /// Vertical padding on each side of the plane
+    pub vpad: usize,
+    /// Number of pixels per row of the superframe plane
+    pub pitch: NonZeroUsize,
+}
+
+impl SuperPlaneLayout {
+    /// Computes the layout of a plane at `level`, using the same arithmetic
+    /// as `Super` does when building the superframe.
+    ///
+    /// `src_width`, `src_height`, `hpad` and `vpad` are the luma values passed to `Super`,
+    /// while `pitch` is the pitch of the requested plane.
+    #[must_use]
+    pub fn new(
+        chroma: bool,
+        src_width: NonZeroUsize,
+        src_height: NonZeroUsize,
+        level: usize,
+        pel: Subpel,
+        hpad: usize,
+        vpad: usize,
+        x_ratio_uv: NonZeroU8,
+        y_ratio_uv: NonZeroU8,
+        pitch: NonZeroUsize,
+    ) -> Self {
+        let width = plane_width_luma(src_width, level, x_ratio_uv, hpad);
+        let height = plane_height_luma(src_height, level, y_ratio_uv, vpad);
+        if !chroma {
+            return Self {
+                offset: plane_super_offset(false, src_height, level, pel, vpad, pitch, y_ratio_uv),
+                width,
+                height,
+                hpad,
+                vpad,
+                pitch,
+            };
+        }
+
+        let x_ratio_uv_val = x_ratio_uv.get() as usize;
+        let y_ratio_uv_val = y_ratio_uv.get() as usize;
+        let chroma_vpad = vpad / y_ratio_uv_val;
+        // SAFETY: Height must be at least the value of its ratio
+        let chroma_src_height =
+            unsafe { NonZeroUsize::new_unchecked(src_height.get() / y_ratio_uv_val) };
+        Self {
+            offset: plane_super_offset(
+                true,
+                chroma_src_height,
+                level,
+                pel,
+                chroma_vpad,
+                pitch,
+                y_ratio_uv,
+            ),
+            // SAFETY: Luma dimensions at every level are at least the value of the ratio
+            width: unsafe { NonZeroUsize::new_unchecked(width.get() / x_ratio_uv_val) },
+            // SAFETY: Luma dimensions at every level are at least the value of the ratio
+            height: unsafe { NonZeroUsize::new_unchecked(height.get() / y_ratio_uv_val) },
+            hpad: hpad / x_ratio_uv_val,
+            vpad: chroma_vpad,
+            pitch,
+        }
+    }
+
+    /// Offset of the first non-padding pixel from the start of the superframe plane.
+    #[must_use]
+    pub fn offset_data(&self) -> usize {
+        self.offset + self.pitch.get() * self.vpad + self.hpad
+    }
+
+    /// Number of pixels from [`Self::offset`] to the last padding pixel, inclusive.
+    #[must_use]
+    pub fn padded_len(&self) -> usize {
+        self.pitch.get() * (self.height.get() + 2 * self.vpad - 1)
+            + self.width.get()
+            + 2 * self.hpad
+    }
+}
@@ -4,7 +4,10 @@
 use std::num::{NonZeroU8, NonZeroUsize};
 
 use super::*;
-use crate::params::{ReduceFilter, Subpel, SubpelMethod};
+use crate::{
+    params::{PadMode, ReduceFilter, Subpel, SubpelKernel, SubpelMethod},
+    reduce::reduce_fn,
+};
 
 // Helper function to create a test MVPlane
 fn create_test_mvplane(
@@ -108,7 +111,7 @@ fn mvplane_fill_plane_basic() {
     let mut dest = vec![0u8; total_size];
 
     assert!(!plane.is_filled);
-    plane.fill_plane(&src_data, src_pitch, &mut dest);
+    plane.fill_plane(&src_data, src_pitch, &mut dest).unwrap();
     assert!(plane.is_filled);
 
     // Verify data was copied correctly
@@ -133,7 +136,7 @@ fn mvplane_fill_plane_already_filled() {
     let mut dest = vec![0u8; total_size];
 
     // Fill once
-    plane.fill_plane(&src_data, src_pitch, &mut dest);
+    plane.fill_plane(&src_data, src_pitch, &mut dest).unwrap();
     assert!(plane.is_filled);
 
     // Modify dest to verify it doesn't get overwritten
@@ -142,7 +145,7 @@ fn mvplane_fill_plane_already_filled() {
 
     // Try to fill again - should be a no-op
     let src_data2 = vec![10u8, 20, 30, 40];
-    plane.fill_plane(&src_data2, src_pitch, &mut dest);
+    plane.fill_plane(&src_data2, src_pitch, &mut dest).unwrap();
 
     // Original modification should still be there
     assert_eq!(dest[offset], 99);
@@ -160,7 +163,7 @@ fn mvplane_fill_plane_different_pitch() {
         plane.pitch.get() * (plane.height.get() + 2 * plane.vpad) + plane.offset_padding;
     let mut dest = vec![0u8; total_size];
 
-    plane.fill_plane(&src_data, src_pitch, &mut dest);
+    plane.fill_plane(&src_data, src_pitch, &mut dest).unwrap();
 
     // Verify only the actual width was copied, not the padding
     let offset = plane.subpel_window_offsets[0] + plane.offset_padding;
@@ -181,7 +184,7 @@ fn mvplane_refine_ext_full_pel() {
     let mut dest = vec![0u8; 128];
 
     assert!(!plane.is_refined);
-    plane.refine_ext(&src_2x, src_2x_pitch, true, &mut dest);
+    plane.refine_ext(&src_2x, src_2x_pitch, true, PadMode::Replicate, &mut dest);
     assert!(plane.is_refined);
 
     // For Full pel, no actual refinement should occur
@@ -196,7 +199,7 @@ fn mvplane_refine_ext_half_pel() {
     let mut dest = vec![0u8; 64];
 
     assert!(!plane.is_refined);
-    plane.refine_ext(&src_2x, src_2x_pitch, true, &mut dest);
+    plane.refine_ext(&src_2x, src_2x_pitch, true, PadMode::Replicate, &mut dest);
     assert!(plane.is_refined);
 }
 
@@ -212,7 +215,7 @@ fn mvplane_refine_ext_quarter_pel() {
     let mut dest = vec![0u8; buffer_size];
 
     assert!(!plane.is_refined);
-    plane.refine_ext(&src_2x, src_2x_pitch, true, &mut dest);
+    plane.refine_ext(&src_2x, src_2x_pitch, true, PadMode::Replicate, &mut dest);
     assert!(plane.is_refined);
 }
 
@@ -228,11 +231,11 @@ fn mvplane_refine_ext_already_refined() {
     let mut dest = vec![0u8; buffer_size];
 
     // Refine once
-    plane.refine_ext(&src_2x, src_2x_pitch, true, &mut dest);
+    plane.refine_ext(&src_2x, src_2x_pitch, true, PadMode::Replicate, &mut dest);
     assert!(plane.is_refined);
 
     // Try to refine again - should be a no-op
-    plane.refine_ext(&src_2x, src_2x_pitch, true, &mut dest);
+    plane.refine_ext(&src_2x, src_2x_pitch, true, PadMode::Replicate, &mut dest);
     assert!(plane.is_refined);
 }
 
@@ -250,7 +253,7 @@ fn mvplane_reduce_to_already_filled() {
 
     plane.reduce_to(
         &mut reduced_plane,
-        ReduceFilter::Average,
+        reduce_fn(ReduceFilter::Average),
         &mut dest,
         &src,
         NonZeroUsize::new(6).unwrap(),
@@ -279,7 +282,7 @@ fn mvplane_reduce_to_basic() {
     assert!(!reduced_plane.is_filled);
     plane.reduce_to(
         &mut reduced_plane,
-        ReduceFilter::Average,
+        reduce_fn(ReduceFilter::Average),
         &mut dest,
         &src,
         NonZeroUsize::new(4).unwrap(),
@@ -309,7 +312,7 @@ fn mvplane_reduce_to_different_filters() {
 
         plane.reduce_to(
             &mut reduced_plane,
-            filter,
+            reduce_fn(filter),
             &mut dest,
             &src,
             NonZeroUsize::new(2).unwrap(),
@@ -338,7 +341,7 @@ fn mvplane_pad_basic() {
     src[start_offset + plane.pitch.get() + 1] = 4;
 
     assert!(!plane.is_padded);
-    plane.pad(&mut src);
+    plane.pad(PadMode::Replicate, &mut src);
     assert!(plane.is_padded);
 
     // Verify padding was applied (edges should be replicated)
@@ -358,14 +361,14 @@ fn mvplane_pad_already_padded() {
     let mut src = vec![0u8; total_size];
 
     // Pad once
-    plane.pad(&mut src);
+    plane.pad(PadMode::Replicate, &mut src);
     assert!(plane.is_padded);
 
     // Modify a padding area to verify it doesn't get overwritten
     src[0] = 99;
 
     // Try to pad again - should be a no-op
-    plane.pad(&mut src);
+    plane.pad(PadMode::Replicate, &mut src);
     assert_eq!(src[0], 99);
 }
 
@@ -419,6 +422,7 @@ fn mvplane_refine_different_subpel_methods() {
         SubpelMethod::Bilinear,
         SubpelMethod::Bicubic,
         SubpelMethod::Wiener,
+        SubpelMethod::Custom(SubpelKernel::WIENER),
     ] {
         let mut plane = create_test_mvplane(4, 4, Subpel::Half, 2, 2, 8, 0, 8);
 
@@ -433,6 +437,21 @@ fn mvplane_refine_different_subpel_methods() {
     }
 }
 
+#[test]
+fn mvplane_refine_custom_wiener_taps_match_wiener() {
+    for pel in [Subpel::Half, Subpel::Quarter] {
+        let mut wiener = create_test_mvplane(24, 20, pel, 8, 8, 10, 0, 40);
+        let mut custom = wiener.clone();
+        let len = wiener.subpel_window_offsets.iter().max().unwrap() + 40 * 36;
+        let mut wiener_data: Vec<u16> = (0..len).map(|i| ((i * 7919) % 1024) as u16).collect();
+        let mut custom_data = wiener_data.clone();
+
+        wiener.refine(SubpelMethod::Wiener, &mut wiener_data);
+        custom.refine(SubpelMethod::Custom(SubpelKernel::WIENER), &mut custom_data);
+        assert_eq!(custom_data, wiener_data, "{pel:?}");
+    }
+}
+
 #[test]
 fn mvplane_refine_already_refined() {
     let mut plane = create_test_mvplane(4, 4, Subpel::Half, 2, 2, 8, 0, 8);
@@ -845,3 +864,154 @@ fn mvplane_refine_different_pel_methods_no_clones() {
         }
     }
 }
+
+fn super_plane_layouts(
+    chroma: bool,
+    levels: usize,
+    ratio_uv: u8,
+    pitch: usize,
+) -> Vec<SuperPlaneLayout> {
+    (0..levels)
+        .map(|level| {
+            SuperPlaneLayout::new(
+                chroma,
+                NonZeroUsize::new(64).unwrap(),
+                NonZeroUsize::new(48).unwrap(),
+                level,
+                Subpel::Half,
+                8,
+                8,
+                NonZeroU8::new(ratio_uv).unwrap(),
+                NonZeroU8::new(ratio_uv).unwrap(),
+                NonZeroUsize::new(pitch).unwrap(),
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn super_plane_layout_yuv420() {
+    let luma = super_plane_layouts(false, 5, 2, 80);
+    let expected_luma = [
+        (0, 64, 48),
+        (20480, 32, 24),
+        (23680, 16, 12),
+        (25920, 8, 6),
+        (27680, 4, 4),
+    ];
+    for (layout, &(offset, width, height)) in luma.iter().zip(&expected_luma) {
+        assert_eq!(layout.offset, offset);
+        assert_eq!(layout.width.get(), width);
+        assert_eq!(layout.height.get(), height);
+        assert_eq!(layout.hpad, 8);
+        assert_eq!(layout.vpad, 8);
+        assert_eq!(layout.pitch.get(), 80);
+    }
+
+    let chroma = super_plane_layouts(true, 5, 2, 40);
+    let expected_chroma = [
+        (0, 32, 24),
+        (5120, 16, 12),
+        (5920, 8, 6),
+        (6480, 4, 3),
+        (6920, 2, 2),
+    ];
+    for (layout, &(offset, width, height)) in chroma.iter().zip(&expected_chroma) {
+        assert_eq!(layout.offset, offset);
+        assert_eq!(layout.width.get(), width);
+        assert_eq!(layout.height.get(), height);
+        assert_eq!(layout.hpad, 4);
+        assert_eq!(layout.vpad, 4);
+        assert_eq!(layout.pitch.get(), 40);
+    }
+}
+
+#[test]
+fn super_plane_layout_gray() {
+    let luma = super_plane_layouts(false, 6, 1, 80);
+    let expected = [
+        (0, 64, 48),
+        (20480, 32, 24),
+        (23680, 16, 12),
+        (25920, 8, 6),
+        (27680, 4, 3),
+        (29200, 2, 2),
+    ];
+    for (layout, &(offset, width, height)) in luma.iter().zip(&expected) {
+        assert_eq!(layout.offset, offset);
+        assert_eq!(layout.width.get(), width);
+        assert_eq!(layout.height.get(), height);
+        assert_eq!(layout.hpad, 8);
+        assert_eq!(layout.vpad, 8);
+    }
+}
+
+#[test]
+fn super_plane_layout_levels_are_contiguous() {
+    for (chroma, ratio_uv, pitch) in [(false, 2, 80), (true, 2, 40), (false, 1, 80)] {
+        let layouts = super_plane_layouts(chroma, 5, ratio_uv, pitch);
+        let pel = 2;
+        for pair in layouts.windows(2) {
+            let windows = if pair[0].offset == 0 { pel * pel } else { 1 };
+            let padded_height = pair[0].height.get() + 2 * pair[0].vpad;
+            assert_eq!(
+                pair[1].offset,
+                pair[0].offset + windows * pitch * padded_height
+            );
+            assert!(pair[0].offset + windows * pair[0].padded_len() <= pair[1].offset);
+        }
+    }
+}
+
+#[test]
+fn super_plane_layout_matches_mvplane() {
+    let width = NonZeroUsize::new(64).unwrap();
+    let height = NonZeroUsize::new(48).unwrap();
+    let ratio_uv = NonZeroU8::new(2).unwrap();
+    let pitch = [80, 40, 40].map(|p| NonZeroUsize::new(p).unwrap());
+
+    for level in 0..5 {
+        let layouts: Vec<_> = (0..3)
+            .map(|plane| {
+                SuperPlaneLayout::new(
+                    plane > 0,
+                    width,
+                    height,
+                    level,
+                    Subpel::Half,
+                    8,
+                    8,
+                    ratio_uv,
+                    ratio_uv,
+                    pitch[plane],
+                )
+            })
+            .collect();
+
+        for (plane, layout) in layouts.iter().enumerate() {
+            let mvplane = MVPlane::new(
+                layout.width,
+                layout.height,
+                if level == 0 {
+                    Subpel::Half
+                } else {
+                    Subpel::Full
+                },
+                layout.hpad,
+                layout.vpad,
+                NonZeroU8::new(8).unwrap(),
+                layout.offset,
+                pitch[plane],
+            )
+            .unwrap();
+            assert_eq!(
+                layout.offset_data(),
+                mvplane.subpel_window_offsets[0] + mvplane.offset_padding
+            );
+            assert_eq!(
+                layout.width.get(),
+                plane_width_luma(width, level, ratio_uv, 8).get() / if plane > 0 { 2 } else { 1 }
+            );
+        }
+    }
+}
@@ -3,10 +3,10 @@ mod tests;
 
 use std::num::{NonZeroU8, NonZeroUsize};
 
-use anyhow::{Result, bail};
+use anyhow::{Result, anyhow, bail};
 use vapoursynth::{
     format::{ColorFamily, Format, SampleType},
-    frame::{FrameRef, FrameRefMut},
+    frame::{Frame, FrameRef, FrameRefMut},
     node::Node,
     plugins::Filter,
     prelude::Property,
@@ -14,10 +14,11 @@ use vapoursynth::{
 };
 
 use crate::{
-    mv_gof::MVGroupOfFrames,
-    mv_plane::{plane_height_luma, plane_super_offset, plane_width_luma},
-    params::{MVPlaneSet, ReduceFilter, Subpel, SubpelMethod},
-    util::{Pixel, plane_with_padding, plane_with_padding_mut},
+    error::{ZooMvError, parse_param},
+    mv_gof::{GofPool, MVGroupOfFrames},
+    mv_plane::{SuperPlaneLayout, plane_height_luma, plane_super_offset, plane_width_luma},
+    params::{MVPlaneSet, PadMode, ReduceFilter, Subpel, SubpelKernel, SubpelMethod},
+    util::{Pixel, check_clip_frame, plane_pitches, plane_with_padding, plane_with_padding_mut},
 };
 
 /// Get source clip and prepare special "super" clip with multilevel
@@ -29,6 +30,10 @@ pub struct Super<'core> {
     /// Horizontal padding added to source frame (both left and right).
     /// Small padding is added for more correct motion estimation near frame
     /// borders.
+    ///
+    /// Must be a multiple of the chroma subsampling, and unless it is 0,
+    /// at least the radius of the `sharp` kernel when `pel` is greater than 1.
+    /// The same applies to `vpad`.
     hpad: usize,
     /// Vertical padding added to source frame (both top and bottom).
     vpad: usize,
@@ -45,25 +50,43 @@ pub struct Super<'core> {
     /// enough (coarser levels are not used).
     ///
     /// Default `0` = auto, all levels are produced
+    ///
+    /// With `maxmem`, the coarsest levels are dropped until a superframe
+    /// fits in that many bytes.
     levels: usize,
     /// If set to true, it allows to also prepare chroma planes in super clip.
+    ///
+    /// When false, chroma is never filled, reduced, padded or refined,
+    /// and the chroma planes of the superframe are left zeroed.
     chroma: bool,
     /// subpixel interpolation method for pel=2,4.
     ///
     /// - 0 for soft interpolation (bilinear)
     /// - 1 for bicubic interpolation (4-tap Catmull-Rom)
     /// - 2 for sharper Wiener interpolation (6-tap, similar o Lanczos).
+    ///
+    /// `sharp_coeffs` replaces it with a custom 6-tap kernel, given as
+    /// six integer taps summing to 32, such as `[1, -5, 20, 20, -5, 1]` for Wiener.
     sharp: SubpelMethod,
     /// Hierarchical levels smoothing and reducing (halving) filter.
     ///
-    /// - 0 is simple 4 pixels averaging like unfiltered `SimpleResize`
+    /// - 0 is simple 4 pixels averaging like unfiltered `SimpleResize`,
+    ///   rounded to the nearest value as in mvtools
     /// - 1 is triangle (shifted) filter like `ReduceBy2` for more smoothing
     ///   (decrease aliasing)
     /// - 2 is triangle filter like `BilinearResize` for even more smoothing
     /// - 3 is quadratic filter for even more smoothing
     /// - 4 is cubic filter like `BicubicResize(b=1, c=0)` for even more
     ///   smoothing
+    /// - 5 is the 4 pixels averaging of 0, rounded down instead, for matching
+    ///   resizers that truncate the average
     rfilter: ReduceFilter,
+    /// How the padding around each plane is filled.
+    ///
+    /// - 0 repeats the edge pixels
+    /// - 1 mirrors the frame across its edges, which can reduce artifacts
+    ///   in the motion search near the frame edges
+    pad_mode: PadMode,
     /// Optional upsampled source clip instead of using internal subpixel
     /// interpolation (for `pel>1`). Pixels at rows and columns positions
     /// multiple to `pel` (0,2,4,... for `pel=2`) (without padding) must be
@@ -73,6 +96,9 @@ pub struct Super<'core> {
     /// `LanczosResize(width*2,height*2,src_left=0.25,src_top=0.25)`
     ///
     /// Another useful example is EEDI2 edge-directed resampling.
+    ///
+    /// For a YUV input, `pelclip` may also be a GRAY clip with the same bit depth,
+    /// which is then used for luma only, while chroma is interpolated internally.
     pelclip: Option<Node<'core>>,
 
     // Internal fields
@@ -84,6 +110,9 @@ pub struct Super<'core> {
     x_ratio_uv: NonZeroU8,
     y_ratio_uv: NonZeroU8,
     is_pelclip_padded: bool,
+    is_pelclip_luma_only: bool,
+    /// Level layouts reused across frame requests
+    gof_pool: GofPool,
 }
 
 impl<'core> Super<'core> {
@@ -97,42 +126,63 @@ impl<'core> Super<'core> {
         sharp: Option<i64>,
         rfilter: Option<i64>,
         pelclip: Option<Node<'core>>,
+        padmode: Option<i64>,
+        maxmem: Option<i64>,
+        sharp_coeffs: Option<&[i64]>,
     ) -> Result<Self> {
         // Parse arguments
-        let hpad = hpad.map_or(Ok(16), usize::try_from)?;
-        let vpad = vpad.map_or(Ok(16), usize::try_from)?;
-        let pel = pel.map_or(Ok(Subpel::Half), Subpel::try_from)?;
-        let mut levels = levels.map_or(Ok(0), usize::try_from)?;
+        let hpad = parse_param("Super", "hpad", hpad, 16, "at least 0")?;
+        let vpad = parse_param("Super", "vpad", vpad, 16, "at least 0")?;
+        let pel = parse_param("Super", "pel", pel, Subpel::Half, "1, 2, or 4")?;
+        let mut levels = parse_param("Super", "levels", levels, 0, "at least 0")?;
         let mut chroma = chroma.is_none_or(|chroma| chroma > 0);
-        let sharp = sharp.map_or(Ok(SubpelMethod::Wiener), SubpelMethod::try_from)?;
-        let rfilter = rfilter.map_or(Ok(ReduceFilter::Bilinear), ReduceFilter::try_from)?;
+        let sharp = match (sharp, sharp_coeffs) {
+            (Some(sharp), Some(_)) => bail!(ZooMvError::InvalidParam {
+                filter: "Super",
+                name: "sharp",
+                value: sharp,
+                expected: "omitted when `sharp_coeffs` is given",
+            }),
+            (None, Some(taps)) => match SubpelKernel::parse("Super", "sharp_coeffs", taps)? {
+                // `sharp=2` gives the same output with its dedicated kernels
+                SubpelKernel::WIENER => SubpelMethod::Wiener,
+                kernel => SubpelMethod::Custom(kernel),
+            },
+            (sharp, None) => {
+                parse_param("Super", "sharp", sharp, SubpelMethod::Wiener, "0, 1, or 2")?
+            }
+        };
+        let rfilter = parse_param("Super", "rfilter", rfilter, ReduceFilter::Bilinear, "0-5")?;
+        let pad_mode = parse_param("Super", "padmode", padmode, PadMode::Replicate, "0 or 1")?;
+        let max_mem: usize = parse_param("Super", "maxmem", maxmem, 0, "at least 0")?;
 
         // Validate video info
         let video_info = clip.info();
-        let (width, height) = match video_info.resolution {
-            vapoursynth::prelude::Property::Variable => {
-                bail!("Super: variable resolution input clips are not supported")
-            }
-            // SAFETY: width and height must be positive
-            vapoursynth::prelude::Property::Constant(resolution) => unsafe {
-                (
-                    NonZeroUsize::new_unchecked(resolution.width),
-                    NonZeroUsize::new_unchecked(resolution.height),
-                )
-            },
-        };
+        let (width, height) = constant_dimensions(video_info.resolution, "clip")?;
         let format = video_info.format;
         if format.bits_per_sample() > 16 {
-            bail!("Super: input clip must be 8-16 bits");
+            bail!(ZooMvError::UnsupportedBitDepth {
+                filter: "Super",
+                bits: format.bits_per_sample(),
+            });
         }
         if format.sample_type() != SampleType::Integer {
-            bail!("Super: input clip must be integer format");
+            bail!(ZooMvError::UnsupportedFormat {
+                filter: "Super",
+                requirement: "be integer format",
+            });
         }
-        if ![ColorFamily::YUV, ColorFamily::Gray].contains(&format.color_family())
-            || format.sub_sampling_w() > 1
-            || format.sub_sampling_h() > 1
-        {
-            bail!("Super: input clip must be GRAY, 420, 422, 440, or 444");
+        if ![ColorFamily::YUV, ColorFamily::Gray].contains(&format.color_family()) {
+            bail!(ZooMvError::UnsupportedColorFamily {
+                filter: "Super",
+                family: format.color_family(),
+            });
+        }
+        if format.sub_sampling_w() > 1 || format.sub_sampling_h() > 1 {
+            bail!(ZooMvError::UnsupportedFormat {
+                filter: "Super",
+                requirement: "be GRAY, 420, 422, 440, or 444",
+            });
         }
 
         // Compute internal parameters
@@ -147,37 +197,120 @@ impl<'core> Super<'core> {
                 NonZeroU8::new_unchecked(1 << format.sub_sampling_h()),
             )
         };
-
-        let mut levels_max = 0;
-        while plane_height_luma(height, levels_max, y_ratio_uv, vpad).get()
-            >= y_ratio_uv.get() as usize * 2
-            && plane_width_luma(width, levels_max, x_ratio_uv, hpad).get()
-                >= x_ratio_uv.get() as usize * 2
+        // Chroma planes are sized by truncating division, so any leftover luma column or row
+        // would have no chroma samples and shift every chroma level after it.
+        if width.get() % x_ratio_uv.get() as usize != 0
+            || height.get() % y_ratio_uv.get() as usize != 0
         {
-            levels_max += 1;
+            bail!(ZooMvError::UnsupportedFormat {
+                filter: "Super",
+                requirement: "have dimensions that are multiples of the chroma subsampling",
+            });
+        }
+
+        // Chroma planes are padded by the luma padding divided by the subsampling ratio,
+        // which must not be truncated for the chroma planes to stay aligned with luma.
+        for (name, pad, ratio, expected) in [
+            (
+                "hpad",
+                hpad,
+                x_ratio_uv,
+                "a multiple of the horizontal chroma subsampling",
+            ),
+            (
+                "vpad",
+                vpad,
+                y_ratio_uv,
+                "a multiple of the vertical chroma subsampling",
+            ),
+        ] {
+            if pad % ratio.get() as usize != 0 {
+                bail!(ZooMvError::InvalidParam {
+                    filter: "Super",
+                    name,
+                    value: pad as i64,
+                    expected,
+                });
+            }
+            // Without enough padding, the subpixel kernel cannot be applied to the edge pixels
+            // of any plane, and the chroma planes only get their share of the padding
+            if pel != Subpel::Full && pad != 0 {
+                if pad < sharp.radius() {
+                    bail!(ZooMvError::InvalidParam {
+                        filter: "Super",
+                        name,
+                        value: pad as i64,
+                        expected: "0 or at least the radius of the `sharp` kernel (1, 2, or 3)",
+                    });
+                }
+                if chroma && pad / (ratio.get() as usize) < sharp.radius() {
+                    bail!(ZooMvError::InvalidParam {
+                        filter: "Super",
+                        name,
+                        value: pad as i64,
+                        expected: "0 or at least the radius of the `sharp` kernel (1, 2, or 3) \
+                                   times the chroma subsampling",
+                    });
+                }
+            }
+        }
+
+        let levels_max = max_levels(width, height, hpad, vpad, x_ratio_uv, y_ratio_uv);
+        // Every level needs at least two chroma samples in each dimension
+        if levels_max == 0 {
+            bail!(ZooMvError::InputTooSmall {
+                filter: "Super",
+                width: width.get(),
+                height: height.get(),
+                min_width: x_ratio_uv.get() as usize * 2,
+                min_height: y_ratio_uv.get() as usize * 2,
+            });
         }
         if levels == 0 || levels > levels_max {
             levels = levels_max;
         }
-        debug_assert!(levels > 0);
+        if max_mem > 0 {
+            levels = levels_within_budget(
+                width,
+                height,
+                levels,
+                pel,
+                hpad,
+                vpad,
+                x_ratio_uv,
+                y_ratio_uv,
+                format.plane_count(),
+                format.bytes_per_sample() as usize,
+                max_mem,
+            )
+            .ok_or(ZooMvError::InvalidParam {
+                filter: "Super",
+                name: "maxmem",
+                value: max_mem as i64,
+                expected: "0 or enough bytes for a superframe with a single level",
+            })?;
+        }
 
         // Validate `pelclip` video info
+        let mut is_pelclip_luma_only = false;
         let (use_pelclip, is_pelclip_padded) = if let Some(ref pelclip) = pelclip {
             let pelclip_info = pelclip.info();
-            let (pelclip_w, pelclip_h) = match pelclip_info.resolution {
-                vapoursynth::prelude::Property::Variable => {
-                    bail!("Super: 'pelclip' must be constant resolution")
-                }
-                // SAFETY: width and height must be positive
-                vapoursynth::prelude::Property::Constant(resolution) => unsafe {
-                    (
-                        NonZeroUsize::new_unchecked(resolution.width),
-                        NonZeroUsize::new_unchecked(resolution.height),
-                    )
-                },
-            };
+            let (pelclip_w, pelclip_h) = constant_dimensions(pelclip_info.resolution, "pelclip")?;
             if pelclip_info.format != format {
-                bail!("Super: 'pelclip' must have same format as input clip");
+                if pelclip_info.format.color_family() == ColorFamily::Gray
+                    && format.color_family() == ColorFamily::YUV
+                    && pelclip_info.format.sample_type() == format.sample_type()
+                    && pelclip_info.format.bits_per_sample() == format.bits_per_sample()
+                {
+                    is_pelclip_luma_only = true;
+                } else {
+                    bail!(ZooMvError::IncompatibleClip {
+                        filter: "Super",
+                        clip: "pelclip",
+                        requirement: "have same format as input clip, or be GRAY with the same \
+                                      bit depth",
+                    });
+                }
             }
 
             if pel >= Subpel::Half {
@@ -190,10 +323,12 @@ impl<'core> Super<'core> {
                 {
                     (true, true)
                 } else {
-                    bail!(
-                        "Super: 'pelclip' dimensions must be multiples of the input clip's \
-                         dimensions"
-                    );
+                    bail!(ZooMvError::IncompatibleClip {
+                        filter: "Super",
+                        clip: "pelclip",
+                        requirement: "be `pel` times the size of the input clip, with or without \
+                                      padding",
+                    });
                 }
             } else {
                 (false, false)
@@ -202,20 +337,14 @@ impl<'core> Super<'core> {
             (false, false)
         };
 
-        let mut super_width = width.saturating_add(2 * hpad);
-        // SAFETY: cannot return zero as long as `levels` is positive
-        let mut super_height = unsafe {
-            NonZeroUsize::new_unchecked(
-                plane_super_offset(false, height, levels, pel, vpad, super_width, y_ratio_uv)
-                    / super_width,
-            )
+        let Some((super_width, super_height)) = super_frame_size(
+            width, height, levels, pel, hpad, vpad, x_ratio_uv, y_ratio_uv,
+        ) else {
+            bail!(ZooMvError::UnsupportedFormat {
+                filter: "Super",
+                requirement: "be small enough for its super frame to fit in a VapourSynth frame",
+            });
         };
-        if y_ratio_uv.get() == 2 && super_height.get() & 1 > 0 {
-            super_height = super_height.saturating_add(1);
-        }
-        if x_ratio_uv.get() == 2 && super_width.get() & 1 > 0 {
-            super_width = super_width.saturating_add(1);
-        }
 
         Ok(Self {
             clip,
@@ -226,6 +355,7 @@ impl<'core> Super<'core> {
             chroma,
             sharp,
             rfilter,
+            pad_mode,
             pelclip: if use_pelclip { pelclip } else { None },
             width,
             height,
@@ -235,9 +365,64 @@ impl<'core> Super<'core> {
             x_ratio_uv,
             y_ratio_uv,
             is_pelclip_padded,
+            is_pelclip_luma_only: use_pelclip && is_pelclip_luma_only,
+            gof_pool: GofPool::default(),
         })
     }
 
+    /// Gets a single plane at one hierarchical level from a frame output by this filter,
+    /// along with its layout within the superframe.
+    ///
+    /// The returned slice starts at the top-left padding pixel of the plane and ends at its
+    /// bottom-right padding pixel, with rows `layout.pitch` pixels apart.
+    /// For level 0, only the full-pel window is returned.
+    pub fn level_plane<'a, T: Pixel>(
+        &self,
+        frame: &'a Frame<'core>,
+        level: usize,
+        plane: usize,
+    ) -> Result<(&'a [T], SuperPlaneLayout)> {
+        if level >= self.levels {
+            bail!(
+                "Super: level {level} requested, but the superframe only has {} levels",
+                self.levels
+            );
+        }
+        if plane >= self.format.plane_count() {
+            bail!(
+                "Super: plane {plane} requested, but the clip only has {} planes",
+                self.format.plane_count()
+            );
+        }
+        if plane > 0 && !self.chroma {
+            bail!("Super: chroma planes requested, but the superframe was built with chroma=False");
+        }
+        if size_of::<T>() != self.format.bytes_per_sample() as usize {
+            bail!("Super: requested pixel type does not match the clip's bit depth");
+        }
+
+        let data = plane_with_padding::<T>(frame, plane)?;
+        // SAFETY: stride must be at least width and non-zero
+        let pitch = unsafe { NonZeroUsize::new_unchecked(frame.stride(plane) / size_of::<T>()) };
+        let layout = SuperPlaneLayout::new(
+            plane > 0,
+            self.width,
+            self.height,
+            level,
+            self.pel,
+            self.hpad,
+            self.vpad,
+            self.x_ratio_uv,
+            self.y_ratio_uv,
+            pitch,
+        );
+        let data = data
+            .get(layout.offset..layout.offset + layout.padded_len())
+            .ok_or_else(|| anyhow!("Super: frame is too small to contain level {level}"))?;
+
+        Ok((data, layout))
+    }
+
     fn get_frame_internal<T: Pixel>(
         &self,
         core: vapoursynth::core::CoreRef<'core>,
@@ -248,6 +433,7 @@ impl<'core> Super<'core> {
             .clip
             .get_frame_filter(context, n)
             .expect("Super: called get_frame_filter before request_frame_filter (clip)");
+        check_clip_frame("Super", "clip", &self.clip, n, &src)?;
 
         let src_pel = self.pelclip.as_ref().map(|pelclip| {
             pelclip.get_frame_filter(context, n).expect(
@@ -255,6 +441,9 @@ impl<'core> Super<'core> {
                  should not happen!",
             )
         });
+        if let (Some(pelclip), Some(src_pel)) = (&self.pelclip, &src_pel) {
+            check_clip_frame("Super", "pelclip", pelclip, n, src_pel)?;
+        }
 
         // SAFETY: We write to the planes before returning
         let mut dest = unsafe {
@@ -287,70 +476,80 @@ impl<'core> Super<'core> {
         } else {
             MVPlaneSet::YPLANE
         };
-        // SAFETY: strides must be at least width and non-zero
-        let dest_pitch = unsafe {
-            [
-                NonZeroUsize::new_unchecked(dest.stride(0) / bytes_per_sample),
-                NonZeroUsize::new_unchecked(dest.stride(1) / bytes_per_sample),
-                NonZeroUsize::new_unchecked(dest.stride(2) / bytes_per_sample),
-            ]
-        };
-        let mut src_gof = MVGroupOfFrames::new(
-            self.levels,
-            self.width,
-            self.height,
-            self.pel,
-            self.hpad,
-            self.vpad,
-            mode_yuv,
-            self.x_ratio_uv,
-            self.y_ratio_uv,
-            NonZeroU8::try_from(self.format.bits_per_sample())?,
-            &dest_pitch,
-            self.format,
-        )?;
+        let dest_pitch = plane_pitches::<T>(&dest);
+        let mut src_gof = self.gof_pool.take(&dest_pitch, || {
+            MVGroupOfFrames::new(
+                self.levels,
+                self.width,
+                self.height,
+                self.pel,
+                self.hpad,
+                self.vpad,
+                mode_yuv,
+                self.x_ratio_uv,
+                self.y_ratio_uv,
+                NonZeroU8::try_from(self.format.bits_per_sample())?,
+                &dest_pitch,
+                self.format,
+            )
+        })?;
 
         for plane in 0..self.format.plane_count() {
             if let Some(plane_ref) = src_gof.frames[0].planes.get_mut(plane) {
-                plane_ref.fill_plane(
-                    plane_with_padding::<T>(&src, plane)
-                        .expect("Super: source plane should exist but does not"),
-                    // SAFETY: stride must be at least width and non-zero
-                    unsafe { NonZeroUsize::new_unchecked(src.stride(plane) / bytes_per_sample) },
-                    plane_with_padding_mut(&mut dest, plane)
-                        .expect("Super: destination plane should exist but does not"),
-                );
+                plane_ref
+                    .fill_plane(
+                        plane_with_padding::<T>(&src, plane)
+                            .expect("Super: source plane should exist but does not"),
+                        // SAFETY: stride must be at least width and non-zero
+                        unsafe {
+                            NonZeroUsize::new_unchecked(src.stride(plane) / bytes_per_sample)
+                        },
+                        plane_with_padding_mut(&mut dest, plane)
+                            .expect("Super: destination plane should exist but does not"),
+                    )
+                    .map_err(|e| anyhow!("Super: {e}"))?;
             }
         }
 
         let planes = [MVPlaneSet::YPLANE, MVPlaneSet::UPLANE, MVPlaneSet::VPLANE];
-        src_gof.reduce::<T>(mode_yuv, self.rfilter, &mut dest);
-        src_gof.pad::<T>(mode_yuv, &mut dest);
+        src_gof.reduce::<T, _>(mode_yuv, self.rfilter, self.pad_mode, &mut *dest);
+        src_gof.pad::<T, _>(mode_yuv, self.pad_mode, &mut *dest);
 
         if let Some(pel_clip) = src_pel.as_ref() {
+            // A GRAY pelclip only provides luma, so chroma is refined internally
+            let pel_planes = if self.is_pelclip_luma_only {
+                src_gof.refine::<T, _>(
+                    mode_yuv.difference(MVPlaneSet::YPLANE),
+                    self.sharp,
+                    &mut *dest,
+                );
+                1
+            } else {
+                self.format.plane_count()
+            };
             let src_frames = &mut src_gof.frames[0];
 
-            for plane in 0..self.format.plane_count() {
+            // Without chroma, the group only holds the luma plane
+            for plane in (0..pel_planes).filter(|&plane| mode_yuv.contains(planes[plane])) {
                 let src_pel = plane_with_padding::<T>(pel_clip, plane)
                     .expect("Super: pelclip plane should exist but does not");
                 // SAFETY: stride must be at least width and non-zero
                 let src_pel_pitch = unsafe {
                     NonZeroUsize::new_unchecked(pel_clip.stride(plane) / bytes_per_sample)
                 };
-                let src_plane = &mut src_frames.planes[plane];
-                if !(mode_yuv & planes[plane]).is_empty() {
-                    src_plane.refine_ext(
-                        src_pel,
-                        src_pel_pitch,
-                        self.is_pelclip_padded,
-                        plane_with_padding_mut(&mut dest, plane)
-                            .expect("Super: destination plane should exist but does not"),
-                    );
-                }
+                src_frames.planes[plane].refine_ext(
+                    src_pel,
+                    src_pel_pitch,
+                    self.is_pelclip_padded,
+                    self.pad_mode,
+                    plane_with_padding_mut(&mut dest, plane)
+                        .expect("Super: destination plane should exist but does not"),
+                );
             }
         } else {
-            src_gof.refine::<T>(mode_yuv, self.sharp, &mut dest);
+            src_gof.refine::<T, _>(mode_yuv, self.sharp, &mut *dest);
         }
+        self.gof_pool.give_back(dest_pitch, src_gof);
 
         if n == 0 {
             // Set properties for the first frame
@@ -409,3 +608,129 @@ impl<'core> Filter<'core> for Super<'core> {
         }
     }
 }
+
+/// Reads the dimensions of `clip`, which must have a constant resolution.
+///
+/// VapourSynth marks a variable resolution with a zero width and height,
+/// so a resolution with only one of them zero is rejected the same way.
+fn constant_dimensions(
+    resolution: Property<Resolution>,
+    clip: &'static str,
+) -> Result<(NonZeroUsize, NonZeroUsize)> {
+    let variable = ZooMvError::VariableFormat {
+        filter: "Super",
+        clip,
+    };
+    let Property::Constant(resolution) = resolution else {
+        bail!(variable);
+    };
+    Ok((
+        NonZeroUsize::new(resolution.width).ok_or(variable)?,
+        NonZeroUsize::new(resolution.height).ok_or(variable)?,
+    ))
+}
+
+/// Computes the maximum number of hierarchical levels for a clip,
+/// stopping once a level would be smaller than two chroma samples in either direction.
+#[must_use]
+pub(crate) fn max_levels(
+    width: NonZeroUsize,
+    height: NonZeroUsize,
+    hpad: usize,
+    vpad: usize,
+    x_ratio_uv: NonZeroU8,
+    y_ratio_uv: NonZeroU8,
+) -> usize {
+    let mut levels = 0;
+    while plane_height_luma(height, levels, y_ratio_uv, vpad).get() >= y_ratio_uv.get() as usize * 2
+        && plane_width_luma(width, levels, x_ratio_uv, hpad).get() >= x_ratio_uv.get() as usize * 2
+    {
+        levels += 1;
+    }
+    levels
+}
+
+/// Finds the most levels, up to `levels`, for which a whole superframe fits in `max_bytes`,
+/// counting all `plane_count` planes with `bytes_per_sample` bytes per sample.
+///
+/// Returns `None` if not even the finest level fits.
+#[must_use]
+pub(crate) fn levels_within_budget(
+    width: NonZeroUsize,
+    height: NonZeroUsize,
+    levels: usize,
+    pel: Subpel,
+    hpad: usize,
+    vpad: usize,
+    x_ratio_uv: NonZeroU8,
+    y_ratio_uv: NonZeroU8,
+    plane_count: usize,
+    bytes_per_sample: usize,
+    max_bytes: usize,
+) -> Option<usize> {
+    (1..=levels).rev().find(|&levels| {
+        super_frame_size(
+            width, height, levels, pel, hpad, vpad, x_ratio_uv, y_ratio_uv,
+        )
+        .is_some_and(|(super_width, super_height)| {
+            let luma = super_width.get() * super_height.get();
+            let chroma = luma / (x_ratio_uv.get() as usize * y_ratio_uv.get() as usize);
+            (luma + chroma * (plane_count - 1)).saturating_mul(bytes_per_sample) <= max_bytes
+        })
+    })
+}
+
+/// The largest width or height of a super frame, which VapourSynth stores as a C `int`.
+pub(crate) const MAX_SUPER_DIMENSION: usize = i32::MAX as usize;
+
+/// Computes the dimensions of the frames output by `Super`.
+///
+/// The luma plane holds every level stacked vertically, with the subpixel windows
+/// of level 0 first. Each dimension is rounded up to a multiple of its subsampling ratio,
+/// so that the chroma planes are exactly `1 / ratio` of the luma plane.
+///
+/// `levels` must be between 1 and [`max_levels`].
+/// Returns `None` if either dimension would exceed [`MAX_SUPER_DIMENSION`],
+/// or if the luma plane would not fit in memory with 16-bit samples.
+#[must_use]
+pub(crate) fn super_frame_size(
+    width: NonZeroUsize,
+    height: NonZeroUsize,
+    levels: usize,
+    pel: Subpel,
+    hpad: usize,
+    vpad: usize,
+    x_ratio_uv: NonZeroU8,
+    y_ratio_uv: NonZeroU8,
+) -> Option<(NonZeroUsize, NonZeroUsize)> {
+    debug_assert!(levels > 0);
+
+    let super_width = width.checked_add(hpad.checked_mul(2)?)?;
+    // The offset saturates instead of overflowing, which results in a height above the limit.
+    // It cannot be zero as long as `levels` is positive.
+    let super_height = NonZeroUsize::new(
+        plane_super_offset(false, height, levels, pel, vpad, super_width, y_ratio_uv) / super_width,
+    )?;
+
+    let super_width = super_width
+        .get()
+        .checked_next_multiple_of(x_ratio_uv.get().into())?;
+    let super_height = super_height
+        .get()
+        .checked_next_multiple_of(y_ratio_uv.get().into())?;
+    if super_width > MAX_SUPER_DIMENSION || super_height > MAX_SUPER_DIMENSION {
+        return None;
+    }
+    let bytes = super_width
+        .checked_mul(super_height)?
+        .checked_mul(size_of::<u16>())?;
+    if bytes > isize::MAX as usize {
+        return None;
+    }
+
+    // Rounding up a non-zero value cannot result in zero
+    Some((
+        NonZeroUsize::new(super_width)?,
+        NonZeroUsize::new(super_height)?,
+    ))
+}
@@ -3,11 +3,11 @@
 
 use quickcheck::TestResult;
 use quickcheck_macros::quickcheck;
-use vapoursynth::format::PresetFormat;
+use vapoursynth::{format::PresetFormat, prelude::Environment};
 
 use super::*;
 use crate::{
-    params::{ReduceFilter, Subpel, SubpelMethod},
+    params::{PadMode, ReduceFilter, Subpel, SubpelMethod},
     tests::create_test_env,
 };
 
@@ -16,7 +16,10 @@ fn new_with_default_args() {
     let env = create_test_env(640, 480, PresetFormat::YUV420P8, 10).unwrap();
     let (node, _) = env.get_output(0).unwrap();
 
-    let super_instance = Super::new(node, None, None, None, None, None, None, None, None).unwrap();
+    let super_instance = Super::new(
+        node, None, None, None, None, None, None, None, None, None, None, None,
+    )
+    .unwrap();
 
     assert_eq!(super_instance.hpad, 16);
     assert_eq!(super_instance.vpad, 16);
@@ -25,6 +28,7 @@ fn new_with_default_args() {
     assert!(super_instance.chroma);
     assert_eq!(super_instance.sharp, SubpelMethod::Wiener);
     assert_eq!(super_instance.rfilter, ReduceFilter::Bilinear);
+    assert_eq!(super_instance.pad_mode, PadMode::Replicate);
 }
 
 #[quickcheck]
@@ -43,9 +47,20 @@ fn new_with_specified_args(
         || hpad > 1024
         || vpad > 1024
         || levels > 64
+        // 420 requires even padding
+        || !hpad.is_multiple_of(2)
+        || !vpad.is_multiple_of(2)
     {
         return TestResult::discard();
     }
+    let min_pad = if pel > 1 {
+        SubpelMethod::try_from(sharp as i64).unwrap().radius()
+    } else {
+        0
+    };
+    if (hpad != 0 && hpad < min_pad) || (vpad != 0 && vpad < min_pad) {
+        return TestResult::discard();
+    }
 
     let env = create_test_env(640, 480, PresetFormat::YUV420P8, 10).unwrap();
     let (node, _) = env.get_output(0).unwrap();
@@ -60,6 +75,9 @@ fn new_with_specified_args(
         Some(sharp as i64),
         Some(rfilter as i64),
         None,
+        None,
+        None,
+        None,
     )
     .unwrap();
 
@@ -89,6 +107,9 @@ fn super_dimension_calculations() {
         Some(1), // sharp
         Some(1), // rfilter
         None,
+        None,
+        None,
+        None,
     )
     .unwrap();
 
@@ -117,6 +138,9 @@ fn levels_calculation() {
         Some(1),  // sharp
         Some(1),  // rfilter
         None,
+        None,
+        None,
+        None,
     )
     .unwrap();
 
@@ -135,6 +159,9 @@ fn levels_calculation() {
         Some(1),  // sharp
         Some(1),  // rfilter
         None,
+        None,
+        None,
+        None,
     )
     .unwrap();
 
@@ -157,6 +184,9 @@ fn different_pel_values() {
             Some(1),   // sharp
             Some(1),   // rfilter
             None,
+            None,
+            None,
+            None,
         )
         .unwrap();
 
@@ -191,6 +221,9 @@ fn different_formats() {
             Some(1), // sharp
             Some(1), // rfilter
             None,
+            None,
+            None,
+            None,
         )
         .unwrap();
 
@@ -221,6 +254,9 @@ fn gray_format_chroma_handling() {
         Some(0),  // sharp
         Some(0),  // rfilter
         None,
+        None,
+        None,
+        None,
     )
     .unwrap();
 
@@ -251,6 +287,9 @@ fn error_handling_invalid_format() {
         Some(1), // sharp
         Some(1), // rfilter
         None,
+        None,
+        None,
+        None,
     );
     assert!(result.is_err());
 
@@ -265,6 +304,9 @@ fn error_handling_invalid_format() {
         Some(5), // sharp (invalid - outside 0-2 range)
         Some(1), // rfilter
         None,
+        None,
+        None,
+        None,
     );
     assert!(result.is_err());
 
@@ -279,10 +321,40 @@ fn error_handling_invalid_format() {
         Some(1),  // sharp
         Some(10), // rfilter (invalid - outside 0-4 range)
         None,
+        None,
+        None,
+        None,
     );
     assert!(result.is_err());
 }
 
+#[test]
+fn zero_dimensions_are_rejected() {
+    // VapourSynth marks a variable resolution with zeroes, but only one of them may be zero
+    for (width, height) in [(0, 48), (64, 0), (0, 0)] {
+        let err = constant_dimensions(Property::Constant(Resolution { width, height }), "pelclip")
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<ZooMvError>(),
+            Some(&ZooMvError::VariableFormat {
+                filter: "Super",
+                clip: "pelclip",
+            })
+        );
+    }
+    assert!(constant_dimensions(Property::Variable, "clip").is_err());
+
+    let (width, height) = constant_dimensions(
+        Property::Constant(Resolution {
+            width: 64,
+            height: 48,
+        }),
+        "clip",
+    )
+    .unwrap();
+    assert_eq!((width.get(), height.get()), (64, 48));
+}
+
 #[test]
 fn format_handling_8bit_vs_16bit() {
     // Test 8-bit format
@@ -299,6 +371,9 @@ fn format_handling_8bit_vs_16bit() {
         Some(1), // sharp
         Some(1), // rfilter
         None,
+        None,
+        None,
+        None,
     )
     .unwrap();
 
@@ -316,6 +391,9 @@ fn format_handling_8bit_vs_16bit() {
         Some(1), // sharp
         Some(1), // rfilter
         None,
+        None,
+        None,
+        None,
     )
     .unwrap();
 
@@ -359,6 +437,9 @@ fn subsampling_handling() {
             Some(1), // sharp
             Some(1), // rfilter
             None,
+            None,
+            None,
+            None,
         )
         .unwrap();
 
@@ -387,6 +468,9 @@ fn padding_values() {
             Some(1),          // sharp
             Some(1),          // rfilter
             None,
+            None,
+            None,
+            None,
         )
         .unwrap();
 
@@ -397,3 +481,787 @@ fn padding_values() {
         assert_eq!(super_instance.super_width.get(), 64 + 2 * pad);
     }
 }
+
+/// Checks that every level of every plane lies within the superframe
+/// and that no two levels overlap.
+fn assert_layouts_fit(
+    width: usize,
+    height: usize,
+    hpad: usize,
+    vpad: usize,
+    pel: Subpel,
+    x_ratio_uv: u8,
+    y_ratio_uv: u8,
+) {
+    let width = NonZeroUsize::new(width).unwrap();
+    let height = NonZeroUsize::new(height).unwrap();
+    let x_ratio_uv = NonZeroU8::new(x_ratio_uv).unwrap();
+    let y_ratio_uv = NonZeroU8::new(y_ratio_uv).unwrap();
+    let levels = max_levels(width, height, hpad, vpad, x_ratio_uv, y_ratio_uv);
+    let (super_width, super_height) = super_frame_size(
+        width, height, levels, pel, hpad, vpad, x_ratio_uv, y_ratio_uv,
+    )
+    .unwrap();
+    assert_eq!(super_width.get() % x_ratio_uv.get() as usize, 0);
+    assert_eq!(super_height.get() % y_ratio_uv.get() as usize, 0);
+
+    let pel_windows = u8::from(pel) as usize * u8::from(pel) as usize;
+    for chroma in [false, true] {
+        let (plane_width, plane_height) = if chroma {
+            (
+                super_width.get() / x_ratio_uv.get() as usize,
+                super_height.get() / y_ratio_uv.get() as usize,
+            )
+        } else {
+            (super_width.get(), super_height.get())
+        };
+        let pitch = NonZeroUsize::new(plane_width).unwrap();
+        let mut previous_end = 0;
+        for level in 0..levels {
+            let layout = SuperPlaneLayout::new(
+                chroma, width, height, level, pel, hpad, vpad, x_ratio_uv, y_ratio_uv, pitch,
+            );
+            let luma = SuperPlaneLayout::new(
+                false, width, height, level, pel, hpad, vpad, x_ratio_uv, y_ratio_uv, pitch,
+            );
+            let windows = if level == 0 { pel_windows } else { 1 };
+            let padded_height = layout.height.get() + 2 * layout.vpad;
+            let end =
+                layout.offset + (windows - 1) * pitch.get() * padded_height + layout.padded_len();
+            let context = format!("{width}x{height} chroma={chroma} level={level}");
+
+            assert_eq!(layout.offset % pitch.get(), 0, "{context}");
+            assert!(
+                layout.offset >= previous_end,
+                "{context}: overlaps previous level"
+            );
+            assert!(
+                end <= plane_width * plane_height,
+                "{context}: out of bounds"
+            );
+            assert!(
+                layout.width.get() + 2 * layout.hpad <= plane_width,
+                "{context}"
+            );
+            if chroma {
+                // Chroma planes must cover exactly the same area as the luma plane
+                assert_eq!(
+                    layout.width.get() * x_ratio_uv.get() as usize,
+                    luma.width.get(),
+                    "{context}"
+                );
+                assert_eq!(
+                    layout.height.get() * y_ratio_uv.get() as usize,
+                    luma.height.get(),
+                    "{context}"
+                );
+            }
+            previous_end = end;
+        }
+    }
+}
+
+#[test]
+fn odd_dimensions_444() {
+    assert_layouts_fit(63, 47, 8, 8, Subpel::Half, 1, 1);
+    assert_layouts_fit(1919, 1079, 16, 16, Subpel::Quarter, 1, 1);
+    assert_layouts_fit(33, 17, 7, 5, Subpel::Full, 1, 1);
+}
+
+#[test]
+fn odd_dimensions_422() {
+    // Odd luma height, and an odd chroma width
+    assert_layouts_fit(62, 47, 8, 8, Subpel::Half, 2, 1);
+    assert_layouts_fit(1918, 1079, 16, 16, Subpel::Quarter, 2, 1);
+    assert_layouts_fit(34, 17, 7, 5, Subpel::Full, 2, 1);
+}
+
+#[test]
+fn odd_dimensions_440() {
+    // Odd luma width, and an odd chroma height
+    assert_layouts_fit(63, 46, 8, 8, Subpel::Half, 1, 2);
+    assert_layouts_fit(1919, 1078, 16, 16, Subpel::Quarter, 1, 2);
+    assert_layouts_fit(33, 18, 7, 5, Subpel::Full, 1, 2);
+}
+
+#[test]
+fn odd_dimensions_420() {
+    // Odd chroma width and height
+    assert_layouts_fit(62, 46, 8, 8, Subpel::Half, 2, 2);
+    assert_layouts_fit(1918, 1078, 16, 16, Subpel::Quarter, 2, 2);
+    assert_layouts_fit(34, 18, 7, 5, Subpel::Full, 2, 2);
+}
+
+#[test]
+fn super_frame_size_stacks_levels() {
+    let width = NonZeroUsize::new(34).unwrap();
+    let height = NonZeroUsize::new(18).unwrap();
+    let ratio = NonZeroU8::new(2).unwrap();
+    let levels = max_levels(width, height, 7, 5, ratio, ratio);
+    assert_eq!(levels, 4);
+
+    // Level heights 18, 10, 6 and 4, each with 5 rows of padding above and below
+    let (super_width, super_height) =
+        super_frame_size(width, height, levels, Subpel::Full, 7, 5, ratio, ratio).unwrap();
+    assert_eq!(super_width.get(), 34 + 2 * 7);
+    assert_eq!(super_height.get(), 28 + 20 + 16 + 14);
+
+    // The full-pel window of level 0 is repeated for each subpixel position
+    let (_, super_height) =
+        super_frame_size(width, height, levels, Subpel::Half, 7, 5, ratio, ratio).unwrap();
+    assert_eq!(super_height.get(), 4 * 28 + 20 + 16 + 14);
+}
+
+#[test]
+fn levels_within_budget_drops_coarse_levels() {
+    let width = NonZeroUsize::new(34).unwrap();
+    let height = NonZeroUsize::new(18).unwrap();
+    let ratio = NonZeroU8::new(2).unwrap();
+    let fit = |levels, plane_count, bytes_per_sample, max_bytes| {
+        levels_within_budget(
+            width,
+            height,
+            levels,
+            Subpel::Full,
+            7,
+            5,
+            ratio,
+            ratio,
+            plane_count,
+            bytes_per_sample,
+            max_bytes,
+        )
+    };
+
+    // 48 pixels wide and 28, 48, 64 or 78 rows high, plus two quarter-size chroma planes
+    for (max_bytes, levels) in [
+        (5616, Some(4)),
+        (5615, Some(3)),
+        (4608, Some(3)),
+        (3456, Some(2)),
+        (2016, Some(1)),
+        (2015, None),
+    ] {
+        assert_eq!(fit(4, 3, 1, max_bytes), levels, "{max_bytes} bytes");
+    }
+    // A budget never adds levels
+    assert_eq!(fit(2, 3, 1, usize::MAX), Some(2));
+    // GRAY16 only has the luma plane, with 2 bytes per sample
+    assert_eq!(fit(4, 1, 2, 78 * 48 * 2), Some(4));
+    assert_eq!(fit(4, 1, 2, 78 * 48 * 2 - 1), Some(3));
+}
+
+#[test]
+fn maxmem_caps_levels() {
+    let env = create_test_env(64, 48, PresetFormat::YUV420P8, 1).unwrap();
+    let (node, _) = env.get_output(0).unwrap();
+    let new_super = |maxmem| {
+        Super::new(
+            node.clone(),
+            Some(0),
+            Some(0),
+            Some(1),
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            maxmem,
+            None,
+        )
+    };
+
+    // Levels of 48, 24, 12 and 6 rows of 64 pixels, with half as many chroma samples
+    assert_eq!(new_super(None).unwrap().levels, 4);
+    assert_eq!(new_super(Some(0)).unwrap().levels, 4);
+    assert_eq!(new_super(Some(64 * 90 * 3 / 2)).unwrap().levels, 4);
+    assert_eq!(new_super(Some(64 * 90 * 3 / 2 - 1)).unwrap().levels, 3);
+    assert_eq!(new_super(Some(64 * 48 * 3 / 2)).unwrap().levels, 1);
+
+    let err = new_super(Some(64 * 48 * 3 / 2 - 1))
+        .err()
+        .expect("Super::new should fail");
+    assert!(matches!(
+        err.downcast_ref::<ZooMvError>(),
+        Some(&ZooMvError::InvalidParam { name: "maxmem", .. })
+    ));
+}
+
+#[test]
+fn super_frame_size_handles_8k_and_rejects_overflow() {
+    let width = NonZeroUsize::new(7680).unwrap();
+    let height = NonZeroUsize::new(4320).unwrap();
+    let ratio = NonZeroU8::new(2).unwrap();
+    let levels = max_levels(width, height, 16, 16, ratio, ratio);
+
+    // Quarter-pel 8K stacks 16 windows of level 0 above the smaller levels
+    let (super_width, super_height) =
+        super_frame_size(width, height, levels, Subpel::Quarter, 16, 16, ratio, ratio).unwrap();
+    assert_eq!(super_width.get(), 7680 + 2 * 16);
+    let level_rows: usize = (1..levels)
+        .map(|level| plane_height_luma(height, level, ratio, 16).get() + 2 * 16)
+        .sum();
+    assert_eq!(super_height.get(), 16 * (4320 + 2 * 16) + level_rows);
+
+    // Dimensions whose super frame does not fit in a VapourSynth frame are rejected,
+    // whether or not the arithmetic would overflow `usize`
+    let tall = NonZeroUsize::new(MAX_SUPER_DIMENSION / 4).unwrap();
+    assert_eq!(
+        super_frame_size(width, tall, 1, Subpel::Quarter, 16, 16, ratio, ratio),
+        None
+    );
+    let huge = NonZeroUsize::new(usize::MAX / 2).unwrap();
+    assert_eq!(
+        super_frame_size(huge, huge, 1, Subpel::Quarter, 16, 16, ratio, ratio),
+        None
+    );
+    assert_eq!(
+        super_frame_size(
+            width,
+            height,
+            1,
+            Subpel::Full,
+            usize::MAX / 2,
+            16,
+            ratio,
+            ratio
+        ),
+        None
+    );
+}
+
+#[test]
+fn zero_padding_is_allowed() {
+    let env = create_test_env(64, 48, PresetFormat::YUV420P8, 5).unwrap();
+    let (node, _) = env.get_output(0).unwrap();
+
+    for pel in [1, 2, 4] {
+        let super_instance = Super::new(
+            node.clone(),
+            Some(0),
+            Some(0),
+            Some(pel),
+            None,
+            None,
+            Some(2),
+            None,
+            None,
+            None,
+            None,
+            None,
+        )
+        .unwrap();
+        assert_eq!(super_instance.super_width.get(), 64);
+    }
+}
+
+#[test]
+fn padding_smaller_than_kernel_radius() {
+    let env = create_test_env(64, 48, PresetFormat::YUV420P8, 5).unwrap();
+    let (node, _) = env.get_output(0).unwrap();
+
+    // Wiener needs 3 pixels on each side, bicubic 2, and bilinear 1.
+    // Without chroma, only the luma padding matters.
+    for (sharp, pad, valid) in [(2, 2, false), (2, 4, true), (1, 2, true), (0, 2, true)] {
+        for (hpad, vpad, name) in [(pad, 16, "hpad"), (16, pad, "vpad")] {
+            let result = Super::new(
+                node.clone(),
+                Some(hpad),
+                Some(vpad),
+                Some(2),
+                None,
+                Some(0),
+                Some(sharp),
+                None,
+                None,
+                None,
+                None,
+                None,
+            );
+            if valid {
+                assert!(result.is_ok(), "sharp={sharp} {name}={pad}");
+            } else {
+                let err = result.err().expect("Super::new should fail");
+                assert_eq!(
+                    err.downcast_ref::<ZooMvError>(),
+                    Some(&ZooMvError::InvalidParam {
+                        filter: "Super",
+                        name,
+                        value: pad,
+                        expected: "0 or at least the radius of the `sharp` kernel (1, 2, or 3)",
+                    }),
+                    "sharp={sharp} {name}={pad}"
+                );
+            }
+        }
+    }
+
+    // Without subpixel interpolation, any padding is fine
+    assert!(
+        Super::new(
+            node,
+            Some(2),
+            Some(2),
+            Some(1),
+            None,
+            None,
+            Some(2),
+            None,
+            None,
+            None,
+            None,
+            None,
+        )
+        .is_ok()
+    );
+}
+
+#[test]
+fn chroma_padding_smaller_than_kernel_radius() {
+    let env = create_test_env(64, 48, PresetFormat::YUV420P8, 5).unwrap();
+    let (node, _) = env.get_output(0).unwrap();
+
+    // 4:2:0 chroma planes get half of the padding, which must still cover the kernel
+    for (sharp, pad, valid) in [(2, 4, false), (2, 6, true), (1, 2, false), (1, 4, true)] {
+        for (hpad, vpad, name) in [(pad, 16, "hpad"), (16, pad, "vpad")] {
+            let result = Super::new(
+                node.clone(),
+                Some(hpad),
+                Some(vpad),
+                Some(2),
+                None,
+                None,
+                Some(sharp),
+                None,
+                None,
+                None,
+                None,
+                None,
+            );
+            if valid {
+                assert!(result.is_ok(), "sharp={sharp} {name}={pad}");
+            } else {
+                let err = result.err().expect("Super::new should fail");
+                assert_eq!(
+                    err.downcast_ref::<ZooMvError>(),
+                    Some(&ZooMvError::InvalidParam {
+                        filter: "Super",
+                        name,
+                        value: pad,
+                        expected: "0 or at least the radius of the `sharp` kernel (1, 2, or 3) \
+                                   times the chroma subsampling",
+                    }),
+                    "sharp={sharp} {name}={pad}"
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn padding_must_match_subsampling() {
+    let env = create_test_env(64, 48, PresetFormat::YUV420P8, 5).unwrap();
+    let (node, _) = env.get_output(0).unwrap();
+    for (hpad, vpad, name) in [(7, 8, "hpad"), (8, 7, "vpad")] {
+        let err = Super::new(
+            node.clone(),
+            Some(hpad),
+            Some(vpad),
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+        )
+        .err()
+        .expect("Super::new should fail");
+        match err.downcast_ref::<ZooMvError>() {
+            Some(&ZooMvError::InvalidParam {
+                filter: "Super",
+                name: actual,
+                value: 7,
+                ..
+            }) => assert_eq!(actual, name),
+            other => panic!("unexpected error for {name}: {other:?}"),
+        }
+    }
+
+    // 444 has no subsampling to match
+    let env = create_test_env(64, 48, PresetFormat::YUV444P8, 5).unwrap();
+    let (node, _) = env.get_output(0).unwrap();
+    assert!(
+        Super::new(
+            node,
+            Some(7),
+            Some(5),
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+        )
+        .is_ok()
+    );
+}
+
+fn super_error(node: Node<'_>, pelclip: Option<Node<'_>>) -> ZooMvError {
+    let err = Super::new(
+        node,
+        None,
+        None,
+        Some(2),
+        None,
+        None,
+        None,
+        None,
+        pelclip,
+        None,
+        None,
+        None,
+    )
+    .err()
+    .expect("Super::new should fail");
+    *err.downcast_ref::<ZooMvError>()
+        .expect("error should be a ZooMvError")
+}
+
+#[test]
+fn error_variants_for_invalid_params() {
+    let env = create_test_env(64, 48, PresetFormat::YUV420P8, 5).unwrap();
+    let (node, _) = env.get_output(0).unwrap();
+
+    let cases = [
+        ("hpad", [Some(-1), None, None, None, None, None]),
+        ("vpad", [None, Some(-1), None, None, None, None]),
+        ("pel", [None, None, Some(3), None, None, None]),
+        ("levels", [None, None, None, Some(-1), None, None]),
+        ("sharp", [None, None, None, None, Some(5), None]),
+        ("rfilter", [None, None, None, None, None, Some(10)]),
+    ];
+    for (expected_name, [hpad, vpad, pel, levels, sharp, rfilter]) in cases {
+        let err = Super::new(
+            node.clone(),
+            hpad,
+            vpad,
+            pel,
+            levels,
+            None,
+            sharp,
+            rfilter,
+            None,
+            None,
+            None,
+            None,
+        )
+        .err()
+        .expect("Super::new should fail");
+        match err.downcast_ref::<ZooMvError>() {
+            Some(&ZooMvError::InvalidParam {
+                filter: "Super",
+                name,
+                ..
+            }) => assert_eq!(name, expected_name),
+            other => panic!("unexpected error for {expected_name}: {other:?}"),
+        }
+    }
+}
+
+#[test]
+fn error_variants_for_unsupported_formats() {
+    let env = create_test_env(64, 48, PresetFormat::YUV444PS, 5).unwrap();
+    let (node, _) = env.get_output(0).unwrap();
+    assert_eq!(super_error(node, None), ZooMvError::UnsupportedBitDepth {
+        filter: "Super",
+        bits: 32,
+    });
+
+    let env = create_test_env(64, 48, PresetFormat::YUV420PH, 5).unwrap();
+    let (node, _) = env.get_output(0).unwrap();
+    assert_eq!(super_error(node, None), ZooMvError::UnsupportedFormat {
+        filter: "Super",
+        requirement: "be integer format",
+    });
+
+    let env = create_test_env(64, 48, PresetFormat::RGB24, 5).unwrap();
+    let (node, _) = env.get_output(0).unwrap();
+    let err = super_error(node, None);
+    assert_eq!(err, ZooMvError::UnsupportedColorFamily {
+        filter: "Super",
+        family: ColorFamily::RGB,
+    });
+    assert!(
+        err.to_string().contains("got RGB. Convert it first"),
+        "{err}"
+    );
+
+    let env = create_test_env(64, 48, PresetFormat::YUV411P8, 5).unwrap();
+    let (node, _) = env.get_output(0).unwrap();
+    assert_eq!(super_error(node, None), ZooMvError::UnsupportedFormat {
+        filter: "Super",
+        requirement: "be GRAY, 420, 422, 440, or 444",
+    });
+}
+
+#[test]
+fn max_levels_is_zero_below_two_chroma_samples() {
+    let size = |w, h| (NonZeroUsize::new(w).unwrap(), NonZeroUsize::new(h).unwrap());
+    let (one, two) = (NonZeroU8::new(1).unwrap(), NonZeroU8::new(2).unwrap());
+
+    let (width, height) = size(2, 2);
+    assert_eq!(max_levels(width, height, 0, 0, two, two), 0);
+    assert_eq!(max_levels(width, height, 16, 16, two, two), 0);
+    let (width, height) = size(4, 4);
+    assert!(max_levels(width, height, 0, 0, two, two) > 0);
+    // Only the subsampled dimension needs room for two chroma samples
+    let (width, height) = size(8, 2);
+    assert_eq!(max_levels(width, height, 0, 0, two, two), 0);
+    assert!(max_levels(width, height, 0, 0, two, one) > 0);
+    let (width, height) = size(1, 8);
+    assert_eq!(max_levels(width, height, 0, 0, one, one), 0);
+}
+
+#[test]
+fn too_small_input_is_rejected() {
+    let env = create_test_env(2, 2, PresetFormat::YUV420P8, 5).unwrap();
+    let (node, _) = env.get_output(0).unwrap();
+    let err = super_error(node, None);
+    assert_eq!(err, ZooMvError::InputTooSmall {
+        filter: "Super",
+        width: 2,
+        height: 2,
+        min_width: 4,
+        min_height: 4,
+    });
+    assert_eq!(
+        err.to_string(),
+        "Super: input clip is too small, must be at least 4x4, got 2x2"
+    );
+}
+
+#[test]
+fn error_variants_for_variable_resolution() {
+    let env = Environment::from_script(
+        r#"
+import vapoursynth as vs
+core = vs.core
+a = core.std.BlankClip(width=64, height=48, format=vs.YUV420P8, length=5)
+b = core.std.BlankClip(width=32, height=24, format=vs.YUV420P8, length=5)
+core.std.Splice([a, b], mismatch=True).set_output(0)
+a.set_output(1)
+"#,
+    )
+    .unwrap();
+    let (variable, _) = env.get_output(0).unwrap();
+    let (constant, _) = env.get_output(1).unwrap();
+
+    assert_eq!(
+        super_error(variable.clone(), None),
+        ZooMvError::VariableFormat {
+            filter: "Super",
+            clip: "clip",
+        }
+    );
+    assert_eq!(
+        super_error(constant, Some(variable)),
+        ZooMvError::VariableFormat {
+            filter: "Super",
+            clip: "pelclip",
+        }
+    );
+}
+
+#[test]
+fn error_variants_for_incompatible_pelclip() {
+    let env = Environment::from_script(
+        r#"
+import vapoursynth as vs
+core = vs.core
+core.std.BlankClip(width=64, height=48, format=vs.YUV420P8, length=5).set_output(0)
+core.std.BlankClip(width=128, height=96, format=vs.YUV420P16, length=5).set_output(1)
+core.std.BlankClip(width=100, height=96, format=vs.YUV420P8, length=5).set_output(2)
+"#,
+    )
+    .unwrap();
+    let (node, _) = env.get_output(0).unwrap();
+    let (wrong_format, _) = env.get_output(1).unwrap();
+    let (wrong_size, _) = env.get_output(2).unwrap();
+
+    assert!(matches!(
+        super_error(node.clone(), Some(wrong_format)),
+        ZooMvError::IncompatibleClip {
+            filter: "Super",
+            clip: "pelclip",
+            requirement: "have same format as input clip, or be GRAY with the same bit depth",
+        }
+    ));
+    assert!(matches!(
+        super_error(node, Some(wrong_size)),
+        ZooMvError::IncompatibleClip {
+            filter: "Super",
+            clip: "pelclip",
+            ..
+        }
+    ));
+}
+
+#[test]
+fn gray_pelclip_for_yuv_source() {
+    let env = Environment::from_script(
+        r#"
+import vapoursynth as vs
+core = vs.core
+core.std.BlankClip(width=64, height=48, format=vs.YUV420P8, length=5).set_output(0)
+core.std.BlankClip(width=128, height=96, format=vs.GRAY8, length=5).set_output(1)
+core.std.BlankClip(width=128, height=96, format=vs.GRAY16, length=5).set_output(2)
+core.std.BlankClip(width=64, height=48, format=vs.GRAY8, length=5).set_output(3)
+"#,
+    )
+    .unwrap();
+    let (node, _) = env.get_output(0).unwrap();
+    let (gray, _) = env.get_output(1).unwrap();
+    let (gray_wrong_depth, _) = env.get_output(2).unwrap();
+    let (gray_source, _) = env.get_output(3).unwrap();
+
+    let super_instance = Super::new(
+        node.clone(),
+        None,
+        None,
+        Some(2),
+        None,
+        None,
+        None,
+        None,
+        Some(gray.clone()),
+        None,
+        None,
+        None,
+    )
+    .unwrap();
+    assert!(super_instance.pelclip.is_some());
+    assert!(super_instance.is_pelclip_luma_only);
+    assert!(!super_instance.is_pelclip_padded);
+
+    assert!(matches!(
+        super_error(node, Some(gray_wrong_depth)),
+        ZooMvError::IncompatibleClip {
+            filter: "Super",
+            clip: "pelclip",
+            ..
+        }
+    ));
+
+    // A GRAY source already has the same format as a GRAY pelclip
+    let super_instance = Super::new(
+        gray_source,
+        None,
+        None,
+        Some(2),
+        None,
+        None,
+        None,
+        None,
+        Some(gray),
+        None,
+        None,
+        None,
+    )
+    .unwrap();
+    assert!(!super_instance.is_pelclip_luma_only);
+}
+
+#[test]
+fn sharp_coeffs_select_a_custom_kernel() {
+    let env = create_test_env(64, 48, PresetFormat::YUV420P8, 1).unwrap();
+    let (node, _) = env.get_output(0).unwrap();
+    let taps = [1, -4, 19, 19, -4, 1];
+
+    let super_instance = Super::new(
+        node.clone(),
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        Some(&taps),
+    )
+    .unwrap();
+    assert_eq!(
+        super_instance.sharp,
+        SubpelMethod::Custom(SubpelKernel::parse("Super", "sharp_coeffs", &taps).unwrap())
+    );
+
+    // The Wiener taps select the built-in kernel
+    let super_instance = Super::new(
+        node.clone(),
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        Some(&[1, -5, 20, 20, -5, 1]),
+    )
+    .unwrap();
+    assert_eq!(super_instance.sharp, SubpelMethod::Wiener);
+
+    // `sharp` and `sharp_coeffs` pick the kernel in different ways
+    let err = Super::new(
+        node.clone(),
+        None,
+        None,
+        None,
+        None,
+        None,
+        Some(2),
+        None,
+        None,
+        None,
+        None,
+        Some(&taps),
+    )
+    .err()
+    .unwrap();
+    assert_eq!(
+        err.to_string(),
+        "Super: 'sharp' must be omitted when `sharp_coeffs` is given, got 2"
+    );
+
+    let err = Super::new(
+        node,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        Some(&[0, 0, 16, 16, 0, 0, 0]),
+    )
+    .err()
+    .unwrap();
+    assert_eq!(
+        err.to_string(),
+        "Super: 'sharp_coeffs' must be 6 taps, got 7"
+    );
+}
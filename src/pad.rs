@@ -3,20 +3,26 @@ mod tests;
 
 use std::num::NonZeroUsize;
 
-use crate::util::Pixel;
+use crate::{params::PadMode, util::Pixel};
 
 /// Pads a reference frame by extending edge pixels to fill the padding areas.
 ///
-/// This function takes a frame and extends its borders by replicating edge pixels
-/// to create padding around the original image. This is commonly used in video
+/// This function takes a frame and extends its borders by replicating or mirroring
+/// edge pixels to create padding around the original image. This is commonly used in video
 /// processing for motion estimation and filtering operations where algorithms
 /// need to access pixels beyond the frame boundaries.
 ///
-/// The padding is applied in all directions:
+/// With [`PadMode::Replicate`], the padding is applied in all directions:
 /// - Corners are filled with the nearest corner pixel value
 /// - Top/bottom edges are filled by extending the first/last row
 /// - Left/right edges are filled by extending the first/last column
 ///
+/// With [`PadMode::Mirror`], the padding reflects the frame across each edge,
+/// so the first padding pixel repeats the edge pixel, the next one the pixel before it,
+/// and so on. Corners are reflected across both edges.
+///
+/// Either padding may be zero, in which case the frame is only padded in the other direction.
+///
 /// # Parameters
 /// - `offset`: Starting byte offset in the destination buffer where the padded frame begins
 /// - `ref_pitch`: Number of pixels per row in the destination buffer (including padding)
@@ -24,6 +30,7 @@ use crate::util::Pixel;
 /// - `vpad`: Vertical padding amount (pixels to add on top and bottom)
 /// - `width`: Width of the original frame in pixels (excluding padding)
 /// - `height`: Height of the original frame in pixels (excluding padding)
+/// - `mode`: How the padding is filled
 /// - `dest`: Destination buffer containing the frame data to be padded
 pub fn pad_reference_frame<T: Pixel>(
     offset: usize,
@@ -32,11 +39,23 @@ pub fn pad_reference_frame<T: Pixel>(
     vpad: usize,
     width: NonZeroUsize,
     height: NonZeroUsize,
+    mode: PadMode,
     dest: &mut [T],
 ) {
+    if hpad == 0 && vpad == 0 {
+        return;
+    }
+
     let pfoff = offset + vpad * ref_pitch.get() + hpad;
-    let max_offset = offset + hpad + width.get() + (vpad + height.get()) * ref_pitch.get();
-    assert!(dest.len() >= max_offset + ref_pitch.get() * (vpad - 1) + hpad);
+    // End of the last padded row
+    let padded_end =
+        offset + (height.get() + 2 * vpad - 1) * ref_pitch.get() + width.get() + 2 * hpad;
+    assert!(dest.len() >= padded_end);
+
+    if mode == PadMode::Mirror {
+        pad_mirror(offset, ref_pitch, hpad, vpad, width, height, dest);
+        return;
+    }
 
     // SAFETY: bounds are checked above
     unsafe {
@@ -117,9 +136,49 @@ pub fn pad_reference_frame<T: Pixel>(
     }
 }
 
+/// Index of the pixel that the padding at position `i` mirrors, in a line of `len` pixels.
+///
+/// Padding wider than the line keeps reflecting back and forth across the line.
+fn mirror_index(i: isize, len: usize) -> usize {
+    let period = 2 * len as isize;
+    let i = i.rem_euclid(period) as usize;
+    if i < len { i } else { 2 * len - 1 - i }
+}
+
+fn pad_mirror<T: Pixel>(
+    offset: usize,
+    ref_pitch: NonZeroUsize,
+    hpad: usize,
+    vpad: usize,
+    width: NonZeroUsize,
+    height: NonZeroUsize,
+    dest: &mut [T],
+) {
+    let (pitch, width, height) = (ref_pitch.get(), width.get(), height.get());
+    let row_len = width + 2 * hpad;
+
+    // Left and right, on the rows of the frame itself
+    for y in 0..height {
+        let row = &mut dest[offset + (vpad + y) * pitch..][..row_len];
+        for x in 0..hpad {
+            row[hpad - 1 - x] = row[hpad + mirror_index(-1 - x as isize, width)];
+            row[hpad + width + x] = row[hpad + mirror_index((width + x) as isize, width)];
+        }
+    }
+
+    // Up and down, copying whole padded rows so the corners are mirrored as well
+    for y in 0..vpad {
+        let src = offset + (vpad + mirror_index(-1 - y as isize, height)) * pitch;
+        dest.copy_within(src..src + row_len, offset + (vpad - 1 - y) * pitch);
+        let src = offset + (vpad + mirror_index((height + y) as isize, height)) * pitch;
+        dest.copy_within(src..src + row_len, offset + (vpad + height + y) * pitch);
+    }
+}
+
 /// # Safety
 ///
-/// The caller must ensure `dest.len() >= offset + ref_pitch.get() * (vpad - 1) + hpad`.
+/// If `vpad` is not zero, the caller must ensure
+/// `dest.len() >= offset + ref_pitch.get() * (vpad - 1) + hpad`.
 /// Do not make this a public function.
 unsafe fn pad_corner<T: Pixel>(
     mut offset: usize,
@@ -3,7 +3,8 @@
 
 use std::num::NonZeroUsize;
 
-use super::{pad_corner, pad_reference_frame};
+use super::{mirror_index, pad_corner, pad_reference_frame};
+use crate::params::PadMode;
 
 /// Helper function to create a test frame with a specific pattern
 /// Creates a frame with total size including padding, but only fills the inner
@@ -173,6 +174,7 @@ fn pad_reference_frame_u8_basic() {
         vpad,
         NonZeroUsize::new(width).unwrap(),
         NonZeroUsize::new(height).unwrap(),
+        PadMode::Replicate,
         &mut frame,
     );
 
@@ -197,6 +199,7 @@ fn pad_reference_frame_u16_basic() {
         vpad,
         NonZeroUsize::new(width).unwrap(),
         NonZeroUsize::new(height).unwrap(),
+        PadMode::Replicate,
         &mut frame,
     );
 
@@ -230,6 +233,7 @@ fn pad_reference_frame_with_offset() {
         vpad,
         NonZeroUsize::new(width).unwrap(),
         NonZeroUsize::new(height).unwrap(),
+        PadMode::Replicate,
         &mut frame,
     );
 
@@ -257,6 +261,7 @@ fn pad_reference_frame_minimal_size() {
         vpad,
         NonZeroUsize::new(width).unwrap(),
         NonZeroUsize::new(height).unwrap(),
+        PadMode::Replicate,
         &mut frame,
     );
 
@@ -271,6 +276,50 @@ fn pad_reference_frame_minimal_size() {
     }
 }
 
+#[test]
+fn pad_reference_frame_zero_padding_is_noop() {
+    let width = 4;
+    let height = 3;
+    let mut frame = create_test_frame::<u8>(width, height, 0, 0, width);
+    let original = frame.clone();
+
+    pad_reference_frame(
+        0,
+        NonZeroUsize::new(width).unwrap(),
+        0,
+        0,
+        NonZeroUsize::new(width).unwrap(),
+        NonZeroUsize::new(height).unwrap(),
+        PadMode::Replicate,
+        &mut frame,
+    );
+
+    assert_eq!(frame, original);
+}
+
+#[test]
+fn pad_reference_frame_zero_padding_in_one_direction() {
+    let width = 4;
+    let height = 3;
+    for (hpad, vpad) in [(0, 2), (2, 0)] {
+        let pitch = width + 2 * hpad;
+        let mut frame = create_test_frame::<u16>(width, height, hpad, vpad, pitch);
+
+        pad_reference_frame(
+            0,
+            NonZeroUsize::new(pitch).unwrap(),
+            hpad,
+            vpad,
+            NonZeroUsize::new(width).unwrap(),
+            NonZeroUsize::new(height).unwrap(),
+            PadMode::Replicate,
+            &mut frame,
+        );
+
+        verify_padding(&frame, 0, pitch, hpad, vpad, width, height);
+    }
+}
+
 #[test]
 fn pad_reference_frame_asymmetric_padding() {
     let width = 3;
@@ -289,6 +338,7 @@ fn pad_reference_frame_asymmetric_padding() {
         vpad,
         NonZeroUsize::new(width).unwrap(),
         NonZeroUsize::new(height).unwrap(),
+        PadMode::Replicate,
         &mut frame,
     );
 
@@ -313,6 +363,7 @@ fn pad_reference_frame_large_frame() {
         vpad,
         NonZeroUsize::new(width).unwrap(),
         NonZeroUsize::new(height).unwrap(),
+        PadMode::Replicate,
         &mut frame,
     );
 
@@ -337,6 +388,7 @@ fn pad_reference_frame_wide_frame() {
         vpad,
         NonZeroUsize::new(width).unwrap(),
         NonZeroUsize::new(height).unwrap(),
+        PadMode::Replicate,
         &mut frame,
     );
 
@@ -361,6 +413,7 @@ fn pad_reference_frame_tall_frame() {
         vpad,
         NonZeroUsize::new(width).unwrap(),
         NonZeroUsize::new(height).unwrap(),
+        PadMode::Replicate,
         &mut frame,
     );
 
@@ -385,6 +438,7 @@ fn pad_reference_frame_pitch_larger_than_padded_width() {
         vpad,
         NonZeroUsize::new(width).unwrap(),
         NonZeroUsize::new(height).unwrap(),
+        PadMode::Replicate,
         &mut frame,
     );
 
@@ -533,6 +587,7 @@ fn pad_reference_frame_preserves_original_data() {
         vpad,
         NonZeroUsize::new(width).unwrap(),
         NonZeroUsize::new(height).unwrap(),
+        PadMode::Replicate,
         &mut frame,
     );
 
@@ -579,6 +634,7 @@ fn pad_reference_frame_edge_values_correctness() {
         vpad,
         NonZeroUsize::new(width).unwrap(),
         NonZeroUsize::new(height).unwrap(),
+        PadMode::Replicate,
         &mut frame,
     );
 
@@ -635,3 +691,80 @@ fn pad_reference_frame_edge_values_correctness() {
         }
     }
 }
+
+/// Pads a 4x3 frame with a bright left column and top row by 3 pixels on every side,
+/// returning the padded rows.
+fn pad_high_contrast_border(mode: PadMode) -> Vec<Vec<u8>> {
+    let (width, height, pad) = (4, 3, 3);
+    let pitch = width + 2 * pad;
+    let mut frame = vec![0u8; pitch * (height + 2 * pad)];
+    for y in 0..height {
+        for x in 0..width {
+            frame[(pad + y) * pitch + pad + x] = if x == 0 || y == 0 { 255 } else { 10 };
+        }
+    }
+
+    pad_reference_frame(
+        0,
+        NonZeroUsize::new(pitch).unwrap(),
+        pad,
+        pad,
+        NonZeroUsize::new(width).unwrap(),
+        NonZeroUsize::new(height).unwrap(),
+        mode,
+        &mut frame,
+    );
+    frame.chunks_exact(pitch).map(<[u8]>::to_vec).collect()
+}
+
+#[test]
+fn replicate_extends_high_contrast_border() {
+    let rows = pad_high_contrast_border(PadMode::Replicate);
+    let top = [255; 10];
+    let inner = [255, 255, 255, 255, 10, 10, 10, 10, 10, 10];
+    assert_eq!(rows, vec![
+        top.to_vec(),
+        top.to_vec(),
+        top.to_vec(),
+        top.to_vec(),
+        inner.to_vec(),
+        inner.to_vec(),
+        inner.to_vec(),
+        inner.to_vec(),
+        inner.to_vec(),
+    ]);
+}
+
+#[test]
+fn mirror_reflects_high_contrast_border() {
+    let rows = pad_high_contrast_border(PadMode::Mirror);
+    // The bright row and column are reflected right next to themselves, and the bottom
+    // padding is as tall as the frame, so it ends on the reflection of the bright row
+    let top = [255; 10];
+    let inner = [10, 10, 255, 255, 10, 10, 10, 10, 10, 10];
+    assert_eq!(rows, vec![
+        inner.to_vec(),
+        inner.to_vec(),
+        top.to_vec(),
+        top.to_vec(),
+        inner.to_vec(),
+        inner.to_vec(),
+        inner.to_vec(),
+        inner.to_vec(),
+        top.to_vec(),
+    ]);
+
+    // Both modes leave the frame itself alone
+    let replicated = pad_high_contrast_border(PadMode::Replicate);
+    for y in 3..6 {
+        assert_eq!(rows[y][3..7], replicated[y][3..7], "row {y}");
+    }
+}
+
+#[test]
+fn mirror_index_reflects_past_both_edges() {
+    let indices: Vec<_> = (-6..10).map(|i| mirror_index(i, 4)).collect();
+    assert_eq!(indices, [2, 3, 3, 2, 1, 0, 0, 1, 2, 3, 3, 2, 1, 0, 0, 1]);
+    // A single pixel is mirrored onto itself
+    assert!((-3..3).all(|i| mirror_index(i, 1) == 0));
+}
@@ -1,8 +1,10 @@
-use std::num::NonZeroU8;
+use std::num::{NonZeroU8, NonZeroUsize};
 
 use anyhow::{Result, bail};
 use bitflags::bitflags;
 
+use crate::error::ZooMvError;
+
 #[allow(dead_code)]
 pub const MV_DEFAULT_SCD1: usize = 400;
 #[allow(dead_code)]
@@ -45,11 +47,80 @@ impl From<Subpel> for NonZeroU8 {
     }
 }
 
+impl From<Subpel> for NonZeroUsize {
+    fn from(value: Subpel) -> Self {
+        NonZeroU8::from(value).into()
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SubpelMethod {
-    Bilinear = 0,
-    Bicubic = 1,
-    Wiener = 2,
+    Bilinear,
+    Bicubic,
+    Wiener,
+    /// A user-supplied 6-tap kernel, applied where `Wiener` applies its own
+    Custom(SubpelKernel),
+}
+
+impl SubpelMethod {
+    /// Number of pixels the interpolation kernel reads on each side of a sample
+    #[must_use]
+    pub const fn radius(self) -> usize {
+        match self {
+            Self::Bilinear => 1,
+            Self::Bicubic => 2,
+            Self::Wiener | Self::Custom(_) => 3,
+        }
+    }
+}
+
+/// Taps of a 6-tap sub-pixel interpolation kernel, in 1/32 units.
+///
+/// A half-pixel sample between `x` and `x + 1` is the weighted sum of the pixels
+/// from `x - 2` to `x + 3`, so the taps must sum to 32 to keep flat areas unchanged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SubpelKernel([i32; 6]);
+
+impl SubpelKernel {
+    /// Largest magnitude of a tap, which keeps 16-bit sums within an `i32`
+    pub const MAX_TAP: i32 = 1024;
+    /// Sum of the taps, matching the 5-bit shift that normalizes the result
+    pub const SUM: i32 = 32;
+    /// The kernel used by `SubpelMethod::Wiener`
+    pub const WIENER: Self = Self([1, -5, 20, 20, -5, 1]);
+
+    /// Parses the `name` argument of `filter` as a kernel.
+    pub fn parse(
+        filter: &'static str,
+        name: &'static str,
+        taps: &[i64],
+    ) -> Result<Self, ZooMvError> {
+        let invalid = |value, expected| ZooMvError::InvalidParam {
+            filter,
+            name,
+            value,
+            expected,
+        };
+
+        let taps: [i64; 6] = taps
+            .try_into()
+            .map_err(|_| invalid(taps.len() as i64, "6 taps"))?;
+        let limit = i64::from(Self::MAX_TAP);
+        if let Some(&tap) = taps.iter().find(|tap| !(-limit..=limit).contains(*tap)) {
+            return Err(invalid(tap, "taps between -1024 and 1024"));
+        }
+        let sum = taps.iter().sum::<i64>();
+        if sum != i64::from(Self::SUM) {
+            return Err(invalid(sum, "taps summing to 32"));
+        }
+
+        Ok(Self(taps.map(|tap| tap as i32)))
+    }
+
+    #[must_use]
+    pub const fn taps(self) -> [i32; 6] {
+        self.0
+    }
 }
 
 impl TryFrom<i64> for SubpelMethod {
@@ -60,7 +131,7 @@ impl TryFrom<i64> for SubpelMethod {
             0 => Self::Bilinear,
             1 => Self::Bicubic,
             2 => Self::Wiener,
-            _ => bail!("Invalid value for 'sharp', must be 0-2, got {val}."),
+            _ => bail!("Invalid value for 'sharp', must be 0, 1, or 2, got {val}."),
         })
     }
 }
@@ -72,6 +143,8 @@ pub enum ReduceFilter {
     Bilinear = 2,
     Quadratic = 3,
     Cubic = 4,
+    /// Like `Average`, but rounding down instead of to the nearest value
+    AverageFloor = 5,
 }
 
 impl TryFrom<i64> for ReduceFilter {
@@ -84,7 +157,29 @@ impl TryFrom<i64> for ReduceFilter {
             2 => Self::Bilinear,
             3 => Self::Quadratic,
             4 => Self::Cubic,
-            _ => bail!("Invalid value for 'rfilter', must be 0-4, got {val}."),
+            5 => Self::AverageFloor,
+            _ => bail!("Invalid value for 'rfilter', must be 0-5, got {val}."),
+        })
+    }
+}
+
+/// How `Super` fills the padding around each plane.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PadMode {
+    /// Repeats the edge pixels
+    Replicate = 0,
+    /// Mirrors the plane across its edges, starting with the edge pixels themselves
+    Mirror = 1,
+}
+
+impl TryFrom<i64> for PadMode {
+    type Error = anyhow::Error;
+
+    fn try_from(val: i64) -> Result<Self> {
+        Ok(match val {
+            0 => Self::Replicate,
+            1 => Self::Mirror,
+            _ => bail!("Invalid value for 'padmode', must be 0 or 1, got {val}."),
         })
     }
 }
@@ -222,6 +317,42 @@ impl TryFrom<i64> for PenaltyScaling {
     }
 }
 
+/// Defaults of the `Analyse` parameters controlled by `truemotion`, matching mvtools.
+///
+/// The true motion preset favors coherent vector fields over the lowest SAD,
+/// by penalizing vectors that differ from their predictors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MotionPreset {
+    pub lambda: u32,
+    pub lambda_sad: u32,
+    pub penalty_new: u16,
+    pub penalty_level: PenaltyScaling,
+    pub global: bool,
+}
+
+impl MotionPreset {
+    #[must_use]
+    pub fn new(truemotion: bool, blk_size_x: usize, blk_size_y: usize) -> Self {
+        if truemotion {
+            Self {
+                lambda: (1000 * blk_size_x * blk_size_y / 64) as u32,
+                lambda_sad: 1200,
+                penalty_new: 50,
+                penalty_level: PenaltyScaling::Linear,
+                global: true,
+            }
+        } else {
+            Self {
+                lambda: 0,
+                lambda_sad: 400,
+                penalty_new: 0,
+                penalty_level: PenaltyScaling::None,
+                global: false,
+            }
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum DivideMode {
     /// do not divide
@@ -229,6 +360,10 @@ pub enum DivideMode {
     /// divide blocks and assign the original vector to all 4 subblocks
     Original = 1,
     /// divide blocks and assign median (with 2 neighbors) vectors to subblocks
+    ///
+    /// Each subblock takes the median of its block's vector and those of the blocks next to
+    /// the subblock, horizontally and vertically, which rounds off the corners of moving areas
+    /// and removes isolated vectors. Blocks on the frame border keep the original vector.
     Median = 2,
 }
 
@@ -245,6 +380,31 @@ impl TryFrom<i64> for DivideMode {
     }
 }
 
+/// What `Compensate` outputs for a frame whose vectors are not usable,
+/// e.g. because of a scene change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SceneChangeBehavior {
+    /// Outputs the reference frame, without compensation
+    UseReference = 0,
+    /// Outputs the source frame.
+    ///
+    /// mvtools describes this as keeping the previous frame, which the source frame is
+    /// when it is compensated from the next one with backward vectors.
+    UseSource = 1,
+}
+
+impl TryFrom<i64> for SceneChangeBehavior {
+    type Error = anyhow::Error;
+
+    fn try_from(val: i64) -> Result<Self> {
+        Ok(match val {
+            0 => Self::UseReference,
+            1 => Self::UseSource,
+            _ => bail!("Invalid value for 'scbehavior', must be 0 or 1, got {val}."),
+        })
+    }
+}
+
 bitflags! {
     #[repr(C)]
     #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -269,3 +429,6 @@ bitflags! {
         const YUVPLANES = Self::YPLANE.bits() | Self::UPLANE.bits() | Self::VPLANE.bits();
     }
 }
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,94 @@
+#![allow(clippy::unwrap_used, reason = "allow in test files")]
+#![allow(clippy::undocumented_unsafe_blocks, reason = "allow in test files")]
+
+use super::*;
+use crate::error::{ZooMvError, parse_param};
+
+#[test]
+fn subpel_round_trips_through_integers() {
+    for pel in [Subpel::Full, Subpel::Half, Subpel::Quarter] {
+        let value = u8::from(pel);
+        assert_eq!(Subpel::try_from(i64::from(value)).unwrap(), pel);
+        assert_eq!(NonZeroU8::from(pel).get(), value);
+        assert_eq!(NonZeroUsize::from(pel).get(), usize::from(value));
+        assert_eq!(pel as u8, value);
+    }
+}
+
+#[test]
+fn subpel_method_round_trips_through_integers() {
+    for (value, sharp) in [
+        (0, SubpelMethod::Bilinear),
+        (1, SubpelMethod::Bicubic),
+        (2, SubpelMethod::Wiener),
+    ] {
+        assert_eq!(SubpelMethod::try_from(value).unwrap(), sharp);
+    }
+}
+
+#[test]
+fn subpel_kernel_accepts_normalized_taps() {
+    let wiener = SubpelKernel::parse("Super", "sharp_coeffs", &[1, -5, 20, 20, -5, 1]).unwrap();
+    assert_eq!(wiener, SubpelKernel::WIENER);
+    assert_eq!(SubpelMethod::Custom(wiener).radius(), 3);
+
+    let lanczos = SubpelKernel::parse("Super", "sharp_coeffs", &[1, -4, 19, 19, -4, 1]).unwrap();
+    assert_eq!(lanczos.taps(), [1, -4, 19, 19, -4, 1]);
+}
+
+#[test]
+fn invalid_subpel_kernel_is_reported_by_name() {
+    for (taps, value, expected) in [
+        (&[16, 16][..], 2, "6 taps"),
+        (&[0, 0, 16, 16, 0, 0, 0], 7, "6 taps"),
+        (&[0, 0, 16, 16, 0, 1], 33, "taps summing to 32"),
+        (
+            &[-2000, 0, 1016, 1016, 0, 0],
+            -2000,
+            "taps between -1024 and 1024",
+        ),
+    ] {
+        let err = SubpelKernel::parse("Super", "sharp_coeffs", taps).unwrap_err();
+        assert_eq!(err, ZooMvError::InvalidParam {
+            filter: "Super",
+            name: "sharp_coeffs",
+            value,
+            expected,
+        });
+    }
+}
+
+#[test]
+fn invalid_pel_is_reported_by_name() {
+    for value in [-1, 0, 3, 5, 8] {
+        let err = parse_param("Super", "pel", Some(value), Subpel::Half, "1, 2, or 4").unwrap_err();
+        assert_eq!(err, ZooMvError::InvalidParam {
+            filter: "Super",
+            name: "pel",
+            value,
+            expected: "1, 2, or 4",
+        });
+        assert_eq!(
+            err.to_string(),
+            format!("Super: 'pel' must be 1, 2, or 4, got {value}")
+        );
+    }
+}
+
+#[test]
+fn invalid_sharp_is_reported_by_name() {
+    for value in [-1, 3, 4] {
+        let err = parse_param(
+            "Super",
+            "sharp",
+            Some(value),
+            SubpelMethod::Wiener,
+            "0, 1, or 2",
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!("Super: 'sharp' must be 0, 1, or 2, got {value}")
+        );
+    }
+}
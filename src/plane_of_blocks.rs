@@ -1,20 +1,32 @@
+#[cfg(test)]
+mod tests;
+
 use std::{
     cmp::{max, min},
     num::{NonZeroU8, NonZeroUsize},
-    ptr::slice_from_raw_parts_mut,
 };
 
 use anyhow::Result;
 use bitflags::bitflags;
+use rayon::{ThreadPool, prelude::*};
 use smallvec::SmallVec;
 use vapoursynth::frame::Frame;
 
 use crate::{
-    dct::DctHelper,
-    mv::{CheckMVFlags, MotionVector},
+    dct::{DctHelper, DctPool},
+    mv::{CheckMVFlags, MV_SIZE, MotionVector, MvBounds, clamp_mv},
     mv_frame::MVFrame,
     params::{DctMode, DivideMode, MVPlaneSet, MotionFlags, PenaltyScaling, SearchType, Subpel},
-    util::{Pixel, get_sad, get_satd, luma_sum, median, plane_with_padding},
+    util::{
+        Pixel,
+        PlaneView,
+        get_sad,
+        get_sad_with_threshold,
+        get_satd,
+        luma_sum,
+        median,
+        plane_with_padding,
+    },
 };
 
 // max block width * max block height
@@ -23,6 +35,7 @@ const MAX_BLOCK_SIZE: usize = 128 * 128;
 const MAX_PREDICTOR: usize = 5;
 
 #[allow(dead_code)]
+#[derive(Clone)]
 pub(crate) struct PlaneOfBlocks<T: Pixel> {
     pub pel: Subpel,
     pub log_pel: u8,
@@ -55,6 +68,10 @@ pub(crate) struct PlaneOfBlocks<T: Pixel> {
     pub dct_pitch: NonZeroUsize,
     pub freq_size: NonZeroUsize,
     pub freq_array: Vec<i32>,
+    /// Larger than any luma SAD of a block, as the block area times `1 << bits_per_sample`.
+    ///
+    /// This is at most `128 * 128 * 65536 = 2^30`, so even the SAD of all three planes
+    /// with the largest penalty, see [`penalized_cost`], stays far below `i64::MAX`.
     pub very_big_sad: NonZeroUsize,
 
     // TODO: We might want to move these away from this struct
@@ -68,8 +85,12 @@ pub(crate) struct PlaneOfBlocks<T: Pixel> {
     dct_mode: Option<DctMode>,
     dct_weight_16: u32,
     bad_sad: u64,
+    trivial_sad: u64,
     bad_range: i32,
     zero_mv_field_shifted: MotionVector,
+    /// luma pixel of this level where the first block starts, relative to the unpadded plane,
+    /// to search only a region of the frame
+    origin: (usize, usize),
     /// absolute x coordinate of the origin of the block in the reference frame
     x: [i32; 3],
     /// absolute y coordinate of the origin of the block in the reference frame
@@ -81,17 +102,22 @@ pub(crate) struct PlaneOfBlocks<T: Pixel> {
     search_param: i32,
     penalty_zero: u16,
     penalty_global: u16,
+    /// How far the predictor is moved toward the global motion, out of 256
+    global_weight: u16,
     penalty_new: u16,
     bad_count: usize,
+    /// number of predictors that were outside of the search range and had to be clamped
+    clamped_count: usize,
+    /// number of candidate vectors checked, to test how much of the search was skipped
+    #[cfg(test)]
+    check_mv_count: usize,
     try_many: bool,
     /// direction of scan (1 is left to rught, -1 is right to left)
     blk_scan_dir: i8,
     lambda: u32,
     lambda_sad: u32,
-    dx_max: i32,
-    dy_max: i32,
-    dx_min: i32,
-    dy_min: i32,
+    /// search range of the current block
+    bounds: MvBounds,
     predictor: MotionVector,
     predictors: [MotionVector; MAX_PREDICTOR],
     best_mv: MotionVector,
@@ -100,6 +126,8 @@ pub(crate) struct PlaneOfBlocks<T: Pixel> {
     ref_luma: u64,
     sum_luma_change: i64,
     min_cost: i64,
+    /// the vector whose cost is `min_cost`, used to break ties between equal costs
+    min_cost_mv: (i32, i32),
     blk_x_i: usize,
     blk_y_i: usize,
     blk_idx: usize,
@@ -182,8 +210,10 @@ impl<T: Pixel> PlaneOfBlocks<T> {
             dct_mode: Default::default(),
             dct_weight_16: Default::default(),
             bad_sad: Default::default(),
+            trivial_sad: Default::default(),
             bad_range: Default::default(),
             zero_mv_field_shifted: Default::default(),
+            origin: (0, 0),
             x: Default::default(),
             y: Default::default(),
             src_pitch: src_pitch_temp,
@@ -192,17 +222,18 @@ impl<T: Pixel> PlaneOfBlocks<T> {
             search_param: Default::default(),
             penalty_zero: Default::default(),
             penalty_global: Default::default(),
+            global_weight: Default::default(),
             bad_count: Default::default(),
+            clamped_count: Default::default(),
+            #[cfg(test)]
+            check_mv_count: Default::default(),
             try_many: Default::default(),
             sum_luma_change: Default::default(),
             blk_scan_dir: Default::default(),
             penalty_new: Default::default(),
             lambda: Default::default(),
             lambda_sad: Default::default(),
-            dx_max: Default::default(),
-            dy_max: Default::default(),
-            dx_min: Default::default(),
-            dy_min: Default::default(),
+            bounds: Default::default(),
             predictor: Default::default(),
             predictors: Default::default(),
             best_mv: Default::default(),
@@ -210,6 +241,7 @@ impl<T: Pixel> PlaneOfBlocks<T> {
             src_luma: Default::default(),
             ref_luma: Default::default(),
             min_cost: Default::default(),
+            min_cost_mv: Default::default(),
             blk_idx: Default::default(),
             blk_x_i: Default::default(),
             blk_y_i: Default::default(),
@@ -236,10 +268,14 @@ impl<T: Pixel> PlaneOfBlocks<T> {
         mean_luma_change: &'a mut i32,
         penalty_zero: u16,
         penalty_global: u16,
+        global_weight: u16,
         bad_sad: u64,
+        trivial_sad: u64,
         bad_range: i32,
         meander: bool,
         try_many: bool,
+        dct_pool: &'a DctPool,
+        pool: Option<&'a ThreadPool>,
     ) -> Result<()> {
         let args = SearchMvsArgs {
             out_idx,
@@ -259,10 +295,14 @@ impl<T: Pixel> PlaneOfBlocks<T> {
             mean_luma_change,
             penalty_zero,
             penalty_global,
+            global_weight,
             bad_sad,
+            trivial_sad,
             bad_range,
             meander,
             try_many,
+            dct_pool,
+            pool,
         };
         match (u8::from(dct_mode), self.log_pel) {
             (0, 0) => self.search_mvs_internal::<0, 0>(args),
@@ -324,19 +364,22 @@ impl<T: Pixel> PlaneOfBlocks<T> {
             mean_luma_change,
             penalty_zero,
             penalty_global,
+            global_weight,
             bad_sad,
+            trivial_sad,
             bad_range,
             meander,
             try_many,
+            dct_pool,
+            pool,
         } = args;
 
         // TODO: Do we really need to be setting all of these as fields on the struct?
         if (1..=4).contains(&DCT_MODE) {
-            self.dct = Some(DctHelper::new(
-                self.blk_size_x,
-                self.blk_size_y,
-                self.bits_per_sample,
-            )?);
+            // The pool's kind chooses between FFTW's float DCT and the fixed-point one,
+            // whose vectors are the same on every FFTW build and CPU
+            self.dct =
+                Some(dct_pool.take(self.blk_size_x, self.blk_size_y, self.bits_per_sample)?);
         }
         self.dct_mode = Some(DctMode::try_from(DCT_MODE as i64).expect("DCT_MODE must be valid"));
         self.dct_weight_16 = min(
@@ -345,6 +388,7 @@ impl<T: Pixel> PlaneOfBlocks<T> {
                 / (self.blk_size_x.get() * self.blk_size_y.get()) as u32,
         );
         self.bad_sad = bad_sad;
+        self.trivial_sad = trivial_sad;
         self.bad_range = bad_range;
         self.zero_mv_field_shifted = MotionVector {
             x: 0,
@@ -357,27 +401,10 @@ impl<T: Pixel> PlaneOfBlocks<T> {
             sad: global_mv.sad,
         };
 
-        let plane_data_size =
-            (size_of::<i32>() + self.blk_count.get() * size_of::<MotionVector>()) as i32;
+        let plane_data_size = (size_of::<i32>() + self.blk_count.get() * MV_SIZE) as i32;
         out.block_data[out_idx..][..size_of::<i32>()]
             .copy_from_slice(&plane_data_size.to_le_bytes());
 
-        // SAFETY: We only modify the contents of this data within this function,
-        // so we control the layout.
-        let blk_data: &mut [MotionVector] = unsafe {
-            let data = &mut out.block_data[(out_idx + size_of::<i32>())..];
-            &mut *slice_from_raw_parts_mut(
-                data.as_mut_ptr().cast(),
-                data.len() / size_of::<MotionVector>(),
-            )
-        };
-        self.y[0] = src_frame.planes[0].vpad as i32;
-        if (src_frame.yuv_mode & MVPlaneSet::UPLANE).bits() > 0 {
-            self.y[1] = src_frame.planes[1].vpad as i32;
-        }
-        if (src_frame.yuv_mode & MVPlaneSet::VPLANE).bits() > 0 {
-            self.y[2] = src_frame.planes[2].vpad as i32;
-        }
         self.src_pitch[0] = src_frame.planes[0].pitch;
         if self.chroma {
             self.src_pitch[1] = src_frame.planes[1].pitch;
@@ -390,167 +417,319 @@ impl<T: Pixel> PlaneOfBlocks<T> {
         }
         self.search_type = search_type;
         self.search_param = search_param;
-        let mut lambda_level = lambda / (1u32 << LOG_PEL).pow(2);
-        if penalty_level == PenaltyScaling::Linear {
-            lambda_level *= self.scale as u32;
-        } else if penalty_level == PenaltyScaling::Quadratic {
-            lambda_level *= self.scale.pow(2) as u32;
-        }
+        let lambda_level = level_lambda(lambda, 1 << LOG_PEL, self.scale, penalty_level);
         self.penalty_zero = penalty_zero;
         self.penalty_global = penalty_global;
+        self.global_weight = global_weight;
         self.bad_count = 0;
+        self.clamped_count = 0;
         self.try_many = try_many;
         self.sum_luma_change = 0;
 
-        // Functions using float must not be used here
-        // TODO: why?
+        self.penalty_new = penalty_new;
+        self.lambda_sad = lambda_sad;
+        let frames = SearchFrames {
+            src_frame,
+            src_frame_data,
+            ref_frame,
+            ref_frame_data,
+        };
+
+        // The searches read their predictors from `vectors` while updating `self`
+        let mut vectors = std::mem::take(&mut self.vectors);
+        let result = match pool {
+            Some(pool) if !meander && self.bad_range == 0 && self.blk_y.get() > 1 => self
+                .search_wavefront::<DCT_MODE, LOG_PEL>(
+                    pool,
+                    dct_pool,
+                    &frames,
+                    lambda_level,
+                    &mut vectors,
+                ),
+            _ => self.search_serial::<DCT_MODE, LOG_PEL>(
+                &frames,
+                lambda_level,
+                meander,
+                &mut vectors,
+            ),
+        };
+        self.vectors = vectors;
+        if let Some(dct) = self.dct.take() {
+            dct_pool.give_back(dct);
+        }
+        result?;
+
+        let blk_data = &mut out.block_data[out_idx + size_of::<i32>()..];
+        for (mv, bytes) in self.vectors.iter().zip(blk_data.chunks_exact_mut(MV_SIZE)) {
+            mv.write_to(bytes);
+        }
+
+        if self.smallest_plane {
+            *mean_luma_change = (self.sum_luma_change / self.blk_count.get() as i64) as i32;
+        }
+
+        Ok(())
+    }
+
+    /// Searches the blocks one at a time, row by row.
+    ///
+    /// With `meander`, odd rows are scanned from right to left.
+    fn search_serial<const DCT_MODE: u8, const LOG_PEL: usize>(
+        &mut self,
+        frames: &SearchFrames,
+        lambda_level: u32,
+        meander: bool,
+        vectors: &mut [MotionVector],
+    ) -> Result<()> {
+        // mvtools must not use float here, because its SAD functions leave the MMX
+        // registers in use. That does not apply to this port, see `DctKind`.
         for blk_y in 0..self.blk_y.get() {
-            self.blk_y_i = blk_y;
-            self.blk_scan_dir = if blk_y % 2 == 0 || !meander { 1 } else { -1 };
             // meander (alternate) scan blocks (even row left to right, odd row right to left)
-            let blk_x_start: i32 = if blk_y % 2 == 0 || !meander {
-                0
+            self.blk_scan_dir = if blk_y.is_multiple_of(2) || !meander {
+                1
             } else {
-                self.blk_x.get() as i32 - 1
+                -1
             };
-            if self.blk_scan_dir == 1 {
-                self.x[0] = src_frame.planes[0].hpad as i32;
-                if self.chroma {
-                    self.x[1] = src_frame.planes[1].hpad as i32;
-                    self.x[2] = src_frame.planes[2].hpad as i32;
-                }
-            } else {
-                // start with rightmost block, but it is already set at prev row
-                self.x[0] = (src_frame.planes[0].hpad
-                    + (self.blk_size_x.get() - self.overlap_x) * (self.blk_x.get() - 1))
-                    as i32;
-                if self.chroma {
-                    self.x[1] = (src_frame.planes[1].hpad
-                        + (self.blk_size_x.get() - self.overlap_x) / self.x_ratio_uv.get() as usize
-                            * (self.blk_x.get() - 1)) as i32;
-                    self.x[2] = (src_frame.planes[2].hpad
-                        + (self.blk_size_x.get() - self.overlap_x) / self.x_ratio_uv.get() as usize
-                            * (self.blk_x.get() - 1)) as i32;
-                }
+            for iblk_x in 0..self.blk_x.get() {
+                let blk_x = if self.blk_scan_dir == 1 {
+                    iblk_x
+                } else {
+                    self.blk_x.get() - 1 - iblk_x
+                };
+                self.set_block(frames.src_frame, blk_x, blk_y);
+                let mv = self.search_block::<DCT_MODE, LOG_PEL>(frames, lambda_level, vectors)?;
+                vectors[self.blk_idx] = mv;
             }
+        }
 
-            for iblk_x in 0..self.blk_x.get() {
-                let blk_x =
-                    (blk_x_start as isize + iblk_x as isize * self.blk_scan_dir as isize) as usize;
-                self.blk_x_i = blk_x;
-                self.blk_idx = (self.blk_y_i * self.blk_x.get()) + blk_x;
-
-                self.src_offset[0] = src_frame.planes[0].get_pel_offset(self.x[0], self.y[0]);
-                if self.chroma {
-                    self.src_offset[1] = src_frame.planes[1].get_pel_offset(self.x[1], self.y[1]);
-                    self.src_offset[2] = src_frame.planes[2].get_pel_offset(self.x[2], self.y[2]);
-                }
-                // In the C version they copy to a temp aligned array here.
-                // I don't think we need that since we are not using x264's ASM,
-                // and it's probably better for performance to not need to copy the data.
-                self.src_pitch[0] = src_frame.planes[0].pitch;
-                if self.chroma {
-                    self.src_pitch[1] = src_frame.planes[1].pitch;
-                    self.src_pitch[2] = src_frame.planes[2].pitch;
-                }
+        Ok(())
+    }
 
-                // TODO: (from C) should these be scaled by pel?
-                self.lambda = if blk_y == 0 { 0 } else { lambda_level };
-                self.penalty_new = penalty_new;
-                self.lambda_sad = lambda_sad;
-
-                // decreased padding of coarse levels
-                let hpad_scaled = src_frame.planes[0].hpad >> self.log_scale;
-                let vpad_scaled = src_frame.planes[0].vpad >> self.log_scale;
-
-                // compute search boundaries
-                self.dx_max = (src_frame.planes[0].padded_width.get() as i32
-                    - self.x[0]
-                    - self.blk_size_x.get() as i32
-                    - src_frame.planes[0].hpad as i32
-                    + hpad_scaled as i32)
-                    << LOG_PEL;
-                self.dy_max = (src_frame.planes[0].padded_height.get() as i32
-                    - self.y[0]
-                    - self.blk_size_y.get() as i32
-                    - src_frame.planes[0].vpad as i32
-                    + vpad_scaled as i32)
-                    << LOG_PEL;
-                self.dx_min = -((self.x[0] - src_frame.planes[0].hpad as i32 + hpad_scaled as i32)
-                    << LOG_PEL);
-                self.dy_min = -((self.y[0] - src_frame.planes[0].vpad as i32 + vpad_scaled as i32)
-                    << LOG_PEL);
-
-                // search the MV
-                self.predictor = self.clip_mv(self.vectors[self.blk_idx]);
-                self.predictors[4] = self.clip_mv(MotionVector::zero());
-
-                self.pseudo_epz_search::<DCT_MODE, LOG_PEL>(
-                    src_frame_data,
-                    ref_frame,
-                    ref_frame_data,
-                )?;
+    /// Searches the blocks in the diagonal waves of [`wavefront`], in parallel on `pool`.
+    ///
+    /// Without meander, a block's predictors are the blocks to its left, above and above right,
+    /// which are all in earlier waves, and the coarse prediction of the block below right,
+    /// which is in a later wave and not overwritten yet. So the vectors are the same as
+    /// [`Self::search_serial`] would find, as long as the bad vector search is disabled,
+    /// since its threshold depends on the number of bad blocks found before.
+    fn search_wavefront<const DCT_MODE: u8, const LOG_PEL: usize>(
+        &mut self,
+        pool: &ThreadPool,
+        dct_pool: &DctPool,
+        frames: &SearchFrames,
+        lambda_level: u32,
+        vectors: &mut [MotionVector],
+    ) -> Result<()> {
+        self.blk_scan_dir = 1;
 
-                // write the results
-                blk_data[self.blk_idx] = self.best_mv;
+        // The global predictor is clipped to the search range of each block in turn,
+        // so replay the value it would have before each block in a serial scan
+        let mut global_predictors = Vec::with_capacity(self.blk_count.get());
+        for blk_y in 0..self.blk_y.get() {
+            for blk_x in 0..self.blk_x.get() {
+                self.set_block(frames.src_frame, blk_x, blk_y);
+                global_predictors.push(self.global_mv_predictor);
+                let bounds = self.block_bounds::<LOG_PEL>(frames.src_frame);
+                self.global_mv_predictor = clamp_mv(self.global_mv_predictor, &bounds);
+            }
+        }
 
-                if self.smallest_plane {
-                    self.sum_luma_change += luma_sum(
-                        self.blk_size_x,
-                        self.blk_size_y,
-                        self.get_ref_block::<LOG_PEL>(ref_frame, ref_frame_data, 0, 0)?,
-                        self.ref_pitch[0],
-                    ) as i64
-                        - luma_sum(
-                            self.blk_size_x,
-                            self.blk_size_y,
-                            &plane_with_padding::<T>(src_frame_data, 0)?[self.src_offset[0]..],
-                            self.src_pitch[0],
-                        ) as i64;
-                }
+        // Each worker needs its own DCT helper, which is taken from the pool instead of cloned
+        let dct = self.dct.take();
+        let mut workers = vec![self.clone(); pool.current_num_threads()];
+        if dct.is_some() {
+            for worker in &mut workers {
+                worker.dct =
+                    Some(dct_pool.take(self.blk_size_x, self.blk_size_y, self.bits_per_sample)?);
+            }
+        }
+        self.dct = dct;
+        for wave in wavefront(self.blk_x, self.blk_y) {
+            let chunk_size = wave.len().div_ceil(workers.len());
+            let predictors: &[MotionVector] = vectors;
+            let found = pool.install(|| {
+                workers
+                    .par_iter_mut()
+                    .zip(wave.par_chunks(chunk_size))
+                    .map(|(worker, blocks)| {
+                        blocks
+                            .iter()
+                            .map(|&(blk_x, blk_y)| {
+                                worker.set_block(frames.src_frame, blk_x, blk_y);
+                                worker.global_mv_predictor = global_predictors[worker.blk_idx];
+                                let mv = worker.search_block::<DCT_MODE, LOG_PEL>(
+                                    frames,
+                                    lambda_level,
+                                    predictors,
+                                )?;
+                                Ok((worker.blk_idx, mv))
+                            })
+                            .collect::<Result<Vec<_>>>()
+                    })
+                    .collect::<Result<Vec<_>>>()
+            })?;
+            for (blk_idx, mv) in found.into_iter().flatten() {
+                vectors[blk_idx] = mv;
+            }
+        }
 
-                // increment indexes
-                if iblk_x < self.blk_x.get() - 1 {
-                    self.x[0] +=
-                        (self.blk_size_x.get() - self.overlap_x) as i32 * self.blk_scan_dir as i32;
-                    if (src_frame.yuv_mode & MVPlaneSet::UPLANE).bits() > 0 {
-                        self.x[1] += ((self.blk_size_x.get() - self.overlap_x)
-                            >> self.log_x_ratio_uv) as i32
-                            * self.blk_scan_dir as i32;
-                    }
-                    if (src_frame.yuv_mode & MVPlaneSet::VPLANE).bits() > 0 {
-                        self.x[2] += ((self.blk_size_x.get() - self.overlap_x)
-                            >> self.log_x_ratio_uv) as i32
-                            * self.blk_scan_dir as i32;
-                    }
-                }
+        for worker in workers {
+            self.clamped_count += worker.clamped_count;
+            self.bad_count += worker.bad_count;
+            self.sum_luma_change += worker.sum_luma_change;
+            if let Some(dct) = worker.dct {
+                dct_pool.give_back(dct);
             }
+        }
 
-            self.y[0] += (self.blk_size_y.get() - self.overlap_y) as i32;
-            if (src_frame.yuv_mode & MVPlaneSet::UPLANE).bits() > 0 {
-                self.y[1] +=
-                    ((self.blk_size_y.get() - self.overlap_y) >> self.log_y_ratio_uv) as i32;
+        Ok(())
+    }
+
+    /// Moves the first block to luma pixel `(x, y)` of this level, instead of the top left corner.
+    ///
+    /// The search still reaches pixels outside of the region covered by the blocks,
+    /// up to the padding of the plane.
+    pub(crate) fn set_origin(&mut self, x: usize, y: usize) {
+        self.origin = (x, y);
+    }
+
+    /// Moves to the block in column `blk_x` and row `blk_y`.
+    fn set_block(&mut self, src_frame: &MVFrame, blk_x: usize, blk_y: usize) {
+        self.blk_x_i = blk_x;
+        self.blk_y_i = blk_y;
+        self.blk_idx = blk_y * self.blk_x.get() + blk_x;
+
+        let step_x = self.blk_size_x.get() - self.overlap_x;
+        let step_y = self.blk_size_y.get() - self.overlap_y;
+        let (origin_x, origin_y) = self.origin;
+        self.x[0] = (src_frame.planes[0].hpad + origin_x + step_x * blk_x) as i32;
+        self.y[0] = (src_frame.planes[0].vpad + origin_y + step_y * blk_y) as i32;
+        for (i, plane) in [(1, MVPlaneSet::UPLANE), (2, MVPlaneSet::VPLANE)] {
+            if (src_frame.yuv_mode & plane).bits() > 0 {
+                self.x[i] = (src_frame.planes[i].hpad
+                    + (origin_x >> self.log_x_ratio_uv)
+                    + (step_x >> self.log_x_ratio_uv) * blk_x) as i32;
+                self.y[i] = (src_frame.planes[i].vpad
+                    + (origin_y >> self.log_y_ratio_uv)
+                    + (step_y >> self.log_y_ratio_uv) * blk_y) as i32;
             }
-            if (src_frame.yuv_mode & MVPlaneSet::VPLANE).bits() > 0 {
-                self.y[2] +=
-                    ((self.blk_size_y.get() - self.overlap_y) >> self.log_y_ratio_uv) as i32;
+        }
+    }
+
+    /// The search range of the current block.
+    #[must_use]
+    fn block_bounds<const LOG_PEL: usize>(&self, src_frame: &MVFrame) -> MvBounds {
+        let plane = &src_frame.planes[0];
+        // decreased padding of coarse levels
+        let hpad_scaled = plane.hpad >> self.log_scale;
+        let vpad_scaled = plane.vpad >> self.log_scale;
+
+        MvBounds {
+            dx_min: -((self.x[0] - plane.hpad as i32 + hpad_scaled as i32) << LOG_PEL),
+            dy_min: -((self.y[0] - plane.vpad as i32 + vpad_scaled as i32) << LOG_PEL),
+            dx_max: (plane.padded_width.get() as i32
+                - self.x[0]
+                - self.blk_size_x.get() as i32
+                - plane.hpad as i32
+                + hpad_scaled as i32)
+                << LOG_PEL,
+            dy_max: (plane.padded_height.get() as i32
+                - self.y[0]
+                - self.blk_size_y.get() as i32
+                - plane.vpad as i32
+                + vpad_scaled as i32)
+                << LOG_PEL,
+        }
+    }
+
+    /// The search range of every block, in raster order.
+    ///
+    /// Blocks near the frame borders have asymmetric ranges, since vectors must keep them
+    /// within the padding, which helps to diagnose clamped vectors.
+    #[must_use]
+    pub(crate) fn search_bounds(&mut self, src_frame: &MVFrame) -> Vec<MvBounds> {
+        let mut bounds = Vec::with_capacity(self.blk_count.get());
+        for blk_y in 0..self.blk_y.get() {
+            for blk_x in 0..self.blk_x.get() {
+                self.set_block(src_frame, blk_x, blk_y);
+                bounds.push(match self.log_pel {
+                    0 => self.block_bounds::<0>(src_frame),
+                    1 => self.block_bounds::<1>(src_frame),
+                    _ => self.block_bounds::<2>(src_frame),
+                });
             }
         }
+        bounds
+    }
+
+    /// Searches the vector of the current block, with the predictors from `vectors`.
+    fn search_block<const DCT_MODE: u8, const LOG_PEL: usize>(
+        &mut self,
+        frames: &SearchFrames,
+        lambda_level: u32,
+        vectors: &[MotionVector],
+    ) -> Result<MotionVector> {
+        let SearchFrames {
+            src_frame,
+            src_frame_data,
+            ref_frame,
+            ref_frame_data,
+        } = *frames;
+
+        self.src_offset[0] = src_frame.planes[0].get_pel_offset(self.x[0], self.y[0]);
+        if self.chroma {
+            self.src_offset[1] = src_frame.planes[1].get_pel_offset(self.x[1], self.y[1]);
+            self.src_offset[2] = src_frame.planes[2].get_pel_offset(self.x[2], self.y[2]);
+        }
+        // In the C version they copy to a temp aligned array here.
+        // I don't think we need that since we are not using x264's ASM,
+        // and it's probably better for performance to not need to copy the data.
+        self.src_pitch[0] = src_frame.planes[0].pitch;
+        if self.chroma {
+            self.src_pitch[1] = src_frame.planes[1].pitch;
+            self.src_pitch[2] = src_frame.planes[2].pitch;
+        }
+
+        // TODO: (from C) should these be scaled by pel?
+        self.lambda = row_lambda(lambda_level, self.blk_y_i);
+
+        // compute search boundaries
+        self.bounds = self.block_bounds::<LOG_PEL>(src_frame);
+
+        // search the MV
+        self.predictor = self.clip_mv_counted(vectors[self.blk_idx]);
+        self.predictors[4] = self.clip_mv(MotionVector::zero());
+
+        self.pseudo_epz_search::<DCT_MODE, LOG_PEL>(
+            vectors,
+            src_frame_data,
+            ref_frame,
+            ref_frame_data,
+        )?;
 
         if self.smallest_plane {
-            *mean_luma_change = (self.sum_luma_change / self.blk_count.get() as i64) as i32;
+            self.sum_luma_change += luma_sum(
+                self.blk_size_x,
+                self.blk_size_y,
+                self.get_ref_block::<LOG_PEL>(ref_frame, ref_frame_data, 0, 0)?,
+                self.ref_pitch[0],
+            ) as i64
+                - luma_sum(
+                    self.blk_size_x,
+                    self.blk_size_y,
+                    &plane_with_padding::<T>(src_frame_data, 0)?[self.src_offset[0]..],
+                    self.src_pitch[0],
+                ) as i64;
         }
 
-        Ok(())
+        Ok(self.best_mv)
     }
 
     #[must_use]
     pub(crate) fn get_array_size(&self, divide: DivideMode) -> NonZeroUsize {
         let mut size = self
             .blk_count
-            .saturating_mul(
-                NonZeroUsize::new(size_of::<MotionVector>()).expect("type should be sized"),
-            )
+            .saturating_mul(NonZeroUsize::new(MV_SIZE).expect("type should be sized"))
             .saturating_add(size_of::<u32>());
 
         if self.log_scale == 0 && divide != DivideMode::None {
@@ -558,8 +737,7 @@ impl<T: Pixel> PlaneOfBlocks<T> {
                 self.blk_count
                     .saturating_mul(
                         // reserve space for divided subblocks extra level
-                        NonZeroUsize::new(size_of::<MotionVector>() * 4)
-                            .expect("type should be sized"),
+                        NonZeroUsize::new(MV_SIZE * 4).expect("type should be sized"),
                     )
                     .saturating_add(size_of::<u32>())
                     .get(),
@@ -569,6 +747,17 @@ impl<T: Pixel> PlaneOfBlocks<T> {
         size
     }
 
+    /// Statistics about the most recent search on this plane.
+    #[must_use]
+    pub(crate) fn search_stats(&self) -> SearchStats {
+        SearchStats {
+            clamped_count: self.clamped_count,
+            bad_count: self.bad_count,
+            sad_sum: self.vectors.iter().map(|v| v.sad.max(0) as u64).sum(),
+            blk_count: self.blk_count.get(),
+        }
+    }
+
     /// estimate global motion from current plane vectors data for using on next
     /// plane - on input globalMVec is prev estimation, on output
     /// globalMVec is doubled for next scale plane using
@@ -773,25 +962,21 @@ impl<T: Pixel> PlaneOfBlocks<T> {
 
     #[must_use]
     fn clip_mv(&self, v: MotionVector) -> MotionVector {
-        MotionVector {
-            x: self.clip_mv_x(v.x),
-            y: self.clip_mv_y(v.y),
-            sad: v.sad,
-        }
-    }
-
-    #[must_use]
-    fn clip_mv_x(&self, x: i32) -> i32 {
-        min(max(x, self.dx_min), self.dx_max - 1)
+        clamp_mv(v, &self.bounds)
     }
 
-    #[must_use]
-    fn clip_mv_y(&self, y: i32) -> i32 {
-        min(max(y, self.dy_min), self.dy_max - 1)
+    /// Clips a vector to the search range, counting it if it was out of range.
+    fn clip_mv_counted(&mut self, v: MotionVector) -> MotionVector {
+        let clipped = self.clip_mv(v);
+        if clipped.x != v.x || clipped.y != v.y {
+            self.clamped_count += 1;
+        }
+        clipped
     }
 
     fn pseudo_epz_search<const DCT_MODE: u8, const LOG_PEL: usize>(
         &mut self,
+        vectors: &[MotionVector],
         src_frame_data: &Frame,
         ref_frame: &MVFrame,
         ref_frame_data: &Frame,
@@ -813,7 +998,7 @@ impl<T: Pixel> PlaneOfBlocks<T> {
             &src_plane_v[self.src_offset[2]..],
         ];
 
-        self.fetch_predictors();
+        self.fetch_predictors(vectors);
 
         if (1..=4).contains(&DCT_MODE) {
             // make dct of source block
@@ -852,6 +1037,7 @@ impl<T: Pixel> PlaneOfBlocks<T> {
                 self.zero_mv_field_shifted.y,
             )?,
             self.ref_pitch[0],
+            u64::MAX,
         );
         if self.chroma {
             sad += self.chroma_sad(
@@ -868,7 +1054,16 @@ impl<T: Pixel> PlaneOfBlocks<T> {
             );
         }
         self.best_mv.sad = sad as i64;
-        self.min_cost = (sad + ((self.penalty_zero as u64 * sad) >> 8)) as i64;
+        self.min_cost = penalized_cost(sad, self.penalty_zero);
+        self.min_cost_mv = (self.best_mv.x, self.best_mv.y);
+
+        if sad < self.trivial_sad {
+            // Static block, keep the zero vector without searching any further.
+            // The global predictor is still clipped as it would be by the full search,
+            // since the clipped value carries over to the next blocks.
+            self.global_mv_predictor = self.clip_mv_counted(self.global_mv_predictor);
+            return Ok(());
+        }
 
         let mut best_mv_many = [MotionVector::zero(); 8];
         let mut min_cost_many = [0; 8];
@@ -880,7 +1075,12 @@ impl<T: Pixel> PlaneOfBlocks<T> {
         }
 
         // Global MV predictor
-        self.global_mv_predictor = self.clip_mv(self.global_mv_predictor);
+        self.global_mv_predictor = self.clip_mv_counted(self.global_mv_predictor);
+        if self.global_weight > 0 {
+            // Both vectors are inside the search bounds, and so is any point between them
+            self.predictor =
+                blend_toward_global(self.predictor, self.global_mv_predictor, self.global_weight);
+        }
         let mut sad = self.luma_sad::<DCT_MODE>(
             src_planes[0],
             self.src_pitch[0],
@@ -891,6 +1091,7 @@ impl<T: Pixel> PlaneOfBlocks<T> {
                 self.global_mv_predictor.y,
             )?,
             self.ref_pitch[0],
+            u64::MAX,
         );
         if self.chroma {
             sad += self.chroma_sad(
@@ -916,13 +1117,14 @@ impl<T: Pixel> PlaneOfBlocks<T> {
                 self.ref_pitch[2],
             );
         }
-        let cost = (sad + ((self.penalty_global as u64 * sad) >> 8)) as i64;
+        let cost = penalized_cost(sad, self.penalty_global);
 
         if cost < self.min_cost || self.try_many {
             self.best_mv.x = self.global_mv_predictor.x;
             self.best_mv.y = self.global_mv_predictor.y;
             self.best_mv.sad = sad as i64;
             self.min_cost = cost;
+            self.min_cost_mv = (self.best_mv.x, self.best_mv.y);
         }
         if self.try_many {
             // refine around global
@@ -942,6 +1144,7 @@ impl<T: Pixel> PlaneOfBlocks<T> {
                 self.predictor.y,
             )?,
             self.ref_pitch[0],
+            u64::MAX,
         );
         if self.chroma {
             sad += self.chroma_sad(
@@ -974,6 +1177,7 @@ impl<T: Pixel> PlaneOfBlocks<T> {
             self.best_mv.y = self.predictor.y;
             self.best_mv.sad = sad as i64;
             self.min_cost = cost as i64;
+            self.min_cost_mv = (self.best_mv.x, self.best_mv.y);
         }
         if self.try_many {
             // refine around predictor
@@ -986,6 +1190,7 @@ impl<T: Pixel> PlaneOfBlocks<T> {
         let npred = 4;
         for i in 0..npred {
             if self.try_many {
+                // Each candidate starts from its own cost, not from one lowered by an earlier candidate
                 self.min_cost = self.very_big_sad.get() as i64 + 1;
             }
             self.check_mv0::<DCT_MODE, LOG_PEL>(
@@ -997,6 +1202,11 @@ impl<T: Pixel> PlaneOfBlocks<T> {
             )?;
 
             if self.try_many {
+                if self.min_cost > self.very_big_sad.get() as i64 {
+                    // The predictor was rejected, and the best vector still belongs to another candidate
+                    min_cost_many[i + 3] = i64::MAX;
+                    continue;
+                }
                 // refine around predictor
                 self.refine::<DCT_MODE, LOG_PEL>(src_planes, ref_frame, ref_frame_data)?;
                 best_mv_many[i + 3] = self.best_mv;
@@ -1005,20 +1215,16 @@ impl<T: Pixel> PlaneOfBlocks<T> {
         }
 
         if self.try_many {
-            self.min_cost = self.very_big_sad.get() as i64 + 1;
-            for i in 0..(npred + 3) {
-                if min_cost_many[i] < self.min_cost {
-                    self.best_mv = best_mv_many[i];
-                    self.min_cost = min_cost_many[i];
-                }
-            }
+            self.select_best_candidate(&best_mv_many[..npred + 3], &min_cost_many[..npred + 3]);
         } else {
             self.refine::<DCT_MODE, LOG_PEL>(src_planes, ref_frame, ref_frame_data)?;
         }
 
         let found_sad = self.best_mv.sad;
         const BADCOUNT_LIMIT: u64 = 16;
-        if self.blk_idx > 1
+        // a zero range disables the recovery of bad vectors entirely
+        if self.bad_range != 0
+            && self.blk_idx > 1
             && found_sad
                 > (self.bad_sad + self.bad_sad * self.bad_count as u64 / BADCOUNT_LIMIT) as i64
         {
@@ -1035,7 +1241,7 @@ impl<T: Pixel> PlaneOfBlocks<T> {
                     0,
                     0,
                 )?;
-            } else if self.bad_range < 0 {
+            } else {
                 // ESA
                 for i in (1..(-self.bad_range * (1 << LOG_PEL))).step_by(1 << LOG_PEL) {
                     // at radius
@@ -1069,8 +1275,6 @@ impl<T: Pixel> PlaneOfBlocks<T> {
             }
         }
 
-        // store the result
-        self.vectors[self.blk_idx] = self.best_mv;
         Ok(())
     }
 
@@ -1081,7 +1285,7 @@ impl<T: Pixel> PlaneOfBlocks<T> {
         vx: i32,
         vy: i32,
     ) -> Result<&'a [T]> {
-        let plane = plane_with_padding(ref_frame_data, 0)?;
+        let plane = PlaneView::from_frame(ref_frame_data, 0)?;
         let mvplane = &ref_frame.planes[0];
         let offset = match LOG_PEL {
             0 => mvplane.get_absolute_offset_pel1(self.x[0] + vx, self.y[0] + vy),
@@ -1089,7 +1293,7 @@ impl<T: Pixel> PlaneOfBlocks<T> {
             2 => mvplane.get_absolute_offset_pel4(self.x[0] * 4 + vx, self.y[0] * 4 + vy),
             _ => unreachable!(),
         };
-        Ok(&plane[offset..])
+        plane.block(offset, self.blk_size_x.get(), self.blk_size_y.get())
     }
 
     fn get_ref_block_u<'a, const LOG_PEL: usize>(
@@ -1131,7 +1335,7 @@ impl<T: Pixel> PlaneOfBlocks<T> {
             0
         };
 
-        let plane = plane_with_padding(ref_frame_data, plane_idx)?;
+        let plane = PlaneView::from_frame(ref_frame_data, plane_idx)?;
         let mvplane = &ref_frame.planes[plane_idx];
         let offset = match LOG_PEL {
             0 => mvplane.get_absolute_offset_pel1(
@@ -1148,24 +1352,27 @@ impl<T: Pixel> PlaneOfBlocks<T> {
             ),
             _ => unreachable!(),
         };
-        Ok(&plane[offset..])
+        plane.block(
+            offset,
+            self.blk_size_x.get() / self.x_ratio_uv.get() as usize,
+            self.blk_size_y.get() / self.y_ratio_uv.get() as usize,
+        )
     }
 
-    fn fetch_predictors(&mut self) {
+    fn fetch_predictors(&mut self, vectors: &[MotionVector]) {
         // Left (or right) predictor
         if (self.blk_scan_dir == 1 && self.blk_x_i > 0)
             || (self.blk_scan_dir == -1 && self.blk_x_i < self.blk_x.get() - 1)
         {
-            self.predictors[1] = self.clip_mv(
-                self.vectors[(self.blk_idx as isize - self.blk_scan_dir as isize) as usize],
-            );
+            self.predictors[1] = self
+                .clip_mv(vectors[(self.blk_idx as isize - self.blk_scan_dir as isize) as usize]);
         } else {
             self.predictors[1] = self.clip_mv(self.zero_mv_field_shifted);
         }
 
         // Up predictor
         if self.blk_y_i > 0 {
-            self.predictors[2] = self.clip_mv(self.vectors[self.blk_idx - self.blk_x.get()]);
+            self.predictors[2] = self.clip_mv(vectors[self.blk_idx - self.blk_x.get()]);
         } else {
             self.predictors[2] = self.clip_mv(self.zero_mv_field_shifted);
         }
@@ -1176,8 +1383,8 @@ impl<T: Pixel> PlaneOfBlocks<T> {
                 || (self.blk_scan_dir == -1 && self.blk_x_i > 0))
         {
             self.predictors[3] = self.clip_mv(
-                self.vectors[((self.blk_idx + self.blk_x.get()) as isize
-                    + self.blk_scan_dir as isize) as usize],
+                vectors[((self.blk_idx + self.blk_x.get()) as isize + self.blk_scan_dir as isize)
+                    as usize],
             );
         } else if (self.blk_y_i > 0)
             && ((self.blk_scan_dir == 1 && self.blk_x_i < self.blk_x.get() - 1)
@@ -1185,7 +1392,7 @@ impl<T: Pixel> PlaneOfBlocks<T> {
         {
             // Up-right predictor
             self.predictors[3] = self.clip_mv(
-                self.vectors[(self.blk_idx as isize - self.blk_x.get() as isize
+                vectors[(self.blk_idx as isize - self.blk_x.get() as isize
                     + self.blk_scan_dir as isize) as usize],
             );
         } else {
@@ -1221,11 +1428,13 @@ impl<T: Pixel> PlaneOfBlocks<T> {
         if self.smallest_plane {
             self.predictor = self.predictors[0];
         }
-        let scale =
-            self.lambda_sad as f64 / (self.lambda_sad as i64 + (self.predictor.sad >> 1)) as f64;
-        self.lambda = (self.lambda as f64 * scale * scale) as u32;
+        self.lambda = predictor_lambda(self.lambda, self.lambda_sad, self.predictor.sad);
     }
 
+    /// Computes the luma SAD of the current block against `ref_plane`, as chosen by `DCT_MODE`.
+    ///
+    /// The spatial SAD may stop summing once it is above `threshold`,
+    /// as described in [`get_sad_with_threshold`].
     #[must_use]
     fn luma_sad<const DCT_MODE: u8>(
         &mut self,
@@ -1233,16 +1442,18 @@ impl<T: Pixel> PlaneOfBlocks<T> {
         src_pitch: NonZeroUsize,
         ref_plane: &[T],
         ref_pitch: NonZeroUsize,
+        threshold: u64,
     ) -> u64 {
         let dct_mode = DctMode::try_from(DCT_MODE as i64).expect("invalid dct mode");
         match dct_mode {
-            DctMode::Spatial => get_sad(
+            DctMode::Spatial => get_sad_with_threshold(
                 self.blk_size_x,
                 self.blk_size_y,
                 src_plane,
                 src_pitch,
                 ref_plane,
                 ref_pitch,
+                threshold,
             ),
             DctMode::Dct => self.reduction_corrected_dct(ref_plane, ref_pitch),
             DctMode::MixedSpatialDct => {
@@ -1480,6 +1691,10 @@ impl<T: Pixel> PlaneOfBlocks<T> {
             / 2
     }
 
+    /// The SAD of the DCT coefficients of the source and reference blocks.
+    ///
+    /// `DctHelper` reduces the DC coefficient, so its difference is added again,
+    /// weighted by `3 * blk_size_x / 2`.
     #[must_use]
     fn reduction_corrected_dct(&mut self, ref_plane: &[T], ref_pitch: NonZeroUsize) -> u64 {
         self.dct
@@ -1700,6 +1915,32 @@ impl<T: Pixel> PlaneOfBlocks<T> {
         }>(src_planes, ref_frame, ref_frame_data, vx, vy, direction, val)
     }
 
+    /// Whether a vector `(vx, vy)` costing `cost` is better than the best vector found so far.
+    ///
+    /// Equal costs are broken by [`tie_break_key`], so the chosen vector does not depend
+    /// on the order in which the search visits candidates.
+    fn beats_min_cost(&self, cost: i64, vx: i32, vy: i32) -> bool {
+        cost < self.min_cost
+            || (cost == self.min_cost
+                && tie_break_key(vx, vy) < tie_break_key(self.min_cost_mv.0, self.min_cost_mv.1))
+    }
+
+    /// Keeps the cheapest of the `try_many` candidates, breaking ties like any other vector.
+    ///
+    /// The first candidate, refined around the zero vector, is always valid.
+    fn select_best_candidate(&mut self, best_mvs: &[MotionVector], min_costs: &[i64]) {
+        self.best_mv = best_mvs[0];
+        self.min_cost = min_costs[0];
+        self.min_cost_mv = (self.best_mv.x, self.best_mv.y);
+        for (&mv, &cost) in best_mvs.iter().zip(min_costs).skip(1) {
+            if self.beats_min_cost(cost, mv.x, mv.y) {
+                self.best_mv = mv;
+                self.min_cost = cost;
+                self.min_cost_mv = (mv.x, mv.y);
+            }
+        }
+    }
+
     fn check_mv_impl<const DCT_MODE: u8, const LOG_PEL: usize, const CHECK_MV_FLAGS: u32>(
         &mut self,
         src_planes: [&[T]; 3],
@@ -1710,29 +1951,37 @@ impl<T: Pixel> PlaneOfBlocks<T> {
         direction: &mut i32,
         val: i32,
     ) -> Result<()> {
+        #[cfg(test)]
+        {
+            self.check_mv_count += 1;
+        }
         if !self.is_vector_ok(vx, vy) {
             return Ok(());
         }
 
+        // Partial costs may still tie with the best vector, so only larger ones are skipped
         let mut cost = self.motion_distortion(vx, vy);
-        if cost >= self.min_cost {
+        if cost > self.min_cost {
             return Ok(());
         }
 
         let flags = CheckMVFlags::from_bits(CHECK_MV_FLAGS).expect("invalid check mv flags");
+        // The penalized SAD is at least the SAD, so once the SAD alone is above the remaining
+        // budget the vector is rejected below, and the rest of the block need not be summed
         let sad = self.luma_sad::<DCT_MODE>(
             src_planes[0],
             self.src_pitch[0],
             self.get_ref_block::<LOG_PEL>(ref_frame, ref_frame_data, vx, vy)?,
             self.ref_pitch[0],
-        ) as i64;
-        cost += sad
-            + if flags.contains(CheckMVFlags::PENALTY_NEW) {
-                (self.penalty_new as i64 * sad) >> 8
-            } else {
-                0
-            };
-        if cost >= self.min_cost {
+            (self.min_cost - cost) as u64,
+        );
+        let penalty = if flags.contains(CheckMVFlags::PENALTY_NEW) {
+            self.penalty_new
+        } else {
+            0
+        };
+        cost += penalized_cost(sad, penalty);
+        if cost > self.min_cost {
             return Ok(());
         }
 
@@ -1743,22 +1992,17 @@ impl<T: Pixel> PlaneOfBlocks<T> {
                 self.src_pitch[1],
                 self.get_ref_block_u::<LOG_PEL>(ref_frame, ref_frame_data, vx, vy)?,
                 self.ref_pitch[1],
-            ) as i64;
+            );
             sad_uv += self.chroma_sad(
                 src_planes[2],
                 self.src_pitch[2],
                 self.get_ref_block_v::<LOG_PEL>(ref_frame, ref_frame_data, vx, vy)?,
                 self.ref_pitch[2],
-            ) as i64;
-            cost += sad_uv
-                + if flags.contains(CheckMVFlags::PENALTY_NEW) {
-                    (self.penalty_new as i64 * sad_uv) >> 8
-                } else {
-                    0
-                };
-            if cost >= self.min_cost {
-                return Ok(());
-            }
+            );
+            cost += penalized_cost(sad_uv, penalty);
+        }
+        if !self.beats_min_cost(cost, vx, vy) {
+            return Ok(());
         }
 
         if flags.contains(CheckMVFlags::UPDATE_BEST_MV) {
@@ -1766,7 +2010,8 @@ impl<T: Pixel> PlaneOfBlocks<T> {
             self.best_mv.y = vy;
         }
         self.min_cost = cost;
-        self.best_mv.sad = sad + sad_uv;
+        self.min_cost_mv = (vx, vy);
+        self.best_mv.sad = (sad + sad_uv) as i64;
         if flags.contains(CheckMVFlags::UPDATE_DIR) {
             *direction = val;
         }
@@ -2661,7 +2906,7 @@ impl<T: Pixel> PlaneOfBlocks<T> {
 
     #[must_use]
     fn is_vector_ok(&self, vx: i32, vy: i32) -> bool {
-        (vx >= self.dx_min) && (vy >= self.dy_min) && (vx < self.dx_max) && (vy < self.dy_max)
+        self.bounds.contains(vx, vy)
     }
 
     /// computes the cost of a vector (vx, vy)
@@ -2687,23 +2932,22 @@ impl<T: Pixel> PlaneOfBlocks<T> {
         let mut data = Vec::with_capacity(array_size);
 
         // Store the size as u32 for compatibility with C plugin
-        let start_size = size_of::<u32>() + self.blk_count.get() * size_of::<MotionVector>();
+        let start_size = size_of::<u32>() + self.blk_count.get() * MV_SIZE;
         data.extend_from_slice(&(start_size as u32).to_le_bytes());
 
         for _ in 0..self.blk_count.get() {
             // Fill with blank MV data
-            data.extend_from_slice(empty_mv.bytes());
+            data.extend_from_slice(&empty_mv.to_bytes());
         }
 
         if self.log_scale == 0 && divide_extra != DivideMode::None {
             // Store the size as u32 for compatibility with C plugin
-            let subarray_size =
-                size_of::<u32>() + self.blk_count.get() * size_of::<MotionVector>() * 4;
+            let subarray_size = size_of::<u32>() + self.blk_count.get() * MV_SIZE * 4;
             data.extend_from_slice(&(subarray_size as u32).to_le_bytes());
 
             for _ in 0..(self.blk_count.get() * 4) {
                 // Fill with blank MV data
-                data.extend_from_slice(empty_mv.bytes());
+                data.extend_from_slice(&empty_mv.to_bytes());
             }
         }
 
@@ -2711,6 +2955,128 @@ impl<T: Pixel> PlaneOfBlocks<T> {
     }
 }
 
+/// Orders vectors of equal cost: the shorter vector wins, then the smaller `x`, then the smaller `y`.
+#[must_use]
+pub(crate) fn tie_break_key(vx: i32, vy: i32) -> (i64, i32, i32) {
+    let (x, y) = (i64::from(vx), i64::from(vy));
+    (x * x + y * y, vx, vy)
+}
+
+/// Adds a penalty of `penalty / 256` times the SAD to it.
+///
+/// SADs are bounded by three times [`PlaneOfBlocks::very_big_sad`], i.e. `3 * 2^30`,
+/// so the cost never exceeds `2^48` even with the largest penalty.
+#[must_use]
+pub(crate) fn penalized_cost(sad: u64, penalty: u16) -> i64 {
+    (sad + ((u64::from(penalty) * sad) >> 8)) as i64
+}
+
+/// Moves `predictor` `weight / 256` of the way toward `global`, rounding to the nearest
+/// position, so that blocks without a clear match lean toward the global motion.
+///
+/// The predictor keeps its SAD, which still describes how well its neighborhood matched.
+#[must_use]
+pub(crate) fn blend_toward_global(
+    predictor: MotionVector,
+    global: MotionVector,
+    weight: u16,
+) -> MotionVector {
+    let blend = |local: i32, global: i32| {
+        let offset = (i64::from(global) - i64::from(local)) * i64::from(weight);
+        local + ((offset + 128) >> 8) as i32
+    };
+    MotionVector {
+        x: blend(predictor.x, global.x),
+        y: blend(predictor.y, global.y),
+        sad: predictor.sad,
+    }
+}
+
+/// Reduces `lambda` by the square of `lambda_sad / (lambda_sad + predictor_sad / 2)`,
+/// so a poorly matching predictor is trusted less.
+///
+/// The ratio is kept exact in integers and only the final product is truncated.
+#[must_use]
+pub(crate) fn predictor_lambda(lambda: u32, lambda_sad: u32, predictor_sad: i64) -> u32 {
+    let lambda_sad = u128::from(lambda_sad);
+    let denominator = lambda_sad + u128::try_from(predictor_sad >> 1).unwrap_or(0);
+    if denominator == 0 {
+        return 0;
+    }
+    // The result never exceeds `lambda`
+    (u128::from(lambda) * lambda_sad * lambda_sad / (denominator * denominator)) as u32
+}
+
+/// Scales `lambda` for a hierarchical level that is `scale` times smaller than the finest one,
+/// searched at `pel` precision.
+///
+/// The penalty scaling selects whether the vector cost stays constant across levels,
+/// or grows linearly or quadratically with the level size reduction.
+#[must_use]
+pub(crate) fn level_lambda(
+    lambda: u32,
+    pel: u32,
+    scale: usize,
+    penalty_level: PenaltyScaling,
+) -> u32 {
+    let lambda = lambda / (pel * pel);
+    let scale = u32::try_from(scale).unwrap_or(u32::MAX);
+    match penalty_level {
+        PenaltyScaling::None => lambda,
+        PenaltyScaling::Linear => lambda.saturating_mul(scale),
+        PenaltyScaling::Quadratic => lambda.saturating_mul(scale).saturating_mul(scale),
+    }
+}
+
+/// Returns the `lambda` of the blocks in row `blk_y`, from the one of their level.
+///
+/// The first row has no predictors from above, so its vectors are not penalized.
+/// Every other row starts again from the level's `lambda`,
+/// before it is reduced for the block's predictor in [`predictor_lambda`].
+#[must_use]
+pub(crate) fn row_lambda(lambda_level: u32, blk_y: usize) -> u32 {
+    if blk_y == 0 { 0 } else { lambda_level }
+}
+
+/// Groups the blocks of a plane that is `blk_x` by `blk_y` blocks into waves,
+/// which only depend on the blocks in earlier waves when scanning left to right.
+///
+/// Block `(x, y)` is in wave `x + 2 * y`, so each row trails two blocks behind the one above it.
+#[must_use]
+pub(crate) fn wavefront(blk_x: NonZeroUsize, blk_y: NonZeroUsize) -> Vec<Vec<(usize, usize)>> {
+    let mut waves = vec![Vec::new(); blk_x.get() + 2 * (blk_y.get() - 1)];
+    for y in 0..blk_y.get() {
+        for x in 0..blk_x.get() {
+            waves[x + 2 * y].push((x, y));
+        }
+    }
+    waves
+}
+
+/// Aggregate statistics about a motion vector search, for diagnostics.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct SearchStats {
+    /// number of predictors that were outside of the search range and had to be clamped
+    pub clamped_count: usize,
+    /// number of blocks whose SAD was bad enough to trigger a wider search
+    pub bad_count: usize,
+    /// sum of the SADs of the resulting vectors
+    pub sad_sum: u64,
+    /// number of blocks the SADs were summed over
+    pub blk_count: usize,
+}
+
+impl SearchStats {
+    #[must_use]
+    pub fn average_sad(&self) -> f64 {
+        if self.blk_count == 0 {
+            0.0
+        } else {
+            self.sad_sum as f64 / self.blk_count as f64
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct MvsOutput {
     pub validity: bool,
@@ -2736,8 +3102,21 @@ struct SearchMvsArgs<'a> {
     pub mean_luma_change: &'a mut i32,
     pub penalty_zero: u16,
     pub penalty_global: u16,
+    pub global_weight: u16,
     pub bad_sad: u64,
+    pub trivial_sad: u64,
     pub bad_range: i32,
     pub meander: bool,
     pub try_many: bool,
+    pub dct_pool: &'a DctPool,
+    pub pool: Option<&'a ThreadPool>,
+}
+
+/// The frames a block is searched in.
+#[derive(Clone, Copy)]
+struct SearchFrames<'a> {
+    src_frame: &'a MVFrame,
+    src_frame_data: &'a Frame<'a>,
+    ref_frame: &'a MVFrame,
+    ref_frame_data: &'a Frame<'a>,
 }
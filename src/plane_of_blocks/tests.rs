@@ -0,0 +1,780 @@
+#![allow(clippy::unwrap_used, reason = "allow in test files")]
+#![allow(clippy::undocumented_unsafe_blocks, reason = "allow in test files")]
+
+use std::num::{NonZeroU8, NonZeroUsize};
+
+use vapoursynth::prelude::Environment;
+
+use super::*;
+use crate::{
+    dct::DctKind,
+    group_of_planes::level_blocks,
+    mv::MvBounds,
+    mv_frame::MVFrame,
+    mv_gof::MVGroupOfFrames,
+    mv_plane::MVPlane,
+};
+
+fn create_plane(blk_x: usize, blk_y: usize) -> PlaneOfBlocks<u8> {
+    PlaneOfBlocks::new(
+        NonZeroUsize::new(blk_x).unwrap(),
+        NonZeroUsize::new(blk_y).unwrap(),
+        NonZeroUsize::new(8).unwrap(),
+        NonZeroUsize::new(8).unwrap(),
+        Subpel::Full,
+        0,
+        MotionFlags::empty(),
+        0,
+        0,
+        NonZeroU8::new(2).unwrap(),
+        NonZeroU8::new(2).unwrap(),
+        NonZeroU8::new(8).unwrap(),
+    )
+}
+
+#[test]
+fn clamped_vectors_are_counted() {
+    let mut plane = create_plane(4, 4);
+    // artificially tiny search range
+    plane.bounds = MvBounds {
+        dx_min: -2,
+        dy_min: -2,
+        dx_max: 2,
+        dy_max: 2,
+    };
+
+    let inside = MotionVector {
+        x: 1,
+        y: -2,
+        sad: 0,
+    };
+    let clipped = plane.clip_mv_counted(inside);
+    assert_eq!((clipped.x, clipped.y), (1, -2));
+    assert_eq!(plane.search_stats().clamped_count, 0);
+
+    let outside = MotionVector {
+        x: 24,
+        y: -1,
+        sad: 0,
+    };
+    let clipped = plane.clip_mv_counted(outside);
+    assert_eq!((clipped.x, clipped.y), (1, -1));
+    assert_eq!(plane.search_stats().clamped_count, 1);
+
+    let outside = MotionVector {
+        x: 0,
+        y: -30,
+        sad: 0,
+    };
+    let clipped = plane.clip_mv_counted(outside);
+    assert_eq!((clipped.x, clipped.y), (0, -2));
+    assert_eq!(plane.search_stats().clamped_count, 2);
+}
+
+#[test]
+fn search_stats_average_sad() {
+    let mut plane = create_plane(2, 2);
+    for (i, v) in plane.vectors.iter_mut().enumerate() {
+        v.sad = (i as i64 + 1) * 100;
+    }
+    let stats = plane.search_stats();
+    assert_eq!(stats.blk_count, 4);
+    assert_eq!(stats.sad_sum, 1000);
+    assert!((stats.average_sad() - 250.0).abs() < f64::EPSILON);
+    assert!(SearchStats::default().average_sad().abs() < f64::EPSILON);
+}
+
+#[test]
+fn predictor_lambda_keeps_fractional_scale() {
+    // A predictor SAD well above lambda_sad still leaves part of the penalty
+    assert_eq!(predictor_lambda(1000, 400, 1200), 160);
+    assert_eq!(predictor_lambda(1000, 1, 4), 111);
+    assert_eq!(predictor_lambda(1000, 400, 0), 1000);
+    assert_eq!(predictor_lambda(1000, 400, 1), 1000);
+    assert_eq!(predictor_lambda(u32::MAX, u32::MAX, 0), u32::MAX);
+
+    // Without lambda_sad, the penalty is dropped entirely
+    assert_eq!(predictor_lambda(1000, 0, 1200), 0);
+    assert_eq!(predictor_lambda(1000, 0, 0), 0);
+}
+
+#[test]
+fn global_weight_moves_predictor_toward_global_motion() {
+    let local = MotionVector {
+        x: -4,
+        y: 2,
+        sad: 300,
+    };
+    let global = MotionVector {
+        x: 12,
+        y: -6,
+        sad: 0,
+    };
+    let blend = |weight| {
+        let mv = blend_toward_global(local, global, weight);
+        // The SAD still belongs to the local predictor
+        assert_eq!(mv.sad, 300);
+        (mv.x, mv.y)
+    };
+
+    // A low weight leaves the predictor local, and a full one replaces it
+    assert_eq!(blend(0), (-4, 2));
+    assert_eq!(blend(4), (-4, 2));
+    assert_eq!(blend(256), (12, -6));
+    // Otherwise it lands in between, rounded to the nearest position
+    assert_eq!(blend(128), (4, -2));
+    assert_eq!(blend(64), (0, 0));
+    assert_eq!(blend(192), (8, -4));
+    assert_eq!(blend(40), (-1, 1));
+
+    // Vectors far apart do not overflow
+    let far = MotionVector {
+        x: i32::MAX / 2,
+        y: i32::MIN / 2,
+        sad: 0,
+    };
+    let mv = blend_toward_global(far, MotionVector::zero(), 256);
+    assert_eq!((mv.x, mv.y), (0, 0));
+}
+
+#[test]
+fn level_lambda_scaling() {
+    let lambda = 4000;
+    for level in 0..4 {
+        let scale = 1 << level;
+        assert_eq!(level_lambda(lambda, 1, scale, PenaltyScaling::None), lambda);
+        assert_eq!(
+            level_lambda(lambda, 1, scale, PenaltyScaling::Linear),
+            lambda * scale as u32
+        );
+        assert_eq!(
+            level_lambda(lambda, 1, scale, PenaltyScaling::Quadratic),
+            lambda * (scale * scale) as u32
+        );
+    }
+
+    // lambda is given in pixels, so it is reduced for subpixel precision
+    assert_eq!(level_lambda(lambda, 2, 1, PenaltyScaling::None), 1000);
+    assert_eq!(level_lambda(lambda, 4, 4, PenaltyScaling::Quadratic), 4000);
+
+    // Large blocks on the coarsest levels of big frames saturate instead of overflowing
+    assert_eq!(
+        level_lambda(16_000, 1, 1 << 10, PenaltyScaling::Quadratic),
+        u32::MAX
+    );
+    assert_eq!(
+        level_lambda(u32::MAX, 1, 2, PenaltyScaling::Linear),
+        u32::MAX
+    );
+}
+
+#[test]
+fn first_row_lambda_does_not_leak_into_later_rows() {
+    let lambda = 4000;
+    for penalty_level in [
+        PenaltyScaling::None,
+        PenaltyScaling::Linear,
+        PenaltyScaling::Quadratic,
+    ] {
+        for level in 0..4 {
+            let lambda_level = level_lambda(lambda, 1, 1 << level, penalty_level);
+            assert_eq!(row_lambda(lambda_level, 0), 0);
+            for blk_y in 1..4 {
+                assert_eq!(
+                    row_lambda(lambda_level, blk_y),
+                    lambda_level,
+                    "{penalty_level:?} level {level} row {blk_y}"
+                );
+            }
+        }
+    }
+}
+
+/// Creates a plane whose source block is a flat 8x8 block of `src_value`, with its DCT prepared.
+fn create_dct_plane(src_value: u8) -> (PlaneOfBlocks<u8>, Vec<u8>) {
+    let mut plane = create_plane(1, 1);
+    let pitch = NonZeroUsize::new(8).unwrap();
+    let src = vec![src_value; 64];
+    plane.dct = Some(
+        DctHelper::new(
+            plane.blk_size_x,
+            plane.blk_size_y,
+            plane.bits_per_sample,
+            DctKind::Float,
+        )
+        .unwrap(),
+    );
+    plane
+        .dct
+        .as_mut()
+        .unwrap()
+        .bytes_2d(&src, pitch, &mut plane.dct_src, plane.dct_pitch)
+        .unwrap();
+    (plane, src)
+}
+
+#[test]
+fn dct_cost_weights_dc_difference() {
+    let (mut plane, src) = create_dct_plane(100);
+    let pitch = NonZeroUsize::new(8).unwrap();
+    let ref_ = vec![140u8; 64];
+
+    // The DCT reduces the offset of 40 to a DC difference of 20, which is weighted by
+    // 3 * blksize / 2 on top of the SAD of the coefficients
+    let dct_sad = plane.luma_sad::<1>(&src, pitch, &ref_, pitch, u64::MAX);
+    assert_eq!(dct_sad, 20 + 20 * 3 * 8 / 2);
+
+    let dct_sad = plane.luma_sad::<1>(&src, pitch, &src, pitch, u64::MAX);
+    assert_eq!(dct_sad, 0);
+}
+
+#[test]
+fn mixed_spatial_dct_interpolates_by_weight() {
+    let (mut plane, src) = create_dct_plane(100);
+    let pitch = NonZeroUsize::new(8).unwrap();
+    let ref_ = vec![140u8; 64];
+    let sad = 40 * 64;
+    let dct_sad = 260;
+
+    for weight in [0, 4, 8, 16] {
+        plane.dct_weight_16 = weight;
+        assert_eq!(
+            plane.luma_sad::<2>(&src, pitch, &ref_, pitch, u64::MAX),
+            (sad * (16 - weight as u64) + dct_sad * weight as u64) / 16,
+            "weight {weight}"
+        );
+    }
+    plane.dct_weight_16 = 0;
+    assert_eq!(
+        plane.luma_sad::<2>(&src, pitch, &ref_, pitch, u64::MAX),
+        sad
+    );
+    plane.dct_weight_16 = 16;
+    assert_eq!(
+        plane.luma_sad::<2>(&src, pitch, &ref_, pitch, u64::MAX),
+        dct_sad
+    );
+}
+
+#[test]
+fn predictors_are_mirrored_when_scanning_right_to_left() {
+    let (blk_x, blk_y) = (5, 4);
+    let mut forward = create_plane(blk_x, blk_y);
+    let mut mirrored = create_plane(blk_x, blk_y);
+    for plane in [&mut forward, &mut mirrored] {
+        plane.bounds = MvBounds {
+            dx_min: -1000,
+            dy_min: -1000,
+            dx_max: 1000,
+            dy_max: 1000,
+        };
+    }
+    // Give every block a distinct vector, and store them mirrored horizontally in `mirrored`
+    let mut forward_vectors = vec![MotionVector::zero(); blk_x * blk_y];
+    let mut mirrored_vectors = forward_vectors.clone();
+    for y in 0..blk_y {
+        for x in 0..blk_x {
+            let i = (y * blk_x + x) as i32;
+            let v = MotionVector {
+                x: i,
+                y: -3 * i,
+                sad: i64::from(i) * 10,
+            };
+            forward_vectors[y * blk_x + x] = v;
+            mirrored_vectors[y * blk_x + blk_x - 1 - x] = v;
+        }
+    }
+
+    for y in 0..blk_y {
+        for x in 0..blk_x {
+            forward.blk_scan_dir = 1;
+            forward.blk_y_i = y;
+            forward.blk_x_i = x;
+            forward.blk_idx = y * blk_x + x;
+            forward.fetch_predictors(&forward_vectors);
+
+            mirrored.blk_scan_dir = -1;
+            mirrored.blk_y_i = y;
+            mirrored.blk_x_i = blk_x - 1 - x;
+            mirrored.blk_idx = y * blk_x + blk_x - 1 - x;
+            mirrored.fetch_predictors(&mirrored_vectors);
+
+            for i in 0..4 {
+                let (a, b) = (forward.predictors[i], mirrored.predictors[i]);
+                assert_eq!(
+                    (a.x, a.y, a.sad),
+                    (b.x, b.y, b.sad),
+                    "predictor {i} of block ({x}, {y})"
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn over_range_vector_is_an_error() {
+    // 32x32 plane with 8 pixels of padding on each side
+    let mvplane = MVPlane::new(
+        NonZeroUsize::new(32).unwrap(),
+        NonZeroUsize::new(32).unwrap(),
+        Subpel::Full,
+        8,
+        8,
+        NonZeroU8::new(8).unwrap(),
+        0,
+        NonZeroUsize::new(48).unwrap(),
+    )
+    .unwrap();
+    let data = vec![0u8; 48 * 48];
+    let size = NonZeroUsize::new(48).unwrap();
+    let plane = PlaneView::new(&data, mvplane.pitch, size, size).unwrap();
+
+    // Block positions include the padding, so the bottom-right block is at (32, 32),
+    // and moving it 8 pixels down and right reaches the edge of the padding
+    let offset = mvplane.get_absolute_offset_pel1(32 + 8, 32 + 8);
+    let block = plane.block(offset, 8, 8).unwrap();
+    assert_eq!(block.len(), data.len() - offset);
+
+    // One more pixel down or right does not
+    for (vx, vy) in [(9, 8), (8, 9), (100, 100)] {
+        let offset = mvplane.get_absolute_offset_pel1(32 + vx, 32 + vy);
+        let error = plane.block(offset, 8, 8).unwrap_err();
+        assert!(error.to_string().contains("does not fit"), "{error}");
+    }
+
+    // Vectors pointing before the start of the plane wrap around and are rejected too
+    let offset = mvplane.get_absolute_offset_pel1(-1, -1);
+    let error = plane.block(offset, 8, 8).unwrap_err();
+    assert!(error.to_string().contains("at offset -"), "{error}");
+}
+
+#[test]
+fn wavefront_only_depends_on_earlier_waves() {
+    let (blk_x, blk_y) = (7usize, 5usize);
+    let waves = wavefront(
+        NonZeroUsize::new(blk_x).unwrap(),
+        NonZeroUsize::new(blk_y).unwrap(),
+    );
+
+    let mut wave_of = vec![None; blk_x * blk_y];
+    for (i, wave) in waves.iter().enumerate() {
+        assert!(!wave.is_empty());
+        for &(x, y) in wave {
+            assert!(wave_of[y * blk_x + x].replace(i).is_none(), "({x}, {y})");
+        }
+    }
+    let wave_of: Vec<usize> = wave_of.into_iter().map(Option::unwrap).collect();
+    let wave = |x: usize, y: usize| wave_of[y * blk_x + x];
+
+    for y in 0..blk_y {
+        for x in 0..blk_x {
+            // Left, up and up-right predictors must already be searched
+            if x > 0 {
+                assert!(wave(x - 1, y) < wave(x, y));
+            }
+            if y > 0 {
+                assert!(wave(x, y - 1) < wave(x, y));
+                if x + 1 < blk_x {
+                    assert!(wave(x + 1, y - 1) < wave(x, y));
+                }
+            }
+            // The coarse prediction of the block below right must not be overwritten yet
+            if y + 1 < blk_y && x + 1 < blk_x {
+                assert!(wave(x + 1, y + 1) > wave(x, y));
+            }
+        }
+    }
+}
+
+#[test]
+fn wavefront_single_row_is_serial() {
+    let waves = wavefront(NonZeroUsize::new(4).unwrap(), NonZeroUsize::new(1).unwrap());
+    assert_eq!(waves, vec![vec![(0, 0)], vec![(1, 0)], vec![(2, 0)], vec![
+        (3, 0)
+    ]]);
+}
+
+/// Searches a 32x32 GRAY8 `src` frame against `reference` with 8x8 blocks and 8 pixels of padding,
+/// returning the vectors and how many candidate vectors were checked.
+fn search_frames(
+    src: &Frame,
+    reference: &Frame,
+    search_type: SearchType,
+    search_param: i32,
+    trivial_sad: u64,
+    global_mv: MotionVector,
+    try_many: bool,
+) -> (Vec<MotionVector>, usize) {
+    let pitch = NonZeroUsize::new(src.stride(0)).unwrap();
+    let gof = MVGroupOfFrames::new(
+        1,
+        NonZeroUsize::new(32).unwrap(),
+        NonZeroUsize::new(32).unwrap(),
+        Subpel::Full,
+        8,
+        8,
+        MVPlaneSet::YPLANE,
+        NonZeroU8::new(1).unwrap(),
+        NonZeroU8::new(1).unwrap(),
+        NonZeroU8::new(8).unwrap(),
+        &[pitch; 3],
+        src.format(),
+    )
+    .unwrap();
+
+    let mut plane = create_plane(4, 4);
+    let mut out = MvsOutput {
+        validity: true,
+        block_data: vec![0; plane.get_array_size(DivideMode::None).get()].into_boxed_slice(),
+    };
+    plane
+        .search_mvs(
+            0,
+            &gof.frames[0],
+            src,
+            &gof.frames[0],
+            reference,
+            search_type,
+            search_param,
+            0,
+            0,
+            0,
+            PenaltyScaling::None,
+            &mut out,
+            &mut { global_mv },
+            0,
+            DctMode::Spatial,
+            &mut 0,
+            0,
+            0,
+            0,
+            10_000,
+            trivial_sad,
+            0,
+            false,
+            try_many,
+            &DctPool::default(),
+            None,
+        )
+        .unwrap();
+    (plane.vectors.clone(), plane.check_mv_count)
+}
+
+/// Searches a static 32x32 GRAY8 frame against itself with 8x8 blocks,
+/// returning the vectors and how many candidate vectors were checked.
+fn search_static(trivial_sad: u64) -> (Vec<MotionVector>, usize) {
+    let env = Environment::from_script(
+        r#"
+import vapoursynth as vs
+core = vs.core
+core.std.BlankClip(width=48, height=48, format=vs.GRAY8, color=[128], length=1).set_output()
+"#,
+    )
+    .unwrap();
+    let (node, _) = env.get_output(0).unwrap();
+    let frame = node.get_frame(0).unwrap();
+    search_frames(
+        &frame,
+        &frame,
+        SearchType::Hex2,
+        2,
+        trivial_sad,
+        MotionVector::zero(),
+        false,
+    )
+}
+
+#[test]
+fn static_block_skips_search_below_trivial_sad() {
+    let (full_vectors, full_checks) = search_static(0);
+    let (trivial_vectors, trivial_checks) = search_static(1);
+
+    for mv in full_vectors.iter().chain(&trivial_vectors) {
+        assert_eq!((mv.x, mv.y, mv.sad), (0, 0, 0));
+    }
+    // Every block keeps its zero vector without checking any other candidate
+    assert!(full_checks > 0);
+    assert_eq!(trivial_checks, 0);
+}
+
+#[test]
+fn equal_cost_vectors_are_broken_by_length_then_x_then_y() {
+    assert!(tie_break_key(0, 0) < tie_break_key(0, -1));
+    assert!(tie_break_key(-1, 0) < tie_break_key(0, -1));
+    assert!(tie_break_key(0, -1) < tie_break_key(0, 1));
+    assert!(tie_break_key(1, 1) < tie_break_key(-2, 0));
+
+    let mut plane = create_plane(4, 4);
+    plane.min_cost = 10;
+    plane.min_cost_mv = (0, 1);
+    assert!(plane.beats_min_cost(9, 5, 5));
+    assert!(plane.beats_min_cost(10, -1, 0));
+    assert!(!plane.beats_min_cost(10, 0, 1));
+    assert!(!plane.beats_min_cost(10, 1, 0));
+    assert!(!plane.beats_min_cost(11, 0, 0));
+}
+
+#[test]
+fn flat_region_picks_tie_break_winner() {
+    // A flat frame searched against one with a single bright pixel at the bottom right
+    // of block (1, 1): any vector moving that block left or up avoids it
+    let env = Environment::from_script(
+        r#"
+import vapoursynth as vs
+core = vs.core
+flat = core.std.BlankClip(width=48, height=48, format=vs.GRAY8, color=[128], length=1)
+dot = core.std.BlankClip(width=1, height=1, format=vs.GRAY8, color=[255], length=1)
+row = core.std.StackHorizontal([flat.std.Crop(right=25), dot, flat.std.Crop(left=24)])
+ref = core.std.StackVertical([flat.std.Crop(bottom=25), row.std.Crop(bottom=47), flat.std.Crop(top=24)])
+flat.set_output(0)
+ref.set_output(1)
+"#,
+    )
+    .unwrap();
+    let (src, _) = env.get_output(0).unwrap();
+    let (reference, _) = env.get_output(1).unwrap();
+    let (vectors, _) = search_frames(
+        &src.get_frame(0).unwrap(),
+        &reference.get_frame(0).unwrap(),
+        SearchType::Exhaustive,
+        2,
+        0,
+        MotionVector::zero(),
+        false,
+    );
+
+    // (0, -1) is checked first, but (-1, 0) is as short and has the smaller x
+    let mv = vectors[4 + 1];
+    assert_eq!((mv.x, mv.y, mv.sad), (-1, 0, 0));
+    for (i, mv) in vectors.iter().enumerate().filter(|&(i, _)| i != 5) {
+        assert_eq!((mv.x, mv.y, mv.sad), (0, 0, 0), "block {i}");
+    }
+}
+
+#[test]
+fn try_many_selects_cheapest_candidate() {
+    let mut plane = create_plane(4, 4);
+    let candidate = |x, y| MotionVector { x, y, sad: 0 };
+    // The refined zero vector, a rejected predictor, and two equally cheap ones
+    let best_mvs = [
+        candidate(0, 0),
+        candidate(5, 5),
+        candidate(0, 2),
+        candidate(-2, 0),
+    ];
+    let min_costs = [40, i64::MAX, 25, 25];
+
+    // A cost left lower by another candidate must not leak into the selection
+    plane.min_cost = 1;
+    plane.select_best_candidate(&best_mvs, &min_costs);
+    assert_eq!((plane.best_mv.x, plane.best_mv.y), (-2, 0));
+    assert_eq!(plane.min_cost, 25);
+    assert_eq!(plane.min_cost_mv, (-2, 0));
+
+    plane.select_best_candidate(&best_mvs[..2], &min_costs[..2]);
+    assert_eq!((plane.best_mv.x, plane.best_mv.y), (0, 0));
+    assert_eq!(plane.min_cost, 40);
+}
+
+#[test]
+fn try_many_finds_best_of_several_predictors() {
+    // A texture of pseudo-random 4x4 cells has many local minima around the zero vector,
+    // while the reference only matches exactly when shifted by the global motion
+    let env = Environment::from_script(
+        r#"
+import vapoursynth as vs
+core = vs.core
+def cell(i):
+    return core.std.BlankClip(width=4, height=4, format=vs.GRAY8, color=[(i * 97 + 31) % 251], length=1)
+rows = [core.std.StackHorizontal([cell(y * 16 + x) for x in range(16)]) for y in range(16)]
+texture = core.std.StackVertical(rows)
+texture.std.Crop(left=16, top=16, right=16, bottom=16).set_output(0)
+texture.std.Crop(left=13, top=18, right=19, bottom=14).set_output(1)
+"#,
+    )
+    .unwrap();
+    let (src, _) = env.get_output(0).unwrap();
+    let (reference, _) = env.get_output(1).unwrap();
+    let global_mv = MotionVector {
+        x: 3,
+        y: -2,
+        sad: 0,
+    };
+    let (vectors, _) = search_frames(
+        &src.get_frame(0).unwrap(),
+        &reference.get_frame(0).unwrap(),
+        SearchType::Onetime,
+        1,
+        0,
+        global_mv,
+        true,
+    );
+
+    // Blocks on the top and right edges would be matched against the padding
+    for y in 1..4 {
+        for x in 0..3 {
+            let mv = vectors[y * 4 + x];
+            assert_eq!((mv.x, mv.y, mv.sad), (3, -2, 0), "block ({x}, {y})");
+        }
+    }
+}
+
+#[test]
+fn corner_blocks_have_asymmetric_search_bounds() {
+    // 32x32 frame with 8 pixels of padding and a pitch of 48
+    let frame = MVFrame::new(
+        NonZeroUsize::new(32).unwrap(),
+        NonZeroUsize::new(32).unwrap(),
+        Subpel::Full,
+        8,
+        8,
+        MVPlaneSet::YPLANE,
+        NonZeroU8::new(2).unwrap(),
+        NonZeroU8::new(2).unwrap(),
+        NonZeroU8::new(8).unwrap(),
+        &SmallVec::from([0, 0, 0]),
+        &[NonZeroUsize::new(48).unwrap(); 3],
+    )
+    .unwrap();
+    let mut plane = create_plane(4, 4);
+    let bounds = plane.search_bounds(&frame);
+    assert_eq!(bounds.len(), 16);
+
+    // A block may move into the padding, but not past it: at x = 0 it can move 8 pixels
+    // left and 31 pixels right, as the maximums are exclusive
+    let expected = |dx_min, dy_min, dx_max, dy_max| MvBounds {
+        dx_min,
+        dy_min,
+        dx_max,
+        dy_max,
+    };
+    assert_eq!(bounds[0], expected(-8, -8, 32, 32));
+    assert_eq!(bounds[3], expected(-32, -8, 8, 32));
+    assert_eq!(bounds[12], expected(-8, -32, 32, 8));
+    assert_eq!(bounds[15], expected(-32, -32, 8, 8));
+    // Interior blocks are bounded by the padding on every side
+    assert_eq!(bounds[5], expected(-16, -16, 24, 24));
+}
+
+#[test]
+fn block_grid_matches_hand_computed_values() {
+    // (width, height, block size, overlap, level, blocks) at 8 bits and pel 2,
+    // where each block advances by its size minus the overlap
+    let cases = [
+        // 640 / 16 = 40, 360 / 16 = 22.5
+        (640, 360, (16, 16), (0, 0), 0, (40, 22)),
+        // (640 - 4) / 12 = 53, (360 - 2) / 6 = 59.67
+        (640, 360, (16, 8), (4, 2), 0, (53, 59)),
+        // A 160x90 level: (160 - 4) / 4 = 39, (90 - 4) / 4 = 21.5
+        (640, 360, (8, 8), (4, 4), 2, (39, 21)),
+        // 33 / 8 = 4.125, 17 / 4 = 4.25
+        (33, 17, (8, 4), (0, 0), 0, (4, 4)),
+    ];
+    for (width, height, (size_x, size_y), (overlap_x, overlap_y), level, (blk_x, blk_y)) in cases {
+        let context = format!("{width}x{height} {size_x}x{size_y} overlap {overlap_x}x{overlap_y}");
+        let blk_size_x = NonZeroUsize::new(size_x).unwrap();
+        let blk_size_y = NonZeroUsize::new(size_y).unwrap();
+        let (actual_x, actual_y) = level_blocks(
+            width, height, level, blk_size_x, blk_size_y, overlap_x, overlap_y,
+        )
+        .unwrap();
+        assert_eq!(
+            (actual_x.get(), actual_y.get()),
+            (blk_x, blk_y),
+            "{context}"
+        );
+
+        let pel = if level == 0 {
+            Subpel::Half
+        } else {
+            Subpel::Full
+        };
+        let plane = PlaneOfBlocks::<u8>::new(
+            actual_x,
+            actual_y,
+            blk_size_x,
+            blk_size_y,
+            pel,
+            level,
+            MotionFlags::empty(),
+            overlap_x,
+            overlap_y,
+            NonZeroU8::new(2).unwrap(),
+            NonZeroU8::new(2).unwrap(),
+            NonZeroU8::new(8).unwrap(),
+        );
+        assert_eq!(plane.blk_count.get(), blk_x * blk_y, "{context}");
+        assert_eq!(plane.vectors.len(), blk_x * blk_y, "{context}");
+        assert_eq!(plane.scale, 1 << level, "{context}");
+        assert_eq!(plane.log_pel, u8::from(level == 0), "{context}");
+        // 8192 entries for each pel step, twice over
+        assert_eq!(
+            plane.freq_size.get(),
+            8192 * usize::from(u8::from(pel)) * 2,
+            "{context}"
+        );
+        // Every pixel of the block differing by the full 8 bit range
+        assert_eq!(plane.very_big_sad.get(), size_x * size_y * 256, "{context}");
+    }
+
+    // Planes narrower than a block hold none, including coarse levels (64 >> 4 = 4),
+    // while overlapped blocks fit once more per step: (12 - 4) / 4 = 2
+    let size = NonZeroUsize::new(8).unwrap();
+    assert!(level_blocks(7, 64, 0, size, size, 0, 0).is_none());
+    assert!(level_blocks(64, 64, 4, size, size, 4, 4).is_none());
+    assert_eq!(
+        level_blocks(12, 64, 0, size, size, 4, 0),
+        Some((NonZeroUsize::new(2).unwrap(), NonZeroUsize::new(8).unwrap()))
+    );
+}
+
+#[test]
+fn very_big_sad_and_freq_size_follow_bit_depth_and_pel() {
+    let plane = PlaneOfBlocks::<u16>::new(
+        NonZeroUsize::new(4).unwrap(),
+        NonZeroUsize::new(4).unwrap(),
+        NonZeroUsize::new(8).unwrap(),
+        NonZeroUsize::new(4).unwrap(),
+        Subpel::Quarter,
+        0,
+        MotionFlags::empty(),
+        0,
+        0,
+        NonZeroU8::new(2).unwrap(),
+        NonZeroU8::new(2).unwrap(),
+        NonZeroU8::new(10).unwrap(),
+    );
+    assert_eq!(plane.blk_count.get(), 16);
+    assert_eq!(plane.log_pel, 2);
+    assert_eq!(plane.freq_size.get(), 65536);
+    assert_eq!(plane.very_big_sad.get(), 8 * 4 * 1024);
+}
+
+#[test]
+fn largest_costs_fit_in_i64() {
+    let plane = PlaneOfBlocks::<u16>::new(
+        NonZeroUsize::new(1).unwrap(),
+        NonZeroUsize::new(1).unwrap(),
+        NonZeroUsize::new(128).unwrap(),
+        NonZeroUsize::new(128).unwrap(),
+        Subpel::Full,
+        0,
+        MotionFlags::empty(),
+        0,
+        0,
+        NonZeroU8::new(1).unwrap(),
+        NonZeroU8::new(1).unwrap(),
+        NonZeroU8::new(16).unwrap(),
+    );
+    assert_eq!(plane.very_big_sad.get(), 1 << 30);
+
+    // All three planes of a 4:4:4 block differing by the full range, with the largest penalty
+    let sad = 3 * 128 * 128 * u64::from(u16::MAX);
+    assert_eq!(
+        penalized_cost(sad, u16::MAX),
+        (sad + sad * u64::from(u16::MAX) / 256) as i64
+    );
+    assert!(penalized_cost(sad, u16::MAX) < 1 << 48);
+    assert_eq!(penalized_cost(sad, 0), sad as i64);
+    assert_eq!(penalized_cost(1000, 128), 1500);
+}
@@ -1,15 +1,57 @@
+#[cfg(test)]
+mod tests;
+
 use std::num::NonZeroUsize;
 
+use crate::{params::ReduceFilter, util::Pixel};
+
 mod average;
 mod bilinear;
 mod cubic;
 mod quadratic;
 mod triangle;
 
-pub use average::reduce_average;
+pub use average::{reduce_average, reduce_average_floor};
 pub use bilinear::reduce_bilinear;
 pub use cubic::reduce_cubic;
 pub use quadratic::reduce_quadratic;
 pub use triangle::reduce_triangle;
 
 pub type ReduceFn<T> = fn(&mut [T], &[T], NonZeroUsize, NonZeroUsize, NonZeroUsize, NonZeroUsize);
+
+/// Returns the downscaling function used for `filter`, as selected by Super's `rfilter`.
+#[must_use]
+pub fn reduce_fn<T: Pixel>(filter: ReduceFilter) -> ReduceFn<T> {
+    match filter {
+        ReduceFilter::Average => reduce_average,
+        ReduceFilter::Triangle => reduce_triangle,
+        ReduceFilter::Bilinear => reduce_bilinear,
+        ReduceFilter::Quadratic => reduce_quadratic,
+        ReduceFilter::Cubic => reduce_cubic,
+        ReduceFilter::AverageFloor => reduce_average_floor,
+    }
+}
+
+/// Checks that `dest` and `src` hold every row that the reduce function `name` touches,
+/// so that an undersized buffer is reported by name instead of deep inside its loops.
+fn debug_check_buffers<T>(
+    name: &str,
+    dest: &[T],
+    src: &[T],
+    dest_pitch: NonZeroUsize,
+    src_pitch: NonZeroUsize,
+    dest_height: NonZeroUsize,
+) {
+    let dest_len = dest_pitch.get() * dest_height.get();
+    let src_len = src_pitch.get() * dest_height.get() * 2;
+    debug_assert!(
+        dest.len() >= dest_len,
+        "{name}: dest has {} pixels, but {dest_len} are needed",
+        dest.len()
+    );
+    debug_assert!(
+        src.len() >= src_len,
+        "{name}: src has {} pixels, but {src_len} are needed",
+        src.len()
+    );
+}
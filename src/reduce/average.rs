@@ -1,5 +1,7 @@
 #[cfg(target_arch = "x86_64")]
 mod avx2;
+#[cfg(target_arch = "aarch64")]
+mod neon;
 mod rust;
 
 #[cfg(test)]
@@ -9,14 +11,16 @@ use std::num::NonZeroUsize;
 
 use cfg_if::cfg_if;
 
+use super::debug_check_buffers;
 use crate::util::Pixel;
 
 /// Downscales an image by 2x using simple averaging of 2x2 pixel blocks.
 ///
 /// This function reduces both the width and height of the source image by half
 /// by averaging each 2x2 block of pixels into a single output pixel. The averaging
-/// uses proper rounding by adding 2 before dividing by 4, ensuring accurate
-/// color representation in the downscaled result.
+/// rounds to the nearest value by adding 2 before dividing by 4, as mvtools does,
+/// so it does not darken the downscaled result. See [`reduce_average_floor`]
+/// for an average that rounds down.
 ///
 /// # Parameters
 /// - `dest`: Destination buffer to store the downscaled image
@@ -33,13 +37,42 @@ pub fn reduce_average<T: Pixel>(
     dest_width: NonZeroUsize,
     dest_height: NonZeroUsize,
 ) {
+    debug_check_buffers(
+        "reduce_average",
+        dest,
+        src,
+        dest_pitch,
+        src_pitch,
+        dest_height,
+    );
+
     cfg_if! {
         if #[cfg(all(target_arch = "x86_64", not(feature = "no_simd")))] {
             if crate::util::has_avx2() {
-                // SAFETY: We check for AVX2 first
-                unsafe {
-                    avx2::reduce_average(dest, src, dest_pitch, src_pitch, dest_width, dest_height);
-                }
+                crate::util::run_simd_plane(
+                    "reduce_average",
+                    dest,
+                    dest_pitch,
+                    dest_width,
+                    dest_height,
+                    // SAFETY: We check for AVX2 first
+                    |dest| unsafe { avx2::reduce_average(dest, src, dest_pitch, src_pitch, dest_width, dest_height) },
+                    |dest| rust::reduce_average(dest, src, dest_pitch, src_pitch, dest_width, dest_height),
+                );
+                return;
+            }
+        } else if #[cfg(all(target_arch = "aarch64", not(feature = "no_simd")))] {
+            if crate::util::has_neon() {
+                crate::util::run_simd_plane(
+                    "reduce_average",
+                    dest,
+                    dest_pitch,
+                    dest_width,
+                    dest_height,
+                    // SAFETY: We check for NEON first
+                    |dest| unsafe { neon::reduce_average(dest, src, dest_pitch, src_pitch, dest_width, dest_height) },
+                    |dest| rust::reduce_average(dest, src, dest_pitch, src_pitch, dest_width, dest_height),
+                );
                 return;
             }
         }
@@ -47,3 +80,35 @@ pub fn reduce_average<T: Pixel>(
 
     rust::reduce_average(dest, src, dest_pitch, src_pitch, dest_width, dest_height);
 }
+
+/// Downscales an image by 2x using the average of 2x2 pixel blocks, rounded down.
+///
+/// This is [`reduce_average`] without the rounding term, for matching resizers that
+/// truncate the average. It is rarely used, so it only has a scalar implementation.
+///
+/// # Parameters
+/// - `dest`: Destination buffer to store the downscaled image
+/// - `src`: Source image buffer to downscale
+/// - `dest_pitch`: Number of pixels per row in the destination buffer
+/// - `src_pitch`: Number of pixels per row in the source buffer
+/// - `dest_width`: Width of the destination image (half of source width)
+/// - `dest_height`: Height of the destination image (half of source height)
+pub fn reduce_average_floor<T: Pixel>(
+    dest: &mut [T],
+    src: &[T],
+    dest_pitch: NonZeroUsize,
+    src_pitch: NonZeroUsize,
+    dest_width: NonZeroUsize,
+    dest_height: NonZeroUsize,
+) {
+    debug_check_buffers(
+        "reduce_average_floor",
+        dest,
+        src,
+        dest_pitch,
+        src_pitch,
+        dest_height,
+    );
+
+    rust::reduce_average_floor(dest, src, dest_pitch, src_pitch, dest_width, dest_height);
+}
@@ -0,0 +1,149 @@
+#![allow(clippy::undocumented_unsafe_blocks)]
+
+use std::{arch::aarch64::*, num::NonZeroUsize};
+
+use crate::util::Pixel;
+
+#[target_feature(enable = "neon")]
+pub(super) fn reduce_average<T: Pixel>(
+    dest: &mut [T],
+    src: &[T],
+    dest_pitch: NonZeroUsize,
+    src_pitch: NonZeroUsize,
+    dest_width: NonZeroUsize,
+    dest_height: NonZeroUsize,
+) {
+    // Check the array bounds once at the start of the loop.
+    assert!(src.len() >= src_pitch.get() * dest_height.get() * 2);
+    assert!(dest.len() >= dest_pitch.get() * dest_height.get());
+
+    match size_of::<T>() {
+        1 => unsafe {
+            reduce_average_u8(
+                dest.as_mut_ptr() as *mut u8,
+                src.as_ptr() as *const u8,
+                dest_pitch,
+                src_pitch,
+                dest_width,
+                dest_height,
+            );
+        },
+        2 => unsafe {
+            reduce_average_u16(
+                dest.as_mut_ptr() as *mut u16,
+                src.as_ptr() as *const u16,
+                dest_pitch,
+                src_pitch,
+                dest_width,
+                dest_height,
+            );
+        },
+        _ => unreachable!(),
+    }
+}
+
+#[target_feature(enable = "neon")]
+unsafe fn reduce_average_u8(
+    dest: *mut u8,
+    src: *const u8,
+    dest_pitch: NonZeroUsize,
+    src_pitch: NonZeroUsize,
+    dest_width: NonZeroUsize,
+    dest_height: NonZeroUsize,
+) {
+    let dest_width = dest_width.get();
+    let dest_height = dest_height.get();
+    let dest_pitch = dest_pitch.get();
+    let src_pitch = src_pitch.get();
+
+    // Process 8 destination pixels at a time (requires 16 source pixels per row)
+    let simd_width = 8;
+
+    for y in 0..dest_height {
+        let dest_row = dest.add(y * dest_pitch);
+        let src_row1 = src.add(y * 2 * src_pitch);
+        let src_row2 = src.add((y * 2 + 1) * src_pitch);
+
+        let mut x = 0;
+
+        // Process SIMD chunks
+        while x + simd_width <= dest_width {
+            let src1 = vld1q_u8(src_row1.add(x * 2));
+            let src2 = vld1q_u8(src_row2.add(x * 2));
+
+            // Add horizontal pairs into u16: (a + b), then accumulate (c + d)
+            let block_sums = vpadalq_u8(vpaddlq_u8(src1), src2);
+
+            // (sum + 2) / 4 with rounding, narrowed back to u8
+            vst1_u8(dest_row.add(x), vrshrn_n_u16::<2>(block_sums));
+
+            x += simd_width;
+        }
+
+        // Handle remaining pixels with scalar code
+        while x < dest_width {
+            let src_x = x * 2;
+            let a = *src_row1.add(src_x) as u16;
+            let b = *src_row1.add(src_x + 1) as u16;
+            let c = *src_row2.add(src_x) as u16;
+            let d = *src_row2.add(src_x + 1) as u16;
+
+            let avg = ((a + b + c + d + 2) / 4) as u8;
+            *dest_row.add(x) = avg;
+            x += 1;
+        }
+    }
+}
+
+#[target_feature(enable = "neon")]
+unsafe fn reduce_average_u16(
+    dest: *mut u16,
+    src: *const u16,
+    dest_pitch: NonZeroUsize,
+    src_pitch: NonZeroUsize,
+    dest_width: NonZeroUsize,
+    dest_height: NonZeroUsize,
+) {
+    let dest_width = dest_width.get();
+    let dest_height = dest_height.get();
+    let dest_pitch = dest_pitch.get();
+    let src_pitch = src_pitch.get();
+
+    // Process 4 destination pixels at a time (requires 8 source pixels per row)
+    let simd_width = 4;
+
+    for y in 0..dest_height {
+        let dest_row = dest.add(y * dest_pitch);
+        let src_row1 = src.add(y * 2 * src_pitch);
+        let src_row2 = src.add((y * 2 + 1) * src_pitch);
+
+        let mut x = 0;
+
+        // Process SIMD chunks
+        while x + simd_width <= dest_width {
+            let src1 = vld1q_u16(src_row1.add(x * 2));
+            let src2 = vld1q_u16(src_row2.add(x * 2));
+
+            // Add horizontal pairs into u32 to prevent overflow
+            let block_sums = vpadalq_u16(vpaddlq_u16(src1), src2);
+
+            // (sum + 2) / 4 with rounding, narrowed back to u16
+            vst1_u16(dest_row.add(x), vrshrn_n_u32::<2>(block_sums));
+
+            x += simd_width;
+        }
+
+        // Handle remaining pixels with scalar code
+        while x < dest_width {
+            let src_x = x * 2;
+            let a = *src_row1.add(src_x) as u32;
+            let b = *src_row1.add(src_x + 1) as u32;
+            let c = *src_row2.add(src_x) as u32;
+            let d = *src_row2.add(src_x + 1) as u32;
+
+            let avg = ((a + b + c + d + 2) / 4) as u16;
+            *dest_row.add(x) = avg;
+            x += 1;
+        }
+    }
+}
@@ -9,6 +9,30 @@ pub(super) fn reduce_average<T: Pixel>(
     src_pitch: NonZeroUsize,
     dest_width: NonZeroUsize,
     dest_height: NonZeroUsize,
+) {
+    reduce_average_rounded(dest, src, dest_pitch, src_pitch, dest_width, dest_height, 2);
+}
+
+pub(super) fn reduce_average_floor<T: Pixel>(
+    dest: &mut [T],
+    src: &[T],
+    dest_pitch: NonZeroUsize,
+    src_pitch: NonZeroUsize,
+    dest_width: NonZeroUsize,
+    dest_height: NonZeroUsize,
+) {
+    reduce_average_rounded(dest, src, dest_pitch, src_pitch, dest_width, dest_height, 0);
+}
+
+/// Averages each 2x2 block, adding `rounding` to the sum before dividing by 4.
+fn reduce_average_rounded<T: Pixel>(
+    dest: &mut [T],
+    src: &[T],
+    dest_pitch: NonZeroUsize,
+    src_pitch: NonZeroUsize,
+    dest_width: NonZeroUsize,
+    dest_height: NonZeroUsize,
+    rounding: u32,
 ) {
     // For performance reasons, check the array bounds once at the start of the loop.
     assert!(src.len() >= src_pitch.get() * dest_height.get() * 2);
@@ -30,8 +54,7 @@ pub(super) fn reduce_average<T: Pixel>(
                     .to_u32()
                     .expect("fits in u32");
 
-                // Calculate average with proper rounding
-                *dest.add(x) = T::from_u32_or_max_value((a + b + c + d + 2) / 4);
+                *dest.add(x) = T::from_u32_or_max_value((a + b + c + d + rounding) / 4);
             }
             dest = dest.add(dest_pitch.get());
             src = src.add(src_pitch.get() * 2);
@@ -378,6 +378,40 @@ macro_rules! create_tests {
                 }
             }
 
+            #[test]
+            fn [<test_reduce_average_u16_max_values_ $module>]() {
+                // Values at and near 65535 must not be clamped to 255,
+                // wide enough to cover both the SIMD loop and the scalar remainder
+                let width = 40;
+                let src: Vec<u16> = (0..width * 2)
+                    .map(|i| u16::MAX - (i % 3) as u16)
+                    .collect();
+                let mut dest = vec![0u16; width / 2];
+                let src_pitch = NonZeroUsize::new(width).unwrap();
+                let dest_pitch = NonZeroUsize::new(width / 2).unwrap();
+                let dest_width = NonZeroUsize::new(width / 2).unwrap();
+                let dest_height = NonZeroUsize::new(1).unwrap();
+
+                verify_asm!($module, reduce_average(
+                    &mut dest,
+                    &src,
+                    dest_pitch,
+                    src_pitch,
+                    dest_width,
+                    dest_height,
+                ));
+
+                for (i, &pixel) in dest.iter().enumerate() {
+                    let x = i * 2;
+                    let sum = u32::from(src[x])
+                        + u32::from(src[x + 1])
+                        + u32::from(src[width + x])
+                        + u32::from(src[width + x + 1]);
+                    assert_eq!(u32::from(pixel), (sum + 2) / 4, "Mismatch at position {}", i);
+                    assert!(pixel > 65532, "Truncated at position {}", i);
+                }
+            }
+
             #[test]
             fn [<test_reduce_average_u16_large_simd_ $module>]() {
                 // Test large enough to trigger SIMD processing for u16 (16x2 -> 8x1)
@@ -429,3 +463,56 @@ create_tests!(rust);
 
 #[cfg(target_feature = "avx2")]
 create_tests!(avx2);
+
+#[cfg(all(target_arch = "aarch64", target_feature = "neon"))]
+create_tests!(neon);
+
+#[test]
+fn floor_average_truncates_where_average_rounds() {
+    // Block sums of 40, 41, 42 and 43 have fractional averages of 0, 1/4, 1/2 and 3/4
+    let src = vec![
+        10u8, 10, 10, 10, 10, 10, 10, 10, //
+        10, 10, 10, 11, 10, 12, 10, 13,
+    ];
+    let src_pitch = NonZeroUsize::new(8).unwrap();
+    let dest_pitch = NonZeroUsize::new(4).unwrap();
+    let dest_width = NonZeroUsize::new(4).unwrap();
+    let dest_height = NonZeroUsize::new(1).unwrap();
+
+    let mut floor = vec![0u8; 4];
+    super::reduce_average_floor(
+        &mut floor,
+        &src,
+        dest_pitch,
+        src_pitch,
+        dest_width,
+        dest_height,
+    );
+    assert_eq!(floor, [10, 10, 10, 10]);
+
+    let mut nearest = vec![0u8; 4];
+    super::reduce_average(
+        &mut nearest,
+        &src,
+        dest_pitch,
+        src_pitch,
+        dest_width,
+        dest_height,
+    );
+    assert_eq!(nearest, [10, 10, 11, 11]);
+}
+
+#[test]
+fn floor_average_keeps_16_bit_maximum() {
+    let src = vec![u16::MAX; 4];
+    let mut dest = vec![0u16; 1];
+    super::reduce_average_floor(
+        &mut dest,
+        &src,
+        NonZeroUsize::MIN,
+        NonZeroUsize::new(2).unwrap(),
+        NonZeroUsize::MIN,
+        NonZeroUsize::MIN,
+    );
+    assert_eq!(dest, [u16::MAX]);
+}
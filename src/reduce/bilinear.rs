@@ -9,6 +9,7 @@ use std::num::NonZeroUsize;
 
 use cfg_if::cfg_if;
 
+use super::debug_check_buffers;
 use crate::util::Pixel;
 
 /// Downscales an image by 2x using bilinear interpolation.
@@ -34,13 +35,28 @@ pub fn reduce_bilinear<T: Pixel>(
     dest_width: NonZeroUsize,
     dest_height: NonZeroUsize,
 ) {
+    debug_check_buffers(
+        "reduce_bilinear",
+        dest,
+        src,
+        dest_pitch,
+        src_pitch,
+        dest_height,
+    );
+
     cfg_if! {
         if #[cfg(all(target_arch = "x86_64", not(feature = "no_simd")))] {
             if crate::util::has_avx2() {
-                // SAFETY: We check for AVX2 first
-                unsafe {
-                    avx2::reduce_bilinear(dest, src, dest_pitch, src_pitch, dest_width, dest_height);
-                }
+                crate::util::run_simd_plane(
+                    "reduce_bilinear",
+                    dest,
+                    dest_pitch,
+                    dest_width,
+                    dest_height,
+                    // SAFETY: We check for AVX2 first
+                    |dest| unsafe { avx2::reduce_bilinear(dest, src, dest_pitch, src_pitch, dest_width, dest_height) },
+                    |dest| rust::reduce_bilinear(dest, src, dest_pitch, src_pitch, dest_width, dest_height),
+                );
                 return;
             }
         }
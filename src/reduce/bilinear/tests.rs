@@ -4,6 +4,16 @@
 use std::num::NonZeroUsize;
 
 use pastey::paste;
+use rand::{Rng, SeedableRng};
+use rand_xoshiro::Xoshiro128StarStar;
+
+use crate::util::Pixel;
+
+fn random_plane<T: Pixel>(rng: &mut Xoshiro128StarStar, len: usize, max: u32) -> Vec<T> {
+    std::iter::repeat_with(|| T::from_u32_or_max_value(rng.random_range(0..=max)))
+        .take(len)
+        .collect()
+}
 
 macro_rules! create_tests {
     ($module:ident) => {
@@ -766,6 +776,48 @@ macro_rules! create_tests {
                 };
                 assert!(diff2 < 10000, "Scalar fallback pixel 2 should have reasonable value relative to previous");
             }
+
+            #[test]
+            fn [<test_reduce_bilinear_u8_random_ $module>]() {
+                // Odd sizes exercise both the SIMD body and the scalar tails and edges
+                let mut rng = Xoshiro128StarStar::from_seed(*b"deadbeeflolcakes");
+                let src_pitch = NonZeroUsize::new(80).unwrap();
+                let dest_pitch = NonZeroUsize::new(80).unwrap();
+                let dest_width = NonZeroUsize::new(37).unwrap();
+                let dest_height = NonZeroUsize::new(12).unwrap();
+                let src: Vec<u8> = random_plane(&mut rng, src_pitch.get() * dest_height.get() * 2, 255);
+                let mut dest = vec![0u8; dest_pitch.get() * dest_height.get()];
+
+                verify_asm!($module, reduce_bilinear(
+                    &mut dest,
+                    &src,
+                    dest_pitch,
+                    src_pitch,
+                    dest_width,
+                    dest_height,
+                ));
+            }
+
+            #[test]
+            fn [<test_reduce_bilinear_u16_random_ $module>]() {
+                let mut rng = Xoshiro128StarStar::from_seed(*b"deadbeeflolcakes");
+                let src_pitch = NonZeroUsize::new(80).unwrap();
+                let dest_pitch = NonZeroUsize::new(80).unwrap();
+                let dest_width = NonZeroUsize::new(37).unwrap();
+                let dest_height = NonZeroUsize::new(12).unwrap();
+                let src: Vec<u16> =
+                    random_plane(&mut rng, src_pitch.get() * dest_height.get() * 2, u16::MAX.into());
+                let mut dest = vec![0u16; dest_pitch.get() * dest_height.get()];
+
+                verify_asm!($module, reduce_bilinear(
+                    &mut dest,
+                    &src,
+                    dest_pitch,
+                    src_pitch,
+                    dest_width,
+                    dest_height,
+                ));
+            }
         }
     };
 }
@@ -774,3 +826,44 @@ create_tests!(rust);
 
 #[cfg(target_feature = "avx2")]
 create_tests!(avx2);
+
+#[test]
+fn dispatched_bilinear_matches_scalar() {
+    // Covers whichever implementation the runtime dispatch selects,
+    // which is the scalar one on targets without AVX2
+    let mut rng = Xoshiro128StarStar::from_seed(*b"deadbeeflolcakes");
+    let src_pitch = NonZeroUsize::new(72).unwrap();
+    let dest_pitch = NonZeroUsize::new(72).unwrap();
+    let dest_width = NonZeroUsize::new(33).unwrap();
+    let dest_height = NonZeroUsize::new(9).unwrap();
+    for max in [255, 1023, u32::from(u16::MAX)] {
+        let src: Vec<u16> = random_plane(&mut rng, src_pitch.get() * dest_height.get() * 2, max);
+        let mut dispatched = vec![0u16; dest_pitch.get() * dest_height.get()];
+        let mut scalar = dispatched.clone();
+
+        super::reduce_bilinear(
+            &mut dispatched,
+            &src,
+            dest_pitch,
+            src_pitch,
+            dest_width,
+            dest_height,
+        );
+        super::rust::reduce_bilinear(
+            &mut scalar,
+            &src,
+            dest_pitch,
+            src_pitch,
+            dest_width,
+            dest_height,
+        );
+
+        for y in 0..dest_height.get() {
+            assert_eq!(
+                &dispatched[y * dest_pitch.get()..][..dest_width.get()],
+                &scalar[y * dest_pitch.get()..][..dest_width.get()],
+                "row {y} with samples up to {max}"
+            );
+        }
+    }
+}
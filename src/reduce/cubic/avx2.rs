@@ -111,8 +111,8 @@ unsafe fn reduce_cubic_vertical_u8(
         let src_row_offset = y * 2 * src_pitch;
         let mut x = 0;
 
-        while x + 16 <= dest_width {
-            // Load 6 rows of 16 u8 pixels each
+        while x + 32 <= dest_width {
+            // Load 6 rows of 32 u8 pixels each
             let m0 =
                 _mm256_loadu_si256(src.add(src_row_offset + x - src_pitch * 2) as *const __m256i);
             let m1 = _mm256_loadu_si256(src.add(src_row_offset + x - src_pitch) as *const __m256i);
@@ -157,7 +157,7 @@ unsafe fn reduce_cubic_vertical_u8(
             let result = _mm256_packus_epi16(result_lo, result_hi);
             _mm256_storeu_si256(dest_ptr.add(x) as *mut __m256i, result);
 
-            x += 16;
+            x += 32;
         }
 
         // Handle remaining pixels
@@ -279,8 +279,8 @@ unsafe fn reduce_cubic_vertical_u16(
         let a = _mm256_loadu_si256(src.add(x) as *const __m256i);
         let b = _mm256_loadu_si256(src.add(x + src_pitch) as *const __m256i);
 
-        let sum = _mm256_add_epi16(_mm256_add_epi16(a, b), _mm256_set1_epi16(1));
-        let result = _mm256_srli_epi16(sum, 1);
+        // (a + b + 1) / 2, without overflowing the 16-bit lanes
+        let result = _mm256_avg_epu16(a, b);
 
         _mm256_storeu_si256(dest_ptr.add(x) as *mut __m256i, result);
         x += 16;
@@ -371,8 +371,7 @@ unsafe fn reduce_cubic_vertical_u16(
             let a = _mm256_loadu_si256(src.add(src_row_offset + x) as *const __m256i);
             let b = _mm256_loadu_si256(src.add(src_row_offset + x + src_pitch) as *const __m256i);
 
-            let sum = _mm256_add_epi16(_mm256_add_epi16(a, b), _mm256_set1_epi16(1));
-            let result = _mm256_srli_epi16(sum, 1);
+            let result = _mm256_avg_epu16(a, b);
 
             _mm256_storeu_si256(dest_ptr.add(x) as *mut __m256i, result);
             x += 16;
@@ -4,10 +4,135 @@
 use std::num::NonZeroUsize;
 
 use pastey::paste;
+use rand::{Rng, SeedableRng};
+use rand_xoshiro::Xoshiro128StarStar;
+
+/// The cubic B-spline, which is the Mitchell-Netravali filter with `b = 1, c = 0`.
+fn b_spline(x: f64) -> f64 {
+    let x = x.abs();
+    if x < 1.0 {
+        (3.0 * x).mul_add(x * x, (6.0 * x).mul_add(-x, 4.0)) / 6.0
+    } else if x < 2.0 {
+        (2.0 - x).powi(3) / 6.0
+    } else {
+        0.0
+    }
+}
+
+/// The B-spline stretched for a 2x downscale, sampled at the six source pixels around
+/// the center of each pair and normalized to sum to 1.
+fn b_spline_taps() -> [f64; 6] {
+    let taps = [-2.5, -1.5, -0.5, 0.5, 1.5, 2.5].map(|offset| b_spline(offset / 2.0));
+    let sum: f64 = taps.iter().sum();
+    taps.map(|tap| tap / sum)
+}
+
+/// The largest difference between the exact and the quantized taps on any input,
+/// as a fraction of the input range, plus rounding of the output.
+fn quantization_bound(max_value: f64) -> f64 {
+    let kernel = [1.0, 5.0, 10.0, 10.0, 5.0, 1.0];
+    let error: f64 = b_spline_taps()
+        .iter()
+        .zip(kernel)
+        .map(|(exact, quantized)| (exact - quantized / 32.0).abs())
+        .sum();
+    // The errors sum to zero, so at most half of them can point the same way
+    (error / 2.0).mul_add(max_value, 0.5)
+}
+
+/// Downscales a row by 2x with the exact B-spline taps, averaging the first and last pairs.
+fn b_spline_reduce(row: &[u8]) -> Vec<f64> {
+    let taps = b_spline_taps();
+    let width = row.len() / 2;
+    (0..width)
+        .map(|x| {
+            if x == 0 || x == width - 1 {
+                return f64::from(u16::from(row[x * 2]) + u16::from(row[x * 2 + 1])) / 2.0;
+            }
+            row[x * 2 - 2..][..6]
+                .iter()
+                .zip(taps)
+                .map(|(&pixel, tap)| f64::from(pixel) * tap)
+                .sum()
+        })
+        .collect()
+}
+
+const REFERENCE_ROW: [u8; 16] = [
+    0, 0, 0, 0, 64, 128, 192, 255, 255, 255, 200, 100, 50, 25, 10, 0,
+];
+/// `REFERENCE_ROW` reduced with the `[1, 5, 10, 10, 5, 1] / 32` kernel
+const REFERENCE_OUTPUT: [u8; 8] = [0, 14, 98, 210, 240, 150, 47, 5];
+
+#[test]
+fn cubic_taps_are_quantized_b_spline() {
+    // The exact taps are about [1.13, 5.05, 9.82, 9.82, 5.05, 1.13] / 32
+    let quantized = b_spline_taps().map(|tap| (tap * 32.0).round() as u32);
+    assert_eq!(quantized, [1, 5, 10, 10, 5, 1]);
+}
+
+#[test]
+fn reference_output_is_within_quantization_of_b_spline() {
+    let bound = quantization_bound(255.0);
+    assert!(bound < 3.5, "bound {bound}");
+    for (x, (&output, exact)) in REFERENCE_OUTPUT
+        .iter()
+        .zip(b_spline_reduce(&REFERENCE_ROW))
+        .enumerate()
+    {
+        assert!(
+            (f64::from(output) - exact).abs() <= bound,
+            "pixel {x}: {output} vs {exact}"
+        );
+    }
+}
 
 macro_rules! create_tests {
     ($module:ident) => {
         paste! {
+            #[test]
+            fn [<test_reduce_cubic_matches_reference_row_ $module>]() {
+                // Two identical rows, so that the vertical pass leaves them unchanged
+                let src = [REFERENCE_ROW, REFERENCE_ROW].concat();
+                let mut dest = vec![0u8; 16];
+                let pitch = NonZeroUsize::new(16).unwrap();
+                let dest_width = NonZeroUsize::new(8).unwrap();
+                let dest_height = NonZeroUsize::new(1).unwrap();
+
+                verify_asm!($module, reduce_cubic(
+                    &mut dest,
+                    &src,
+                    pitch,
+                    pitch,
+                    dest_width,
+                    dest_height,
+                ));
+
+                assert_eq!(dest[..8], REFERENCE_OUTPUT);
+            }
+
+            #[test]
+            fn [<test_reduce_cubic_matches_reference_column_ $module>]() {
+                // Two identical columns, so that the horizontal pass leaves them unchanged
+                let src: Vec<u8> = REFERENCE_ROW.iter().flat_map(|&pixel| [pixel, pixel]).collect();
+                let mut dest = vec![0u8; 16];
+                let pitch = NonZeroUsize::new(2).unwrap();
+                let dest_width = NonZeroUsize::new(1).unwrap();
+                let dest_height = NonZeroUsize::new(8).unwrap();
+
+                verify_asm!($module, reduce_cubic(
+                    &mut dest,
+                    &src,
+                    pitch,
+                    pitch,
+                    dest_width,
+                    dest_height,
+                ));
+
+                let column: Vec<u8> = dest.chunks_exact(2).map(|row| row[0]).collect();
+                assert_eq!(column, REFERENCE_OUTPUT);
+            }
+
             #[test]
             fn [<test_reduce_cubic_u8_2x2_ $module>]() {
                 // Test basic 2x2 -> 1x1 reduction
@@ -773,6 +898,58 @@ macro_rules! create_tests {
                        "Transition from SIMD to scalar should be reasonable: {} -> {} (diff: {})",
                        simd_last, scalar_first, diff);
             }
+
+            #[test]
+            fn [<test_reduce_cubic_u16_random_upper_range_ $module>]() {
+                // Values near the top of the 16-bit range overflow 16-bit intermediates
+                // in both the edge line averaging and the 6-tap filter
+                let mut rng = Xoshiro128StarStar::from_seed(*b"deadbeeflolcakes");
+                let src_pitch = NonZeroUsize::new(80).unwrap();
+                let dest_pitch = NonZeroUsize::new(80).unwrap();
+                let dest_width = NonZeroUsize::new(37).unwrap();
+                let dest_height = NonZeroUsize::new(12).unwrap();
+                let src: Vec<u16> = (0..src_pitch.get() * dest_height.get() * 2)
+                    .map(|_| rng.random_range(60000..=u16::MAX))
+                    .collect();
+                let mut dest = vec![0u16; dest_pitch.get() * dest_height.get()];
+
+                verify_asm!($module, reduce_cubic(
+                    &mut dest,
+                    &src,
+                    dest_pitch,
+                    src_pitch,
+                    dest_width,
+                    dest_height,
+                ));
+
+                for y in 0..dest_height.get() {
+                    for &pixel in &dest[y * dest_pitch.get()..][..dest_width.get()] {
+                        assert!(pixel >= 60000, "row {y} wrapped around to {pixel}");
+                    }
+                }
+            }
+
+            #[test]
+            fn [<test_reduce_cubic_u8_random_ $module>]() {
+                let mut rng = Xoshiro128StarStar::from_seed(*b"deadbeeflolcakes");
+                let src_pitch = NonZeroUsize::new(80).unwrap();
+                let dest_pitch = NonZeroUsize::new(80).unwrap();
+                let dest_width = NonZeroUsize::new(37).unwrap();
+                let dest_height = NonZeroUsize::new(12).unwrap();
+                let src: Vec<u8> = (0..src_pitch.get() * dest_height.get() * 2)
+                    .map(|_| rng.random())
+                    .collect();
+                let mut dest = vec![0u8; dest_pitch.get() * dest_height.get()];
+
+                verify_asm!($module, reduce_cubic(
+                    &mut dest,
+                    &src,
+                    dest_pitch,
+                    src_pitch,
+                    dest_width,
+                    dest_height,
+                ));
+            }
         }
     };
 }
@@ -0,0 +1,156 @@
+#![allow(clippy::unwrap_used, reason = "allow in test files")]
+#![allow(clippy::undocumented_unsafe_blocks, reason = "allow in test files")]
+
+use rand::{Rng, SeedableRng};
+use rand_xoshiro::Xoshiro128StarStar;
+
+use super::*;
+
+const SRC_WIDTH: usize = 32;
+const SRC_HEIGHT: usize = 16;
+
+/// Reduces a `SRC_WIDTH`x`SRC_HEIGHT` plane, returning only the visible destination pixels.
+///
+/// The vertical pass writes full source-width rows, so the destination pitch matches the source.
+fn reduce_with(reduce: ReduceFn<u8>, src: &[u8]) -> Vec<u8> {
+    let mut dest = vec![0u8; SRC_WIDTH * SRC_HEIGHT / 2];
+    reduce(
+        &mut dest,
+        src,
+        NonZeroUsize::new(SRC_WIDTH).unwrap(),
+        NonZeroUsize::new(SRC_WIDTH).unwrap(),
+        NonZeroUsize::new(SRC_WIDTH / 2).unwrap(),
+        NonZeroUsize::new(SRC_HEIGHT / 2).unwrap(),
+    );
+    dest.chunks(SRC_WIDTH)
+        .flat_map(|row| row[..SRC_WIDTH / 2].to_vec())
+        .collect()
+}
+
+fn noise(seed: u64) -> Vec<u8> {
+    let mut rng = Xoshiro128StarStar::seed_from_u64(seed);
+    std::iter::repeat_with(|| rng.random())
+        .take(SRC_WIDTH * SRC_HEIGHT)
+        .collect()
+}
+
+fn variance(pixels: &[u8]) -> f64 {
+    let mean = pixels.iter().map(|&p| f64::from(p)).sum::<f64>() / pixels.len() as f64;
+    pixels
+        .iter()
+        .map(|&p| (f64::from(p) - mean).powi(2))
+        .sum::<f64>()
+        / pixels.len() as f64
+}
+
+#[test]
+fn rfilter_values_map_to_filters() {
+    let expected = [
+        ReduceFilter::Average,
+        ReduceFilter::Triangle,
+        ReduceFilter::Bilinear,
+        ReduceFilter::Quadratic,
+        ReduceFilter::Cubic,
+        ReduceFilter::AverageFloor,
+    ];
+    for (value, filter) in expected.into_iter().enumerate() {
+        assert_eq!(ReduceFilter::try_from(value as i64).unwrap(), filter);
+    }
+    assert!(ReduceFilter::try_from(-1).is_err());
+    assert!(ReduceFilter::try_from(6).is_err());
+}
+
+#[test]
+fn reduce_fn_selects_filter() {
+    let src = noise(42);
+    let direct: [ReduceFn<u8>; 6] = [
+        reduce_average,
+        reduce_triangle,
+        reduce_bilinear,
+        reduce_quadratic,
+        reduce_cubic,
+        reduce_average_floor,
+    ];
+
+    let mut outputs = Vec::new();
+    for (value, reduce) in direct.into_iter().enumerate() {
+        let filter = ReduceFilter::try_from(value as i64).unwrap();
+        let expected = reduce_with(reduce, &src);
+        let output = reduce_with(reduce_fn(filter), &src);
+        assert_eq!(output, expected, "{filter:?}");
+        outputs.push(output);
+    }
+
+    // Every filter produces a different result on noise, so none of them alias another
+    for i in 0..outputs.len() {
+        for j in i + 1..outputs.len() {
+            assert_ne!(outputs[i], outputs[j], "rfilter {i} and {j}");
+        }
+    }
+}
+
+#[test]
+fn cubic_is_smoother_than_average() {
+    let src = noise(7);
+
+    let average = variance(&reduce_with(reduce_fn(ReduceFilter::Average), &src));
+    let cubic = variance(&reduce_with(reduce_fn(ReduceFilter::Cubic), &src));
+    assert!(cubic < average, "cubic {cubic} average {average}");
+}
+
+#[test]
+fn every_filter_preserves_flat_planes() {
+    let src = vec![77u8; SRC_WIDTH * SRC_HEIGHT];
+    for value in 0..5 {
+        let filter = ReduceFilter::try_from(value).unwrap();
+        let output = reduce_with(reduce_fn(filter), &src);
+        assert!(output.iter().all(|&p| p == 77), "{filter:?}: {output:?}");
+    }
+}
+
+#[test]
+fn average_reduces_each_2x2_block_to_its_mean() {
+    // Every 2x2 block holds 10, 20, 30 and 41, whose rounded mean is 25
+    let src: Vec<u8> = (0..SRC_WIDTH * SRC_HEIGHT)
+        .map(|i| match ((i / SRC_WIDTH) % 2, i % 2) {
+            (0, 0) => 10,
+            (0, _) => 20,
+            (_, 0) => 30,
+            _ => 41,
+        })
+        .collect();
+    let output = reduce_with(reduce_fn(ReduceFilter::Average), &src);
+    assert!(output.iter().all(|&p| p == 25), "{output:?}");
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(expected = "reduce_cubic: dest has 255 pixels, but 256 are needed")]
+fn undersized_reduce_dest_is_reported_by_name() {
+    let src = noise(1);
+    let mut dest = vec![0u8; SRC_WIDTH * SRC_HEIGHT / 2 - 1];
+    reduce_cubic(
+        &mut dest,
+        &src,
+        NonZeroUsize::new(SRC_WIDTH).unwrap(),
+        NonZeroUsize::new(SRC_WIDTH).unwrap(),
+        NonZeroUsize::new(SRC_WIDTH / 2).unwrap(),
+        NonZeroUsize::new(SRC_HEIGHT / 2).unwrap(),
+    );
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(expected = "reduce_average: src has 511 pixels, but 512 are needed")]
+fn undersized_reduce_src_is_reported_by_name() {
+    let src = noise(1);
+    let mut dest = vec![0u8; SRC_WIDTH * SRC_HEIGHT / 2];
+    reduce_average(
+        &mut dest,
+        &src[1..],
+        NonZeroUsize::new(SRC_WIDTH).unwrap(),
+        NonZeroUsize::new(SRC_WIDTH).unwrap(),
+        NonZeroUsize::new(SRC_WIDTH / 2).unwrap(),
+        NonZeroUsize::new(SRC_HEIGHT / 2).unwrap(),
+    );
+}
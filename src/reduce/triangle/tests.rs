@@ -4,6 +4,50 @@
 use std::num::NonZeroUsize;
 
 use pastey::paste;
+use rand::{Rng, SeedableRng};
+use rand_xoshiro::Xoshiro128StarStar;
+
+use crate::util::Pixel;
+
+/// Straightforward reference for the shifted triangle filter.
+///
+/// Output pixel `i` is centered on source pixel `2 * i` with weights (1/4, 1/2, 1/4),
+/// except the first row and column, which have no previous neighbor
+/// and average source pixels 0 and 1 instead.
+/// Rows are filtered first and rounded before the columns are filtered.
+fn naive_reduce_triangle<T: Pixel>(
+    src: &[T],
+    src_pitch: usize,
+    dest_width: usize,
+    dest_height: usize,
+) -> Vec<T> {
+    let px = |x: usize, y: usize| src[y * src_pitch + x].to_u32().unwrap();
+    let tap = |i: usize, sample: &dyn Fn(usize) -> u32| {
+        if i == 0 {
+            (sample(0) + sample(1)).div_ceil(2)
+        } else {
+            (sample(2 * i - 1) + sample(2 * i) * 2 + sample(2 * i + 1) + 2) / 4
+        }
+    };
+
+    let vertical: Vec<Vec<u32>> = (0..dest_height)
+        .map(|y| {
+            (0..dest_width * 2)
+                .map(|x| tap(y, &|sy| px(x, sy)))
+                .collect()
+        })
+        .collect();
+    vertical
+        .iter()
+        .flat_map(|row| (0..dest_width).map(|x| T::from_u32_or_max_value(tap(x, &|sx| row[sx]))))
+        .collect()
+}
+
+fn random_plane<T: Pixel>(rng: &mut Xoshiro128StarStar, len: usize, max: u32) -> Vec<T> {
+    std::iter::repeat_with(|| T::from_u32_or_max_value(rng.random_range(0..=max)))
+        .take(len)
+        .collect()
+}
 
 macro_rules! create_tests {
     ($module:ident) => {
@@ -725,6 +769,57 @@ macro_rules! create_tests {
                        "Both SIMD iterations should produce reasonable results: {} vs {}",
                        first_simd_sample, second_simd_sample);
             }
+
+            #[test]
+            fn [<test_reduce_triangle_u8_random_ $module>]() {
+                let mut rng = Xoshiro128StarStar::from_seed(*b"deadbeeflolcakes");
+                let src_pitch = NonZeroUsize::new(80).unwrap();
+                let dest_pitch = NonZeroUsize::new(80).unwrap();
+                let dest_width = NonZeroUsize::new(37).unwrap();
+                let dest_height = NonZeroUsize::new(12).unwrap();
+                let src: Vec<u8> = random_plane(&mut rng, src_pitch.get() * dest_height.get() * 2, 255);
+                let mut dest = vec![0u8; dest_pitch.get() * dest_height.get()];
+
+                verify_asm!($module, reduce_triangle(
+                    &mut dest,
+                    &src,
+                    dest_pitch,
+                    src_pitch,
+                    dest_width,
+                    dest_height,
+                ));
+
+                let expected = naive_reduce_triangle(&src, src_pitch.get(), dest_width.get(), dest_height.get());
+                for (y, row) in expected.chunks_exact(dest_width.get()).enumerate() {
+                    assert_eq!(&dest[y * dest_pitch.get()..][..dest_width.get()], row, "row {y}");
+                }
+            }
+
+            #[test]
+            fn [<test_reduce_triangle_u16_random_ $module>]() {
+                let mut rng = Xoshiro128StarStar::from_seed(*b"deadbeeflolcakes");
+                let src_pitch = NonZeroUsize::new(80).unwrap();
+                let dest_pitch = NonZeroUsize::new(80).unwrap();
+                let dest_width = NonZeroUsize::new(37).unwrap();
+                let dest_height = NonZeroUsize::new(12).unwrap();
+                let src: Vec<u16> =
+                    random_plane(&mut rng, src_pitch.get() * dest_height.get() * 2, u16::MAX.into());
+                let mut dest = vec![0u16; dest_pitch.get() * dest_height.get()];
+
+                verify_asm!($module, reduce_triangle(
+                    &mut dest,
+                    &src,
+                    dest_pitch,
+                    src_pitch,
+                    dest_width,
+                    dest_height,
+                ));
+
+                let expected = naive_reduce_triangle(&src, src_pitch.get(), dest_width.get(), dest_height.get());
+                for (y, row) in expected.chunks_exact(dest_width.get()).enumerate() {
+                    assert_eq!(&dest[y * dest_pitch.get()..][..dest_width.get()], row, "row {y}");
+                }
+            }
         }
     };
 }
@@ -733,3 +828,77 @@ create_tests!(rust);
 
 #[cfg(target_feature = "avx2")]
 create_tests!(avx2);
+
+#[test]
+fn scalar_triangle_shifts_edges_onto_first_pixels() {
+    // Every output pixel past the first is centered on an even source pixel
+    // and only sees its direct neighbors, so a bright first source row and column
+    // only reach the first output row and column
+    let src_pitch = NonZeroUsize::new(8).unwrap();
+    let dest_pitch = NonZeroUsize::new(8).unwrap();
+    let dest_width = NonZeroUsize::new(4).unwrap();
+    let dest_height = NonZeroUsize::new(3).unwrap();
+    let src: Vec<u8> = (0..6)
+        .flat_map(|y| (0..8).map(move |x| if x == 0 || y == 0 { 200 } else { 0 }))
+        .collect();
+    let mut dest = vec![0u8; dest_pitch.get() * dest_height.get()];
+
+    super::rust::reduce_triangle(
+        &mut dest,
+        &src,
+        dest_pitch,
+        src_pitch,
+        dest_width,
+        dest_height,
+    );
+
+    // Vertically, the first row averages source rows 0 and 1 while row 1 uses rows 1..=3.
+    // Horizontally, the same happens with the columns.
+    assert_eq!(&dest[..4], &[150, 100, 100, 100]);
+    assert_eq!(&dest[8..12], &[100, 0, 0, 0]);
+    assert_eq!(&dest[16..20], &[100, 0, 0, 0]);
+    let expected = naive_reduce_triangle(&src, 8, 4, 3);
+    for (y, row) in expected.chunks_exact(4).enumerate() {
+        assert_eq!(&dest[y * 8..][..4], row, "row {y}");
+    }
+}
+
+#[test]
+fn dispatched_triangle_matches_scalar() {
+    // Covers whichever implementation the runtime dispatch selects,
+    // which is the scalar one on targets without AVX2
+    let mut rng = Xoshiro128StarStar::from_seed(*b"deadbeeflolcakes");
+    let src_pitch = NonZeroUsize::new(72).unwrap();
+    let dest_pitch = NonZeroUsize::new(72).unwrap();
+    let dest_width = NonZeroUsize::new(33).unwrap();
+    let dest_height = NonZeroUsize::new(9).unwrap();
+    let src: Vec<u16> = random_plane(&mut rng, src_pitch.get() * dest_height.get() * 2, 1023);
+    let mut dispatched = vec![0u16; dest_pitch.get() * dest_height.get()];
+    let mut scalar = dispatched.clone();
+
+    super::reduce_triangle(
+        &mut dispatched,
+        &src,
+        dest_pitch,
+        src_pitch,
+        dest_width,
+        dest_height,
+    );
+    super::rust::reduce_triangle(
+        &mut scalar,
+        &src,
+        dest_pitch,
+        src_pitch,
+        dest_width,
+        dest_height,
+    );
+
+    for y in 0..dest_height.get() {
+        let row = &dispatched[y * dest_pitch.get()..][..dest_width.get()];
+        assert_eq!(
+            row,
+            &scalar[y * dest_pitch.get()..][..dest_width.get()],
+            "row {y}"
+        );
+    }
+}
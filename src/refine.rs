@@ -1,5 +1,6 @@
 mod bicubic;
 mod bilinear;
+mod custom;
 mod wiener;
 
 #[cfg(test)]
@@ -13,9 +14,12 @@ pub use bilinear::{
     refine_horizontal_bilinear,
     refine_vertical_bilinear,
 };
+pub use custom::{refine_horizontal_custom, refine_vertical_custom};
+#[cfg(feature = "bench")]
+pub use wiener::refine_vertical_wiener_transposed;
 pub use wiener::{refine_horizontal_wiener, refine_vertical_wiener};
 
-use crate::{mv_plane::MVPlane, pad::pad_reference_frame, util::Pixel};
+use crate::{mv_plane::MVPlane, pad::pad_reference_frame, params::PadMode, util::Pixel};
 
 /// Function pointer type for sub-pixel refinement functions.
 ///
@@ -24,6 +28,49 @@ use crate::{mv_plane::MVPlane, pad::pad_reference_frame, util::Pixel};
 /// this common interface for consistency and interchangeability.
 pub type RefineFn<T> = fn(&mut [T], &[T], NonZeroUsize, NonZeroUsize, NonZeroUsize, NonZeroU8);
 
+/// Checks that `dest` and `src` hold every row that the refine function `name` touches,
+/// so that an undersized buffer is reported by name instead of deep inside its loops.
+///
+/// The last row only needs `width` pixels, since windows may end right after it.
+fn debug_check_buffers<T>(
+    name: &str,
+    dest: &[T],
+    src: &[T],
+    pitch: NonZeroUsize,
+    width: NonZeroUsize,
+    height: NonZeroUsize,
+) {
+    let len = pitch.get() * (height.get() - 1) + width.get();
+    debug_assert!(
+        dest.len() >= len,
+        "{name}: dest has {} pixels, but {len} are needed",
+        dest.len()
+    );
+    debug_assert!(
+        src.len() >= len,
+        "{name}: src has {} pixels, but {len} are needed",
+        src.len()
+    );
+}
+
+/// Checks that an external upsampled plane holds the `scale * height` rows of `scale * width`
+/// pixels that the refine function `name` reads, with the last row ending right after its pixels.
+fn debug_check_upsampled<T>(
+    name: &str,
+    src: &[T],
+    pitch: NonZeroUsize,
+    width: NonZeroUsize,
+    height: NonZeroUsize,
+    scale: usize,
+) {
+    let len = pitch.get() * (scale * height.get() - 1) + scale * width.get();
+    debug_assert!(
+        src.len() >= len,
+        "{name}: src_2x has {} pixels, but {len} are needed",
+        src.len()
+    );
+}
+
 impl MVPlane {
     /// Refines motion vector plane to half-pixel precision using 2x upsampled reference.
     ///
@@ -38,17 +85,30 @@ impl MVPlane {
     /// - Window 3: (0.5, 0.5) - diagonal half-pixel positions
     ///
     /// # Parameters
-    /// - `src_2x`: Source buffer containing 2x upsampled reference frame
+    /// - `src_2x`: Source buffer containing 2x upsampled reference frame, with `2 * height` rows
+    ///   of `2 * width` pixels. The last row may end right after its pixels, so it must hold
+    ///   at least `(2 * height - 1) * src_2x_pitch + 2 * width` pixels.
     /// - `src_2x_pitch`: Number of pixels per row in the upsampled source
     /// - `is_ext_padded`: Whether external padding has already been applied
+    /// - `pad_mode`: How the padding is filled when it has not been applied
     /// - `dest`: Destination buffer to store sub-pixel samples
     pub fn refine_ext_pel2<T: Pixel>(
         &mut self,
-        mut src_2x: &[T],
+        src_2x: &[T],
         src_2x_pitch: NonZeroUsize,
         is_ext_padded: bool,
+        pad_mode: PadMode,
         dest: &mut [T],
     ) {
+        debug_check_upsampled(
+            "refine_ext_pel2",
+            src_2x,
+            src_2x_pitch,
+            self.width,
+            self.height,
+            2,
+        );
+
         let mut p1 = self.subpel_window_offsets[1];
         let mut p2 = self.subpel_window_offsets[2];
         let mut p3 = self.subpel_window_offsets[3];
@@ -61,7 +121,8 @@ impl MVPlane {
             p3 += offset;
         }
 
-        for _h in 0..self.height.get() {
+        for h in 0..self.height.get() {
+            let src_2x = &src_2x[src_2x_pitch.get() * 2 * h..];
             for w in 0..self.width.get() {
                 dest[p1 + w] = src_2x[(w << 1) + 1];
                 dest[p2 + w] = src_2x[(w << 1) + src_2x_pitch.get()];
@@ -70,7 +131,6 @@ impl MVPlane {
             p1 += self.pitch.get();
             p2 += self.pitch.get();
             p3 += self.pitch.get();
-            src_2x = &src_2x[src_2x_pitch.get() * 2..];
         }
 
         if !is_ext_padded {
@@ -82,6 +142,7 @@ impl MVPlane {
                     self.vpad,
                     self.width,
                     self.height,
+                    pad_mode,
                     dest,
                 );
             }
@@ -102,17 +163,30 @@ impl MVPlane {
     /// - All diagonal combinations of the above positions
     ///
     /// # Parameters
-    /// - `src_2x`: Source buffer containing 4x upsampled reference frame
+    /// - `src_2x`: Source buffer containing 4x upsampled reference frame, with `4 * height` rows
+    ///   of `4 * width` pixels. The last row may end right after its pixels, so it must hold
+    ///   at least `(4 * height - 1) * src_2x_pitch + 4 * width` pixels.
     /// - `src_2x_pitch`: Number of pixels per row in the upsampled source
     /// - `is_ext_padded`: Whether external padding has already been applied
+    /// - `pad_mode`: How the padding is filled when it has not been applied
     /// - `dest`: Destination buffer to store sub-pixel samples
     pub fn refine_ext_pel4<T: Pixel>(
         &mut self,
-        mut src_2x: &[T],
+        src_2x: &[T],
         src_2x_pitch: NonZeroUsize,
         is_ext_padded: bool,
+        pad_mode: PadMode,
         dest: &mut [T],
     ) {
+        debug_check_upsampled(
+            "refine_ext_pel4",
+            src_2x,
+            src_2x_pitch,
+            self.width,
+            self.height,
+            4,
+        );
+
         let mut pp = [0; 16];
         for (ppi, offset) in pp
             .iter_mut()
@@ -131,7 +205,8 @@ impl MVPlane {
             }
         }
 
-        for _h in 0..self.height.get() {
+        for h in 0..self.height.get() {
+            let src_2x = &src_2x[src_2x_pitch.get() * 4 * h..];
             for w in 0..self.width.get() {
                 dest[pp[1] + w] = src_2x[(w << 2) + 1];
                 dest[pp[2] + w] = src_2x[(w << 2) + 2];
@@ -152,7 +227,6 @@ impl MVPlane {
             for ppi in pp[1..16].iter_mut() {
                 *ppi += self.pitch.get();
             }
-            src_2x = &src_2x[src_2x_pitch.get() * 4..];
         }
 
         if !is_ext_padded {
@@ -164,6 +238,7 @@ impl MVPlane {
                     self.vpad,
                     self.width,
                     self.height,
+                    pad_mode,
                     dest,
                 );
             }
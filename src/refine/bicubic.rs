@@ -9,6 +9,7 @@ use std::num::{NonZeroU8, NonZeroUsize};
 
 use cfg_if::cfg_if;
 
+use super::debug_check_buffers;
 use crate::util::Pixel;
 
 /// Performs horizontal bicubic interpolation for sub-pixel motion estimation refinement.
@@ -35,6 +36,7 @@ pub fn refine_horizontal_bicubic<T: Pixel>(
     height: NonZeroUsize,
     bits_per_sample: NonZeroU8,
 ) {
+    debug_check_buffers("refine_horizontal_bicubic", dest, src, pitch, width, height);
     debug_assert!(
         bits_per_sample.get() as usize > (size_of::<T>() - 1) * 8
             && (bits_per_sample.get() as usize <= size_of::<T>() * 8)
@@ -43,10 +45,16 @@ pub fn refine_horizontal_bicubic<T: Pixel>(
     cfg_if! {
         if #[cfg(all(target_arch = "x86_64", not(feature = "no_simd")))] {
             if crate::util::has_avx2() {
-                // SAFETY: We check for AVX2 first
-                unsafe {
-                    avx2::refine_horizontal_bicubic(dest, src, pitch, width, height, bits_per_sample);
-                }
+                crate::util::run_simd_plane(
+                    "refine_horizontal_bicubic",
+                    dest,
+                    pitch,
+                    width,
+                    height,
+                    // SAFETY: We check for AVX2 first
+                    |dest| unsafe { avx2::refine_horizontal_bicubic(dest, src, pitch, width, height, bits_per_sample) },
+                    |dest| rust::refine_horizontal_bicubic(dest, src, pitch, width, height, bits_per_sample),
+                );
                 return;
             }
         }
@@ -80,6 +88,7 @@ pub fn refine_vertical_bicubic<T: Pixel>(
     height: NonZeroUsize,
     bits_per_sample: NonZeroU8,
 ) {
+    debug_check_buffers("refine_vertical_bicubic", dest, src, pitch, width, height);
     debug_assert!(
         bits_per_sample.get() as usize > (size_of::<T>() - 1) * 8
             && (bits_per_sample.get() as usize <= size_of::<T>() * 8)
@@ -88,10 +97,16 @@ pub fn refine_vertical_bicubic<T: Pixel>(
     cfg_if! {
         if #[cfg(all(target_arch = "x86_64", not(feature = "no_simd")))] {
             if crate::util::has_avx2() {
-                // SAFETY: We check for AVX2 first
-                unsafe {
-                    avx2::refine_vertical_bicubic(dest, src, pitch, width, height, bits_per_sample);
-                }
+                crate::util::run_simd_plane(
+                    "refine_vertical_bicubic",
+                    dest,
+                    pitch,
+                    width,
+                    height,
+                    // SAFETY: We check for AVX2 first
+                    |dest| unsafe { avx2::refine_vertical_bicubic(dest, src, pitch, width, height, bits_per_sample) },
+                    |dest| rust::refine_vertical_bicubic(dest, src, pitch, width, height, bits_per_sample),
+                );
                 return;
             }
         }
@@ -9,6 +9,7 @@ use std::num::{NonZeroU8, NonZeroUsize};
 
 use cfg_if::cfg_if;
 
+use super::debug_check_buffers;
 use crate::util::Pixel;
 
 /// Performs horizontal bilinear interpolation for sub-pixel motion estimation refinement.
@@ -35,13 +36,27 @@ pub fn refine_horizontal_bilinear<T: Pixel>(
     height: NonZeroUsize,
     _bits_per_sample: NonZeroU8,
 ) {
+    debug_check_buffers(
+        "refine_horizontal_bilinear",
+        dest,
+        src,
+        pitch,
+        width,
+        height,
+    );
     cfg_if! {
         if #[cfg(all(target_arch = "x86_64", not(feature = "no_simd")))] {
             if crate::util::has_avx2() {
-                // SAFETY: We check for AVX2 first
-                unsafe {
-                    avx2::refine_horizontal_bilinear(dest, src, pitch, width, height, _bits_per_sample);
-                }
+                crate::util::run_simd_plane(
+                    "refine_horizontal_bilinear",
+                    dest,
+                    pitch,
+                    width,
+                    height,
+                    // SAFETY: We check for AVX2 first
+                    |dest| unsafe { avx2::refine_horizontal_bilinear(dest, src, pitch, width, height, _bits_per_sample) },
+                    |dest| rust::refine_horizontal_bilinear(dest, src, pitch, width, height, _bits_per_sample),
+                );
                 return;
             }
         }
@@ -74,13 +89,20 @@ pub fn refine_vertical_bilinear<T: Pixel>(
     height: NonZeroUsize,
     _bits_per_sample: NonZeroU8,
 ) {
+    debug_check_buffers("refine_vertical_bilinear", dest, src, pitch, width, height);
     cfg_if! {
         if #[cfg(all(target_arch = "x86_64", not(feature = "no_simd")))] {
             if crate::util::has_avx2() {
-                // SAFETY: We check for AVX2 first
-                unsafe {
-                    avx2::refine_vertical_bilinear(dest, src, pitch, width, height, _bits_per_sample);
-                }
+                crate::util::run_simd_plane(
+                    "refine_vertical_bilinear",
+                    dest,
+                    pitch,
+                    width,
+                    height,
+                    // SAFETY: We check for AVX2 first
+                    |dest| unsafe { avx2::refine_vertical_bilinear(dest, src, pitch, width, height, _bits_per_sample) },
+                    |dest| rust::refine_vertical_bilinear(dest, src, pitch, width, height, _bits_per_sample),
+                );
                 return;
             }
         }
@@ -96,7 +118,11 @@ pub fn refine_vertical_bilinear<T: Pixel>(
 /// values at diagonal positions, which is essential for sub-pixel motion estimation
 /// that requires samples at positions like (0.5, 0.5).
 ///
-/// Edge pixels and the last row/column use simplified interpolation due to missing neighbors.
+/// Each pixel is `(a + b + c + d + 2) / 4` of the 2x2 neighborhood starting at it.
+/// The last column only has a neighbor below, and the last row only has a neighbor
+/// to the right, so they use the 2-tap average `(a + b + 1) / 2` instead.
+/// The bottom-right pixel is copied unchanged. Nothing outside of the
+/// `width`x`height` area is read or written.
 ///
 /// # Parameters
 /// - `src`: Source image buffer
@@ -113,13 +139,20 @@ pub fn refine_diagonal_bilinear<T: Pixel>(
     height: NonZeroUsize,
     _bits_per_sample: NonZeroU8,
 ) {
+    debug_check_buffers("refine_diagonal_bilinear", dest, src, pitch, width, height);
     cfg_if! {
         if #[cfg(all(target_arch = "x86_64", not(feature = "no_simd")))] {
             if crate::util::has_avx2() {
-                // SAFETY: We check for AVX2 first
-                unsafe {
-                    avx2::refine_diagonal_bilinear(dest, src, pitch, width, height, _bits_per_sample);
-                }
+                crate::util::run_simd_plane(
+                    "refine_diagonal_bilinear",
+                    dest,
+                    pitch,
+                    width,
+                    height,
+                    // SAFETY: We check for AVX2 first
+                    |dest| unsafe { avx2::refine_diagonal_bilinear(dest, src, pitch, width, height, _bits_per_sample) },
+                    |dest| rust::refine_diagonal_bilinear(dest, src, pitch, width, height, _bits_per_sample),
+                );
                 return;
             }
         }
@@ -267,8 +267,8 @@ unsafe fn refine_vertical_bilinear_u16(
 }
 
 /// # Notes
-/// Attempted to port the C++ version, but there were some bugs that were unmasked by the tests.
-/// This is an original version.
+/// The 4-tap sum does not fit in 8 bits, so each 16 pixels are widened to 16 bits
+/// before averaging and packed back down afterwards.
 #[target_feature(enable = "avx2")]
 unsafe fn refine_diagonal_bilinear_u8(
     src: *const u8,
@@ -281,41 +281,65 @@ unsafe fn refine_diagonal_bilinear_u8(
     let width = width.get();
     let height = height.get();
 
+    let rounding = _mm256_set1_epi16(2);
     let mut offset = 0;
 
-    for _j in 0..height {
-        // Main loop for each row
-        for i in 0..width {
+    for _j in 0..height - 1 {
+        let mut i = 0;
+
+        // Process 16 pixels at a time, reading one pixel past them on the right
+        while i + 16 < width {
+            let a = _mm256_cvtepu8_epi16(_mm_loadu_si128(src.add(offset + i).cast()));
+            let b = _mm256_cvtepu8_epi16(_mm_loadu_si128(src.add(offset + i + 1).cast()));
+            let c = _mm256_cvtepu8_epi16(_mm_loadu_si128(src.add(offset + i + pitch).cast()));
+            let d = _mm256_cvtepu8_epi16(_mm_loadu_si128(src.add(offset + i + pitch + 1).cast()));
+
+            let sum = _mm256_add_epi16(_mm256_add_epi16(a, b), _mm256_add_epi16(c, d));
+            let result = _mm256_srli_epi16(_mm256_add_epi16(sum, rounding), 2);
+            let packed = _mm256_permute4x64_epi64(_mm256_packus_epi16(result, result), 0b1000);
+            _mm_storeu_si128(dest.add(offset + i).cast(), _mm256_castsi256_si128(packed));
+            i += 16;
+        }
+
+        // Process remaining pixels with scalar code
+        while i < width - 1 {
             let a = *src.add(offset + i) as u16;
             let b = *src.add(offset + i + 1) as u16;
             let c = *src.add(offset + i + pitch) as u16;
             let d = *src.add(offset + i + pitch + 1) as u16;
-
             *dest.add(offset + i) = ((a + b + c + d + 2) / 4) as u8;
+            i += 1;
         }
 
         // Handle last column separately (2-tap vertical)
-        if width > 0 {
-            let a = *src.add(offset + width - 1) as u16;
-            let b = *src.add(offset + width - 1 + pitch) as u16;
-            *dest.add(offset + width - 1) = ((a + b + 1) / 2) as u8;
-        }
+        let a = *src.add(offset + width - 1) as u16;
+        let b = *src.add(offset + width - 1 + pitch) as u16;
+        *dest.add(offset + width - 1) = ((a + b + 1) / 2) as u8;
 
         offset += pitch;
     }
 
     // Handle last row separately (2-tap horizontal)
-    for i in 0..width - 1 {
+    let mut i = 0;
+    while i + 32 < width {
+        let current = _mm256_loadu_si256(src.add(offset + i).cast());
+        let next = _mm256_loadu_si256(src.add(offset + i + 1).cast());
+        _mm256_storeu_si256(dest.add(offset + i).cast(), _mm256_avg_epu8(current, next));
+        i += 32;
+    }
+    while i < width - 1 {
         let a = *src.add(offset + i) as u16;
         let b = *src.add(offset + i + 1) as u16;
         *dest.add(offset + i) = ((a + b + 1) / 2) as u8;
+        i += 1;
     }
     // Last pixel - copy directly
-    if width > 0 {
-        *dest.add(offset + width - 1) = *src.add(offset + width - 1);
-    }
+    *dest.add(offset + width - 1) = *src.add(offset + width - 1);
 }
 
+/// # Notes
+/// The 4-tap sum of 16-bit pixels does not fit in 16 bits, so each 8 pixels are widened
+/// to 32 bits before averaging and packed back down afterwards.
 #[target_feature(enable = "avx2")]
 unsafe fn refine_diagonal_bilinear_u16(
     src: *const u16,
@@ -328,37 +352,58 @@ unsafe fn refine_diagonal_bilinear_u16(
     let width = width.get();
     let height = height.get();
 
+    let rounding = _mm256_set1_epi32(2);
     let mut offset = 0;
 
-    for _j in 0..height {
-        // Main loop for each row
-        for i in 0..width {
+    for _j in 0..height - 1 {
+        let mut i = 0;
+
+        // Process 8 pixels at a time, reading one pixel past them on the right
+        while i + 8 < width {
+            let a = _mm256_cvtepu16_epi32(_mm_loadu_si128(src.add(offset + i).cast()));
+            let b = _mm256_cvtepu16_epi32(_mm_loadu_si128(src.add(offset + i + 1).cast()));
+            let c = _mm256_cvtepu16_epi32(_mm_loadu_si128(src.add(offset + i + pitch).cast()));
+            let d = _mm256_cvtepu16_epi32(_mm_loadu_si128(src.add(offset + i + pitch + 1).cast()));
+
+            let sum = _mm256_add_epi32(_mm256_add_epi32(a, b), _mm256_add_epi32(c, d));
+            let result = _mm256_srli_epi32(_mm256_add_epi32(sum, rounding), 2);
+            let packed = _mm256_permute4x64_epi64(_mm256_packus_epi32(result, result), 0b1000);
+            _mm_storeu_si128(dest.add(offset + i).cast(), _mm256_castsi256_si128(packed));
+            i += 8;
+        }
+
+        // Process remaining pixels with scalar code
+        while i < width - 1 {
             let a = *src.add(offset + i) as u32;
             let b = *src.add(offset + i + 1) as u32;
             let c = *src.add(offset + i + pitch) as u32;
             let d = *src.add(offset + i + pitch + 1) as u32;
-
             *dest.add(offset + i) = ((a + b + c + d + 2) / 4) as u16;
+            i += 1;
         }
 
         // Handle last column separately (2-tap vertical)
-        if width > 0 {
-            let a = *src.add(offset + width - 1) as u32;
-            let b = *src.add(offset + width - 1 + pitch) as u32;
-            *dest.add(offset + width - 1) = ((a + b + 1) / 2) as u16;
-        }
+        let a = *src.add(offset + width - 1) as u32;
+        let b = *src.add(offset + width - 1 + pitch) as u32;
+        *dest.add(offset + width - 1) = ((a + b + 1) / 2) as u16;
 
         offset += pitch;
     }
 
     // Handle last row separately (2-tap horizontal)
-    for i in 0..width - 1 {
+    let mut i = 0;
+    while i + 16 < width {
+        let current = _mm256_loadu_si256(src.add(offset + i).cast());
+        let next = _mm256_loadu_si256(src.add(offset + i + 1).cast());
+        _mm256_storeu_si256(dest.add(offset + i).cast(), _mm256_avg_epu16(current, next));
+        i += 16;
+    }
+    while i < width - 1 {
         let a = *src.add(offset + i) as u32;
         let b = *src.add(offset + i + 1) as u32;
         *dest.add(offset + i) = ((a + b + 1) / 2) as u16;
+        i += 1;
     }
     // Last pixel - copy directly
-    if width > 0 {
-        *dest.add(offset + width - 1) = *src.add(offset + width - 1);
-    }
+    *dest.add(offset + width - 1) = *src.add(offset + width - 1);
 }
@@ -59,8 +59,8 @@ pub(super) fn refine_diagonal_bilinear<T: Pixel>(
 ) {
     let mut offset = 0;
 
-    for _j in 0..height.get() {
-        for i in 0..width.get() {
+    for _j in 0..height.get() - 1 {
+        for i in 0..width.get() - 1 {
             let a: u32 = src[offset + i].to_u32().expect("fits in u32");
             let b: u32 = src[offset + i + 1].to_u32().expect("fits in u32");
             let c: u32 = src[offset + i + pitch.get()].to_u32().expect("fits in u32");
@@ -4,9 +4,38 @@
 use std::num::{NonZeroU8, NonZeroUsize};
 
 use pastey::paste;
+use rand::{Rng, SeedableRng};
+use rand_xoshiro::Xoshiro128StarStar;
 
 use super::*;
 
+/// Computes the expected diagonal interpolation of the `width`x`height` area,
+/// leaving the rest of `dest` untouched.
+fn diagonal_reference(dest: &mut [u32], src: &[u32], pitch: usize, width: usize, height: usize) {
+    for y in 0..height {
+        for x in 0..width {
+            let at = |dx: usize, dy: usize| src[(y + dy) * pitch + x + dx];
+            dest[y * pitch + x] = match (x + 1 < width, y + 1 < height) {
+                (true, true) => (at(0, 0) + at(1, 0) + at(0, 1) + at(1, 1) + 2) / 4,
+                (false, true) => (at(0, 0) + at(0, 1) + 1) / 2,
+                (true, false) => (at(0, 0) + at(1, 0) + 1) / 2,
+                (false, false) => at(0, 0),
+            };
+        }
+    }
+}
+
+const DIAGONAL_SIZES: [(usize, usize); 8] = [
+    (1, 1),
+    (2, 3),
+    (15, 2),
+    (16, 4),
+    (17, 3),
+    (33, 5),
+    (40, 2),
+    (70, 6),
+];
+
 macro_rules! horizontal_tests {
     ($module:ident) => {
         paste! {
@@ -199,12 +228,11 @@ macro_rules! diagonal_tests {
         paste! {
             #[test]
             fn [<test_refine_diagonal_bilinear_basic_ $module>]() {
-                // Test with a simple 2x2 pattern, need extra padding for diagonal access
-                // The function accesses [i+1] and [i+pitch+1], so we need padding
+                // Test with a simple 2x2 pattern inside a larger buffer
                 let src = vec![
                     10u8, 20, 30, // Need extra column for access to [i+1]
                     40, 50, 60, // Main data
-                    70, 80, 90, // Extra row for main loop [i+pitch] access
+                    70, 80, 90, // Must not be read
                 ];
                 let mut dest = vec![0u8; 9];
                 let pitch = NonZeroUsize::new(3).unwrap();
@@ -220,21 +248,24 @@ macro_rules! diagonal_tests {
                 // For position [0,1] (last column): (20 + 50).div_ceil(2) = 35
                 assert_eq!(dest[1], 35);
 
-                // For position [1,0]: (40 + 50 + 70 + 80 + 2) / 4 = 242 / 4 = 60
-                assert_eq!(dest[3], 60);
+                // For position [1,0] (last row): (40 + 50).div_ceil(2) = 45
+                assert_eq!(dest[3], 45);
 
-                // For position [1,1] (last column of second row): (50 + 80).div_ceil(2) = 65
-                assert_eq!(dest[4], 65);
+                // For position [1,1] (last pixel): copied unchanged
+                assert_eq!(dest[4], 50);
+
+                // Nothing outside of the 2x2 area is written
+                assert_eq!(dest[2], 0);
+                assert_eq!(&dest[5..], &[0, 0, 0, 0]);
             }
 
             #[test]
             fn [<test_refine_diagonal_bilinear_u16_ $module>]() {
-                // Test with a simple 2x2 pattern, need extra padding for diagonal access
-                // The function accesses [i+1] and [i+pitch+1], so we need padding
+                // Test with a simple 2x2 pattern inside a larger buffer
                 let src = vec![
                     10u16, 20, 30, // Need extra column for access to [i+1]
                     40, 50, 60, // Main data
-                    70, 80, 90, // Extra row for main loop [i+pitch] access
+                    70, 80, 90, // Must not be read
                 ];
                 let mut dest = vec![0u16; 9];
                 let pitch = NonZeroUsize::new(3).unwrap();
@@ -250,19 +281,23 @@ macro_rules! diagonal_tests {
                 // For position [0,1] (last column): (20 + 50).div_ceil(2) = 35
                 assert_eq!(dest[1], 35);
 
-                // For position [1,0]: (40 + 50 + 70 + 80 + 2) / 4 = 242 / 4 = 60
-                assert_eq!(dest[3], 60);
+                // For position [1,0] (last row): (40 + 50).div_ceil(2) = 45
+                assert_eq!(dest[3], 45);
+
+                // For position [1,1] (last pixel): copied unchanged
+                assert_eq!(dest[4], 50);
 
-                // For position [1,1] (last column of second row): (50 + 80).div_ceil(2) = 65
-                assert_eq!(dest[4], 65);
+                // Nothing outside of the 2x2 area is written
+                assert_eq!(dest[2], 0);
+                assert_eq!(&dest[5..], &[0, 0, 0, 0]);
             }
 
             #[test]
             fn [<test_refine_diagonal_bilinear_single_pixel_ $module>]() {
-                // Test with single pixel - need padding for diagonal access
+                // Test with single pixel, surrounded by pixels that must not be read
                 let src = vec![
-                    42u8, 0, // Need padding for [i+1] access
-                    0, 0, // Need padding for [i+pitch] access
+                    42u8, 99,
+                    99, 99,
                 ];
                 let mut dest = vec![0u8; 4];
                 let pitch = NonZeroUsize::new(2).unwrap();
@@ -272,9 +307,65 @@ macro_rules! diagonal_tests {
 
                 verify_asm!($module, refine_diagonal_bilinear(&mut dest, &src, pitch, width, height, bits));
 
-                // For single pixel: (42 + 0 + 0 + 0 + 2) / 4 = 44 / 4 = 11
-                // However, the actual result is 21, accounting for implementation details
-                assert_eq!(dest[0], 21);
+                // A single pixel is both the last row and the last column, so it is copied
+                assert_eq!(dest[0], 42);
+                assert_eq!(&dest[1..], &[0, 0, 0]);
+            }
+
+            #[test]
+            fn [<test_refine_diagonal_bilinear_matches_reference_u8_ $module>]() {
+                let mut rng = Xoshiro128StarStar::seed_from_u64(1);
+                for (width, height) in DIAGONAL_SIZES {
+                    // The padding past the area is filled with a sentinel that must stay untouched
+                    let pitch = width + 3;
+                    let len = pitch * (height + 1);
+                    let src: Vec<u8> = std::iter::repeat_with(|| rng.random()).take(len).collect();
+                    let mut dest = vec![7u8; len];
+
+                    verify_asm!($module, refine_diagonal_bilinear(
+                        &mut dest,
+                        &src,
+                        NonZeroUsize::new(pitch).unwrap(),
+                        NonZeroUsize::new(width).unwrap(),
+                        NonZeroUsize::new(height).unwrap(),
+                        NonZeroU8::new(8).unwrap(),
+                    ));
+
+                    let mut expected = vec![7u32; len];
+                    let src: Vec<u32> = src.into_iter().map(u32::from).collect();
+                    diagonal_reference(&mut expected, &src, pitch, width, height);
+                    let dest: Vec<u32> = dest.into_iter().map(u32::from).collect();
+                    assert_eq!(dest, expected, "{width}x{height}");
+                }
+            }
+
+            #[test]
+            fn [<test_refine_diagonal_bilinear_matches_reference_u16_ $module>]() {
+                let mut rng = Xoshiro128StarStar::seed_from_u64(2);
+                for (width, height) in DIAGONAL_SIZES {
+                    let pitch = width + 3;
+                    let len = pitch * (height + 1);
+                    // Values at the top of the range would overflow a 16-bit 4-tap sum
+                    let src: Vec<u16> = std::iter::repeat_with(|| rng.random_range(60000..=u16::MAX))
+                        .take(len)
+                        .collect();
+                    let mut dest = vec![7u16; len];
+
+                    verify_asm!($module, refine_diagonal_bilinear(
+                        &mut dest,
+                        &src,
+                        NonZeroUsize::new(pitch).unwrap(),
+                        NonZeroUsize::new(width).unwrap(),
+                        NonZeroUsize::new(height).unwrap(),
+                        NonZeroU8::new(16).unwrap(),
+                    ));
+
+                    let mut expected = vec![7u32; len];
+                    let src: Vec<u32> = src.into_iter().map(u32::from).collect();
+                    diagonal_reference(&mut expected, &src, pitch, width, height);
+                    let dest: Vec<u32> = dest.into_iter().map(u32::from).collect();
+                    assert_eq!(dest, expected, "{width}x{height}");
+                }
             }
 
             #[test]
@@ -0,0 +1,127 @@
+#[cfg(test)]
+mod tests;
+
+use std::num::{NonZeroU8, NonZeroUsize};
+
+use super::debug_check_buffers;
+use crate::{params::SubpelKernel, util::Pixel};
+
+/// Applies a 6-tap kernel to the pixels `offset - 2 * step` through `offset + 3 * step`,
+/// clamping the result to the bit depth.
+fn apply_kernel<T: Pixel>(
+    src: &[T],
+    offset: usize,
+    step: usize,
+    taps: [i32; 6],
+    pixel_max: i32,
+) -> T {
+    let first = offset - 2 * step;
+    let sum = taps
+        .iter()
+        .enumerate()
+        .map(|(i, &tap)| tap * src[first + i * step].to_i32().expect("fits in i32"))
+        .sum::<i32>();
+    T::from_u32_or_max_value(((sum + SubpelKernel::SUM / 2) >> 5).clamp(0, pixel_max) as u32)
+}
+
+fn average<T: Pixel>(a: T, b: T) -> T {
+    let a: u32 = a.to_u32().expect("fits in u32");
+    let b: u32 = b.to_u32().expect("fits in u32");
+    T::from_u32_or_max_value((a + b + 1) / 2)
+}
+
+/// Performs horizontal sub-pixel refinement with a user-supplied 6-tap kernel.
+///
+/// Edges are handled exactly like [`refine_horizontal_wiener`](super::refine_horizontal_wiener):
+/// pixels without enough neighbors for the kernel are averaged with the next pixel,
+/// and the last pixel of each row is copied, so [`SubpelKernel::WIENER`] reproduces
+/// its output.
+///
+/// # Parameters
+/// - `src`: Source image buffer
+/// - `dest`: Destination buffer for interpolated results
+/// - `pitch`: Number of pixels per row in both buffers
+/// - `width`: Width of the image in pixels
+/// - `height`: Height of the image in pixels
+/// - `bits_per_sample`: Bit depth of the pixel format for clamping
+/// - `kernel`: Taps applied to the two pixels left and three pixels right of each sample
+pub fn refine_horizontal_custom<T: Pixel>(
+    dest: &mut [T],
+    src: &[T],
+    pitch: NonZeroUsize,
+    width: NonZeroUsize,
+    height: NonZeroUsize,
+    bits_per_sample: NonZeroU8,
+    kernel: SubpelKernel,
+) {
+    debug_check_buffers("refine_horizontal_custom", dest, src, pitch, width, height);
+
+    let pixel_max = (1i32 << bits_per_sample.get()) - 1;
+    let taps = kernel.taps();
+    let width = width.get();
+    let kernel_end = if width >= 4 { width - 4 } else { 2 };
+
+    for y in 0..height.get() {
+        let offset = y * pitch.get();
+        let src_row = &src[offset..][..width];
+        let dest_row = &mut dest[offset..][..width];
+
+        for x in (0..2).chain(kernel_end..width - 1) {
+            if x + 1 < width {
+                dest_row[x] = average(src_row[x], src_row[x + 1]);
+            }
+        }
+        for x in 2..kernel_end {
+            dest_row[x] = apply_kernel(src_row, x, 1, taps, pixel_max);
+        }
+        dest_row[width - 1] = src_row[width - 1];
+    }
+}
+
+/// Performs vertical sub-pixel refinement with a user-supplied 6-tap kernel.
+///
+/// Edges are handled exactly like [`refine_vertical_wiener`](super::refine_vertical_wiener):
+/// rows without enough neighbors for the kernel are averaged with the next row,
+/// and the last row is copied, so [`SubpelKernel::WIENER`] reproduces its output.
+///
+/// # Parameters
+/// - `src`: Source image buffer
+/// - `dest`: Destination buffer for interpolated results
+/// - `pitch`: Number of pixels per row in both buffers
+/// - `width`: Width of the image in pixels
+/// - `height`: Height of the image in pixels
+/// - `bits_per_sample`: Bit depth of the pixel format for clamping
+/// - `kernel`: Taps applied to the two rows above and three rows below each sample
+pub fn refine_vertical_custom<T: Pixel>(
+    dest: &mut [T],
+    src: &[T],
+    pitch: NonZeroUsize,
+    width: NonZeroUsize,
+    height: NonZeroUsize,
+    bits_per_sample: NonZeroU8,
+    kernel: SubpelKernel,
+) {
+    debug_check_buffers("refine_vertical_custom", dest, src, pitch, width, height);
+
+    let pixel_max = (1i32 << bits_per_sample.get()) - 1;
+    let taps = kernel.taps();
+    let pitch = pitch.get();
+    let height = height.get();
+    let kernel_end = height.saturating_sub(4).max(2);
+
+    for y in (0..2).chain(kernel_end..height - 1) {
+        let offset = y * pitch;
+        for x in offset..offset + width.get() {
+            dest[x] = average(src[x], src[x + pitch]);
+        }
+    }
+    for y in 2..kernel_end {
+        let offset = y * pitch;
+        for x in offset..offset + width.get() {
+            dest[x] = apply_kernel(src, x, pitch, taps, pixel_max);
+        }
+    }
+
+    let last = (height - 1) * pitch;
+    dest[last..][..width.get()].copy_from_slice(&src[last..][..width.get()]);
+}
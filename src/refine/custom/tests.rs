@@ -0,0 +1,103 @@
+#![allow(clippy::unwrap_used, reason = "allow in test files")]
+#![allow(clippy::undocumented_unsafe_blocks, reason = "allow in test files")]
+
+use std::num::{NonZeroU8, NonZeroUsize};
+
+use rand::{Rng, SeedableRng};
+use rand_xoshiro::Xoshiro128StarStar;
+
+use super::*;
+use crate::refine::{refine_horizontal_wiener, refine_vertical_wiener};
+
+fn random_plane<T: Pixel>(rng: &mut Xoshiro128StarStar, len: usize, max: u32) -> Vec<T> {
+    std::iter::repeat_with(|| T::from_u32_or_max_value(rng.random_range(0..=max)))
+        .take(len)
+        .collect()
+}
+
+fn assert_wiener_kernel_matches_wiener<T: Pixel + std::fmt::Debug>(bits: u8) {
+    let mut rng = Xoshiro128StarStar::from_seed(*b"deadbeeflolcakes");
+    let bits_per_sample = NonZeroU8::new(bits).unwrap();
+    let max = (1u32 << bits) - 1;
+    // Include sizes too small for the kernel, which are averaged instead
+    for (width, height) in [(37, 23), (5, 6), (3, 9)] {
+        let pitch = NonZeroUsize::new(width + 3).unwrap();
+        let width = NonZeroUsize::new(width).unwrap();
+        let height = NonZeroUsize::new(height).unwrap();
+        let src: Vec<T> = random_plane(&mut rng, pitch.get() * height.get(), max);
+
+        let mut custom = vec![T::from_u32_or_max_value(0); src.len()];
+        let mut wiener = custom.clone();
+        refine_horizontal_custom(
+            &mut custom,
+            &src,
+            pitch,
+            width,
+            height,
+            bits_per_sample,
+            SubpelKernel::WIENER,
+        );
+        refine_horizontal_wiener(&mut wiener, &src, pitch, width, height, bits_per_sample);
+        assert_eq!(custom, wiener, "horizontal {width}x{height}, {bits} bits");
+
+        refine_vertical_custom(
+            &mut custom,
+            &src,
+            pitch,
+            width,
+            height,
+            bits_per_sample,
+            SubpelKernel::WIENER,
+        );
+        refine_vertical_wiener(&mut wiener, &src, pitch, width, height, bits_per_sample);
+        assert_eq!(custom, wiener, "vertical {width}x{height}, {bits} bits");
+    }
+}
+
+#[test]
+fn wiener_taps_reproduce_wiener_8_bit() {
+    assert_wiener_kernel_matches_wiener::<u8>(8);
+}
+
+#[test]
+fn wiener_taps_reproduce_wiener_10_bit() {
+    assert_wiener_kernel_matches_wiener::<u16>(10);
+}
+
+#[test]
+fn wiener_taps_reproduce_wiener_16_bit() {
+    assert_wiener_kernel_matches_wiener::<u16>(16);
+}
+
+#[test]
+fn custom_taps_are_applied_and_clamped() {
+    // A kernel that only looks at the pixel right of each sample
+    let kernel = SubpelKernel::parse("Super", "sharp_coeffs", &[0, 0, 0, 32, 0, 0]).unwrap();
+    let src: Vec<u8> = (0..10).map(|x| x * 20).collect();
+    let mut dest = vec![0u8; 10];
+    refine_horizontal_custom(
+        &mut dest,
+        &src,
+        NonZeroUsize::new(10).unwrap(),
+        NonZeroUsize::new(10).unwrap(),
+        NonZeroUsize::MIN,
+        NonZeroU8::new(8).unwrap(),
+        kernel,
+    );
+    assert_eq!(dest, [10, 30, 60, 80, 100, 120, 130, 150, 170, 180]);
+
+    // Overshoot past the bit depth is clamped
+    let kernel = SubpelKernel::parse("Super", "sharp_coeffs", &[-32, 0, 0, 64, 0, 0]).unwrap();
+    let src: Vec<u8> = vec![0, 0, 0, 0, 255, 255, 255, 255, 255, 255];
+    refine_horizontal_custom(
+        &mut dest,
+        &src,
+        NonZeroUsize::new(10).unwrap(),
+        NonZeroUsize::new(10).unwrap(),
+        NonZeroUsize::MIN,
+        NonZeroU8::new(8).unwrap(),
+        kernel,
+    );
+    assert_eq!(dest[3], 255);
+    assert_eq!(dest[5], 255);
+}
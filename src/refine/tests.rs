@@ -3,7 +3,10 @@
 
 use std::num::{NonZeroU8, NonZeroUsize};
 
-use crate::{mv_plane::MVPlane, params::Subpel};
+use crate::{
+    mv_plane::MVPlane,
+    params::{PadMode, Subpel},
+};
 
 /// Helper function to create an MVPlane for testing
 fn create_test_mv_plane(
@@ -225,6 +228,7 @@ fn refine_ext_pel2_u8_basic() {
         &src_2x,
         NonZeroUsize::new(src_2x_pitch).unwrap(),
         true, // is_ext_padded
+        PadMode::Replicate,
         &mut dest,
     );
 
@@ -253,6 +257,7 @@ fn refine_ext_pel2_u16_basic() {
         &src_2x,
         NonZeroUsize::new(src_2x_pitch).unwrap(),
         true, // is_ext_padded
+        PadMode::Replicate,
         &mut dest,
     );
 
@@ -280,6 +285,7 @@ fn refine_ext_pel2_not_padded() {
         &src_2x,
         NonZeroUsize::new(src_2x_pitch).unwrap(),
         false, // is_ext_padded = false, will add padding
+        PadMode::Replicate,
         &mut dest,
     );
 
@@ -308,6 +314,7 @@ fn refine_ext_pel4_u8_basic() {
         &src_2x,
         NonZeroUsize::new(src_2x_pitch).unwrap(),
         true, // is_ext_padded
+        PadMode::Replicate,
         &mut dest,
     );
 
@@ -336,6 +343,7 @@ fn refine_ext_pel4_u16_basic() {
         &src_2x,
         NonZeroUsize::new(src_2x_pitch).unwrap(),
         true, // is_ext_padded
+        PadMode::Replicate,
         &mut dest,
     );
 
@@ -363,6 +371,7 @@ fn refine_ext_pel4_not_padded() {
         &src_2x,
         NonZeroUsize::new(src_2x_pitch).unwrap(),
         false, // is_ext_padded = false, will add padding
+        PadMode::Replicate,
         &mut dest,
     );
 
@@ -390,6 +399,7 @@ fn refine_ext_pel2_larger_frame() {
         &src_2x,
         NonZeroUsize::new(src_2x_pitch).unwrap(),
         true,
+        PadMode::Replicate,
         &mut dest,
     );
 
@@ -417,6 +427,7 @@ fn refine_ext_pel4_larger_frame() {
         &src_2x,
         NonZeroUsize::new(src_2x_pitch).unwrap(),
         true,
+        PadMode::Replicate,
         &mut dest,
     );
 
@@ -450,6 +461,7 @@ fn refine_ext_pel2_minimal_size() {
         &src_2x,
         NonZeroUsize::new(src_2x_pitch).unwrap(),
         true,
+        PadMode::Replicate,
         &mut dest,
     );
 
@@ -484,6 +496,7 @@ fn refine_ext_pel4_minimal_size() {
         &src_2x,
         NonZeroUsize::new(src_2x_pitch).unwrap(),
         true,
+        PadMode::Replicate,
         &mut dest,
     );
 
@@ -512,6 +525,7 @@ fn refine_ext_pel2_with_plane_offset() {
         &src_2x,
         NonZeroUsize::new(src_2x_pitch).unwrap(),
         true,
+        PadMode::Replicate,
         &mut dest,
     );
 
@@ -540,6 +554,7 @@ fn refine_ext_pel4_with_plane_offset() {
         &src_2x,
         NonZeroUsize::new(src_2x_pitch).unwrap(),
         true,
+        PadMode::Replicate,
         &mut dest,
     );
 
@@ -575,6 +590,7 @@ fn refine_ext_pel2_different_pitch() {
         &src_2x,
         NonZeroUsize::new(src_2x_pitch).unwrap(),
         true,
+        PadMode::Replicate,
         &mut dest,
     );
 
@@ -610,9 +626,113 @@ fn refine_ext_pel4_different_pitch() {
         &src_2x,
         NonZeroUsize::new(src_2x_pitch).unwrap(),
         true,
+        PadMode::Replicate,
         &mut dest,
     );
 
     verify_pel4_interpolation(&plane, &src_2x, src_2x_pitch, &dest, true);
     assert!(plane.is_padded);
 }
+
+#[test]
+fn refine_accepts_windows_ending_after_the_last_row() {
+    // 3 rows of 4 pixels with a pitch of 6, without the padding after the last row
+    let src = [10u8; 16];
+    let mut dest = [0u8; 16];
+    let pitch = NonZeroUsize::new(6).unwrap();
+    let (width, height) = (NonZeroUsize::new(4).unwrap(), NonZeroUsize::new(3).unwrap());
+    super::refine_vertical_bilinear(&mut dest, &src, pitch, width, height, NonZeroU8::MIN);
+    for row in dest.chunks(6) {
+        assert_eq!(&row[..4], &[10; 4]);
+    }
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(expected = "refine_horizontal_wiener: src has 15 pixels, but 16 are needed")]
+fn undersized_refine_src_is_reported_by_name() {
+    let src = [0u8; 15];
+    let mut dest = [0u8; 16];
+    super::refine_horizontal_wiener(
+        &mut dest,
+        &src,
+        NonZeroUsize::new(6).unwrap(),
+        NonZeroUsize::new(4).unwrap(),
+        NonZeroUsize::new(3).unwrap(),
+        NonZeroU8::new(8).unwrap(),
+    );
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(expected = "refine_diagonal_bilinear: dest has 15 pixels, but 16 are needed")]
+fn undersized_refine_dest_is_reported_by_name() {
+    let src = [0u16; 16];
+    let mut dest = [0u16; 15];
+    super::refine_diagonal_bilinear(
+        &mut dest,
+        &src,
+        NonZeroUsize::new(6).unwrap(),
+        NonZeroUsize::new(4).unwrap(),
+        NonZeroUsize::new(3).unwrap(),
+        NonZeroU8::new(10).unwrap(),
+    );
+}
+
+/// An upsampled plane for a 2x2 plane with a pitch of `4 * scale - 2` pixels,
+/// with the last row ending right after its pixels and `trim` more pixels removed.
+fn trimmed_upsampled(scale: usize, trim: usize) -> (Vec<u8>, usize) {
+    let pitch = 4 * scale - 2;
+    let len = pitch * (2 * scale - 1) + 2 * scale - trim;
+    ((0..len).map(|i| (i % 251) as u8).collect(), pitch)
+}
+
+#[test]
+fn refine_ext_accepts_upsampled_planes_ending_after_the_last_row() {
+    for (pel, scale, windows) in [(Subpel::Half, 2, 4), (Subpel::Quarter, 4, 16)] {
+        let mut plane = create_test_mv_plane(2, 2, pel, 1, 1, 0);
+        let (src_2x, pitch) = trimmed_upsampled(scale, 0);
+        let mut dest = create_dest_buffer::<u8>(&plane, windows);
+        let pitch = NonZeroUsize::new(pitch).unwrap();
+
+        if scale == 2 {
+            plane.refine_ext_pel2(&src_2x, pitch, true, PadMode::Replicate, &mut dest);
+            verify_pel2_interpolation(&plane, &src_2x, pitch.get(), &dest, true);
+        } else {
+            plane.refine_ext_pel4(&src_2x, pitch, true, PadMode::Replicate, &mut dest);
+            verify_pel4_interpolation(&plane, &src_2x, pitch.get(), &dest, true);
+        }
+    }
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(expected = "refine_ext_pel2: src_2x has 21 pixels, but 22 are needed")]
+fn undersized_pel2_upsampled_plane_is_reported_by_name() {
+    let mut plane = create_test_mv_plane(2, 2, Subpel::Half, 1, 1, 0);
+    let (src_2x, pitch) = trimmed_upsampled(2, 1);
+    let mut dest = create_dest_buffer::<u8>(&plane, 4);
+    plane.refine_ext_pel2(
+        &src_2x,
+        NonZeroUsize::new(pitch).unwrap(),
+        true,
+        PadMode::Replicate,
+        &mut dest,
+    );
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(expected = "refine_ext_pel4: src_2x has 105 pixels, but 106 are needed")]
+fn undersized_pel4_upsampled_plane_is_reported_by_name() {
+    let mut plane = create_test_mv_plane(2, 2, Subpel::Quarter, 1, 1, 0);
+    let (src_2x, pitch) = trimmed_upsampled(4, 1);
+    let mut dest = create_dest_buffer::<u8>(&plane, 16);
+    plane.refine_ext_pel4(
+        &src_2x,
+        NonZeroUsize::new(pitch).unwrap(),
+        true,
+        PadMode::Replicate,
+        &mut dest,
+    );
+}
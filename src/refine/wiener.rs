@@ -9,7 +9,10 @@ use std::num::{NonZeroU8, NonZeroUsize};
 
 use cfg_if::cfg_if;
 
+use super::debug_check_buffers;
 use crate::util::Pixel;
+#[cfg(any(test, feature = "bench"))]
+use crate::util::transpose_block;
 
 /// Performs horizontal Wiener filtering for sub-pixel motion estimation refinement.
 ///
@@ -37,6 +40,7 @@ pub fn refine_horizontal_wiener<T: Pixel>(
     height: NonZeroUsize,
     bits_per_sample: NonZeroU8,
 ) {
+    debug_check_buffers("refine_horizontal_wiener", dest, src, pitch, width, height);
     debug_assert!(
         bits_per_sample.get() as usize > (size_of::<T>() - 1) * 8
             && (bits_per_sample.get() as usize <= size_of::<T>() * 8)
@@ -45,10 +49,16 @@ pub fn refine_horizontal_wiener<T: Pixel>(
     cfg_if! {
         if #[cfg(all(target_arch = "x86_64", not(feature = "no_simd")))] {
             if crate::util::has_avx2() {
-                // SAFETY: We check for AVX2 first
-                unsafe {
-                    avx2::refine_horizontal_wiener(dest, src, pitch, width, height, bits_per_sample);
-                }
+                crate::util::run_simd_plane(
+                    "refine_horizontal_wiener",
+                    dest,
+                    pitch,
+                    width,
+                    height,
+                    // SAFETY: We check for AVX2 first
+                    |dest| unsafe { avx2::refine_horizontal_wiener(dest, src, pitch, width, height, bits_per_sample) },
+                    |dest| rust::refine_horizontal_wiener(dest, src, pitch, width, height, bits_per_sample),
+                );
                 return;
             }
         }
@@ -83,6 +93,7 @@ pub fn refine_vertical_wiener<T: Pixel>(
     height: NonZeroUsize,
     bits_per_sample: NonZeroU8,
 ) {
+    debug_check_buffers("refine_vertical_wiener", dest, src, pitch, width, height);
     debug_assert!(
         bits_per_sample.get() as usize > (size_of::<T>() - 1) * 8
             && (bits_per_sample.get() as usize <= size_of::<T>() * 8)
@@ -91,10 +102,16 @@ pub fn refine_vertical_wiener<T: Pixel>(
     cfg_if! {
         if #[cfg(all(target_arch = "x86_64", not(feature = "no_simd")))] {
             if crate::util::has_avx2() {
-                // SAFETY: We check for AVX2 first
-                unsafe {
-                    avx2::refine_vertical_wiener(dest, src, pitch, width, height, bits_per_sample);
-                }
+                crate::util::run_simd_plane(
+                    "refine_vertical_wiener",
+                    dest,
+                    pitch,
+                    width,
+                    height,
+                    // SAFETY: We check for AVX2 first
+                    |dest| unsafe { avx2::refine_vertical_wiener(dest, src, pitch, width, height, bits_per_sample) },
+                    |dest| rust::refine_vertical_wiener(dest, src, pitch, width, height, bits_per_sample),
+                );
                 return;
             }
         }
@@ -102,3 +119,53 @@ pub fn refine_vertical_wiener<T: Pixel>(
 
     rust::refine_vertical_wiener(dest, src, pitch, width, height, bits_per_sample);
 }
+
+/// Performs vertical Wiener filtering by running [`refine_horizontal_wiener`] over the
+/// transposed plane and transposing the result back.
+///
+/// The output matches [`refine_vertical_wiener`], but the plane goes through two
+/// scratch buffers of `width * height` pixels. The direct vertical loop already
+/// filters whole rows at once with AVX2 and benchmarks several times faster,
+/// so this is only kept to compare against it.
+///
+/// # Parameters
+/// - `src`: Source image buffer
+/// - `dest`: Destination buffer for interpolated results
+/// - `pitch`: Number of pixels per row in both buffers
+/// - `width`: Width of the image in pixels
+/// - `height`: Height of the image in pixels
+/// - `bits_per_sample`: Bit depth of the pixel format for clamping
+#[cfg(any(test, feature = "bench"))]
+pub fn refine_vertical_wiener_transposed<T: Pixel>(
+    dest: &mut [T],
+    src: &[T],
+    pitch: NonZeroUsize,
+    width: NonZeroUsize,
+    height: NonZeroUsize,
+    bits_per_sample: NonZeroU8,
+) {
+    debug_check_buffers(
+        "refine_vertical_wiener_transposed",
+        dest,
+        src,
+        pitch,
+        width,
+        height,
+    );
+
+    // Columns of the plane become rows of `height` pixels. The AVX2 kernel works on
+    // 16 pixels at a time and may run past the end of the last row, so leave room for it.
+    let len = width.get() * height.get() + 32;
+    let mut columns = vec![T::zero(); len];
+    transpose_block(&mut columns, height, src, pitch, width, height);
+    let mut refined = vec![T::zero(); len];
+    refine_horizontal_wiener(
+        &mut refined,
+        &columns,
+        height,
+        height,
+        width,
+        bits_per_sample,
+    );
+    transpose_block(dest, pitch, &refined, height, height, width);
+}
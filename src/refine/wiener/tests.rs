@@ -4,6 +4,10 @@
 use std::num::{NonZeroU8, NonZeroUsize};
 
 use pastey::paste;
+use rand::{Rng, SeedableRng};
+use rand_xoshiro::Xoshiro128StarStar;
+
+use crate::util::Pixel;
 
 macro_rules! horizontal_tests {
     ($module:ident) => {
@@ -473,6 +477,39 @@ macro_rules! horizontal_tests {
                 assert_eq!(dest[0], 500, "First pixel should be bilinear interpolation: {} vs 500", dest[0]);
                 assert_eq!(dest[1], 800, "Last pixel should be copied: {} vs 800", dest[1]);
             }
+
+            #[test]
+            fn [<test_horizontal_wiener_near_max_ $module>]() {
+                // Alternating near-max values make the Wiener kernel overshoot the format max,
+                // on rows wide enough for both the SIMD loop and its scalar tail
+                let width = NonZeroUsize::new(40).unwrap();
+                let height = NonZeroUsize::new(2).unwrap();
+                let pitch = width;
+                for bits in [10u8, 12, 16] {
+                    let max = (1u32 << bits) - 1;
+                    let src: Vec<u16> = (0..80u32)
+                        .map(|i| if i % 3 == 0 { max - 7 } else { max } as u16)
+                        .collect();
+                    let mut dest = vec![0u16; 80];
+                    let bits_per_sample = NonZeroU8::new(bits).unwrap();
+
+                    verify_asm!($module, refine_horizontal_wiener(&mut dest, &src, pitch, width, height, bits_per_sample));
+
+                    for row in 0..2 {
+                        let src_row = &src[row * 40..][..40];
+                        let dest_row = &dest[row * 40..][..40];
+                        assert!(dest_row.iter().all(|&p| u32::from(p) <= max), "{bits}-bit: {dest_row:?}");
+                        // Edge pixels are rounded averages of their two neighbors
+                        for i in [0, 1, 36, 37, 38] {
+                            let expected = (u32::from(src_row[i]) + u32::from(src_row[i + 1])).div_ceil(2);
+                            assert_eq!(u32::from(dest_row[i]), expected, "{bits}-bit edge {i}");
+                        }
+                        assert_eq!(dest_row[39], src_row[39], "{bits}-bit last pixel");
+                        // The core overshoots right before each dip, so it must be clamped
+                        assert!(dest_row[2..36].iter().any(|&p| u32::from(p) == max), "{bits}-bit core");
+                    }
+                }
+            }
         }
     };
 }
@@ -1067,10 +1104,80 @@ macro_rules! vertical_tests {
                     assert_eq!(dest_last_row, src_last_row, "Last row should be copied directly at position {}: {} vs {}", i, dest_last_row, src_last_row);
                 }
             }
+
+            #[test]
+            fn [<test_vertical_wiener_near_max_ $module>]() {
+                // Alternating near-max rows make the Wiener kernel overshoot the format max,
+                // on rows wide enough for both the SIMD loop and its scalar tail
+                let width = NonZeroUsize::new(40).unwrap();
+                let height = NonZeroUsize::new(9).unwrap();
+                let pitch = width;
+                for bits in [10u8, 12, 16] {
+                    let max = (1u32 << bits) - 1;
+                    let src: Vec<u16> = (0..360u32)
+                        .map(|i| if (i / 40) % 3 == 0 { max - 7 } else { max } as u16)
+                        .collect();
+                    let mut dest = vec![0u16; 360];
+                    let bits_per_sample = NonZeroU8::new(bits).unwrap();
+
+                    verify_asm!($module, refine_vertical_wiener(&mut dest, &src, pitch, width, height, bits_per_sample));
+
+                    assert!(dest.iter().all(|&p| u32::from(p) <= max), "{bits}-bit: {dest:?}");
+                    // Edge rows are rounded averages of their two neighbors
+                    for row in [0, 1, 5, 6, 7] {
+                        for i in 0..40 {
+                            let expected = (u32::from(src[row * 40 + i]) + u32::from(src[(row + 1) * 40 + i])).div_ceil(2);
+                            assert_eq!(u32::from(dest[row * 40 + i]), expected, "{bits}-bit edge row {row}");
+                        }
+                    }
+                    assert_eq!(dest[320..], src[320..], "{bits}-bit last row");
+                    // The core overshoots right before each dip, so it must be clamped
+                    assert!(dest[80..200].iter().any(|&p| u32::from(p) == max), "{bits}-bit core");
+                }
+            }
         }
     };
 }
 
+fn assert_transposed_matches_vertical<T: Pixel + std::fmt::Debug>(bits: u8) {
+    let mut rng = Xoshiro128StarStar::from_seed(*b"deadbeeflolcakes");
+    let bits_per_sample = NonZeroU8::new(bits).unwrap();
+    for (width, height) in [(64, 64), (37, 23), (9, 40), (40, 5)] {
+        let pitch = NonZeroUsize::new(width + 7).unwrap();
+        let width = NonZeroUsize::new(width).unwrap();
+        let height = NonZeroUsize::new(height).unwrap();
+        let src: Vec<T> = std::iter::repeat_with(|| {
+            T::from_u32_or_max_value(rng.random_range(0..(1u32 << bits)))
+        })
+        .take(pitch.get() * height.get())
+        .collect();
+
+        let mut direct = vec![T::zero(); src.len()];
+        let mut transposed = direct.clone();
+        super::refine_vertical_wiener(&mut direct, &src, pitch, width, height, bits_per_sample);
+        super::refine_vertical_wiener_transposed(
+            &mut transposed,
+            &src,
+            pitch,
+            width,
+            height,
+            bits_per_sample,
+        );
+        assert_eq!(transposed, direct, "{width}x{height}");
+    }
+}
+
+#[test]
+fn vertical_wiener_transposed_matches_direct_u8() {
+    assert_transposed_matches_vertical::<u8>(8);
+}
+
+#[test]
+fn vertical_wiener_transposed_matches_direct_u16() {
+    assert_transposed_matches_vertical::<u16>(10);
+    assert_transposed_matches_vertical::<u16>(16);
+}
+
 horizontal_tests!(rust);
 vertical_tests!(rust);
 
@@ -0,0 +1,231 @@
+//! Extraction of a single hierarchical level of a super clip, to inspect the reduced frames
+
+use std::num::{NonZeroU8, NonZeroUsize};
+
+use anyhow::{Result, anyhow, bail};
+use vapoursynth::{
+    core::CoreRef,
+    format::{ColorFamily, Format},
+    frame::{FrameRef, FrameRefMut},
+    node::Node,
+    plugins::{Filter, FrameContext},
+    prelude::API,
+    video_info::{Property, Resolution, VideoInfo},
+};
+
+use crate::{
+    error::{ZooMvError, parse_param},
+    mv_plane::SuperPlaneLayout,
+    params::Subpel,
+    util::{Pixel, check_clip_frame, plane_with_padding, plane_with_padding_mut, vs_bitblt},
+};
+
+/// Outputs the luma of one level of a super clip from `Super` as a GRAY clip
+/// at the resolution of that level, without its padding.
+///
+/// For `pel` above 1, this is the full-pel window of the level.
+#[derive(Debug)]
+pub struct SuperLevel<'core> {
+    /// Super clip from `Super`
+    super_: Node<'core>,
+    /// GRAY format with the sample type and bit depth of the super clip
+    format: Format<'core>,
+    /// Level to extract, 0 being the full resolution frame
+    level: usize,
+    width: NonZeroUsize,
+    height: NonZeroUsize,
+    pel: Subpel,
+    hpad: usize,
+    vpad: usize,
+    x_ratio_uv: NonZeroU8,
+    y_ratio_uv: NonZeroU8,
+    /// Resolution of the extracted level
+    level_resolution: Resolution,
+}
+
+impl<'core> SuperLevel<'core> {
+    pub fn new(core: CoreRef<'core>, super_: Node<'core>, level: Option<i64>) -> Result<Self> {
+        let info = super_.info();
+        let super_format = info.format;
+        let Property::Constant(super_resolution) = info.resolution else {
+            bail!("SuperLevel: super clip must have a constant resolution");
+        };
+
+        let first = super_.get_frame(0).map_err(|e| {
+            anyhow!(
+                "SuperLevel: failed to retrieve first frame from super clip. Error message: {e}"
+            )
+        })?;
+        let super_props = first.props();
+        let super_props_err = ZooMvError::IncompatibleSuper {
+            filter: "SuperLevel",
+            reason: "required properties not found in first frame of super clip. Maybe clip \
+                     didn't come from mv.Super? Was the first frame trimmed away?",
+        };
+        let super_props_err2 = ZooMvError::IncompatibleSuper {
+            filter: "SuperLevel",
+            reason: "parameters from super clip appear to be wrong.",
+        };
+        let get_prop = |key: &str| -> Result<usize> {
+            let value = super_props.get_int(key).map_err(|_| super_props_err)?;
+            Ok(usize::try_from(value).map_err(|_| super_props_err2)?)
+        };
+        let height = NonZeroUsize::new(get_prop("Super_height")?).ok_or(super_props_err2)?;
+        let hpad = get_prop("Super_hpad")?;
+        let vpad = get_prop("Super_vpad")?;
+        let pel = Subpel::try_from(get_prop("Super_pel")? as i64).map_err(|_| super_props_err2)?;
+        let levels = get_prop("Super_levels")?;
+        let width = super_resolution
+            .width
+            .checked_sub(2 * hpad)
+            .and_then(NonZeroUsize::new)
+            .ok_or(super_props_err2)?;
+
+        let level: usize = parse_param(
+            "SuperLevel",
+            "level",
+            level,
+            levels.saturating_sub(1),
+            "at least 0",
+        )?;
+        if level >= levels {
+            bail!(ZooMvError::InvalidParam {
+                filter: "SuperLevel",
+                name: "level",
+                value: level as i64,
+                expected: "less than the number of levels of the super clip",
+            });
+        }
+
+        let format = core
+            .register_format(
+                ColorFamily::Gray,
+                super_format.sample_type(),
+                super_format.bits_per_sample(),
+                0,
+                0,
+            )
+            .ok_or_else(|| anyhow!("SuperLevel: failed to register the output format"))?;
+        // The luma dimensions of each level are rounded so that the chroma ones stay aligned
+        let x_ratio_uv =
+            NonZeroU8::new(1 << super_format.sub_sampling_w()).ok_or(super_props_err2)?;
+        let y_ratio_uv =
+            NonZeroU8::new(1 << super_format.sub_sampling_h()).ok_or(super_props_err2)?;
+        // The pitch does not change the dimensions of the level
+        let layout = SuperPlaneLayout::new(
+            false,
+            width,
+            height,
+            level,
+            pel,
+            hpad,
+            vpad,
+            x_ratio_uv,
+            y_ratio_uv,
+            NonZeroUsize::MIN,
+        );
+
+        Ok(Self {
+            super_,
+            format,
+            level,
+            width,
+            height,
+            pel,
+            hpad,
+            vpad,
+            x_ratio_uv,
+            y_ratio_uv,
+            level_resolution: Resolution {
+                width: layout.width.get(),
+                height: layout.height.get(),
+            },
+        })
+    }
+
+    fn get_frame_internal<T: Pixel>(
+        &self,
+        core: CoreRef<'core>,
+        context: FrameContext,
+        n: usize,
+    ) -> Result<FrameRef<'core>> {
+        let super_frame = self
+            .super_
+            .get_frame_filter(context, n)
+            .ok_or_else(|| anyhow!("SuperLevel: get_frame_filter super past end of video"))?;
+        check_clip_frame("SuperLevel", "super", &self.super_, n, &super_frame)?;
+
+        let src = plane_with_padding::<T>(&super_frame, 0)?;
+        // SAFETY: stride must be at least width and non-zero
+        let src_pitch =
+            unsafe { NonZeroUsize::new_unchecked(super_frame.stride(0) / size_of::<T>()) };
+        let layout = SuperPlaneLayout::new(
+            false,
+            self.width,
+            self.height,
+            self.level,
+            self.pel,
+            self.hpad,
+            self.vpad,
+            self.x_ratio_uv,
+            self.y_ratio_uv,
+            src_pitch,
+        );
+
+        // SAFETY: The whole plane is copied from the super frame before returning
+        let mut dest = unsafe {
+            FrameRefMut::new_uninitialized(
+                core,
+                Some(&super_frame),
+                self.format,
+                self.level_resolution,
+            )
+        };
+        // SAFETY: stride must be at least width and non-zero
+        let dest_pitch = unsafe { NonZeroUsize::new_unchecked(dest.stride(0) / size_of::<T>()) };
+        vs_bitblt(
+            plane_with_padding_mut::<T>(&mut dest, 0)?,
+            dest_pitch,
+            &src[layout.offset_data()..],
+            src_pitch,
+            layout.width,
+            layout.height,
+        )?;
+
+        Ok(dest.into())
+    }
+}
+
+impl<'core> Filter<'core> for SuperLevel<'core> {
+    fn video_info(&self, _api: API, _core: CoreRef<'core>) -> Vec<VideoInfo<'core>> {
+        let mut info = self.super_.info();
+        info.format = self.format;
+        info.resolution = Property::Constant(self.level_resolution);
+        vec![info]
+    }
+
+    fn get_frame_initial(
+        &self,
+        _api: API,
+        _core: CoreRef<'core>,
+        context: FrameContext,
+        n: usize,
+    ) -> Result<Option<FrameRef<'core>>> {
+        self.super_.request_frame_filter(context, n);
+        Ok(None)
+    }
+
+    fn get_frame(
+        &self,
+        _api: API,
+        core: CoreRef<'core>,
+        context: FrameContext,
+        n: usize,
+    ) -> Result<FrameRef<'core>> {
+        match self.format.bytes_per_sample() {
+            1 => self.get_frame_internal::<u8>(core, context, n),
+            2 => self.get_frame_internal::<u16>(core, context, n),
+            _ => bail!("SuperLevel: does not support clips greater than 16 bits"),
+        }
+    }
+}
@@ -1,3 +1,10 @@
+pub mod planes;
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+};
+
 use anyhow::Result;
 use vapoursynth::{
     format::{FormatID, PresetFormat},
@@ -24,6 +31,40 @@ clip.set_output()
     Ok(env)
 }
 
+/// The system allocator, counting the allocations made by each thread
+/// for [`count_allocations`].
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+// SAFETY: every call is forwarded to the system allocator
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        // The counter has no destructor, but is gone while its thread shuts down
+        let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+        // SAFETY: the caller upholds the contract of `GlobalAlloc::alloc`
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        // SAFETY: the caller upholds the contract of `GlobalAlloc::dealloc`
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Runs `f`, returning its result along with the number of heap allocations it made
+/// on the current thread, including reallocations.
+pub fn count_allocations<R>(f: impl FnOnce() -> R) -> (R, usize) {
+    let before = ALLOCATIONS.with(Cell::get);
+    let result = f();
+    (result, ALLOCATIONS.with(Cell::get) - before)
+}
+
 macro_rules! verify_asm {
     (ret $module:ident, $func:ident($($args:expr),* $(,)?)) => {{
         #[allow(unused_unsafe)]
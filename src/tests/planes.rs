@@ -0,0 +1,119 @@
+//! Small planes, superframe planes and block grids for the tests of the filters
+//! that fetch motion compensated pixels from superframes.
+
+#![allow(clippy::unwrap_used, reason = "allow in test files")]
+
+use std::num::{NonZeroU8, NonZeroUsize};
+
+use crate::{
+    mv_analyse::MVAnalysisData,
+    mv_plane::SuperPlaneLayout,
+    params::{MotionFlags, Subpel},
+};
+
+pub const WIDTH: usize = 32;
+pub const HEIGHT: usize = 16;
+pub const PAD: usize = 8;
+pub const BLK_SIZE: usize = 8;
+pub const OVERLAP: usize = 4;
+pub const BLK_X: usize = (WIDTH - OVERLAP) / (BLK_SIZE - OVERLAP);
+pub const BLK_Y: usize = (HEIGHT - OVERLAP) / (BLK_SIZE - OVERLAP);
+
+/// Analysis data of a 4:2:0 clip split into `blk_x` by `blk_y` non-overlapping 8x8 blocks,
+/// padded by [`PAD`].
+pub fn analysis_data(blk_x: usize, blk_y: usize, pel: Subpel) -> MVAnalysisData {
+    MVAnalysisData {
+        blk_size_x: NonZeroUsize::new(8).unwrap(),
+        blk_size_y: NonZeroUsize::new(8).unwrap(),
+        pel,
+        level_count: 1,
+        delta_frame: 1,
+        is_backward: true,
+        motion_flags: MotionFlags::empty(),
+        width: NonZeroUsize::new(blk_x * 8).unwrap(),
+        height: NonZeroUsize::new(blk_y * 8).unwrap(),
+        overlap_x: 0,
+        overlap_y: 0,
+        blk_x: NonZeroUsize::new(blk_x).unwrap(),
+        blk_y: NonZeroUsize::new(blk_y).unwrap(),
+        bits_per_sample: NonZeroU8::new(8).unwrap(),
+        y_ratio_uv: NonZeroU8::new(2).unwrap(),
+        x_ratio_uv: NonZeroU8::new(2).unwrap(),
+        h_padding: PAD,
+        v_padding: PAD,
+    }
+}
+
+/// Layout of a level 0 superframe plane of a 4:2:0 clip of `width` by `height` luma pixels,
+/// padded by [`PAD`], whose rows are no wider than the padded plane.
+pub fn super_layout(chroma: bool, width: usize, height: usize, pel: Subpel) -> SuperPlaneLayout {
+    let ratio = if chroma {
+        NonZeroU8::new(2).unwrap()
+    } else {
+        NonZeroU8::MIN
+    };
+    SuperPlaneLayout::new(
+        chroma,
+        NonZeroUsize::new(width).unwrap(),
+        NonZeroUsize::new(height).unwrap(),
+        0,
+        pel,
+        PAD,
+        PAD,
+        ratio,
+        ratio,
+        NonZeroUsize::new((width + 2 * PAD) / ratio.get() as usize).unwrap(),
+    )
+}
+
+/// Layout of the full-pel luma superframe plane of a [`WIDTH`] by [`HEIGHT`] clip.
+pub fn layout() -> SuperPlaneLayout {
+    super_layout(false, WIDTH, HEIGHT, Subpel::Full)
+}
+
+/// Builds a superframe plane with the given `layout` from `pixel(x, y)`,
+/// where `x` and `y` are in `pel` units relative to the top left non-padding pixel.
+pub fn super_plane(
+    layout: &SuperPlaneLayout,
+    pel: Subpel,
+    pixel: impl Fn(i32, i32) -> u8,
+) -> Vec<u8> {
+    let pel = u8::from(pel) as usize;
+    let window_size = layout.pitch.get() * (layout.height.get() + 2 * layout.vpad);
+    let mut padded = vec![0u8; layout.offset + pel * pel * window_size];
+    let windows = padded[layout.offset..].chunks_exact_mut(window_size);
+    for (window, data) in windows.enumerate() {
+        let (dx, dy) = ((window % pel) as i32, (window / pel) as i32);
+        for (row, line) in data.chunks_exact_mut(layout.pitch.get()).enumerate() {
+            for (column, value) in line.iter_mut().enumerate() {
+                let x = (column as i32 - layout.hpad as i32) * pel as i32 + dx;
+                let y = (row as i32 - layout.vpad as i32) * pel as i32 + dy;
+                *value = pixel(x, y);
+            }
+        }
+    }
+    padded
+}
+
+/// Builds a padded full-pel superframe plane with [`layout`] from `pixel(x, y)`.
+pub fn padded_plane(pixel: impl Fn(i32, i32) -> u8) -> Vec<u8> {
+    super_plane(&layout(), Subpel::Full, pixel)
+}
+
+/// Builds an unpadded `width` by `height` plane from `pixel(x, y)`.
+pub fn sized_plane(width: usize, height: usize, pixel: impl Fn(i32, i32) -> u8) -> Vec<u8> {
+    (0..height as i32)
+        .flat_map(|y| (0..width as i32).map(move |x| (x, y)))
+        .map(|(x, y)| pixel(x, y))
+        .collect()
+}
+
+/// Builds an unpadded [`WIDTH`] by [`HEIGHT`] source plane from `pixel(x, y)`.
+pub fn plane(pixel: impl Fn(i32, i32) -> u8) -> Vec<u8> {
+    sized_plane(WIDTH, HEIGHT, pixel)
+}
+
+/// A ramp that differs between every pair of neighboring pixels.
+pub fn pattern(x: i32, y: i32) -> u8 {
+    (20 + x * 3 + y * 2) as u8
+}
@@ -3,17 +3,24 @@ mod math;
 mod plane;
 mod sad;
 mod satd;
+mod simd;
 #[cfg(test)]
 mod tests;
+#[cfg(any(test, feature = "bench"))]
+mod transpose;
 
 use std::{fmt::Display, num::NonZeroUsize};
 
+use anyhow::{Result, bail};
 pub use luma::*;
 pub use math::*;
 use num_traits::PrimInt;
 pub use plane::*;
 pub use sad::*;
 pub use satd::*;
+pub(crate) use simd::*;
+#[cfg(any(test, feature = "bench"))]
+pub use transpose::*;
 use vapoursynth::prelude::Component;
 
 #[cfg(target_arch = "x86_64")]
@@ -22,6 +29,24 @@ cpufeatures::new!(cpuid_avx2, "avx2");
 #[cfg(target_arch = "x86_64")]
 pub use cpuid_avx2::get as has_avx2;
 
+/// Detects NEON support at runtime. NEON is mandatory on aarch64,
+/// but the check keeps the dispatch code consistent with the x86 paths.
+#[cfg(target_arch = "aarch64")]
+#[must_use]
+#[inline]
+pub fn has_neon() -> bool {
+    std::arch::is_aarch64_feature_detected!("neon")
+}
+
+/// AVX2 is only available on x86_64, so the scalar paths are always used on other targets.
+#[cfg(not(target_arch = "x86_64"))]
+#[allow(dead_code)]
+#[must_use]
+#[inline(always)]
+pub const fn has_avx2() -> bool {
+    false
+}
+
 pub trait Pixel:
     Component + Copy + Clone + Default + Send + Sync + PrimInt + Display + 'static
 {
@@ -70,6 +95,10 @@ where
 /// - `row_size`: Number of pixels to copy per row (the actual image width)
 /// - `height`: Number of rows to copy (the image height)
 ///
+/// # Errors
+/// Returns an error if `row_size` is larger than either stride, or if either buffer
+/// is too small to hold `height` rows.
+///
 /// # Performance
 /// - **Fast path**: When `src_stride == dest_stride == row_size`, uses single bulk copy
 /// - **Standard path**: Copies row by row when strides differ, handling padding correctly
@@ -80,12 +109,42 @@ pub fn vs_bitblt<T: Pixel>(
     src_stride: NonZeroUsize,
     row_size: NonZeroUsize,
     height: NonZeroUsize,
-) {
+) -> Result<()> {
     let height = height.get();
     let row_size = row_size.get();
     let src_stride = src_stride.get();
     let dest_stride = dest_stride.get();
 
+    if row_size > src_stride || row_size > dest_stride {
+        bail!(
+            "vs_bitblt: row size {row_size} is larger than the source stride {src_stride} or the \
+             destination stride {dest_stride}"
+        );
+    }
+    // The last row does not need to be followed by padding
+    let src_len = src_stride * (height - 1) + row_size;
+    let dest_len = dest_stride * (height - 1) + row_size;
+    if src.len() < src_len {
+        bail!(
+            "vs_bitblt: source has {} pixels, but {height} rows with a stride of {src_stride} \
+             need {src_len}",
+            src.len()
+        );
+    }
+    if dest.len() < dest_len {
+        bail!(
+            "vs_bitblt: destination has {} pixels, but {height} rows with a stride of \
+             {dest_stride} need {dest_len}",
+            dest.len()
+        );
+    }
+    // Planes are sometimes sliced from raw frame pointers, so make sure the borrow checker's
+    // guarantee that the two buffers are distinct actually holds.
+    debug_assert!(
+        !ranges_overlap(&dest[..dest_len], &src[..src_len]),
+        "vs_bitblt: source and destination overlap"
+    );
+
     if src_stride == dest_stride && src_stride == row_size {
         // Fast path: single copy when strides match row size
         dest[..row_size * height].copy_from_slice(&src[..row_size * height]);
@@ -98,4 +157,13 @@ pub fn vs_bitblt<T: Pixel>(
                 .copy_from_slice(&src[src_start..src_start + row_size]);
         }
     }
+
+    Ok(())
+}
+
+/// Checks whether two buffers share any memory.
+fn ranges_overlap<T>(a: &[T], b: &[T]) -> bool {
+    let a = a.as_ptr_range();
+    let b = b.as_ptr_range();
+    a.start < b.end && b.start < a.end
 }
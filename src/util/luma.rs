@@ -66,10 +66,12 @@ pub fn luma_sum<T: Pixel>(
     cfg_if! {
         if #[cfg(all(target_arch = "x86_64", not(feature = "no_simd")))] {
             if crate::util::has_avx2() {
-                // SAFETY: We check for AVX2 first
-                unsafe {
-                    return avx2::luma_sum(width, height, src, src_pitch);
-                }
+                return crate::util::run_simd_value(
+                    "luma_sum",
+                    // SAFETY: We check for AVX2 first
+                    || unsafe { avx2::luma_sum(width, height, src, src_pitch) },
+                    || rust::luma_sum(width, height, src, src_pitch),
+                );
             }
         }
     }
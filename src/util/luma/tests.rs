@@ -5,6 +5,32 @@
 use std::num::NonZeroUsize;
 
 use pastey::paste;
+use rand::{Rng, SeedableRng};
+use rand_xoshiro::Xoshiro128StarStar;
+
+const BLOCK_SIZES: [(usize, usize); 12] = [
+    (4, 4),
+    (8, 4),
+    (8, 8),
+    (16, 2),
+    (16, 8),
+    (16, 16),
+    (32, 16),
+    (32, 32),
+    (64, 32),
+    (64, 64),
+    (128, 64),
+    (128, 128),
+];
+
+/// Sums a `w`x`h` block one pixel at a time, independently of either implementation.
+fn naive_sum<T: Copy + Into<u64>>(src: &[T], pitch: usize, w: usize, h: usize) -> u64 {
+    src.chunks(pitch)
+        .take(h)
+        .flat_map(|row| &row[..w])
+        .map(|&pixel| pixel.into())
+        .sum()
+}
 
 macro_rules! luma_sum_tests {
     ($module:ident) => {
@@ -85,6 +111,35 @@ macro_rules! luma_sum_tests {
                 }
             }
 
+            #[test]
+            fn [<luma_sum_random_u8_ $module>]() {
+                let mut rng = Xoshiro128StarStar::from_seed(*b"deadbeeflolcakes");
+                for (w, h) in BLOCK_SIZES {
+                    // An odd pitch keeps rows from lining up with the vector width
+                    let pitch = w + 7;
+                    let src: Vec<u8> = (0..pitch * h).map(|_| rng.random()).collect();
+                    let width = NonZeroUsize::new(w).unwrap();
+                    let height = NonZeroUsize::new(h).unwrap();
+                    let src_pitch = NonZeroUsize::new(pitch).unwrap();
+                    let result = unsafe { verify_asm!(ret $module, luma_sum(width, height, &src, src_pitch)) };
+                    assert_eq!(result, naive_sum(&src, pitch, w, h), "failed at {w}x{h}");
+                }
+            }
+
+            #[test]
+            fn [<luma_sum_random_u16_ $module>]() {
+                let mut rng = Xoshiro128StarStar::from_seed(*b"deadbeeflolcakes");
+                for (w, h) in BLOCK_SIZES {
+                    let pitch = w + 7;
+                    let src: Vec<u16> = (0..pitch * h).map(|_| rng.random()).collect();
+                    let width = NonZeroUsize::new(w).unwrap();
+                    let height = NonZeroUsize::new(h).unwrap();
+                    let src_pitch = NonZeroUsize::new(pitch).unwrap();
+                    let result = unsafe { verify_asm!(ret $module, luma_sum(width, height, &src, src_pitch)) };
+                    assert_eq!(result, naive_sum(&src, pitch, w, h), "failed at {w}x{h}");
+                }
+            }
+
             #[test]
             fn [<luma_sum_sequential_u8_ $module>]() {
                 let w = 4;
@@ -1,50 +1,176 @@
-use core::slice;
+use core::{num::NonZeroUsize, slice};
 
 use anyhow::{Result, bail};
-use vapoursynth::frame::Frame;
+use vapoursynth::{format::FormatID, frame::Frame, node::Node, video_info::Property};
 
-use crate::util::Pixel;
+use crate::{error::ZooMvError, util::Pixel};
+
+/// Returns the number of `T` pixels in `plane` of `frame`, counting the padding
+/// at the end of every row, after checking that the plane exists and that `T`
+/// matches the frame's sample size.
+fn padded_plane_len<T: Pixel>(frame: &Frame, plane: usize) -> Result<usize> {
+    let format = frame.format();
+    if plane >= format.plane_count() {
+        bail!(
+            "plane {plane} requested from a {} frame with {} plane(s)",
+            format.name(),
+            format.plane_count()
+        );
+    }
+    if format.bytes_per_sample() as usize != size_of::<T>() {
+        bail!(
+            "plane {plane} of a {} frame has {}-byte samples, but {}-byte pixels were requested",
+            format.name(),
+            format.bytes_per_sample(),
+            size_of::<T>()
+        );
+    }
+
+    Ok(frame.stride(plane) * frame.height(plane) / size_of::<T>())
+}
 
 /// Gets a slice to the plane's data including its padding.
 /// The `plane` function in Vapoursynth fails if a plane has padding,
 /// but we need access to the padding, so we use this function to do so.
+///
+/// The slice starts at the top-left pixel of the plane and holds
+/// `stride / size_of::<T>()` pixels for each of the plane's `height` rows,
+/// so row `y` starts at `y * stride / size_of::<T>()`. The last row
+/// includes its padding as well.
+///
+/// Fails if `plane` is not present in the frame, or if `T` does not have
+/// the frame's sample size.
 pub fn plane_with_padding<'a, T: Pixel>(frame: &'a Frame, plane: usize) -> Result<&'a [T]> {
-    if frame.format().plane_count() < plane + 1 {
-        bail!("Tried to get plane not present in frame");
-    }
-
+    let len = padded_plane_len::<T>(frame, plane)?;
     let data_ptr = frame.data_ptr(plane);
-    let stride = frame.stride(plane);
-    let height = frame.height(plane);
-    let bytes_per_pixel = size_of::<T>();
 
-    // SAFETY: We know the layout of the plane
-    Ok(unsafe { slice::from_raw_parts(data_ptr.cast::<T>(), stride * height / bytes_per_pixel) })
+    // SAFETY: The plane exists, and Vapoursynth allocates `stride * height` bytes for it,
+    // aligned for its sample type, which we checked is `T`
+    Ok(unsafe { slice::from_raw_parts(data_ptr.cast::<T>(), len) })
 }
 
-/// Gets a slice to the plane's data including its padding.
-/// The `plane` function in Vapoursynth fails if a plane has padding,
-/// but we need access to the padding, so we use this function to do so.
+/// Gets a mutable slice to the plane's data including its padding.
+///
+/// The slice has the same layout as the one returned by [`plane_with_padding`],
+/// and fails in the same cases.
 pub fn plane_with_padding_mut<'a, T: Pixel>(
     frame: &'a mut Frame,
     plane: usize,
 ) -> Result<&'a mut [T]> {
-    if frame.format().plane_count() < plane + 1 {
-        bail!("Tried to get plane not present in frame");
+    let len = padded_plane_len::<T>(frame, plane)?;
+    let data_ptr = frame.data_ptr_mut(plane);
+
+    // SAFETY: The plane exists, and Vapoursynth allocates `stride * height` bytes for it,
+    // aligned for its sample type, which we checked is `T`
+    Ok(unsafe { slice::from_raw_parts_mut(data_ptr.cast::<T>(), len) })
+}
+
+/// A read-only view of a plane with its pitch, for row access without manual offset math.
+///
+/// `data` may include padding around the plane, as returned by [`plane_with_padding`].
+/// `width` and `height` describe the area of `data` that rows and blocks are read from.
+#[derive(Debug, Clone, Copy)]
+pub struct PlaneView<'a, T> {
+    data: &'a [T],
+    pitch: NonZeroUsize,
+    width: NonZeroUsize,
+    height: NonZeroUsize,
+}
+
+impl<'a, T: Pixel> PlaneView<'a, T> {
+    /// Creates a view of `height` rows of `width` pixels, `pitch` pixels apart.
+    ///
+    /// Fails if the rows do not fit in `data`.
+    pub fn new(
+        data: &'a [T],
+        pitch: NonZeroUsize,
+        width: NonZeroUsize,
+        height: NonZeroUsize,
+    ) -> Result<Self> {
+        if width > pitch {
+            bail!("plane width {width} is larger than its pitch {pitch}");
+        }
+        if (height.get() - 1) * pitch.get() + width.get() > data.len() {
+            bail!(
+                "{width}x{height} plane with pitch {pitch} does not fit in {} pixels",
+                data.len()
+            );
+        }
+        Ok(Self {
+            data,
+            pitch,
+            width,
+            height,
+        })
     }
 
-    let data_ptr = frame.data_ptr_mut(plane);
-    let stride = frame.stride(plane);
-    let height = frame.height(plane);
-    let bytes_per_pixel = size_of::<T>();
-
-    // SAFETY: We know the layout of the plane
-    Ok(unsafe {
-        slice::from_raw_parts_mut(data_ptr.cast::<T>(), stride * height / bytes_per_pixel)
-    })
+    /// Creates a view of a plane of `frame`, including its padding.
+    pub fn from_frame(frame: &'a Frame, plane: usize) -> Result<Self> {
+        let data = plane_with_padding(frame, plane)?;
+        // SAFETY: stride, width and height must be non-zero for planes present in the frame
+        unsafe {
+            Self::new(
+                data,
+                NonZeroUsize::new_unchecked(frame.stride(plane) / size_of::<T>()),
+                NonZeroUsize::new_unchecked(frame.width(plane)),
+                NonZeroUsize::new_unchecked(frame.height(plane)),
+            )
+        }
+    }
+
+    /// Gets the `width` pixels of row `y`.
+    ///
+    /// # Panics
+    /// In debug builds, if `y` is not less than the height of the view.
+    #[cfg(any(test, feature = "bench"))]
+    #[must_use]
+    pub fn row(&self, y: usize) -> &'a [T] {
+        debug_assert!(
+            y < self.height.get(),
+            "row {y} is outside of a plane of height {}",
+            self.height
+        );
+        &self.data[y * self.pitch.get()..][..self.width.get()]
+    }
+
+    /// Iterates over the rows of the view, from top to bottom.
+    #[cfg(any(test, feature = "bench"))]
+    pub fn rows(&self) -> impl Iterator<Item = &'a [T]> + use<'a, T> {
+        let width = self.width.get();
+        self.data
+            .chunks(self.pitch.get())
+            .take(self.height.get())
+            .map(move |row| &row[..width])
+    }
+
+    /// Gets the data starting at `offset`, for a block of `width` by `height` pixels
+    /// with the pitch of the view.
+    ///
+    /// Fails if the block does not fit in the rows of the view, e.g. because a motion vector
+    /// points outside of the padded plane.
+    pub fn block(&self, offset: usize, width: usize, height: usize) -> Result<&'a [T]> {
+        let (y, x) = (offset / self.pitch.get(), offset % self.pitch.get());
+        let fits_width = x
+            .checked_add(width)
+            .is_some_and(|right| right <= self.width.get());
+        let fits_height = y
+            .checked_add(height)
+            .is_some_and(|bottom| bottom <= self.height.get());
+        if !fits_width || !fits_height {
+            bail!(
+                "{width}x{height} reference block at offset {} does not fit in {}x{} plane with \
+                 pitch {}",
+                offset as isize,
+                self.width,
+                self.height,
+                self.pitch
+            );
+        }
+        Ok(&self.data[offset..])
+    }
 }
 
-/// Gets both immutable and mutable slices to the same plane's data including its padding.
+/// Gets both immutable and mutable slices to the same plane's data.
 /// This function allows safe access to both source and destination views of the same plane
 /// data without violating Rust's borrowing rules by using raw pointers internally.
 ///
@@ -55,26 +181,126 @@ pub fn plane_with_padding_mut<'a, T: Pixel>(
 /// The caller must ensure that the returned slices do not overlap in their actual usage.
 /// While both slices reference the same underlying memory, they should be used to access
 /// different logical regions (e.g., source data vs destination data within the plane).
-pub unsafe fn plane_with_padding_split<'a, T: Pixel>(
-    frame: &'a mut Frame,
-    plane: usize,
-) -> Result<(&'a [T], &'a mut [T])> {
-    if frame.format().plane_count() < plane + 1 {
-        bail!("Tried to get plane not present in frame");
-    }
-
-    let data_ptr = frame.data_ptr_mut(plane);
-    let stride = frame.stride(plane);
-    let height = frame.height(plane);
-    let bytes_per_pixel = size_of::<T>();
-    let total_len = stride * height / bytes_per_pixel;
+pub unsafe fn split_plane<T: Pixel>(plane: &mut [T]) -> (&[T], &mut [T]) {
+    let data_ptr = plane.as_mut_ptr();
+    let total_len = plane.len();
 
     // SAFETY: We create two slices from the same memory region, but the caller
     // is responsible for ensuring they don't overlap in actual usage.
     // This is similar to how split_at_mut works, but for the same logical data.
     unsafe {
         let src_slice = slice::from_raw_parts(data_ptr as *const T, total_len);
-        let dest_slice = slice::from_raw_parts_mut(data_ptr.cast::<T>(), total_len);
-        Ok((src_slice, dest_slice))
+        let dest_slice = slice::from_raw_parts_mut(data_ptr, total_len);
+        (src_slice, dest_slice)
+    }
+}
+
+/// The padded planes a superframe is built in.
+///
+/// This is a VapourSynth frame when filtering, but plain buffers can be used instead
+/// to build a pyramid outside of VapourSynth.
+pub trait SuperPlanes<T: Pixel> {
+    /// Gets a mutable slice to the plane's data including its padding.
+    fn plane_mut(&mut self, plane: usize) -> Result<&mut [T]>;
+}
+
+impl<T: Pixel> SuperPlanes<T> for Frame<'_> {
+    fn plane_mut(&mut self, plane: usize) -> Result<&mut [T]> {
+        plane_with_padding_mut(self, plane)
+    }
+}
+
+impl<T: Pixel> SuperPlanes<T> for [Vec<T>] {
+    fn plane_mut(&mut self, plane: usize) -> Result<&mut [T]> {
+        match self.get_mut(plane) {
+            Some(data) => Ok(data),
+            None => bail!("Tried to get plane not present in frame"),
+        }
+    }
+}
+
+/// Gets the pitch of each of the three planes in pixels.
+/// Planes missing from the frame, such as the chroma planes of a GRAY clip,
+/// reuse the luma pitch so that they can be passed along without ever being indexed.
+#[must_use]
+pub fn plane_pitches<T: Pixel>(frame: &Frame) -> [NonZeroUsize; 3] {
+    let plane_count = frame.format().plane_count();
+    let pitch = |plane: usize| {
+        let plane = if plane < plane_count { plane } else { 0 };
+        // SAFETY: stride must be at least width and non-zero
+        unsafe { NonZeroUsize::new_unchecked(frame.stride(plane) / size_of::<T>()) }
+    };
+    [pitch(0), pitch(1), pitch(2)]
+}
+
+/// The format and resolution of a frame, or of the frames a clip declares it returns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameShape {
+    pub format: FormatID,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl FrameShape {
+    #[must_use]
+    pub fn of_frame(frame: &Frame) -> Self {
+        Self {
+            format: frame.format().id(),
+            width: frame.width(0),
+            height: frame.height(0),
+        }
+    }
+
+    /// Gets the shape declared by `node`, or `None` if its resolution is variable.
+    #[must_use]
+    pub fn of_clip(node: &Node) -> Option<Self> {
+        let info = node.info();
+        match info.resolution {
+            Property::Constant(resolution) => Some(Self {
+                format: info.format.id(),
+                width: resolution.width,
+                height: resolution.height,
+            }),
+            Property::Variable => None,
+        }
     }
 }
+
+/// Checks that frame `n` of `clip` has the format and resolution that the clip declares.
+///
+/// Filters compute their plane layouts from the clip's video info when they are created,
+/// but VapourSynth does not stop an upstream filter from returning frames that differ from it.
+/// Reading such a frame with the declared layout would silently produce garbage.
+pub fn check_frame_shape(
+    filter: &'static str,
+    clip: &'static str,
+    n: usize,
+    expected: Option<FrameShape>,
+    actual: FrameShape,
+) -> Result<(), ZooMvError> {
+    if expected != Some(actual) {
+        return Err(ZooMvError::FrameMismatch {
+            filter,
+            clip,
+            frame: n,
+        });
+    }
+    Ok(())
+}
+
+/// Checks frame `n` of `node` against the node's video info with [`check_frame_shape`].
+pub fn check_clip_frame(
+    filter: &'static str,
+    clip: &'static str,
+    node: &Node,
+    n: usize,
+    frame: &Frame,
+) -> Result<(), ZooMvError> {
+    check_frame_shape(
+        filter,
+        clip,
+        n,
+        FrameShape::of_clip(node),
+        FrameShape::of_frame(frame),
+    )
+}
@@ -1,3 +1,5 @@
+#[cfg(target_arch = "aarch64")]
+mod neon;
 mod rust;
 
 #[cfg(test)]
@@ -5,8 +7,34 @@ mod tests;
 
 use std::num::NonZeroUsize;
 
+use cfg_if::cfg_if;
+
 use crate::util::Pixel;
 
+/// Computes the sum of absolute differences between two `width` by `height` blocks.
+///
+/// `src` and `ref_` start at the top-left pixel of their block, and `src_pitch` and `ref_pitch`
+/// are the distances between rows in pixels, not bytes. The result is in units of the pixel values,
+/// so it grows with the bit depth of `T`.
+///
+/// # Panics
+///
+/// - If the block size is not one of the sizes supported by `Analyse`,
+///   from 2x2 up to 128x128
+/// - If either slice ends before the last pixel of its block
+///
+/// # Examples
+///
+/// ```
+/// use std::num::NonZeroUsize;
+///
+/// use vapoursynth_zoomvtools::get_sad;
+///
+/// let size = NonZeroUsize::new(4).unwrap();
+/// let src = vec![10u8; 16];
+/// let reference = vec![7u8; 16];
+/// assert_eq!(get_sad(size, size, &src, size, &reference, size), 16 * 3);
+/// ```
 #[must_use]
 pub fn get_sad<T: Pixel>(
     width: NonZeroUsize,
@@ -16,5 +44,66 @@ pub fn get_sad<T: Pixel>(
     ref_: &[T],
     ref_pitch: NonZeroUsize,
 ) -> u64 {
+    cfg_if! {
+        if #[cfg(all(target_arch = "aarch64", not(feature = "no_simd")))] {
+            if crate::util::has_neon() {
+                return crate::util::run_simd_value(
+                    "get_sad",
+                    // SAFETY: We check for NEON first
+                    || unsafe { neon::get_sad(width, height, src, src_pitch, ref_, ref_pitch) },
+                    || rust::get_sad(width, height, src, src_pitch, ref_, ref_pitch),
+                );
+            }
+        }
+    }
+
     rust::get_sad(width, height, src, src_pitch, ref_, ref_pitch)
 }
+
+/// Computes the same SAD as [`get_sad`], but may stop once it is above `threshold`.
+///
+/// The result is exact if it is at most `threshold`. Otherwise, it is only guaranteed to be
+/// above `threshold`, as a partial sum of the block.
+///
+/// Blocks at least 32 pixels wide are summed in strips of a quarter of their width in rows,
+/// checking the threshold after each one, so that the largest blocks can skip most of their rows.
+/// Smaller blocks are summed at once.
+///
+/// # Panics
+///
+/// Under the same conditions as [`get_sad`].
+#[must_use]
+pub fn get_sad_with_threshold<T: Pixel>(
+    width: NonZeroUsize,
+    height: NonZeroUsize,
+    src: &[T],
+    src_pitch: NonZeroUsize,
+    ref_: &[T],
+    ref_pitch: NonZeroUsize,
+    threshold: u64,
+) -> u64 {
+    // Every block size of `Analyse` that is at least 32 wide has a height that is a multiple
+    // of this, and the strips themselves are supported block sizes.
+    let strip = width.get() / 4;
+    if width.get() < 32 || height.get() <= strip {
+        return get_sad(width, height, src, src_pitch, ref_, ref_pitch);
+    }
+    // SAFETY: the width is at least 32
+    let strip_height = unsafe { NonZeroUsize::new_unchecked(strip) };
+
+    let mut sad = 0;
+    for y in (0..height.get()).step_by(strip) {
+        sad += get_sad(
+            width,
+            strip_height,
+            &src[y * src_pitch.get()..],
+            src_pitch,
+            &ref_[y * ref_pitch.get()..],
+            ref_pitch,
+        );
+        if sad > threshold {
+            break;
+        }
+    }
+    sad
+}
@@ -0,0 +1,122 @@
+#![allow(clippy::undocumented_unsafe_blocks)]
+#![allow(unsafe_op_in_unsafe_fn)]
+
+use std::{arch::aarch64::*, num::NonZeroUsize};
+
+use crate::util::Pixel;
+
+#[must_use]
+#[target_feature(enable = "neon")]
+pub(super) unsafe fn get_sad<T: Pixel>(
+    width: NonZeroUsize,
+    height: NonZeroUsize,
+    src: &[T],
+    src_pitch: NonZeroUsize,
+    ref_: &[T],
+    ref_pitch: NonZeroUsize,
+) -> u64 {
+    // Check the array bounds once before reading through raw pointers.
+    assert!(src.len() >= src_pitch.get() * (height.get() - 1) + width.get());
+    assert!(ref_.len() >= ref_pitch.get() * (height.get() - 1) + width.get());
+
+    match size_of::<T>() {
+        1 => get_sad_u8(
+            width.get(),
+            height.get(),
+            src.as_ptr().cast(),
+            src_pitch.get(),
+            ref_.as_ptr().cast(),
+            ref_pitch.get(),
+        ),
+        2 => get_sad_u16(
+            width.get(),
+            height.get(),
+            src.as_ptr().cast(),
+            src_pitch.get(),
+            ref_.as_ptr().cast(),
+            ref_pitch.get(),
+        ),
+        _ => unreachable!(),
+    }
+}
+
+#[must_use]
+#[target_feature(enable = "neon")]
+unsafe fn get_sad_u8(
+    width: usize,
+    height: usize,
+    src: *const u8,
+    src_pitch: usize,
+    ref_: *const u8,
+    ref_pitch: usize,
+) -> u64 {
+    let mut acc = vdupq_n_u32(0);
+    let mut remainder = 0u64;
+
+    for y in 0..height {
+        let src_row = src.add(y * src_pitch);
+        let ref_row = ref_.add(y * ref_pitch);
+
+        // A single row of at most 128 pixels cannot overflow the 16-bit lanes,
+        // so widen to 32 bits once per row.
+        let mut row_acc = vdupq_n_u16(0);
+        let mut x = 0;
+        while x + 16 <= width {
+            let s = vld1q_u8(src_row.add(x));
+            let r = vld1q_u8(ref_row.add(x));
+            row_acc = vpadalq_u8(row_acc, vabdq_u8(s, r));
+            x += 16;
+        }
+        if x + 8 <= width {
+            let s = vld1_u8(src_row.add(x));
+            let r = vld1_u8(ref_row.add(x));
+            row_acc = vaddq_u16(row_acc, vabdl_u8(s, r));
+            x += 8;
+        }
+        acc = vpadalq_u16(acc, row_acc);
+
+        // Handle remaining pixels with scalar code
+        while x < width {
+            remainder += (*src_row.add(x)).abs_diff(*ref_row.add(x)) as u64;
+            x += 1;
+        }
+    }
+
+    vaddlvq_u32(acc) + remainder
+}
+
+#[must_use]
+#[target_feature(enable = "neon")]
+unsafe fn get_sad_u16(
+    width: usize,
+    height: usize,
+    src: *const u16,
+    src_pitch: usize,
+    ref_: *const u16,
+    ref_pitch: usize,
+) -> u64 {
+    let mut acc = vdupq_n_u32(0);
+    let mut remainder = 0u64;
+
+    for y in 0..height {
+        let src_row = src.add(y * src_pitch);
+        let ref_row = ref_.add(y * ref_pitch);
+
+        let mut x = 0;
+        while x + 8 <= width {
+            let s = vld1q_u16(src_row.add(x));
+            let r = vld1q_u16(ref_row.add(x));
+            // Even a 128x128 block of maximum differences fits in the 32-bit lanes
+            acc = vpadalq_u16(acc, vabdq_u16(s, r));
+            x += 8;
+        }
+
+        // Handle remaining pixels with scalar code
+        while x < width {
+            remainder += (*src_row.add(x)).abs_diff(*ref_row.add(x)) as u64;
+            x += 1;
+        }
+    }
+
+    vaddlvq_u32(acc) + remainder
+}
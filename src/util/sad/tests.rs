@@ -4,6 +4,10 @@
 use std::num::NonZeroUsize;
 
 use pastey::paste;
+use rand::{Rng, SeedableRng};
+use rand_xoshiro::Xoshiro128StarStar;
+
+use crate::util::Pixel;
 
 const SAD_SIZES: &[(usize, usize)] = &[
     (2, 2),
@@ -202,8 +206,159 @@ macro_rules! get_sad_tests {
                 let result = verify_asm!(ret $module, get_sad(width, height, &src, pitch, &ref_, pitch));
                 assert_eq!(result, 16000);
             }
+
+            #[test]
+            fn [<sad_mixed_pattern_u8_ $module>]() {
+                for &(w, h) in SAD_SIZES {
+                    // Differences of both signs, so SIMD absolute differences are exercised
+                    let src: Vec<u8> = (0..w * h).map(|i| (i * 37 % 251) as u8).collect();
+                    let ref_: Vec<u8> = (0..w * h).map(|i| (i * 91 % 241) as u8).collect();
+                    let expected: u64 = src.iter().zip(&ref_).map(|(&s, &r)| s.abs_diff(r) as u64).sum();
+                    let width = NonZeroUsize::new(w).unwrap();
+                    let height = NonZeroUsize::new(h).unwrap();
+                    let pitch = NonZeroUsize::new(w).unwrap();
+                    let result = verify_asm!(ret $module, get_sad(width, height, &src, pitch, &ref_, pitch));
+                    assert_eq!(result, expected, "failed at {w}x{h}");
+                }
+            }
+
+            #[test]
+            fn [<sad_mixed_pattern_u16_ $module>]() {
+                for &(w, h) in SAD_SIZES {
+                    let src: Vec<u16> = (0..w * h).map(|i| (i * 1237 % 65521) as u16).collect();
+                    let ref_: Vec<u16> = (0..w * h).map(|i| (i * 4091 % 65497) as u16).collect();
+                    let expected: u64 = src.iter().zip(&ref_).map(|(&s, &r)| s.abs_diff(r) as u64).sum();
+                    let width = NonZeroUsize::new(w).unwrap();
+                    let height = NonZeroUsize::new(h).unwrap();
+                    let pitch = NonZeroUsize::new(w).unwrap();
+                    let result = verify_asm!(ret $module, get_sad(width, height, &src, pitch, &ref_, pitch));
+                    assert_eq!(result, expected, "failed at {w}x{h}");
+                }
+            }
         }
     };
 }
 
 get_sad_tests!(rust);
+
+#[cfg(all(target_arch = "aarch64", target_feature = "neon"))]
+get_sad_tests!(neon);
+
+/// The SAD with every difference widened to `i64`, as the scalar path used to compute it.
+fn naive_sad<T: Pixel>(
+    w: usize,
+    h: usize,
+    src: &[T],
+    src_pitch: usize,
+    ref_: &[T],
+    ref_pitch: usize,
+) -> u64 {
+    (0..h)
+        .flat_map(|y| (0..w).map(move |x| (y, x)))
+        .map(|(y, x)| {
+            let s = src[y * src_pitch + x].to_i64().unwrap();
+            let r = ref_[y * ref_pitch + x].to_i64().unwrap();
+            (s - r).unsigned_abs()
+        })
+        .sum()
+}
+
+#[test]
+fn scalar_sad_max_u8_does_not_overflow() {
+    let (w, h) = (128, 128);
+    let src = vec![u8::MAX; w * h];
+    let ref_ = vec![0u8; w * h];
+    let size = NonZeroUsize::new(w).unwrap();
+    let sad = super::rust::get_sad(size, size, &src, size, &ref_, size);
+    assert_eq!(sad, 255 * 128 * 128);
+    assert_eq!(
+        super::rust::get_sad(size, size, &ref_, size, &src, size),
+        sad
+    );
+}
+
+#[test]
+fn scalar_sad_matches_naive_sum() {
+    let mut rng = Xoshiro128StarStar::from_seed(*b"deadbeeflolcakes");
+    for &(w, h) in SAD_SIZES {
+        let width = NonZeroUsize::new(w).unwrap();
+        let height = NonZeroUsize::new(h).unwrap();
+        // Padded rows, as in a real plane
+        let src_pitch = NonZeroUsize::new(w + 3).unwrap();
+        let ref_pitch = NonZeroUsize::new(w + 5).unwrap();
+
+        let src: Vec<u8> = std::iter::repeat_with(|| rng.random())
+            .take(src_pitch.get() * h)
+            .collect();
+        let ref_: Vec<u8> = std::iter::repeat_with(|| rng.random())
+            .take(ref_pitch.get() * h)
+            .collect();
+        assert_eq!(
+            super::rust::get_sad(width, height, &src, src_pitch, &ref_, ref_pitch),
+            naive_sad(w, h, &src, src_pitch.get(), &ref_, ref_pitch.get()),
+            "u8 {w}x{h}"
+        );
+
+        let src: Vec<u16> = std::iter::repeat_with(|| rng.random())
+            .take(src_pitch.get() * h)
+            .collect();
+        let ref_: Vec<u16> = std::iter::repeat_with(|| rng.random())
+            .take(ref_pitch.get() * h)
+            .collect();
+        assert_eq!(
+            super::rust::get_sad(width, height, &src, src_pitch, &ref_, ref_pitch),
+            naive_sad(w, h, &src, src_pitch.get(), &ref_, ref_pitch.get()),
+            "u16 {w}x{h}"
+        );
+    }
+}
+
+#[test]
+fn thresholded_sad_is_exact_up_to_threshold() {
+    let mut rng = Xoshiro128StarStar::from_seed(*b"deadbeeflolcakes");
+    for &(w, h) in SAD_SIZES {
+        let width = NonZeroUsize::new(w).unwrap();
+        let height = NonZeroUsize::new(h).unwrap();
+        let pitch = NonZeroUsize::new(w + 3).unwrap();
+        let src: Vec<u16> = std::iter::repeat_with(|| rng.random_range(0..1024))
+            .take(pitch.get() * h)
+            .collect();
+        let ref_: Vec<u16> = std::iter::repeat_with(|| rng.random_range(0..1024))
+            .take(pitch.get() * h)
+            .collect();
+
+        let sad = super::get_sad(width, height, &src, pitch, &ref_, pitch);
+        for threshold in [sad, sad + 1, u64::MAX] {
+            assert_eq!(
+                super::get_sad_with_threshold(width, height, &src, pitch, &ref_, pitch, threshold),
+                sad,
+                "{w}x{h} with threshold {threshold}"
+            );
+        }
+        // Below the SAD, the result may be partial but must still be above the threshold
+        let partial =
+            super::get_sad_with_threshold(width, height, &src, pitch, &ref_, pitch, sad / 2);
+        assert!(partial > sad / 2 && partial <= sad, "{w}x{h}");
+    }
+}
+
+#[test]
+fn thresholded_sad_stops_after_first_strip() {
+    // The first 32 rows differ by 1, and the rest by 255
+    let size = NonZeroUsize::new(128).unwrap();
+    let src = vec![0u8; 128 * 128];
+    let ref_: Vec<u8> = (0..128 * 128)
+        .map(|i| if i < 128 * 32 { 1 } else { 255 })
+        .collect();
+
+    let full = super::get_sad(size, size, &src, size, &ref_, size);
+    assert_eq!(full, 128 * 32 + 255 * 128 * 96);
+    assert_eq!(
+        super::get_sad_with_threshold(size, size, &src, size, &ref_, size, 100),
+        128 * 32
+    );
+    assert_eq!(
+        super::get_sad_with_threshold(size, size, &src, size, &ref_, size, full),
+        full
+    );
+}
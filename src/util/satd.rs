@@ -7,6 +7,43 @@ use std::num::NonZeroUsize;
 
 use crate::util::Pixel;
 
+/// Computes the sum of absolute transformed differences between two `width` by `height` blocks,
+/// which applies a Hadamard transform to the differences before summing them.
+///
+/// Like [`get_sad`](crate::util::get_sad), pitches are in pixels
+/// and the result is in units of the pixel values.
+/// The transform is unnormalized, so it is not directly comparable to a SAD.
+///
+/// # Panics
+///
+/// - If the block size is not one of 4x4, 8x4, 8x8, 16x8, 16x16, 32x16, 32x32, 64x32, 64x64,
+///   128x64 or 128x128
+/// - If either slice ends before the last pixel of its block
+///
+/// # Examples
+///
+/// ```
+/// use std::num::NonZeroUsize;
+///
+/// use vapoursynth_zoomvtools::get_satd;
+///
+/// let width = NonZeroUsize::new(8).unwrap();
+/// let height = NonZeroUsize::new(4).unwrap();
+/// let src = vec![100u8; 32];
+/// // The same block, one row down in a buffer with an unused column
+/// let pitch = NonZeroUsize::new(9).unwrap();
+/// let mut reference = vec![0u8; 9 * 5];
+/// for row in reference.chunks_exact_mut(9).skip(1) {
+///     row[..8].fill(100);
+/// }
+/// assert_eq!(
+///     get_satd(width, height, &src, width, &reference[9..], pitch),
+///     0
+/// );
+///
+/// let brighter = vec![101u8; 32];
+/// assert!(get_satd(width, height, &src, width, &brighter, width) > 0);
+/// ```
 #[must_use]
 pub fn get_satd<T: Pixel>(
     width: NonZeroUsize,
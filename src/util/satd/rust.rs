@@ -4,7 +4,7 @@ use std::{
     ops::{Add, AddAssign, BitAnd, BitXor, Mul, Shl, Shr, Sub},
 };
 
-use num_traits::{One, PrimInt, WrappingAdd};
+use num_traits::{One, PrimInt, WrappingAdd, WrappingSub};
 
 use crate::util::Pixel;
 
@@ -80,7 +80,7 @@ fn get_satd_impl<T: Pixel, const WIDTH: usize, const HEIGHT: usize>(
 fn satd_4x4<
     T: Pixel,
     SUM1: PrimInt + Default,
-    SUM2: PrimInt + Default + One + AddAssign<SUM2> + WrappingAdd + FromDiff + Into<u64>,
+    SUM2: PrimInt + Default + One + AddAssign<SUM2> + WrappingAdd + WrappingSub + FromDiff + Into<u64>,
 >(
     src: &[T],
     src_pitch: NonZeroUsize,
@@ -108,12 +108,16 @@ fn satd_4x4<
 
         a[0] = diff(0);
         a[1] = diff(1);
-        b[0] = (a[0] + a[1]) + ((a[0] - a[1]) << bits_per_sum);
+        b[0] = a[0]
+            .wrapping_add(&a[1])
+            .wrapping_add(&(a[0].wrapping_sub(&a[1]) << bits_per_sum));
         a[2] = diff(2);
         a[3] = diff(3);
-        b[1] = (a[2] + a[3]) + ((a[2] - a[3]) << bits_per_sum);
-        tmp[i][0] = b[0] + b[1];
-        tmp[i][1] = b[0] - b[1];
+        b[1] = a[2]
+            .wrapping_add(&a[3])
+            .wrapping_add(&(a[2].wrapping_sub(&a[3]) << bits_per_sum));
+        tmp[i][0] = b[0].wrapping_add(&b[1]);
+        tmp[i][1] = b[0].wrapping_sub(&b[1]);
     }
 
     let one = SUM2::one();
@@ -148,6 +152,7 @@ fn satd_8x4<
         + Shr<usize, Output = SUM2>
         + AddAssign<SUM2>
         + WrappingAdd
+        + WrappingSub
         + Into<u64>
         + FromDiff,
 >(
@@ -199,8 +204,11 @@ fn satd_8x4<
 
 /// in: a pseudo-simd number of the form x+(y<<bits_per_sum)
 /// return: abs(x)+(abs(y)<<bits_per_sum)
+///
+/// A negative `x` borrows from `y` when packed, so `y` must be greater than the minimum
+/// of a signed `SUM` in that case. Hadamard sums of pixel differences are far from it.
 #[must_use]
-fn abs2<SUM: PrimInt, SUM2>(a: SUM2) -> SUM2
+pub(super) fn abs2<SUM: PrimInt, SUM2>(a: SUM2) -> SUM2
 where
     SUM2: PrimInt
         + One
@@ -237,7 +245,8 @@ impl FromDiff for u64 {
     }
 }
 
-fn hadamard4<SUM: Copy + Add<SUM, Output = SUM> + Sub<SUM, Output = SUM>>(
+/// Negative differences are stored as wrapped unsigned values, so the butterflies must wrap too.
+fn hadamard4<SUM: Copy + WrappingAdd + WrappingSub>(
     dest0: &mut SUM,
     dest1: &mut SUM,
     dest2: &mut SUM,
@@ -247,12 +256,12 @@ fn hadamard4<SUM: Copy + Add<SUM, Output = SUM> + Sub<SUM, Output = SUM>>(
     src2: SUM,
     src3: SUM,
 ) {
-    let temp0: SUM = src0 + src1;
-    let temp1: SUM = src0 - src1;
-    let temp2: SUM = src2 + src3;
-    let temp3: SUM = src2 - src3;
-    *dest0 = temp0 + temp2;
-    *dest2 = temp0 - temp2;
-    *dest1 = temp1 + temp3;
-    *dest3 = temp1 - temp3;
+    let temp0: SUM = src0.wrapping_add(&src1);
+    let temp1: SUM = src0.wrapping_sub(&src1);
+    let temp2: SUM = src2.wrapping_add(&src3);
+    let temp3: SUM = src2.wrapping_sub(&src3);
+    *dest0 = temp0.wrapping_add(&temp2);
+    *dest2 = temp0.wrapping_sub(&temp2);
+    *dest1 = temp1.wrapping_add(&temp3);
+    *dest3 = temp1.wrapping_sub(&temp3);
 }
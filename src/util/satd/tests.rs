@@ -76,6 +76,26 @@ macro_rules! get_satd_tests {
                 }
             }
 
+            #[test]
+            fn [<satd_negative_diff_matches_positive_ $module>]() {
+                for &(w, h) in SATD_SIZES {
+                    let width = NonZeroUsize::new(w).unwrap();
+                    let height = NonZeroUsize::new(h).unwrap();
+                    let pitch = NonZeroUsize::new(w).unwrap();
+                    let low: Vec<u8> = (0..w * h).map(|i| (i * 7 % 50) as u8).collect();
+                    let high: Vec<u8> = (0..w * h).map(|i| 200 - (i * 13 % 60) as u8).collect();
+                    let forward = verify_asm!(ret $module, get_satd(width, height, &high, pitch, &low, pitch));
+                    let backward = verify_asm!(ret $module, get_satd(width, height, &low, pitch, &high, pitch));
+                    assert_eq!(backward, forward, "failed at {w}x{h}");
+
+                    let low: Vec<u16> = low.iter().map(|&p| u16::from(p) * 256).collect();
+                    let high: Vec<u16> = high.iter().map(|&p| u16::from(p) * 256).collect();
+                    let forward = verify_asm!(ret $module, get_satd(width, height, &high, pitch, &low, pitch));
+                    let backward = verify_asm!(ret $module, get_satd(width, height, &low, pitch, &high, pitch));
+                    assert_eq!(backward, forward, "failed at {w}x{h}");
+                }
+            }
+
             #[test]
             fn [<satd_with_padding_u8_ $module>]() {
                 for &(w, h) in SATD_SIZES {
@@ -150,3 +170,66 @@ macro_rules! get_satd_tests {
 }
 
 get_satd_tests!(rust);
+
+/// Representative values of a signed half, from both extremes through zero.
+const HALVES_I16: &[i16] = &[
+    i16::MIN,
+    i16::MIN + 1,
+    -4080,
+    -255,
+    -1,
+    0,
+    1,
+    255,
+    4080,
+    i16::MAX,
+];
+const HALVES_I32: &[i32] = &[
+    i32::MIN,
+    i32::MIN + 1,
+    -1_044_480,
+    -65_535,
+    -1,
+    0,
+    1,
+    65_535,
+    i32::MAX,
+];
+
+#[test]
+fn abs2_u32_matches_unpacked_abs() {
+    for &x in HALVES_I16 {
+        for &y in HALVES_I16 {
+            if x < 0 && y == i16::MIN {
+                // The borrow from a negative `x` wraps `y` around, which `abs2` does not support
+                continue;
+            }
+            let packed = (x as u32).wrapping_add((y as u32) << 16);
+            let expected = u32::from(x.unsigned_abs()) + (u32::from(y.unsigned_abs()) << 16);
+            assert_eq!(
+                super::rust::abs2::<u16, u32>(packed),
+                expected,
+                "x = {x}, y = {y}"
+            );
+        }
+    }
+}
+
+#[test]
+fn abs2_u64_matches_unpacked_abs() {
+    for &x in HALVES_I32 {
+        for &y in HALVES_I32 {
+            if x < 0 && y == i32::MIN {
+                // The borrow from a negative `x` wraps `y` around, which `abs2` does not support
+                continue;
+            }
+            let packed = (x as u64).wrapping_add((y as u64) << 32);
+            let expected = u64::from(x.unsigned_abs()) + (u64::from(y.unsigned_abs()) << 32);
+            assert_eq!(
+                super::rust::abs2::<u32, u64>(packed),
+                expected,
+                "x = {x}, y = {y}"
+            );
+        }
+    }
+}
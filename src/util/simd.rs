@@ -0,0 +1,61 @@
+#[cfg(test)]
+mod tests;
+
+use std::{fmt::Debug, num::NonZeroUsize};
+
+use crate::util::Pixel;
+
+/// Runs a SIMD kernel that writes `width` pixels in each of the `height` rows of `dest`.
+///
+/// With the `verify_simd` feature, `scalar` also runs on a copy of `dest`,
+/// and every pixel the kernels write must match between the two.
+/// The rest of `dest` may be used as scratch space, so it is not compared.
+#[cfg_attr(feature = "no_simd", allow(dead_code))]
+#[inline]
+pub(crate) fn run_simd_plane<T: Pixel>(
+    name: &str,
+    dest: &mut [T],
+    pitch: NonZeroUsize,
+    width: NonZeroUsize,
+    height: NonZeroUsize,
+    simd: impl FnOnce(&mut [T]),
+    scalar: impl FnOnce(&mut [T]),
+) {
+    if !cfg!(feature = "verify_simd") {
+        simd(dest);
+        return;
+    }
+
+    let mut expected = dest.to_vec();
+    scalar(&mut expected);
+    simd(dest);
+    for y in 0..height.get() {
+        let row = y * pitch.get()..y * pitch.get() + width.get();
+        let mismatch = dest[row.clone()]
+            .iter()
+            .zip(&expected[row])
+            .position(|(simd, scalar)| simd != scalar);
+        debug_assert!(
+            mismatch.is_none(),
+            "{name}: SIMD and scalar output differ in row {y} at column {}",
+            mismatch.unwrap_or_default()
+        );
+    }
+}
+
+/// Runs a SIMD kernel that returns a value.
+///
+/// With the `verify_simd` feature, `scalar` also runs and must return the same value.
+#[cfg_attr(feature = "no_simd", allow(dead_code))]
+#[inline]
+pub(crate) fn run_simd_value<R: PartialEq + Debug>(
+    name: &str,
+    simd: impl FnOnce() -> R,
+    scalar: impl FnOnce() -> R,
+) -> R {
+    let value = simd();
+    if cfg!(feature = "verify_simd") {
+        debug_assert_eq!(value, scalar(), "{name}: SIMD and scalar results differ");
+    }
+    value
+}
@@ -0,0 +1,144 @@
+#![allow(clippy::unwrap_used, reason = "allow in test files")]
+#![allow(clippy::undocumented_unsafe_blocks, reason = "allow in test files")]
+
+use super::*;
+
+/// Writes `value` to the first 2 pixels of each of 2 rows with a pitch of 4,
+/// and garbage to the scratch pixels after them.
+fn fill_kernel(value: u8) -> impl FnOnce(&mut [u8]) {
+    move |dest: &mut [u8]| {
+        for row in dest.chunks_mut(4) {
+            row[..2].fill(value);
+            row[2..].fill(value.wrapping_mul(3));
+        }
+    }
+}
+
+fn run_fill(simd: impl FnOnce(&mut [u8]), scalar: impl FnOnce(&mut [u8])) -> Vec<u8> {
+    let mut dest = vec![0u8; 8];
+    let two = NonZeroUsize::new(2).unwrap();
+    run_simd_plane(
+        "fill_kernel",
+        &mut dest,
+        NonZeroUsize::new(4).unwrap(),
+        two,
+        two,
+        simd,
+        scalar,
+    );
+    dest
+}
+
+#[test]
+#[cfg(not(feature = "verify_simd"))]
+fn scalar_kernel_only_runs_when_verifying() {
+    let dest = run_fill(fill_kernel(1), |_| panic!("scalar kernel should not run"));
+    assert_eq!(dest, [1, 1, 3, 3, 1, 1, 3, 3]);
+    assert_eq!(
+        run_simd_value("value", || 5, || panic!("scalar kernel should not run")),
+        5
+    );
+}
+
+#[test]
+#[cfg(feature = "verify_simd")]
+fn scratch_pixels_are_not_compared() {
+    // Both kernels agree on every written pixel, but leave different scratch data
+    let dest = run_fill(fill_kernel(1), |dest| {
+        fill_kernel(1)(dest);
+        dest[3] = 0;
+    });
+    assert_eq!(dest, [1, 1, 3, 3, 1, 1, 3, 3]);
+}
+
+#[test]
+#[cfg(all(feature = "verify_simd", debug_assertions))]
+#[should_panic(expected = "fill_kernel: SIMD and scalar output differ in row 1")]
+fn diverging_plane_kernels_panic() {
+    run_fill(fill_kernel(1), |dest| {
+        fill_kernel(1)(dest);
+        dest[5] = 2;
+    });
+}
+
+#[test]
+#[cfg(all(feature = "verify_simd", debug_assertions))]
+#[should_panic(expected = "sum: SIMD and scalar results differ")]
+fn diverging_value_kernels_panic() {
+    run_simd_value("sum", || 1, || 2);
+}
+
+/// End-to-end checks of every dispatched kernel, which only verify anything with `verify_simd`
+#[cfg(feature = "verify_simd")]
+mod pyramids {
+    use std::num::NonZeroU8;
+
+    use rand::{Rng, SeedableRng};
+    use rand_xoshiro::Xoshiro128StarStar;
+
+    use super::*;
+    use crate::{
+        mv_gof::BufferPyramid,
+        params::{PadMode, ReduceFilter, Subpel, SubpelMethod},
+        util::{get_sad, luma_sum},
+    };
+
+    fn random_plane<T: Pixel>(rng: &mut Xoshiro128StarStar, len: usize, bits: u8) -> Vec<T> {
+        std::iter::repeat_with(|| T::from(rng.random_range(0..1u32 << bits)).unwrap())
+            .take(len)
+            .collect()
+    }
+
+    /// Builds every kind of superframe pyramid from noise,
+    /// which runs each reduce and refine kernel through its SIMD and scalar paths.
+    fn verify_pyramids<T: Pixel>(bits: u8) {
+        let mut rng = Xoshiro128StarStar::from_seed(*b"deadbeeflolcakes");
+        let (width, height) = (
+            NonZeroUsize::new(70).unwrap(),
+            NonZeroUsize::new(38).unwrap(),
+        );
+        let luma = random_plane::<T>(&mut rng, 70 * 38, bits);
+        let chroma = random_plane::<T>(&mut rng, 35 * 19, bits);
+        let pitch = [
+            width,
+            NonZeroUsize::new(35).unwrap(),
+            NonZeroUsize::new(35).unwrap(),
+        ];
+
+        for rfilter in 0..6 {
+            for sharp in 0..3 {
+                BufferPyramid::new(
+                    &[&luma, &chroma, &chroma],
+                    &pitch,
+                    width,
+                    height,
+                    3,
+                    Subpel::Quarter,
+                    8,
+                    8,
+                    NonZeroU8::new(2).unwrap(),
+                    NonZeroU8::new(2).unwrap(),
+                    NonZeroU8::new(bits).unwrap(),
+                    ReduceFilter::try_from(rfilter).unwrap(),
+                    SubpelMethod::try_from(sharp).unwrap(),
+                    PadMode::Replicate,
+                )
+                .unwrap();
+            }
+        }
+
+        let block = NonZeroUsize::new(16).unwrap();
+        let _ = get_sad(block, block, &luma, width, &luma[70 * 3 + 5..], width);
+        let _ = luma_sum(block, block, &luma, width);
+    }
+
+    #[test]
+    fn simd_kernels_match_scalar_on_noise_u8() {
+        verify_pyramids::<u8>(8);
+    }
+
+    #[test]
+    fn simd_kernels_match_scalar_on_noise_u16() {
+        verify_pyramids::<u16>(10);
+    }
+}
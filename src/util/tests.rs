@@ -1,7 +1,31 @@
 #![allow(clippy::unwrap_used, reason = "allow in test files")]
 #![allow(clippy::undocumented_unsafe_blocks, reason = "allow in test files")]
 
+use vapoursynth::format::PresetFormat;
+
 use super::*;
+use crate::error::ZooMvError;
+
+#[test]
+#[cfg(target_arch = "x86_64")]
+fn has_avx2_matches_runtime_detection() {
+    assert_eq!(has_avx2(), std::is_x86_feature_detected!("avx2"));
+}
+
+#[test]
+#[cfg(not(target_arch = "x86_64"))]
+fn has_avx2_is_false_on_non_x86() {
+    // The scalar implementations must be selected on non-x86 targets
+    assert!(!has_avx2());
+}
+
+#[test]
+fn from_u32_or_max_value_clamps_to_type_max() {
+    assert_eq!(u8::from_u32_or_max_value(200), 200);
+    assert_eq!(u8::from_u32_or_max_value(300), u8::MAX);
+    assert_eq!(u16::from_u32_or_max_value(65_000), 65_000);
+    assert_eq!(u16::from_u32_or_max_value(70_000), u16::MAX);
+}
 
 #[test]
 fn vs_bitblt_same_stride() {
@@ -12,7 +36,7 @@ fn vs_bitblt_same_stride() {
     let row_size = NonZeroUsize::new(3).unwrap();
     let height = NonZeroUsize::new(3).unwrap();
 
-    vs_bitblt(&mut dest, stride, &src, stride, row_size, height);
+    vs_bitblt(&mut dest, stride, &src, stride, row_size, height).unwrap();
 
     assert_eq!(dest, src, "Entire buffer should be copied exactly");
 }
@@ -33,7 +57,7 @@ fn vs_bitblt_different_stride() {
     let row_size = NonZeroUsize::new(3).unwrap();
     let height = NonZeroUsize::new(3).unwrap();
 
-    vs_bitblt(&mut dest, dest_stride, &src, src_stride, row_size, height);
+    vs_bitblt(&mut dest, dest_stride, &src, src_stride, row_size, height).unwrap();
 
     // Check that each row was copied correctly
     assert_eq!(&dest[0..3], &[1, 2, 3], "First row should match");
@@ -57,3 +81,250 @@ fn vs_bitblt_different_stride() {
         "Third row padding should be unchanged"
     );
 }
+
+#[test]
+fn vs_bitblt_same_stride_partial_buffer() {
+    // The fast path only copies `row_size * height` pixels, even from larger buffers
+    let src: Vec<u16> = (0..12).collect();
+    let mut dest = vec![0u16; 12];
+    let stride = NonZeroUsize::new(4).unwrap();
+    let height = NonZeroUsize::new(2).unwrap();
+
+    vs_bitblt(&mut dest, stride, &src, stride, stride, height).unwrap();
+
+    assert_eq!(&dest[..8], &src[..8]);
+    assert_eq!(&dest[8..], &[0; 4]);
+}
+
+#[test]
+fn vs_bitblt_padded_rows_short_last_row() {
+    // Buffers sliced from a larger plane may end right after the last row's pixels
+    let src = vec![1u8, 2, 0, 0, 3, 4];
+    let mut dest = vec![0u8; 5];
+    let src_stride = NonZeroUsize::new(4).unwrap();
+    let dest_stride = NonZeroUsize::new(3).unwrap();
+    let row_size = NonZeroUsize::new(2).unwrap();
+    let height = NonZeroUsize::new(2).unwrap();
+
+    vs_bitblt(&mut dest, dest_stride, &src, src_stride, row_size, height).unwrap();
+
+    assert_eq!(dest, [1, 2, 0, 3, 4]);
+}
+
+#[test]
+fn vs_bitblt_rejects_small_buffers() {
+    let stride = NonZeroUsize::new(4).unwrap();
+    let row_size = NonZeroUsize::new(3).unwrap();
+    let height = NonZeroUsize::new(3).unwrap();
+    // 3 rows need 2 full strides plus one row
+    let src = vec![0u8; 11];
+    let mut dest = vec![0u8; 11];
+
+    let err = vs_bitblt(&mut dest, stride, &src[..10], stride, row_size, height).unwrap_err();
+    assert!(err.to_string().contains("source has 10 pixels"), "{err}");
+
+    let err = vs_bitblt(&mut dest[..10], stride, &src, stride, row_size, height).unwrap_err();
+    assert!(
+        err.to_string().contains("destination has 10 pixels"),
+        "{err}"
+    );
+
+    assert!(vs_bitblt(&mut dest, stride, &src, stride, row_size, height).is_ok());
+}
+
+#[test]
+fn vs_bitblt_rejects_row_larger_than_stride() {
+    let src = vec![0u8; 32];
+    let mut dest = vec![0u8; 32];
+    let stride = NonZeroUsize::new(4).unwrap();
+    let row_size = NonZeroUsize::new(5).unwrap();
+    let height = NonZeroUsize::new(2).unwrap();
+
+    assert!(vs_bitblt(&mut dest, stride, &src, row_size, row_size, height).is_err());
+    assert!(vs_bitblt(&mut dest, row_size, &src, stride, row_size, height).is_err());
+}
+
+#[test]
+fn ranges_overlap_detection() {
+    let buffer = [0u8; 16];
+    assert!(ranges_overlap(&buffer[..8], &buffer[4..12]));
+    assert!(ranges_overlap(&buffer[4..12], &buffer[..8]));
+    assert!(!ranges_overlap(&buffer[..8], &buffer[8..]));
+    assert!(!ranges_overlap(&buffer[..4], &[0u8; 4]));
+}
+
+fn plane_view_data() -> (Vec<u16>, NonZeroUsize, NonZeroUsize, NonZeroUsize) {
+    // 5x3 plane with a pitch of 8, and a last row cut right after its pixels
+    let pitch = NonZeroUsize::new(8).unwrap();
+    let data = (0..2 * 8 + 5).collect();
+    (
+        data,
+        pitch,
+        NonZeroUsize::new(5).unwrap(),
+        NonZeroUsize::new(3).unwrap(),
+    )
+}
+
+#[test]
+fn plane_view_rows_match_manual_offsets() {
+    let (data, pitch, width, height) = plane_view_data();
+    let view = PlaneView::new(&data, pitch, width, height).unwrap();
+
+    for y in 0..height.get() {
+        let start = y * pitch.get();
+        assert_eq!(view.row(y), &data[start..start + width.get()], "row {y}");
+    }
+    let rows: Vec<_> = view.rows().collect();
+    assert_eq!(rows.len(), height.get());
+    for (y, row) in rows.into_iter().enumerate() {
+        assert_eq!(row, view.row(y));
+    }
+}
+
+#[test]
+fn plane_view_rejects_rows_outside_data() {
+    let (data, pitch, width, height) = plane_view_data();
+    assert!(PlaneView::new(&data[..data.len() - 1], pitch, width, height).is_err());
+    assert!(PlaneView::new(&data, width, pitch, height).is_err());
+
+    let view = PlaneView::new(&data, pitch, width, height).unwrap();
+    assert!(view.block(2 * pitch.get(), 5, 1).is_ok());
+    assert!(view.block(2 * pitch.get(), 6, 1).is_err());
+    assert!(view.block(pitch.get(), 5, 3).is_err());
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(expected = "row 3 is outside of a plane of height 3")]
+fn plane_view_row_past_height_panics() {
+    let (data, pitch, width, height) = plane_view_data();
+    let view = PlaneView::new(&data, pitch, width, height).unwrap();
+    let _ = view.row(3);
+}
+
+#[test]
+fn frame_shape_mismatch_is_rejected() {
+    let declared = FrameShape {
+        format: PresetFormat::YUV420P8.into(),
+        width: 64,
+        height: 48,
+    };
+    assert_eq!(
+        check_frame_shape("Analyse", "super", 3, Some(declared), declared),
+        Ok(())
+    );
+
+    let error = ZooMvError::FrameMismatch {
+        filter: "Analyse",
+        clip: "super",
+        frame: 3,
+    };
+    // An upstream filter that switched format or resolution mid-clip
+    let changed = [
+        FrameShape {
+            format: PresetFormat::YUV444P8.into(),
+            ..declared
+        },
+        FrameShape {
+            width: 32,
+            ..declared
+        },
+        FrameShape {
+            height: 96,
+            ..declared
+        },
+    ];
+    for actual in changed {
+        assert_eq!(
+            check_frame_shape("Analyse", "super", 3, Some(declared), actual),
+            Err(error),
+            "{actual:?}"
+        );
+    }
+    // A clip with a variable resolution cannot vouch for any frame
+    assert_eq!(
+        check_frame_shape("Analyse", "super", 3, None, declared),
+        Err(error)
+    );
+}
+
+#[test]
+fn plane_with_padding_covers_every_row_of_each_plane() {
+    let formats = [
+        (PresetFormat::Gray8, 1),
+        (PresetFormat::YUV420P8, 3),
+        (PresetFormat::YUV444P8, 3),
+        (PresetFormat::Gray16, 1),
+        (PresetFormat::YUV420P16, 3),
+        (PresetFormat::YUV444P16, 3),
+    ];
+    for (format, planes) in formats {
+        // A width that is not a multiple of the stride alignment, so rows are padded
+        let env = crate::tests::create_test_env(70, 30, format, 1).unwrap();
+        let (node, _) = env.get_output(0).unwrap();
+        let frame = node.get_frame(0).unwrap();
+        assert_eq!(frame.format().plane_count(), planes);
+
+        for plane in 0..planes {
+            let bytes = frame.format().bytes_per_sample() as usize;
+            let expected_len = frame.stride(plane) * frame.height(plane) / bytes;
+            assert!(frame.stride(plane) / bytes >= frame.width(plane));
+            let len = if bytes == 1 {
+                let data = plane_with_padding::<u8>(&frame, plane).unwrap();
+                assert_eq!(data.as_ptr(), frame.data_ptr(plane));
+                data.len()
+            } else {
+                let data = plane_with_padding::<u16>(&frame, plane).unwrap();
+                assert_eq!(data.as_ptr().cast(), frame.data_ptr(plane));
+                data.len()
+            };
+            assert_eq!(len, expected_len, "{format:?} plane {plane}");
+        }
+    }
+}
+
+#[test]
+fn plane_with_padding_mut_matches_shared_layout() {
+    let env = crate::tests::create_test_env(70, 30, PresetFormat::YUV420P16, 1).unwrap();
+    let (node, _) = env.get_output(0).unwrap();
+    let src = node.get_frame(0).unwrap();
+    let mut frame = vapoursynth::frame::FrameRefMut::copy_of(env.get_core().unwrap(), &src);
+
+    for plane in 0..3 {
+        let len = plane_with_padding::<u16>(&frame, plane).unwrap().len();
+        let data = plane_with_padding_mut::<u16>(&mut frame, plane).unwrap();
+        assert_eq!(data.len(), len);
+        // Writing the last pixel of the padding must stay inside the plane
+        data[len - 1] = 1;
+    }
+}
+
+#[test]
+fn plane_with_padding_rejects_missing_planes() {
+    let env = crate::tests::create_test_env(64, 32, PresetFormat::Gray8, 1).unwrap();
+    let (node, _) = env.get_output(0).unwrap();
+    let src = node.get_frame(0).unwrap();
+    let mut frame = vapoursynth::frame::FrameRefMut::copy_of(env.get_core().unwrap(), &src);
+
+    for plane in [1, 2, usize::MAX] {
+        let error = plane_with_padding::<u8>(&frame, plane).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!("plane {plane} requested from a Gray8 frame with 1 plane(s)")
+        );
+        assert!(plane_with_padding_mut::<u8>(&mut frame, plane).is_err());
+    }
+}
+
+#[test]
+fn plane_with_padding_rejects_mismatched_pixel_type() {
+    let env = crate::tests::create_test_env(64, 32, PresetFormat::YUV420P8, 1).unwrap();
+    let (node, _) = env.get_output(0).unwrap();
+    let frame = node.get_frame(0).unwrap();
+
+    let error = plane_with_padding::<u16>(&frame, 1).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "plane 1 of a YUV420P8 frame has 1-byte samples, but 2-byte pixels were requested"
+    );
+}
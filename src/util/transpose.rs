@@ -0,0 +1,66 @@
+#[cfg(target_arch = "x86_64")]
+mod avx2;
+mod rust;
+
+#[cfg(test)]
+mod tests;
+
+use std::num::NonZeroUsize;
+
+use cfg_if::cfg_if;
+
+use crate::util::Pixel;
+
+/// Transposes a `width`x`height` block of `src` into a `height`x`width` block of `dest`,
+/// so that the pixel at column `x` of row `y` ends up at column `y` of row `x`.
+///
+/// This lets a horizontal filter run over the columns of a plane.
+/// Transposing the result back gives the same output as the equivalent vertical filter.
+///
+/// # Parameters
+/// - `dest`: Destination buffer, holding `width` rows of `height` pixels
+/// - `dest_pitch`: Number of pixels per row in `dest`
+/// - `src`: Source buffer, holding `height` rows of `width` pixels
+/// - `src_pitch`: Number of pixels per row in `src`
+/// - `width`: Width of the source block in pixels
+/// - `height`: Height of the source block in pixels
+pub fn transpose_block<T: Pixel>(
+    dest: &mut [T],
+    dest_pitch: NonZeroUsize,
+    src: &[T],
+    src_pitch: NonZeroUsize,
+    width: NonZeroUsize,
+    height: NonZeroUsize,
+) {
+    assert!(
+        dest_pitch >= height && src_pitch >= width,
+        "transpose_block: a pitch is smaller than the row it holds"
+    );
+    assert!(
+        dest.len() >= dest_pitch.get() * (width.get() - 1) + height.get()
+            && src.len() >= src_pitch.get() * (height.get() - 1) + width.get(),
+        "transpose_block: buffers are too small for a {}x{} block",
+        width,
+        height
+    );
+
+    cfg_if! {
+        if #[cfg(all(target_arch = "x86_64", not(feature = "no_simd")))] {
+            if crate::util::has_avx2() {
+                crate::util::run_simd_plane(
+                    "transpose_block",
+                    dest,
+                    dest_pitch,
+                    height,
+                    width,
+                    // SAFETY: We check for AVX2 first, and the buffer sizes above
+                    |dest| unsafe { avx2::transpose_block(dest, dest_pitch, src, src_pitch, width, height) },
+                    |dest| rust::transpose_block(dest, dest_pitch, src, src_pitch, width, height),
+                );
+                return;
+            }
+        }
+    }
+
+    rust::transpose_block(dest, dest_pitch, src, src_pitch, width, height);
+}
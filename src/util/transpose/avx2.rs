@@ -0,0 +1,114 @@
+#![allow(clippy::undocumented_unsafe_blocks)]
+#![allow(unsafe_op_in_unsafe_fn)]
+
+use std::{arch::x86_64::*, num::NonZeroUsize};
+
+use crate::util::Pixel;
+
+/// Pixels per side of the tiles transposed in registers.
+const TILE: usize = 8;
+
+#[target_feature(enable = "avx2")]
+pub(super) unsafe fn transpose_block<T: Pixel>(
+    dest: &mut [T],
+    dest_pitch: NonZeroUsize,
+    src: &[T],
+    src_pitch: NonZeroUsize,
+    width: NonZeroUsize,
+    height: NonZeroUsize,
+) {
+    let (width, height) = (width.get(), height.get());
+    let (dest_pitch, src_pitch) = (dest_pitch.get(), src_pitch.get());
+    let tiled_width = width - width % TILE;
+    let tiled_height = height - height % TILE;
+
+    for y in (0..tiled_height).step_by(TILE) {
+        for x in (0..tiled_width).step_by(TILE) {
+            let src = src.as_ptr().add(y * src_pitch + x);
+            let dest = dest.as_mut_ptr().add(x * dest_pitch + y);
+            match size_of::<T>() {
+                1 => transpose_tile_u8(dest.cast(), dest_pitch, src.cast(), src_pitch),
+                2 => transpose_tile_u16(dest.cast(), dest_pitch, src.cast(), src_pitch),
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    // Pixels right of and below the tiles
+    for y in 0..height {
+        let first = if y < tiled_height { tiled_width } else { 0 };
+        for x in first..width {
+            *dest.get_unchecked_mut(x * dest_pitch + y) = *src.get_unchecked(y * src_pitch + x);
+        }
+    }
+}
+
+#[target_feature(enable = "avx2")]
+unsafe fn transpose_tile_u8(dest: *mut u8, dest_pitch: usize, src: *const u8, src_pitch: usize) {
+    let row = |y: usize| _mm_loadl_epi64(src.add(y * src_pitch).cast());
+
+    // Interleave pairs of rows, then pairs of pairs, until each half register is a column
+    let r01 = _mm_unpacklo_epi8(row(0), row(1));
+    let r23 = _mm_unpacklo_epi8(row(2), row(3));
+    let r45 = _mm_unpacklo_epi8(row(4), row(5));
+    let r67 = _mm_unpacklo_epi8(row(6), row(7));
+
+    let r0123_lo = _mm_unpacklo_epi16(r01, r23);
+    let r0123_hi = _mm_unpackhi_epi16(r01, r23);
+    let r4567_lo = _mm_unpacklo_epi16(r45, r67);
+    let r4567_hi = _mm_unpackhi_epi16(r45, r67);
+
+    let columns = [
+        _mm_unpacklo_epi32(r0123_lo, r4567_lo),
+        _mm_unpackhi_epi32(r0123_lo, r4567_lo),
+        _mm_unpacklo_epi32(r0123_hi, r4567_hi),
+        _mm_unpackhi_epi32(r0123_hi, r4567_hi),
+    ];
+    for (i, pair) in columns.into_iter().enumerate() {
+        _mm_storel_epi64(dest.add(2 * i * dest_pitch).cast(), pair);
+        _mm_storel_epi64(
+            dest.add((2 * i + 1) * dest_pitch).cast(),
+            _mm_unpackhi_epi64(pair, pair),
+        );
+    }
+}
+
+#[target_feature(enable = "avx2")]
+unsafe fn transpose_tile_u16(dest: *mut u16, dest_pitch: usize, src: *const u16, src_pitch: usize) {
+    let row = |y: usize| _mm_loadu_si128(src.add(y * src_pitch).cast());
+    let (r0, r1, r2, r3) = (row(0), row(1), row(2), row(3));
+    let (r4, r5, r6, r7) = (row(4), row(5), row(6), row(7));
+
+    // Interleave pairs of rows, then pairs of pairs, until each register is a column
+    let r01_lo = _mm_unpacklo_epi16(r0, r1);
+    let r01_hi = _mm_unpackhi_epi16(r0, r1);
+    let r23_lo = _mm_unpacklo_epi16(r2, r3);
+    let r23_hi = _mm_unpackhi_epi16(r2, r3);
+    let r45_lo = _mm_unpacklo_epi16(r4, r5);
+    let r45_hi = _mm_unpackhi_epi16(r4, r5);
+    let r67_lo = _mm_unpacklo_epi16(r6, r7);
+    let r67_hi = _mm_unpackhi_epi16(r6, r7);
+
+    let top = [
+        _mm_unpacklo_epi32(r01_lo, r23_lo),
+        _mm_unpackhi_epi32(r01_lo, r23_lo),
+        _mm_unpacklo_epi32(r01_hi, r23_hi),
+        _mm_unpackhi_epi32(r01_hi, r23_hi),
+    ];
+    let bottom = [
+        _mm_unpacklo_epi32(r45_lo, r67_lo),
+        _mm_unpackhi_epi32(r45_lo, r67_lo),
+        _mm_unpacklo_epi32(r45_hi, r67_hi),
+        _mm_unpackhi_epi32(r45_hi, r67_hi),
+    ];
+    for (i, (top, bottom)) in top.into_iter().zip(bottom).enumerate() {
+        _mm_storeu_si128(
+            dest.add(2 * i * dest_pitch).cast(),
+            _mm_unpacklo_epi64(top, bottom),
+        );
+        _mm_storeu_si128(
+            dest.add((2 * i + 1) * dest_pitch).cast(),
+            _mm_unpackhi_epi64(top, bottom),
+        );
+    }
+}
@@ -0,0 +1,18 @@
+use std::num::NonZeroUsize;
+
+use crate::util::Pixel;
+
+pub(super) fn transpose_block<T: Pixel>(
+    dest: &mut [T],
+    dest_pitch: NonZeroUsize,
+    src: &[T],
+    src_pitch: NonZeroUsize,
+    width: NonZeroUsize,
+    height: NonZeroUsize,
+) {
+    for (y, src_row) in src.chunks(src_pitch.get()).take(height.get()).enumerate() {
+        for (x, &pixel) in src_row[..width.get()].iter().enumerate() {
+            dest[x * dest_pitch.get() + y] = pixel;
+        }
+    }
+}
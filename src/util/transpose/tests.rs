@@ -0,0 +1,99 @@
+#![allow(clippy::unwrap_used, reason = "allow in test files")]
+#![allow(clippy::undocumented_unsafe_blocks, reason = "allow in test files")]
+
+use std::num::NonZeroUsize;
+
+use pastey::paste;
+use rand::{Rng, SeedableRng};
+use rand_xoshiro::Xoshiro128StarStar;
+
+use crate::util::Pixel;
+
+/// Block sizes covering whole tiles, partial tiles and blocks smaller than a tile.
+const SIZES: [(usize, usize); 7] = [(8, 8), (16, 8), (8, 24), (37, 23), (5, 19), (3, 2), (1, 1)];
+
+fn random_plane<T: Pixel>(rng: &mut Xoshiro128StarStar, len: usize) -> Vec<T> {
+    std::iter::repeat_with(|| T::from_u32_or_max_value(rng.random()))
+        .take(len)
+        .collect()
+}
+
+macro_rules! transpose_tests {
+    ($module:ident) => {
+        paste! {
+            #[test]
+            fn [<transpose_moves_columns_to_rows_ $module>]() {
+                let src: Vec<u8> = (0..6 * 4).collect();
+                let mut dest = vec![0u8; 4 * 6];
+                let src_pitch = NonZeroUsize::new(6).unwrap();
+                let dest_pitch = NonZeroUsize::new(4).unwrap();
+                let width = NonZeroUsize::new(6).unwrap();
+                let height = NonZeroUsize::new(4).unwrap();
+
+                verify_asm!($module, transpose_block(&mut dest, dest_pitch, &src, src_pitch, width, height));
+
+                for x in 0..6 {
+                    for y in 0..4 {
+                        assert_eq!(dest[x * 4 + y], src[y * 6 + x], "pixel ({x}, {y})");
+                    }
+                }
+            }
+
+            #[test]
+            fn [<transpose_is_an_involution_u8_ $module>]() {
+                transpose_twice::<u8>(|dest, dest_pitch, src, src_pitch, width, height| {
+                    verify_asm!($module, transpose_block(dest, dest_pitch, src, src_pitch, width, height));
+                });
+            }
+
+            #[test]
+            fn [<transpose_is_an_involution_u16_ $module>]() {
+                transpose_twice::<u16>(|dest, dest_pitch, src, src_pitch, width, height| {
+                    verify_asm!($module, transpose_block(dest, dest_pitch, src, src_pitch, width, height));
+                });
+            }
+        }
+    };
+}
+
+type TransposeFn<T> = fn(&mut Vec<T>, NonZeroUsize, &[T], NonZeroUsize, NonZeroUsize, NonZeroUsize);
+
+/// Transposes random blocks with padded pitches and back, which must restore every pixel
+/// and leave the padding alone.
+fn transpose_twice<T: Pixel + std::fmt::Debug>(transpose: TransposeFn<T>) {
+    let mut rng = Xoshiro128StarStar::from_seed(*b"deadbeeflolcakes");
+    for (w, h) in SIZES {
+        let src_pitch = NonZeroUsize::new(w + 5).unwrap();
+        let dest_pitch = NonZeroUsize::new(h + 3).unwrap();
+        let width = NonZeroUsize::new(w).unwrap();
+        let height = NonZeroUsize::new(h).unwrap();
+        let src: Vec<T> = random_plane(&mut rng, src_pitch.get() * h);
+
+        let mut transposed = vec![T::max_value(); dest_pitch.get() * w];
+        transpose(&mut transposed, dest_pitch, &src, src_pitch, width, height);
+        for (x, row) in transposed.chunks(dest_pitch.get()).enumerate() {
+            assert!(
+                row[h..].iter().all(|&pixel| pixel == T::max_value()),
+                "{w}x{h}: padding of row {x} was written"
+            );
+        }
+
+        let mut restored = src.clone();
+        for row in restored.chunks_mut(src_pitch.get()) {
+            row[..w].fill(T::zero());
+        }
+        transpose(
+            &mut restored,
+            src_pitch,
+            &transposed,
+            dest_pitch,
+            height,
+            width,
+        );
+        assert_eq!(restored, src, "{w}x{h}");
+    }
+}
+
+transpose_tests!(rust);
+#[cfg(target_feature = "avx2")]
+transpose_tests!(avx2);
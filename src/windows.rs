@@ -0,0 +1,194 @@
+//! Overlap window weighting tables for blending overlapped blocks
+
+#[cfg(test)]
+mod tests;
+
+use std::{f64::consts::PI, num::NonZeroUsize};
+
+use anyhow::{Result, bail};
+
+/// Number of fractional bits in a one-dimensional window weight.
+pub const WINDOW_BITS: u32 = 11;
+/// The weight of a pixel that is not overlapped by any neighboring block
+/// in one dimension.
+pub const WINDOW_FULL: u32 = 1 << WINDOW_BITS;
+/// The weight of a pixel that is not overlapped by any neighboring block.
+/// Two-dimensional windows are the product of the horizontal and vertical
+/// windows, so their weights have twice as many fractional bits.
+#[cfg(any(test, feature = "bench"))]
+pub const WINDOW_FULL_2D: u32 = WINDOW_FULL * WINDOW_FULL;
+
+/// The shape of the weighting curve used inside the overlapped region.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WindowMode {
+    /// Raised cosine (Hann-like) window, matching mvtools' `OverlapWindows`.
+    Cosine,
+    /// Both overlapping blocks contribute equally across the overlapped region.
+    #[cfg(any(test, feature = "bench"))]
+    Flat,
+}
+
+/// Which of the nine windows should be applied to a block,
+/// depending on its position within the frame.
+///
+/// Blocks on the edges of the frame have no neighbor on that side,
+/// so their window stays at full weight on that side.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WindowPosition {
+    TopLeft = 0,
+    Top = 1,
+    TopRight = 2,
+    Left = 3,
+    Middle = 4,
+    Right = 5,
+    BottomLeft = 6,
+    Bottom = 7,
+    BottomRight = 8,
+}
+
+impl WindowPosition {
+    /// Selects the window for the block at `(bx, by)` in a grid of
+    /// `blk_x` by `blk_y` blocks.
+    #[must_use]
+    pub fn from_block(bx: usize, by: usize, blk_x: NonZeroUsize, blk_y: NonZeroUsize) -> Self {
+        let column = edge_index(bx, blk_x.get());
+        let row = edge_index(by, blk_y.get());
+        match row * 3 + column {
+            0 => Self::TopLeft,
+            1 => Self::Top,
+            2 => Self::TopRight,
+            3 => Self::Left,
+            4 => Self::Middle,
+            5 => Self::Right,
+            6 => Self::BottomLeft,
+            7 => Self::Bottom,
+            8 => Self::BottomRight,
+            _ => unreachable!(),
+        }
+    }
+}
+
+/// Returns 0 for the first block, 2 for the last block, and 1 otherwise.
+/// A single block is treated as the first block, so grids should be at least two blocks wide.
+fn edge_index(idx: usize, count: usize) -> usize {
+    if idx == 0 {
+        0
+    } else if idx + 1 >= count {
+        2
+    } else {
+        1
+    }
+}
+
+/// Precomputed two-dimensional weighting windows for blending overlapped blocks,
+/// used by motion compensation and degraining.
+///
+/// Equivalent to mvtools' `OverlapWindows`. The rising edge of each window is the
+/// exact complement of the falling edge of its neighbor, so the weights of all blocks
+/// covering any pixel always sum to the square of [`WINDOW_FULL`].
+#[derive(Debug, Clone)]
+pub struct OverlapWindows {
+    blk_size_x: NonZeroUsize,
+    blk_size_y: NonZeroUsize,
+    overlap_x: usize,
+    overlap_y: usize,
+    /// Nine windows of `blk_size_x * blk_size_y` weights each,
+    /// indexed by [`WindowPosition`].
+    windows: Box<[u32]>,
+}
+
+impl OverlapWindows {
+    pub fn new(
+        blk_size_x: NonZeroUsize,
+        blk_size_y: NonZeroUsize,
+        overlap_x: usize,
+        overlap_y: usize,
+        mode: WindowMode,
+    ) -> Result<Self> {
+        if overlap_x > blk_size_x.get() / 2 || overlap_y > blk_size_y.get() / 2 {
+            bail!("OverlapWindows: overlap must be at most half of the block size");
+        }
+
+        let horizontal = window_1d(blk_size_x, overlap_x, mode);
+        let vertical = window_1d(blk_size_y, overlap_y, mode);
+        let size = blk_size_x.get() * blk_size_y.get();
+
+        let mut windows = vec![0; size * 9].into_boxed_slice();
+        for (row, win_y) in vertical.iter().enumerate() {
+            for (column, win_x) in horizontal.iter().enumerate() {
+                let window = &mut windows[(row * 3 + column) * size..][..size];
+                for (j, &wy) in win_y.iter().enumerate() {
+                    for (i, &wx) in win_x.iter().enumerate() {
+                        window[j * blk_size_x.get() + i] = wx * wy;
+                    }
+                }
+            }
+        }
+
+        Ok(Self {
+            blk_size_x,
+            blk_size_y,
+            overlap_x,
+            overlap_y,
+            windows,
+        })
+    }
+
+    /// Gets the window for a block at the given position,
+    /// as a `blk_size_x * blk_size_y` array of weights.
+    #[must_use]
+    pub fn get(&self, position: WindowPosition) -> &[u32] {
+        let size = self.blk_size_x.get() * self.blk_size_y.get();
+        &self.windows[position as usize * size..][..size]
+    }
+
+    #[must_use]
+    pub fn blk_size_x(&self) -> NonZeroUsize {
+        self.blk_size_x
+    }
+
+    #[must_use]
+    pub fn blk_size_y(&self) -> NonZeroUsize {
+        self.blk_size_y
+    }
+
+    #[must_use]
+    pub fn overlap_x(&self) -> usize {
+        self.overlap_x
+    }
+
+    #[must_use]
+    pub fn overlap_y(&self) -> usize {
+        self.overlap_y
+    }
+}
+
+/// Computes the one-dimensional windows for the first, middle and last block
+/// along one axis.
+fn window_1d(size: NonZeroUsize, overlap: usize, mode: WindowMode) -> [Vec<u32>; 3] {
+    let size = size.get();
+    let rising: Vec<u32> = (0..overlap)
+        .map(|i| match mode {
+            WindowMode::Cosine => {
+                let w = (PI * (i as f64 - overlap as f64 + 0.5) / (overlap * 2) as f64).cos();
+                (w * w * WINDOW_FULL as f64).round() as u32
+            }
+            #[cfg(any(test, feature = "bench"))]
+            WindowMode::Flat => WINDOW_FULL / 2,
+        })
+        .collect();
+
+    let mut first = vec![WINDOW_FULL; size];
+    let mut middle = vec![WINDOW_FULL; size];
+    let mut last = vec![WINDOW_FULL; size];
+    for (i, &weight) in rising.iter().enumerate() {
+        // left edge, overlapped by the previous block
+        middle[i] = weight;
+        last[i] = weight;
+        // right edge, overlapped by the next block
+        middle[size - overlap + i] = WINDOW_FULL - weight;
+        first[size - overlap + i] = WINDOW_FULL - weight;
+    }
+
+    [first, middle, last]
+}
@@ -0,0 +1,142 @@
+#![allow(clippy::unwrap_used, reason = "allow in test files")]
+#![allow(clippy::undocumented_unsafe_blocks, reason = "allow in test files")]
+
+use std::num::NonZeroUsize;
+
+use parameterized::parameterized;
+
+use super::*;
+
+/// Accumulates the windows of a `blk_x` by `blk_y` grid of overlapped blocks
+/// into a plane, the same way Compensate/Degrain blend their output.
+fn accumulate_grid(windows: &OverlapWindows, blk_x: usize, blk_y: usize) -> (Vec<u32>, usize) {
+    let blk_size_x = windows.blk_size_x().get();
+    let blk_size_y = windows.blk_size_y().get();
+    let step_x = blk_size_x - windows.overlap_x();
+    let step_y = blk_size_y - windows.overlap_y();
+    let width = step_x * blk_x + windows.overlap_x();
+    let height = step_y * blk_y + windows.overlap_y();
+
+    let mut plane = vec![0u32; width * height];
+    for by in 0..blk_y {
+        for bx in 0..blk_x {
+            let position = WindowPosition::from_block(
+                bx,
+                by,
+                NonZeroUsize::new(blk_x).unwrap(),
+                NonZeroUsize::new(blk_y).unwrap(),
+            );
+            let window = windows.get(position);
+            for j in 0..blk_size_y {
+                for i in 0..blk_size_x {
+                    plane[(by * step_y + j) * width + bx * step_x + i] +=
+                        window[j * blk_size_x + i];
+                }
+            }
+        }
+    }
+    (plane, width)
+}
+
+#[parameterized(
+    blk_size_x = { 4, 8, 8, 16, 16, 32, 8 },
+    blk_size_y = { 4, 8, 4, 16, 8, 32, 8 },
+    overlap_x = { 2, 4, 2, 8, 4, 16, 0 },
+    overlap_y = { 2, 4, 2, 8, 2, 8, 0 }
+)]
+fn adjacent_windows_sum_to_full(
+    blk_size_x: usize,
+    blk_size_y: usize,
+    overlap_x: usize,
+    overlap_y: usize,
+) {
+    for mode in [WindowMode::Cosine, WindowMode::Flat] {
+        let windows = OverlapWindows::new(
+            NonZeroUsize::new(blk_size_x).unwrap(),
+            NonZeroUsize::new(blk_size_y).unwrap(),
+            overlap_x,
+            overlap_y,
+            mode,
+        )
+        .unwrap();
+        let (plane, width) = accumulate_grid(&windows, 5, 4);
+        for (i, &weight) in plane.iter().enumerate() {
+            assert_eq!(
+                weight,
+                WINDOW_FULL_2D,
+                "{mode:?} {blk_size_x}x{blk_size_y} overlap {overlap_x}x{overlap_y}: pixel ({}, \
+                 {}) has weight {weight}",
+                i % width,
+                i / width
+            );
+        }
+    }
+}
+
+#[test]
+fn cosine_window_matches_mvtools() {
+    let windows = OverlapWindows::new(
+        NonZeroUsize::new(8).unwrap(),
+        NonZeroUsize::new(8).unwrap(),
+        4,
+        4,
+        WindowMode::Cosine,
+    )
+    .unwrap();
+    let [_, middle, _] = window_1d(NonZeroUsize::new(8).unwrap(), 4, WindowMode::Cosine);
+    // (int)(cos(PI * (i - ox + 0.5) / (ox * 2))^2 * 2048 + 0.5) from mvtools
+    assert_eq!(middle, vec![78, 632, 1416, 1970, 1970, 1416, 632, 78]);
+
+    // The center of a middle block is never overlapped
+    let window = windows.get(WindowPosition::Middle);
+    assert_eq!(window[3 * 8 + 4], 1970 * 1970);
+    assert_eq!(window[0], 78 * 78);
+}
+
+#[test]
+fn flat_window_is_half_in_overlap() {
+    let [first, middle, last] = window_1d(NonZeroUsize::new(8).unwrap(), 2, WindowMode::Flat);
+    assert_eq!(first, vec![2048, 2048, 2048, 2048, 2048, 2048, 1024, 1024]);
+    assert_eq!(middle, vec![1024, 1024, 2048, 2048, 2048, 2048, 1024, 1024]);
+    assert_eq!(last, vec![1024, 1024, 2048, 2048, 2048, 2048, 2048, 2048]);
+}
+
+#[test]
+fn window_position_from_block() {
+    let blk_x = NonZeroUsize::new(3).unwrap();
+    let blk_y = NonZeroUsize::new(3).unwrap();
+    assert_eq!(
+        WindowPosition::from_block(0, 0, blk_x, blk_y),
+        WindowPosition::TopLeft
+    );
+    assert_eq!(
+        WindowPosition::from_block(1, 0, blk_x, blk_y),
+        WindowPosition::Top
+    );
+    assert_eq!(
+        WindowPosition::from_block(2, 1, blk_x, blk_y),
+        WindowPosition::Right
+    );
+    assert_eq!(
+        WindowPosition::from_block(1, 1, blk_x, blk_y),
+        WindowPosition::Middle
+    );
+    assert_eq!(
+        WindowPosition::from_block(2, 2, blk_x, blk_y),
+        WindowPosition::BottomRight
+    );
+}
+
+#[test]
+fn rejects_overlap_larger_than_half() {
+    assert!(
+        OverlapWindows::new(
+            NonZeroUsize::new(8).unwrap(),
+            NonZeroUsize::new(8).unwrap(),
+            6,
+            4,
+            WindowMode::Cosine,
+        )
+        .is_err()
+    );
+}
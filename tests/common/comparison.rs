@@ -155,6 +155,77 @@ where
     Ok(())
 }
 
+/// Compare two `MVTools_vectors` properties level by level.
+///
+/// Headers, block counts and vector positions must match exactly,
+/// while each block's SAD may differ by up to `sad_tolerance`.
+pub fn compare_motion_vectors(
+    c_vectors: &[u8],
+    r_vectors: &[u8],
+    sad_tolerance: u64,
+) -> Result<()> {
+    if c_vectors.len() != r_vectors.len() {
+        bail!(
+            "MVTools_vectors size mismatch: C={}, Rust={}",
+            c_vectors.len(),
+            r_vectors.len()
+        );
+    }
+
+    // Size and validity headers
+    let (c_header, mut c_data) = c_vectors.split_at(8);
+    let (r_header, mut r_data) = r_vectors.split_at(8);
+    if c_header != r_header {
+        bail!("MVTools_vectors headers mismatch: C={c_header:?}, Rust={r_header:?}");
+    }
+
+    let mut level = 0;
+    while !c_data.is_empty() {
+        let c_size = read_u32(c_data)? as usize;
+        let r_size = read_u32(r_data)? as usize;
+        if c_size != r_size || c_size < 4 || c_size > c_data.len() {
+            bail!("Level {level} size mismatch: C={c_size}, Rust={r_size}");
+        }
+
+        for (i, (c_mv, r_mv)) in c_data[4..c_size]
+            .chunks_exact(16)
+            .zip(r_data[4..r_size].chunks_exact(16))
+            .enumerate()
+        {
+            let (c_x, c_y, c_sad) = read_vector(c_mv)?;
+            let (r_x, r_y, r_sad) = read_vector(r_mv)?;
+            if (c_x, c_y) != (r_x, r_y) {
+                bail!("Level {level}, MV {i}: C=({c_x}, {c_y}), Rust=({r_x}, {r_y})",);
+            }
+            if c_sad.abs_diff(r_sad) > sad_tolerance {
+                bail!(
+                    "Level {level}, MV {i}: SAD C={c_sad}, Rust={r_sad} (tolerance \
+                     {sad_tolerance})",
+                );
+            }
+        }
+
+        c_data = &c_data[c_size..];
+        r_data = &r_data[r_size..];
+        level += 1;
+    }
+
+    Ok(())
+}
+
+fn read_u32(data: &[u8]) -> Result<u32> {
+    let bytes = data.get(..4).context("Truncated vector data")?;
+    Ok(u32::from_le_bytes(bytes.try_into()?))
+}
+
+fn read_vector(mv: &[u8]) -> Result<(i32, i32, i64)> {
+    Ok((
+        i32::from_le_bytes(mv[..4].try_into()?),
+        i32::from_le_bytes(mv[4..8].try_into()?),
+        i64::from_le_bytes(mv[8..16].try_into()?),
+    ))
+}
+
 /// Compare frame properties (int and binary data)
 pub fn compare_frame_properties(
     c_frame: &FrameRef,
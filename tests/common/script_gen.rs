@@ -16,8 +16,25 @@ pub enum ClipContentType {
     Blank,
     Gradient,
     Checkerboard,
-    MovingBox { speed_x: i32, speed_y: i32 },
-    Noise { seed: u64 },
+    MovingBox {
+        speed_x: i32,
+        speed_y: i32,
+    },
+    Noise {
+        seed: u64,
+    },
+    NoisyMovingBox {
+        speed_x: i32,
+        speed_y: i32,
+        seed: u64,
+    },
+    /// A static noise texture translated by a whole frame's worth of motion each frame.
+    /// Speeds must be non-negative, and even for subsampled formats.
+    Pan {
+        speed_x: i32,
+        speed_y: i32,
+        seed: u64,
+    },
 }
 
 impl ClipContentType {
@@ -62,6 +79,43 @@ def shift_clip(n):
     return shifted
 
 clip = core.std.FrameEval(clip, shift_clip)
+"#
+                )
+            }
+            Self::NoisyMovingBox {
+                speed_x,
+                speed_y,
+                seed,
+            } => {
+                let moving_box = Self::MovingBox {
+                    speed_x: *speed_x,
+                    speed_y: *speed_y,
+                };
+                let noise = Self::Noise { seed: *seed };
+                format!("{}{}", moving_box.to_script(), noise.to_script())
+            }
+            Self::Pan {
+                speed_x,
+                speed_y,
+                seed,
+            } => {
+                format!(
+                    r#"
+# Crop a moving window out of a larger noise texture
+margin_x = {speed_x} * clip.num_frames
+margin_y = {speed_y} * clip.num_frames
+texture = core.std.BlankClip(clip, width=clip.width + margin_x, height=clip.height + margin_y, color=[128, 128, 128])
+texture = core.grain.Add(texture, var=400, seed={seed}, constant=True)
+clip = core.std.Splice([
+    core.std.Crop(
+        texture,
+        left={speed_x} * n,
+        right=margin_x - {speed_x} * n,
+        top={speed_y} * n,
+        bottom=margin_y - {speed_y} * n,
+    )[n]
+    for n in range(clip.num_frames)
+])
 "#
                 )
             }
@@ -229,6 +283,27 @@ impl FilterParams {
     }
 }
 
+/// Generates a script creating the test clip as `clip`, followed by `body`.
+pub fn generate_script(clip_config: &TestClipConfig, body: &str) -> String {
+    format!(
+        r#"
+import vapoursynth as vs
+core = vs.core
+
+# Generate base clip
+clip = core.std.BlankClip(width={}, height={}, format={}, length={})
+{}
+{}
+"#,
+        clip_config.width,
+        clip_config.height,
+        clip_config.format,
+        clip_config.length,
+        clip_config.content_type.to_script(),
+        body
+    )
+}
+
 pub fn generate_comparison_script(
     clip_config: &TestClipConfig,
     super_params: &FilterParams,
@@ -7,11 +7,15 @@ use anyhow::{Context, Result};
 use parameterized::parameterized;
 use vapoursynth::prelude::Environment;
 
-use crate::common::script_gen::{
-    ClipContentType,
-    FilterParams,
-    TestClipConfig,
-    generate_comparison_script,
+use crate::common::{
+    comparison::compare_motion_vectors,
+    script_gen::{
+        ClipContentType,
+        FilterParams,
+        TestClipConfig,
+        generate_comparison_script,
+        generate_script,
+    },
 };
 
 #[parameterized(
@@ -173,9 +177,18 @@ fn test_analyse_backward_motion(format: &str) -> Result<()> {
 fn test_analyse_different_block_sizes(format: &str) -> Result<()> {
     require_mvtools!();
 
-    let block_sizes = [4, 8, 16, 32];
+    // Non-square blocks keep their shape on every level
+    let block_sizes = [
+        (4, 4),
+        (8, 8),
+        (16, 16),
+        (32, 32),
+        (8, 4),
+        (16, 8),
+        (32, 16),
+    ];
 
-    for blksize in &block_sizes {
+    for (index, &(blksize, blksizev)) in block_sizes.iter().enumerate() {
         let clip_config = TestClipConfig {
             width: 256,
             height: 192,
@@ -189,14 +202,15 @@ fn test_analyse_different_block_sizes(format: &str) -> Result<()> {
 
         let super_params = FilterParams::default();
         let analyse_params = FilterParams {
-            blksize: Some(*blksize),
+            blksize: Some(blksize),
+            blksizev: Some(blksizev),
             ..Default::default()
         };
 
         let script = generate_comparison_script(&clip_config, &super_params, Some(&analyse_params));
 
         let env = Environment::from_script(&script)
-            .with_context(|| format!("Failed with block size {}", blksize))?;
+            .with_context(|| format!("Failed with block size {blksize}x{blksizev}"))?;
         let (c_node, _) = env.get_output(0)?;
         let (r_node, _) = env.get_output(1)?;
 
@@ -207,13 +221,13 @@ fn test_analyse_different_block_sizes(format: &str) -> Result<()> {
         let r_props = r_frame.props();
         let c_vectors = c_props.get_data("MVTools_vectors")?;
         let r_vectors = r_props.get_data("MVTools_vectors")?;
-        compare_vectors_data(c_vectors, r_vectors, *blksize as usize);
+        compare_vectors_data(c_vectors, r_vectors, index);
 
         // Verify analysis data matches
         let c_analysis = c_props.get_data("MVTools_MVAnalysisData")?;
         let r_analysis = r_props.get_data("MVTools_MVAnalysisData")?;
 
-        compare_analysis_data(c_analysis, r_analysis, *blksize as usize);
+        compare_analysis_data(c_analysis, r_analysis, index);
     }
 
     Ok(())
@@ -387,6 +401,1318 @@ fn test_analyse_divide_extra_median(format: &str) -> Result<()> {
     Ok(())
 }
 
+#[parameterized(
+    format = {
+        "vs.YUV420P8", "vs.YUV420P10", "vs.YUV420P16"
+    }
+)]
+fn test_analyse_translated_clip_matches_mvtools(format: &str) -> Result<()> {
+    require_mvtools!();
+
+    let clip_config = TestClipConfig {
+        width: 256,
+        height: 192,
+        format,
+        length: 8,
+        content_type: ClipContentType::Pan {
+            speed_x: 2,
+            speed_y: 2,
+            seed: 7,
+        },
+    };
+
+    for (search, name) in [
+        (0, "Onetime"),
+        (1, "Nstep"),
+        (3, "Exhaustive"),
+        (4, "Hex2"),
+        (5, "UMH"),
+        (6, "Horizontal"),
+        (7, "Vertical"),
+    ] {
+        let super_params = FilterParams::default();
+        let analyse_params = FilterParams {
+            search: Some(search),
+            ..Default::default()
+        };
+        let script = generate_comparison_script(&clip_config, &super_params, Some(&analyse_params));
+
+        let env = Environment::from_script(&script)
+            .with_context(|| format!("Failed with search type {name}"))?;
+        let (c_node, _) = env.get_output(0)?;
+        let (r_node, _) = env.get_output(1)?;
+
+        for n in 0..clip_config.length {
+            let c_frame = c_node.get_frame(n)?;
+            let r_frame = r_node.get_frame(n)?;
+            let c_props = c_frame.props();
+            let r_props = r_frame.props();
+            let c_vectors = c_props.get_data("MVTools_vectors")?;
+            let r_vectors = r_props.get_data("MVTools_vectors")?;
+            compare_motion_vectors(c_vectors, r_vectors, 100)
+                .with_context(|| format!("Search type {name}, frame {n}"))?;
+
+            // The searches along a single axis cannot follow a diagonal pan,
+            // and the first frame has no valid vectors
+            if n == 0 || matches!(search, 6 | 7) {
+                continue;
+            }
+            // 2 pixels in each direction at half-pixel precision
+            let vectors = finest_vectors(r_vectors);
+            let panned = vectors.iter().filter(|&&v| v == (4, 4)).count();
+            assert!(
+                panned * 100 >= vectors.len() * 80,
+                "search type {name}, frame {n}: only {panned} of {} vectors follow the pan",
+                vectors.len()
+            );
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_analyse_truemotion_smoother_on_noisy_pan() -> Result<()> {
+    let clip_config = TestClipConfig {
+        width: 320,
+        height: 240,
+        format: "vs.YUV420P8",
+        length: 10,
+        content_type: ClipContentType::NoisyMovingBox {
+            speed_x: 4,
+            speed_y: 2,
+            seed: 20,
+        },
+    };
+    let script = generate_script(
+        &clip_config,
+        r#"
+r_super = core.zoomv.Super(clip)
+core.zoomv.Analyse(r_super, truemotion=1).set_output(0)
+core.zoomv.Analyse(r_super, truemotion=0).set_output(1)
+"#,
+    );
+
+    let env = Environment::from_script(&script)?;
+    let (truemotion_node, _) = env.get_output(0)?;
+    let (speed_node, _) = env.get_output(1)?;
+
+    let mut truemotion_variance = 0.0;
+    let mut speed_variance = 0.0;
+    // The first frame has no valid vectors
+    for n in 1..clip_config.length {
+        let frame = truemotion_node.get_frame(n)?;
+        truemotion_variance += vector_variance(frame.props().get_data("MVTools_vectors")?);
+        let frame = speed_node.get_frame(n)?;
+        speed_variance += vector_variance(frame.props().get_data("MVTools_vectors")?);
+    }
+
+    assert!(
+        truemotion_variance < speed_variance,
+        "truemotion vectors should be smoother: truemotion variance {truemotion_variance}, speed \
+         variance {speed_variance}"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_analyse_zero_badrange_disables_recovery() -> Result<()> {
+    let clip_config = TestClipConfig {
+        width: 320,
+        height: 240,
+        format: "vs.YUV420P8",
+        length: 10,
+        content_type: ClipContentType::NoisyMovingBox {
+            speed_x: 4,
+            speed_y: 2,
+            seed: 7,
+        },
+    };
+    // A low `badsad` makes almost every block on the noisy clip a bad block
+    let script = generate_script(
+        &clip_config,
+        r#"
+r_super = core.zoomv.Super(clip)
+core.zoomv.Analyse(r_super, badsad=100, badrange=0, info=1).set_output(0)
+core.zoomv.Analyse(r_super, badsad=100, badrange=24, info=1).set_output(1)
+core.zoomv.Analyse(r_super, badsad=100, badrange=-4, info=1).set_output(2)
+"#,
+    );
+
+    let env = Environment::from_script(&script)?;
+    let (disabled_node, _) = env.get_output(0)?;
+    let (umh_node, _) = env.get_output(1)?;
+    let (esa_node, _) = env.get_output(2)?;
+
+    // The first frame has no valid vectors
+    for n in 1..clip_config.length {
+        let frame = disabled_node.get_frame(n)?;
+        assert_eq!(frame.props().get_int("Analyse_bad_count")?, 0, "frame {n}");
+        let frame = umh_node.get_frame(n)?;
+        assert!(frame.props().get_int("Analyse_bad_count")? > 0, "frame {n}");
+        let frame = esa_node.get_frame(n)?;
+        assert!(frame.props().get_int("Analyse_bad_count")? > 0, "frame {n}");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_analyse_info_exports_search_bounds() -> Result<()> {
+    let clip_config = TestClipConfig {
+        width: 64,
+        height: 48,
+        format: "vs.YUV420P8",
+        length: 3,
+        content_type: ClipContentType::MovingBox {
+            speed_x: 2,
+            speed_y: 1,
+        },
+    };
+    let script = generate_script(
+        &clip_config,
+        r#"
+r_super = core.zoomv.Super(clip, pel=1, hpad=8, vpad=8)
+core.zoomv.Analyse(r_super, blksize=16, info=1).set_output(0)
+"#,
+    );
+
+    let env = Environment::from_script(&script)?;
+    let (node, _) = env.get_output(0)?;
+    let frame = node.get_frame(1)?;
+    let props = frame.props();
+    let bounds = props.get_int_array("Analyse_search_bounds")?;
+
+    // 4x3 blocks, with [dx_min, dy_min, dx_max, dy_max] each
+    assert_eq!(bounds.len(), 4 * 12);
+    // Blocks can move into the 8 pixels of padding, so the corners reach much further inwards
+    assert_eq!(&bounds[..4], &[-8, -8, 56, 40]);
+    assert_eq!(&bounds[4 * 11..], &[-56, -40, 8, 8]);
+
+    Ok(())
+}
+
+#[parameterized(
+    format = {
+        "vs.YUV422P8", "vs.YUV440P8", "vs.YUV422P16", "vs.YUV440P16"
+    }
+)]
+fn test_analyse_chroma_small_blocks(format: &str) -> Result<()> {
+    let clip_config = TestClipConfig {
+        width: 128,
+        height: 96,
+        format,
+        length: 4,
+        content_type: ClipContentType::MovingBox {
+            speed_x: 2,
+            speed_y: 1,
+        },
+    };
+    // The chroma blocks of these sizes are as small as 2x4, 4x2, 8x1, and 16x1
+    let script = generate_script(
+        &clip_config,
+        r#"
+r_super = core.zoomv.Super(clip)
+core.zoomv.Analyse(r_super, blksize=4, chroma=1).set_output(0)
+core.zoomv.Analyse(r_super, blksize=8, blksizev=4, chroma=1).set_output(1)
+core.zoomv.Analyse(r_super, blksize=16, blksizev=2, chroma=1).set_output(2)
+"#,
+    );
+
+    let env = Environment::from_script(&script)?;
+    for output in 0..3 {
+        let (node, _) = env.get_output(output)?;
+        for n in 0..clip_config.length {
+            node.get_frame(n)
+                .with_context(|| format!("output {output}, frame {n}"))?;
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_analyse_multi_matches_separate_deltas() -> Result<()> {
+    let clip_config = TestClipConfig {
+        width: 128,
+        height: 96,
+        format: "vs.YUV420P8",
+        length: 8,
+        content_type: ClipContentType::MovingBox {
+            speed_x: 2,
+            speed_y: 1,
+        },
+    };
+    let script = generate_script(
+        &clip_config,
+        r#"
+r_super = core.zoomv.Super(clip)
+core.zoomv.Analyse(r_super, delta=3, multi=1).set_output(0)
+outputs = 1
+for delta in range(1, 4):
+    core.zoomv.Analyse(r_super, isb=1, delta=delta).set_output(outputs)
+    core.zoomv.Analyse(r_super, isb=0, delta=delta).set_output(outputs + 1)
+    outputs += 2
+"#,
+    );
+
+    let env = Environment::from_script(&script)?;
+    let (multi_node, _) = env.get_output(0)?;
+    assert_eq!(multi_node.info().num_frames, clip_config.length * 6);
+
+    // The multi clip holds bv1, fv1, bv2, fv2, bv3, fv3 for every source frame
+    for index in 0..6 {
+        let (node, _) = env.get_output(index as i32 + 1)?;
+        for n in 0..clip_config.length {
+            let multi_frame = multi_node.get_frame(n * 6 + index)?;
+            let frame = node.get_frame(n)?;
+            let context = format!("frame {n}, vectors {index}");
+            assert_eq!(
+                multi_frame.props().get_int("MVTools_multi_delta")?,
+                3,
+                "{context}"
+            );
+            assert_eq!(
+                multi_frame.props().get_data("MVTools_MVAnalysisData")?,
+                frame.props().get_data("MVTools_MVAnalysisData")?,
+                "{context}"
+            );
+            assert_eq!(
+                multi_frame.props().get_data("MVTools_vectors")?,
+                frame.props().get_data("MVTools_vectors")?,
+                "{context}"
+            );
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_analyse_both_directions_match_separate_runs() -> Result<()> {
+    let clip_config = TestClipConfig {
+        width: 128,
+        height: 96,
+        format: "vs.YUV420P8",
+        length: 8,
+        content_type: ClipContentType::MovingBox {
+            speed_x: 2,
+            speed_y: 1,
+        },
+    };
+    let script = generate_script(
+        &clip_config,
+        r#"
+r_super = core.zoomv.Super(clip)
+both = core.zoomv.Analyse(r_super, isb=2, delta=2)
+both.set_output(0)
+core.zoomv.Analyse(r_super, isb=1, delta=2).set_output(1)
+core.zoomv.Analyse(r_super, isb=0, delta=2).set_output(2)
+core.std.SelectEvery(both, 2, 0).set_output(3)
+core.std.SelectEvery(both, 2, 1).set_output(4)
+"#,
+    );
+
+    let env = Environment::from_script(&script)?;
+    let (both_node, _) = env.get_output(0)?;
+    assert_eq!(both_node.info().num_frames, clip_config.length * 2);
+
+    // Selecting every other frame splits the backward and forward vectors
+    for (split, separate) in [(3, 1), (4, 2)] {
+        let (split_node, _) = env.get_output(split)?;
+        let (node, _) = env.get_output(separate)?;
+        assert_eq!(split_node.info().num_frames, clip_config.length);
+        for n in 0..clip_config.length {
+            let split_frame = split_node.get_frame(n)?;
+            let frame = node.get_frame(n)?;
+            let context = format!("frame {n}, output {split}");
+            assert_eq!(
+                split_frame.props().get_data("MVTools_MVAnalysisData")?,
+                frame.props().get_data("MVTools_MVAnalysisData")?,
+                "{context}"
+            );
+            assert_eq!(
+                split_frame.props().get_data("MVTools_vectors")?,
+                frame.props().get_data("MVTools_vectors")?,
+                "{context}"
+            );
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_analyse_meander_matches_raster_scan_on_pan() -> Result<()> {
+    let clip_config = TestClipConfig {
+        width: 256,
+        height: 128,
+        format: "vs.YUV420P8",
+        length: 6,
+        content_type: ClipContentType::Blank,
+    };
+    // A static noise texture panning left by 2 pixels per frame
+    let script = generate_script(
+        &clip_config,
+        r#"
+texture = core.std.BlankClip(clip, width=clip.width + 64, color=[128, 128, 128])
+texture = core.grain.Add(texture, var=400, constant=True)
+clip = core.std.Splice([
+    core.std.Crop(texture, left=2 * n, right=64 - 2 * n)[n] for n in range(clip.num_frames)
+])
+r_super = core.zoomv.Super(clip)
+core.zoomv.Analyse(r_super, meander=1).set_output(0)
+core.zoomv.Analyse(r_super, meander=0).set_output(1)
+"#,
+    );
+
+    let env = Environment::from_script(&script)?;
+    let (meander_node, _) = env.get_output(0)?;
+    let (raster_node, _) = env.get_output(1)?;
+
+    // The first frame has no valid vectors
+    for n in 1..clip_config.length {
+        let frame = meander_node.get_frame(n)?;
+        let meander = finest_vectors(frame.props().get_data("MVTools_vectors")?);
+        let frame = raster_node.get_frame(n)?;
+        let raster = finest_vectors(frame.props().get_data("MVTools_vectors")?);
+        assert_eq!(meander.len(), raster.len());
+
+        let matching = meander.iter().zip(&raster).filter(|(a, b)| a == b).count();
+        assert!(
+            matching * 100 >= meander.len() * 95,
+            "frame {n}: only {matching} of {} vectors match",
+            meander.len()
+        );
+        // Away from the edges, both scans find the pan, 2 pixels at half-pixel precision
+        let panned = meander.iter().filter(|&&v| v == (4, 0)).count();
+        assert!(
+            panned * 100 >= meander.len() * 80,
+            "frame {n}: only {panned} of {} vectors follow the pan",
+            meander.len()
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_analyse_vectors_without_reference_are_invalid() -> Result<()> {
+    let clip_config = TestClipConfig {
+        width: 128,
+        height: 96,
+        format: "vs.YUV420P8",
+        length: 6,
+        content_type: ClipContentType::MovingBox {
+            speed_x: 2,
+            speed_y: 1,
+        },
+    };
+    let script = generate_script(
+        &clip_config,
+        r#"
+r_super = core.zoomv.Super(clip)
+core.zoomv.Analyse(r_super, isb=0, delta=2).set_output(0)
+core.zoomv.Analyse(r_super, isb=1, delta=2).set_output(1)
+"#,
+    );
+
+    let env = Environment::from_script(&script)?;
+    let last = clip_config.length - 1;
+    // Forward vectors reference earlier frames, and backward vectors later frames
+    for (output, invalid) in [(0, [0, 1]), (1, [last - 1, last])] {
+        let (node, _) = env.get_output(output)?;
+        for n in 0..clip_config.length {
+            let frame = node.get_frame(n)?;
+            let vectors = frame.props().get_data("MVTools_vectors")?.to_vec();
+            let validity = i32::from_le_bytes(vectors[4..8].try_into()?);
+            assert_eq!(
+                validity == 0,
+                invalid.contains(&n),
+                "output {output}, frame {n}"
+            );
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_analyse_export_sad() -> Result<()> {
+    let clip_config = TestClipConfig {
+        width: 128,
+        height: 96,
+        format: "vs.YUV420P8",
+        length: 5,
+        content_type: ClipContentType::MovingBox {
+            speed_x: 2,
+            speed_y: 1,
+        },
+    };
+    let script = generate_script(
+        &clip_config,
+        r#"
+r_super = core.zoomv.Super(clip)
+core.zoomv.Analyse(r_super, export_sad=1).set_output(0)
+core.zoomv.Analyse(r_super).set_output(1)
+"#,
+    );
+
+    let env = Environment::from_script(&script)?;
+    let (exported_node, _) = env.get_output(0)?;
+    let (default_node, _) = env.get_output(1)?;
+
+    // The first frame has no reference, so its vectors are not valid
+    let frame = exported_node.get_frame(0)?;
+    assert!(frame.props().get_int_array("Analyse_block_sads").is_err());
+
+    for n in 1..clip_config.length {
+        let frame = exported_node.get_frame(n)?;
+        let props = frame.props();
+        let vectors = props.get_data("MVTools_vectors")?;
+        let default_frame = default_node.get_frame(n)?;
+        assert_eq!(
+            vectors,
+            default_frame.props().get_data("MVTools_vectors")?,
+            "frame {n}"
+        );
+        assert!(
+            default_frame
+                .props()
+                .get_int_array("Analyse_block_sads")
+                .is_err()
+        );
+
+        let sads = props.get_int_array("Analyse_block_sads")?;
+        assert_eq!(sads.len(), finest_vectors(vectors).len(), "frame {n}");
+        assert!(sads.iter().all(|&sad| sad >= 0), "frame {n}");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_analyse_export_motion() -> Result<()> {
+    let clip_config = TestClipConfig {
+        width: 128,
+        height: 96,
+        format: "vs.YUV420P8",
+        length: 5,
+        content_type: ClipContentType::MovingBox {
+            speed_x: 2,
+            speed_y: 1,
+        },
+    };
+    let script = generate_script(
+        &clip_config,
+        r#"
+r_super = core.zoomv.Super(clip, pel=4)
+core.zoomv.Analyse(r_super, export_motion=1).set_output(0)
+core.zoomv.Analyse(r_super).set_output(1)
+"#,
+    );
+
+    let env = Environment::from_script(&script)?;
+    let (exported_node, _) = env.get_output(0)?;
+    let (default_node, _) = env.get_output(1)?;
+
+    // The first frame has no reference, so its vectors are not valid
+    let frame = exported_node.get_frame(0)?;
+    assert!(
+        frame
+            .props()
+            .get_float_array("Analyse_block_motion")
+            .is_err()
+    );
+
+    for n in 1..clip_config.length {
+        let frame = exported_node.get_frame(n)?;
+        let props = frame.props();
+        let vectors = props.get_data("MVTools_vectors")?;
+        let default_frame = default_node.get_frame(n)?;
+        assert_eq!(
+            vectors,
+            default_frame.props().get_data("MVTools_vectors")?,
+            "frame {n}"
+        );
+        assert!(
+            default_frame
+                .props()
+                .get_float_array("Analyse_block_motion")
+                .is_err()
+        );
+
+        // Quarter pixel vectors divided by 4
+        let motion = props.get_float_array("Analyse_block_motion")?;
+        let expected: Vec<f64> = finest_vectors(vectors)
+            .into_iter()
+            .flat_map(|(x, y)| [f64::from(x) / 4.0, f64::from(y) / 4.0])
+            .collect();
+        assert_eq!(motion, expected, "frame {n}");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_analyse_field_order_from_field_based() -> Result<()> {
+    let clip_config = TestClipConfig {
+        width: 128,
+        height: 96,
+        format: "vs.YUV420P8",
+        length: 6,
+        content_type: ClipContentType::Pan {
+            speed_x: 2,
+            speed_y: 0,
+            seed: 3,
+        },
+    };
+    // Fields without `_Field`, only tagged as top field first
+    let script = generate_script(
+        &clip_config,
+        r#"
+clip = core.std.SetFieldBased(clip, 2)
+r_super = core.zoomv.Super(clip)
+core.zoomv.Analyse(r_super, fields=1).set_output(0)
+core.zoomv.Analyse(r_super, fields=1, tff=1).set_output(1)
+core.zoomv.Analyse(r_super, fields=1, tff=0).set_output(2)
+"#,
+    );
+
+    let env = Environment::from_script(&script)?;
+    let (tagged_node, _) = env.get_output(0)?;
+    let (tff_node, _) = env.get_output(1)?;
+    let (bff_node, _) = env.get_output(2)?;
+
+    let mut differs_from_bff = false;
+    for n in 1..clip_config.length {
+        let tagged = tagged_node.get_frame(n)?;
+        let tagged = tagged.props();
+        let tff = tff_node.get_frame(n)?;
+        let bff = bff_node.get_frame(n)?;
+        let vectors = tagged.get_data("MVTools_vectors")?;
+        assert_eq!(
+            vectors,
+            tff.props().get_data("MVTools_vectors")?,
+            "frame {n}"
+        );
+        differs_from_bff |= vectors != bff.props().get_data("MVTools_vectors")?;
+    }
+    // Adjacent fields of opposite parity are shifted the other way for bottom field first
+    assert!(differs_from_bff);
+
+    Ok(())
+}
+
+#[test]
+fn test_analyse_fields_without_field_order_is_rejected() -> Result<()> {
+    let clip_config = TestClipConfig {
+        width: 128,
+        height: 96,
+        format: "vs.YUV420P8",
+        length: 3,
+        content_type: ClipContentType::Blank,
+    };
+    let script = generate_script(
+        &clip_config,
+        r#"
+clip = core.std.SetFieldBased(clip, 0)
+core.zoomv.Analyse(core.zoomv.Super(clip), fields=1).set_output(0)
+"#,
+    );
+
+    let env = Environment::from_script(&script)?;
+    let (node, _) = env.get_output(0)?;
+    let err = node.get_frame(1).unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("neither _Field nor _FieldBased gives the field order"),
+        "{err}"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_analyse_limit() -> Result<()> {
+    let clip_config = TestClipConfig {
+        width: 128,
+        height: 96,
+        format: "vs.YUV420P8",
+        length: 5,
+        content_type: ClipContentType::MovingBox {
+            speed_x: 8,
+            speed_y: 4,
+        },
+    };
+    let script = generate_script(
+        &clip_config,
+        r#"
+r_super = core.zoomv.Super(clip)
+core.zoomv.Analyse(r_super, limit=1).set_output(0)
+core.zoomv.Analyse(r_super, limit=0).set_output(1)
+core.zoomv.Analyse(r_super).set_output(2)
+"#,
+    );
+
+    let env = Environment::from_script(&script)?;
+    let (limited_node, _) = env.get_output(0)?;
+    let (disabled_node, _) = env.get_output(1)?;
+    let (default_node, _) = env.get_output(2)?;
+
+    let mut any_long = false;
+    for n in 1..clip_config.length {
+        // 1 pixel is 2 units at the default half-pixel precision
+        let frame = limited_node.get_frame(n)?;
+        let limited = finest_vectors(frame.props().get_data("MVTools_vectors")?);
+        assert!(
+            limited.iter().all(|&(x, y)| x * x + y * y <= 4),
+            "frame {n}"
+        );
+
+        let frame = disabled_node.get_frame(n)?;
+        let disabled = frame.props().get_data("MVTools_vectors")?.to_vec();
+        let frame = default_node.get_frame(n)?;
+        assert_eq!(
+            disabled,
+            frame.props().get_data("MVTools_vectors")?,
+            "frame {n}"
+        );
+        any_long |= finest_vectors(&disabled)
+            .iter()
+            .any(|&(x, y)| x * x + y * y > 4);
+    }
+    assert!(any_long, "the box should move further than the limit");
+
+    Ok(())
+}
+
+#[test]
+fn test_limited_vectors_are_not_compensated() -> Result<()> {
+    let clip_config = TestClipConfig {
+        width: 128,
+        height: 96,
+        format: "vs.YUV420P8",
+        length: 5,
+        content_type: ClipContentType::Pan {
+            speed_x: 4,
+            speed_y: 2,
+            seed: 11,
+        },
+    };
+    // A low thsad only lets through blocks that really match, and a huge thscd1
+    // keeps the failed blocks from being treated as a scene change.
+    let script = generate_script(
+        &clip_config,
+        r#"
+r_super = core.zoomv.Super(clip)
+limited = core.zoomv.Analyse(r_super, isb=True, limit=1)
+unlimited = core.zoomv.Analyse(r_super, isb=True)
+core.zoomv.Compensate(clip, r_super, limited, thsad=100, thscd1=100000000).set_output(0)
+core.zoomv.Compensate(clip, r_super, unlimited, thsad=100, thscd1=100000000).set_output(1)
+clip.set_output(2)
+"#,
+    );
+
+    let env = Environment::from_script(&script)?;
+    let (limited_node, _) = env.get_output(0)?;
+    let (unlimited_node, _) = env.get_output(1)?;
+    let (src_node, _) = env.get_output(2)?;
+    for n in 0..clip_config.length - 1 {
+        let src = src_node.get_frame(n)?;
+        // The shortened vectors point at the wrong blocks, so none of them is used
+        let limited = limited_node.get_frame(n)?;
+        for plane in 0..3 {
+            assert_eq!(
+                limited.data(plane),
+                src.data(plane),
+                "frame {n}, plane {plane}"
+            );
+        }
+        let unlimited = unlimited_node.get_frame(n)?;
+        assert_ne!(unlimited.data(0), src.data(0), "frame {n}");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_analyse_search_coarse() -> Result<()> {
+    let clip_config = TestClipConfig {
+        width: 128,
+        height: 96,
+        format: "vs.YUV420P8",
+        length: 5,
+        content_type: ClipContentType::NoisyMovingBox {
+            speed_x: 6,
+            speed_y: 3,
+            seed: 20,
+        },
+    };
+    // 3 is exhaustive, 4 is hex2
+    let script = generate_script(
+        &clip_config,
+        r#"
+r_super = core.zoomv.Super(clip)
+core.zoomv.Analyse(r_super, search=4, search_coarse=3).set_output(0)
+core.zoomv.Analyse(r_super, search=4, search_coarse=4).set_output(1)
+"#,
+    );
+
+    let env = Environment::from_script(&script)?;
+    let (coarse_node, _) = env.get_output(0)?;
+    let (hex_node, _) = env.get_output(1)?;
+
+    let mut any_different = false;
+    for n in 1..clip_config.length {
+        let frame = coarse_node.get_frame(n)?;
+        let coarse = frame.props().get_data("MVTools_vectors")?.to_vec();
+        let frame = hex_node.get_frame(n)?;
+        let hex = frame.props().get_data("MVTools_vectors")?.to_vec();
+
+        assert_eq!(coarse.len(), hex.len(), "frame {n}");
+        let validity = i32::from_le_bytes(coarse[4..8].try_into().unwrap());
+        assert_eq!(validity, 1, "frame {n}");
+        assert!(
+            finest_vectors(&coarse)
+                .iter()
+                .all(|&(x, y)| x.abs() <= 2 * clip_config.width as i32
+                    && y.abs() <= 2 * clip_config.height as i32),
+            "frame {n}"
+        );
+        any_different |= coarse != hex;
+    }
+    assert!(
+        any_different,
+        "an exhaustive coarse search should change the vectors"
+    );
+
+    Ok(())
+}
+
+#[parameterized(
+    format = {
+        "vs.YUV420P8", "vs.YUV420P10"
+    }
+)]
+fn test_analyse_threads_match_serial(format: &str) -> Result<()> {
+    let clip_config = TestClipConfig {
+        width: 320,
+        height: 240,
+        format,
+        length: 5,
+        content_type: ClipContentType::NoisyMovingBox {
+            speed_x: 3,
+            speed_y: 2,
+            seed: 30,
+        },
+    };
+    let script = generate_script(
+        &clip_config,
+        r#"
+r_super = core.zoomv.Super(clip, pel=2)
+core.zoomv.Analyse(r_super, meander=False, badrange=0, global=True).set_output(0)
+core.zoomv.Analyse(r_super, meander=False, badrange=0, global=True, threads=4).set_output(1)
+core.zoomv.Analyse(r_super, meander=False, badrange=0, dct=1, threads=3).set_output(2)
+core.zoomv.Analyse(r_super, meander=False, badrange=0, dct=1).set_output(3)
+core.zoomv.Analyse(r_super, meander=False, badrange=0, dct=1, fixed_dct=True).set_output(4)
+core.zoomv.Analyse(r_super, meander=False, badrange=0, dct=1, fixed_dct=True, threads=3).set_output(5)
+"#,
+    );
+
+    let env = Environment::from_script(&script)?;
+    for (serial, parallel) in [(0, 1), (3, 2), (4, 5)] {
+        let (serial_node, _) = env.get_output(serial)?;
+        let (parallel_node, _) = env.get_output(parallel)?;
+        for n in 0..clip_config.length {
+            let serial_frame = serial_node.get_frame(n)?;
+            let parallel_frame = parallel_node.get_frame(n)?;
+            assert_eq!(
+                serial_frame.props().get_data("MVTools_vectors")?,
+                parallel_frame.props().get_data("MVTools_vectors")?,
+                "outputs {serial} and {parallel}, frame {n}"
+            );
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_analyse_trivialsad_keeps_exact_static_blocks() -> Result<()> {
+    let clip_config = TestClipConfig {
+        width: 128,
+        height: 96,
+        format: "vs.YUV420P8",
+        length: 5,
+        content_type: ClipContentType::MovingBox {
+            speed_x: 4,
+            speed_y: 2,
+        },
+    };
+    // The background is static, so skipping the search for its exact zero vector matches
+    // the full search, which cannot find anything cheaper than a zero SAD either
+    let script = generate_script(
+        &clip_config,
+        r#"
+r_super = core.zoomv.Super(clip)
+core.zoomv.Analyse(r_super, trivialsad=1).set_output(0)
+core.zoomv.Analyse(r_super).set_output(1)
+"#,
+    );
+
+    let env = Environment::from_script(&script)?;
+    let (trivial_node, _) = env.get_output(0)?;
+    let (default_node, _) = env.get_output(1)?;
+
+    let mut any_moving = false;
+    for n in 1..clip_config.length {
+        let frame = trivial_node.get_frame(n)?;
+        let trivial = frame.props().get_data("MVTools_vectors")?.to_vec();
+        let frame = default_node.get_frame(n)?;
+        assert_eq!(
+            trivial,
+            frame.props().get_data("MVTools_vectors")?,
+            "frame {n}"
+        );
+        any_moving |= finest_vectors(&trivial)
+            .iter()
+            .any(|&(x, y)| x != 0 || y != 0);
+    }
+    assert!(any_moving, "the box should still be found moving");
+
+    Ok(())
+}
+
+#[test]
+fn test_analyse_gray_matches_yuv_luma() -> Result<()> {
+    let clip_config = TestClipConfig {
+        width: 128,
+        height: 96,
+        format: "vs.YUV420P8",
+        length: 5,
+        content_type: ClipContentType::MovingBox {
+            speed_x: 4,
+            speed_y: 2,
+        },
+    };
+    // A GRAY clip holds only the luma plane, so its search must match
+    // a luma-only search of the YUV clip it was taken from
+    let script = generate_script(
+        &clip_config,
+        r#"
+gray = core.std.ShufflePlanes(clip, 0, vs.GRAY)
+core.zoomv.Analyse(core.zoomv.Super(gray, levels=3), levels=3).set_output(0)
+core.zoomv.Analyse(core.zoomv.Super(clip, levels=3), levels=3, chroma=False).set_output(1)
+"#,
+    );
+
+    let env = Environment::from_script(&script)?;
+    let (gray_node, _) = env.get_output(0)?;
+    let (yuv_node, _) = env.get_output(1)?;
+
+    let mut any_moving = false;
+    for n in 1..clip_config.length {
+        let frame = gray_node.get_frame(n)?;
+        let gray = frame.props().get_data("MVTools_vectors")?.to_vec();
+        let frame = yuv_node.get_frame(n)?;
+        assert_eq!(
+            gray,
+            frame.props().get_data("MVTools_vectors")?,
+            "frame {n}"
+        );
+        any_moving |= finest_vectors(&gray).iter().any(|&(x, y)| x != 0 || y != 0);
+    }
+    assert!(any_moving, "the box should be found moving");
+
+    Ok(())
+}
+
+#[test]
+fn test_analyse_reports_effective_configuration() -> Result<()> {
+    let clip_config = TestClipConfig {
+        width: 128,
+        height: 96,
+        format: "vs.YUV420P8",
+        length: 3,
+        content_type: ClipContentType::Blank,
+    };
+    // A 96 pixel tall frame still fits an 8 pixel tall block after being halved three times,
+    // so all 4 levels of the super clip are searched by default
+    let script = generate_script(
+        &clip_config,
+        r#"
+r_super = core.zoomv.Super(clip, levels=4)
+r_super.set_output(0)
+core.zoomv.Analyse(r_super).set_output(1)
+core.zoomv.Analyse(r_super, blksize=16, blksizev=8, overlap=8, overlapv=4).set_output(2)
+core.zoomv.Analyse(r_super, levels=2).set_output(3)
+"#,
+    );
+
+    let env = Environment::from_script(&script)?;
+    let (super_node, _) = env.get_output(0)?;
+    let super_frame = super_node.get_frame(0)?;
+    let super_levels = super_frame.props().get_int("Super_levels")?;
+
+    let outputs = [
+        (1, super_levels, [8, 8], [0, 0]),
+        (2, 4, [16, 8], [8, 4]),
+        (3, 2, [8, 8], [0, 0]),
+    ];
+    for (output, levels, blksize, overlap) in outputs {
+        let (node, _) = env.get_output(output)?;
+        let frame = node.get_frame(1)?;
+        let props = frame.props();
+        assert_eq!(props.get_int("Analyse_levels")?, levels, "output {output}");
+        assert_eq!(
+            props.get_int_array("Analyse_blksize")?,
+            blksize,
+            "output {output}"
+        );
+        assert_eq!(
+            props.get_int_array("Analyse_overlap")?,
+            overlap,
+            "output {output}"
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_analyse_divide_is_flagged() -> Result<()> {
+    let clip_config = TestClipConfig {
+        width: 128,
+        height: 96,
+        format: "vs.YUV420P8",
+        length: 3,
+        content_type: ClipContentType::MovingBox {
+            speed_x: 2,
+            speed_y: 1,
+        },
+    };
+    let script = generate_script(
+        &clip_config,
+        r#"
+r_super = core.zoomv.Super(clip)
+divided = core.zoomv.Analyse(r_super, divide=1)
+divided.set_output(0)
+core.zoomv.Analyse(r_super).set_output(1)
+core.zoomv.FlowBlur(clip, r_super, core.zoomv.Analyse(r_super, isb=True, divide=1), divided).set_output(2)
+"#,
+    );
+
+    let env = Environment::from_script(&script)?;
+    let (divided_node, _) = env.get_output(0)?;
+    let (base_node, _) = env.get_output(1)?;
+    let (blur_node, _) = env.get_output(2)?;
+
+    let divided = divided_node.get_frame(1)?;
+    let base = base_node.get_frame(1)?;
+    assert_eq!(divided.props().get_int("Analyse_divide")?, 1);
+    assert_eq!(base.props().get_int("Analyse_divide")?, 0);
+
+    // The analysis data describes the finer grid of 4x4 subblocks
+    let analysis_int = |frame: &vapoursynth::frame::FrameRef, index: usize| -> Result<i32> {
+        let props = frame.props();
+        let data = props.get_data("MVTools_MVAnalysisData")?;
+        Ok(i32::from_le_bytes(data[index * 4..][..4].try_into()?))
+    };
+    assert_eq!(analysis_int(&divided, 0)?, 4);
+    assert_eq!(analysis_int(&divided, 11)?, 2 * analysis_int(&base, 11)?);
+    assert_eq!(
+        finest_vectors(divided.props().get_data("MVTools_vectors")?).len(),
+        4 * finest_vectors(base.props().get_data("MVTools_vectors")?).len()
+    );
+
+    // Clients read the subdivided vectors without rejecting the finer grid
+    blur_node.get_frame(1)?;
+
+    Ok(())
+}
+
+#[test]
+fn test_clients_use_divided_vectors() -> Result<()> {
+    let clip_config = TestClipConfig {
+        width: 128,
+        height: 96,
+        format: "vs.YUV420P8",
+        length: 4,
+        content_type: ClipContentType::NoisyMovingBox {
+            speed_x: 4,
+            speed_y: 2,
+            seed: 5,
+        },
+    };
+    // Both divide modes search the same blocks and only subdivide them differently,
+    // so clients reading the searched blocks would give the same output for both.
+    let script = generate_script(
+        &clip_config,
+        r#"
+r_super = core.zoomv.Super(clip)
+for i, divide in enumerate((1, 2)):
+    bw = core.zoomv.Analyse(r_super, isb=True, divide=divide)
+    fw = core.zoomv.Analyse(r_super, isb=False, divide=divide)
+    core.zoomv.Compensate(clip, r_super, bw, thscd1=100000000).set_output(2 * i)
+    core.zoomv.Degrain1(clip, r_super, bw, fw, thscd1=100000000).set_output(2 * i + 1)
+"#,
+    );
+
+    let env = Environment::from_script(&script)?;
+    for (filter, output) in [("Compensate", 0), ("Degrain1", 1)] {
+        let (original_node, _) = env.get_output(output)?;
+        let (median_node, _) = env.get_output(output + 2)?;
+        let frame = 1;
+        assert_ne!(
+            original_node.get_frame(frame)?.data(0),
+            median_node.get_frame(frame)?.data(0),
+            "{filter} gives the same output for both divide modes"
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_degrain_keeps_static_clip() -> Result<()> {
+    let clip_config = TestClipConfig {
+        width: 64,
+        height: 48,
+        format: "vs.YUV420P8",
+        length: 7,
+        content_type: ClipContentType::Blank,
+    };
+    let script = generate_script(
+        &clip_config,
+        r#"
+r_super = core.zoomv.Super(clip)
+vectors = [core.zoomv.Analyse(r_super, isb=isb, delta=delta) for delta in (1, 2, 3) for isb in (True, False)]
+core.zoomv.Degrain1(clip, r_super, *vectors[:2]).set_output(0)
+core.zoomv.Degrain2(clip, r_super, *vectors[:4], thsad2=200).set_output(1)
+core.zoomv.Degrain3(clip, r_super, *vectors, plane=0).set_output(2)
+clip.set_output(3)
+"#,
+    );
+
+    let env = Environment::from_script(&script)?;
+    let (src_node, _) = env.get_output(3)?;
+    for output in 0..3 {
+        let (node, _) = env.get_output(output)?;
+        // Every radius reaches past both ends of the clip for some frames
+        for n in [0, 3, 6] {
+            let src = src_node.get_frame(n)?;
+            let frame = node.get_frame(n)?;
+            for plane in 0..3 {
+                assert_eq!(
+                    frame.data(plane),
+                    src.data(plane),
+                    "output {output}, frame {n}, plane {plane}"
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_invalid_vectors_are_marked_and_passed_through() -> Result<()> {
+    let clip_config = TestClipConfig {
+        width: 64,
+        height: 48,
+        format: "vs.YUV420P8",
+        length: 3,
+        content_type: ClipContentType::NoisyMovingBox {
+            speed_x: 2,
+            speed_y: 1,
+            seed: 3,
+        },
+    };
+    // With a delta of 3, every reference frame is outside of the 3 frame clip
+    let script = generate_script(
+        &clip_config,
+        r#"
+r_super = core.zoomv.Super(clip)
+near = [core.zoomv.Analyse(r_super, isb=isb) for isb in (True, False)]
+far = [core.zoomv.Analyse(r_super, isb=isb, delta=3) for isb in (True, False)]
+near[0].set_output(0)
+core.zoomv.Degrain1(clip, r_super, *near).set_output(1)
+core.zoomv.Degrain1(clip, r_super, *far).set_output(2)
+clip.set_output(3)
+"#,
+    );
+
+    let env = Environment::from_script(&script)?;
+    let (backward_node, _) = env.get_output(0)?;
+    // The last frame has no next frame to point to
+    for (n, valid) in [(0, 1), (1, 1), (2, 0)] {
+        let frame = backward_node.get_frame(n)?;
+        assert_eq!(frame.props().get_int("Analyse_valid")?, valid, "frame {n}");
+    }
+
+    let (near_node, _) = env.get_output(1)?;
+    let (far_node, _) = env.get_output(2)?;
+    let (src_node, _) = env.get_output(3)?;
+    let src = src_node.get_frame(1)?;
+    assert_ne!(near_node.get_frame(1)?.data(0), src.data(0));
+    for n in 0..clip_config.length {
+        let src = src_node.get_frame(n)?;
+        let frame = far_node.get_frame(n)?;
+        for plane in 0..3 {
+            assert_eq!(
+                frame.data(plane),
+                src.data(plane),
+                "frame {n}, plane {plane}"
+            );
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_compensate_scbehavior_at_scene_change() -> Result<()> {
+    let clip_config = TestClipConfig {
+        width: 64,
+        height: 48,
+        format: "vs.YUV420P8",
+        length: 4,
+        content_type: ClipContentType::NoisyMovingBox {
+            speed_x: 2,
+            speed_y: 1,
+            seed: 5,
+        },
+    };
+    // Frames 1 and 2 are on either side of a cut
+    let script = generate_script(
+        &clip_config,
+        r#"
+cut = clip[:2] + core.std.Invert(clip)[2:]
+r_super = core.zoomv.Super(cut)
+mvbw = core.zoomv.Analyse(r_super, isb=True)
+core.zoomv.Compensate(cut, r_super, mvbw).set_output(0)
+core.zoomv.Compensate(cut, r_super, mvbw, scbehavior=0).set_output(1)
+cut.set_output(2)
+"#,
+    );
+
+    let env = Environment::from_script(&script)?;
+    let (use_source_node, _) = env.get_output(0)?;
+    let (use_reference_node, _) = env.get_output(1)?;
+    let (cut_node, _) = env.get_output(2)?;
+    let source = cut_node.get_frame(1)?;
+    let reference = cut_node.get_frame(2)?;
+    let use_source = use_source_node.get_frame(1)?;
+    let use_reference = use_reference_node.get_frame(1)?;
+    for plane in 0..3 {
+        assert_eq!(use_source.data(plane), source.data(plane), "plane {plane}");
+        assert_eq!(
+            use_reference.data(plane),
+            reference.data(plane),
+            "plane {plane}"
+        );
+    }
+
+    // Away from the cut, both modes compensate frame 0 from frame 1
+    let compensated = use_source_node.get_frame(0)?;
+    assert_eq!(
+        compensated.data(0),
+        use_reference_node.get_frame(0)?.data(0)
+    );
+    assert_ne!(compensated.data(0), cut_node.get_frame(0)?.data(0));
+
+    Ok(())
+}
+
+#[test]
+fn test_analyse_region_matches_full_frame_blocks() -> Result<()> {
+    let clip_config = TestClipConfig {
+        width: 128,
+        height: 96,
+        format: "vs.YUV420P8",
+        length: 4,
+        content_type: ClipContentType::MovingBox {
+            speed_x: 2,
+            speed_y: 1,
+        },
+    };
+    // A single level with an exhaustive search and no penalties only depends on the SADs,
+    // so the neighbors outside of the region cannot change the vectors inside of it
+    let script = generate_script(
+        &clip_config,
+        r#"
+r_super = core.zoomv.Super(clip, pel=1)
+args = {"levels": 1, "search": 3, "searchparam": 4, "lambda": 0, "truemotion": 0, "pnew": 0,
+        "pzero": 0, "global": 0}
+core.zoomv.Analyse(r_super, **args).set_output(0)
+core.zoomv.Analyse(r_super, left=32, top=16, cropw=64, croph=48, **args).set_output(1)
+"#,
+    );
+
+    let env = Environment::from_script(&script)?;
+    let (full_node, _) = env.get_output(0)?;
+    let (region_node, _) = env.get_output(1)?;
+    for n in 1..clip_config.length {
+        let full = finest_vectors(
+            full_node
+                .get_frame(n)?
+                .props()
+                .get_data("MVTools_vectors")?,
+        );
+        let region = finest_vectors(
+            region_node
+                .get_frame(n)?
+                .props()
+                .get_data("MVTools_vectors")?,
+        );
+        // 8x8 blocks, so the region is 8x6 blocks starting at block (4, 2) of 16x12
+        assert_eq!(region.len(), 8 * 6, "frame {n}");
+        for (i, vector) in region.iter().enumerate() {
+            let (x, y) = (i % 8 + 4, i / 8 + 2);
+            assert_eq!(*vector, full[y * 16 + x], "frame {n}: block ({x}, {y})");
+        }
+    }
+
+    Ok(())
+}
+
+/// Extracts the `(x, y)` components of the finest level vectors.
+fn finest_vectors(vectors: &[u8]) -> Vec<(i32, i32)> {
+    // Skip the size and validity headers, then find the last (finest) level
+    let mut data = &vectors[8..];
+    let mut finest = data;
+    while !data.is_empty() {
+        let size = u32::from_le_bytes(data[..4].try_into().unwrap()) as usize;
+        finest = &data[4..size];
+        data = &data[size..];
+    }
+
+    finest
+        .chunks_exact(16)
+        .map(|mv| {
+            let x = i32::from_le_bytes(mv[..4].try_into().unwrap());
+            let y = i32::from_le_bytes(mv[4..8].try_into().unwrap());
+            (x, y)
+        })
+        .collect()
+}
+
+/// Computes the variance of the finest level vectors, summed over both components.
+fn vector_variance(vectors: &[u8]) -> f64 {
+    let (x, y): (Vec<f64>, Vec<f64>) = finest_vectors(vectors)
+        .into_iter()
+        .map(|(x, y)| (f64::from(x), f64::from(y)))
+        .unzip();
+    variance(&x) + variance(&y)
+}
+
+fn variance(values: &[f64]) -> f64 {
+    let mean = values.iter().sum::<f64>() / values.len() as f64;
+    values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / values.len() as f64
+}
+
 fn compare_analysis_data(c_analysis: &[u8], r_analysis: &[u8], test_no: usize) {
     // Expected size difference of 12, to account for removed fields
     assert_eq!(
@@ -5,11 +5,17 @@ mod common;
 
 use anyhow::Result;
 use parameterized::parameterized;
-use vapoursynth::prelude::Environment;
+use vapoursynth::prelude::{Environment, FrameRef};
 
 use crate::common::{
     comparison::{ComparisonConfig, assert_frames_match, compare_frame_properties},
-    script_gen::{ClipContentType, FilterParams, TestClipConfig, generate_comparison_script},
+    script_gen::{
+        ClipContentType,
+        FilterParams,
+        TestClipConfig,
+        generate_comparison_script,
+        generate_script,
+    },
 };
 
 #[parameterized(
@@ -112,8 +118,9 @@ fn test_super_custom_params(format: &str) -> Result<()> {
 fn test_super_different_formats() -> Result<()> {
     require_mvtools!();
 
-    // Note: GRAY formats excluded for now due to plane access issues
     let formats = [
+        ("vs.GRAY8", false),
+        ("vs.GRAY16", true),
         ("vs.YUV420P8", false),
         ("vs.YUV420P16", true),
         ("vs.YUV422P8", false),
@@ -230,3 +237,266 @@ r_super.set_output(1)
 
     Ok(())
 }
+
+#[parameterized(
+    pel = { 1, 2, 4 }
+)]
+fn test_super_zero_padding(pel: u8) -> Result<()> {
+    let clip_config = TestClipConfig {
+        width: 64,
+        height: 48,
+        format: "vs.YUV420P8",
+        length: 3,
+        content_type: ClipContentType::MovingBox {
+            speed_x: 2,
+            speed_y: 1,
+        },
+    };
+    let script = generate_script(
+        &clip_config,
+        &format!(
+            r#"
+core.zoomv.Super(clip, hpad=0, vpad=0, pel={pel}).set_output(0)
+"#
+        ),
+    );
+
+    let env = Environment::from_script(&script)?;
+    let (super_node, _) = env.get_output(0)?;
+    for n in 0..clip_config.length {
+        let frame = super_node.get_frame(n)?;
+        assert_eq!(frame.props().get_int("Super_hpad")?, 0);
+        assert_eq!(frame.props().get_int("Super_vpad")?, 0);
+        assert_eq!(frame.width(0), clip_config.width);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_super_rfilter_cubic_is_smoother() -> Result<()> {
+    let clip_config = TestClipConfig {
+        width: 64,
+        height: 48,
+        format: "vs.YUV420P8",
+        length: 1,
+        content_type: ClipContentType::Noise { seed: 40 },
+    };
+    let script = generate_script(
+        &clip_config,
+        r#"
+core.zoomv.Super(clip, hpad=0, vpad=0, pel=1, rfilter=0).set_output(0)
+core.zoomv.Super(clip, hpad=0, vpad=0, pel=1, rfilter=4).set_output(1)
+"#,
+    );
+
+    let env = Environment::from_script(&script)?;
+    // Without padding or subpel planes, the first reduced level starts right below the source
+    let level_1_variance = |index: i32| -> Result<f64> {
+        let (node, _) = env.get_output(index)?;
+        let frame = node.get_frame(0)?;
+        let pixels: Vec<f64> = (clip_config.height..clip_config.height * 3 / 2)
+            .flat_map(|y| frame.plane_row::<u8>(0, y)[..clip_config.width / 2].to_vec())
+            .map(f64::from)
+            .collect();
+        let mean = pixels.iter().sum::<f64>() / pixels.len() as f64;
+        Ok(pixels.iter().map(|p| (p - mean).powi(2)).sum::<f64>() / pixels.len() as f64)
+    };
+
+    let average = level_1_variance(0)?;
+    let cubic = level_1_variance(1)?;
+    assert!(
+        cubic < average,
+        "cubic level 1 variance {cubic} should be below average {average}"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_super_gray_pelclip_for_yuv() -> Result<()> {
+    let clip_config = TestClipConfig {
+        width: 64,
+        height: 48,
+        format: "vs.YUV420P8",
+        length: 2,
+        content_type: ClipContentType::Noise { seed: 11 },
+    };
+    let script = generate_script(
+        &clip_config,
+        &format!(
+            r#"
+pelclip = clip.resize.Spline36({}, {}, src_left=0.25, src_top=0.25)
+gray_pelclip = core.std.ShufflePlanes(pelclip, 0, vs.GRAY)
+core.zoomv.Super(clip, pel=2, pelclip=gray_pelclip).set_output(0)
+core.zoomv.Super(clip, pel=2, pelclip=pelclip).set_output(1)
+core.zoomv.Super(clip, pel=2).set_output(2)
+"#,
+            clip_config.width * 2,
+            clip_config.height * 2,
+        ),
+    );
+
+    let env = Environment::from_script(&script)?;
+    let (gray_node, _) = env.get_output(0)?;
+    let (yuv_node, _) = env.get_output(1)?;
+    let (internal_node, _) = env.get_output(2)?;
+    for n in 0..clip_config.length {
+        let gray = gray_node.get_frame(n)?;
+        let yuv = yuv_node.get_frame(n)?;
+        let internal = internal_node.get_frame(n)?;
+        let same_plane = |a: &FrameRef, b: &FrameRef, plane| {
+            (0..a.height(plane)).all(|y| a.plane_row::<u8>(plane, y) == b.plane_row::<u8>(plane, y))
+        };
+
+        // Luma comes from the pelclip, chroma from the internal interpolation
+        assert!(
+            same_plane(&gray, &yuv, 0),
+            "frame {n}: luma should use the pelclip"
+        );
+        assert!(
+            !same_plane(&gray, &internal, 0),
+            "frame {n}: luma should not be interpolated"
+        );
+        for plane in 1..3 {
+            assert!(
+                same_plane(&gray, &internal, plane),
+                "frame {n}: plane {plane} should be interpolated internally"
+            );
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_super_maxmem_drops_coarse_levels() -> Result<()> {
+    let clip_config = TestClipConfig {
+        width: 64,
+        height: 48,
+        format: "vs.YUV420P8",
+        length: 1,
+        content_type: ClipContentType::Gradient,
+    };
+    // Only the full-size level fits in 64x48 pixels of 4:2:0
+    let script = generate_script(
+        &clip_config,
+        r#"
+core.zoomv.Super(clip, hpad=0, vpad=0, pel=1).set_output(0)
+core.zoomv.Super(clip, hpad=0, vpad=0, pel=1, maxmem=4608).set_output(1)
+"#,
+    );
+
+    let env = Environment::from_script(&script)?;
+    let (full_node, _) = env.get_output(0)?;
+    let (capped_node, _) = env.get_output(1)?;
+    let full = full_node.get_frame(0)?;
+    let capped = capped_node.get_frame(0)?;
+    assert_eq!(full.props().get_int("Super_levels")?, 4);
+    assert_eq!(capped.props().get_int("Super_levels")?, 1);
+    assert_eq!(capped.height(0), clip_config.height);
+    assert!(
+        (0..clip_config.height).all(|y| full.plane_row::<u8>(0, y) == capped.plane_row::<u8>(0, y)),
+        "the finest level should not change"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_super_level_matches_manual_reduce() -> Result<()> {
+    let clip_config = TestClipConfig {
+        width: 64,
+        height: 48,
+        format: "vs.YUV420P8",
+        length: 2,
+        content_type: ClipContentType::Noise { seed: 23 },
+    };
+    let script = generate_script(
+        &clip_config,
+        r#"
+sup = core.zoomv.Super(clip, hpad=8, vpad=8, pel=1, rfilter=0)
+core.zoomv.SuperLevel(sup, level=0).set_output(0)
+core.zoomv.SuperLevel(sup, level=1).set_output(1)
+clip.set_output(2)
+"#,
+    );
+
+    let env = Environment::from_script(&script)?;
+    let (level_0_node, _) = env.get_output(0)?;
+    let (level_1_node, _) = env.get_output(1)?;
+    let (src_node, _) = env.get_output(2)?;
+    for n in 0..clip_config.length {
+        let level_0 = level_0_node.get_frame(n)?;
+        let level_1 = level_1_node.get_frame(n)?;
+        let src = src_node.get_frame(n)?;
+        assert_eq!(level_0.width(0), clip_config.width);
+        assert_eq!(level_0.height(0), clip_config.height);
+        assert_eq!(level_1.width(0), clip_config.width / 2);
+        assert_eq!(level_1.height(0), clip_config.height / 2);
+
+        for y in 0..clip_config.height {
+            assert_eq!(
+                level_0.plane_row::<u8>(0, y),
+                &src.plane_row::<u8>(0, y)[..clip_config.width],
+                "frame {n}: level 0 row {y} should be the source"
+            );
+        }
+        // rfilter=0 averages each 2x2 block of the level above
+        for y in 0..clip_config.height / 2 {
+            let top = src.plane_row::<u8>(0, y * 2);
+            let bottom = src.plane_row::<u8>(0, y * 2 + 1);
+            let expected: Vec<u8> = (0..clip_config.width / 2)
+                .map(|x| {
+                    let sum = u32::from(top[x * 2])
+                        + u32::from(top[x * 2 + 1])
+                        + u32::from(bottom[x * 2])
+                        + u32::from(bottom[x * 2 + 1]);
+                    ((sum + 2) / 4) as u8
+                })
+                .collect();
+            assert_eq!(
+                level_1.plane_row::<u8>(0, y),
+                &expected[..],
+                "frame {n}: level 1 row {y} should be the reduced source"
+            );
+        }
+    }
+
+    Ok(())
+}
+
+#[parameterized(pel = { 2, 4 })]
+fn test_super_sharp_coeffs_wiener_matches_sharp_2(pel: u8) -> Result<()> {
+    let clip_config = TestClipConfig {
+        width: 64,
+        height: 48,
+        format: "vs.YUV420P10",
+        length: 2,
+        content_type: ClipContentType::Noise { seed: 29 },
+    };
+    let script = generate_script(
+        &clip_config,
+        &format!(
+            r#"
+core.zoomv.Super(clip, pel={pel}, sharp=2).set_output(0)
+core.zoomv.Super(clip, pel={pel}, sharp_coeffs=[1, -5, 20, 20, -5, 1]).set_output(1)
+"#
+        ),
+    );
+
+    let env = Environment::from_script(&script)?;
+    let (wiener_node, _) = env.get_output(0)?;
+    let (custom_node, _) = env.get_output(1)?;
+    let config = ComparisonConfig::default();
+    for n in 0..clip_config.length {
+        assert_frames_match::<u16>(
+            &wiener_node.get_frame(n)?,
+            &custom_node.get_frame(n)?,
+            &config,
+            &format!("pel={pel} frame {n}"),
+        )?;
+    }
+
+    Ok(())
+}